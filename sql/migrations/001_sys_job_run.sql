@@ -0,0 +1,17 @@
+-- 定时任务的执行记录
+CREATE TABLE IF NOT EXISTS `sys_job_run` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `job_name` varchar(50) NOT NULL COMMENT '任务名',
+  `trigger_type` tinyint NOT NULL DEFAULT '1' COMMENT '1 定时触发，2 手动触发',
+  `status` tinyint NOT NULL DEFAULT '1' COMMENT '1 执行中，2 成功，3 失败',
+  `message` varchar(1000) DEFAULT NULL COMMENT '执行结果或错误信息',
+  `instance` varchar(64) DEFAULT NULL COMMENT '执行任务的服务实例',
+  `trigger_uid` bigint DEFAULT NULL COMMENT '手动触发的管理员 uid',
+  `started_at` datetime NOT NULL COMMENT '开始时间',
+  `finished_at` datetime DEFAULT NULL COMMENT '结束时间',
+  `duration_ms` bigint DEFAULT NULL COMMENT '耗时 ms',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`) USING BTREE,
+  KEY `job_name` (`job_name`,`id`) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='系统：定时任务的执行记录';
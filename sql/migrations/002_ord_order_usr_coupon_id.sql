@@ -0,0 +1,3 @@
+-- 订单使用的用户优惠券，取消订单时返还
+ALTER TABLE `ord_order`
+  ADD COLUMN `usr_coupon_id` int DEFAULT NULL COMMENT '使用的用户优惠券 usr_coupon.id' AFTER `reduce_des`;
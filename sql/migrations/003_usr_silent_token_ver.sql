@@ -0,0 +1,3 @@
+-- 用户 token 版本号，退出登录、修改权限或角色时加 1，使已签发的 token 全部失效
+ALTER TABLE `usr_silent`
+  ADD COLUMN `token_ver` int unsigned NOT NULL DEFAULT '0' COMMENT 'token 版本号' AFTER `password`;
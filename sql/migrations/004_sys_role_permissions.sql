@@ -0,0 +1,53 @@
+-- 角色权限改为接口声明的权限 key，不再按路径包含匹配
+ALTER TABLE `sys_role`
+  ADD COLUMN `permissions` varchar(1024) DEFAULT '' COMMENT '角色拥有的接口权限 key，以逗号分开' AFTER `identifier`;
+
+-- 原有的 api_paths 按请求路径包含匹配（路径中包含其中一项即可访问），
+-- 转换时保持同样的范围：如 `/sales`、`/sales/invite` 转换为其下所有接口的权限
+CREATE TEMPORARY TABLE `tmp_perm_route` (
+  `route` varchar(100) NOT NULL,
+  `perm` varchar(64) NOT NULL
+);
+INSERT INTO `tmp_perm_route` VALUES
+  ('/sales/invite/sale/code', 'sales:invite_sale_code'),
+  ('/sales/invite/sale/del', 'sales:invite_sale_del'),
+  ('/sales/invite/user/code', 'sales:invite_user_code'),
+  ('/sales/invite/user/del', 'sales:invite_user_del'),
+  ('/sales/list/sale/{page}/{limit}', 'sales:list_sale'),
+  ('/sales/list/user/{page}/{limit}', 'sales:list_user'),
+  ('/mall/write_off/do', 'mall:write_off_do');
+
+-- 每个角色的 api_paths 拆为多行
+CREATE TEMPORARY TABLE `tmp_role_path` AS
+  SELECT `sys_role`.`id` AS `role_id`, TRIM(`jt`.`path`) AS `path`
+  FROM `sys_role`,
+    JSON_TABLE(
+      CONCAT('["', REPLACE(REPLACE(IFNULL(`sys_role`.`api_paths`, ''), '"', ''), ',', '","'), '"]'),
+      '$[*]' COLUMNS (`path` varchar(255) PATH '$')
+    ) AS `jt`
+  WHERE TRIM(`jt`.`path`) <> '';
+
+UPDATE `sys_role` SET `permissions` = IFNULL((
+  SELECT GROUP_CONCAT(DISTINCT `r`.`perm` ORDER BY `r`.`perm`)
+  FROM `tmp_role_path` AS `p`
+  INNER JOIN `tmp_perm_route` AS `r` ON LOCATE(`p`.`path`, `r`.`route`) > 0
+  WHERE `p`.`role_id` = `sys_role`.`id`
+), '');
+
+-- 没有对应到任何接口权限的路径，记下来后再删除 api_paths，需要人工检查后在后台重新分配权限。
+-- 检查完可删除此表
+CREATE TABLE `sys_role_unmapped_path` (
+  `role_id` int NOT NULL,
+  `path` varchar(255) NOT NULL,
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='系统：角色权限迁移时未对应的路径';
+INSERT INTO `sys_role_unmapped_path` (`role_id`, `path`)
+  SELECT `p`.`role_id`, `p`.`path`
+  FROM `tmp_role_path` AS `p`
+  WHERE NOT EXISTS (SELECT 1 FROM `tmp_perm_route` AS `r` WHERE LOCATE(`p`.`path`, `r`.`route`) > 0);
+SELECT `role_id`, `path` AS `unmapped_api_path` FROM `sys_role_unmapped_path`;
+
+DROP TEMPORARY TABLE `tmp_role_path`;
+DROP TEMPORARY TABLE `tmp_perm_route`;
+
+ALTER TABLE `sys_role` DROP COLUMN `api_paths`;
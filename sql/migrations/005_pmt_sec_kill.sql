@@ -0,0 +1,19 @@
+-- 秒杀活动：时间段、每人限购、秒杀价、秒杀库存
+ALTER TABLE `pmt_sec_kill`
+  ADD COLUMN `title` varchar(100) NOT NULL DEFAULT '' COMMENT '活动名' AFTER `id`,
+  ADD COLUMN `unit_sn` int NOT NULL COMMENT '秒杀的商品编号' AFTER `title`,
+  ADD COLUMN `price` decimal(10,2) NOT NULL COMMENT '秒杀价' AFTER `unit_sn`,
+  ADD COLUMN `stock` int NOT NULL DEFAULT '0' COMMENT '秒杀库存' AFTER `price`,
+  ADD COLUMN `sold` int NOT NULL DEFAULT '0' COMMENT '已抢购数量，取消订单时减回' AFTER `stock`,
+  ADD COLUMN `limit_per_user` int NOT NULL DEFAULT '1' COMMENT '每人限购数量' AFTER `sold`,
+  ADD COLUMN `start_time` datetime NOT NULL COMMENT '开始时间' AFTER `limit_per_user`,
+  ADD COLUMN `end_time` datetime NOT NULL COMMENT '结束时间' AFTER `start_time`,
+  MODIFY COLUMN `status` tinyint DEFAULT '1' COMMENT '通用状态 0为审核不通过 1为审核 2正常上线 3为下架',
+  ADD KEY `unit_sn` (`unit_sn`),
+  ADD KEY `end_time` (`end_time`);
+
+-- 购物车、订单项记录秒杀活动，用于按秒杀价下单、取消订单时返还秒杀库存
+ALTER TABLE `ord_shop_cart`
+  ADD COLUMN `sec_kill_id` int DEFAULT NULL COMMENT '秒杀活动 pmt_sec_kill.id';
+ALTER TABLE `ord_order_item`
+  ADD COLUMN `sec_kill_id` int DEFAULT NULL COMMENT '秒杀活动 pmt_sec_kill.id';
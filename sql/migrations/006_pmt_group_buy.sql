@@ -0,0 +1,70 @@
+-- 拼团活动：拼团价、成团人数、成团时限
+ALTER TABLE `pmt_group_buy`
+  ADD COLUMN `title` varchar(100) NOT NULL DEFAULT '' COMMENT '活动名' AFTER `id`,
+  ADD COLUMN `unit_sn` int NOT NULL COMMENT '拼团的商品编号' AFTER `title`,
+  ADD COLUMN `price` decimal(10,2) NOT NULL COMMENT '拼团价' AFTER `unit_sn`,
+  ADD COLUMN `group_size` int NOT NULL DEFAULT '2' COMMENT '成团人数' AFTER `price`,
+  ADD COLUMN `group_hours` int NOT NULL DEFAULT '24' COMMENT '开团后多少小时内成团，超时未成团自动退款' AFTER `group_size`,
+  ADD COLUMN `start_time` datetime NOT NULL COMMENT '开始时间' AFTER `group_hours`,
+  ADD COLUMN `end_time` datetime NOT NULL COMMENT '结束时间，之后不能再开团' AFTER `start_time`,
+  MODIFY COLUMN `status` tinyint DEFAULT '1' COMMENT '通用状态 0为审核不通过 1为审核 2正常上线 3为下架',
+  ADD KEY `unit_sn` (`unit_sn`),
+  ADD KEY `end_time` (`end_time`);
+
+-- 用户开的团
+CREATE TABLE `ord_group` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `group_buy_id` int NOT NULL COMMENT '拼团活动 pmt_group_buy.id',
+  `share_code` varchar(32) NOT NULL COMMENT '分享码，其他用户通过分享码参团',
+  `leader_uid` bigint NOT NULL COMMENT '团长',
+  `group_size` int NOT NULL COMMENT '成团人数，开团时的活动设置',
+  `joined` int NOT NULL DEFAULT '0' COMMENT '已占名额，待支付和已支付的成员',
+  `paid` int NOT NULL DEFAULT '0' COMMENT '已支付的成员',
+  `status` tinyint NOT NULL DEFAULT '0' COMMENT '0待开团(团长未支付) 1拼团中 2已成团 3拼团失败',
+  `expire_time` datetime DEFAULT NULL COMMENT '成团截止时间，团长支付后开始计时',
+  `success_time` datetime DEFAULT NULL COMMENT '成团时间',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `share_code` (`share_code`),
+  KEY `group_buy_id` (`group_buy_id`),
+  KEY `status_expire` (`status`,`expire_time`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：拼团';
+
+-- 团成员，每个成员一个订单
+CREATE TABLE `ord_group_member` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `group_id` bigint NOT NULL COMMENT 'ord_group.id',
+  `uid` bigint NOT NULL COMMENT '用户id',
+  `order_sn` varchar(50) NOT NULL COMMENT '订单号',
+  `is_leader` tinyint NOT NULL DEFAULT '0' COMMENT '是否为团长',
+  `status` tinyint NOT NULL DEFAULT '1' COMMENT '0已取消 1待支付 2已支付 3已退款',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `order_sn` (`order_sn`),
+  KEY `group_id` (`group_id`),
+  KEY `uid` (`uid`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：拼团成员';
+
+-- 团的状态变更记录
+CREATE TABLE `ord_group_log` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `group_id` bigint NOT NULL COMMENT 'ord_group.id',
+  `status` tinyint NOT NULL COMMENT '变更后团的状态',
+  `content` varchar(255) NOT NULL DEFAULT '' COMMENT '变更说明',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `group_id` (`group_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：拼团状态记录';
+
+-- 购物车记录拼团活动和要参加的团，开团时 group_id 为空
+ALTER TABLE `ord_shop_cart`
+  ADD COLUMN `group_buy_id` int DEFAULT NULL COMMENT '拼团活动 pmt_group_buy.id',
+  ADD COLUMN `group_id` bigint DEFAULT NULL COMMENT '参加的团 ord_group.id';
+ALTER TABLE `ord_order`
+  ADD COLUMN `group_id` bigint DEFAULT NULL COMMENT '拼团订单所在的团 ord_group.id',
+  MODIFY COLUMN `status` tinyint DEFAULT '1' COMMENT '1待支付，2已支付，0取消支付，4为申请退款，5为已退款，6为退款中，7为拒绝退款，8为已支付待成团';
+ALTER TABLE `ord_order_item`
+  MODIFY COLUMN `status` int DEFAULT '0' COMMENT '用户商品订单状态  0 待发货，1 待收货, 2 已完成, 3 已评价，4 申请退货，5 已退货，6退款中，7拒绝退款，8待成团';
@@ -0,0 +1,36 @@
+-- 商品评价：每个已完成的子订单评价一次
+ALTER TABLE `eva_product`
+  ADD COLUMN `uid` bigint NOT NULL COMMENT '用户id' AFTER `id`,
+  ADD COLUMN `order_item_id` varchar(50) NOT NULL COMMENT '子订单id ord_order_item.order_item_id' AFTER `uid`,
+  ADD COLUMN `order_sn` varchar(50) NOT NULL COMMENT '订单编号' AFTER `order_item_id`,
+  ADD COLUMN `product_sn` int NOT NULL COMMENT '产品编号' AFTER `order_sn`,
+  ADD COLUMN `unit_sn` int NOT NULL COMMENT '商品编号' AFTER `product_sn`,
+  ADD COLUMN `unit_name` varchar(100) DEFAULT NULL COMMENT '商品名' AFTER `unit_sn`,
+  ADD COLUMN `rating` tinyint NOT NULL COMMENT '评分 1-5' AFTER `unit_name`,
+  ADD COLUMN `content` varchar(500) NOT NULL DEFAULT '' COMMENT '评价内容' AFTER `rating`,
+  ADD COLUMN `imgs` varchar(1024) DEFAULT NULL COMMENT '评价图片，多个用逗号分隔' AFTER `content`,
+  ADD COLUMN `reply` varchar(500) DEFAULT NULL COMMENT '商家回复' AFTER `imgs`,
+  ADD COLUMN `reply_at` datetime DEFAULT NULL COMMENT '回复时间' AFTER `reply`,
+  MODIFY COLUMN `status` tinyint DEFAULT '1' COMMENT '0审核不通过 1待审核 2正常显示 3已隐藏',
+  ADD UNIQUE KEY `order_item_id` (`order_item_id`),
+  ADD KEY `product_status` (`product_sn`,`status`);
+
+-- 店铺评分，可选
+ALTER TABLE `eva_com_store`
+  ADD COLUMN `uid` bigint NOT NULL COMMENT '用户id' AFTER `id`,
+  ADD COLUMN `order_item_id` varchar(50) NOT NULL COMMENT '子订单id' AFTER `uid`,
+  ADD COLUMN `store_code` int NOT NULL COMMENT '店铺编号' AFTER `order_item_id`,
+  ADD COLUMN `rating` tinyint NOT NULL COMMENT '评分 1-5' AFTER `store_code`,
+  MODIFY COLUMN `status` tinyint DEFAULT '1' COMMENT '与商品评价的状态一致',
+  ADD UNIQUE KEY `order_item_id` (`order_item_id`),
+  ADD KEY `store_code` (`store_code`);
+
+-- 物流评分，可选
+ALTER TABLE `eva_delivery`
+  ADD COLUMN `uid` bigint NOT NULL COMMENT '用户id' AFTER `id`,
+  ADD COLUMN `order_item_id` varchar(50) NOT NULL COMMENT '子订单id' AFTER `uid`,
+  ADD COLUMN `order_sn` varchar(50) NOT NULL COMMENT '订单编号' AFTER `order_item_id`,
+  ADD COLUMN `rating` tinyint NOT NULL COMMENT '评分 1-5' AFTER `order_sn`,
+  MODIFY COLUMN `status` tinyint DEFAULT '1' COMMENT '与商品评价的状态一致',
+  ADD UNIQUE KEY `order_item_id` (`order_item_id`),
+  ADD KEY `order_sn` (`order_sn`);
@@ -0,0 +1,49 @@
+-- 售后：按子订单、数量申请仅退款或退货退款
+CREATE TABLE `ord_after_sale` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `after_sale_sn` varchar(50) NOT NULL COMMENT '售后单号',
+  `uid` bigint NOT NULL COMMENT '用户id',
+  `order_sn` varchar(50) NOT NULL COMMENT '订单号',
+  `type` tinyint NOT NULL COMMENT '1仅退款 2退货退款',
+  `reason` varchar(255) NOT NULL DEFAULT '' COMMENT '申请原因',
+  `imgs` varchar(1024) DEFAULT NULL COMMENT '凭证图片，多个用逗号分隔',
+  `refund_amount` decimal(10,2) NOT NULL COMMENT '退款金额',
+  `pay_type` varchar(50) DEFAULT NULL COMMENT '原订单的支付类型，原路退回',
+  `out_refund_no` varchar(64) DEFAULT NULL COMMENT '微信退款单号',
+  `status` tinyint NOT NULL DEFAULT '1' COMMENT '0已拒绝 1待审核 2待寄回 3待收货 4退款中 5已退款 6已取消',
+  `remark` varchar(255) DEFAULT NULL COMMENT '后台处理说明，如拒绝原因',
+  `delivery_name` varchar(50) DEFAULT NULL COMMENT '退货物流公司',
+  `waybill_id` varchar(50) DEFAULT NULL COMMENT '退货运单号',
+  `refund_time` datetime DEFAULT NULL COMMENT '退款完成时间',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `after_sale_sn` (`after_sale_sn`),
+  UNIQUE KEY `out_refund_no` (`out_refund_no`),
+  KEY `uid` (`uid`),
+  KEY `order_sn` (`order_sn`),
+  KEY `status` (`status`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：售后';
+
+-- 售后单里的商品
+CREATE TABLE `ord_after_sale_item` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `after_sale_id` bigint NOT NULL COMMENT 'ord_after_sale.id',
+  `order_item_id` varchar(50) NOT NULL COMMENT '子订单id',
+  `unit_sn` int NOT NULL COMMENT '商品编号',
+  `quantity` int NOT NULL COMMENT '退的数量',
+  `refund_amount` decimal(10,2) NOT NULL COMMENT '分摊的退款金额',
+  `item_status` tinyint NOT NULL COMMENT '申请前子订单的状态，拒绝、取消或部分退款后恢复',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `after_sale_id` (`after_sale_id`),
+  KEY `order_item_id` (`order_item_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：售后商品';
+
+ALTER TABLE `ord_order_item`
+  ADD COLUMN `refund_quantity` int NOT NULL DEFAULT '0' COMMENT '售后已退的数量' AFTER `amount`;
+
+INSERT INTO `sys_constants` (`key`, `label`, `value`) VALUES
+  ('tran_type', '总销售分账扣回', 'MAIN_SALE_SPLIT_BACK'),
+  ('tran_type', '销售分账扣回', 'SALE_SPLIT_BACK');
@@ -0,0 +1,35 @@
+-- 销售佣金：支付后先冻结，商品完成并过了冻结期后才结算到零钱，退款时取消或扣回
+CREATE TABLE `usr_commission` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `uid` bigint NOT NULL COMMENT '拿佣金的销售或总销售',
+  `order_sn` varchar(50) NOT NULL COMMENT '订单号',
+  `order_item_id` varchar(50) NOT NULL COMMENT '子订单id',
+  `unit_sn` int NOT NULL COMMENT '商品编号',
+  `tran_type` varchar(20) NOT NULL COMMENT 'MAIN_SALE_SPLIT 总销售分账，SALE_SPLIT 销售分账',
+  `pay_type` varchar(50) DEFAULT NULL COMMENT '订单的支付类型',
+  `unit_amount` decimal(10,2) NOT NULL COMMENT '单件商品的佣金',
+  `quantity` int NOT NULL COMMENT '购买数量',
+  `refund_quantity` int NOT NULL DEFAULT '0' COMMENT '已退的数量',
+  `amount` decimal(10,2) NOT NULL COMMENT '下单时的佣金',
+  `settled_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '结算入零钱的佣金',
+  `back_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '结算后退款扣回的佣金',
+  `status` tinyint NOT NULL DEFAULT '0' COMMENT '0冻结中 1已结算 2已取消',
+  `settle_time` datetime DEFAULT NULL COMMENT '结算时间',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `uid_status` (`uid`,`status`),
+  KEY `order_sn` (`order_sn`),
+  KEY `order_item_id` (`order_item_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='用户：销售佣金';
+
+ALTER TABLE `ord_order_item`
+  ADD COLUMN `complete_time` datetime DEFAULT NULL COMMENT '完成时间，佣金的冻结期从此开始' AFTER `refund_quantity`;
+
+-- 已完成的子订单，按最后修改时间补上完成时间
+UPDATE `ord_order_item` SET `complete_time` = `updated_at` WHERE `status` IN (2, 3) AND `complete_time` IS NULL;
+
+-- 有销售、客户列表权限的角色，同时开通我的佣金
+UPDATE `sys_role` SET `permissions` = CONCAT_WS(',', NULLIF(`permissions`, ''), 'sales:commission')
+  WHERE FIND_IN_SET('sales:list_sale', `permissions`) OR FIND_IN_SET('sales:list_user', `permissions`);
@@ -0,0 +1,25 @@
+-- 佣金规则：按产品、分类、品牌、店铺设置，按实付比例或每件固定金额，可按销售的月销售额分档
+CREATE TABLE `sal_commission_rule` (
+  `id` int NOT NULL AUTO_INCREMENT,
+  `name` varchar(100) NOT NULL DEFAULT '' COMMENT '规则名',
+  `scope_type` tinyint NOT NULL COMMENT '适用范围 1产品 2分类 3品牌 4店铺，优先级依次降低',
+  `scope_id` int NOT NULL COMMENT '产品为 product_sn，分类为分类id，品牌为 brand_code，店铺为 store_code',
+  `split_type` tinyint NOT NULL COMMENT '1按实付金额的百分比 2每件固定金额',
+  `main_sale_value` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '总销售的分成，百分比或金额',
+  `sale_value` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '销售的分成，百分比或金额',
+  `tiers` varchar(1024) DEFAULT NULL COMMENT '按月销售额分档，json：[{"min_volume":10000,"main_sale_value":2,"sale_value":6}]',
+  `status` tinyint DEFAULT '2' COMMENT '通用状态 2正常 3下线',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `scope` (`scope_type`,`scope_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='分销：佣金规则';
+
+ALTER TABLE `usr_commission`
+  ADD COLUMN `rule_id` int DEFAULT NULL COMMENT '使用的佣金规则，空为商品上的固定分成' AFTER `pay_type`,
+  ADD COLUMN `base_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '商品实付（分摊优惠券后），计入销售的月销售额' AFTER `rule_id`;
+
+-- 已有的佣金，按子订单原价补上
+UPDATE `usr_commission` INNER JOIN `ord_order_item` ON `usr_commission`.`order_item_id` = `ord_order_item`.`order_item_id`
+  SET `usr_commission`.`base_amount` = `ord_order_item`.`amount`;
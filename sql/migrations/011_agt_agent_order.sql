@@ -0,0 +1,36 @@
+-- 代理：区域内已支付订单的代理分成记录
+CREATE TABLE `agt_agent_order` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `uid` bigint NOT NULL COMMENT '代理人用户id',
+  `area_id` int NOT NULL COMMENT 'agt_agent_area 的 id',
+  `level` tinyint NOT NULL COMMENT '1省代理 2市代理',
+  `order_sn` varchar(50) NOT NULL COMMENT '订单编号',
+  `base_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '订单商品实付（不含运费）',
+  `percent` decimal(5,2) NOT NULL DEFAULT '0.00' COMMENT '分成的百分比',
+  `amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '分成金额',
+  `back_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '退款扣回的分成',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `order_area` (`order_sn`,`area_id`),
+  KEY `uid` (`uid`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='代理：订单分成';
+
+-- 代理金额改为分成金额，且不能为空
+UPDATE `agt_amount` SET `total_amount` = IFNULL(`total_amount`, 0), `withdraw_amount` = IFNULL(`withdraw_amount`, 0),
+  `have_amount` = IFNULL(`have_amount`, 0);
+ALTER TABLE `agt_amount`
+  MODIFY COLUMN `total_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '累计分成金额（已减去退款扣回的）',
+  MODIFY COLUMN `withdraw_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '累计提现金额',
+  MODIFY COLUMN `have_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '未提现金额';
+
+ALTER TABLE `agt_agent_area`
+  ADD KEY `area` (`province`,`city`);
+
+INSERT INTO `sys_constants` (`key`, `label`, `value`) VALUES
+  ('tran_type', '代理分成转入', 'AGENT_INCOME');
+
+-- 代理角色开通代理看板、代理提现
+UPDATE `sys_role` SET `permissions` = CONCAT_WS(',', NULLIF(`permissions`, ''), 'agent:dashboard,agent:withdraw')
+  WHERE `identifier` = 2000;
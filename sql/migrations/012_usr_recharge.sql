@@ -0,0 +1,25 @@
+-- 充值金额的预设，可设置赠送金额
+ALTER TABLE `sys_recharge_amount`
+  ADD COLUMN `bonus_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '充值后赠送的零钱，单位元' AFTER `amount`;
+
+-- 零钱充值单，微信支付成功后到账
+CREATE TABLE `usr_recharge` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `uid` bigint NOT NULL COMMENT '用户id',
+  `out_trade_no` varchar(50) NOT NULL COMMENT '微信支付的商户订单号，RC 开头',
+  `recharge_amount_id` int NOT NULL COMMENT '选择的 sys_recharge_amount',
+  `amount` decimal(10,2) NOT NULL COMMENT '充值金额',
+  `bonus_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '赠送金额',
+  `transaction_id` varchar(50) DEFAULT NULL COMMENT '微信支付订单号',
+  `pay_time` datetime DEFAULT NULL COMMENT '到账时间',
+  `status` tinyint NOT NULL DEFAULT '1' COMMENT '0已取消 1待支付 2已到账',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `out_trade_no` (`out_trade_no`),
+  KEY `uid_status` (`uid`,`status`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='用户：零钱充值';
+
+INSERT INTO `sys_constants` (`key`, `label`, `value`) VALUES
+  ('tran_type', '充值赠送', 'RECHARGE_BONUS');
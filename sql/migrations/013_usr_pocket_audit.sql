@@ -0,0 +1,15 @@
+-- 零钱对账发现的问题，每次对账一个批次
+CREATE TABLE `usr_pocket_audit` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `batch` varchar(20) NOT NULL COMMENT '对账批次，对账开始的时间 yyyyMMddHHmmss',
+  `uid` bigint NOT NULL COMMENT '用户id',
+  `kind` tinyint NOT NULL COMMENT '1 钱包金额的 hash 不符，2 余额与交易记录合计不符，3 交易记录的 hash 不符',
+  `record_id` bigint DEFAULT NULL COMMENT 'kind 为 3 时，对应的 usr_transaction_records.id',
+  `amount` decimal(10,2) NOT NULL COMMENT '钱包余额，kind 为 3 时为交易金额',
+  `expected_amount` decimal(10,2) DEFAULT NULL COMMENT 'kind 为 2 时，交易记录的合计',
+  `is_frozen` tinyint NOT NULL DEFAULT '0' COMMENT '本次对账是否冻结了该钱包',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `batch` (`batch`),
+  KEY `uid` (`uid`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='用户：零钱对账的问题记录';
@@ -0,0 +1,35 @@
+-- 满减活动：不需要优惠券，订单（或店铺的商品）满足金额时自动减，可设置多档
+CREATE TABLE `pmt_full_reduction` (
+  `id` int NOT NULL AUTO_INCREMENT,
+  `title` varchar(100) NOT NULL DEFAULT '' COMMENT '活动名',
+  `store_code` int DEFAULT NULL COMMENT '店铺的满减只算该店铺的商品，空为平台满减',
+  `tiers` varchar(1024) NOT NULL COMMENT '分档，json：[{"full_amount":300,"reduce_amount":30},{"full_amount":500,"reduce_amount":80}]',
+  `start_time` datetime NOT NULL COMMENT '开始时间',
+  `end_time` datetime NOT NULL COMMENT '结束时间',
+  `status` tinyint DEFAULT '2' COMMENT '通用状态 2正常 3下线',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `store_code` (`store_code`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='促销：满减活动';
+
+-- 订单使用的每一项优惠，按 满减活动、店铺券、平台券 的顺序
+CREATE TABLE `ord_order_discount` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `order_sn` varchar(50) NOT NULL,
+  `seq` tinyint NOT NULL DEFAULT '0' COMMENT '使用的顺序',
+  `kind` tinyint NOT NULL COMMENT '1满减活动 2店铺券 3平台券',
+  `ref_id` bigint NOT NULL COMMENT '满减活动为 pmt_full_reduction.id，优惠券为 usr_coupon.id',
+  `store_code` int DEFAULT NULL COMMENT '店铺的满减、店铺券的店铺',
+  `title` varchar(255) NOT NULL DEFAULT '' COMMENT '优惠描述',
+  `amount` decimal(10,2) NOT NULL COMMENT '优惠金额',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `order_sn` (`order_sn`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：优惠明细';
+
+-- 子订单分摊到的优惠，之前的订单为空，退款、分佣时按订单实付和原价占比分摊
+ALTER TABLE `ord_order_item`
+  ADD COLUMN `reduce_amount` decimal(10,2) DEFAULT NULL COMMENT '分摊到的优惠金额' AFTER `amount`,
+  ADD COLUMN `pay_amount` decimal(10,2) DEFAULT NULL COMMENT '分摊优惠后的实付' AFTER `reduce_amount`;
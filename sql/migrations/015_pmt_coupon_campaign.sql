@@ -0,0 +1,48 @@
+-- 每人可领取的张数，自己领取、活动发放、兑换码兑换都受此限制
+ALTER TABLE `pmt_coupon`
+  ADD COLUMN `per_user_limit` int NOT NULL DEFAULT '1' COMMENT '每人最多领取的张数' AFTER `coupon_num`;
+
+-- 同一优惠券每人可以有多张，去掉 (coupon_id, uid) 的唯一索引，记录发放的活动
+ALTER TABLE `usr_coupon`
+  DROP INDEX `sn`,
+  ADD KEY `uid_coupon` (`uid`,`coupon_id`),
+  ADD COLUMN `campaign_id` int DEFAULT NULL COMMENT '发放的活动，用户自己领取的为空' AFTER `coupon_id`;
+
+-- 优惠券发放活动：批量发放给一批用户、生成兑换码、新用户首次登录发放
+CREATE TABLE `pmt_coupon_campaign` (
+  `id` int NOT NULL AUTO_INCREMENT,
+  `title` varchar(100) NOT NULL DEFAULT '' COMMENT '活动名',
+  `coupon_id` int NOT NULL COMMENT '发放的优惠券',
+  `kind` tinyint NOT NULL COMMENT '1批量发放 2兑换码 3新用户首次登录发放',
+  `target` tinyint DEFAULT NULL COMMENT '批量发放的用户：1全部用户 2某个角色 3购买过某个产品 4最近N天注册',
+  `target_value` varchar(50) DEFAULT NULL COMMENT '角色编号、产品编号或天数',
+  `quantity` int NOT NULL DEFAULT '1' COMMENT '每人发放的张数，不超过优惠券的每人限领张数',
+  `code_count` int NOT NULL DEFAULT '0' COMMENT '兑换码的数量',
+  `start_time` datetime DEFAULT NULL COMMENT '兑换码可兑换、新用户发放的开始时间，空为不限',
+  `end_time` datetime DEFAULT NULL COMMENT '兑换码可兑换、新用户发放的结束时间，空为不限',
+  `status` tinyint NOT NULL DEFAULT '0' COMMENT '0待执行 1排队中 2执行中 3已完成 4失败 5已停止',
+  `run_at` datetime DEFAULT NULL COMMENT '开始执行的时间，最近N天注册按此时间算',
+  `last_uid` bigint NOT NULL DEFAULT '0' COMMENT '批量发放已处理到的用户id，中断后从此继续',
+  `done_count` int NOT NULL DEFAULT '0' COMMENT '已发放的张数，兑换码为已生成的数量',
+  `message` varchar(1000) DEFAULT NULL COMMENT '执行结果',
+  `mana_uid` bigint DEFAULT NULL COMMENT '创建的管理员',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `kind_status` (`kind`,`status`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='促销：优惠券发放活动';
+
+-- 兑换码，一个码只能兑换一次
+CREATE TABLE `pmt_coupon_code` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `campaign_id` int NOT NULL,
+  `coupon_id` int NOT NULL,
+  `code` varchar(32) NOT NULL,
+  `uid` bigint DEFAULT NULL COMMENT '兑换的用户，空为未兑换',
+  `redeemed_at` datetime DEFAULT NULL COMMENT '兑换时间',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `code` (`code`),
+  KEY `campaign_id` (`campaign_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='促销：优惠券兑换码';
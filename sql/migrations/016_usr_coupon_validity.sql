@@ -0,0 +1,17 @@
+-- 优惠券的有效期：1 固定时间，可用时间为 start_time 到 expire_time；2 领取后 valid_days 天内有效，expire_time 为领取的截止时间
+ALTER TABLE `pmt_coupon`
+  ADD COLUMN `valid_type` tinyint NOT NULL DEFAULT '1' COMMENT '有效期类型 1固定时间 2领取后N天内有效' AFTER `expire_time`,
+  ADD COLUMN `start_time` datetime DEFAULT NULL COMMENT '固定时间的开始时间，空为领取后即可用' AFTER `valid_type`,
+  ADD COLUMN `valid_days` int DEFAULT NULL COMMENT '领取后多少天内有效' AFTER `start_time`;
+
+-- 用户的每张优惠券，领取时按优惠券的有效期确定可用时间
+ALTER TABLE `usr_coupon`
+  ADD COLUMN `start_time` datetime DEFAULT NULL COMMENT '可用的开始时间，空为不限' AFTER `status`,
+  ADD COLUMN `end_time` datetime DEFAULT NULL COMMENT '过期时间，空为不过期' AFTER `start_time`,
+  ADD COLUMN `remind_at` datetime DEFAULT NULL COMMENT '到期提醒的时间，空为未提醒' AFTER `end_time`,
+  ADD KEY `status_end_time` (`status`,`end_time`);
+
+-- 之前领取的，按优惠券的过期时间
+UPDATE `usr_coupon` INNER JOIN `pmt_coupon` ON `usr_coupon`.`coupon_id` = `pmt_coupon`.`id`
+  SET `usr_coupon`.`end_time` = `pmt_coupon`.`expire_time`
+  WHERE `usr_coupon`.`end_time` IS NULL;
@@ -0,0 +1,24 @@
+-- 运费模板：固定运费、按件数、按重量计费，可按地区设置不同的规则，满金额或满件数包邮
+CREATE TABLE `ord_freight_template` (
+  `id` int NOT NULL AUTO_INCREMENT,
+  `title` varchar(100) NOT NULL DEFAULT '' COMMENT '模板名',
+  `store_code` int DEFAULT NULL COMMENT '所属店铺，平台的为空',
+  `charge_type` tinyint NOT NULL DEFAULT '1' COMMENT '计费方式 1固定运费 2按件数 3按重量',
+  `rules` json NOT NULL COMMENT '计费规则，regions 为空的是默认规则，其他按省或 省/市 匹配收货地址',
+  `is_del` tinyint DEFAULT '0',
+  `created_at` datetime DEFAULT CURRENT_TIMESTAMP,
+  `updated_at` datetime DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (`id`),
+  KEY `store_code` (`store_code`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='订单：运费模板';
+
+-- 产品的运费模板，为空时使用店铺的运费模板，都没有的包邮
+ALTER TABLE `spu_product`
+  ADD COLUMN `freight_template_id` int DEFAULT NULL COMMENT '运费模板，为空时使用店铺的' AFTER `delivery_type`;
+
+ALTER TABLE `com_store`
+  ADD COLUMN `freight_template_id` int DEFAULT NULL COMMENT '店铺商品默认的运费模板，为空时包邮';
+
+-- 按重量计费时，商品的重量
+ALTER TABLE `sku_unit`
+  ADD COLUMN `weight` int NOT NULL DEFAULT '0' COMMENT '重量 g，按重量计算运费' AFTER `price`;
@@ -0,0 +1,3 @@
+-- 购物车的商品是否选中结算，加入购物车时默认选中
+ALTER TABLE `ord_shop_cart`
+  ADD COLUMN `is_selected` tinyint NOT NULL DEFAULT '1' COMMENT '是否选中结算 1选中 0未选中' AFTER `buy_quantity`;
@@ -0,0 +1,6 @@
+-- 订单记录每个店铺的运费，售后退完订单的全部商品时退回剩余的运费
+ALTER TABLE `ord_order`
+  ADD COLUMN `freights` json DEFAULT NULL COMMENT '每个店铺的运费 [{store_code, amount}]，不需要物流的为空' AFTER `delivery_amount`;
+
+ALTER TABLE `ord_after_sale`
+  ADD COLUMN `freight_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '退款金额中退回的运费' AFTER `refund_amount`;
@@ -0,0 +1,3 @@
+-- 支付回调的金额与订单金额不一致时，不改订单状态，记下异常待人工处理，并应答回调
+ALTER TABLE `ord_order`
+  ADD COLUMN `pay_error` varchar(255) DEFAULT NULL COMMENT '支付回调异常（如金额不一致），不为空时需人工处理，超时任务不再取消' AFTER `transaction_id`;
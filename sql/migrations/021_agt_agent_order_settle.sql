@@ -0,0 +1,14 @@
+-- 代理分成先冻结，订单的商品都完成并过了冻结期后，才计入未提现金额；结算后退款扣回不足的记为差额
+ALTER TABLE `agt_agent_order`
+  ADD COLUMN `refund_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '已退款的商品金额，累计退完时扣回剩下的全部分成' AFTER `back_amount`,
+  ADD COLUMN `shortfall` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '结算后退款，未提现金额不足没扣回的分成，由后台线下处理' AFTER `refund_amount`,
+  ADD COLUMN `status` tinyint NOT NULL DEFAULT '0' COMMENT '0冻结中 1已结算 2已取消' AFTER `shortfall`,
+  ADD COLUMN `settle_time` datetime DEFAULT NULL COMMENT '结算时间' AFTER `status`,
+  ADD KEY `status` (`status`);
+
+-- 之前的分成在支付时已计入未提现金额，记为已结算；已退款的商品金额按扣回的分成折算
+UPDATE `agt_agent_order` SET `status` = 1, `settle_time` = `created_at`,
+  `refund_amount` = IF(`amount` > 0, LEAST(ROUND(`base_amount` * `back_amount` / `amount`, 2), `base_amount`), 0);
+
+ALTER TABLE `agt_amount`
+  ADD COLUMN `freeze_amount` decimal(10,2) NOT NULL DEFAULT '0.00' COMMENT '冻结中的分成，结算后转入未提现金额' AFTER `have_amount`;
@@ -1,6 +1,12 @@
+mod config;
+pub(crate) use config::*;
+
 mod constants;
 pub(crate) use constants::*;
 
+mod money;
+pub(crate) use money::*;
+
 mod secret;
 pub(crate) use secret::*;
 
@@ -0,0 +1,280 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+use utoipa::openapi::schema::{KnownFormat, ObjectBuilder, SchemaFormat, Type};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
+
+/// 金额，内部以 分 为单位的整数保存，避免 f64 计算带来的分位误差。
+///
+/// 与数据库 decimal(10,2) 字段、接口 json 中的 元 互相转换：
+/// - 反序列化：支持 "12.30" 这类字符串（数据库读出的值），及 12.3 这类数字（前端传入的值）
+/// - 序列化：输出为 元 的数字，如 12.3
+/// - 写入数据库时，使用 `to_string()` 得到 "12.30"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    /// 0 元
+    pub const ZERO: Money = Money(0);
+
+    /// 通过 分 创建
+    pub const fn from_cent(cent: i64) -> Self {
+        Money(cent)
+    }
+    /// 获取 分，如微信支付的金额
+    pub const fn cent(self) -> i64 {
+        self.0
+    }
+    /// 通过 元 的浮点数创建。只用于解析前端传入的 json 数字
+    ///
+    /// 按数字的最短十进制表示解析，与字符串的规则一致：超过2位小数（如 1.234）的返回错误，不做四舍五入
+    pub fn from_yuan_f64(yuan: f64) -> anyhow::Result<Self> {
+        if !yuan.is_finite() {
+            anyhow::bail!("金额格式错误：{}", yuan);
+        }
+        yuan.to_string().parse()
+    }
+    /// 转为 元 的浮点数，只用于输出展示
+    pub fn to_yuan_f64(self) -> f64 {
+        self.0 as f64 / 100.
+    }
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+    /// 按百分比计算，四舍五入到分。如 打85折：`mul_percent(85)`
+    pub fn mul_percent(self, percent: i64) -> Self {
+        let v = self.0 as i128 * percent as i128;
+        let r = if v >= 0 {
+            (v + 50) / 100
+        } else {
+            (v - 50) / 100
+        };
+        Money(r as i64)
+    }
+    /// 去掉末尾 0 的金额字符串，如 12.50 为 "12.5"，12.00 为 "12"。
+    ///
+    /// 与以前 f64 的显示格式一致，用于生成金额的防篡改 hash，兼容已有数据
+    pub fn to_plain_string(self) -> String {
+        let s = self.to_string();
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        if s == "-0" {
+            "0".to_string()
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, abs / 100, abs % 100)
+    }
+}
+
+impl FromStr for Money {
+    type Err = anyhow::Error;
+    /// 解析 "12"、"12.3"、"-12.30"、"00000012.30"(zerofill) 等格式，最多2位小数
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let t = s.trim();
+        let (neg, t) = match t.strip_prefix('-') {
+            Some(r) => (true, r),
+            None => (false, t.strip_prefix('+').unwrap_or(t)),
+        };
+        let (int_part, frac_part) = t.split_once('.').unwrap_or((t, ""));
+        let frac_part = frac_part.trim_end_matches('0');
+        if (int_part.is_empty() && frac_part.is_empty())
+            || !int_part.bytes().all(|b| b.is_ascii_digit())
+            || !frac_part.bytes().all(|b| b.is_ascii_digit())
+            || frac_part.len() > 2
+        {
+            anyhow::bail!("金额格式错误：{}", s);
+        }
+        let yuan = if int_part.is_empty() {
+            0
+        } else {
+            int_part
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("金额格式错误：{}", s))?
+        };
+        let frac = format!("{:0<2}", frac_part).parse::<i64>().unwrap_or(0);
+        let cent = yuan
+            .checked_mul(100)
+            .and_then(|v| v.checked_add(frac))
+            .ok_or_else(|| anyhow::anyhow!("金额超出范围：{}", s))?;
+        Ok(Money(if neg { -cent } else { cent }))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_yuan_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MoneyVisitor;
+        impl Visitor<'_> for MoneyVisitor {
+            type Value = Money;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("金额，数字或字符串，最多2位小数")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Money, E> {
+                v.parse::<Money>().map_err(E::custom)
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Money, E> {
+                v.checked_mul(100)
+                    .map(Money)
+                    .ok_or_else(|| E::custom("金额超出范围"))
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Money, E> {
+                i64::try_from(v)
+                    .ok()
+                    .and_then(|v| v.checked_mul(100))
+                    .map(Money)
+                    .ok_or_else(|| E::custom("金额超出范围"))
+            }
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Money, E> {
+                Money::from_yuan_f64(v).map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+impl PartialSchema for Money {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::Number)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Double)))
+            .description(Some("金额，单位元，最多2位小数"))
+            .examples([serde_json::json!(12.3)])
+            .into()
+    }
+}
+impl ToSchema for Money {}
+
+impl Add for Money {
+    type Output = Money;
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        self.0 -= rhs.0;
+    }
+}
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+/// 单价 * 数量
+impl Mul<u32> for Money {
+    type Output = Money;
+    fn mul(self, rhs: u32) -> Money {
+        Money(self.0 * rhs as i64)
+    }
+}
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, |a, b| a + b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_money_parse_display() {
+        assert_eq!("12.3".parse::<Money>().unwrap(), Money::from_cent(1230));
+        assert_eq!(
+            "00000012.30".parse::<Money>().unwrap(),
+            Money::from_cent(1230)
+        );
+        assert_eq!("-0.05".parse::<Money>().unwrap(), Money::from_cent(-5));
+        assert_eq!("8".parse::<Money>().unwrap(), Money::from_cent(800));
+        assert!("1.234".parse::<Money>().is_err());
+        assert!("1,2".parse::<Money>().is_err());
+        assert!("".parse::<Money>().is_err());
+        assert_eq!(Money::from_cent(1230).to_string(), "12.30");
+        assert_eq!(Money::from_cent(-5).to_string(), "-0.05");
+        assert_eq!(Money::from_cent(1230).to_plain_string(), "12.3");
+        assert_eq!(Money::from_cent(1200).to_plain_string(), "12");
+        assert_eq!(Money::ZERO.to_plain_string(), "0");
+    }
+
+    #[test]
+    fn test_money_serde() {
+        #[derive(Serialize, Deserialize)]
+        struct Item {
+            price: Money,
+            amount: Option<Money>,
+        }
+        let db: Item = serde_json::from_str(r#"{"price": "0.10", "amount": null}"#).unwrap();
+        assert_eq!(db.price, Money::from_cent(10));
+        assert_eq!(db.amount, None);
+        let req: Item = serde_json::from_str(r#"{"price": 19.99, "amount": 3}"#).unwrap();
+        assert_eq!(req.price, Money::from_cent(1999));
+        assert_eq!(req.amount, Some(Money::from_cent(300)));
+        // 不足1分的数字与字符串一样拒绝，不四舍五入
+        assert!(serde_json::from_str::<Item>(r#"{"price": 1.234, "amount": null}"#).is_err());
+        assert!(serde_json::from_str::<Item>(r#"{"price": "1.234", "amount": null}"#).is_err());
+        assert_eq!(Money::from_yuan_f64(0.1).unwrap(), Money::from_cent(10));
+        assert_eq!(
+            Money::from_yuan_f64(-12.5).unwrap(),
+            Money::from_cent(-1250)
+        );
+        assert!(Money::from_yuan_f64(0.001).is_err());
+        assert!(Money::from_yuan_f64(f64::NAN).is_err());
+        assert!(Money::from_yuan_f64(1e30).is_err());
+        assert_eq!(
+            serde_json::to_string(&req).unwrap(),
+            r#"{"price":19.99,"amount":3.0}"#
+        );
+    }
+
+    #[test]
+    fn test_money_calc() {
+        // f64 下 0.1 + 0.2 != 0.3
+        let a: Money = "0.1".parse().unwrap();
+        let b: Money = "0.2".parse().unwrap();
+        assert_eq!(a + b, "0.3".parse().unwrap());
+        assert_eq!(Money::from_cent(1999) * 3, Money::from_cent(5997));
+        assert_eq!(Money::from_cent(999).mul_percent(85), Money::from_cent(849));
+        assert_eq!(
+            Money::from_cent(1000).mul_percent(85),
+            Money::from_cent(850)
+        );
+        let total: Money = vec![Money::from_cent(1), Money::from_cent(2)]
+            .into_iter()
+            .sum();
+        assert_eq!(total, Money::from_cent(3));
+    }
+}
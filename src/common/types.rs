@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 use utoipa::ToSchema;
 
-use super::{FILE_STORAGE_TYPE, PROJECT_NAME, STATIC_FILE_URL};
+use super::{PROJECT_NAME, config};
 
 pub enum OssBucket {
     EobFiles,
@@ -11,7 +11,7 @@ impl OssBucket {
     pub fn get_name(&self) -> &str {
         match &self {
             OssBucket::EobFiles => {
-                if FILE_STORAGE_TYPE == 1 {
+                if config().file.storage_type == 1 {
                     "local-files"
                 } else {
                     "files"
@@ -22,10 +22,10 @@ impl OssBucket {
     pub fn get_base_url(&self) -> String {
         match &self {
             OssBucket::EobFiles => {
-                if FILE_STORAGE_TYPE == 1 {
-                    STATIC_FILE_URL.to_string()
+                if config().file.storage_type == 1 {
+                    config().file.static_file_url.clone()
                 } else {
-                    "https://oshou.aliyuncs.com".to_string()
+                    config().file.oss_base_url.clone()
                 }
             }
         }
@@ -50,6 +50,10 @@ pub enum FileDir {
     Brand,
     /// 用户提交表单里的图片文件
     QuestionForm,
+    /// 商品评价的图片
+    Review,
+    /// 售后申请的凭证图片
+    AfterSale,
     Empty,
 }
 impl FileDir {
@@ -66,6 +70,8 @@ impl FileDir {
             FileDir::Credential => format!("{}/credential", PROJECT_NAME),
             FileDir::Brand => format!("{}/brand", PROJECT_NAME),
             FileDir::QuestionForm => format!("{}/question_form", PROJECT_NAME),
+            FileDir::Review => format!("{}/review", PROJECT_NAME),
+            FileDir::AfterSale => format!("{}/after_sale", PROJECT_NAME),
             FileDir::Empty => "".to_string(),
         }
     }
@@ -84,6 +90,8 @@ impl From<String> for FileDir {
             "credential" => FileDir::Credential,
             "brand" => FileDir::Brand,
             "question_form" => FileDir::QuestionForm,
+            "review" => FileDir::Review,
+            "after_sale" => FileDir::AfterSale,
             _ => FileDir::Empty,
         }
     }
@@ -192,6 +200,22 @@ pub enum TranType {
     #[serde(rename = "SALE_SPLIT")]
     #[strum(to_string = "SALE_SPLIT")]
     SaleSplit,
+    /// 售后退款，扣回总销售分账 -
+    #[serde(rename = "MAIN_SALE_SPLIT_BACK")]
+    #[strum(to_string = "MAIN_SALE_SPLIT_BACK")]
+    MainSaleSplitBack,
+    /// 售后退款，扣回销售分账 -
+    #[serde(rename = "SALE_SPLIT_BACK")]
+    #[strum(to_string = "SALE_SPLIT_BACK")]
+    SaleSplitBack,
+    /// 代理提现时，代理分成转入零钱 +
+    #[serde(rename = "AGENT_INCOME")]
+    #[strum(to_string = "AGENT_INCOME")]
+    AgentIncome,
+    /// 充值赠送 +
+    #[serde(rename = "RECHARGE_BONUS")]
+    #[strum(to_string = "RECHARGE_BONUS")]
+    RechargeBonus,
     /// 未知交易类型
     #[serde(rename = "UNKNOWN")]
     #[strum(to_string = "UNKNOWN")]
@@ -206,6 +230,10 @@ impl From<String> for TranType {
             "REFUND" => TranType::Refund,
             "MAIN_SALE_SPLIT" => TranType::MainSaleSplit,
             "SALE_SPLIT" => TranType::SaleSplit,
+            "MAIN_SALE_SPLIT_BACK" => TranType::MainSaleSplitBack,
+            "SALE_SPLIT_BACK" => TranType::SaleSplitBack,
+            "AGENT_INCOME" => TranType::AgentIncome,
+            "RECHARGE_BONUS" => TranType::RechargeBonus,
             _ => TranType::Unknown,
         }
     }
@@ -358,7 +386,7 @@ pub enum UserCouponStatus {
     Used,
 }
 
-/// 用户订单支付状态 1 为待支付，2 为已支付，0 为取消支付  4 为申请退款  5 为已退款  6 为退款中
+/// 用户订单支付状态 1 为待支付，2 为已支付，0 为取消支付  4 为申请退款  5 为已退款  6 为退款中  8 为已支付待成团
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
 pub enum OrderPayStatus {
     /// 0 取消支付
@@ -375,9 +403,11 @@ pub enum OrderPayStatus {
     Refunding = 6,
     /// 7 为拒绝退款
     Refuse = 7,
+    /// 8 为拼团订单已支付，待成团。成团后改为已支付，未成团则自动退款
+    GroupPending = 8,
 }
 
-/// 用户子订单物流等状态 0 待发货，1 待收货, 2 已完成, 3 已评价，4 申请退货，5 已退货，6 为退款中
+/// 用户子订单物流等状态 0 待发货，1 待收货, 2 已完成, 3 已评价，4 申请退货，5 已退货，6 为退款中，8 为待成团
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
 pub enum OrderItemStatus {
     /// 0 为待发货
@@ -396,6 +426,8 @@ pub enum OrderItemStatus {
     Refunding,
     /// 7 为拒绝退款
     Refuse = 7,
+    /// 8 为待成团，成团前不能发货、核销
+    GroupPending = 8,
 }
 impl From<u8> for OrderItemStatus {
     fn from(value: u8) -> Self {
@@ -406,11 +438,136 @@ impl From<u8> for OrderItemStatus {
             3 => Self::Evaluated,
             4 => Self::Apply,
             5 => Self::Refund,
+            6 => Self::Refunding,
+            7 => Self::Refuse,
+            8 => Self::GroupPending,
             _ => Self::WaitDeliverGoods,
         }
     }
 }
 
+/// 团的状态 0 待开团（团长未支付），1 拼团中，2 已成团，3 拼团失败
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum GroupStatus {
+    /// 0 待开团，团长还未支付，其他用户不能参团
+    Opening,
+    /// 1 拼团中
+    Grouping,
+    /// 2 已成团
+    Success,
+    /// 3 拼团失败，超时未成团或团长未支付
+    Failed,
+}
+
+/// 团成员的状态 0 已取消，1 待支付，2 已支付，3 已退款
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum GroupMemberStatus {
+    /// 0 已取消，订单取消后释放名额
+    Cancelled,
+    /// 1 待支付
+    PendingPayment,
+    /// 2 已支付
+    Paid,
+    /// 3 已退款，未成团时自动退款
+    Refunded,
+}
+
+/// 售后类型，1 仅退款，2 退货退款
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum AfterSaleType {
+    /// 1 仅退款，未发货或不用寄回的商品
+    RefundOnly = 1,
+    /// 2 退货退款，用户寄回商品，后台确认收货后退款
+    ReturnRefund = 2,
+}
+
+/// 售后单的状态 0 已拒绝，1 待审核，2 待寄回，3 待收货，4 退款中，5 已退款，6 已取消
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum AfterSaleStatus {
+    /// 0 已拒绝
+    Rejected,
+    /// 1 待审核
+    Apply,
+    /// 2 待寄回，退货退款审核通过后，等用户填写退货运单
+    WaitReturn,
+    /// 3 待收货，用户已寄回，等后台确认收货
+    Returning,
+    /// 4 退款中，微信退款等回调
+    Refunding,
+    /// 5 已退款
+    Refunded,
+    /// 6 已取消，用户撤销申请
+    Cancelled,
+}
+
+/// 销售佣金的状态 0 冻结中，1 已结算，2 已取消
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum CommissionStatus {
+    /// 0 冻结中，商品完成并过了冻结期后结算
+    Pending,
+    /// 1 已结算，已入零钱
+    Settled,
+    /// 2 已取消，结算前全部退款
+    Cancelled,
+}
+
+/// 佣金规则适用的范围，同一商品匹配多条时，按 商品 > 分类 > 品牌 > 店铺 的顺序取第一条
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum CommissionScope {
+    /// 1 产品，scope_id 为 product_sn
+    Product = 1,
+    /// 2 分类，scope_id 为分类 id，三级分类优先于二级、一级
+    Category = 2,
+    /// 3 品牌，scope_id 为 brand_code
+    Brand = 3,
+    /// 4 店铺，scope_id 为 store_code
+    Store = 4,
+}
+
+/// 佣金的计算方式 1 按实付金额的百分比，2 每件固定金额
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum CommissionSplitType {
+    /// 1 商品实付（分摊优惠券后）的百分比，如 5.5 为 5.5%
+    Percent = 1,
+    /// 2 每件固定金额
+    Fixed = 2,
+}
+
+/// 零钱充值单的状态，0 已取消，1 待支付，2 已到账
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum RechargeStatus {
+    /// 0 已取消
+    Cancel = 0,
+    /// 1 待支付
+    WaitPay = 1,
+    /// 2 已到账
+    Paid = 2,
+}
+
+/// 代理的级别，1 省代理（city 为空），2 市代理
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum AgentLevel {
+    /// 1 代理整个省/直辖市
+    Province = 1,
+    /// 2 代理市/直辖市的区
+    City = 2,
+}
+
+/// 零钱对账发现的问题，1 钱包金额的 hash 不符，2 余额与交易记录合计不符，3 交易记录的 hash 不符，
+/// 4 交易记录的 hash 时间与写入时间相差过大
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum PocketAuditKind {
+    /// 1 钱包金额的 hash 不符，金额被改过
+    WalletHash = 1,
+    /// 2 钱包余额与交易记录的合计不符
+    Balance = 2,
+    /// 3 交易记录的 hash 不符，记录被改过
+    RecordHash = 3,
+    /// 4 交易记录的用户、金额、类型都与 hash 一致，只是 hash 的时间与 created_at 相差超过
+    /// `pocket.audit_time_tolerance_sec`，可能是服务器与数据库的时钟不一致，只记录不冻结钱包
+    RecordTime = 4,
+}
+
 /// 核销单子的状态，0 为取消订单，1 为待核销，2 为已核销，3 已过期
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
 pub enum WriteOffStatus {
@@ -440,3 +597,129 @@ pub enum WithdrawalReqStatus {
     /// 正在提现
     Ing,
 }
+
+/// 定时任务的触发方式，1 定时触发，2 手动触发
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum JobTrigger {
+    /// 1 定时触发
+    Cron = 1,
+    /// 2 管理后台手动触发
+    Manual,
+}
+
+/// 定时任务的执行状态，1 执行中，2 成功，3 失败
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, Eq, PartialEq)]
+pub enum JobRunStatus {
+    /// 1 执行中
+    Running = 1,
+    /// 2 成功
+    Success,
+    /// 3 失败
+    Fail,
+}
+
+/// 订单的优惠，按 1 满减活动、2 店铺券、3 平台券 的顺序使用
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq, PartialOrd, Ord)]
+pub enum DiscountKind {
+    /// 1 满减活动，店铺的或平台的
+    FullReduction = 1,
+    /// 2 店铺券，每个店铺一张
+    StoreCoupon = 2,
+    /// 3 平台券，一张
+    PlatformCoupon = 3,
+}
+
+/// 优惠券发放活动的类型，1 批量发放，2 兑换码，3 新用户首次登录发放
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum CampaignKind {
+    /// 1 批量发放给一批用户
+    Grant = 1,
+    /// 2 生成兑换码，线下发放，用户自己兑换
+    RedeemCode = 2,
+    /// 3 新用户首次登录时发放
+    NewUser = 3,
+}
+impl TryFrom<u8> for CampaignKind {
+    type Error = &'static str;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Grant),
+            2 => Ok(Self::RedeemCode),
+            3 => Ok(Self::NewUser),
+            _ => Err("活动类型错误"),
+        }
+    }
+}
+
+/// 批量发放的用户，1 全部用户，2 某个角色，3 购买过某个产品，4 最近 N 天注册
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum CampaignTarget {
+    /// 1 全部用户
+    All = 1,
+    /// 2 某个角色的用户，target_value 为角色编号
+    Role = 2,
+    /// 3 购买过某个产品的用户，target_value 为产品编号
+    ProductBuyer = 3,
+    /// 4 最近 N 天注册的用户，target_value 为天数
+    RecentRegister = 4,
+}
+impl TryFrom<u8> for CampaignTarget {
+    type Error = &'static str;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::All),
+            2 => Ok(Self::Role),
+            3 => Ok(Self::ProductBuyer),
+            4 => Ok(Self::RecentRegister),
+            _ => Err("发放的用户范围错误"),
+        }
+    }
+}
+
+/// 优惠券发放活动的状态，0 待执行，1 排队中，2 执行中，3 已完成，4 失败，5 已停止
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum CampaignStatus {
+    /// 0 待执行，刚创建
+    Draft = 0,
+    /// 1 排队中，等待定时任务执行
+    Queued = 1,
+    /// 2 执行中；新用户发放的活动为进行中
+    Running = 2,
+    /// 3 已完成
+    Done = 3,
+    /// 4 失败，如优惠券已领完、已下架
+    Fail = 4,
+    /// 5 已停止，兑换码不能再兑换
+    Stopped = 5,
+}
+
+/// 优惠券的有效期类型，1 固定时间，2 领取后 N 天内有效
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum CouponValidType {
+    /// 1 固定时间，start_time 到 expire_time
+    Fixed = 1,
+    /// 2 领取后 valid_days 天内有效，expire_time 为领取的截止时间
+    AfterClaim = 2,
+}
+
+/// 运费模板的计费方式，1 固定运费，2 按件数，3 按重量
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema, Eq, PartialEq)]
+pub enum FreightChargeType {
+    /// 1 固定运费，不论件数、重量
+    Fixed = 1,
+    /// 2 按件数，首件 + 续件
+    PerItem = 2,
+    /// 3 按重量，首重 + 续重，单位为克
+    PerWeight = 3,
+}
+impl TryFrom<u8> for FreightChargeType {
+    type Error = &'static str;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Fixed),
+            2 => Ok(Self::PerItem),
+            3 => Ok(Self::PerWeight),
+            _ => Err("计费方式错误"),
+        }
+    }
+}
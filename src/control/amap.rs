@@ -2,7 +2,7 @@ use actix_web::{Error, error};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    common::{AMAP_WEB_SERVER_KEY, AMAP_WEB_URL},
+    common::config,
     utils::utils::{keep_decimal, log_err},
 };
 
@@ -31,10 +31,10 @@ pub struct AmapStreetNumber {
 }
 /// 高德地图，坐标获取地址信息 (lat, lng)
 pub async fn amap_geocode_regeo(loc: (f64, f64)) -> anyhow::Result<AmapGeocodeRegeo, Error> {
-    let url = AMAP_WEB_URL.to_string()
+    let url = config().amap.web_url.clone()
         + "/geocode/regeo?"
         + "key="
-        + AMAP_WEB_SERVER_KEY
+        + &config().amap.web_server_key
         + "&location="
         + &loc.1.to_string()
         + ","
@@ -94,10 +94,10 @@ pub async fn amap_drive_distance(
         /// 预计行驶时间 秒
         duration: String,
     }
-    let url = AMAP_WEB_URL.to_string()
+    let url = config().amap.web_url.clone()
         + "/direction/driving?"
         + "key="
-        + AMAP_WEB_SERVER_KEY
+        + &config().amap.web_server_key
         + "&origin="
         + &start.1.to_string()
         + ","
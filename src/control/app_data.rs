@@ -12,7 +12,9 @@ pub enum SlownWorker {
     OssFileName,
 }
 
-/// 全局变量，获取雪花随机数
+/// 全局变量，启动时创建一次，所有 worker 共享。获取雪花随机数。
+///
+/// 数据库、redis 连接池是 `db` 模块中的全局变量，不放在这里，见 `db::mysql_conn`、`db::redis_conn`
 pub struct AppData {
     order_sn: SnowflakeGenerator,
     out_trade_no: SnowflakeGenerator,
@@ -2,7 +2,7 @@ use lettre::message::header::ContentType;
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{Message, SmtpTransport, Transport};
 
-use crate::common::{EMAIL_HOST, EMAIL_PASSWORD, EMAIL_USERNAME};
+use crate::common::config;
 
 pub struct SendEmail {
     sender: String,
@@ -12,16 +12,17 @@ pub struct SendEmail {
 impl SendEmail {
     /// 创建一个新的邮件发送器
     pub fn new() -> Self {
-        let creds = Credentials::new(EMAIL_USERNAME.to_owned(), EMAIL_PASSWORD.to_owned());
+        let email = &config().email;
+        let creds = Credentials::new(email.username.to_owned(), email.password.to_owned());
 
         // Open a remote connection to 163
-        let mailer = SmtpTransport::relay(EMAIL_HOST)
+        let mailer = SmtpTransport::relay(&email.host)
             .unwrap()
             .credentials(creds)
             .build();
 
         Self {
-            sender: EMAIL_USERNAME.to_string(),
+            sender: email.username.to_string(),
             mailer,
         }
     }
@@ -1,26 +1,960 @@
-use actix_jobs::{run_forever, Job, Scheduler};
+//! 定时任务
+//!
+//! 随服务启动，按 `config.toml` 中 `[jobs.cron]` 的 cron 表达式定时执行已注册的任务。
+//! 每次执行前用 redis 加锁，多实例部署时同一任务同一时间只会在一个实例上执行，
+//! 执行结果记录到 `sys_job_run` 表中，管理后台可查看及手动触发。
+use std::collections::BTreeMap;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration as StdDuration, Instant};
 
-// TODO 用户优惠券，过期状态的定时任务
-// TODO 用户立即购买，但 一直没去结算，的购物车状态修改，且要把对应数量的商品返回 30分钟一次？
+use actix_web::{Error, error};
+use chrono::{DateTime, Duration, Local};
+use cron::Schedule;
+use mysql_quick::{
+    MY_EXCLUSIVE_LOCK, PooledConn, Queryable, Transaction, TxOpts, myfind, myset, myupdate,
+};
+use serde::{Deserialize, Serialize};
 
-/// 清理超过半年的，用户的检测数据。
-#[allow(unused)]
-struct SomeJob;
+use crate::common::types::{
+    GroupStatus, JobRunStatus, JobTrigger, OrderPayStatus, PayType, ShopCartStatus,
+    UserCouponStatus,
+};
+use crate::common::{PROJECT_NAME, config};
+use crate::control::seckill::seckill_release;
+use crate::db::{
+    my_exec_tran_drop, my_run_drop, my_run_tran_drop, my_run_tran_vec, my_run_vec, mysql_conn,
+    redis_conn,
+};
+use crate::middleware::save_logs;
+use crate::routes::pay_notify_handle;
+use crate::routes::utils_set::agent_set::{agent_order_settle, agent_order_settle_ids};
+use crate::routes::utils_set::coupon_campaign_set::{pending_campaign_ids, run_coupon_campaign};
+use crate::routes::utils_set::coupon_set::{
+    expiring_coupons_for_remind, group_coupon_reminds, mark_coupon_reminded, send_coupon_remind,
+};
+use crate::routes::utils_set::group_set::group_fail;
+use crate::routes::utils_set::mall_set::{
+    cancel_pending_order, close_wx_order, query_wx_paid, refund_wx_order,
+};
+use crate::routes::utils_set::pocket_audit_set::pocket_reconcile;
+use crate::routes::utils_set::recharge_set::cancel_timeout_recharge;
+use crate::routes::utils_set::sales_set::{commission_settle, commission_settle_ids};
+use crate::utils::time::{NowTimeType, get_now_time};
+use crate::utils::utils::log_err;
 
-impl Job for SomeJob {
-    fn cron(&self) -> &str {
-        "*/5 * * * * * *"
+/// 执行记录中，信息的最大长度
+const JOB_MESSAGE_MAX_LEN: usize = 1000;
+/// 调度线程最长的休眠时间，避免系统时间被修改后长时间不执行
+const SCHEDULER_MAX_SLEEP: StdDuration = StdDuration::from_secs(60);
+
+/// 定时任务
+pub trait Job: Sync {
+    /// 任务名，唯一，也是配置 `[jobs.cron]` 中的键
+    fn name(&self) -> &'static str;
+    /// 任务说明
+    fn des(&self) -> &'static str;
+    /// 默认的 cron 表达式（秒 分 时 日 月 周）
+    fn default_cron(&self) -> &'static str;
+    /// 执行任务，成功时返回执行结果的说明
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error>;
+}
+
+/// 已注册的任务，新增任务时加到这里
+static JOBS: &[&dyn Job] = &[
+    &CouponExpireJob,
+    &StaleBuyNowJob,
+    &OrderPayTimeoutJob,
+    &GroupBuyExpireJob,
+    &CommissionSettleJob,
+    &PocketReconcileJob,
+    &CouponCampaignJob,
+    &CouponRemindJob,
+];
+
+/// 当前服务实例的标识，写入任务锁及执行记录
+fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    INSTANCE_ID.get_or_init(|| {
+        let host = std::env::var("HOSTNAME").unwrap_or_default();
+        let uuid = uuid::Uuid::new_v4().simple().to_string();
+        format!("{}-{}", host, &uuid[..8])
+            .trim_start_matches('-')
+            .to_string()
+    })
+}
+
+/// 所有已注册的任务
+pub fn all_jobs() -> &'static [&'static dyn Job] {
+    JOBS
+}
+
+/// 根据任务名查找任务
+pub fn find_job(name: &str) -> Option<&'static dyn Job> {
+    JOBS.iter().find(|j| j.name() == name).copied()
+}
+
+/// 任务当前使用的 cron 表达式，为空字符串时，表示不定时执行
+pub fn job_cron(job: &dyn Job) -> String {
+    job_cron_of(&config().jobs.cron, job)
+}
+fn job_cron_of(crons: &BTreeMap<String, String>, job: &dyn Job) -> String {
+    crons
+        .get(job.name())
+        .cloned()
+        .unwrap_or_else(|| job.default_cron().to_string())
+}
+
+/// 解析 cron 表达式，为空时返回 None
+fn parse_cron(expr: &str) -> anyhow::Result<Option<Schedule>> {
+    if expr.is_empty() {
+        return Ok(None);
+    }
+    Schedule::from_str(expr)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("cron 表达式 {} 格式错误: {}", expr, e))
+}
+
+/// 任务下次定时执行的时间
+pub fn job_next_time(job: &dyn Job) -> Option<String> {
+    let schedule = parse_cron(&job_cron(job)).ok()??;
+    schedule
+        .upcoming(Local)
+        .next()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// 记录任务的异常到日志文件，用于没法写入执行记录的情况，如 redis、mysql 连接不上
+fn job_log(name: &str, content: &str) {
+    let file_time_name = get_now_time(NowTimeType::Date).replace("-", "_") + ".log";
+    save_logs(
+        (String::from("logs/jobs/") + file_time_name.as_str()).as_str(),
+        format!(
+            "------- {} {} -------\n{}\n\n",
+            get_now_time(NowTimeType::DateTime),
+            name,
+            content
+        )
+        .as_str(),
+    );
+}
+
+fn job_lock_key(name: &str) -> String {
+    format!("{}:job_lock:{}", PROJECT_NAME, name)
+}
+
+/// 用 redis 给任务加锁，已被其他实例锁住时返回 false
+fn job_lock(name: &str, token: &str) -> anyhow::Result<bool> {
+    let mut redis_con = redis_conn().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let res: Option<String> = redis::cmd("SET")
+        .arg(job_lock_key(name))
+        .arg(token)
+        .arg("NX")
+        .arg("EX")
+        .arg(config().jobs.lock_ttl_sec)
+        .query(&mut *redis_con)?;
+    Ok(res.is_some())
+}
+
+/// 释放任务锁，只释放自己加的锁
+fn job_unlock(name: &str, token: &str) -> anyhow::Result<()> {
+    let mut redis_con = redis_conn().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let _: i32 = redis::Script::new(
+        r"if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('del', KEYS[1]) else return 0 end",
+    )
+    .key(job_lock_key(name))
+    .arg(token)
+    .invoke(&mut *redis_con)?;
+    Ok(())
+}
+
+/// 给自己加的锁续期，锁已过期或被其他实例持有时返回 false
+fn job_lock_renew(name: &str, token: &str) -> anyhow::Result<bool> {
+    let mut redis_con = redis_conn().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let res: i32 = redis::Script::new(
+        r"if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('expire', KEYS[1], ARGV[2]) else return 0 end",
+    )
+    .key(job_lock_key(name))
+    .arg(token)
+    .arg(config().jobs.lock_ttl_sec)
+    .invoke(&mut *redis_con)?;
+    Ok(res == 1)
+}
+
+/// 续期的间隔，为锁过期时间的 1/3
+fn job_lock_renew_interval() -> StdDuration {
+    StdDuration::from_secs((config().jobs.lock_ttl_sec / 3).max(1))
+}
+
+/// 截断过长的执行信息
+fn job_message(msg: String) -> String {
+    if msg.chars().count() > JOB_MESSAGE_MAX_LEN {
+        msg.chars().take(JOB_MESSAGE_MAX_LEN).collect()
+    } else {
+        msg
+    }
+}
+
+/// 任务的执行记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobRun {
+    pub id: u64,
+    pub job_name: String,
+    /// 1 定时触发，2 手动触发
+    pub trigger_type: u8,
+    /// 1 执行中，2 成功，3 失败
+    pub status: u8,
+    pub message: Option<String>,
+    pub instance: Option<String>,
+    pub trigger_uid: Option<u64>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub duration_ms: Option<u64>,
+}
+
+/// 执行一次任务，并记录执行结果。
+///
+/// 任务正在其他实例（或本实例）执行时，不执行，返回 None。
+/// 执行期间另起线程定时给锁续期，执行时间超过锁的过期时间也不会被其他实例重复执行。
+/// 任务本身执行失败时，返回的记录 status 为失败，只有锁、记录等出错时才返回 Err
+pub fn run_job(
+    job: &dyn Job,
+    trigger: JobTrigger,
+    trigger_uid: Option<u64>,
+) -> anyhow::Result<Option<JobRun>> {
+    let name = job.name();
+    let token = format!("{}:{}", instance_id(), uuid::Uuid::new_v4().simple());
+    if !job_lock(name, &token)? {
+        return Ok(None);
+    }
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let token = token.as_str();
+    let res = std::thread::scope(|s| {
+        s.spawn(move || {
+            // 任务结束时 stop_tx 被丢弃，recv 返回 Disconnected，停止续期
+            while let Err(RecvTimeoutError::Timeout) =
+                stop_rx.recv_timeout(job_lock_renew_interval())
+            {
+                match job_lock_renew(name, token) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        job_log(name, "任务锁已失效，停止续期");
+                        break;
+                    }
+                    Err(e) => job_log(name, &log_err(&e, "任务锁续期失败")),
+                }
+            }
+        });
+        let res = run_job_locked(job, trigger, trigger_uid);
+        drop(stop_tx);
+        res
+    });
+    if let Err(e) = job_unlock(name, token) {
+        job_log(name, &log_err(&e, "释放任务锁失败"));
+    }
+    res.map(Some)
+}
+
+fn run_job_locked(
+    job: &dyn Job,
+    trigger: JobTrigger,
+    trigger_uid: Option<u64>,
+) -> anyhow::Result<JobRun> {
+    let to_anyhow = |e: Error| anyhow::anyhow!(e.to_string());
+    let mut conn = mysql_conn().map_err(to_anyhow)?;
+    let started_at = get_now_time(NowTimeType::DateTime);
+    let id = my_run_drop(
+        &mut conn,
+        myset!("sys_job_run", {
+            "job_name": job.name(),
+            "trigger_type": trigger.clone() as u8,
+            "status": JobRunStatus::Running as u8,
+            "instance": instance_id(),
+            "trigger_uid": trigger_uid,
+            "started_at": &started_at,
+        }),
+    )
+    .map_err(to_anyhow)?;
+
+    let start = Instant::now();
+    // 任务 panic 时，也要记录结果、释放锁
+    let res = match catch_unwind(AssertUnwindSafe(|| job.run(&mut conn))) {
+        Ok(r) => r.map_err(|e| e.to_string()),
+        Err(p) => Err(p
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| p.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "任务执行 panic".to_string())),
+    };
+    let duration_ms = start.elapsed().as_millis() as u64;
+    let (status, message) = match res {
+        Ok(m) => (JobRunStatus::Success, job_message(m)),
+        Err(m) => (JobRunStatus::Fail, job_message(m)),
+    };
+    let finished_at = get_now_time(NowTimeType::DateTime);
+    // 任务 panic 后，连接的状态不确定，重新获取
+    let mut conn = mysql_conn().map_err(to_anyhow)?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("sys_job_run", id, {
+            "status": status.clone() as u8,
+            "message": &message,
+            "finished_at": &finished_at,
+            "duration_ms": duration_ms,
+        }),
+    )
+    .map_err(to_anyhow)?;
+
+    Ok(JobRun {
+        id,
+        job_name: job.name().to_string(),
+        trigger_type: trigger as u8,
+        status: status as u8,
+        message: Some(message),
+        instance: Some(instance_id().to_string()),
+        trigger_uid,
+        started_at,
+        finished_at: Some(finished_at),
+        duration_ms: Some(duration_ms),
+    })
+}
+
+/// 计算任务在 after 之后的下次执行时间
+fn next_after(schedule: &Schedule, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+    schedule.after(after).next()
+}
+
+/// 随服务启动定时任务，在单独的线程中调度，每次执行任务再开一个线程，避免任务之间相互等待
+pub fn start_jobs() -> anyhow::Result<()> {
+    let cfg = &config().jobs;
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let mut plans: Vec<(&'static dyn Job, Schedule)> = vec![];
+    for job in JOBS {
+        if let Some(s) = parse_cron(&job_cron(*job))? {
+            plans.push((*job, s));
+        }
+    }
+    if plans.is_empty() {
+        return Ok(());
+    }
+    std::thread::Builder::new()
+        .name("mall-jobs".to_string())
+        .spawn(move || {
+            let now = Local::now();
+            let mut nexts: Vec<Option<DateTime<Local>>> =
+                plans.iter().map(|(_, s)| next_after(s, &now)).collect();
+            loop {
+                let now = Local::now();
+                for (i, (job, schedule)) in plans.iter().enumerate() {
+                    let Some(next) = nexts[i] else {
+                        continue;
+                    };
+                    if next > now {
+                        continue;
+                    }
+                    nexts[i] = next_after(schedule, &now);
+                    let job: &'static dyn Job = *job;
+                    let spawned = std::thread::Builder::new()
+                        .name(format!("mall-job-{}", job.name()))
+                        .spawn(move || {
+                            if let Err(e) = run_job(job, JobTrigger::Cron, None) {
+                                job_log(job.name(), &log_err(&e, "定时任务执行出错"));
+                            }
+                        });
+                    if let Err(e) = spawned {
+                        job_log(job.name(), &log_err(&e, "定时任务线程创建失败"));
+                    }
+                }
+                let sleep = nexts
+                    .iter()
+                    .flatten()
+                    .min()
+                    .and_then(|t| (*t - Local::now()).to_std().ok())
+                    .unwrap_or(StdDuration::from_millis(200))
+                    .min(SCHEDULER_MAX_SLEEP);
+                std::thread::sleep(sleep);
+            }
+        })?;
+    Ok(())
+}
+
+/// 用户领取的优惠券，过了这张券的过期时间后修改为已过期状态
+pub struct CouponExpireJob;
+impl Job for CouponExpireJob {
+    fn name(&self) -> &'static str {
+        "coupon_expire"
+    }
+    fn des(&self) -> &'static str {
+        "用户未使用的优惠券，过期后修改为已过期"
+    }
+    fn default_cron(&self) -> &'static str {
+        "0 */10 * * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let now = get_now_time(NowTimeType::DateTime);
+        // 没有过期时间的，是之前领取的，按优惠券的过期时间
+        conn.exec_drop(
+            "UPDATE usr_coupon INNER JOIN pmt_coupon ON usr_coupon.coupon_id = pmt_coupon.id
+                SET usr_coupon.status = ?
+                WHERE usr_coupon.status = ? AND usr_coupon.is_del = 0
+                AND ((usr_coupon.end_time IS NOT NULL AND usr_coupon.end_time <= ?)
+                OR (usr_coupon.end_time IS NULL AND pmt_coupon.expire_time IS NOT NULL
+                AND pmt_coupon.expire_time <= ?))",
+            (
+                UserCouponStatus::Expired as u8,
+                UserCouponStatus::NotUsed as u8,
+                &now,
+                &now,
+            ),
+        )
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "优惠券过期更新失败")))?;
+        Ok(format!("已过期优惠券 {} 张", conn.affected_rows()))
+    }
+}
+
+/// 立即购买后一直未结算的购物车记录，删除，并返还占用的库存
+pub struct StaleBuyNowJob;
+impl Job for StaleBuyNowJob {
+    fn name(&self) -> &'static str {
+        "stale_buy_now"
+    }
+    fn des(&self) -> &'static str {
+        "立即购买后超时未结算的商品，返还库存"
     }
-    fn run(&mut self) {
-        println!("jobs...");
+    fn default_cron(&self) -> &'static str {
+        "0 */5 * * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let minutes = config().jobs.stale_buy_now_minutes as i64;
+        let deadline = (Local::now() - Duration::minutes(minutes))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let mut tran = conn
+            .start_transaction(TxOpts::default())
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+        match release_stale_buy_now(&mut tran, &deadline) {
+            Ok((carts, units, seckills)) => {
+                tran.commit()
+                    .map_err(|e| error::ErrorInternalServerError(log_err(&e, "事务提交失败")))?;
+                // 秒杀抢购的，同时返还 redis 中的秒杀库存
+                for (sec_kill_id, uid, quantity) in seckills {
+                    if let Err(e) = seckill_release(sec_kill_id, uid, quantity) {
+                        job_log(self.name(), &log_err(&e, "秒杀库存返还失败"));
+                    }
+                }
+                Ok(format!(
+                    "释放购物车记录 {} 条，返还库存的商品 {} 个",
+                    carts, units
+                ))
+            }
+            Err(e) => {
+                tran.rollback().unwrap();
+                Err(e)
+            }
+        }
     }
 }
 
-/// 执行job操作
-#[allow(unused)]
-pub fn run_jobs() {
-    let mut scheduler = Scheduler::new();
-    scheduler.add(Box::new(SomeJob));
+/// 一次最多处理的购物车记录数，剩下的下次再处理
+const STALE_BUY_NOW_BATCH: u32 = 500;
+
+/// 删除 deadline 之前的立即购买记录，返还库存。
+///
+/// 返回 (购物车记录数, 商品数, 秒杀抢购的 (秒杀活动id, 用户id, 数量))，redis 中的秒杀库存在提交后返还
+fn release_stale_buy_now(
+    tran: &mut Transaction,
+    deadline: &str,
+) -> Result<(usize, usize, Vec<(u32, u64, u32)>), Error> {
+    #[derive(Deserialize)]
+    struct StaleCart {
+        id: u64,
+        uid: u64,
+        unit_sn: u32,
+        buy_quantity: u32,
+        sec_kill_id: Option<u32>,
+    }
+    let carts: Vec<StaleCart> = my_run_tran_vec(
+        tran,
+        myfind!("ord_shop_cart", {
+            p0: ["status", "=", ShopCartStatus::BuyNow as u8],
+            p1: ["is_del", "=", 0],
+            p2: ["created_at", "<", deadline],
+            r: "p0 && p1 && p2",
+            limit: STALE_BUY_NOW_BATCH,
+            select: "id,uid,unit_sn,buy_quantity,sec_kill_id",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let mut units: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut seckills: Vec<(u32, u64, u32)> = vec![];
+    for c in &carts {
+        my_run_tran_drop(tran, myupdate!("ord_shop_cart", c.id, { "is_del": 1 }))?;
+        *units.entry(c.unit_sn).or_insert(0) += c.buy_quantity;
+        if let Some(sk_id) = c.sec_kill_id {
+            my_exec_tran_drop(
+                tran,
+                "update pmt_sec_kill set sold = greatest(sold - ?, 0) where id = ?",
+                (c.buy_quantity, sk_id),
+            )?;
+            seckills.push((sk_id, c.uid, c.buy_quantity));
+        }
+    }
+    for (unit_sn, quantity) in &units {
+        my_run_tran_drop(
+            tran,
+            myupdate!("sku_unit", {"unit_sn": unit_sn}, {
+                "quantity": ["incr", quantity],
+            }),
+        )?;
+    }
+    Ok((carts.len(), units.len(), seckills))
+}
 
-    run_forever(scheduler);
+/// 微信支付超时未支付的订单，自动取消，并返还库存和优惠券
+pub struct OrderPayTimeoutJob;
+impl Job for OrderPayTimeoutJob {
+    fn name(&self) -> &'static str {
+        "order_pay_timeout"
+    }
+    fn des(&self) -> &'static str {
+        "超时未支付的订单，自动取消，并返还库存和优惠券；超时未支付的充值单，自动取消"
+    }
+    fn default_cron(&self) -> &'static str {
+        "0 * * * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let minutes = config().order.pay_timeout_minutes as i64;
+        let deadline = (Local::now() - Duration::minutes(minutes))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        #[derive(Deserialize)]
+        struct OrderGet {
+            order_sn: String,
+        }
+        let orders: Vec<OrderGet> = my_run_vec(
+            conn,
+            myfind!("ord_order", {
+                p0: ["status", "=", OrderPayStatus::PendingPayment as u8],
+                p1: ["is_del", "=", 0],
+                p2: ["created_at", "<", &deadline],
+                p3: ["pay_error", "is_null", true],
+                r: "p0 && p1 && p2 && p3",
+                limit: ORDER_PAY_TIMEOUT_BATCH,
+                select: "order_sn",
+            }),
+        )?;
+        // 关闭微信订单是异步接口，定时任务在普通线程中执行，用单线程的运行时等待
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "运行时创建失败")))?;
+        let mut fails: Vec<String> = vec![];
+        for o in &orders {
+            if let Err(e) = cancel_timeout_order(conn, &rt, &o.order_sn) {
+                fails.push(format!("{}: {}", o.order_sn, e));
+            }
+        }
+        let recharges = match cancel_timeout_recharge(conn, &deadline) {
+            Ok(n) => n,
+            Err(e) => {
+                fails.push(format!("充值单: {}", e));
+                0
+            }
+        };
+        let msg = format!(
+            "取消超时未支付的订单 {} 个，充值单 {} 个",
+            orders.len() - fails.len(),
+            recharges
+        );
+        if fails.is_empty() {
+            Ok(msg)
+        } else {
+            Err(error::ErrorInternalServerError(format!(
+                "{}，失败 {} 个：{}",
+                msg,
+                fails.len(),
+                fails.join("；")
+            )))
+        }
+    }
+}
+
+/// 一次最多处理的超时订单数，剩下的下次再处理
+const ORDER_PAY_TIMEOUT_BATCH: u32 = 100;
+
+/// 取消一个超时的订单。先关闭微信订单再提交事务，关闭失败（如用户刚好已支付）时回滚。
+///
+/// 微信订单已支付、只是没收到支付回调的，不取消，按支付回调补处理
+fn cancel_timeout_order(
+    conn: &mut PooledConn,
+    rt: &tokio::runtime::Runtime,
+    order_sn: &str,
+) -> Result<(), Error> {
+    let mut tran = conn
+        .start_transaction(TxOpts::default())
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+    let mut paid = None;
+    let res = cancel_pending_order(&mut tran, order_sn, None, "超时未支付，系统自动取消").and_then(
+        |pay_type| {
+            if pay_type != PayType::WxPay {
+                return Ok(());
+            }
+            paid = rt.block_on(query_wx_paid(order_sn));
+            if paid.is_some() {
+                return Ok(());
+            }
+            rt.block_on(close_wx_order(order_sn))
+        },
+    );
+    match (res, paid) {
+        (Ok(()), Some(data)) => {
+            tran.rollback().unwrap();
+            pay_notify_handle(data)
+        }
+        (Ok(()), None) => {
+            tran.commit()
+                .map_err(|e| error::ErrorInternalServerError(log_err(&e, "事务提交失败")))?;
+            Ok(())
+        }
+        (Err(e), _) => {
+            tran.rollback().unwrap();
+            Err(e)
+        }
+    }
+}
+
+/// 超时未成团的团，自动取消未支付的订单，已支付的退款
+pub struct GroupBuyExpireJob;
+impl Job for GroupBuyExpireJob {
+    fn name(&self) -> &'static str {
+        "group_buy_expire"
+    }
+    fn des(&self) -> &'static str {
+        "超时未成团的拼团，取消未支付的订单，已支付的自动退款"
+    }
+    fn default_cron(&self) -> &'static str {
+        "30 * * * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let now = get_now_time(NowTimeType::DateTime);
+        #[derive(Deserialize)]
+        struct GroupGet {
+            id: u64,
+        }
+        let groups: Vec<GroupGet> = my_run_vec(
+            conn,
+            myfind!("ord_group", {
+                p0: ["status", "=", GroupStatus::Grouping as u8],
+                p1: ["is_del", "=", 0],
+                p2: ["expire_time", "<", &now],
+                r: "p0 && p1 && p2",
+                limit: GROUP_BUY_EXPIRE_BATCH,
+                select: "id",
+            }),
+        )?;
+        // 微信关闭订单、退款是异步接口，用单线程的运行时等待
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "运行时创建失败")))?;
+        let mut fails: Vec<String> = vec![];
+        for g in &groups {
+            if let Err(e) = fail_expired_group(conn, &rt, g.id) {
+                fails.push(format!("{}: {}", g.id, e));
+            }
+        }
+        let msg = format!("处理超时未成团的团 {} 个", groups.len() - fails.len());
+        if fails.is_empty() {
+            Ok(msg)
+        } else {
+            Err(error::ErrorInternalServerError(format!(
+                "{}，失败 {} 个：{}",
+                msg,
+                fails.len(),
+                fails.join("；")
+            )))
+        }
+    }
+}
+
+/// 一次最多处理的超时团数，剩下的下次再处理
+const GROUP_BUY_EXPIRE_BATCH: u32 = 50;
+
+/// 处理一个超时未成团的团。先关闭、退款微信订单再提交事务，有失败时回滚，下次重试
+fn fail_expired_group(
+    conn: &mut PooledConn,
+    rt: &tokio::runtime::Runtime,
+    group_id: u64,
+) -> Result<(), Error> {
+    let mut tran = conn
+        .start_transaction(TxOpts::default())
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+    let reason = "超时未成团，系统自动退款";
+    let res = group_fail(&mut tran, group_id, reason).and_then(|wx| {
+        for order_sn in &wx.close_orders {
+            rt.block_on(close_wx_order(order_sn))?;
+        }
+        for r in &wx.refunds {
+            rt.block_on(refund_wx_order(
+                &r.transaction_id,
+                &r.out_refund_no,
+                r.amount,
+                r.amount,
+                reason,
+            ))?;
+        }
+        Ok(())
+    });
+    match res {
+        Ok(()) => {
+            tran.commit()
+                .map_err(|e| error::ErrorInternalServerError(log_err(&e, "事务提交失败")))?;
+            Ok(())
+        }
+        Err(e) => {
+            tran.rollback().unwrap();
+            Err(e)
+        }
+    }
+}
+
+/// 冻结期已过的销售佣金结算到零钱，代理分成结算到未提现金额
+pub struct CommissionSettleJob;
+impl Job for CommissionSettleJob {
+    fn name(&self) -> &'static str {
+        "commission_settle"
+    }
+    fn des(&self) -> &'static str {
+        "商品完成并过了冻结期的销售佣金结算到零钱，代理分成结算到未提现金额"
+    }
+    fn default_cron(&self) -> &'static str {
+        "0 0 * * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let days = config().order.commission_freeze_days as i64;
+        let deadline = (Local::now() - Duration::days(days))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let ids = commission_settle_ids(conn, &deadline, COMMISSION_SETTLE_BATCH)?;
+        // 每条佣金单独一个事务，个别用户零钱异常时不影响其他的结算
+        let mut settled = 0;
+        let mut fails: Vec<String> = vec![];
+        for id in &ids {
+            let mut tran = conn
+                .start_transaction(TxOpts::default())
+                .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+            match commission_settle(&mut tran, *id, &deadline) {
+                Ok(done) => {
+                    tran.commit().map_err(|e| {
+                        error::ErrorInternalServerError(log_err(&e, "事务提交失败"))
+                    })?;
+                    if done {
+                        settled += 1;
+                    }
+                }
+                Err(e) => {
+                    tran.rollback().unwrap();
+                    fails.push(format!("{}: {}", id, e));
+                }
+            }
+        }
+        let ids = agent_order_settle_ids(conn, &deadline, COMMISSION_SETTLE_BATCH)?;
+        let mut agent_settled = 0;
+        for id in &ids {
+            let mut tran = conn
+                .start_transaction(TxOpts::default())
+                .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+            match agent_order_settle(&mut tran, *id, &deadline) {
+                Ok(done) => {
+                    tran.commit().map_err(|e| {
+                        error::ErrorInternalServerError(log_err(&e, "事务提交失败"))
+                    })?;
+                    if done {
+                        agent_settled += 1;
+                    }
+                }
+                Err(e) => {
+                    tran.rollback().unwrap();
+                    fails.push(format!("代理分成 {}: {}", id, e));
+                }
+            }
+        }
+        let msg = format!("结算佣金 {} 条，代理分成 {} 条", settled, agent_settled);
+        if fails.is_empty() {
+            Ok(msg)
+        } else {
+            Err(error::ErrorInternalServerError(format!(
+                "{}，失败 {} 条：{}",
+                msg,
+                fails.len(),
+                fails.join("；")
+            )))
+        }
+    }
+}
+
+/// 一次最多结算的佣金数，剩下的下次再处理
+const COMMISSION_SETTLE_BATCH: u32 = 500;
+
+/// 零钱对账，校验钱包及交易记录的 hash、余额与交易记录的合计，有问题的钱包冻结
+pub struct PocketReconcileJob;
+impl Job for PocketReconcileJob {
+    fn name(&self) -> &'static str {
+        "pocket_reconcile"
+    }
+    fn des(&self) -> &'static str {
+        "零钱对账，钱包或交易记录被篡改、余额与交易记录不符的钱包冻结，问题记录到 usr_pocket_audit"
+    }
+    fn default_cron(&self) -> &'static str {
+        "0 30 3 * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let report = pocket_reconcile(conn)?;
+        if report.fails.is_empty() {
+            Ok(report.summary())
+        } else {
+            Err(error::ErrorInternalServerError(report.summary()))
+        }
+    }
+}
+
+/// 优惠券发放活动，批量发放给用户、生成兑换码
+pub struct CouponCampaignJob;
+impl Job for CouponCampaignJob {
+    fn name(&self) -> &'static str {
+        "coupon_campaign"
+    }
+    fn des(&self) -> &'static str {
+        "执行排队中、执行中的优惠券发放活动，批量发放优惠券、生成兑换码，没处理完的下次接着处理"
+    }
+    fn default_cron(&self) -> &'static str {
+        "15 * * * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let ids = pending_campaign_ids(conn)?;
+        let mut msgs: Vec<String> = vec![];
+        let mut fails: Vec<String> = vec![];
+        for id in ids {
+            match run_coupon_campaign(conn, id) {
+                Ok(m) => msgs.push(m),
+                Err(e) => fails.push(format!("{}: {}", id, e)),
+            }
+        }
+        let msg = if msgs.is_empty() {
+            "没有要执行的活动".to_string()
+        } else {
+            msgs.join("；")
+        };
+        if fails.is_empty() {
+            Ok(msg)
+        } else {
+            Err(error::ErrorInternalServerError(format!(
+                "{}，失败 {} 个：{}",
+                msg,
+                fails.len(),
+                fails.join("；")
+            )))
+        }
+    }
+}
+
+/// 优惠券到期提醒，每张券只提醒一次
+pub struct CouponRemindJob;
+impl Job for CouponRemindJob {
+    fn name(&self) -> &'static str {
+        "coupon_expire_remind"
+    }
+    fn des(&self) -> &'static str {
+        "即将过期、未使用的优惠券，按配置的渠道（小程序订阅消息、短信）提醒用户，同一用户的合并为一条"
+    }
+    fn default_cron(&self) -> &'static str {
+        "0 0 10 * * *"
+    }
+    fn run(&self, conn: &mut PooledConn) -> Result<String, Error> {
+        let cfg = &config().coupon;
+        if cfg.remind_channels.is_empty() {
+            return Ok("未配置提醒渠道".to_string());
+        }
+        let now = Local::now();
+        let deadline = (now + Duration::days(cfg.expiring_days as i64))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let now = now.format("%Y-%m-%d %H:%M:%S").to_string();
+        // 发送消息是异步接口，定时任务在普通线程中执行，用单线程的运行时等待
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "运行时创建失败")))?;
+        let (mut sent, mut skipped) = (0, 0);
+        let mut fails: Vec<String> = vec![];
+        loop {
+            let list = expiring_coupons_for_remind(conn, &now, &deadline, COUPON_REMIND_BATCH)?;
+            if list.is_empty() {
+                break;
+            }
+            let ids: Vec<u64> = list.iter().map(|x| x.id).collect();
+            for r in group_coupon_reminds(list) {
+                match rt.block_on(send_coupon_remind(&r)) {
+                    Ok(true) => sent += 1,
+                    Ok(false) => skipped += 1,
+                    Err(e) => fails.push(format!("{}: {}", r.uid, e)),
+                }
+            }
+            // 发送失败的也记录为已提醒，不重复发送
+            mark_coupon_reminded(conn, &ids, &now)?;
+        }
+        let msg = format!("已提醒用户 {} 个，无联系方式 {} 个", sent, skipped);
+        if fails.is_empty() {
+            Ok(msg)
+        } else {
+            Err(error::ErrorInternalServerError(format!(
+                "{}，失败 {} 个：{}",
+                msg,
+                fails.len(),
+                fails.join("；")
+            )))
+        }
+    }
+}
+
+/// 到期提醒每批查询的优惠券数
+const COUPON_REMIND_BATCH: u32 = 500;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_jobs_registry() {
+        let mut names = all_jobs().iter().map(|j| j.name()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), all_jobs().len(), "任务名不能重复");
+        for job in all_jobs() {
+            assert!(find_job(job.name()).is_some());
+            assert!(parse_cron(job.default_cron()).unwrap().is_some());
+        }
+        assert!(find_job("not_exist").is_none());
+
+        let job = find_job("coupon_expire").unwrap();
+        let mut crons = BTreeMap::new();
+        assert_eq!(job_cron_of(&crons, job), job.default_cron());
+        crons.insert("coupon_expire".to_string(), "".to_string());
+        assert!(parse_cron(&job_cron_of(&crons, job)).unwrap().is_none());
+        crons.insert("coupon_expire".to_string(), "0 0 3 * * *".to_string());
+        let schedule = parse_cron(&job_cron_of(&crons, job)).unwrap().unwrap();
+        let next = schedule.upcoming(Local).next().unwrap();
+        assert_eq!(next.format("%H:%M:%S").to_string(), "03:00:00");
+        assert!(parse_cron("every 5 min").is_err());
+    }
+
+    #[test]
+    fn test_job_message() {
+        assert_eq!(job_message("ok".to_string()), "ok");
+        let long = "错".repeat(JOB_MESSAGE_MAX_LEN + 10);
+        assert_eq!(job_message(long).chars().count(), JOB_MESSAGE_MAX_LEN);
+    }
 }
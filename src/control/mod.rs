@@ -3,6 +3,10 @@ pub(crate) mod app_data;
 pub(crate) mod email;
 pub(crate) mod frequency;
 pub(crate) mod jobs;
+pub(crate) mod payment;
+pub(crate) mod seckill;
 pub(crate) mod sms;
+pub(crate) mod token;
 pub(crate) mod wx_delivery;
+pub(crate) mod wx_message;
 pub(crate) mod wx_info;
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use actix_web::{Error, HttpRequest, error};
+use chrono::{Local, SecondsFormat};
+use futures_util::future::BoxFuture;
+use wx_pay::decode::{
+    WxPayResource, WxPayResourceAmount, WxRefundResource, WxRefundResourceAmount,
+    WxTransferResource,
+};
+use wx_pay::{Payer, RefundStatus, TradeState, TransferBillStatus, WxPayData};
+
+use super::{PayQuery, PaymentProvider, PrepayReq, RefundReq, RefundRes, TransferReq, TransferRes};
+use crate::common::{Money, config};
+use crate::middleware::save_logs;
+use crate::routes::{pay_notify_handle, pay_refund_notify_handle, pay_transfer_notify_handle};
+use crate::utils::utils::log_err;
+
+/// 模拟的支付订单号、退款单号、转账单号的前缀
+const MOCK_PREFIX: &str = "MOCK";
+
+/// 模拟支付的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockOutcome {
+    /// 接口调用成功，回调通知为成功
+    Success,
+    /// 接口调用失败
+    Fail,
+    /// 接口调用成功，回调通知为失败
+    NotifyFail,
+}
+impl MockOutcome {
+    fn from_config(s: &str) -> Self {
+        match s {
+            "fail" => MockOutcome::Fail,
+            "notify_fail" => MockOutcome::NotifyFail,
+            _ => MockOutcome::Success,
+        }
+    }
+}
+
+/// 模拟的回调通知，与微信支付 v3 解密后的数据一致
+#[derive(Debug, Clone)]
+pub enum MockNotify {
+    Pay(Box<WxPayResource>),
+    Refund(WxRefundResource),
+    Transfer(WxTransferResource),
+}
+
+/// 模拟的支付订单
+#[derive(Debug, Clone)]
+struct MockTrade {
+    transaction_id: String,
+    openid: String,
+    amount: Money,
+    state: TradeState,
+    /// 已申请退款的金额
+    refunded: Money,
+}
+
+fn now_rfc3339() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/// 本地模拟的支付，不访问网络。
+///
+/// 下单、退款、转账后，按配置的延迟在单独的线程中直接调用回调通知的处理，
+/// 模拟用户支付及微信的异步通知。订单只保存在内存中，服务重启后查询不到
+pub struct MockPayProvider {
+    outcome: MockOutcome,
+    /// 回调通知的延迟，为 None 时不发出回调通知
+    notify_delay: Option<Duration>,
+    trades: Mutex<HashMap<String, MockTrade>>,
+}
+
+impl MockPayProvider {
+    pub fn new(outcome: MockOutcome, notify_delay: Option<Duration>) -> Self {
+        MockPayProvider {
+            outcome,
+            notify_delay,
+            trades: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 按配置 `[payment.mock]` 创建的全局实例
+    pub fn global() -> &'static Self {
+        static MOCK: OnceLock<MockPayProvider> = OnceLock::new();
+        MOCK.get_or_init(|| {
+            let cfg = &config().payment.mock;
+            MockPayProvider::new(
+                MockOutcome::from_config(&cfg.outcome),
+                Some(Duration::from_millis(cfg.notify_delay_ms)),
+            )
+        })
+    }
+
+    /// 模拟接口调用失败
+    fn check(&self, action: &str) -> Result<(), Error> {
+        if self.outcome == MockOutcome::Fail {
+            return Err(error::ErrorBadGateway(format!("模拟支付：{action}失败")));
+        }
+        Ok(())
+    }
+
+    fn notify_success(&self) -> bool {
+        self.outcome == MockOutcome::Success
+    }
+
+    /// 延迟发出回调通知，处理出错时只记录日志，与微信回调失败一样
+    fn notify(&self, notify: MockNotify) {
+        let Some(delay) = self.notify_delay else {
+            return;
+        };
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let res = match notify {
+                MockNotify::Pay(d) => pay_notify_handle(*d),
+                MockNotify::Refund(d) => pay_refund_notify_handle(d),
+                MockNotify::Transfer(d) => pay_transfer_notify_handle(d),
+            };
+            if let Err(e) = res {
+                save_logs(
+                    "logs/utils/mock_pay.log",
+                    &log_err(&e, "模拟支付回调处理失败"),
+                );
+            }
+        });
+    }
+
+    fn pay_notify_data(&self, out_trade_no: &str, trade: &MockTrade) -> MockNotify {
+        MockNotify::Pay(Box::new(WxPayResource {
+            appid: config().wechat.mini_app_id.clone(),
+            out_trade_no: out_trade_no.to_string(),
+            transaction_id: trade.transaction_id.clone(),
+            trade_state: trade.state.clone(),
+            trade_state_desc: format!("模拟支付：{:?}", trade.state),
+            success_time: now_rfc3339(),
+            payer: Payer {
+                openid: trade.openid.clone(),
+            },
+            amount: WxPayResourceAmount {
+                total: trade.amount.cent() as u64,
+                payer_total: trade.amount.cent() as u64,
+                currency: "CNY".to_string(),
+                payer_currency: "CNY".to_string(),
+            },
+            ..Default::default()
+        }))
+    }
+}
+
+impl PaymentProvider for MockPayProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    /// 下单后即视为用户已支付，延迟发出支付通知
+    fn prepay<'a>(&'a self, req: &'a PrepayReq) -> BoxFuture<'a, Result<WxPayData, Error>> {
+        Box::pin(async move {
+            self.check("下单")?;
+            let trade = MockTrade {
+                transaction_id: format!("{MOCK_PREFIX}{}", req.out_trade_no),
+                openid: req.openid.clone(),
+                amount: req.amount,
+                state: if self.notify_success() {
+                    TradeState::SUCCESS
+                } else {
+                    TradeState::PAYERROR
+                },
+                refunded: Money::ZERO,
+            };
+            let notify = self.pay_notify_data(&req.out_trade_no, &trade);
+            self.trades
+                .lock()
+                .unwrap()
+                .insert(req.out_trade_no.clone(), trade);
+            self.notify(notify);
+            Ok(WxPayData {
+                app_id: Some(config().wechat.mini_app_id.clone()),
+                sign_type: "RSA".to_string(),
+                pay_sign: MOCK_PREFIX.to_string(),
+                package: format!("prepay_id={MOCK_PREFIX}{}", req.out_trade_no),
+                nonce_str: uuid::Uuid::new_v4().simple().to_string(),
+                time_stamp: Local::now().timestamp().to_string(),
+            })
+        })
+    }
+
+    fn query<'a>(&'a self, out_trade_no: &'a str) -> BoxFuture<'a, Result<PayQuery, Error>> {
+        Box::pin(async move {
+            self.check("订单查询")?;
+            let trades = self.trades.lock().unwrap();
+            let Some(trade) = trades.get(out_trade_no) else {
+                return Err(error::ErrorNotFound("模拟支付：订单不存在"));
+            };
+            Ok(PayQuery {
+                out_trade_no: out_trade_no.to_string(),
+                transaction_id: Some(trade.transaction_id.clone()),
+                trade_state: trade.state.clone(),
+                amount: Some(trade.amount),
+            })
+        })
+    }
+
+    fn close<'a>(&'a self, out_trade_no: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.check("订单关闭")?;
+            let mut trades = self.trades.lock().unwrap();
+            if let Some(trade) = trades.get_mut(out_trade_no) {
+                if trade.state == TradeState::SUCCESS {
+                    return Err(error::ErrorBadGateway("模拟支付：订单已支付"));
+                }
+                trade.state = TradeState::CLOSED;
+            }
+            Ok(())
+        })
+    }
+
+    fn refund<'a>(&'a self, req: &'a RefundReq) -> BoxFuture<'a, Result<RefundRes, Error>> {
+        Box::pin(async move {
+            self.check("退款")?;
+            if !req.amount.is_positive() || req.amount > req.total {
+                return Err(error::ErrorBadRequest("模拟支付：退款金额错误"));
+            }
+            // 支付订单号由商户订单号加前缀生成，内存中没有的订单也能找回商户订单号
+            let out_trade_no = match (&req.out_trade_no, &req.transaction_id) {
+                (Some(no), _) => no.clone(),
+                (None, Some(id)) => id.trim_start_matches(MOCK_PREFIX).to_string(),
+                (None, None) => return Err(error::ErrorBadRequest("模拟支付：缺少订单号")),
+            };
+            let mut trades = self.trades.lock().unwrap();
+            if let Some(trade) = trades.get_mut(&out_trade_no) {
+                if trade.state != TradeState::SUCCESS {
+                    return Err(error::ErrorBadGateway("模拟支付：订单未支付"));
+                }
+                if trade.refunded + req.amount > trade.amount {
+                    return Err(error::ErrorBadGateway("模拟支付：退款金额超过订单金额"));
+                }
+                trade.refunded += req.amount;
+            }
+            drop(trades);
+
+            let refund_id = format!("{MOCK_PREFIX}{}", req.out_refund_no);
+            self.notify(MockNotify::Refund(WxRefundResource {
+                out_trade_no: out_trade_no.clone(),
+                transaction_id: format!("{MOCK_PREFIX}{out_trade_no}"),
+                out_refund_no: req.out_refund_no.clone(),
+                refund_id: refund_id.clone(),
+                refund_status: if self.notify_success() {
+                    RefundStatus::SUCCESS
+                } else {
+                    RefundStatus::ABNORMAL
+                },
+                success_time: Some(now_rfc3339()),
+                user_received_account: "支付用户零钱".to_string(),
+                amount: WxRefundResourceAmount {
+                    total: req.total.cent() as u64,
+                    refund: req.amount.cent() as u64,
+                    payer_total: req.total.cent() as u64,
+                    payer_refund: req.amount.cent() as u64,
+                },
+                ..Default::default()
+            }));
+            Ok(RefundRes {
+                refund_id,
+                out_refund_no: req.out_refund_no.clone(),
+            })
+        })
+    }
+
+    fn transfer<'a>(&'a self, req: &'a TransferReq) -> BoxFuture<'a, Result<TransferRes, Error>> {
+        Box::pin(async move {
+            self.check("转账")?;
+            if !req.amount.is_positive() {
+                return Err(error::ErrorBadRequest("模拟支付：转账金额错误"));
+            }
+            let transfer_bill_no = format!("{MOCK_PREFIX}{}", req.out_bill_no);
+            let now = now_rfc3339();
+            self.notify(MockNotify::Transfer(WxTransferResource {
+                create_time: now.clone(),
+                openid: req.openid.clone(),
+                out_bill_no: req.out_bill_no.clone(),
+                state: if self.notify_success() {
+                    TransferBillStatus::SUCCESS
+                } else {
+                    TransferBillStatus::FAIL
+                },
+                transfer_amount: req.amount.cent() as u64,
+                transfer_bill_no: transfer_bill_no.clone(),
+                update_time: now,
+                ..Default::default()
+            }));
+            Ok(TransferRes {
+                out_bill_no: req.out_bill_no.clone(),
+                transfer_bill_no,
+                package_info: Some(MOCK_PREFIX.to_string()),
+            })
+        })
+    }
+
+    /// 手动模拟回调时，body 为解密后的通知数据，请求头 `Mockpay-Token` 需与配置一致
+    fn verify_notify(&self, body: &[u8], req: &HttpRequest) -> Result<serde_json::Value, Error> {
+        let token = &config().payment.mock.notify_token;
+        let header = req
+            .headers()
+            .get("Mockpay-Token")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if token.is_empty() || header != token {
+            return Err(error::ErrorNotAcceptable("签名验证失败"));
+        }
+        Ok(serde_json::from_slice(body)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mysql_quick::{myget, myset, myupdate};
+    use serde::Deserialize;
+
+    use crate::common::types::{DeliveryType, OrderPayStatus, PayType};
+    use crate::db::{my_run_drop, my_run_vec, mysql_conn};
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    /// 等回调线程处理完，订单改为 status 时返回 true
+    fn wait_order_status(order_sn: &str, status: OrderPayStatus) -> bool {
+        #[derive(Deserialize)]
+        struct StatusGet {
+            status: i8,
+        }
+        for _ in 0..50 {
+            let mut conn = mysql_conn().unwrap();
+            let list: Vec<StatusGet> = my_run_vec(
+                &mut conn,
+                myget!("ord_order", {"order_sn": order_sn}, "status"),
+            )
+            .unwrap();
+            if list
+                .first()
+                .is_some_and(|x| x.status == status.clone() as i8)
+            {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_mock_pay() {
+        let mock = MockPayProvider::new(MockOutcome::Success, None);
+        let prepay = PrepayReq {
+            out_trade_no: "300000000000000001".to_string(),
+            amount: money("99.9"),
+            openid: "openid".to_string(),
+            ..Default::default()
+        };
+        let data = mock.prepay(&prepay).await.unwrap();
+        assert_eq!(data.package, "prepay_id=MOCK300000000000000001");
+        let query = mock.query(&prepay.out_trade_no).await.unwrap();
+        assert_eq!(query.trade_state, TradeState::SUCCESS);
+        assert_eq!(query.amount, Some(money("99.9")));
+        // 已支付的不能关闭
+        assert!(mock.close(&prepay.out_trade_no).await.is_err());
+        assert!(mock.query("not_exist").await.is_err());
+
+        // 按支付订单号部分退款，累计不能超过订单金额
+        let mut refund = RefundReq {
+            transaction_id: query.transaction_id,
+            out_refund_no: "R1".to_string(),
+            amount: money("50"),
+            total: money("99.9"),
+            ..Default::default()
+        };
+        let res = mock.refund(&refund).await.unwrap();
+        assert_eq!(res.refund_id, "MOCKR1");
+        refund.out_refund_no = "R2".to_string();
+        assert!(mock.refund(&refund).await.is_err());
+        refund.amount = money("49.9");
+        assert!(mock.refund(&refund).await.is_ok());
+
+        let transfer = TransferReq {
+            out_bill_no: "T1".to_string(),
+            amount: money("10"),
+            ..Default::default()
+        };
+        assert_eq!(
+            mock.transfer(&transfer).await.unwrap().transfer_bill_no,
+            "MOCKT1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_pay_fail() {
+        let mock = MockPayProvider::new(MockOutcome::Fail, None);
+        let prepay = PrepayReq {
+            out_trade_no: "300000000000000002".to_string(),
+            amount: money("1"),
+            ..Default::default()
+        };
+        assert!(mock.prepay(&prepay).await.is_err());
+        assert!(mock.close(&prepay.out_trade_no).await.is_err());
+
+        // 回调通知为失败的，订单未支付，可以关闭，不能退款
+        let mock = MockPayProvider::new(MockOutcome::NotifyFail, None);
+        mock.prepay(&prepay).await.unwrap();
+        let query = mock.query(&prepay.out_trade_no).await.unwrap();
+        assert_eq!(query.trade_state, TradeState::PAYERROR);
+        let refund = RefundReq {
+            out_trade_no: Some(prepay.out_trade_no.clone()),
+            out_refund_no: "R3".to_string(),
+            amount: money("1"),
+            total: money("1"),
+            ..Default::default()
+        };
+        assert!(mock.refund(&refund).await.is_err());
+        mock.close(&prepay.out_trade_no).await.unwrap();
+        let query = mock.query(&prepay.out_trade_no).await.unwrap();
+        assert_eq!(query.trade_state, TradeState::CLOSED);
+    }
+
+    /// 模拟支付的完整流程：下单 → 支付回调 → 订单已支付 → 退款 → 退款回调 → 订单已退款
+    #[tokio::test]
+    #[ignore = "需要 mysql，cargo test -- --ignored"]
+    async fn test_mock_pay_order_flow() {
+        let mock = MockPayProvider::new(MockOutcome::Success, Some(Duration::ZERO));
+        let order_sn = format!("{MOCK_PREFIX}{}", Local::now().timestamp_micros());
+        let amount = money("19.9");
+        let mut conn = mysql_conn().unwrap();
+        my_run_drop(
+            &mut conn,
+            myset!("ord_order", {
+                "uid": 0,
+                "order_sn": &order_sn,
+                "total_amount": amount.to_string(),
+                "pay_amount": amount.to_string(),
+                "total_quantity": 1,
+                "delivery_type": DeliveryType::NoDelivery.to_string(),
+                "pay_type": PayType::WxPay.to_string(),
+                "status": OrderPayStatus::PendingPayment as i8,
+            }),
+        )
+        .unwrap();
+
+        let prepay = PrepayReq {
+            out_trade_no: order_sn.clone(),
+            amount,
+            openid: "openid".to_string(),
+            ..Default::default()
+        };
+        mock.prepay(&prepay).await.unwrap();
+        assert!(wait_order_status(&order_sn, OrderPayStatus::Paid));
+
+        // 与后台整单退款一样，先改为退款中再申请退款
+        my_run_drop(
+            &mut conn,
+            myupdate!("ord_order", {"order_sn": &order_sn}, {
+                "status": OrderPayStatus::Refunding as i8,
+            }),
+        )
+        .unwrap();
+        let refund = RefundReq {
+            out_trade_no: Some(order_sn.clone()),
+            out_refund_no: format!("R{order_sn}"),
+            amount,
+            total: amount,
+            ..Default::default()
+        };
+        mock.refund(&refund).await.unwrap();
+        assert!(wait_order_status(&order_sn, OrderPayStatus::Refund));
+
+        my_run_drop(
+            &mut conn,
+            myupdate!("ord_order", {"order_sn": &order_sn}, {"is_del": 1}),
+        )
+        .unwrap();
+    }
+}
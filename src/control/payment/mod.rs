@@ -0,0 +1,130 @@
+//! 支付渠道
+//!
+//! 下单、查询、关单、退款、转账及回调通知的验证，都通过 `payment()` 返回的 `PaymentProvider` 调用，
+//! 按 `[payment] provider` 选择微信支付 v3 或本地模拟的支付。
+//! 回调通知的数据统一使用微信支付 v3 解密后的格式（`WxPayResource` 等）。
+use actix_web::{Error, HttpRequest, error};
+use futures_util::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use wx_pay::{TradeState, WxPayData};
+
+use crate::common::{Money, config};
+use crate::utils::utils::log_err;
+
+mod mock;
+mod wechat;
+
+pub use mock::MockPayProvider;
+pub use wechat::WechatPayProvider;
+
+/// 发起支付
+#[derive(Debug, Clone, Default)]
+pub struct PrepayReq {
+    /// 商品描述
+    pub description: String,
+    /// 商户订单号
+    pub out_trade_no: String,
+    /// 支付金额
+    pub amount: Money,
+    /// 付款用户的 openid
+    pub openid: String,
+    /// 支付的失效时间，rfc3339 格式
+    pub time_expire: Option<String>,
+}
+
+/// 查询支付的结果
+#[derive(Debug, Clone)]
+pub struct PayQuery {
+    pub out_trade_no: String,
+    pub transaction_id: Option<String>,
+    pub trade_state: TradeState,
+    /// 订单金额
+    pub amount: Option<Money>,
+}
+
+/// 退款申请。out_refund_no 相同时只会退款一次，重试时要使用同一个退款单号
+#[derive(Debug, Clone, Default)]
+pub struct RefundReq {
+    /// 支付订单号，与 out_trade_no 二选一
+    pub transaction_id: Option<String>,
+    /// 商户订单号
+    pub out_trade_no: Option<String>,
+    /// 退款单号
+    pub out_refund_no: String,
+    /// 本次退款金额
+    pub amount: Money,
+    /// 原订单的支付金额
+    pub total: Money,
+    pub reason: Option<String>,
+}
+
+/// 退款申请的结果，退款是否成功以回调通知为准
+#[derive(Debug, Clone)]
+pub struct RefundRes {
+    pub refund_id: String,
+    pub out_refund_no: String,
+}
+
+/// 转账到用户零钱
+#[derive(Debug, Clone, Default)]
+pub struct TransferReq {
+    /// 转账单号
+    pub out_bill_no: String,
+    /// 收款用户的 openid
+    pub openid: String,
+    /// 转账金额
+    pub amount: Money,
+    /// 转账场景 ID
+    pub scene_id: String,
+    /// 转账备注，用户收款时可见
+    pub remark: String,
+    /// 用户收款感知
+    pub recv_perception: Option<String>,
+    /// 转账场景报备信息，(信息类型, 信息内容)
+    pub report_infos: Vec<(String, String)>,
+}
+
+/// 转账的结果，转账是否成功以回调通知为准
+#[derive(Debug, Clone)]
+pub struct TransferRes {
+    pub out_bill_no: String,
+    pub transfer_bill_no: String,
+    /// 小程序中用户确认收款时使用
+    pub package_info: Option<String>,
+}
+
+/// 支付渠道
+pub trait PaymentProvider: Sync {
+    /// 渠道名，与配置 `[payment] provider` 一致
+    fn name(&self) -> &'static str;
+    /// 发起支付，返回客户端调起支付的参数
+    fn prepay<'a>(&'a self, req: &'a PrepayReq) -> BoxFuture<'a, Result<WxPayData, Error>>;
+    /// 按商户订单号查询支付
+    fn query<'a>(&'a self, out_trade_no: &'a str) -> BoxFuture<'a, Result<PayQuery, Error>>;
+    /// 关闭未支付的订单，关闭后用户无法再支付
+    fn close<'a>(&'a self, out_trade_no: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+    /// 申请退款，结果由退款回调通知
+    fn refund<'a>(&'a self, req: &'a RefundReq) -> BoxFuture<'a, Result<RefundRes, Error>>;
+    /// 转账到用户零钱，结果由转账回调通知
+    fn transfer<'a>(&'a self, req: &'a TransferReq) -> BoxFuture<'a, Result<TransferRes, Error>>;
+    /// 验证回调通知，返回解密后的通知数据
+    fn verify_notify(&self, body: &[u8], req: &HttpRequest) -> Result<serde_json::Value, Error>;
+}
+
+/// 当前配置的支付渠道
+pub fn payment() -> &'static dyn PaymentProvider {
+    match config().payment.provider.as_str() {
+        "mock" => MockPayProvider::global(),
+        _ => &WechatPayProvider,
+    }
+}
+
+/// 验证并解析回调通知
+pub fn decode_notify<T>(body: &[u8], req: &HttpRequest) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let value = payment().verify_notify(body, req)?;
+    serde_json::from_value(value)
+        .map_err(|e| error::ErrorBadRequest(log_err(&e, "回调通知数据格式错误")))
+}
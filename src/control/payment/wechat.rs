@@ -0,0 +1,164 @@
+use actix_web::{Error, HttpRequest, error};
+use futures_util::future::BoxFuture;
+use wx_pay::decode::{WxNotify, decode_wx_notify};
+use wx_pay::verification::WxPayVerification;
+use wx_pay::{
+    Amount, Jsapi, Payer, Refund, RefundAmount, Transfer, TransferSceneReportInfo, WxPayData,
+};
+
+use super::{PayQuery, PaymentProvider, PrepayReq, RefundReq, RefundRes, TransferReq, TransferRes};
+use crate::common::{Money, config};
+use crate::control::wx_info::wx_pay_init;
+use crate::utils::utils::log_err;
+
+/// 微信支付 v3
+pub struct WechatPayProvider;
+
+impl PaymentProvider for WechatPayProvider {
+    fn name(&self) -> &'static str {
+        "wechat"
+    }
+
+    fn prepay<'a>(&'a self, req: &'a PrepayReq) -> BoxFuture<'a, Result<WxPayData, Error>> {
+        Box::pin(async move {
+            wx_pay_init()
+                .jsapi(&Jsapi {
+                    description: req.description.clone(),
+                    out_trade_no: req.out_trade_no.clone(),
+                    time_expire: req.time_expire.clone(),
+                    amount: Amount {
+                        total: req.amount.cent() as u64,
+                        ..Default::default()
+                    },
+                    payer: Payer {
+                        openid: req.openid.clone(),
+                    },
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| error::ErrorBadGateway(log_err(&e, "微信下单失败")))
+        })
+    }
+
+    fn query<'a>(&'a self, out_trade_no: &'a str) -> BoxFuture<'a, Result<PayQuery, Error>> {
+        Box::pin(async move {
+            let detail = wx_pay_init()
+                .get_transactions_by_out_trade_no(out_trade_no)
+                .await
+                .map_err(|e| error::ErrorBadGateway(log_err(&e, "微信订单查询失败")))?;
+            Ok(PayQuery {
+                out_trade_no: detail.out_trade_no,
+                transaction_id: detail.transaction_id,
+                trade_state: detail.trade_state,
+                amount: detail.amount.map(|a| Money::from_cent(a.total as i64)),
+            })
+        })
+    }
+
+    fn close<'a>(&'a self, out_trade_no: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            wx_pay_init()
+                .close(out_trade_no)
+                .await
+                .map_err(|e| error::ErrorBadGateway(log_err(&e, "微信订单关闭失败")))
+        })
+    }
+
+    fn refund<'a>(&'a self, req: &'a RefundReq) -> BoxFuture<'a, Result<RefundRes, Error>> {
+        Box::pin(async move {
+            let refund = Refund {
+                transaction_id: req.transaction_id.clone(),
+                out_trade_no: req.out_trade_no.clone(),
+                out_refund_no: req.out_refund_no.clone(),
+                reason: req.reason.clone(),
+                notify_url: Some(config().wechat_pay.refund_notify_url.clone()),
+                funds_account: None,
+                amount: RefundAmount {
+                    refund: req.amount.cent() as u64,
+                    total: req.total.cent() as u64,
+                    currency: "CNY".to_string(),
+                    from: None,
+                    payer_total: None,
+                    payer_refund: None,
+                    settlement_refund: None,
+                    settlement_total: None,
+                    discount_refund: None,
+                    refund_fee: None,
+                },
+                goods_detail: None,
+            };
+            let detail = wx_pay_init()
+                .refund(&refund)
+                .await
+                .map_err(|e| error::ErrorBadGateway(log_err(&e, "微信退款失败")))?;
+            Ok(RefundRes {
+                refund_id: detail.refund_id,
+                out_refund_no: detail.out_refund_no,
+            })
+        })
+    }
+
+    fn transfer<'a>(&'a self, req: &'a TransferReq) -> BoxFuture<'a, Result<TransferRes, Error>> {
+        Box::pin(async move {
+            let wx_pay = wx_pay_init();
+            let transfer = Transfer {
+                appid: wx_pay.appid.to_string(),
+                out_bill_no: req.out_bill_no.clone(),
+                transfer_scene_id: req.scene_id.clone(),
+                openid: req.openid.clone(),
+                user_name: None,
+                transfer_amount: req.amount.cent() as u64,
+                transfer_remark: req.remark.clone(),
+                notify_url: Some(config().wechat_pay.transfer_notify_url.clone()),
+                user_recv_perception: req.recv_perception.clone(),
+                transfer_scene_report_infos: req
+                    .report_infos
+                    .iter()
+                    .map(|(t, c)| TransferSceneReportInfo {
+                        info_type: t.clone(),
+                        info_content: c.clone(),
+                    })
+                    .collect(),
+            };
+            let detail = wx_pay
+                .transfer(&transfer)
+                .await
+                .map_err(|e| error::ErrorBadGateway(log_err(&e, "微信转账失败")))?;
+            Ok(TransferRes {
+                out_bill_no: detail.out_bill_no,
+                transfer_bill_no: detail.transfer_bill_no,
+                package_info: detail.package_info,
+            })
+        })
+    }
+
+    /// 用微信支付公钥验签，再用 apiv3 密钥解密
+    fn verify_notify(&self, body: &[u8], req: &HttpRequest) -> Result<serde_json::Value, Error> {
+        // 1. 用原始 body 进行验签
+        let body_str = std::str::from_utf8(body)?;
+        let verification = WxPayVerification::new(config().wechat_pay.pubkey.clone());
+        // 获取验签所需的 HTTP 头信息
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+        };
+        let timestamp = header("Wechatpay-Timestamp");
+        let nonce = header("Wechatpay-Nonce");
+        let signature = header("Wechatpay-Signature");
+        if WxPayVerification::is_test_signature(signature) {
+            return Err(error::ErrorNotAcceptable("测试签名"));
+        }
+        let is_verifi_ok = verification
+            .verify_response(timestamp, nonce, body_str, signature)
+            .map_err(error::ErrorInternalServerError)?;
+        if !is_verifi_ok {
+            return Err(error::ErrorNotAcceptable("签名验证失败"));
+        }
+        // 2. 验签成功后再解析 JSON
+        let params: WxNotify = serde_json::from_slice(body)?;
+        decode_wx_notify(&config().wechat_pay.apiv3, params)
+            .map_err(error::ErrorInternalServerError)
+    }
+}
@@ -0,0 +1,192 @@
+use actix_web::{Error, error};
+use chrono::{Local, NaiveDateTime};
+use redis::Commands;
+
+use crate::common::PROJECT_NAME;
+use crate::db::redis_conn;
+use crate::utils::utils::log_err;
+
+/// 活动结束后，redis 中的库存、限购记录再保留的时间
+const SECKILL_KEEP_SEC: i64 = 24 * 3600;
+
+/// 扣减秒杀库存，同时记录用户已抢购的数量。
+///
+/// 返回 -1 库存未初始化，-2 库存不足，-3 超过限购，>= 0 为剩余库存
+const RESERVE_SCRIPT: &str = r"
+local stock = redis.call('get', KEYS[1])
+if not stock then return -1 end
+stock = tonumber(stock)
+local q = tonumber(ARGV[2])
+if stock < q then return -2 end
+local bought = tonumber(redis.call('hget', KEYS[2], ARGV[1]) or '0')
+if bought + q > tonumber(ARGV[3]) then return -3 end
+redis.call('decrby', KEYS[1], q)
+redis.call('hincrby', KEYS[2], ARGV[1], q)
+redis.call('expire', KEYS[2], ARGV[4])
+return stock - q
+";
+
+/// 返还秒杀库存和用户的抢购数量，库存未初始化时不处理
+const RELEASE_SCRIPT: &str = r"
+if redis.call('exists', KEYS[1]) == 1 then
+  redis.call('incrby', KEYS[1], ARGV[2])
+end
+local bought = tonumber(redis.call('hget', KEYS[2], ARGV[1]) or '0')
+if bought > 0 then
+  redis.call('hincrby', KEYS[2], ARGV[1], -math.min(bought, tonumber(ARGV[2])))
+end
+return 1
+";
+
+fn stock_key(sec_kill_id: u32) -> String {
+    format!("{}:seckill:stock:{}", PROJECT_NAME, sec_kill_id)
+}
+/// 用户已抢购的数量，hash: uid -> 数量
+fn bought_key(sec_kill_id: u32) -> String {
+    format!("{}:seckill:bought:{}", PROJECT_NAME, sec_kill_id)
+}
+
+/// 秒杀活动，用于 redis 库存的初始化和限购判断
+#[derive(Debug, Clone)]
+pub struct SeckillStock {
+    pub id: u32,
+    /// 秒杀库存
+    pub stock: u32,
+    /// 已抢购数量
+    pub sold: u32,
+    /// 每人限购数量
+    pub limit_per_user: u32,
+    /// 结束时间，如 2025-01-01 10:00:00
+    pub end_time: String,
+}
+
+/// redis 中的库存、限购记录的有效时间：活动结束后再保留一段时间，最少 60 秒
+fn keep_sec(end_time: &str, now: NaiveDateTime) -> i64 {
+    NaiveDateTime::parse_from_str(end_time, "%Y-%m-%d %H:%M:%S")
+        .map(|end| (end - now).num_seconds() + SECKILL_KEEP_SEC)
+        .unwrap_or(SECKILL_KEEP_SEC)
+        .max(60)
+}
+
+/// 抢购失败的原因
+#[derive(Debug, PartialEq)]
+pub enum SeckillReserveErr {
+    /// 库存不足
+    SoldOut,
+    /// 超过限购
+    OverLimit,
+}
+impl SeckillReserveErr {
+    pub fn message(&self) -> &'static str {
+        match self {
+            SeckillReserveErr::SoldOut => "已抢光了",
+            SeckillReserveErr::OverLimit => "超过每人限购数量",
+        }
+    }
+}
+
+fn reserve_result(code: i64) -> Option<Result<u32, SeckillReserveErr>> {
+    match code {
+        -1 => None,
+        -2 => Some(Err(SeckillReserveErr::SoldOut)),
+        -3 => Some(Err(SeckillReserveErr::OverLimit)),
+        n => Some(Ok(n as u32)),
+    }
+}
+
+/// 在 redis 中抢占秒杀库存，成功时返回剩余库存。
+///
+/// 只有抢占成功的请求才会去操作数据库，避免大量请求同时锁住商品库存
+pub fn seckill_reserve(
+    sk: &SeckillStock,
+    uid: u64,
+    quantity: u32,
+) -> Result<Result<u32, SeckillReserveErr>, Error> {
+    let mut redis_con = redis_conn()?;
+    let ttl = keep_sec(&sk.end_time, Local::now().naive_local());
+    let script = redis::Script::new(RESERVE_SCRIPT);
+    let reserve = |con: &mut dyn redis::ConnectionLike| -> Result<i64, Error> {
+        script
+            .key(stock_key(sk.id))
+            .key(bought_key(sk.id))
+            .arg(uid)
+            .arg(quantity)
+            .arg(sk.limit_per_user)
+            .arg(ttl)
+            .invoke(con)
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "秒杀库存扣减")))
+    };
+    if let Some(res) = reserve_result(reserve(&mut *redis_con)?) {
+        return Ok(res);
+    }
+    // 库存未初始化（如刚上线、或 redis 数据丢失），按数据库的剩余库存初始化，并发时只有一个生效
+    let _: Option<String> = redis::cmd("SET")
+        .arg(stock_key(sk.id))
+        .arg(sk.stock.saturating_sub(sk.sold))
+        .arg("NX")
+        .arg("EX")
+        .arg(ttl)
+        .query(&mut *redis_con)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "秒杀库存初始化")))?;
+    Ok(reserve_result(reserve(&mut *redis_con)?).unwrap_or(Err(SeckillReserveErr::SoldOut)))
+}
+
+/// 返还秒杀库存，用于下单失败、取消订单
+pub fn seckill_release(sec_kill_id: u32, uid: u64, quantity: u32) -> Result<(), Error> {
+    let mut redis_con = redis_conn()?;
+    let _: i64 = redis::Script::new(RELEASE_SCRIPT)
+        .key(stock_key(sec_kill_id))
+        .key(bought_key(sec_kill_id))
+        .arg(uid)
+        .arg(quantity)
+        .invoke(&mut *redis_con)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "秒杀库存返还")))?;
+    Ok(())
+}
+
+/// 秒杀活动的剩余库存，redis 中没有时为 None
+pub fn seckill_remain(sec_kill_id: u32) -> Result<Option<u32>, Error> {
+    let mut redis_con = redis_conn()?;
+    let remain: Option<i64> = redis_con
+        .get(stock_key(sec_kill_id))
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "秒杀库存查询")))?;
+    Ok(remain.map(|r| r.max(0) as u32))
+}
+
+/// 清除秒杀库存，活动修改后下次抢购时按数据库重新初始化
+pub fn seckill_reset(sec_kill_id: u32) -> Result<(), Error> {
+    let mut redis_con = redis_conn()?;
+    let _: () = redis_con
+        .del(stock_key(sec_kill_id))
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "秒杀库存清除")))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seckill_keep_sec() {
+        let now =
+            NaiveDateTime::parse_from_str("2025-01-01 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            keep_sec("2025-01-01 11:00:00", now),
+            3600 + SECKILL_KEEP_SEC
+        );
+        // 已结束很久的，最少 60 秒
+        assert_eq!(keep_sec("2024-01-01 11:00:00", now), 60);
+        assert_eq!(keep_sec("bad", now), SECKILL_KEEP_SEC);
+        assert_eq!(stock_key(3), "mall_server:seckill:stock:3");
+        assert_eq!(bought_key(3), "mall_server:seckill:bought:3");
+    }
+
+    #[test]
+    fn test_seckill_reserve_result() {
+        assert_eq!(reserve_result(-1), None);
+        assert_eq!(reserve_result(-2), Some(Err(SeckillReserveErr::SoldOut)));
+        assert_eq!(reserve_result(-3), Some(Err(SeckillReserveErr::OverLimit)));
+        assert_eq!(reserve_result(0), Some(Ok(0)));
+        assert_eq!(reserve_result(8), Some(Ok(8)));
+    }
+}
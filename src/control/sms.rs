@@ -3,9 +3,7 @@ use rand::prelude::*;
 use redis::Commands;
 use sms::aliyun::Aliyun;
 
-use crate::common::{
-    PROJECT_NAME, SMS_ACCESS_KEY_ID, SMS_ACCESS_KEY_SECRET, SMS_SIGN_NAME, SMS_TEMPLATE_CODE,
-};
+use crate::common::{PROJECT_NAME, config};
 use crate::db::redis_conn;
 use crate::middleware::save_logs;
 
@@ -24,9 +22,10 @@ pub async fn sms_send_code(phone: &str) -> Result<()> {
         return Ok(());
     }
 
-    let aliyun = Aliyun::new(SMS_ACCESS_KEY_ID, SMS_ACCESS_KEY_SECRET);
+    let sms = &config().sms;
+    let aliyun = Aliyun::new(&sms.access_key_id, &sms.access_key_secret);
     let resp = match aliyun
-        .send_sms(phone, SMS_SIGN_NAME, SMS_TEMPLATE_CODE, &msg)
+        .send_sms(phone, &sms.sign_name, &sms.template_code, &msg)
         .await
     {
         Ok(d) => d,
@@ -50,6 +49,26 @@ pub async fn sms_send_code(phone: &str) -> Result<()> {
     }
 }
 
+/// 按模板发送通知短信，param 为模板变量的 json，如 {"name":"满100减10"}
+pub async fn sms_send(phone: &str, template_code: &str, param: &str) -> Result<()> {
+    let sms = &config().sms;
+    let aliyun = Aliyun::new(&sms.access_key_id, &sms.access_key_secret);
+    let resp = match aliyun
+        .send_sms(phone, &sms.sign_name, template_code, param)
+        .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            save_logs("logs/utils/sms.log", &format!("{:?}", &e));
+            return Err(anyhow!("sms接口请求出错"));
+        }
+    };
+    match resp.get("Code") {
+        Some(s) if s == "OK" => Ok(()),
+        _ => Err(anyhow!("sms发送失败: {:?}", resp.get("Message"))),
+    }
+}
+
 pub struct SmsVerify {
     pub status: u8,
     pub message: String,
@@ -0,0 +1,199 @@
+use actix_web::{Error, error};
+use mysql_quick::{TxOpts, myget, myupdate};
+use redis::Commands;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{PROJECT_NAME, config};
+use crate::db::{my_exec_first, my_run_tran_drop, my_run_tran_vec, mysql_conn, redis_conn};
+use crate::middleware::AuthUser;
+use crate::utils::jwt::get_token;
+use crate::utils::utils::log_err;
+
+/// 用户 token 版本号的缓存时间
+const TOKEN_VER_CACHE_SEC: u64 = 600;
+
+fn token_ver_key(uid: u64) -> String {
+    format!("{}:token_ver:{}", PROJECT_NAME, uid)
+}
+fn refresh_key(refresh_token: &str) -> String {
+    format!("{}:refresh:{}", PROJECT_NAME, refresh_token)
+}
+/// 用户所有的 refresh token，用于一次性吊销
+fn refresh_user_key(uid: u64) -> String {
+    format!("{}:refresh_user:{}", PROJECT_NAME, uid)
+}
+
+/// 用户当前的 token 版本号，用户不存在时为 None。
+///
+/// 优先读 redis 缓存，没有时查数据库并写入缓存；redis 不可用时直接查数据库
+pub fn token_ver(uid: u64) -> Result<Option<u32>, Error> {
+    let key = token_ver_key(uid);
+    let mut redis_con = redis_conn().ok();
+    if let Some(r) = redis_con.as_mut() {
+        let cached: Option<u32> = r.get(&key).unwrap_or(None);
+        if cached.is_some() {
+            return Ok(cached);
+        }
+    }
+    let mut conn = mysql_conn()?;
+    let ver: Option<u32> = my_exec_first(
+        &mut conn,
+        "select token_ver from usr_silent where id = ?",
+        (uid,),
+    )?;
+    if let (Some(r), Some(v)) = (redis_con.as_mut(), ver) {
+        // 只在没有缓存时写入，不覆盖 bump_token_ver 同时写入的新版本号
+        let res: redis::RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(&key)
+            .arg(v)
+            .arg("NX")
+            .arg("EX")
+            .arg(TOKEN_VER_CACHE_SEC)
+            .query(&mut **r);
+        if let Err(e) = res {
+            log_err(&e, "token 版本号缓存");
+        }
+    }
+    Ok(ver)
+}
+
+/// 使用户所有已签发的 token 和 refresh token 立即失效。
+///
+/// 用于退出登录、修改用户权限或角色等。
+/// 新的版本号直接写入缓存，不删除缓存，避免并发的 `token_ver` 把旧版本号写回缓存
+pub fn bump_token_ver(uid: u64) -> Result<(), Error> {
+    let mut conn = mysql_conn()?;
+    #[derive(Deserialize)]
+    struct VerGet {
+        token_ver: u32,
+    }
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    let ver: Vec<VerGet> = match my_run_tran_drop(
+        &mut tran,
+        myupdate!("usr_silent", uid, { "token_ver": ["incr", 1] }),
+    )
+    .and_then(|_| my_run_tran_vec(&mut tran, myget!("usr_silent", uid, "token_ver")))
+    {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+    let mut redis_con = redis_conn()?;
+    if let Some(v) = ver.first() {
+        let _: () = redis_con
+            .set_ex(token_ver_key(uid), v.token_ver, TOKEN_VER_CACHE_SEC)
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "token 版本号缓存")))?;
+    }
+    let user_key = refresh_user_key(uid);
+    let tokens: Vec<String> = redis_con
+        .smembers(&user_key)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "refresh token 查询")))?;
+    let mut keys: Vec<String> = tokens.iter().map(|t| refresh_key(t)).collect();
+    keys.push(user_key);
+    let _: () = redis_con
+        .del(keys)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "token 吊销")))?;
+    Ok(())
+}
+
+/// refresh token 在 redis 中保存的信息
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct RefreshInfo {
+    uid: u64,
+    ver: u32,
+    /// 是否为管理后台登录，决定 token 的有效时间
+    manage: bool,
+}
+
+/// 登录后签发的 token 对
+#[derive(Debug)]
+pub struct LoginTokens {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// 签发 token 和 refresh token，manage 为是否是管理后台登录
+pub fn issue_login_tokens(uid: u64, manage: bool) -> Result<LoginTokens, Error> {
+    let ver = token_ver(uid)?.ok_or(error::ErrorUnauthorized("用户不存在"))?;
+    issue_tokens(&RefreshInfo { uid, ver, manage })
+}
+
+fn issue_tokens(info: &RefreshInfo) -> Result<LoginTokens, Error> {
+    let cfg = config();
+    let expires_sec = if info.manage {
+        cfg.jwt.manage_expires_sec
+    } else {
+        cfg.jwt.normal_expires_sec
+    };
+    let token = get_token(AuthUser { id: info.uid }, info.ver, expires_sec)?;
+    let refresh_token = uuid::Uuid::new_v4().simple().to_string();
+    let value = serde_json::to_string(info)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "refresh token 序列化")))?;
+    let refresh_sec = cfg.jwt.refresh_expires_sec as u64;
+    let user_key = refresh_user_key(info.uid);
+    let mut redis_con = redis_conn()?;
+    let _: () = redis::pipe()
+        .atomic()
+        .set_ex(refresh_key(&refresh_token), value, refresh_sec)
+        .ignore()
+        .sadd(&user_key, &refresh_token)
+        .ignore()
+        .expire(&user_key, refresh_sec as i64)
+        .ignore()
+        .query(&mut *redis_con)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "refresh token 保存")))?;
+    Ok(LoginTokens {
+        token,
+        refresh_token,
+    })
+}
+
+/// 取出并删除 refresh token，每个 refresh token 只能使用一次
+fn take_refresh(refresh_token: &str) -> Result<Option<RefreshInfo>, Error> {
+    let mut redis_con = redis_conn()?;
+    let value: Option<String> = redis_con
+        .get_del(refresh_key(refresh_token))
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "refresh token 查询")))?;
+    let info = value.and_then(|v| serde_json::from_str::<RefreshInfo>(&v).ok());
+    if let Some(i) = &info {
+        let _: () = redis_con
+            .srem(refresh_user_key(i.uid), refresh_token)
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "refresh token 删除")))?;
+    }
+    Ok(info)
+}
+
+/// 用 refresh token 换取新的 token 对，旧的 refresh token 随即失效
+pub fn refresh_login_tokens(refresh_token: &str) -> Result<LoginTokens, Error> {
+    let expired = || error::ErrorUnauthorized("登录过期，请重新登录");
+    let info = take_refresh(refresh_token)?.ok_or_else(expired)?;
+    // 签发后用户的权限、角色有变更，或已退出登录
+    if token_ver(info.uid)? != Some(info.ver) {
+        return Err(expired());
+    }
+    issue_tokens(&info)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_keys() {
+        assert_eq!(token_ver_key(12), "mall_server:token_ver:12");
+        assert_eq!(refresh_key("abc"), "mall_server:refresh:abc");
+        assert_eq!(refresh_user_key(12), "mall_server:refresh_user:12");
+        let info = RefreshInfo {
+            uid: 12,
+            ver: 3,
+            manage: true,
+        };
+        let s = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<RefreshInfo>(&s).unwrap(), info);
+    }
+}
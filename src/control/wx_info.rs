@@ -1,30 +1,25 @@
-use crate::common::{
-    PROJECT_NAME, WECHAT_GZH_APP_ID, WECHAT_GZH_APP_SECRET, WECHAT_GZH_JS_SDK_URL,
-    WECHAT_MINI_APP_ID, WECHAT_MINI_APP_SECRET, WECHAT_PAY_APIV3, WECHAT_PAY_MCH_ID,
-    WECHAT_PAY_NOTIFY_URL, WECHAT_PAY_PUBKEY, WECHAT_PAY_SERIAL, WECHAT_PRIVATE_KEY,
-};
+use crate::common::{PROJECT_NAME, config};
 use crate::db::redis_conn;
 use crate::utils::random::rand_unique;
 use crate::utils::utils::log_err;
-use actix_web::{Error, error, web};
+use actix_web::{Error, error};
 use redis::Commands;
 use serde::{Deserialize, Serialize};
 use sha1::Digest;
 use utoipa::ToSchema;
 use wx_pay::WxPay;
-use wx_pay::decode::{WxNotify, decode_wx_notify};
-use wx_pay::verification::WxPayVerification;
 
 /// 微信支付 初始化
-pub fn wx_pay_init<'a>() -> WxPay<'a> {
+pub fn wx_pay_init() -> WxPay<'static> {
+    let cfg = config();
     WxPay {
-        appid: WECHAT_MINI_APP_ID,
-        mchid: WECHAT_PAY_MCH_ID,
-        private_key: WECHAT_PRIVATE_KEY,
-        serial_no: WECHAT_PAY_SERIAL,
-        api_v3_private_key: WECHAT_PAY_APIV3,
-        notify_url: WECHAT_PAY_NOTIFY_URL,
-        wx_public_key: Some(WECHAT_PAY_PUBKEY),
+        appid: &cfg.wechat.mini_app_id,
+        mchid: &cfg.wechat_pay.mch_id,
+        private_key: &cfg.wechat_pay.private_key,
+        serial_no: &cfg.wechat_pay.serial,
+        api_v3_private_key: &cfg.wechat_pay.apiv3,
+        notify_url: &cfg.wechat_pay.notify_url,
+        wx_public_key: Some(&cfg.wechat_pay.pubkey),
         wx_public_key_id: None,
     }
 }
@@ -39,12 +34,16 @@ pub async fn get_wx_mini_access_token() -> Result<String, Error> {
     let mut redis_con = redis_conn()?;
 
     let wx_url = "https://api.weixin.qq.com/cgi-bin/token?appid=".to_string()
-        + WECHAT_MINI_APP_ID
+        + &config().wechat.mini_app_id
         + "&secret="
-        + WECHAT_MINI_APP_SECRET
+        + &config().wechat.mini_app_secret
         + "&grant_type=client_credential";
 
-    let at_key_name = format!("{}:{}:access_token", PROJECT_NAME, WECHAT_MINI_APP_ID);
+    let at_key_name = format!(
+        "{}:{}:access_token",
+        PROJECT_NAME,
+        config().wechat.mini_app_id
+    );
     let mut at_v: String = redis_con.get(&at_key_name).unwrap_or("".to_string());
     if at_v == String::from("") {
         let access_token_res: WxAccessToken = reqwest::get(wx_url)
@@ -74,12 +73,16 @@ pub async fn get_wx_gzh_access_token() -> Result<String, Error> {
     let mut redis_con = redis_conn()?;
 
     let wx_url = "https://api.weixin.qq.com/cgi-bin/token?appid=".to_string()
-        + WECHAT_GZH_APP_ID
+        + &config().wechat.gzh_app_id
         + "&secret="
-        + WECHAT_GZH_APP_SECRET
+        + &config().wechat.gzh_app_secret
         + "&grant_type=client_credential";
 
-    let at_key_name = format!("{}:{}:access_token", PROJECT_NAME, WECHAT_GZH_APP_ID);
+    let at_key_name = format!(
+        "{}:{}:access_token",
+        PROJECT_NAME,
+        config().wechat.gzh_app_id
+    );
     let mut at_v: String = redis_con.get(&at_key_name).unwrap_or("".to_string());
     if at_v == String::from("") {
         let access_token_res: WxAccessToken = reqwest::get(wx_url)
@@ -117,7 +120,11 @@ pub async fn get_wx_gzh_jsapi_ticket() -> Result<String, Error> {
         + &access_token
         + "&type=jsapi";
 
-    let at_key_name = format!("{}:{}:jsapi_ticket", PROJECT_NAME, WECHAT_GZH_APP_ID);
+    let at_key_name = format!(
+        "{}:{}:jsapi_ticket",
+        PROJECT_NAME,
+        config().wechat.gzh_app_id
+    );
     let mut at_v: String = redis_con.get(&at_key_name).unwrap_or("".to_string());
     if at_v == String::from("") {
         let res: WxJsapiTicket = reqwest::get(wx_url)
@@ -157,7 +164,10 @@ pub async fn sign_wx_gzh_jssdk() -> Result<WxJsSdkSign, Error> {
 
     let content = format!(
         "jsapi_ticket={}&noncestr={}&timestamp={}&url={}",
-        ticket, noncestr, timestamp, WECHAT_GZH_JS_SDK_URL
+        ticket,
+        noncestr,
+        timestamp,
+        config().wechat.gzh_js_sdk_url
     );
 
     let mut hasher = sha1::Sha1::new();
@@ -170,7 +180,7 @@ pub async fn sign_wx_gzh_jssdk() -> Result<WxJsSdkSign, Error> {
         .join("");
 
     Ok(WxJsSdkSign {
-        app_id: WECHAT_GZH_APP_ID.to_string(),
+        app_id: config().wechat.gzh_app_id.clone(),
         signature,
         noncestr,
         timestamp,
@@ -190,9 +200,9 @@ pub struct WXGzhWebAccessToken {
 pub async fn get_wx_gzh_web_silent(code: &str) -> Result<WXGzhWebAccessToken, Error> {
     let mut redis_con = redis_conn()?;
     let wx_url = "https://api.weixin.qq.com/sns/oauth2/access_token?appid=".to_string()
-        + WECHAT_GZH_APP_ID
+        + &config().wechat.gzh_app_id
         + "&secret="
-        + WECHAT_GZH_APP_SECRET
+        + &config().wechat.gzh_app_secret
         + "&code="
         + code
         + "&grant_type=authorization_code";
@@ -211,7 +221,9 @@ pub async fn get_wx_gzh_web_silent(code: &str) -> Result<WXGzhWebAccessToken, Er
     };
     let at_key_name = format!(
         "{}:{}:web_access_token:{}",
-        PROJECT_NAME, WECHAT_GZH_APP_ID, res.openid
+        PROJECT_NAME,
+        config().wechat.gzh_app_id,
+        res.openid
     );
     let at_value = res.access_token.clone();
 
@@ -225,7 +237,9 @@ pub async fn get_wx_gzh_web_silent(code: &str) -> Result<WXGzhWebAccessToken, Er
 pub fn get_wx_gzh_web_access_token(openid: &str) -> Result<String, Error> {
     let at_key_name = format!(
         "{}:{}:web_access_token:{}",
-        PROJECT_NAME, WECHAT_GZH_APP_ID, openid
+        PROJECT_NAME,
+        config().wechat.gzh_app_id,
+        openid
     );
     let mut redis_con = redis_conn()?;
     let at_v: String = redis_con.get(&at_key_name).unwrap_or("".to_string());
@@ -264,49 +278,6 @@ pub async fn get_wx_gzh_web_user_info(openid: &str) -> Result<WXGzhWebUserInfo,
     Ok(res)
 }
 
-/// 微信支付的回调数据验证和解密
-pub fn get_decode_wx_notify<T>(
-    body: web::Bytes,
-    req: actix_web::HttpRequest,
-) -> anyhow::Result<T, Error>
-where
-    T: serde::de::DeserializeOwned,
-{
-    // 1. 用原始 body 进行验签
-    let body_str = std::str::from_utf8(&body)?;
-    let verification = WxPayVerification::new(WECHAT_PAY_PUBKEY.to_string());
-    // 获取验签所需的 HTTP 头信息
-    let timestamp = req
-        .headers()
-        .get("Wechatpay-Timestamp")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    let nonce = req
-        .headers()
-        .get("Wechatpay-Nonce")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    let signature = req
-        .headers()
-        .get("Wechatpay-Signature")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    if WxPayVerification::is_test_signature(signature) {
-        return Err(error::ErrorNotAcceptable("测试签名"));
-    }
-    let is_verifi_ok = verification
-        .verify_response(timestamp, nonce, body_str, signature)
-        .map_err(|e| error::ErrorInternalServerError(e))?;
-    if !is_verifi_ok {
-        return Err(error::ErrorNotAcceptable("签名验证失败"));
-    }
-    // 2. 验签成功后再解析 JSON
-    let params: WxNotify = serde_json::from_slice(&body)?;
-    let data: T = decode_wx_notify(WECHAT_PAY_APIV3, params)
-        .map_err(|e| error::ErrorInternalServerError(e))?;
-    Ok(data)
-}
-
 #[cfg(test)]
 mod test {
     use sha1::{Digest, Sha1};
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use actix_web::error::{self, Error};
+use serde::{Deserialize, Serialize};
+
+use super::wx_info::get_wx_mini_access_token;
+
+/// 订阅消息 thing 类型的字段，最多 20 个字符
+pub const WX_THING_MAX_CHARS: usize = 20;
+
+/// 模板字段的值
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct WxMessageValue {
+    pub value: String,
+}
+
+/// 发送订阅消息
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct WxSubscribeMessage {
+    /// 接收者（用户）的 openid
+    pub touser: String,
+    /// 所需下发的订阅模板id
+    pub template_id: String,
+    /// 点击模板卡片后的跳转页面，仅限本小程序内的页面
+    pub page: Option<String>,
+    /// 模板内容，格式形如 { "thing1": { "value": "xxx" } }
+    pub data: BTreeMap<String, WxMessageValue>,
+}
+impl WxSubscribeMessage {
+    pub fn new(touser: &str, template_id: &str, page: &str) -> Self {
+        WxSubscribeMessage {
+            touser: touser.to_string(),
+            template_id: template_id.to_string(),
+            page: if page.is_empty() {
+                None
+            } else {
+                Some(page.to_string())
+            },
+            data: BTreeMap::new(),
+        }
+    }
+    /// 添加模板字段
+    pub fn value(mut self, key: &str, value: &str) -> Self {
+        self.data.insert(
+            key.to_string(),
+            WxMessageValue {
+                value: value.to_string(),
+            },
+        );
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct WxMessageRes {
+    pub errcode: Option<i64>,
+    pub errmsg: Option<String>,
+}
+
+/// 截取 thing 类型字段的值，超出时以 … 结尾
+pub fn wx_thing_value(s: &str) -> String {
+    if s.chars().count() <= WX_THING_MAX_CHARS {
+        return s.to_string();
+    }
+    let mut v: String = s.chars().take(WX_THING_MAX_CHARS - 1).collect();
+    v.push('…');
+    v
+}
+
+/// 小程序，[发送订阅消息](https://developers.weixin.qq.com/miniprogram/dev/OpenApiDoc/mp-message-management/subscribe-message/sendMessage.html)
+/// 用户未订阅或已拒收时，返回 errcode 43101
+pub async fn send_wx_subscribe_message(body: &WxSubscribeMessage) -> Result<WxMessageRes, Error> {
+    let access_token = get_wx_mini_access_token().await?;
+    let url = "https://api.weixin.qq.com/cgi-bin/message/subscribe/send?access_token=".to_string()
+        + &access_token;
+
+    let client = reqwest::Client::new();
+    let res: WxMessageRes = client
+        .post(url)
+        .json(body)
+        .send()
+        .await
+        .map_err(error::ErrorBadGateway)?
+        .json()
+        .await
+        .map_err(error::ErrorBadGateway)?;
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wx_message_body() {
+        assert_eq!(wx_thing_value("满100减10"), "满100减10");
+        let long = "一二三四五六七八九十一二三四五六七八九十一";
+        assert_eq!(wx_thing_value(long).chars().count(), WX_THING_MAX_CHARS);
+        assert!(wx_thing_value(long).ends_with('…'));
+
+        let msg = WxSubscribeMessage::new("oid", "tid", "")
+            .value("thing1", "满100减10")
+            .value("time2", "2026-10-20 10:00");
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["page"], serde_json::Value::Null);
+        assert_eq!(json["data"]["thing1"]["value"], "满100减10");
+    }
+}
@@ -2,4 +2,4 @@ mod mysql_conn;
 pub(crate) use mysql_conn::*;
 
 mod redis_conn;
-pub(crate) use redis_conn::redis_conn;
+pub(crate) use redis_conn::{init_redis_pool, redis_conn};
@@ -1,21 +1,97 @@
-use actix_web::{Error, error};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use actix_web::{Error, error, web};
+use mysql::prelude::{FromRow, Queryable};
+use mysql::{Opts, OptsBuilder, Params, Pool, PoolConstraints, PoolOpts};
 use mysql_quick::{
-    MysqlQuick, PooledConn, Transaction, TxOpts, my_run_drop as run_drop,
-    my_run_tran_drop as run_tran_drop, my_run_tran_vec as run_tran_vec, my_run_vec as run_vec,
+    PooledConn, Transaction, TxOpts, my_run_drop as run_drop, my_run_tran_drop as run_tran_drop,
+    my_run_tran_vec as run_tran_vec, my_run_vec as run_vec,
 };
 use serde::de::DeserializeOwned;
 
-use crate::{common::MYSQL_URL, utils::utils::log_aes_err};
+use crate::{
+    common::{MysqlConfig, config},
+    utils::utils::log_aes_err,
+};
+
+/// 全局 mysql 连接池，是连接池唯一的持有者。
+/// 接口、定时任务、回调处理都通过 `mysql_conn()` 从这里取连接，`AppData` 不再保存连接池
+static MYSQL_POOL: OnceLock<Pool> = OnceLock::new();
+
+/// 毫秒数转为超时时间，0 为不限制
+fn timeout_ms(ms: u64) -> Option<Duration> {
+    if ms == 0 {
+        None
+    } else {
+        Some(Duration::from_millis(ms))
+    }
+}
+
+/// 按配置创建 mysql 连接池
+fn new_mysql_pool(cfg: &MysqlConfig) -> anyhow::Result<Pool> {
+    let constraints = PoolConstraints::new(cfg.pool_min, cfg.pool_max)
+        .ok_or_else(|| anyhow::anyhow!("mysql.pool_min 不能大于 mysql.pool_max"))?;
+    let opts = OptsBuilder::from_opts(Opts::from_url(&cfg.url)?)
+        .pool_opts(PoolOpts::default().with_constraints(constraints))
+        .tcp_connect_timeout(timeout_ms(cfg.connect_timeout_ms))
+        .read_timeout(timeout_ms(cfg.read_timeout_ms))
+        .write_timeout(timeout_ms(cfg.write_timeout_ms));
+    Ok(Pool::new(opts)?)
+}
+
+/// 启动时创建全局 mysql 连接池，连不上数据库时返回错误
+pub fn init_mysql_pool() -> anyhow::Result<()> {
+    let pool = new_mysql_pool(&config().mysql)?;
+    MYSQL_POOL.get_or_init(|| pool);
+    Ok(())
+}
+
+/// 全局 mysql 连接池，未初始化时（如单元测试、定时任务）按配置创建
+pub fn mysql_pool() -> anyhow::Result<&'static Pool, Error> {
+    if let Some(p) = MYSQL_POOL.get() {
+        return Ok(p);
+    }
+    let pool = new_mysql_pool(&config().mysql)
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, "数据库连接出错")))?;
+    Ok(MYSQL_POOL.get_or_init(|| pool))
+}
 
 pub fn mysql_conn() -> anyhow::Result<PooledConn, Error> {
-    let conn = MysqlQuick::new(MYSQL_URL)
-        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, "数据库连接出错")))?
-        .pool
-        .get_conn()
+    mysql_pool_conn(mysql_pool()?)
+}
+
+/// 从指定连接池获取连接，超时时间见配置 mysql.acquire_timeout_ms
+pub fn mysql_pool_conn(pool: &Pool) -> anyhow::Result<PooledConn, Error> {
+    let timeout = Duration::from_millis(config().mysql.acquire_timeout_ms);
+    let conn = pool
+        .try_get_conn(timeout)
         .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, "数据库连接出错2")))?;
     Ok(conn)
 }
 
+/// 在阻塞线程池中执行同步的数据库、redis 操作，避免阻塞 actix 的工作线程
+pub async fn db_block<F, R>(f: F) -> anyhow::Result<R, Error>
+where
+    F: FnOnce() -> anyhow::Result<R, Error> + Send + 'static,
+    R: Send + 'static,
+{
+    // actix 的 Error 不能跨线程传递，先转为 (状态码, 信息)，回到工作线程后再还原
+    web::block(move || f().map_err(|e| (e.as_response_error().status_code(), e.to_string())))
+        .await
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, "数据库任务执行出错")))?
+        .map_err(|(status, msg)| error::InternalError::new(msg, status).into())
+}
+
+/// 在阻塞线程池中取 mysql 连接并执行 f
+pub async fn mysql_block<F, R>(f: F) -> anyhow::Result<R, Error>
+where
+    F: FnOnce(&mut PooledConn) -> anyhow::Result<R, Error> + Send + 'static,
+    R: Send + 'static,
+{
+    db_block(move || mysql_conn().and_then(|mut conn| f(&mut conn))).await
+}
+
 pub fn mysql_tran(conn: &mut PooledConn) -> anyhow::Result<Transaction, Error> {
     let tran = conn
         .start_transaction(TxOpts::default())
@@ -52,3 +128,140 @@ pub fn my_run_tran_drop(tran: &mut Transaction, sql: String) -> anyhow::Result<u
         .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, &sql)))?;
     Ok(data)
 }
+
+/// 参数绑定查询，返回第一行。sql 中用 `?` 占位，用户输入只能通过 params 传入
+pub fn my_exec_first<T, P>(
+    conn: &mut PooledConn,
+    sql: &str,
+    params: P,
+) -> anyhow::Result<Option<T>, Error>
+where
+    T: FromRow,
+    P: Into<Params>,
+{
+    conn.exec_first(sql, params)
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, sql)))
+}
+
+/// 参数绑定查询，返回所有行
+pub fn my_exec_vec<T, P>(
+    conn: &mut PooledConn,
+    sql: &str,
+    params: P,
+) -> anyhow::Result<Vec<T>, Error>
+where
+    T: FromRow,
+    P: Into<Params>,
+{
+    conn.exec(sql, params)
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, sql)))
+}
+
+/// 参数绑定执行，返回影响的行数
+pub fn my_exec_drop<P>(conn: &mut PooledConn, sql: &str, params: P) -> anyhow::Result<u64, Error>
+where
+    P: Into<Params>,
+{
+    conn.exec_drop(sql, params)
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, sql)))?;
+    Ok(conn.affected_rows())
+}
+
+/// 事务中参数绑定查询，返回所有行
+pub fn my_exec_tran_vec<T, P>(
+    tran: &mut Transaction,
+    sql: &str,
+    params: P,
+) -> anyhow::Result<Vec<T>, Error>
+where
+    T: FromRow,
+    P: Into<Params>,
+{
+    tran.exec(sql, params)
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, sql)))
+}
+
+/// 事务中参数绑定执行，返回影响的行数
+pub fn my_exec_tran_drop<P>(
+    tran: &mut Transaction,
+    sql: &str,
+    params: P,
+) -> anyhow::Result<u64, Error>
+where
+    P: Into<Params>,
+{
+    tran.exec_drop(sql, params)
+        .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, sql)))?;
+    Ok(tran.affected_rows())
+}
+
+/// `in (...)` 的占位符，如 3 个参数时为 `?,?,?`
+pub fn in_placeholders(n: usize) -> String {
+    vec!["?"; n].join(",")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 带引号、注释、分号的输入，只能作为值，不会改变 sql
+    const PAYLOADS: [&str; 5] = [
+        r#"admin" or "1"="1"#,
+        "admin' or '1'='1",
+        "admin' -- ",
+        "x'); drop table t_bind; --",
+        r#"o'brien "\" \\"#,
+    ];
+
+    /// 临时表只在当前连接可见，不影响数据库中的表
+    fn temp_table(conn: &mut PooledConn) {
+        conn.query_drop(
+            "create temporary table t_bind (id int primary key, name varchar(100) not null)",
+        )
+        .unwrap();
+        my_exec_drop(conn, "insert into t_bind values (1, ?)", ("admin",)).unwrap();
+    }
+
+    #[test]
+    #[ignore = "需要 mysql，cargo test -- --ignored"]
+    fn test_exec_first_bind_quotes() {
+        let mut conn = mysql_conn().unwrap();
+        temp_table(&mut conn);
+        for (i, p) in PAYLOADS.iter().enumerate() {
+            let found: Option<u32> =
+                my_exec_first(&mut conn, "select id from t_bind where name = ?", (p,)).unwrap();
+            assert_eq!(found, None, "{}", p);
+            // 原样写入，原样读出
+            my_exec_drop(&mut conn, "insert into t_bind values (?, ?)", (i + 10, p)).unwrap();
+            let name: Option<String> =
+                my_exec_first(&mut conn, "select name from t_bind where id = ?", (i + 10,))
+                    .unwrap();
+            assert_eq!(name.as_deref(), Some(*p));
+        }
+        let count: Option<u32> =
+            my_exec_first(&mut conn, "select count(*) from t_bind", ()).unwrap();
+        assert_eq!(count, Some(PAYLOADS.len() as u32 + 1));
+    }
+
+    #[test]
+    #[ignore = "需要 mysql，cargo test -- --ignored"]
+    fn test_in_placeholders_bind_quotes() {
+        let mut conn = mysql_conn().unwrap();
+        temp_table(&mut conn);
+        let sql = format!(
+            "select id from t_bind where name in ({}) order by id",
+            in_placeholders(PAYLOADS.len())
+        );
+        let ids: Vec<u32> = my_exec_vec(&mut conn, &sql, PAYLOADS.to_vec()).unwrap();
+        assert!(ids.is_empty());
+
+        let mut names = PAYLOADS.to_vec();
+        names.push("admin");
+        let sql = format!(
+            "select id from t_bind where name in ({}) order by id",
+            in_placeholders(names.len())
+        );
+        let ids: Vec<u32> = my_exec_vec(&mut conn, &sql, names).unwrap();
+        assert_eq!(ids, vec![1]);
+    }
+}
@@ -1,14 +1,59 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
 use actix_web::{Error, error};
-use redis::{Client, Connection};
+use r2d2::{Pool, PooledConnection};
+use redis::Client;
+
+use crate::{
+    common::{RedisConfig, config},
+    utils::utils::log_err,
+};
+
+/// redis 连接池
+pub type RedisPool = Pool<Client>;
+/// 从连接池取出的 redis 连接，可直接调用 `redis::Commands` 的方法
+pub type RedisConn = PooledConnection<Client>;
 
-use crate::{common::REDIS_URL, utils::utils::log_err};
+/// 全局 redis 连接池，是连接池唯一的持有者，`AppData` 不保存连接池
+static REDIS_POOL: OnceLock<RedisPool> = OnceLock::new();
+
+/// 按配置创建 redis 连接池，不预先建立连接
+fn new_redis_pool(cfg: &RedisConfig) -> anyhow::Result<RedisPool> {
+    let client = Client::open(cfg.url.as_str())?;
+    let min_idle = if cfg.pool_min_idle == 0 {
+        None
+    } else {
+        Some(cfg.pool_min_idle)
+    };
+    let pool = Pool::builder()
+        .max_size(cfg.pool_max)
+        .min_idle(min_idle)
+        .connection_timeout(Duration::from_millis(cfg.acquire_timeout_ms))
+        .build_unchecked(client);
+    Ok(pool)
+}
 
-pub fn redis_conn() -> anyhow::Result<Connection, Error> {
-    let url = REDIS_URL;
-    let client = Client::open(url)
+/// 启动时创建全局 redis 连接池
+pub fn init_redis_pool() -> anyhow::Result<()> {
+    let pool = new_redis_pool(&config().redis)?;
+    REDIS_POOL.get_or_init(|| pool);
+    Ok(())
+}
+
+/// 全局 redis 连接池，未初始化时（如单元测试、定时任务）按配置创建
+pub fn redis_pool() -> anyhow::Result<&'static RedisPool, Error> {
+    if let Some(p) = REDIS_POOL.get() {
+        return Ok(p);
+    }
+    let pool = new_redis_pool(&config().redis)
         .map_err(|e| error::ErrorInternalServerError(log_err(&e, "Redis连接出错啦")))?;
-    let con = client
-        .get_connection()
+    Ok(REDIS_POOL.get_or_init(|| pool))
+}
+
+pub fn redis_conn() -> anyhow::Result<RedisConn, Error> {
+    let con = redis_pool()?
+        .get()
         .map_err(|e| error::ErrorInternalServerError(log_err(&e, "Redis连接出错啦～")))?;
     Ok(con)
 }
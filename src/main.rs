@@ -13,34 +13,64 @@ mod middleware;
 mod routes;
 mod utils;
 
-use crate::common::SERVER_PROT;
+use crate::common::{Config, config_data};
 use crate::control::app_data::AppData;
-use crate::middleware::{CustomRootSpanBuilder, IpExtractor};
+use crate::control::jobs::start_jobs;
+use crate::control::payment::payment;
+use crate::db::{init_mysql_pool, init_redis_pool};
+use crate::middleware::{CustomRootSpanBuilder, IpExtractor, check_role_permissions};
 use crate::routes::*;
-// use crate::utils::jobs::run_jobs;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let port: u16 = SERVER_PROT;
+    let cfg = match Config::init() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    // 连接池只在启动时创建一次
+    if let Err(e) = init_mysql_pool() {
+        eprintln!("mysql 连接池创建失败: {e}");
+        std::process::exit(1);
+    }
+    if let Err(e) = init_redis_pool() {
+        eprintln!("redis 连接池创建失败: {e}");
+        std::process::exit(1);
+    }
+    let app_data = web::Data::new(AppData::new());
+    let port: u16 = cfg.server.port;
     println!("██████████ PORT: {} ██████████", port);
+    println!("支付渠道: {}", payment().name());
     std::fs::create_dir_all("static/images")?;
 
-    // 定时任务执行。
-    // run_jobs();
+    // 检查角色中是否有未声明的接口权限
+    if let Err(e) = check_role_permissions() {
+        eprintln!("角色权限检查失败: {e}");
+    }
+
+    // 定时任务，在单独的线程中执行
+    if let Err(e) = start_jobs() {
+        eprintln!("定时任务启动失败: {e}");
+        std::process::exit(1);
+    }
 
     HttpServer::new(move || {
+        let mut cors = Cors::default();
+        for origin in &cfg.server.cors_origins {
+            cors = cors.allowed_origin(origin);
+        }
         let mut app = App::new()
-            .app_data(web::Data::new(AppData::new()))
+            .app_data(app_data.clone())
+            .app_data(config_data())
             .app_data(
                 MultipartFormConfig::default()
                     .total_limit(200 * 1024 * 1024) // 200 MB
                     .memory_limit(100 * 1024 * 1024), // 100 MB
             )
             .wrap(
-                Cors::default()
-                    .allowed_origin("http://localhost:9000")
-                    .allowed_origin("https://xxx.com")
-                    .allowed_methods(vec!["GET", "POST", "PUT"])
+                cors.allowed_methods(vec!["GET", "POST", "PUT"])
                     .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
                     .allowed_header(header::CONTENT_TYPE)
                     .supports_credentials()
@@ -69,11 +99,16 @@ async fn main() -> std::io::Result<()> {
             .service(manage_system_role_update)
             .service(manage_system_role_del)
             .service(manage_system_role_user)
+            .service(manage_system_permission_list)
             .service(manage_system_module_switch_list)
             .service(manage_system_module_switch_change)
+            .service(manage_system_job_list)
+            .service(manage_system_job_runs)
+            .service(manage_system_job_run)
             .service(manage_user_search)
             .service(manage_user_update_authority)
             .service(manage_user_update_user_role)
+            .service(manage_user_session_revoke)
             .service(manage_user_all_users)
             .service(manage_user_feedback_list)
             .service(manage_user_search_phone)
@@ -83,6 +118,14 @@ async fn main() -> std::io::Result<()> {
             .service(manage_user_withdraw_req_list)
             .service(manage_user_withdraw_req_status)
             .service(manage_user_withdraw_req_del)
+            .service(manage_user_recharge_amount_add)
+            .service(manage_user_recharge_amount_list)
+            .service(manage_user_recharge_amount_del)
+            .service(manage_user_recharge_amount_status)
+            .service(manage_user_recharge_list)
+            .service(manage_user_pocket_reconcile)
+            .service(manage_user_pocket_audit_list)
+            .service(manage_user_pocket_status)
             .service(manage_mall_cat_list)
             .service(manage_mall_cat_add)
             .service(manage_mall_cat_del)
@@ -125,6 +168,37 @@ async fn main() -> std::io::Result<()> {
             .service(manage_mall_coupon_condition_add)
             .service(manage_mall_coupon_condition_list)
             .service(manage_mall_coupon_condition_search)
+            .service(manage_mall_coupon_campaign_add)
+            .service(manage_mall_coupon_campaign_list)
+            .service(manage_mall_coupon_campaign_run)
+            .service(manage_mall_coupon_campaign_stop)
+            .service(manage_mall_coupon_campaign_del)
+            .service(manage_mall_coupon_campaign_code_list)
+            .service(manage_mall_coupon_campaign_code_export)
+            .service(manage_mall_full_reduction_add)
+            .service(manage_mall_full_reduction_list)
+            .service(manage_mall_full_reduction_del)
+            .service(manage_mall_full_reduction_status)
+            .service(manage_mall_freight_add)
+            .service(manage_mall_freight_list)
+            .service(manage_mall_freight_del)
+            .service(manage_mall_seckill_add)
+            .service(manage_mall_seckill_list)
+            .service(manage_mall_seckill_del)
+            .service(manage_mall_seckill_status)
+            .service(manage_mall_group_buy_add)
+            .service(manage_mall_group_buy_list)
+            .service(manage_mall_group_buy_del)
+            .service(manage_mall_group_buy_status)
+            .service(manage_mall_group_buy_group_list)
+            .service(manage_mall_group_buy_group_detail)
+            .service(manage_mall_review_list)
+            .service(manage_mall_review_audit)
+            .service(manage_mall_review_hide)
+            .service(manage_mall_review_reply)
+            .service(manage_mall_after_sale_list)
+            .service(manage_mall_after_sale_audit)
+            .service(manage_mall_after_sale_receive)
             .service(manage_mall_brand_add)
             .service(manage_mall_brand_list)
             .service(manage_mall_brand_search)
@@ -151,6 +225,11 @@ async fn main() -> std::io::Result<()> {
             .service(manage_sales_main_sale_del)
             .service(manage_sales_sale_user_del)
             .service(manage_sales_records_list)
+            .service(manage_sales_commission_rule_add)
+            .service(manage_sales_commission_rule_list)
+            .service(manage_sales_commission_rule_del)
+            .service(manage_sales_commission_rule_status)
+            .service(manage_sales_commission_rule_preview)
             // .service(upload_image)
             // .service(upload_avatar)
             .service(upload_file)
@@ -163,6 +242,8 @@ async fn main() -> std::io::Result<()> {
             .service(login_wechat_gzh_info)
             .service(login_register_manage)
             .service(login_sms_bind_phone)
+            .service(login_refresh)
+            .service(login_logout)
             .service(common_province_list)
             .service(common_banner_list)
             .service(common_base_info)
@@ -180,9 +261,12 @@ async fn main() -> std::io::Result<()> {
             .service(user_addr_detail)
             .service(user_addr_del)
             .service(user_coupon_list)
+            .service(user_coupon_expiring)
             .service(user_pocket_money)
             .service(user_pocket_withdraw_req)
             .service(user_pocket_pending_withdraw)
+            .service(user_pocket_recharge_options)
+            .service(user_pocket_recharge)
             .service(user_pocket_tran)
             .service(user_pocket_transfer)
             .service(user_pocket_transfer_list)
@@ -196,8 +280,28 @@ async fn main() -> std::io::Result<()> {
             .service(mall_order_list)
             .service(mall_order_detail)
             .service(mall_order_modify_status)
+            .service(mall_order_cancel)
+            .service(mall_order_cart_list)
+            .service(mall_order_cart_quantity)
+            .service(mall_order_cart_select)
+            .service(mall_order_cart_del)
+            .service(mall_order_cart_clear)
             .service(mall_coupon_receive)
+            .service(mall_coupon_redeem)
             .service(mall_coupon_list)
+            .service(mall_seckill_list)
+            .service(mall_seckill_buy)
+            .service(mall_group_buy_list)
+            .service(mall_group_buy_add)
+            .service(mall_group_buy_group)
+            .service(mall_group_buy_my)
+            .service(mall_review_add)
+            .service(mall_review_list)
+            .service(mall_after_sale_apply)
+            .service(mall_after_sale_waybill)
+            .service(mall_after_sale_cancel)
+            .service(mall_after_sale_list)
+            .service(mall_after_sale_detail)
             .service(mall_product_list)
             .service(mall_product_unit_list)
             .service(mall_product_user_publish)
@@ -228,7 +332,12 @@ async fn main() -> std::io::Result<()> {
             .service(sales_invite_user_bind)
             .service(sales_invite_user_del)
             .service(sales_list_sale)
-            .service(sales_list_user);
+            .service(sales_list_user)
+            .service(sales_commission)
+            .service(agent_apply)
+            .service(agent_dashboard)
+            .service(agent_order_list)
+            .service(agent_withdraw_req);
         if cfg!(feature = "doc") {
             // ** ********* 测试版功能 ********* **/
             app = app
@@ -239,7 +348,7 @@ async fn main() -> std::io::Result<()> {
         }
         app
     })
-    .bind(("127.0.0.1", port))?
+    .bind((cfg.server.host.as_str(), port))?
     .run()
     .await
 }
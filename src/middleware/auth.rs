@@ -1,9 +1,69 @@
-use crate::common::SUPER_SYSTEM_USER_ID;
-use crate::db::mysql_conn;
-use crate::utils::jwt::validate_token;
+use crate::common::config;
+use crate::control::token::token_ver;
+use crate::db::{db_block, my_exec_first, mysql_block};
+use crate::middleware::{PermissionMarker, has_permission, role_permission_table};
+use crate::utils::jwt::{Claims, validate_token};
+use crate::utils::utils::log_err;
 use actix_web::{Error, FromRequest, HttpRequest, dev::Payload, error};
-use mysql_quick::Queryable;
-use std::future::{Ready, ready};
+use futures_util::future::LocalBoxFuture;
+use mysql_quick::PooledConn;
+use std::marker::PhantomData;
+
+/// 解析请求头中的 token，未登录或 token 过期时返回 401
+fn bearer_claims(req: &HttpRequest) -> Result<Claims, Error> {
+    let auth = req.headers().get("Authorization");
+    if let Some(val) = auth {
+        if val == "Bearer" {
+            Err(error::ErrorUnauthorized("用户未登录"))
+        } else {
+            let token = val
+                .to_str()
+                .unwrap_or_default()
+                .split("Bearer ")
+                .collect::<Vec<&str>>()
+                .pop()
+                .unwrap_or_default();
+            match validate_token(token) {
+                Ok(data) => Ok(data.claims),
+                Err(_e) => Err(error::ErrorUnauthorized("登录过期，请重新登录")),
+            }
+        }
+    } else {
+        Err(error::ErrorUnauthorized("用户未登录"))
+    }
+}
+
+/// token 的版本号与用户当前的版本号是否一致，用户不存在时视为不一致
+fn ver_matches(current: Option<u32>, claims_ver: u32) -> bool {
+    current == Some(claims_ver)
+}
+
+/// 校验 token 的版本号。用户退出登录、权限或角色变更后，旧的 token 立即失效
+fn check_token_ver(claims: &Claims) -> Result<(), Error> {
+    if ver_matches(token_ver(claims.id)?, claims.ver) {
+        Ok(())
+    } else {
+        Err(error::ErrorUnauthorized("登录已失效，请重新登录"))
+    }
+}
+
+/// 解析并校验请求头中的 token，redis、数据库查询放到阻塞线程池中执行
+fn verified_claims(req: &HttpRequest) -> LocalBoxFuture<'static, Result<Claims, Error>> {
+    let claims = bearer_claims(req);
+    Box::pin(async move {
+        let claims = claims?;
+        db_block(move || check_token_ver(&claims).map(|_| claims)).await
+    })
+}
+
+/// 鉴权时的数据库查询，放到阻塞线程池中执行，避免阻塞 actix 的工作线程
+fn auth_db<F, R>(f: F) -> LocalBoxFuture<'static, Result<R, Error>>
+where
+    F: FnOnce(&mut PooledConn) -> Result<R, Error> + Send + 'static,
+    R: Send + 'static,
+{
+    Box::pin(mysql_block(f))
+}
 
 /// 判断用户是否登录的中间件，如果用户未登录，则会返回 401
 #[derive(Debug)]
@@ -12,32 +72,13 @@ pub struct AuthOptionUser {
 }
 impl FromRequest for AuthOptionUser {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            let auth = req.headers().get("Authorization");
-            if let Some(val) = auth {
-                if val == "Bearer" {
-                    Ok(Self { id: None })
-                } else {
-                    let token = val
-                        .to_str()
-                        .unwrap()
-                        .split("Bearer ")
-                        .collect::<Vec<&str>>()
-                        .pop()
-                        .unwrap();
-                    let result = validate_token(token);
-                    match result {
-                        Ok(data) => Ok(Self {
-                            id: Some(data.claims.id),
-                        }),
-                        Err(_e) => Ok(Self { id: None }),
-                    }
-                }
-            } else {
-                Ok(Self { id: None })
-            }
+        let claims = verified_claims(req);
+        Box::pin(async move {
+            Ok(Self {
+                id: claims.await.ok().map(|c| c.id),
+            })
         })
     }
 }
@@ -49,148 +90,93 @@ pub struct AuthUser {
 }
 impl FromRequest for AuthUser {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            let auth = req.headers().get("Authorization");
-            if let Some(val) = auth {
-                if val == "Bearer" {
-                    Err(error::ErrorUnauthorized("用户未登录"))
-                } else {
-                    let token = val
-                        .to_str()
-                        .unwrap()
-                        .split("Bearer ")
-                        .collect::<Vec<&str>>()
-                        .pop()
-                        .unwrap();
-                    let result = validate_token(token);
-                    match result {
-                        Ok(data) => Ok(Self { id: data.claims.id }),
-                        Err(_e) => Err(error::ErrorUnauthorized("登录过期，请重新登录")),
-                    }
-                }
-            } else {
-                Err(error::ErrorUnauthorized("用户未登录"))
-            }
+        let claims = verified_claims(req);
+        Box::pin(async move {
+            Ok(Self {
+                id: claims.await?.id,
+            })
+        })
+    }
+}
+
+/// 解析用户的角色编号，如 "1001,1002"。有非数字的内容时，视为数据错误
+fn parse_role_ids(role: &str) -> Result<Vec<u16>, Error> {
+    role.split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse::<u16>()
+                .map_err(|e| error::ErrorForbidden(log_err(&e, "用户角色数据错误")))
         })
+        .collect()
+}
+
+/// 查询用户的角色编号
+fn user_role_ids(conn: &mut PooledConn, uid: u64) -> Result<Vec<u16>, Error> {
+    let role: Option<String> =
+        my_exec_first(conn, "select role from usr_silent where id = ?", (uid,))?;
+    match role {
+        Some(r) => parse_role_ids(&r),
+        None => Ok(vec![]),
     }
 }
 
-/// 判断用户是否登录，及用户当前角色的中间件。不通过，则返回 401 403
+/// 判断用户是否登录，及用户的角色是否有接口声明的权限。不通过，则返回 401 403。
+///
+/// 如 `user: AuthRole<PermMallWriteOffDo>`
 #[allow(unused)]
-pub struct AuthRole {
+pub struct AuthRole<P: PermissionMarker> {
     pub id: u64,
     pub role: Vec<u16>,
+    _p: PhantomData<P>,
 }
-impl FromRequest for AuthRole {
+impl<P: PermissionMarker + 'static> FromRequest for AuthRole<P> {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            let auth = req.headers().get("Authorization");
-            let uri = req.path();
-            if let Some(val) = auth {
-                if val == "Bearer" {
-                    Err(error::ErrorUnauthorized("用户未登录"))
-                } else {
-                    let token = val
-                        .to_str()
-                        .unwrap()
-                        .split("Bearer ")
-                        .collect::<Vec<&str>>()
-                        .pop()
-                        .unwrap();
-                    let result = validate_token(token);
-                    match result {
-                        Ok(data) => {
-                            // 用户 登录成功， 进行角色api权限校验
-                            let uid = data.claims.id;
-                            match mysql_conn() {
-                                Ok(c) => {
-                                    let mut conn = c;
-                                    let user_role: Option<String> = conn
-                                        .query_first(
-                                            "select role from usr_silent where id = ".to_string()
-                                                + uid.to_string().as_str(),
-                                        )
-                                        .unwrap();
-                                    if let Some(r) = user_role {
-                                        if !r.is_empty() {
-                                            let user_role2 = r
-                                                .clone()
-                                                .split(",")
-                                                .map(|i| i.parse::<u16>().unwrap())
-                                                .collect::<Vec<u16>>();
-                                            let user_r = user_role2
-                                                .clone()
-                                                .iter()
-                                                .map(|x| x.to_string())
-                                                .collect::<Vec<String>>()
-                                                .join(",");
-                                            struct ApiPaths {
-                                                api_paths: String,
-                                            }
-                                            // 根据用户 role,查找role的api权限
-                                            let role_api = conn.query_map(
-                                                "select api_paths from sys_role where is_del = 0 and identifier in (".to_string()
-                                                + user_r.as_str() + ")",
-                                                |api_paths| {
-                                                    ApiPaths {api_paths}
-                                                }
-                                            ).unwrap();
-                                            if role_api.len() == 0 {
-                                                Err(error::ErrorForbidden("暂无访问权限"))
-                                            } else {
-                                                let mut is_pass: bool = false;
-                                                let user_all = role_api
-                                                    .iter()
-                                                    .map(|x| x.api_paths.as_str())
-                                                    .collect::<Vec<&str>>()
-                                                    .join(",")
-                                                    .split(",")
-                                                    .filter(|x| !x.is_empty())
-                                                    .map(|x| x.to_string())
-                                                    .collect::<Vec<String>>();
-
-                                                for p in user_all {
-                                                    if uri.contains(p.as_str()) {
-                                                        is_pass = true;
-                                                        break;
-                                                    }
-                                                }
-
-                                                if !is_pass {
-                                                    Err(error::ErrorForbidden("暂无访问权限"))
-                                                } else {
-                                                    Ok(Self {
-                                                        id: uid,
-                                                        role: user_role2,
-                                                    })
-                                                }
-                                            }
-                                        } else {
-                                            Err(error::ErrorForbidden("暂无访问权限"))
-                                        }
-                                    } else {
-                                        Err(error::ErrorForbidden("暂无访问权限"))
-                                    }
-                                }
-                                Err(_) => {
-                                    Err(error::ErrorInternalServerError("Auth 数据库连接错误"))
-                                }
-                            }
-                        }
-                        Err(_e) => Err(error::ErrorUnauthorized("登录过期，请重新登录")),
-                    }
-                }
+        let claims = match bearer_claims(req) {
+            Ok(c) => c,
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let uid = claims.id;
+        // 用户 登录成功， 进行角色权限校验
+        let key = P::PERMISSION.key;
+        let role = auth_db(move |conn| {
+            check_token_ver(&claims)?;
+            let role = user_role_ids(conn, uid)?;
+            if has_permission(&role_permission_table()?, &role, key) {
+                Ok(role)
             } else {
-                Err(error::ErrorUnauthorized("用户未登录"))
+                Err(error::ErrorForbidden("暂无访问权限"))
             }
+        });
+        Box::pin(async move {
+            Ok(Self {
+                id: uid,
+                role: role.await?,
+                _p: PhantomData,
+            })
         })
     }
 }
 
+/// 查询用户的管理后台权限，没有时为 None
+fn manage_authority(conn: &mut PooledConn, uid: u64) -> Result<Option<Vec<String>>, Error> {
+    let authority: Option<String> = my_exec_first(
+        conn,
+        "select authority from usr_authority where uid = ?",
+        (uid,),
+    )?;
+    Ok(authority.map(|a| {
+        a.split(',')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect()
+    }))
+}
+
 /// 管理后台：判断用户是否登录，及用户是否有 普通 管理员 的功能。
 #[allow(unused)]
 pub struct AuthMana {
@@ -199,59 +185,18 @@ pub struct AuthMana {
 }
 impl FromRequest for AuthMana {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            let auth = req.headers().get("Authorization");
-            if let Some(val) = auth {
-                if val == "Bearer" {
-                    Err(error::ErrorUnauthorized("用户未登录"))
-                } else {
-                    let token = val
-                        .to_str()
-                        .unwrap()
-                        .split("Bearer ")
-                        .collect::<Vec<&str>>()
-                        .pop()
-                        .unwrap();
-                    let result = validate_token(token);
-                    match result {
-                        Ok(data) => {
-                            let uid = data.claims.id;
-                            match mysql_conn() {
-                                Ok(c) => {
-                                    let mut conn = c;
-                                    let user_manage_auth: Option<String> = conn
-                                        .query_first(
-                                            "
-                                        select authority from usr_authority where uid = "
-                                                .to_string()
-                                                + uid.to_string().as_str(),
-                                        )
-                                        .unwrap();
-                                    if let Some(a) = user_manage_auth {
-                                        let a_vec = a
-                                            .split(",")
-                                            .map(|i| "\"".to_string() + i + "\"")
-                                            .collect::<Vec<_>>();
-                                        Ok(Self {
-                                            id: uid,
-                                            authority: a_vec,
-                                        })
-                                    } else {
-                                        Err(error::ErrorForbidden("没有管理员权限"))
-                                    }
-                                }
-                                Err(_) => {
-                                    Err(error::ErrorInternalServerError("Auth 数据库连接错误"))
-                                }
-                            }
-                        }
-                        Err(_e) => Err(error::ErrorUnauthorized("登录过期，请重新登录")),
-                    }
-                }
-            } else {
-                Err(error::ErrorUnauthorized("用户未登录"))
+        let claims = match bearer_claims(req) {
+            Ok(c) => c,
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let uid = claims.id;
+        auth_db(move |conn| {
+            check_token_ver(&claims)?;
+            match manage_authority(conn, uid)? {
+                Some(authority) => Ok(Self { id: uid, authority }),
+                None => Err(error::ErrorForbidden("没有管理员权限")),
             }
         })
     }
@@ -265,65 +210,101 @@ pub struct AuthSuperMana {
 }
 impl FromRequest for AuthSuperMana {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            let auth = req.headers().get("Authorization");
-            if let Some(val) = auth {
-                if val == "Bearer" {
-                    Err(error::ErrorUnauthorized("用户未登录"))
-                } else {
-                    let token = val
-                        .to_str()
-                        .unwrap()
-                        .split("Bearer ")
-                        .collect::<Vec<&str>>()
-                        .pop()
-                        .unwrap();
-                    let result = validate_token(token);
-                    match result {
-                        Ok(data) => {
-                            let uid = data.claims.id;
-                            match mysql_conn() {
-                                Ok(c) => {
-                                    let mut conn = c;
-                                    let user_manage_auth: Option<String> = conn
-                                        .query_first(
-                                            "
-                                        select authority from usr_authority where uid = "
-                                                .to_string()
-                                                + uid.to_string().as_str(),
-                                        )
-                                        .unwrap();
-                                    if let Some(a) = user_manage_auth {
-                                        let s_uid = SUPER_SYSTEM_USER_ID;
-                                        if s_uid == uid {
-                                            let a_vec = a
-                                                .split(",")
-                                                .map(|i| "\"".to_string() + i + "\"")
-                                                .collect::<Vec<_>>();
-                                            Ok(Self {
-                                                id: uid,
-                                                authority: a_vec,
-                                            })
-                                        } else {
-                                            Err(error::ErrorForbidden("没有超级管理员权限"))
-                                        }
-                                    } else {
-                                        Err(error::ErrorForbidden("没有管理员权限"))
-                                    }
-                                }
-                                Err(_) => {
-                                    Err(error::ErrorInternalServerError("Auth 数据库连接错误"))
-                                }
-                            }
-                        }
-                        Err(_e) => Err(error::ErrorUnauthorized("登录过期，请重新登录")),
+        let claims = match bearer_claims(req) {
+            Ok(c) => c,
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let uid = claims.id;
+        auth_db(move |conn| {
+            check_token_ver(&claims)?;
+            match manage_authority(conn, uid)? {
+                Some(authority) => {
+                    if config().server.super_system_user_id == uid {
+                        Ok(Self { id: uid, authority })
+                    } else {
+                        Err(error::ErrorForbidden("没有超级管理员权限"))
                     }
                 }
-            } else {
-                Err(error::ErrorUnauthorized("用户未登录"))
+                None => Err(error::ErrorForbidden("没有管理员权限")),
             }
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::in_placeholders;
+
+    #[test]
+    fn test_parse_role_ids() {
+        assert_eq!(parse_role_ids("1001,1002").unwrap(), vec![1001, 1002]);
+        assert_eq!(parse_role_ids("1001, ,1002,").unwrap(), vec![1001, 1002]);
+        assert!(parse_role_ids("").unwrap().is_empty());
+        // 被篡改的角色数据，不能拼进 sql，直接拒绝
+        for payload in [
+            "1001) or (1=1",
+            "1001,1002) union select api_paths from sys_role --",
+            "0 or 1=1",
+            "1001;drop table sys_role",
+            "99999",
+        ] {
+            assert!(parse_role_ids(payload).is_err(), "{}", payload);
+        }
+        assert_eq!(in_placeholders(3), "?,?,?");
+        assert_eq!(in_placeholders(1), "?");
+    }
+
+    #[test]
+    #[ignore = "需要 mysql，cargo test -- --ignored"]
+    fn test_user_role_ids_bind() {
+        use crate::db::{my_exec_drop, mysql_conn};
+        use mysql::prelude::Queryable;
+        use std::collections::HashMap;
+
+        let mut conn = mysql_conn().unwrap();
+        // 临时表只在当前连接可见，查询时会替代同名的 usr_silent，不影响数据库中的表
+        conn.query_drop(
+            "create temporary table usr_silent (id bigint primary key, role varchar(255))",
+        )
+        .unwrap();
+        for (id, role) in [
+            (1, "1001,1002"),
+            (2, "1001) or (1=1"),
+            (3, "' or ''='"),
+            (
+                4,
+                r#"1001" union select identifier from sys_role where "1"="1"#,
+            ),
+        ] {
+            my_exec_drop(
+                &mut conn,
+                "insert into usr_silent values (?, ?)",
+                (id, role),
+            )
+            .unwrap();
+        }
+        let role = user_role_ids(&mut conn, 1).unwrap();
+        assert_eq!(role, vec![1001, 1002]);
+        let table = HashMap::from([(1002, vec!["mall:write_off".to_string()])]);
+        assert!(has_permission(&table, &role, "mall:write_off"));
+        // 被篡改的角色数据原样读出，解析时拒绝，不会进入后续的查询
+        for id in [2, 3, 4] {
+            assert!(user_role_ids(&mut conn, id).is_err(), "{}", id);
+        }
+        // 不存在的用户没有角色
+        assert!(user_role_ids(&mut conn, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ver_matches() {
+        assert!(ver_matches(Some(0), 0));
+        assert!(ver_matches(Some(3), 3));
+        // 已退出登录或权限变更过的旧 token
+        assert!(!ver_matches(Some(3), 2));
+        // 用户不存在
+        assert!(!ver_matches(None, 0));
+    }
+}
@@ -8,7 +8,7 @@ use actix_web::dev::{ServiceRequest, ServiceResponse};
 use tracing::Span;
 use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
 
-use crate::common::LOG_LEVEL_STATUS;
+use crate::common::config;
 use crate::middleware::get_client_ip;
 use crate::utils::jwt::uid_token;
 use crate::utils::time::{NowTimeType, get_now_time};
@@ -51,7 +51,7 @@ impl RootSpanBuilder for CustomRootSpanBuilder {
                     ip_info = format!("IP:{}", client_ip.ip());
                 }
 
-                if status.as_u16() < LOG_LEVEL_STATUS {
+                if status.as_u16() < config().server.log_level_status {
                     return DefaultRootSpanBuilder::on_request_end(span, outcome);
                 }
                 let method = res.request().method();
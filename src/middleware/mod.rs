@@ -7,5 +7,8 @@ pub use logs::*;
 mod ip;
 pub use ip::*;
 
-// mod module;
-// pub use module::*;
+mod module;
+pub use module::*;
+
+mod permission;
+pub use permission::*;
@@ -1,9 +1,13 @@
-use crate::db::my_run_vec;
-use crate::db::mysql_conn;
-use actix_web::{dev::Payload, error, Error, FromRequest, HttpRequest};
-use mysql_quick::{myfind, PooledConn};
+use crate::common::PROJECT_NAME;
+use crate::db::{my_run_vec, mysql_conn, redis_conn};
+use crate::utils::utils::{log_aes_err, log_err};
+use actix_web::{Error, FromRequest, HttpRequest, dev::Payload, error, web};
+use futures_util::future::LocalBoxFuture;
+use mysql_quick::myfind;
+use redis::Commands;
 use serde::Deserialize;
-use std::future::{ready, Ready};
+use std::collections::HashMap;
+use std::marker::PhantomData;
 
 // 功能块 code
 const COUPON: &str = "COUPON";
@@ -18,8 +22,11 @@ const ARTICLE: &str = "ARTICLE";
 // 不是任何功能块
 const NOT_MODULE: &str = "NOT_MODULE";
 
+/// 功能模块开关表的缓存时间，修改开关时会主动清除
+const MODULE_SWITCH_CACHE_SEC: u64 = 600;
+
 /// 判断当前功能块，是否开启了
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Module {
     Coupon,
     Distribution,
@@ -30,21 +37,35 @@ pub enum Module {
     Seckill,
     GroupBuy,
     Article,
-    NotModule,
+    Unknown,
 }
 impl Module {
-    fn to_string(&self) -> String {
+    fn code(&self) -> &'static str {
         match &self {
-            Module::Coupon => COUPON.to_string(),
-            Module::Distribution => DISTRIBUTION.to_string(),
-            Module::PocketMoney => POCKET_MONEY.to_string(),
-            Module::Agent => AGENT.to_string(),
-            Module::Join => JOIN.to_string(),
-            Module::ShoppingCart => SHOPPING_CART.to_string(),
-            Module::Seckill => SECKILL.to_string(),
-            Module::GroupBuy => GROUP_BUY.to_string(),
-            Module::Article => ARTICLE.to_string(),
-            Module::NotModule => NOT_MODULE.to_string(),
+            Module::Coupon => COUPON,
+            Module::Distribution => DISTRIBUTION,
+            Module::PocketMoney => POCKET_MONEY,
+            Module::Agent => AGENT,
+            Module::Join => JOIN,
+            Module::ShoppingCart => SHOPPING_CART,
+            Module::Seckill => SECKILL,
+            Module::GroupBuy => GROUP_BUY,
+            Module::Article => ARTICLE,
+            Module::Unknown => NOT_MODULE,
+        }
+    }
+    fn name(&self) -> &'static str {
+        match &self {
+            Module::Coupon => "优惠券",
+            Module::Distribution => "分销",
+            Module::PocketMoney => "零钱",
+            Module::Agent => "代理",
+            Module::Join => "加盟",
+            Module::ShoppingCart => "购物车",
+            Module::Seckill => "秒杀",
+            Module::GroupBuy => "拼团",
+            Module::Article => "文章",
+            Module::Unknown => "未知",
         }
     }
 }
@@ -60,220 +81,179 @@ impl From<String> for Module {
             SECKILL => Self::Seckill,
             GROUP_BUY => Self::GroupBuy,
             ARTICLE => Self::Article,
-            _ => Self::NotModule,
+            _ => Self::Unknown,
         }
     }
 }
-fn get_module_by_code(conn: &mut PooledConn, md: Module) -> Result<Module, Error> {
+
+fn module_switch_key() -> String {
+    format!("{}:module_switch", PROJECT_NAME)
+}
+
+/// 所有功能模块的开关，code -> 是否开启。
+///
+/// 优先读 redis 缓存，没有时查数据库并写入缓存；redis 不可用时直接查数据库
+pub fn module_switch_table() -> Result<HashMap<String, bool>, Error> {
     #[derive(Deserialize)]
-    struct ModuleGet {
+    struct ModuleSwitchGet {
         code: String,
+        is_on: u8,
+    }
+    let key = module_switch_key();
+    let mut redis_con = redis_conn().ok();
+    if let Some(r) = redis_con.as_mut() {
+        let cached: Option<String> = r.get(&key).unwrap_or(None);
+        if let Some(c) = cached
+            && let Ok(table) = serde_json::from_str::<HashMap<String, bool>>(&c)
+        {
+            return Ok(table);
+        }
     }
-    let code = md.to_string();
-    let m: Vec<ModuleGet> = my_run_vec(
-        conn,
+    let mut conn = mysql_conn()?;
+    let list: Vec<ModuleSwitchGet> = my_run_vec(
+        &mut conn,
         myfind!("sys_module_switch", {
-            p0: ["is_on", "=", 1],
-            p1: ["is_del", "=", 0],
-            p2: ["code", "=", &code],
-            r: "p0 && p1 && p2",
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            select: "code,is_on",
         }),
     )?;
-    if m.len() > 0 {
-        let module: Module = m[0].code.clone().into();
-        if module == Module::NotModule {
-            return Err(error::ErrorForbidden(format!(
-                "没有开通[{}]功能模块",
-                &code
-            )));
+    let table: HashMap<String, bool> = list.into_iter().map(|x| (x.code, x.is_on == 1)).collect();
+    if let Some(r) = redis_con.as_mut()
+        && let Ok(v) = serde_json::to_string(&table)
+    {
+        let res: redis::RedisResult<()> = r.set_ex(&key, v, MODULE_SWITCH_CACHE_SEC);
+        if let Err(e) = res {
+            log_err(&e, "功能模块开关缓存");
         }
-        Ok(module)
+    }
+    Ok(table)
+}
+
+/// 清除功能模块开关的缓存，修改开关后调用
+pub fn clear_module_switch_cache() -> Result<(), Error> {
+    let mut redis_con = redis_conn()?;
+    let _: () = redis_con
+        .del(module_switch_key())
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "功能模块开关缓存")))?;
+    Ok(())
+}
+
+/// 功能模块未开启时，统一返回的错误
+fn module_disabled(md: Module) -> Error {
+    error::ErrorForbidden(format!("[{}]功能未开启", md.name()))
+}
+
+fn module_is_on(table: &HashMap<String, bool>, md: Module) -> bool {
+    md != Module::Unknown && table.get(md.code()).copied().unwrap_or(false)
+}
+
+/// 判断功能模块是否开启，未开启时返回 403。
+///
+/// 用于同一个接口里，只有部分功能需要模块开启的情况，如 零钱支付、使用优惠券
+pub fn check_module(md: Module) -> Result<(), Error> {
+    if module_is_on(&module_switch_table()?, md) {
+        Ok(())
     } else {
-        Err(error::ErrorForbidden(format!(
-            "没有开通[{}]功能模块",
-            &code
-        )))
+        Err(module_disabled(md))
     }
 }
 
-/// 优惠券功能，是否开启的鉴权。后面类似
-#[allow(unused)]
+/// 功能模块的标记类型，配合 `RequireModule` 使用
+pub trait ModuleMarker {
+    const MODULE: Module;
+}
+/// 优惠券
 pub struct ModuleCoupon;
-impl FromRequest for ModuleCoupon {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleCoupon),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleCoupon {
+    const MODULE: Module = Module::Coupon;
 }
-#[allow(unused)]
+/// 分销
 pub struct ModuleDistribution;
-impl FromRequest for ModuleDistribution {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleDistribution),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleDistribution {
+    const MODULE: Module = Module::Distribution;
 }
-#[allow(unused)]
+/// 零钱
 pub struct ModulePocketMoney;
-impl FromRequest for ModulePocketMoney {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModulePocketMoney),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModulePocketMoney {
+    const MODULE: Module = Module::PocketMoney;
 }
-#[allow(unused)]
+/// 代理
 pub struct ModuleAgent;
-impl FromRequest for ModuleAgent {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleAgent),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleAgent {
+    const MODULE: Module = Module::Agent;
 }
+/// 加盟
 #[allow(unused)]
 pub struct ModuleJoin;
-impl FromRequest for ModuleJoin {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleJoin),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleJoin {
+    const MODULE: Module = Module::Join;
 }
-#[allow(unused)]
+/// 购物车
 pub struct ModuleShoppingCart;
-impl FromRequest for ModuleShoppingCart {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleShoppingCart),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleShoppingCart {
+    const MODULE: Module = Module::ShoppingCart;
 }
-#[allow(unused)]
+/// 秒杀
 pub struct ModuleSeckill;
-impl FromRequest for ModuleSeckill {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleSeckill),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleSeckill {
+    const MODULE: Module = Module::Seckill;
 }
-#[allow(unused)]
+/// 拼团
 pub struct ModuleGroupBuy;
-impl FromRequest for ModuleGroupBuy {
-    type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
-    fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleGroupBuy),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
-        })
-    }
+impl ModuleMarker for ModuleGroupBuy {
+    const MODULE: Module = Module::GroupBuy;
 }
-#[allow(unused)]
+/// 文章
 pub struct ModuleArticle;
-impl FromRequest for ModuleArticle {
+impl ModuleMarker for ModuleArticle {
+    const MODULE: Module = Module::Article;
+}
+
+/// 功能模块是否开启的鉴权，未开启时返回 403。如 `_m: RequireModule<ModuleCoupon>`
+pub struct RequireModule<M: ModuleMarker>(PhantomData<M>);
+impl<M: ModuleMarker + 'static> FromRequest for RequireModule<M> {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(_req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready({
-            match mysql_conn() {
-                Ok(c) => {
-                    let mut conn = c;
-                    match get_module_by_code(&mut conn, Module::Coupon) {
-                        Ok(_) => Ok(ModuleArticle),
-                        Err(e) => Err(e),
-                    }
-                }
-                Err(_) => Err(error::ErrorInternalServerError("Module 数据库连接错误")),
-            }
+        Box::pin(async move {
+            // redis、数据库查询放到阻塞线程池中执行。actix 的 Error 不能跨线程传递，先转为 (状态码, 信息)
+            web::block(|| {
+                check_module(M::MODULE)
+                    .map_err(|e| (e.as_response_error().status_code(), e.to_string()))
+            })
+            .await
+            .map_err(|e| error::ErrorInternalServerError(log_aes_err(&e, "功能模块鉴权出错")))?
+            .map_err(|(status, msg)| Error::from(error::InternalError::new(msg, status)))?;
+            Ok(RequireModule(PhantomData))
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_module_is_on() {
+        let table = HashMap::from([
+            (COUPON.to_string(), true),
+            (POCKET_MONEY.to_string(), false),
+            (NOT_MODULE.to_string(), true),
+        ]);
+        assert!(module_is_on(&table, Module::Coupon));
+        assert!(!module_is_on(&table, Module::PocketMoney));
+        // 开关表里没有的，视为未开启
+        assert!(!module_is_on(&table, Module::Article));
+        assert!(!module_is_on(&table, Module::Unknown));
+        assert_eq!(
+            Module::from(SHOPPING_CART.to_string()),
+            Module::ShoppingCart
+        );
+        assert_eq!(Module::from("XX".to_string()), Module::Unknown);
+        assert_eq!(
+            module_disabled(Module::PocketMoney).to_string(),
+            "[零钱]功能未开启"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::common::PROJECT_NAME;
+use crate::db::{my_exec_vec, mysql_conn, redis_conn};
+use crate::utils::utils::log_err;
+use actix_web::{Error, error};
+use redis::Commands;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// 角色权限表的缓存时间，修改角色时会主动清除
+const ROLE_PERMISSION_CACHE_SEC: u64 = 600;
+
+/// 接口权限，每个需要角色鉴权的接口声明一个
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permission {
+    /// 权限唯一标识，保存在 sys_role.permissions 中
+    pub key: &'static str,
+    /// 权限名
+    pub name: &'static str,
+}
+
+/// 接口权限的标记类型，配合 `AuthRole` 使用，如 `user: AuthRole<PermMallWriteOffDo>`
+pub trait PermissionMarker {
+    const PERMISSION: Permission;
+}
+
+/// 声明权限的标记类型，并把所有权限登记到 `PERMISSIONS` 中
+macro_rules! permissions {
+    ($($(#[$doc:meta])* $marker:ident => ($key:expr, $name:expr);)*) => {
+        $(
+            $(#[$doc])*
+            pub struct $marker;
+            impl PermissionMarker for $marker {
+                const PERMISSION: Permission = Permission { key: $key, name: $name };
+            }
+        )*
+        /// 所有接口权限
+        const PERMISSIONS: &[Permission] = &[$(<$marker as PermissionMarker>::PERMISSION),*];
+    };
+}
+
+permissions! {
+    /// 分销：邀请销售码
+    PermSalesInviteSaleCode => ("sales:invite_sale_code", "邀请销售码");
+    /// 分销：删除销售
+    PermSalesInviteSaleDel => ("sales:invite_sale_del", "删除销售");
+    /// 分销：邀请客户码
+    PermSalesInviteUserCode => ("sales:invite_user_code", "邀请客户码");
+    /// 分销：删除客户
+    PermSalesInviteUserDel => ("sales:invite_user_del", "删除客户");
+    /// 分销：销售列表
+    PermSalesListSale => ("sales:list_sale", "销售列表");
+    /// 分销：客户列表
+    PermSalesListUser => ("sales:list_user", "客户列表");
+    /// 分销：我的佣金
+    PermSalesCommission => ("sales:commission", "我的佣金");
+    /// 代理：代理看板、区域订单
+    PermAgentDashboard => ("agent:dashboard", "代理看板");
+    /// 代理：代理分成提现
+    PermAgentWithdraw => ("agent:withdraw", "代理提现");
+    /// 核销：核销员核销
+    PermMallWriteOffDo => ("mall:write_off_do", "核销员核销");
+}
+
+/// 权限注册表，key -> 权限。启动时创建，有重复的 key 时报错
+fn build_registry(list: &[Permission]) -> Result<HashMap<&'static str, Permission>, String> {
+    let mut registry = HashMap::new();
+    for p in list {
+        if registry.insert(p.key, *p).is_some() {
+            return Err(format!("权限 {} 重复声明", p.key));
+        }
+    }
+    Ok(registry)
+}
+
+static PERMISSION_REGISTRY: OnceLock<HashMap<&'static str, Permission>> = OnceLock::new();
+
+fn permission_registry() -> &'static HashMap<&'static str, Permission> {
+    PERMISSION_REGISTRY.get_or_init(|| build_registry(PERMISSIONS).expect("接口权限声明有误"))
+}
+
+/// 所有已声明的接口权限，按 key 排序
+pub fn all_permissions() -> Vec<Permission> {
+    let mut list: Vec<Permission> = permission_registry().values().copied().collect();
+    list.sort_by_key(|p| p.key);
+    list
+}
+
+/// 不存在的权限 key
+pub fn unknown_permissions(keys: &[String]) -> Vec<String> {
+    let registry = permission_registry();
+    keys.iter()
+        .filter(|k| !registry.contains_key(k.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// 解析角色的权限，如 "sales:list_sale,mall:write_off_do"
+pub fn parse_permissions(permissions: &str) -> Vec<String> {
+    permissions
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+fn role_permission_key() -> String {
+    format!("{}:role_permission", PROJECT_NAME)
+}
+
+/// 所有角色的权限，角色编号 -> 权限 key。
+///
+/// 优先读 redis 缓存，没有时查数据库并写入缓存；redis 不可用时直接查数据库
+pub fn role_permission_table() -> Result<HashMap<u16, Vec<String>>, Error> {
+    let key = role_permission_key();
+    let mut redis_con = redis_conn().ok();
+    if let Some(r) = redis_con.as_mut() {
+        let cached: Option<String> = r.get(&key).unwrap_or(None);
+        if let Some(c) = cached
+            && let Ok(table) = serde_json::from_str::<HashMap<u16, Vec<String>>>(&c)
+        {
+            return Ok(table);
+        }
+    }
+    let mut conn = mysql_conn()?;
+    let list: Vec<(u16, Option<String>)> = my_exec_vec(
+        &mut conn,
+        "select identifier, permissions from sys_role where is_del = 0",
+        (),
+    )?;
+    let table: HashMap<u16, Vec<String>> = list
+        .into_iter()
+        .map(|(identifier, p)| (identifier, parse_permissions(&p.unwrap_or_default())))
+        .collect();
+    if let Some(r) = redis_con.as_mut()
+        && let Ok(v) = serde_json::to_string(&table)
+    {
+        let res: redis::RedisResult<()> = r.set_ex(&key, v, ROLE_PERMISSION_CACHE_SEC);
+        if let Err(e) = res {
+            log_err(&e, "角色权限缓存");
+        }
+    }
+    Ok(table)
+}
+
+/// 清除角色权限的缓存，新增、修改、删除角色后调用
+pub fn clear_role_permission_cache() -> Result<(), Error> {
+    let mut redis_con = redis_conn()?;
+    let _: () = redis_con
+        .del(role_permission_key())
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "角色权限缓存")))?;
+    Ok(())
+}
+
+/// 用户的角色中，是否有一个拥有该权限
+pub fn has_permission(table: &HashMap<u16, Vec<String>>, roles: &[u16], key: &str) -> bool {
+    roles
+        .iter()
+        .filter_map(|r| table.get(r))
+        .any(|p| p.iter().any(|x| x == key))
+}
+
+/// 启动时检查角色里是否有未声明的权限，如接口删除后残留的 key，只打印提示
+pub fn check_role_permissions() -> Result<(), Error> {
+    let registry = permission_registry();
+    let table = role_permission_table()?;
+    let unknown: HashSet<&String> = table
+        .values()
+        .flatten()
+        .filter(|k| !registry.contains_key(k.as_str()))
+        .collect();
+    for k in unknown {
+        eprintln!("sys_role 中有未声明的权限: {k}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permission_registry() {
+        assert_eq!(permission_registry().len(), PERMISSIONS.len());
+        assert_eq!(
+            PermMallWriteOffDo::PERMISSION.key,
+            permission_registry()["mall:write_off_do"].key
+        );
+        let dup = [PermSalesListSale::PERMISSION, PermSalesListSale::PERMISSION];
+        assert!(build_registry(&dup).is_err());
+        assert_eq!(
+            unknown_permissions(&["sales:list_sale".to_string(), "/sales".to_string()]),
+            vec!["/sales".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_has_permission() {
+        let table = HashMap::from([
+            (
+                1000,
+                parse_permissions("sales:list_sale, sales:invite_sale_code"),
+            ),
+            (3000, parse_permissions("mall:write_off_do")),
+        ]);
+        assert!(has_permission(&table, &[1000], "sales:list_sale"));
+        assert!(has_permission(&table, &[1001, 3000], "mall:write_off_do"));
+        // 只按完整的 key 匹配，不再按路径包含
+        assert!(!has_permission(&table, &[1000], "sales:list"));
+        assert!(!has_permission(&table, &[1000], "mall:write_off_do"));
+        assert!(!has_permission(&table, &[], "sales:list_sale"));
+    }
+}
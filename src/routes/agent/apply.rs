@@ -0,0 +1,156 @@
+use actix_web::{Responder, Result, post, web};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, TxOpts, myfind, myset, myupdate};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::types::{NormalStatus, OssBucket, Role};
+use crate::db::{my_exec_tran_drop, my_run_tran_drop, my_run_tran_vec, mysql_conn};
+use crate::middleware::{AuthUser, ModuleAgent, RequireModule};
+use crate::routes::Res;
+use crate::routes::utils_set::agent_set::agent_area_taken;
+use crate::utils::files::get_path_from_urls;
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AgentApply {
+    /// 代理的省/直辖市
+    province: String,
+    province_id: u32,
+    /// 代理的市/直辖市的区，为空时代理整个省/直辖市
+    city: Option<String>,
+    city_id: Option<u32>,
+    /// 申请说明
+    content: Option<String>,
+    /// 资质图片
+    imgs: Option<Vec<String>>,
+}
+/// 【代理】申请成为区域代理，提交后由后台在用户角色认证中审核
+#[utoipa::path(
+    request_body = AgentApply,
+    responses((status = 200, description = "【请求：AgentApply】【返回：String】", body = String))
+)]
+#[post("/agent/apply")]
+pub async fn agent_apply(
+    user: AuthUser,
+    _m: RequireModule<ModuleAgent>,
+    params: web::Json<AgentApply>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    let province = params.province.trim();
+    if province.is_empty() {
+        return Ok(web::Json(Res::fail("请选择代理的区域")));
+    }
+    let city = params
+        .city
+        .as_deref()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty());
+    let area_name = format!("{}{}", province, city.unwrap_or_default());
+    let role = (Role::Agent as u16).to_string();
+    let imgs = params
+        .imgs
+        .as_ref()
+        .map(|im| get_path_from_urls(im, &OssBucket::EobFiles).join(","));
+
+    let mut conn = mysql_conn()?;
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+
+    // 代理的认证，审核不通过的可以重新申请
+    #[derive(Deserialize)]
+    struct CredentialGet {
+        id: u32,
+        status: u8,
+    }
+    let credential: Vec<CredentialGet> = match my_run_tran_vec(
+        &mut tran,
+        myfind!("usr_credential", {
+            p0: ["uid", "=", uid],
+            p1: ["role", "=", &role],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "id,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    if let Some(c) = credential.first() {
+        let msg = if c.status == NormalStatus::UnderReview as u8 {
+            Some("你已申请过了，请等待审核")
+        } else if c.status == NormalStatus::Online as u8 {
+            Some("你已是代理，增加代理区域请联系客服")
+        } else if c.status == NormalStatus::OffShelf as u8 {
+            Some("你的代理已下线，请联系客服")
+        } else {
+            None
+        };
+        if let Some(msg) = msg {
+            tran.rollback().unwrap();
+            return Ok(web::Json(Res::fail(msg)));
+        }
+    }
+
+    match agent_area_taken(&mut tran, province, city, uid) {
+        Ok(false) => (),
+        Ok(true) => {
+            tran.rollback().unwrap();
+            return Ok(web::Json(Res::fail("该区域已有代理")));
+        }
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    }
+
+    // 之前审核不通过的区域不再保留
+    if let Err(e) = my_exec_tran_drop(
+        &mut tran,
+        "update agt_agent_area set is_del = 1 where uid = ? and status = ? and is_del = 0",
+        (uid, NormalStatus::NotPass as u8),
+    ) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    if let Err(e) = my_run_tran_drop(
+        &mut tran,
+        myset!("agt_agent_area", {
+            "uid": uid,
+            "province": province,
+            "province_id": params.province_id,
+            "city": city,
+            "city_id": if city.is_some() { params.city_id } else { None },
+            "status": NormalStatus::UnderReview as u8,
+        }),
+    ) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+
+    let title = format!("申请{}代理", area_name);
+    let sql = match credential.first() {
+        Some(c) => myupdate!("usr_credential", c.id, {
+            "title": &title,
+            "content": &params.content,
+            "imgs": &imgs,
+            "reason": "null",
+            "status": NormalStatus::UnderReview as u8,
+        }),
+        None => myset!("usr_credential", {
+            "uid": uid,
+            "title": &title,
+            "content": &params.content,
+            "imgs": &imgs,
+            "role": &role,
+            "status": NormalStatus::UnderReview as u8,
+        }),
+    };
+    if let Err(e) = my_run_tran_drop(&mut tran, sql) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    tran.commit().unwrap();
+
+    Ok(web::Json(Res::success("")))
+}
@@ -0,0 +1,175 @@
+use actix_web::{Responder, Result, error, get, web};
+use mysql_quick::myfind;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::Role;
+use crate::db::{my_exec_first, my_run_vec, mysql_conn};
+use crate::middleware::{AuthRole, ModuleAgent, PermAgentDashboard, RequireModule};
+use crate::routes::Res;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct AgentAreaItem {
+    id: u32,
+    /// 代理的省/直辖市
+    province: String,
+    /// 代理的市/直辖市的区，为空时代理整个省/直辖市
+    city: Option<String>,
+    /// 2正常，1审核中，0审核不通过，3为下线
+    status: u8,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct AgentDashboard {
+    /// 代理的区域
+    areas: Vec<AgentAreaItem>,
+    /// 累计分成（已减去退款扣回的）
+    total_amount: Money,
+    /// 累计提现
+    withdraw_amount: Money,
+    /// 可提现
+    have_amount: Money,
+    /// 冻结中，订单的商品都完成并过了冻结期后转为可提现
+    freeze_amount: Money,
+    /// 区域内分成的订单数
+    order_count: u64,
+    /// 区域内分成的订单商品实付合计
+    order_amount: Money,
+    /// 本月分成的订单数
+    month_order_count: u64,
+    /// 本月分成的订单商品实付合计
+    month_order_amount: Money,
+    /// 本月分成（已减去退款扣回的）
+    month_amount: Money,
+}
+/// 【代理】代理看板，代理的区域、分成金额及区域内订单的统计
+#[utoipa::path(
+    responses((status = 200, description = "【返回：AgentDashboard】", body = AgentDashboard))
+)]
+#[get("/agent/dashboard")]
+pub async fn agent_dashboard(
+    user: AuthRole<PermAgentDashboard>,
+    _m: RequireModule<ModuleAgent>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if !user.role.contains(&(Role::Agent as u16)) {
+        return Err(error::ErrorUnauthorized("你不是代理"));
+    }
+    let mut conn = mysql_conn()?;
+
+    let areas: Vec<AgentAreaItem> = my_run_vec(
+        &mut conn,
+        myfind!("agt_agent_area", {
+            p0: ["uid", "=", uid],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id,province,city,status",
+        }),
+    )?;
+
+    let amount: Option<(String, String, String, String)> = my_exec_first(
+        &mut conn,
+        "select total_amount, withdraw_amount, have_amount, freeze_amount from agt_amount where uid = ? and is_del = 0",
+        (uid,),
+    )?;
+    let parse = |x: Option<String>| x.and_then(|s| s.parse::<Money>().ok()).unwrap_or_default();
+    let (total_amount, withdraw_amount, have_amount, freeze_amount) = match amount {
+        Some((t, w, h, f)) => (
+            parse(Some(t)),
+            parse(Some(w)),
+            parse(Some(h)),
+            parse(Some(f)),
+        ),
+        None => Default::default(),
+    };
+
+    // 同一订单可能同时有省、市两个区域的分成，按订单去重
+    #[allow(clippy::type_complexity)]
+    let sum: Option<(u64, Option<String>, u64, Option<String>, Option<String>)> = my_exec_first(
+        &mut conn,
+        "select count(*), sum(base_amount), sum(is_month), sum(if(is_month, base_amount, 0)),
+            sum(if(is_month, month_amount, 0))
+            from (select order_sn, max(base_amount) as base_amount,
+                max(created_at) >= date_format(now(), '%Y-%m-01') as is_month,
+                sum(amount - back_amount) as month_amount
+                from agt_agent_order where uid = ? and is_del = 0 group by order_sn) t",
+        (uid,),
+    )?;
+    let (order_count, order_amount, month_order_count, month_order_amount, month_amount) =
+        sum.unwrap_or_default();
+
+    Ok(web::Json(Res::success(AgentDashboard {
+        areas,
+        total_amount,
+        withdraw_amount,
+        have_amount,
+        freeze_amount,
+        order_count,
+        order_amount: parse(order_amount),
+        month_order_count,
+        month_order_amount: parse(month_order_amount),
+        month_amount: parse(month_amount),
+    })))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct AgentOrderItem {
+    id: u64,
+    order_sn: String,
+    /// 1省代理 2市代理
+    level: u8,
+    /// 订单收货地址
+    province: Option<String>,
+    city: Option<String>,
+    area: Option<String>,
+    /// 订单商品实付（不含运费）
+    base_amount: Money,
+    /// 分成的百分比
+    percent: Money,
+    /// 分成金额
+    amount: Money,
+    /// 退款扣回的分成
+    back_amount: Money,
+    /// 结算后退款，可提现金额不足没扣回的分成
+    shortfall: Money,
+    /// 分成状态 0冻结中 1已结算 2已取消
+    settle_status: u8,
+    /// 订单状态 2已支付，4为申请退款，5为已退款，6为退款中，7为拒绝退款
+    status: u8,
+    created_at: String,
+}
+/// 【代理】代理区域内的分成订单
+#[utoipa::path(
+    responses((status = 200, description = "【返回：AgentOrderItem[]】", body = Vec<AgentOrderItem>)),
+    params(("page", description="页码"),("limit", description="每页数量"))
+)]
+#[get("/agent/order/list/{page}/{limit}")]
+pub async fn agent_order_list(
+    user: AuthRole<PermAgentDashboard>,
+    _m: RequireModule<ModuleAgent>,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if !user.role.contains(&(Role::Agent as u16)) {
+        return Err(error::ErrorUnauthorized("你不是代理"));
+    }
+    let page = query.0.parse::<u32>().unwrap();
+    let limit = query.1.parse::<u32>().unwrap();
+    let mut conn = mysql_conn()?;
+
+    let list: Vec<AgentOrderItem> = my_run_vec(
+        &mut conn,
+        myfind!("agt_agent_order", {
+            j0: ["order_sn", "inner", "ord_order.order_sn"],
+            p0: ["uid", "=", uid],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,order_sn,level,ord_order.province,ord_order.city,ord_order.area,base_amount,percent,amount,back_amount,shortfall,agt_agent_order.status as settle_status,ord_order.status,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(list)))
+}
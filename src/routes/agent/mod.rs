@@ -0,0 +1,8 @@
+mod apply;
+pub use apply::*;
+
+mod dashboard;
+pub use dashboard::*;
+
+mod withdraw;
+pub use withdraw::*;
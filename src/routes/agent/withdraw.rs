@@ -0,0 +1,54 @@
+use actix_web::{Responder, Result, error, post, web};
+use mysql_quick::TxOpts;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::Role;
+use crate::db::mysql_conn;
+use crate::middleware::{AuthRole, ModuleAgent, PermAgentWithdraw, RequireModule};
+use crate::routes::Res;
+use crate::routes::utils_set::agent_set::agent_amount_to_pocket;
+use crate::routes::utils_set::pocket_set::{add_withdrawal_request, init_user_pocket_money};
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AgentWithdraw {
+    /// 申请提现金额，单位元
+    req_amount: Money,
+}
+/// 【代理】代理分成提现。分成先转入零钱，再按零钱提现申请，由后台审核后打款
+#[utoipa::path(
+    request_body = AgentWithdraw,
+    responses((status = 200, description = "【请求：AgentWithdraw】【返回：String】", body = String))
+)]
+#[post("/agent/withdraw_req")]
+pub async fn agent_withdraw_req(
+    user: AuthRole<PermAgentWithdraw>,
+    _m: RequireModule<ModuleAgent>,
+    data: web::Json<AgentWithdraw>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if !user.role.contains(&(Role::Agent as u16)) {
+        return Err(error::ErrorUnauthorized("你不是代理"));
+    }
+    let req_amount = data.req_amount;
+    if !req_amount.is_positive() {
+        return Err(error::ErrorBadRequest("提现金额必须大于0"));
+    }
+
+    let mut conn = mysql_conn()?;
+    init_user_pocket_money(&mut conn, uid)?;
+
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    if let Err(e) = agent_amount_to_pocket(&mut tran, uid, req_amount) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    if let Err(e) = add_withdrawal_request(&mut tran, uid, req_amount) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    tran.commit().unwrap();
+
+    Ok(web::Json(Res::success("提现申请提交成功")))
+}
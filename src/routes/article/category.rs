@@ -5,6 +5,7 @@ use utoipa::ToSchema;
 
 use crate::common::types::NormalStatus;
 use crate::db::{my_run_vec, mysql_conn};
+use crate::middleware::{ModuleArticle, RequireModule};
 use crate::routes::Res;
 use crate::utils::filter::deserialize_path_to_url;
 
@@ -19,7 +20,7 @@ pub struct ArticleCat {
     responses((status = 200, description = "【返回：ArticleCat[]】", body = Vec<ArticleCat>)),
 )]
 #[get("/article/category/list")]
-pub async fn article_category_list() -> Result<impl Responder> {
+pub async fn article_category_list(_m: RequireModule<ModuleArticle>) -> Result<impl Responder> {
     let mut conn = mysql_conn()?;
 
     #[derive(Serialize, Deserialize, Debug)]
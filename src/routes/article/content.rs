@@ -5,7 +5,7 @@ use utoipa::ToSchema;
 
 use crate::common::types::NormalStatus;
 use crate::db::{my_run_drop, my_run_vec, mysql_conn};
-use crate::middleware::AuthOptionUser;
+use crate::middleware::{AuthOptionUser, ModuleArticle, RequireModule};
 use crate::routes::Res;
 use crate::utils::files::get_file_url;
 use crate::utils::html::to_html_image_urls;
@@ -38,6 +38,7 @@ pub struct Article {
 #[get("/article/content/list/{category}/{page}")]
 pub async fn article_content_list(
     user: AuthOptionUser,
+    _m: RequireModule<ModuleArticle>,
     query: web::Path<(String, String)>,
 ) -> Result<impl Responder> {
     let category = query.0.parse::<u32>().unwrap();
@@ -150,6 +151,7 @@ pub struct ArticleDetail {
 #[get("/article/content/detail/{id}")]
 pub async fn article_content_detail(
     user: AuthOptionUser,
+    _m: RequireModule<ModuleArticle>,
     id: web::Path<String>,
 ) -> Result<impl Responder> {
     let id = id.parse::<u32>().unwrap();
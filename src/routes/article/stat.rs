@@ -5,7 +5,7 @@ use utoipa::ToSchema;
 
 use crate::common::types::NormalStatus;
 use crate::db::{my_run_drop, my_run_vec, mysql_conn};
-use crate::middleware::AuthUser;
+use crate::middleware::{AuthUser, ModuleArticle, RequireModule};
 use crate::routes::Res;
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
@@ -20,6 +20,7 @@ pub struct ArticleId {
 #[post("/article/stat/praise")]
 pub async fn article_stat_praise(
     user: AuthUser,
+    _m: RequireModule<ModuleArticle>,
     params: web::Json<ArticleId>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -1,8 +1,6 @@
-use std::collections::HashMap;
-
-use actix_web::{Responder, Result, get, post, web};
+use actix_web::{Error, Responder, Result, error, get, post, web};
 use base64::{Engine as _, engine::general_purpose};
-use mysql_quick::{Queryable, myfind, myget, myset, myupdate};
+use mysql_quick::{PooledConn, Queryable, myfind, myget, myset, myupdate};
 use serde::{Deserialize, Serialize};
 
 use serde_json::json;
@@ -12,9 +10,10 @@ use crate::control::frequency::freq_user_day;
 use crate::control::sms::sms_send_code;
 use crate::control::wx_info::{get_wx_mini_access_token, sign_wx_gzh_jssdk};
 use crate::db::{my_run_drop, my_run_vec, mysql_conn};
-use crate::middleware::AuthUser;
+use crate::middleware::{AuthUser, module_switch_table};
 use crate::routes::{BaseInfo, BaseStrInfo, PdCat, Res, WxJsSdkSign};
 use crate::utils::files::get_file_urls;
+use crate::utils::utils::log_err;
 
 #[derive(Serialize, Clone, Debug, ToSchema)]
 pub struct AreaItem {
@@ -71,7 +70,7 @@ pub async fn common_province_list(grade: web::Path<String>) -> Result<impl Respo
                 town,
             },
         )
-        .unwrap();
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "cmn_province")))?;
 
     let mut list: Vec<ProvItem> = vec![];
     for item in &temp_list {
@@ -273,11 +272,6 @@ pub async fn common_check_content(
     user: AuthUser,
     params: web::Json<CheckContent>,
 ) -> Result<impl Responder> {
-    #[derive(Serialize, Deserialize, Debug)]
-    struct CheckRes {
-        result: CheckResult,
-    }
-
     if params.content.clone() == String::from("") {
         return Ok(web::Json(Res::<u8>::info(1, "检测通过")));
     }
@@ -291,48 +285,64 @@ pub async fn common_check_content(
         None
     };
     if let Some(user_openid) = user_temp_info {
-        let at_v = get_wx_mini_access_token().await?;
-        let client = reqwest::Client::new();
-        let data = json!({
-            "content": params.content,
-            "version": 2,
-            "scene": 1,
-            "openid": user_openid
-        });
-        let check_res: CheckRes = client
-            .post(
-                "https://api.weixin.qq.com/wxa/msg_sec_check?access_token=".to_string()
-                    + at_v.as_str(),
-            )
-            .json(&data)
-            .send()
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap();
-        if check_res.result.suggest == String::from("risky") {
-            match check_res.result.label {
-                100 => return Ok(web::Json(Res::<u8>::info(1, "检测通过"))),
-                10001 => return Ok(web::Json(Res::<u8>::info(0, "文本包含广告内容"))),
-                20001 => return Ok(web::Json(Res::<u8>::info(0, "文本包含时政内容"))),
-                20002 => return Ok(web::Json(Res::<u8>::info(0, "文本包含色情内容"))),
-                20003 => return Ok(web::Json(Res::<u8>::info(0, "文本包含辱骂内容"))),
-                20006 => return Ok(web::Json(Res::<u8>::info(0, "文本包含违法犯罪内容"))),
-                20008 => return Ok(web::Json(Res::<u8>::info(0, "文本包含欺诈内容"))),
-                20012 => return Ok(web::Json(Res::<u8>::info(0, "文本包含低俗内容"))),
-                20013 => return Ok(web::Json(Res::<u8>::info(0, "文本包含版权内容"))),
-                21000 => return Ok(web::Json(Res::<u8>::info(0, "文本包含违规内容"))),
-                _ => return Ok(web::Json(Res::<u8>::info(1, "检测通过"))),
-            }
-        } else {
-            return Ok(web::Json(Res::<u8>::info(1, "检测通过")));
+        match wx_check_text(&user_openid, &params.content).await? {
+            Some(msg) => return Ok(web::Json(Res::<u8>::info(0, msg))),
+            None => return Ok(web::Json(Res::<u8>::info(1, "检测通过"))),
         }
     } else {
         return Ok(web::Json(Res::<u8>::info(1, "检测通过")));
     };
 }
 
+/// 微信小程序文本内容安全检测，有风险时返回原因，通过时返回 None
+pub async fn wx_check_text(openid: &str, content: &str) -> Result<Option<&'static str>, Error> {
+    #[derive(Serialize, Deserialize, Debug)]
+    struct CheckRes {
+        result: CheckResult,
+    }
+
+    let at_v = get_wx_mini_access_token().await?;
+    let client = reqwest::Client::new();
+    let data = json!({
+        "content": content,
+        "version": 2,
+        "scene": 1,
+        "openid": openid
+    });
+    let check_res: CheckRes = client
+        .post(
+            "https://api.weixin.qq.com/wxa/msg_sec_check?access_token=".to_string() + at_v.as_str(),
+        )
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "msg_sec_check")))?
+        .json()
+        .await
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "msg_sec_check")))?;
+    if check_res.result.suggest == String::from("risky") {
+        Ok(text_risky_msg(check_res.result.label))
+    } else {
+        Ok(None)
+    }
+}
+
+/// 文本检测命中的标签，100 为正常
+fn text_risky_msg(label: u16) -> Option<&'static str> {
+    match label {
+        10001 => Some("文本包含广告内容"),
+        20001 => Some("文本包含时政内容"),
+        20002 => Some("文本包含色情内容"),
+        20003 => Some("文本包含辱骂内容"),
+        20006 => Some("文本包含违法犯罪内容"),
+        20008 => Some("文本包含欺诈内容"),
+        20012 => Some("文本包含低俗内容"),
+        20013 => Some("文本包含版权内容"),
+        21000 => Some("文本包含违规内容"),
+        _ => None,
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct CheckListItem {
     id: u64,
@@ -449,18 +459,22 @@ pub struct CheckMedia {
     media_name: String,
     local_path: String,
 }
+impl CheckMedia {
+    /// 检测已上传的图片，用文件路径作为 media_name
+    pub fn image(media_url: String, local_path: String) -> Self {
+        CheckMedia {
+            media_type: 2,
+            media_url,
+            media_name: local_path.clone(),
+            local_path,
+        }
+    }
+}
 #[post("/common/check/media")]
 pub async fn common_check_media(
     user: AuthUser,
     params: web::Json<CheckMedia>,
 ) -> Result<impl Responder> {
-    #[derive(Serialize, Deserialize, Debug)]
-    struct CheckRes {
-        errcode: u16,
-        errmsg: String,
-        trace_id: String,
-    }
-
     if params.media_url.clone() == String::from("") {
         return Ok(web::Json(Res::<u8>::info(1, "检测通过")));
     }
@@ -474,46 +488,64 @@ pub async fn common_check_media(
         None
     };
     if let Some(user_openid) = user_temp_info {
-        let at_v = get_wx_mini_access_token().await?;
-        let client = reqwest::Client::new();
-        let data = json!({
-            "media_url": params.media_url.clone(),
-            "version": 2,
-            "scene": 1,
-            "media_type": params.media_type.clone(),
-            "openid": user_openid
-        });
-
-        let check_res: CheckRes = client
-            .post(
-                "https://api.weixin.qq.com/wxa/media_check_async?access_token=".to_string()
-                    + at_v.as_str(),
-            )
-            .json(&data)
-            .send()
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap();
-        // 将该条记录，保存到数据库
-        my_run_drop(
-            &mut conn,
-            myset!("check_list", {
-                "uid": user.id,
-                "media_name": params.media_name.clone(),
-                "media_type": params.media_type,
-                "media_url": params.media_url.clone(),
-                "local_path": params.local_path.clone(),
-                "trace_id": check_res.trace_id.clone(),
-            }),
-        )?;
+        wx_check_media(&mut conn, user.id, &user_openid, &params).await?;
         return Ok(web::Json(Res::<u8>::info(1, "检测通过")));
     } else {
         return Ok(web::Json(Res::<u8>::info(1, "检测通过")));
     };
 }
 
+/// 微信小程序图片音频异步检测，检测结果由 /common/wx/message 推送后更新到 check_list
+pub async fn wx_check_media(
+    conn: &mut PooledConn,
+    uid: u64,
+    openid: &str,
+    media: &CheckMedia,
+) -> Result<(), Error> {
+    #[derive(Serialize, Deserialize, Debug)]
+    struct CheckRes {
+        errcode: u16,
+        errmsg: String,
+        trace_id: String,
+    }
+
+    let at_v = get_wx_mini_access_token().await?;
+    let client = reqwest::Client::new();
+    let data = json!({
+        "media_url": media.media_url.clone(),
+        "version": 2,
+        "scene": 1,
+        "media_type": media.media_type,
+        "openid": openid
+    });
+
+    let check_res: CheckRes = client
+        .post(
+            "https://api.weixin.qq.com/wxa/media_check_async?access_token=".to_string()
+                + at_v.as_str(),
+        )
+        .json(&data)
+        .send()
+        .await
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "media_check_async")))?
+        .json()
+        .await
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "media_check_async")))?;
+    // 将该条记录，保存到数据库
+    my_run_drop(
+        conn,
+        myset!("check_list", {
+            "uid": uid,
+            "media_name": media.media_name.clone(),
+            "media_type": media.media_type,
+            "media_url": media.media_url.clone(),
+            "local_path": media.local_path.clone(),
+            "trace_id": check_res.trace_id.clone(),
+        }),
+    )?;
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct WxMessage {
     signature: String,
@@ -704,28 +736,7 @@ pub async fn common_wx_js_sdk_sign() -> Result<impl Responder> {
 )]
 #[get("/common/module/switch_list")]
 pub async fn common_module_switch_list() -> Result<impl Responder> {
-    let mut conn = mysql_conn()?;
-    #[derive(Serialize, Clone, Deserialize)]
-    struct ModuleSwitchGet {
-        id: u32,
-        name: String,
-        code: String,
-        is_on: u8,
-    }
-    let list: Vec<ModuleSwitchGet> = my_run_vec(
-        &mut conn,
-        myfind!("sys_module_switch", {
-            p0: ["is_del", "=", 0],
-            r: "p0",
-            select: "id,name,is_on,code",
-        }),
-    )?;
-    let mut hash = HashMap::new();
-
-    for x in list.into_iter() {
-        let temp_on = if x.is_on == 1 { true } else { false };
-        hash.insert(x.code, temp_on);
-    }
+    let hash = module_switch_table()?;
     Ok(web::Json(Res::success(hash)))
 }
 
@@ -1,26 +1,23 @@
 use actix_web::{Responder, Result, error, post, put, web};
 use bcrypt::{DEFAULT_COST, hash, verify};
 use mysql_quick::{MysqlQuickCount, Queryable, mycount, myfind, myget, myset, myupdate};
-use regex::Regex;
 use reqwest;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use super::Res;
 use super::utils_set::user_set::{user_set_with_union_open, user_upd_phone};
+use crate::common::Config;
 use crate::common::types::{FileDir, OssBucket};
-use crate::common::{
-    JWT_MANAGE_EXPIRES_SEC, JWT_NORMAL_EXPIRES_SEC, WECHAT_MINI_APP_ID, WECHAT_MINI_APP_SECRET,
-};
 use crate::control::app_data::{AppData, SlownWorker};
 use crate::control::sms::sms_verify;
+use crate::control::token::{bump_token_ver, issue_login_tokens, refresh_login_tokens};
 use crate::control::wx_info::{
     get_wx_gzh_web_silent, get_wx_gzh_web_user_info, get_wx_mini_access_token,
 };
-use crate::db::{my_run_drop, my_run_vec, mysql_conn};
+use crate::db::{my_exec_first, my_run_drop, my_run_vec, mysql_conn};
 use crate::middleware::AuthUser;
 use crate::utils::files::{download_file_to_oss, get_file_url_sec, get_path_from_url};
-use crate::utils::jwt::get_token;
 use crate::utils::utils::log_err;
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
@@ -39,6 +36,8 @@ pub struct UserInfo {
     pub role: Vec<u16>,
     /// 登录授权 token
     pub token: String,
+    /// 用于换取新 token 的 refresh token
+    pub refresh_token: String,
     /// 手机号，仅在绑定手机号接口时才返回
     pub phone: Option<String>,
 }
@@ -52,13 +51,16 @@ pub struct WechatSilent {
     responses((status = 200, description = "【请求：WechatSilent】【返回：UserInfo】", body = UserInfo))
 )]
 #[post("/login/silent/wechat/mini")]
-pub async fn login_silent_wechat_mini(params: web::Json<WechatSilent>) -> Result<impl Responder> {
+pub async fn login_silent_wechat_mini(
+    params: web::Json<WechatSilent>,
+    cfg: web::Data<Config>,
+) -> Result<impl Responder> {
     let code = &params.code;
 
     let session_url = "https://api.weixin.qq.com/sns/jscode2session?appid=".to_string()
-        + WECHAT_MINI_APP_ID
+        + &cfg.wechat.mini_app_id
         + "&secret="
-        + WECHAT_MINI_APP_SECRET
+        + &cfg.wechat.mini_app_secret
         + "&js_code="
         + code
         + "&grant_type=authorization_code";
@@ -95,6 +97,7 @@ pub struct WechatPhone {
 pub async fn login_wechat_phone_mini(
     user: AuthUser,
     params: web::Json<WechatPhone>,
+    cfg: web::Data<Config>,
 ) -> Result<impl Responder> {
     let uid = user.id;
     let code = &params.code;
@@ -142,7 +145,7 @@ pub async fn login_wechat_phone_mini(
             "小程序获取手机号失败",
         )));
     }
-    if data.phone_info.watermark.appid != WECHAT_MINI_APP_ID {
+    if data.phone_info.watermark.appid != cfg.wechat.mini_app_id {
         return Err(error::ErrorBadRequest("请求 code 错误"));
     }
 
@@ -186,6 +189,7 @@ pub struct WechatLoginMiniInfo {
 pub async fn login_wechat_mini_info(
     user: AuthUser,
     params: web::Json<WechatLoginMiniInfo>,
+    cfg: web::Data<Config>,
 ) -> Result<impl Responder> {
     let uid = user.id;
     let mut conn = mysql_conn()?;
@@ -223,7 +227,7 @@ pub async fn login_wechat_mini_info(
             "id,username,nickname,avatar_url,gender,role"
         ),
     )?;
-    let token = get_token(AuthUser { id: user_get[0].id }, JWT_NORMAL_EXPIRES_SEC)?;
+    let tokens = issue_login_tokens(user_get[0].id, false)?;
     let user = UserInfo {
         id: user_get[0].id,
         username: user_get[0].username.clone(),
@@ -232,8 +236,8 @@ pub async fn login_wechat_mini_info(
             if a == String::from("") {
                 None
             } else {
-                let temp_url =
-                    get_file_url_sec(Some(&a), JWT_NORMAL_EXPIRES_SEC).unwrap_or("".to_string());
+                let temp_url = get_file_url_sec(Some(&a), cfg.jwt.normal_expires_sec)
+                    .unwrap_or("".to_string());
                 Some(temp_url)
             }
         } else {
@@ -250,7 +254,8 @@ pub async fn login_wechat_mini_info(
                 .collect::<Vec<u16>>()
         },
         phone: None,
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
     };
 
     Ok(web::Json(Res::success(user)))
@@ -265,6 +270,7 @@ pub async fn login_wechat_mini_info(
 pub async fn login_wechat_gzh_info(
     params: web::Json<WechatSilent>,
     app_data: web::Data<AppData>,
+    cfg: web::Data<Config>,
 ) -> Result<impl Responder> {
     let mut conn = mysql_conn()?;
     let data = &app_data;
@@ -329,7 +335,7 @@ pub async fn login_wechat_gzh_info(
                 if a == String::from("") {
                     None
                 } else {
-                    let temp_url = get_file_url_sec(Some(&a), JWT_NORMAL_EXPIRES_SEC)
+                    let temp_url = get_file_url_sec(Some(&a), cfg.jwt.normal_expires_sec)
                         .unwrap_or("".to_string());
                     Some(temp_url)
                 }
@@ -348,6 +354,7 @@ pub async fn login_wechat_gzh_info(
             },
             phone: None,
             token: user_init.token,
+            refresh_token: user_init.refresh_token,
         };
 
         Ok(web::Json(Res::success(user)))
@@ -376,7 +383,14 @@ pub struct LoginManaRes {
     authority: Option<String>,
     /// 登录授权 token
     token: String,
+    /// 用于换取新 token 的 refresh token
+    refresh_token: String,
 }
+/// 管理后台的用户名，只能是 数字、字母、下划线
+fn is_manage_username(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ManageLogin {
     username: String,
@@ -384,37 +398,38 @@ pub struct ManageLogin {
 }
 /// 管理后台，的用户登录
 #[post("/login/manage")]
-pub async fn login_manage(params: web::Json<ManageLogin>) -> Result<impl Responder> {
+pub async fn login_manage(
+    params: web::Json<ManageLogin>,
+    cfg: web::Data<Config>,
+) -> Result<impl Responder> {
+    // 用户名只能是 数字、字母、下划线，与注册时一致，其他的直接视为登录失败
+    if !is_manage_username(&params.username) {
+        return Ok(web::Json(Res::fail("用户名或密码错误。")));
+    }
     let mut conn = mysql_conn()?;
     // 查寻有没有当前的用户
     let username = params.username.clone();
     let from_pass = params.password.clone();
-    let user_pass: Option<String> = conn
-        .query_first(
-            "
-        select password from usr_silent where username = \""
-                .to_string()
-                + username.as_str()
-                + "\"",
-        )
-        .unwrap();
+    let user_pass: Option<String> = my_exec_first(
+        &mut conn,
+        "select password from usr_silent where username = ?",
+        (&username,),
+    )?;
 
     let result: Res<LoginManaRes>;
 
     if let Some(p) = user_pass {
-        let is_valid = verify(from_pass, &p).unwrap();
+        // 密码格式不对（如不是 bcrypt 的 hash）时，也视为密码错误
+        let is_valid = verify(from_pass, &p).unwrap_or(false);
         if is_valid {
-            let query_string = "select
-            usr_silent.id,username,nickname,avatar_url,gender,phone,role,usr_authority.authority
-            from usr_silent
-            left join usr_authority on usr_silent.id = usr_authority.uid
-            where username = \""
-                .to_string()
-                + username.as_str()
-                + "\"";
             let mut user: Vec<LoginManaRes> = conn
-                .query_map(
-                    query_string.as_str(),
+                .exec_map(
+                    "select
+                    usr_silent.id,username,nickname,avatar_url,gender,phone,role,usr_authority.authority
+                    from usr_silent
+                    left join usr_authority on usr_silent.id = usr_authority.uid
+                    where username = ?",
+                    (&username,),
                     |(id, username, nickname, avatar_url, gender, phone, role, authority)| {
                         LoginManaRes {
                             id,
@@ -426,17 +441,22 @@ pub async fn login_manage(params: web::Json<ManageLogin>) -> Result<impl Respond
                             role,
                             authority,
                             token: "".to_string(),
+                            refresh_token: "".to_string(),
                         }
                     },
                 )
-                .unwrap();
-            let token = get_token(AuthUser { id: user[0].id }, JWT_MANAGE_EXPIRES_SEC)?;
-            user[0].token = token;
+                .map_err(|e| error::ErrorInternalServerError(log_err(&e, "login_manage")))?;
+            if user.is_empty() {
+                return Ok(web::Json(Res::fail("用户名或密码错误。")));
+            }
+            let tokens = issue_login_tokens(user[0].id, true)?;
+            user[0].token = tokens.token;
+            user[0].refresh_token = tokens.refresh_token;
             user[0].avatar_url = if let Some(a) = user[0].avatar_url.clone() {
                 if a == String::from("") {
                     Some(String::from(""))
                 } else {
-                    let temp_url = get_file_url_sec(Some(&a), JWT_MANAGE_EXPIRES_SEC)
+                    let temp_url = get_file_url_sec(Some(&a), cfg.jwt.manage_expires_sec)
                         .unwrap_or("".to_string());
                     Some(temp_url)
                 }
@@ -462,7 +482,10 @@ pub struct ManageRegister {
 }
 /// 管理后台，用户注册
 #[post("/login/register/manage")]
-pub async fn login_register_manage(params: web::Json<ManageRegister>) -> Result<impl Responder> {
+pub async fn login_register_manage(
+    params: web::Json<ManageRegister>,
+    cfg: web::Data<Config>,
+) -> Result<impl Responder> {
     #[derive(Serialize, Debug, Deserialize)]
     struct UserTmpInfo {
         id: u64,
@@ -480,9 +503,7 @@ pub async fn login_register_manage(params: web::Json<ManageRegister>) -> Result<
     let pass = params.password.clone();
     let pass2 = params.password2.clone();
 
-    let re = Regex::new(r"^[0-9a-zA-Z_]{1,}$").unwrap();
-
-    if !re.is_match(name.as_str()) {
+    if !is_manage_username(&name) {
         return Ok(web::Json(Res::fail("用户名不正确，只能数字、字母、下划线")));
     }
     if name.len() < 6 {
@@ -532,6 +553,7 @@ pub async fn login_register_manage(params: web::Json<ManageRegister>) -> Result<
             }
         ),
     )?;
+    let tokens = issue_login_tokens(up_uid, true)?;
     let tmp_user = LoginManaRes {
         id: userinfo[0].id,
         username: userinfo[0].username.clone(),
@@ -540,8 +562,8 @@ pub async fn login_register_manage(params: web::Json<ManageRegister>) -> Result<
             if a == String::from("") {
                 Some(String::from(""))
             } else {
-                let temp_url =
-                    get_file_url_sec(Some(&a), JWT_MANAGE_EXPIRES_SEC).unwrap_or("".to_string());
+                let temp_url = get_file_url_sec(Some(&a), cfg.jwt.manage_expires_sec)
+                    .unwrap_or("".to_string());
                 Some(temp_url)
             }
         } else {
@@ -551,7 +573,8 @@ pub async fn login_register_manage(params: web::Json<ManageRegister>) -> Result<
         phone: userinfo[0].phone.clone(),
         role: userinfo[0].role.clone(),
         authority: userinfo[0].authority.clone(),
-        token: get_token(AuthUser { id: up_uid }, JWT_MANAGE_EXPIRES_SEC)?,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
     };
 
     Ok(web::Json(Res::success(tmp_user)))
@@ -590,9 +613,46 @@ pub async fn login_sms_bind_phone(
     Ok(web::Json(Res::success("")))
 }
 
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RefreshToken {
+    /// 登录时返回的 refresh token
+    refresh_token: String,
+}
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RefreshRes {
+    /// 新的登录授权 token
+    token: String,
+    /// 新的 refresh token，旧的已失效
+    refresh_token: String,
+}
+/// 【登录】刷新 token
+#[utoipa::path(
+    request_body = RefreshToken,
+    responses((status = 200, description = "【请求：RefreshToken】【返回：RefreshRes】", body = RefreshRes)),
+)]
+#[post("/login/refresh")]
+pub async fn login_refresh(params: web::Json<RefreshToken>) -> Result<impl Responder> {
+    let tokens = refresh_login_tokens(&params.refresh_token)?;
+    Ok(web::Json(Res::success(RefreshRes {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+    })))
+}
+
+/// 【登录】退出登录，该用户所有设备上的登录都会失效
+#[utoipa::path(
+    responses((status = 200, description = "【返回：String】", body = String)),
+)]
+#[post("/login/logout")]
+pub async fn login_logout(user: AuthUser) -> Result<impl Responder> {
+    bump_token_ver(user.id)?;
+    Ok(web::Json(Res::success("退出成功")))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::config_data;
     use actix_web::{App, test};
     use bcrypt::{DEFAULT_COST, hash};
     use serde_json::json;
@@ -606,7 +666,12 @@ mod tests {
 
     #[actix_web::test]
     async fn test_login_wechat_phone_mini() {
-        let app = test::init_service(App::new().service(login_wechat_phone_mini)).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(config_data())
+                .service(login_wechat_phone_mini),
+        )
+        .await;
 
         let req = test::TestRequest::put()
             .uri("/login/wechat/phone/mini")
@@ -618,4 +683,39 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
     }
+
+    #[actix_web::test]
+    async fn test_is_manage_username() {
+        assert!(is_manage_username("admin"));
+        assert!(is_manage_username("mana_01"));
+        assert!(!is_manage_username(""));
+        assert!(!is_manage_username("管理员"));
+        assert!(!is_manage_username("admin "));
+    }
+
+    #[actix_web::test]
+    async fn test_login_manage_injection() {
+        let app =
+            test::init_service(App::new().app_data(config_data()).service(login_manage)).await;
+        let payloads = [
+            r#"admin" or "1"="1"#,
+            r#"admin" -- "#,
+            r#"" or 1=1 #"#,
+            "admin'; drop table usr_silent; --",
+            r#"admin" union select password from usr_silent where "1"="1"#,
+            "admin\\\" or \\\"\\\"=\\\"",
+        ];
+        for payload in payloads {
+            let req = test::TestRequest::post()
+                .uri("/login/manage")
+                .set_json(json!({
+                    "username": payload,
+                    "password": "123456",
+                }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], 0, "{}", payload);
+            assert!(resp["objects"].is_null(), "{}", payload);
+        }
+    }
 }
@@ -0,0 +1,527 @@
+use actix_web::{Error, Responder, Result, error, get, post, put, web};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, Transaction, TxOpts, myfind, myset};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::{
+    AfterSaleStatus, AfterSaleType, FileDir, OrderItemStatus, OrderPayStatus, WriteOffStatus,
+};
+use crate::control::app_data::{AppData, SlownWorker};
+use crate::db::{
+    my_exec_drop, my_run_tran_drop, my_run_tran_vec, my_run_vec, mysql_block, mysql_conn,
+};
+use crate::middleware::AuthUser;
+use crate::routes::Res;
+use crate::routes::utils_set::after_sale_set::{
+    AfterSaleItemRes, close_after_sale, get_after_sale_item_res, order_item_refund_amount,
+};
+use crate::routes::utils_set::mall_set::upd_order_item_status;
+use crate::utils::files::get_file_urls;
+
+/// 售后原因的最大字数
+const AFTER_SALE_REASON_MAX: usize = 200;
+/// 售后凭证图片的最大数量
+const AFTER_SALE_IMGS_MAX: usize = 9;
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct AfterSaleItemAdd {
+    /// 子订单id
+    order_item_id: String,
+    /// 退的数量
+    quantity: u32,
+}
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct AfterSaleApply {
+    /// 订单号
+    order_sn: String,
+    /// 售后类型，RefundOnly 仅退款，ReturnRefund 退货退款
+    after_sale_type: AfterSaleType,
+    /// 要退的商品和数量
+    items: Vec<AfterSaleItemAdd>,
+    /// 申请原因
+    reason: String,
+    /// 凭证图片，/upload/file 上传（category 为 after_sale）后返回的 path
+    imgs: Vec<String>,
+}
+
+/// 检查售后申请的参数
+fn check_after_sale(params: &AfterSaleApply) -> Result<(), &'static str> {
+    if params.items.is_empty() {
+        return Err("请选择要退的商品");
+    }
+    if params.items.iter().any(|x| x.quantity == 0) {
+        return Err("退的数量不能为0");
+    }
+    let ids: HashSet<&str> = params
+        .items
+        .iter()
+        .map(|x| x.order_item_id.as_str())
+        .collect();
+    if ids.len() != params.items.len() {
+        return Err("商品重复");
+    }
+    let reason = params.reason.trim();
+    if reason.is_empty() {
+        return Err("请填写申请原因");
+    }
+    if reason.chars().count() > AFTER_SALE_REASON_MAX {
+        return Err("申请原因不能超过200字");
+    }
+    if params.imgs.len() > AFTER_SALE_IMGS_MAX {
+        return Err("凭证图片不能超过9张");
+    }
+    // 只能用售后目录下上传的图片
+    let dir = format!("{}/", FileDir::AfterSale.get_dir());
+    if params.imgs.iter().any(|x| !x.starts_with(&dir)) {
+        return Err("凭证图片有误");
+    }
+    Ok(())
+}
+
+/// 校验订单和商品，保存售后单，子订单改为申请退货。不能申请时返回 Ok(Err(原因))
+fn add_after_sale(
+    tran: &mut Transaction,
+    uid: u64,
+    after_sale_sn: &str,
+    params: &AfterSaleApply,
+) -> Result<Result<(), &'static str>, Error> {
+    #[derive(Deserialize)]
+    struct OrderGet {
+        uid: u64,
+        status: i8,
+        total_amount: Money,
+        delivery_amount: Option<Money>,
+        pay_amount: Money,
+    }
+    let order: Vec<OrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order", {
+            p0: ["order_sn", "=", &params.order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "uid,status,total_amount,delivery_amount,pay_amount",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(order) = order.into_iter().next().filter(|x| x.uid == uid) else {
+        return Ok(Err("订单不存在"));
+    };
+    if order.status != OrderPayStatus::Paid as i8 {
+        return Ok(Err("只有已支付的订单才能申请售后"));
+    }
+    let goods_pay = order.pay_amount - order.delivery_amount.unwrap_or(Money::ZERO);
+
+    #[derive(Deserialize)]
+    struct ItemGet {
+        order_item_id: String,
+        unit_sn: u32,
+        price: Money,
+        buy_quantity: u32,
+        pay_amount: Option<Money>,
+        refund_quantity: u32,
+        status: u8,
+        write_off_status: Option<u8>,
+    }
+    let items: Vec<ItemGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order_item", {
+            j0: ["order_item_id", "left", "ord_write_off_item.order_item_id"],
+            p0: ["order_sn", "=", &params.order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "order_item_id,unit_sn,price,buy_quantity,pay_amount,refund_quantity,status,ord_write_off_item.write_off_status",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+
+    let mut refund_items = vec![];
+    for add in &params.items {
+        let Some(item) = items.iter().find(|x| x.order_item_id == add.order_item_id) else {
+            return Ok(Err("商品不在订单中"));
+        };
+        let status = OrderItemStatus::from(item.status);
+        if status == OrderItemStatus::Apply {
+            return Ok(Err("商品售后处理中"));
+        }
+        if ![
+            OrderItemStatus::WaitDeliverGoods,
+            OrderItemStatus::WaitTakeDelivery,
+            OrderItemStatus::Complete,
+            OrderItemStatus::Evaluated,
+        ]
+        .contains(&status)
+        {
+            return Ok(Err("商品当前状态不能申请售后"));
+        }
+        if item
+            .write_off_status
+            .is_some_and(|x| x != WriteOffStatus::PendingWriteOff as u8)
+        {
+            return Ok(Err("已核销的商品不能申请售后"));
+        }
+        if params.after_sale_type == AfterSaleType::ReturnRefund
+            && status == OrderItemStatus::WaitDeliverGoods
+        {
+            return Ok(Err("商品还未发货，请申请仅退款"));
+        }
+        if add.quantity > item.buy_quantity.saturating_sub(item.refund_quantity) {
+            return Ok(Err("退的数量超过可退数量"));
+        }
+        let amount = order_item_refund_amount(
+            item.pay_amount,
+            goods_pay,
+            order.total_amount,
+            item.price,
+            item.buy_quantity,
+            item.refund_quantity,
+            add.quantity,
+        );
+        refund_items.push((item, add.quantity, amount));
+    }
+    let refund_amount: Money = refund_items.iter().map(|x| x.2).sum();
+
+    let imgs = params.imgs.join(",");
+    let after_sale_id = my_run_tran_drop(
+        tran,
+        myset!("ord_after_sale", {
+            "after_sale_sn": after_sale_sn,
+            "uid": uid,
+            "order_sn": &params.order_sn,
+            "type": params.after_sale_type.clone() as u8,
+            "reason": params.reason.trim(),
+            "imgs": if imgs.is_empty() { "null" } else { &imgs },
+            "refund_amount": refund_amount.to_string(),
+            "status": AfterSaleStatus::Apply as u8,
+        }),
+    )?;
+    for (item, quantity, amount) in refund_items {
+        my_run_tran_drop(
+            tran,
+            myset!("ord_after_sale_item", {
+                "after_sale_id": after_sale_id,
+                "order_item_id": &item.order_item_id,
+                "unit_sn": item.unit_sn,
+                "quantity": quantity,
+                "refund_amount": amount.to_string(),
+                "item_status": item.status,
+            }),
+        )?;
+        upd_order_item_status(tran, &item.order_item_id, OrderItemStatus::Apply)?;
+    }
+    Ok(Ok(()))
+}
+
+/// 【售后】申请仅退款或退货退款
+///
+/// 按子订单和数量申请，退款金额按商品实付分摊，不含运费；退完订单的全部商品时，
+/// 最后退款的售后单一并退回运费。申请期间子订单为申请退货，
+/// 后台审核通过后，仅退款的直接原路退款，退货退款的需填写退货运单
+#[utoipa::path(
+    request_body = AfterSaleApply,
+    responses((status = 200, description = "【请求：AfterSaleApply】【返回：String 售后单号】", body = String)),
+)]
+#[post("/mall/after_sale/apply")]
+pub async fn mall_after_sale_apply(
+    user: AuthUser,
+    app_data: web::Data<AppData>,
+    params: web::Json<AfterSaleApply>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if let Err(msg) = check_after_sale(&params) {
+        return Ok(web::Json(Res::fail(msg)));
+    }
+    let after_sale_sn = app_data.rand_no(SlownWorker::OrderSn);
+    let params = params.into_inner();
+    let res = mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        match add_after_sale(&mut tran, uid, &after_sale_sn, &params) {
+            Ok(Ok(())) => {}
+            Ok(Err(msg)) => {
+                tran.rollback().unwrap();
+                return Ok(Res::fail(msg));
+            }
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        }
+        tran.commit().unwrap();
+        // ---- 事务结束 ----
+        Ok(Res::success(after_sale_sn))
+    })
+    .await?;
+    Ok(web::Json(res))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct AfterSaleWaybill {
+    /// 售后单号
+    after_sale_sn: String,
+    /// 退货物流公司
+    delivery_name: String,
+    /// 退货运单号
+    waybill_id: String,
+}
+/// 【售后】退货退款审核通过后，填写退货运单
+#[utoipa::path(
+    request_body = AfterSaleWaybill,
+    responses((status = 200, description = "【请求：AfterSaleWaybill】【返回：String】", body = String)),
+)]
+#[put("/mall/after_sale/waybill")]
+pub async fn mall_after_sale_waybill(
+    user: AuthUser,
+    params: web::Json<AfterSaleWaybill>,
+) -> Result<impl Responder> {
+    let delivery_name = params.delivery_name.trim();
+    let waybill_id = params.waybill_id.trim();
+    if delivery_name.is_empty() || waybill_id.is_empty() {
+        return Ok(web::Json(Res::fail("请填写退货物流公司和运单号")));
+    }
+    if delivery_name.chars().count() > 50 || waybill_id.chars().count() > 50 {
+        return Ok(web::Json(Res::fail("物流信息过长")));
+    }
+    let (delivery_name, waybill_id) = (delivery_name.to_string(), waybill_id.to_string());
+    let after_sale_sn = params.into_inner().after_sale_sn;
+    let affected = mysql_block(move |conn| {
+        my_exec_drop(
+            conn,
+            "update ord_after_sale set delivery_name = ?, waybill_id = ?, status = ? where after_sale_sn = ? and uid = ? and status = ? and is_del = 0",
+            (
+                delivery_name,
+                waybill_id,
+                AfterSaleStatus::Returning as u8,
+                after_sale_sn,
+                user.id,
+                AfterSaleStatus::WaitReturn as u8,
+            ),
+        )
+    })
+    .await?;
+    if affected == 0 {
+        return Ok(web::Json(Res::fail("售后单不是待寄回状态")));
+    }
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct AfterSaleCancel {
+    /// 售后单号
+    after_sale_sn: String,
+}
+/// 【售后】撤销待审核或待寄回的售后申请
+#[utoipa::path(
+    request_body = AfterSaleCancel,
+    responses((status = 200, description = "【请求：AfterSaleCancel】【返回：String】", body = String)),
+)]
+#[put("/mall/after_sale/cancel")]
+pub async fn mall_after_sale_cancel(
+    user: AuthUser,
+    params: web::Json<AfterSaleCancel>,
+) -> Result<impl Responder> {
+    let after_sale_sn = params.into_inner().after_sale_sn;
+    let res = mysql_block(move |conn| {
+        #[derive(Deserialize)]
+        struct IdGet {
+            id: u64,
+        }
+        let list: Vec<IdGet> = my_run_vec(
+            conn,
+            myfind!("ord_after_sale", {
+                p0: ["after_sale_sn", "=", &after_sale_sn],
+                p1: ["uid", "=", user.id],
+                p2: ["is_del", "=", 0],
+                r: "p0 && p1 && p2",
+                select: "id",
+            }),
+        )?;
+        let Some(IdGet { id }) = list.into_iter().next() else {
+            return Ok(Res::fail("售后单不存在"));
+        };
+
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        let closed = match close_after_sale(
+            &mut tran,
+            id,
+            &[AfterSaleStatus::Apply, AfterSaleStatus::WaitReturn],
+            AfterSaleStatus::Cancelled,
+            None,
+        ) {
+            Ok(d) => d,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+        if !closed {
+            tran.rollback().unwrap();
+            return Ok(Res::fail("售后单已在处理中，不能撤销"));
+        }
+        tran.commit().unwrap();
+        // ---- 事务结束 ----
+        Ok(Res::success("成功"))
+    })
+    .await?;
+    Ok(web::Json(res))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct AfterSaleRes {
+    /// 售后单号
+    after_sale_sn: String,
+    /// 订单号
+    order_sn: String,
+    /// 售后类型 1 仅退款，2 退货退款
+    after_sale_type: u8,
+    /// 申请原因
+    reason: String,
+    /// 凭证图片
+    imgs: Vec<String>,
+    /// 退款金额
+    refund_amount: Money,
+    /// 状态 0 已拒绝，1 待审核，2 待寄回，3 待收货，4 退款中，5 已退款，6 已取消
+    status: u8,
+    /// 后台处理说明，如拒绝原因
+    remark: Option<String>,
+    /// 退货物流公司
+    delivery_name: Option<String>,
+    /// 退货运单号
+    waybill_id: Option<String>,
+    /// 退款完成时间
+    refund_time: Option<String>,
+    /// 申请时间
+    created_at: String,
+    /// 退的商品
+    items: Vec<AfterSaleItemRes>,
+}
+/// 用户的售后单，after_sale_sn 为空时分页查询全部
+fn get_user_after_sales(
+    uid: u64,
+    after_sale_sn: Option<&str>,
+    page: u32,
+) -> Result<Vec<AfterSaleRes>, Error> {
+    let mut conn = mysql_conn()?;
+    #[derive(Deserialize)]
+    struct AfterSaleGet {
+        id: u64,
+        after_sale_sn: String,
+        order_sn: String,
+        #[serde(rename = "type")]
+        after_sale_type: u8,
+        reason: String,
+        imgs: Option<String>,
+        refund_amount: Money,
+        status: u8,
+        remark: Option<String>,
+        delivery_name: Option<String>,
+        waybill_id: Option<String>,
+        refund_time: Option<String>,
+        created_at: String,
+    }
+    let r = if after_sale_sn.is_some() {
+        "p0 && p1 && p2"
+    } else {
+        "p0 && p1"
+    };
+    let list: Vec<AfterSaleGet> = my_run_vec(
+        &mut conn,
+        myfind!("ord_after_sale", {
+            p0: ["uid", "=", uid],
+            p1: ["is_del", "=", 0],
+            p2: ["after_sale_sn", "=", after_sale_sn.unwrap_or_default()],
+            r: r,
+            page: page,
+            limit: 20,
+            order_by: "-created_at",
+            select: "id,after_sale_sn,order_sn,type,reason,imgs,refund_amount,status,remark,delivery_name,waybill_id,refund_time,created_at",
+        }),
+    )?;
+    let ids: Vec<u64> = list.iter().map(|x| x.id).collect();
+    let mut items = get_after_sale_item_res(&mut conn, &ids)?;
+
+    Ok(list
+        .into_iter()
+        .map(|x| AfterSaleRes {
+            items: items.remove(&x.id).unwrap_or_default(),
+            after_sale_sn: x.after_sale_sn,
+            order_sn: x.order_sn,
+            after_sale_type: x.after_sale_type,
+            reason: x.reason,
+            imgs: get_file_urls(x.imgs),
+            refund_amount: x.refund_amount,
+            status: x.status,
+            remark: x.remark,
+            delivery_name: x.delivery_name,
+            waybill_id: x.waybill_id,
+            refund_time: x.refund_time,
+            created_at: x.created_at,
+        })
+        .collect())
+}
+
+/// 【售后】我的售后单
+#[utoipa::path(
+    responses((status = 200, description = "【返回：AfterSaleRes[]】", body = Vec<AfterSaleRes>)),
+    params(("page", description="第几页"))
+)]
+#[get("/mall/after_sale/list/{page}")]
+pub async fn mall_after_sale_list(
+    user: AuthUser,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let page: u32 = path.to_owned().parse().map_err(error::ErrorBadRequest)?;
+    let list = get_user_after_sales(user.id, None, page)?;
+    Ok(web::Json(Res::success(list)))
+}
+
+/// 【售后】售后单详情
+#[utoipa::path(
+    responses((status = 200, description = "【返回：AfterSaleRes】", body = AfterSaleRes)),
+    params(("after_sale_sn", description="售后单号"))
+)]
+#[get("/mall/after_sale/detail/{after_sale_sn}")]
+pub async fn mall_after_sale_detail(
+    user: AuthUser,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let list = get_user_after_sales(user.id, Some(&path), 1)?;
+    let Some(after_sale) = list.into_iter().next() else {
+        return Ok(web::Json(Res::fail("售后单不存在")));
+    };
+    Ok(web::Json(Res::success(after_sale)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_after_sale() {
+        let dir = FileDir::AfterSale.get_dir();
+        let item = |id: &str, quantity| AfterSaleItemAdd {
+            order_item_id: id.to_string(),
+            quantity,
+        };
+        let mut p = AfterSaleApply {
+            order_sn: "299477755089784832".to_string(),
+            after_sale_type: AfterSaleType::ReturnRefund,
+            items: vec![item("66c15f522854347e5023000", 1)],
+            reason: "尺码不合适".to_string(),
+            imgs: vec![format!("{dir}/1.jpg")],
+        };
+        assert_eq!(check_after_sale(&p), Ok(()));
+        p.items.push(item("66c15f522854347e5023000", 1));
+        assert_eq!(check_after_sale(&p), Err("商品重复"));
+        p.items = vec![item("66c15f522854347e5023000", 0)];
+        assert_eq!(check_after_sale(&p), Err("退的数量不能为0"));
+        p.items = vec![];
+        assert_eq!(check_after_sale(&p), Err("请选择要退的商品"));
+        p.items = vec![item("66c15f522854347e5023000", 2)];
+        p.reason = " ".to_string();
+        assert_eq!(check_after_sale(&p), Err("请填写申请原因"));
+        p.reason = "破损".to_string();
+        p.imgs = vec!["mall_server/review/1.jpg".to_string()];
+        assert_eq!(check_after_sale(&p), Err("凭证图片有误"));
+    }
+}
@@ -0,0 +1,372 @@
+use actix_web::{Responder, Result, get, put, web};
+use mysql_quick::{TxOpts, myfind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::ShopCartStatus;
+use crate::db::{in_placeholders, my_exec_drop, my_run_vec, mysql_block};
+use crate::middleware::{AuthUser, ModuleShoppingCart, RequireModule};
+use crate::routes::utils_set::mall_set::{del_shop_cart_units, set_shop_cart_quantity};
+use crate::routes::{Res, UnitAttrInfo};
+use crate::utils::files::get_file_url;
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CartItem {
+    /// 购物车的id
+    id: u64,
+    /// 商品编号
+    unit_sn: u32,
+    /// 商品封面图
+    unit_cover: String,
+    /// 商品名
+    unit_name: Option<String>,
+    /// 产品编号
+    product_sn: u32,
+    /// 产品名
+    product_name: String,
+    /// 商品属性信息
+    unit_attr_info: Vec<UnitAttrInfo>,
+    /// 当前价格
+    price: Money,
+    /// 购买数量
+    buy_quantity: u32,
+    /// 当前剩余库存
+    stock: i64,
+    /// 是否可购买，产品或商品已下架时为 false
+    is_available: bool,
+    /// 是否选中结算
+    is_selected: bool,
+}
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CartStore {
+    /// 店铺编号，为空时为平台商品
+    store_code: Option<u32>,
+    /// 店铺名
+    store_name: Option<String>,
+    /// 店铺下的商品
+    items: Vec<CartItem>,
+}
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CartRes {
+    /// 合计多少件，只计算选中且可购买的商品
+    total_quantity: u32,
+    /// 合计多少钱,(元)，只计算选中且可购买的商品
+    total_amount: Money,
+    /// 按店铺分组的商品
+    stores: Vec<CartStore>,
+}
+
+/// 按店铺分组，保持商品原有的顺序
+fn group_by_store(list: Vec<(Option<u32>, Option<String>, CartItem)>) -> Vec<CartStore> {
+    let mut stores: Vec<CartStore> = vec![];
+    for (store_code, store_name, item) in list {
+        match stores.iter_mut().find(|s| s.store_code == store_code) {
+            Some(s) => s.items.push(item),
+            None => stores.push(CartStore {
+                store_code,
+                store_name,
+                items: vec![item],
+            }),
+        }
+    }
+    stores
+}
+
+/// 【购物车】购物车列表
+#[utoipa::path(
+    responses((status = 200, description = "【返回：CartRes】", body = CartRes)),
+)]
+#[get("/mall/order/cart/list")]
+pub async fn mall_order_cart_list(
+    user: AuthUser,
+    _m: RequireModule<ModuleShoppingCart>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    #[derive(Deserialize)]
+    struct CartGet {
+        id: u64,
+        unit_sn: u32,
+        unit_cover: Option<String>,
+        unit_name: Option<String>,
+        product_sn: u32,
+        product_name: String,
+        unit_attr_info: Option<String>,
+        price: Money,
+        buy_quantity: u32,
+        is_selected: u8,
+        stock: i64,
+        unit_status: u8,
+        product_status: u8,
+        unit_is_del: u8,
+        product_is_del: u8,
+        store_code: Option<u32>,
+        store_name: Option<String>,
+    }
+    let list: Vec<CartGet> = mysql_block(move |conn| {
+        my_run_vec(
+            conn,
+            myfind!("ord_shop_cart", {
+            j0: ["unit_sn", "inner", "sku_unit.unit_sn"],
+            j1: ["sku_unit.product_sn", "inner", "spu_product.product_sn"],
+            j2: ["spu_product.store_code", "left", "com_store.code"],
+            p0: ["uid", "=", uid],
+            p1: ["status", "=", ShopCartStatus::PendingPayment as u8],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            order_by: "-created_at",
+            select: "id, unit_sn, unit_cover, unit_name, unit_attr_info, buy_quantity, is_selected,
+                    sku_unit.product_sn, product_name, sku_unit.price,
+                    sku_unit.quantity as stock, sku_unit.status as unit_status,
+                    sku_unit.is_del as unit_is_del, spu_product.status as product_status,
+                    spu_product.is_del as product_is_del, spu_product.store_code,
+                    com_store.name as store_name",
+            }),
+        )
+    })
+    .await?;
+
+    let mut total_quantity = 0;
+    let mut total_amount = Money::ZERO;
+    let list = list
+        .into_iter()
+        .map(|x| {
+            let is_available = x.unit_status == 2
+                && x.product_status == 2
+                && x.unit_is_del == 0
+                && x.product_is_del == 0;
+            let is_selected = x.is_selected == 1;
+            if is_available && is_selected {
+                total_quantity += x.buy_quantity;
+                total_amount += x.price * x.buy_quantity;
+            }
+            (
+                x.store_code,
+                x.store_name,
+                CartItem {
+                    id: x.id,
+                    unit_sn: x.unit_sn,
+                    unit_cover: get_file_url(x.unit_cover).unwrap_or_default(),
+                    unit_name: x.unit_name,
+                    product_sn: x.product_sn,
+                    product_name: x.product_name,
+                    unit_attr_info: x
+                        .unit_attr_info
+                        .and_then(|u| serde_json::from_str::<Vec<UnitAttrInfo>>(&u).ok())
+                        .unwrap_or_default(),
+                    price: x.price,
+                    buy_quantity: x.buy_quantity,
+                    stock: x.stock,
+                    is_available,
+                    is_selected,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Ok(web::Json(Res::success(CartRes {
+        total_quantity,
+        total_amount,
+        stores: group_by_store(list),
+    })))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CartQuantity {
+    /// 购物车的id
+    id: u64,
+    /// 修改后的购买数量
+    buy_quantity: u32,
+}
+/// 【购物车】修改商品数量
+#[utoipa::path(
+    request_body = CartQuantity,
+    responses((status = 200, description = "【请求：CartQuantity】【返回：String】", body = String)),
+)]
+#[put("/mall/order/cart/quantity")]
+pub async fn mall_order_cart_quantity(
+    user: AuthUser,
+    _m: RequireModule<ModuleShoppingCart>,
+    params: web::Json<CartQuantity>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if params.buy_quantity == 0 {
+        return Ok(web::Json(Res::fail("购买数量不能小于1")));
+    }
+    let (id, buy_quantity) = (params.id, params.buy_quantity);
+    let res = mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        let info = match set_shop_cart_quantity(&mut tran, uid, id, buy_quantity) {
+            Ok(d) => d,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+        if info.status == 1 {
+            tran.commit().unwrap();
+            Ok(Res::success("修改成功"))
+        } else {
+            tran.rollback().unwrap();
+            Ok(Res::fail(&info.message))
+        }
+        // ---- 事务结束 ----
+    })
+    .await?;
+    Ok(web::Json(res))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CartSelect {
+    /// 要选中或取消选中的购物车id，不传时为全部
+    ids: Option<Vec<u64>>,
+    /// true 选中，false 取消选中
+    is_selected: bool,
+}
+/// 修改选中状态的 sql，ids 为 id 的个数，None 时为用户的全部商品
+fn cart_select_sql(ids: Option<usize>) -> String {
+    let mut sql =
+        "UPDATE ord_shop_cart SET is_selected = ? WHERE uid = ? AND status = ? AND is_del = 0"
+            .to_string();
+    if let Some(n) = ids {
+        sql += &format!(" AND id IN ({})", in_placeholders(n));
+    }
+    sql
+}
+/// 【购物车】选中、取消选中商品，结算预览和去支付只取选中的商品
+#[utoipa::path(
+    request_body = CartSelect,
+    responses((status = 200, description = "【请求：CartSelect】【返回：String】", body = String)),
+)]
+#[put("/mall/order/cart/select")]
+pub async fn mall_order_cart_select(
+    user: AuthUser,
+    _m: RequireModule<ModuleShoppingCart>,
+    params: web::Json<CartSelect>,
+) -> Result<impl Responder> {
+    if params.ids.as_ref().is_some_and(|x| x.is_empty()) {
+        return Ok(web::Json(Res::fail("请选择商品")));
+    }
+    let mut values: Vec<mysql::Value> = vec![
+        (params.is_selected as u8).into(),
+        user.id.into(),
+        (ShopCartStatus::PendingPayment as u8).into(),
+    ];
+    if let Some(ids) = &params.ids {
+        values.extend(ids.iter().map(|x| (*x).into()));
+    }
+    let sql = cart_select_sql(params.ids.as_ref().map(|x| x.len()));
+    mysql_block(move |conn| my_exec_drop(conn, &sql, values)).await?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CartDel {
+    /// 要删除的购物车id
+    ids: Vec<u64>,
+}
+/// 【购物车】删除商品
+#[utoipa::path(
+    request_body = CartDel,
+    responses((status = 200, description = "【请求：CartDel】【返回：String】", body = String)),
+)]
+#[put("/mall/order/cart/del")]
+pub async fn mall_order_cart_del(
+    user: AuthUser,
+    _m: RequireModule<ModuleShoppingCart>,
+    params: web::Json<CartDel>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if params.ids.is_empty() {
+        return Ok(web::Json(Res::fail("请选择要删除的商品")));
+    }
+    let ids = params.into_inner().ids;
+    mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        if let Err(e) = del_shop_cart_units(&mut tran, uid, Some(&ids)) {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+        tran.commit().unwrap();
+        // ---- 事务结束 ----
+        Ok(())
+    })
+    .await?;
+    Ok(web::Json(Res::success("删除成功")))
+}
+
+/// 【购物车】清空购物车
+#[utoipa::path(
+    responses((status = 200, description = "【返回：String】", body = String)),
+)]
+#[put("/mall/order/cart/clear")]
+pub async fn mall_order_cart_clear(
+    user: AuthUser,
+    _m: RequireModule<ModuleShoppingCart>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        if let Err(e) = del_shop_cart_units(&mut tran, uid, None) {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+        tran.commit().unwrap();
+        // ---- 事务结束 ----
+        Ok(())
+    })
+    .await?;
+    Ok(web::Json(Res::success("清空成功")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn item(id: u64) -> CartItem {
+        CartItem {
+            id,
+            unit_sn: 10000 + id as u32,
+            unit_cover: String::new(),
+            unit_name: None,
+            product_sn: 10000,
+            product_name: String::new(),
+            unit_attr_info: vec![],
+            price: Money::from_cent(100),
+            buy_quantity: 1,
+            stock: 10,
+            is_available: true,
+            is_selected: true,
+        }
+    }
+
+    #[test]
+    fn test_group_by_store() {
+        let stores = group_by_store(vec![
+            (Some(1), Some("A".to_string()), item(1)),
+            (None, None, item(2)),
+            (Some(1), Some("A".to_string()), item(3)),
+            (Some(2), Some("B".to_string()), item(4)),
+        ]);
+        assert_eq!(stores.len(), 3);
+        assert_eq!(stores[0].store_code, Some(1));
+        assert_eq!(
+            stores[0].items.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(stores[1].store_code, None);
+        assert_eq!(stores[2].items[0].id, 4);
+    }
+
+    #[test]
+    fn test_cart_select_sql() {
+        assert_eq!(
+            cart_select_sql(None),
+            "UPDATE ord_shop_cart SET is_selected = ? WHERE uid = ? AND status = ? AND is_del = 0"
+        );
+        assert!(cart_select_sql(Some(3)).ends_with("AND is_del = 0 AND id IN (?,?,?)"));
+    }
+}
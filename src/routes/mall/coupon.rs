@@ -1,14 +1,17 @@
-use actix_web::{Responder, Result, error, get, post, web};
-use mysql_quick::{MY_EXCLUSIVE_LOCK, TxOpts, myfind, myget, myset, myupdate};
+use actix_web::{Responder, Result, get, post, web};
+use mysql_quick::{TxOpts, myfind};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::common::Money;
 use crate::common::types::NormalStatus;
-use crate::db::{my_run_tran_drop, my_run_tran_vec, my_run_vec, mysql_conn};
-use crate::middleware::AuthUser;
+use crate::db::{my_run_vec, mysql_conn};
+use crate::middleware::{AuthUser, ModuleCoupon, RequireModule};
 use crate::routes::Res;
+use crate::routes::utils_set::coupon_campaign_set::{
+    CODE_LEN, grant_coupon, normalize_redeem_code, redeem_coupon_code,
+};
 use crate::utils::time::{NowTimeType, get_now_time};
-use crate::utils::utils::log_err;
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
 pub struct CouponReceive {
@@ -23,96 +26,69 @@ pub struct CouponReceive {
 #[post("/mall/coupon/receive")]
 pub async fn mall_coupon_receive(
     user: AuthUser,
+    _m: RequireModule<ModuleCoupon>,
     params: web::Json<CouponReceive>,
 ) -> Result<impl Responder> {
     let uid = user.id;
     let mut conn = mysql_conn()?;
     // ---- 事务开始 ----
     let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
-    let sql_cou = myget!(
-        "pmt_coupon",
-        params.coupon_id,
-        "coupon_num,expire_time,status,is_del"
-    ) + MY_EXCLUSIVE_LOCK;
-    #[derive(Deserialize, Debug)]
-    struct CouponGet {
-        coupon_num: u32,
-        expire_time: Option<String>,
-        status: i8,
-        is_del: u8,
-    }
-    let coupon: Vec<CouponGet> = match my_run_tran_vec(&mut tran, sql_cou) {
-        Ok(d) => d,
-        Err(e) => {
+    // 检查优惠券是否可领、每人限领张数，并扣减数量
+    match grant_coupon(&mut tran, uid, params.coupon_id, 1, None) {
+        Ok(Ok(_)) => (),
+        Ok(Err(r)) => {
             tran.rollback().unwrap();
-            return Err(error::ErrorInternalServerError(log_err(&e, &params)));
+            return Ok(web::Json(Res::fail(&r.to_string())));
         }
-    };
-    if coupon.len() == 0 {
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail("优惠券不存在")));
-    }
-    if coupon[0].is_del == 1 {
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail("优惠券不存在")));
-    }
-    if let Some(time) = coupon[0].expire_time.clone() {
-        if time <= get_now_time(NowTimeType::DateTime) {
-            tran.rollback().unwrap();
-            return Ok(web::Json(Res::fail("优惠券已过期")));
-        }
-    }
-    if coupon[0].status != NormalStatus::Online as i8 {
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail("优惠券已下架")));
-    }
-    if coupon[0].coupon_num <= 0 {
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail("优惠券已被领完了")));
-    }
-    // 查找当前用户，有没有已经领取过同一优惠券
-    let sql_usr = myfind!("usr_coupon", {
-        p0: ["uid", "=", uid],
-        p1: ["coupon_id", "=", params.coupon_id],
-        r: "p0 && p1",
-    }) + MY_EXCLUSIVE_LOCK;
-    let usr_cou: Vec<serde_json::Value> = match my_run_tran_vec(&mut tran, sql_usr) {
-        Ok(d) => d,
         Err(e) => {
             tran.rollback().unwrap();
-            return Err(error::ErrorInternalServerError(log_err(&e, &params)));
+            return Err(e);
         }
-    };
-    if usr_cou.len() > 0 {
-        // 已经领取过，则不能领了
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail("你已领取过了")));
     }
-    // 用户没有领取过，则领取
-    match my_run_tran_drop(
-        &mut tran,
-        myupdate!("pmt_coupon", params.coupon_id, { "coupon_num": ["incr", -1] }),
-    ) {
-        Ok(_) => (),
-        Err(e) => {
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+
+    Ok(web::Json(Res::success("领取成功")))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CouponRedeem {
+    /// 兑换码，不区分大小写
+    code: String,
+}
+/// 【优惠券】兑换码兑换优惠券
+#[utoipa::path(
+    request_body = CouponRedeem,
+    responses((status = 200, description = "【请求：CouponRedeem】【返回：String】", body = String)),
+)]
+#[post("/mall/coupon/redeem")]
+pub async fn mall_coupon_redeem(
+    user: AuthUser,
+    _m: RequireModule<ModuleCoupon>,
+    params: web::Json<CouponRedeem>,
+) -> Result<impl Responder> {
+    let code = normalize_redeem_code(&params.code);
+    if code.len() != CODE_LEN {
+        return Ok(web::Json(Res::fail("兑换码不存在")));
+    }
+    let mut conn = mysql_conn()?;
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    match redeem_coupon_code(&mut tran, user.id, &code) {
+        Ok(Ok(_)) => (),
+        Ok(Err(msg)) => {
             tran.rollback().unwrap();
-            return Err(error::ErrorInternalServerError(log_err(&e, &params)));
+            return Ok(web::Json(Res::fail(&msg)));
         }
-    }
-    match my_run_tran_drop(
-        &mut tran,
-        myset!("usr_coupon", { "uid": uid, "coupon_id": params.coupon_id }),
-    ) {
-        Ok(_) => (),
         Err(e) => {
             tran.rollback().unwrap();
-            return Err(error::ErrorInternalServerError(log_err(&e, &params)));
+            return Err(e);
         }
     }
     tran.commit().unwrap();
     // ---- 事务结束 ----
 
-    Ok(web::Json(Res::success("领取成功")))
+    Ok(web::Json(Res::success("兑换成功")))
 }
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
@@ -122,13 +98,19 @@ pub struct CouponRes {
     /// 优惠券名称
     coupon_name: String,
     /// 优惠券优惠金额
-    reduce_amount: Option<f64>,
+    reduce_amount: Option<Money>,
     /// 优惠券折扣额度
     discount: Option<f64>,
     /// 剩余数量
     coupon_num: u32,
-    /// 过期时间
+    /// 过期时间，领取后 N 天内有效的为领取的截止时间
     expire_time: Option<String>,
+    /// 有效期类型，1 固定时间，2 领取后 N 天内有效
+    valid_type: u8,
+    /// 固定时间的开始时间，为空时领取后即可用
+    start_time: Option<String>,
+    /// 领取后多少天内有效
+    valid_days: Option<u32>,
     /// 优惠券条件id
     coupon_condition_id: u32,
     /// 优惠券条件标题
@@ -139,17 +121,20 @@ pub struct CouponRes {
     responses((status = 200, description = "【返回：CouponRes[]】", body = Vec<CouponRes>))
 )]
 #[get("/mall/coupon/list")]
-pub async fn mall_coupon_list() -> Result<impl Responder> {
+pub async fn mall_coupon_list(_m: RequireModule<ModuleCoupon>) -> Result<impl Responder> {
     let mut conn = mysql_conn()?;
 
     #[derive(Deserialize)]
     pub struct CouponGet {
         id: u64,
         coupon_name: String,
-        reduce_amount: Option<String>,
+        reduce_amount: Option<Money>,
         discount: Option<String>,
         coupon_num: u32,
         expire_time: Option<String>,
+        valid_type: u8,
+        start_time: Option<String>,
+        valid_days: Option<u32>,
         coupon_condition_id: u32,
         coupon_condition_title: String,
     }
@@ -160,7 +145,7 @@ pub async fn mall_coupon_list() -> Result<impl Responder> {
         p1: ["status", "=", NormalStatus::Online as u8],
         p2: ["expire_time", ">", &now_date], // 要找没过期的
         r: "p0 && p1 && p2",
-        select: "id, coupon_name, reduce_amount, discount, coupon_num, expire_time, coupon_condition_id, pmt_coupon_condition.title as coupon_condition_title",
+        select: "id, coupon_name, reduce_amount, discount, coupon_num, expire_time, valid_type, start_time, valid_days, coupon_condition_id, pmt_coupon_condition.title as coupon_condition_title",
     });
 
     let list: Vec<CouponGet> = my_run_vec(&mut conn, sql)?;
@@ -171,11 +156,7 @@ pub async fn mall_coupon_list() -> Result<impl Responder> {
             return CouponRes {
                 id: x.id,
                 coupon_name: x.coupon_name,
-                reduce_amount: if let Some(d) = x.reduce_amount {
-                    Some(d.parse::<f64>().unwrap())
-                } else {
-                    None
-                },
+                reduce_amount: x.reduce_amount,
                 discount: if let Some(d) = x.discount {
                     Some(d.parse::<f64>().unwrap())
                 } else {
@@ -183,6 +164,9 @@ pub async fn mall_coupon_list() -> Result<impl Responder> {
                 },
                 coupon_num: x.coupon_num,
                 expire_time: x.expire_time.clone(),
+                valid_type: x.valid_type,
+                start_time: x.start_time,
+                valid_days: x.valid_days,
                 coupon_condition_id: x.coupon_condition_id,
                 coupon_condition_title: x.coupon_condition_title,
             };
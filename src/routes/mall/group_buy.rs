@@ -0,0 +1,348 @@
+use actix_web::{Responder, Result, error, get, post, web};
+use mysql_quick::{TxOpts, myfind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::{GroupMemberStatus, NormalStatus};
+use crate::db::{my_run_vec, mysql_conn};
+use crate::middleware::{AuthUser, ModuleGroupBuy, RequireModule};
+use crate::routes::Res;
+use crate::routes::utils_set::group_set::add_group_buy_to_buy_now;
+use crate::utils::time::{NowTimeType, get_now_time};
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct GroupBuyRes {
+    /// 拼团活动id
+    id: u32,
+    /// 活动名
+    title: String,
+    /// 商品编号
+    unit_sn: u32,
+    /// 商品名
+    unit_name: Option<String>,
+    /// 商品封面图
+    unit_cover: Option<String>,
+    /// 商品原价
+    original_price: Option<Money>,
+    /// 拼团价
+    price: Money,
+    /// 成团人数
+    group_size: u32,
+    /// 开团后多少小时内成团
+    group_hours: u32,
+    /// 开始时间
+    start_time: String,
+    /// 结束时间
+    end_time: String,
+}
+/// 【拼团】进行中的拼团活动
+#[utoipa::path(
+    responses((status = 200, description = "【返回：GroupBuyRes[]】", body = Vec<GroupBuyRes>))
+)]
+#[get("/mall/group_buy/list")]
+pub async fn mall_group_buy_list(_m: RequireModule<ModuleGroupBuy>) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let now = get_now_time(NowTimeType::DateTime);
+    let list: Vec<GroupBuyRes> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_group_buy", {
+            j0: ["unit_sn", "inner", "sku_unit.unit_sn"],
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", NormalStatus::Online as u8],
+            p2: ["start_time", "<=", &now],
+            p3: ["end_time", ">", &now],
+            r: "p0 && p1 && p2 && p3",
+            order_by: "end_time",
+            select: "id,title,unit_sn,sku_unit.unit_name,sku_unit.unit_cover,sku_unit.price as original_price,price,group_size,group_hours,start_time,end_time",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(list)))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct GroupBuyAdd {
+    /// 拼团活动id
+    group_buy_id: u32,
+    /// 参团时传团的分享码，不传则为开团
+    share_code: Option<String>,
+    /// 购买数量
+    buy_quantity: u32,
+}
+/// 【拼团】开团或参团，成功后按立即购买的方式下单
+#[utoipa::path(
+    request_body = GroupBuyAdd,
+    responses((status = 200, description = "【请求：GroupBuyAdd】【返回：String】", body = String)),
+)]
+#[post("/mall/group_buy/add")]
+pub async fn mall_group_buy_add(
+    user: AuthUser,
+    _m: RequireModule<ModuleGroupBuy>,
+    params: web::Json<GroupBuyAdd>,
+) -> Result<impl Responder> {
+    if params.buy_quantity == 0 {
+        return Ok(web::Json(Res::fail("购买数量不能小于1")));
+    }
+    let share_code = params
+        .share_code
+        .as_deref()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty());
+    let mut conn = mysql_conn()?;
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    let add_info = match add_group_buy_to_buy_now(
+        &mut tran,
+        user.id,
+        params.group_buy_id,
+        share_code,
+        params.buy_quantity,
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    if add_info.status == 1 {
+        tran.commit().unwrap();
+    } else {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail(&add_info.message)));
+    }
+    // ---- 事务结束 ----
+    Ok(web::Json(Res::success("添加成功")))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct GroupMemberRes {
+    /// 昵称
+    nickname: Option<String>,
+    /// 头像
+    avatar_url: Option<String>,
+    /// 是否为团长
+    is_leader: bool,
+    /// 参团时间
+    created_at: String,
+}
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct GroupRes {
+    /// 团id
+    id: u64,
+    /// 分享码
+    share_code: String,
+    /// 拼团活动id
+    group_buy_id: u32,
+    /// 活动名
+    title: String,
+    /// 商品编号
+    unit_sn: u32,
+    /// 商品名
+    unit_name: Option<String>,
+    /// 商品封面图
+    unit_cover: Option<String>,
+    /// 拼团价
+    price: Money,
+    /// 成团人数
+    group_size: u32,
+    /// 已支付的人数
+    paid: u32,
+    /// 还差几人成团
+    remain: u32,
+    /// 团状态 0 待开团（团长未支付），1 拼团中，2 已成团，3 拼团失败
+    status: u8,
+    /// 成团截止时间
+    expire_time: Option<String>,
+    /// 成团时间
+    success_time: Option<String>,
+    /// 已支付的成员
+    members: Vec<GroupMemberRes>,
+}
+#[derive(Deserialize)]
+struct GroupGet {
+    id: u64,
+    share_code: String,
+    group_buy_id: u32,
+    title: String,
+    unit_sn: u32,
+    unit_name: Option<String>,
+    unit_cover: Option<String>,
+    price: Money,
+    group_size: u32,
+    paid: u32,
+    status: u8,
+    expire_time: Option<String>,
+    success_time: Option<String>,
+}
+/// 【拼团】通过分享码查看团的进度
+#[utoipa::path(
+    responses((status = 200, description = "【返回：GroupRes】", body = GroupRes)),
+    params(("share_code", description="团的分享码"))
+)]
+#[get("/mall/group_buy/group/{share_code}")]
+pub async fn mall_group_buy_group(
+    _user: AuthUser,
+    _m: RequireModule<ModuleGroupBuy>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let share_code = path.into_inner();
+    let mut conn = mysql_conn()?;
+    let list: Vec<GroupGet> = my_run_vec(
+        &mut conn,
+        myfind!("ord_group", {
+            j0: ["group_buy_id", "inner", "pmt_group_buy.id"],
+            j1: ["pmt_group_buy.unit_sn", "left", "sku_unit.unit_sn"],
+            p0: ["share_code", "=", &share_code],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id,share_code,group_buy_id,pmt_group_buy.title,pmt_group_buy.unit_sn,sku_unit.unit_name,sku_unit.unit_cover,pmt_group_buy.price,group_size,paid,status,expire_time,success_time",
+        }),
+    )?;
+    let Some(group) = list.into_iter().next() else {
+        return Ok(web::Json(Res::fail("团不存在")));
+    };
+
+    #[derive(Deserialize)]
+    struct MemberGet {
+        nickname: Option<String>,
+        avatar_url: Option<String>,
+        is_leader: u8,
+        created_at: String,
+    }
+    let members: Vec<MemberGet> = my_run_vec(
+        &mut conn,
+        myfind!("ord_group_member", {
+            j0: ["uid", "inner", "usr_silent.id"],
+            p0: ["group_id", "=", group.id],
+            p1: ["status", "=", GroupMemberStatus::Paid as u8],
+            r: "p0 && p1",
+            order_by: "-is_leader,created_at",
+            select: "usr_silent.nickname,usr_silent.avatar_url,is_leader,created_at",
+        }),
+    )?;
+    let members = members
+        .into_iter()
+        .map(|x| GroupMemberRes {
+            nickname: x.nickname,
+            avatar_url: x.avatar_url,
+            is_leader: x.is_leader == 1,
+            created_at: x.created_at,
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(GroupRes {
+        id: group.id,
+        share_code: group.share_code,
+        group_buy_id: group.group_buy_id,
+        title: group.title,
+        unit_sn: group.unit_sn,
+        unit_name: group.unit_name,
+        unit_cover: group.unit_cover,
+        price: group.price,
+        group_size: group.group_size,
+        paid: group.paid,
+        remain: group.group_size.saturating_sub(group.paid),
+        status: group.status,
+        expire_time: group.expire_time,
+        success_time: group.success_time,
+        members,
+    })))
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct MyGroupRes {
+    /// 团id
+    group_id: u64,
+    /// 分享码
+    share_code: String,
+    /// 活动名
+    title: String,
+    /// 商品名
+    unit_name: Option<String>,
+    /// 商品封面图
+    unit_cover: Option<String>,
+    /// 拼团价
+    price: Money,
+    /// 成团人数
+    group_size: u32,
+    /// 已支付的人数
+    paid: u32,
+    /// 团状态 0 待开团（团长未支付），1 拼团中，2 已成团，3 拼团失败
+    status: u8,
+    /// 成团截止时间
+    expire_time: Option<String>,
+    /// 我的订单号
+    order_sn: String,
+    /// 是否为团长
+    is_leader: bool,
+    /// 参团时间
+    created_at: String,
+}
+/// 【拼团】我参加的团
+#[utoipa::path(
+    responses((status = 200, description = "【返回：MyGroupRes[]】", body = Vec<MyGroupRes>)),
+    params(("page", description="第几页"))
+)]
+#[get("/mall/group_buy/my/{page}")]
+pub async fn mall_group_buy_my(
+    user: AuthUser,
+    _m: RequireModule<ModuleGroupBuy>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let page: u32 = path.to_owned().parse().map_err(error::ErrorBadRequest)?;
+    let mut conn = mysql_conn()?;
+
+    #[derive(Deserialize)]
+    struct MyGroupGet {
+        group_id: u64,
+        share_code: String,
+        title: String,
+        unit_name: Option<String>,
+        unit_cover: Option<String>,
+        price: Money,
+        group_size: u32,
+        paid: u32,
+        status: u8,
+        expire_time: Option<String>,
+        order_sn: String,
+        is_leader: u8,
+        created_at: String,
+    }
+    // 取消支付的不显示
+    let list: Vec<MyGroupGet> = my_run_vec(
+        &mut conn,
+        myfind!("ord_group_member", {
+            j0: ["group_id", "inner", "ord_group.id"],
+            j1: ["ord_group.group_buy_id", "inner", "pmt_group_buy.id"],
+            j2: ["pmt_group_buy.unit_sn", "left", "sku_unit.unit_sn"],
+            p0: ["uid", "=", user.id],
+            p1: ["status", "!=", GroupMemberStatus::Cancelled as u8],
+            r: "p0 && p1",
+            page: page,
+            limit: 20,
+            order_by: "-created_at",
+            select: "group_id,ord_group.share_code,pmt_group_buy.title,sku_unit.unit_name,sku_unit.unit_cover,pmt_group_buy.price,ord_group.group_size,ord_group.paid,ord_group.status,ord_group.expire_time,order_sn,is_leader,created_at",
+        }),
+    )?;
+    let list: Vec<MyGroupRes> = list
+        .into_iter()
+        .map(|x| MyGroupRes {
+            group_id: x.group_id,
+            share_code: x.share_code,
+            title: x.title,
+            unit_name: x.unit_name,
+            unit_cover: x.unit_cover,
+            price: x.price,
+            group_size: x.group_size,
+            paid: x.paid,
+            status: x.status,
+            expire_time: x.expire_time,
+            order_sn: x.order_sn,
+            is_leader: x.is_leader == 1,
+            created_at: x.created_at,
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(list)))
+}
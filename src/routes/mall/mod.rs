@@ -3,6 +3,8 @@ use utoipa::ToSchema;
 
 mod order;
 pub use order::*;
+mod cart;
+pub use cart::*;
 mod coupon;
 pub use coupon::*;
 mod product;
@@ -21,6 +23,14 @@ mod store;
 pub use store::*;
 mod write_off;
 pub use write_off::*;
+mod seckill;
+pub use seckill::*;
+mod group_buy;
+pub use group_buy::*;
+mod review;
+pub use review::*;
+mod after_sale;
+pub use after_sale::*;
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
 pub struct UnitAttrInfo {
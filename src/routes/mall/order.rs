@@ -3,24 +3,28 @@ use mysql_quick::{TxOpts, myfind, myget};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use utoipa::{IntoParams, ToSchema};
-use wx_pay::{Amount, Jsapi, Payer, WxPayData};
+use wx_pay::WxPayData;
 
-use crate::common::UNIT_START_SN;
 use crate::common::types::{
-    DeliveryType, OrderItemStatus, OrderPayStatus, PayType, ShopCartStatus, TranType,
-    WriteOffStatus,
+    AfterSaleStatus, DeliveryType, OrderItemStatus, OrderPayStatus, PayType, ShopCartStatus,
+    TranType, WriteOffStatus,
 };
+use crate::common::{Money, UNIT_START_SN};
 use crate::control::app_data::AppData;
-use crate::control::wx_info::wx_pay_init;
-use crate::db::{my_run_tran_vec, my_run_vec, mysql_conn};
-use crate::middleware::AuthUser;
+use crate::control::payment::{PrepayReq, payment};
+use crate::db::{my_run_tran_vec, my_run_vec, mysql_block, mysql_conn};
+use crate::middleware::{AuthUser, Module, ModuleShoppingCart, RequireModule, check_module};
 use crate::routes::Res;
+use crate::routes::utils_set::agent_set::do_order_agent_split;
+use crate::routes::utils_set::coupon_set::CouponSelect;
+use crate::routes::utils_set::discount_set::{OrderDiscount, get_order_discounts};
+use crate::routes::utils_set::group_set::{group_order_paid, join_group_on_order};
 use crate::routes::utils_set::mall_set::*;
 use crate::routes::utils_set::pocket_set::pocket_money_sub;
 use crate::routes::utils_set::sales_set::do_order_sale_split;
 use crate::routes::utils_set::write_off_item::add_write_off;
 use crate::utils::files::get_file_url;
-use crate::utils::utils::{keep_uint, log_err};
+use crate::utils::utils::log_err;
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
 pub struct AddShopCart {
@@ -37,6 +41,7 @@ pub struct AddShopCart {
 #[post("/mall/order/add/shop_cart")]
 pub async fn mall_order_add_shop_cart(
     user: AuthUser,
+    _m: RequireModule<ModuleShoppingCart>,
     params: web::Json<AddShopCart>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -48,30 +53,34 @@ pub async fn mall_order_add_shop_cart(
         return Ok(web::Json(Res::fail("购买数量不能小于1")));
     }
 
-    let mut conn = mysql_conn()?;
-    // ---- 事务开始 ----
-    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
-    let add_info = match add_unit_to_shop_cart(
-        &mut tran,
-        uid,
-        params.unit_sn,
-        params.buy_quantity,
-        ShopCartStatus::PendingPayment,
-    ) {
-        Ok(d) => d,
-        Err(e) => {
+    let (unit_sn, buy_quantity) = (params.unit_sn, params.buy_quantity);
+    let res = mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        let add_info = match add_unit_to_shop_cart(
+            &mut tran,
+            uid,
+            unit_sn,
+            buy_quantity,
+            ShopCartStatus::PendingPayment,
+        ) {
+            Ok(d) => d,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+        if add_info.status == 1 {
+            tran.commit().unwrap();
+            Ok(Res::success("添加购物车成功"))
+        } else {
             tran.rollback().unwrap();
-            return Err(e);
+            Ok(Res::fail(&add_info.message))
         }
-    };
-    if add_info.status == 1 {
-        tran.commit().unwrap();
-    } else {
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail(&add_info.message)));
-    }
-    // ---- 事务结束 ----
-    Ok(web::Json(Res::success("添加购物车成功")))
+        // ---- 事务结束 ----
+    })
+    .await?;
+    Ok(web::Json(res))
 }
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
@@ -100,30 +109,34 @@ pub async fn mall_order_add_buy_now(
         return Ok(web::Json(Res::fail("购买数量不能小于1")));
     }
 
-    let mut conn = mysql_conn()?;
-    // ---- 事务开始 ----
-    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
-    let add_info = match add_unit_to_shop_cart(
-        &mut tran,
-        uid,
-        params.unit_sn,
-        params.buy_quantity,
-        ShopCartStatus::BuyNow,
-    ) {
-        Ok(d) => d,
-        Err(e) => {
+    let (unit_sn, buy_quantity) = (params.unit_sn, params.buy_quantity);
+    let res = mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+        let add_info = match add_unit_to_shop_cart(
+            &mut tran,
+            uid,
+            unit_sn,
+            buy_quantity,
+            ShopCartStatus::BuyNow,
+        ) {
+            Ok(d) => d,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+        if add_info.status == 1 {
+            tran.commit().unwrap();
+            Ok(Res::success("添加立即购买成功"))
+        } else {
             tran.rollback().unwrap();
-            return Err(e);
+            Ok(Res::fail(&add_info.message))
         }
-    };
-    if add_info.status == 1 {
-        tran.commit().unwrap();
-    } else {
-        tran.rollback().unwrap();
-        return Ok(web::Json(Res::fail(&add_info.message)));
-    }
-    // ---- 事务结束 ----
-    Ok(web::Json(Res::success("添加立即购买成功")))
+        // ---- 事务结束 ----
+    })
+    .await?;
+    Ok(web::Json(res))
 }
 
 #[derive(Serialize, Debug, Deserialize, IntoParams, ToSchema, Clone)]
@@ -132,8 +145,13 @@ pub struct MakePrePare {
     unit_sns: String,
     /// 购买类型：pending 为购物车的待结算，buy_now 为立即购买方式
     buy_type: String,
-    /// 优惠券id
-    coupon_id: Option<u32>,
+    /// 优惠券id，多个用逗号分隔，每个店铺一张店铺券，一张平台券。
+    /// 不传时自动选择优惠最多的优惠券，为空或 0 时不使用优惠券
+    coupon_ids: Option<String>,
+    /// 用户地址id，按地址计算运费，不传时按默认地址
+    usr_address_id: Option<u64>,
+    /// 用户选择的物流方式，不需要收货地址的（如到店自提）不计算运费。不传时按需要物流计算
+    delivery_type: Option<String>,
 }
 /// 【订单】生成预览订单
 #[utoipa::path(
@@ -164,27 +182,67 @@ pub async fn mall_order_make_prepare(
     {
         return Ok(web::Json(Res::fail("购买状态错误")));
     }
-    let mut conn = mysql_conn()?;
-    // ---- 事务开始 ----
-    let mut tran = conn
-        .start_transaction(TxOpts::default())
-        .map_err(|e| error::ErrorInternalServerError(log_err(&e, &params)))?;
-    let prepare = match get_order_prepare(
-        &mut tran,
-        uid,
-        &unit_sn_list,
-        &buy_type,
-        params.coupon_id,
-        false,
-    ) {
-        Ok(p) => p,
-        Err(e) => {
-            tran.rollback().unwrap();
-            return Err(e);
+    // 购物车结算、使用优惠券，需要对应的功能模块已开启
+    if buy_type == ShopCartStatus::PendingPayment {
+        check_module(Module::ShoppingCart)?;
+    }
+    // 指定了优惠券的，需要优惠券功能已开启；未开启时不返回优惠券
+    let coupon_ids = params.coupon_ids.as_deref().map(|x| {
+        x.split(",")
+            .filter_map(|id| id.trim().parse::<u32>().ok())
+            .filter(|id| *id > 0)
+            .collect::<Vec<u32>>()
+    });
+    let coupon = match coupon_ids {
+        Some(ids) if !ids.is_empty() => {
+            check_module(Module::Coupon)?;
+            CouponSelect::Ids(ids)
         }
+        _ if check_module(Module::Coupon).is_err() => CouponSelect::Skip,
+        Some(_) => CouponSelect::None,
+        None => CouponSelect::Best,
     };
-    tran.commit().unwrap();
-    // ---- 事务结束 ----
+    let need_addr = params
+        .delivery_type
+        .as_ref()
+        .is_none_or(|d| is_need_address(&d.into()));
+    let params = params.into_inner();
+    let prepare = mysql_block(move |conn| {
+        // ---- 事务开始 ----
+        let mut tran = conn
+            .start_transaction(TxOpts::default())
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, &params)))?;
+        let ship_to = if need_addr {
+            match get_prepare_address(&mut tran, uid, params.usr_address_id) {
+                Ok(a) => Some(a),
+                Err(e) => {
+                    tran.rollback().unwrap();
+                    return Err(e);
+                }
+            }
+        } else {
+            None
+        };
+        let prepare = match get_order_prepare(
+            &mut tran,
+            uid,
+            &unit_sn_list,
+            &buy_type,
+            coupon,
+            false,
+            ship_to.as_ref(),
+        ) {
+            Ok(p) => p,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+        tran.commit().unwrap();
+        // ---- 事务结束 ----
+        Ok(prepare)
+    })
+    .await?;
     Ok(web::Json(Res::success(prepare)))
 }
 
@@ -194,8 +252,8 @@ pub struct MakePay {
     pub unit_sns: Vec<u32>,
     /// 购买类型：pending 为购物车的待结算，buy_now 为立即购买方式
     pub buy_type: String,
-    /// 优惠券id
-    pub coupon_id: Option<u32>,
+    /// 优惠券id，为预览订单返回的 coupon_ids，不传或为空时不使用优惠券
+    pub coupon_ids: Option<Vec<u32>>,
     /// 用户备注
     pub notes: Option<String>,
     /// 用户地址id
@@ -217,6 +275,18 @@ pub struct WxPayInfo {
     nonce_str: String,
     time_stamp: String,
 }
+impl From<WxPayData> for WxPayInfo {
+    fn from(data: WxPayData) -> Self {
+        WxPayInfo {
+            app_id: data.app_id,
+            sign_type: data.sign_type,
+            pay_sign: data.pay_sign,
+            package: data.package,
+            nonce_str: data.nonce_str,
+            time_stamp: data.time_stamp,
+        }
+    }
+}
 /// 客户端发起支付返回的参数信息
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
 pub struct MakePayRes {
@@ -266,16 +336,47 @@ pub async fn mall_order_make_pay(
     if pay_type == PayType::UnknownPay {
         return Ok(web::Json(Res::fail("支付方式错误")));
     }
+    // 购物车结算、使用优惠券、零钱支付，需要对应的功能模块已开启
+    if buy_type == ShopCartStatus::PendingPayment {
+        check_module(Module::ShoppingCart)?;
+    }
+    let coupon_ids: Vec<u32> = params
+        .coupon_ids
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|id| *id > 0)
+        .collect();
+    let coupon = if coupon_ids.is_empty() {
+        CouponSelect::Skip
+    } else {
+        check_module(Module::Coupon)?;
+        CouponSelect::Ids(coupon_ids)
+    };
+    if pay_type == PayType::PocketPay {
+        check_module(Module::PocketMoney)?;
+    }
 
     // ---- 事务开始 ----
     let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    // 查找用户地址，按地址计算运费
+    let user_addr =
+        match get_user_address_or_none(&mut tran, params.usr_address_id, &params.delivery_type) {
+            Ok(d) => d,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+    let ship_to = is_need_address(&params.delivery_type).then_some(&user_addr);
     let prepare = match get_order_prepare(
         &mut tran,
         uid,
         &params.unit_sns,
         &buy_type,
-        params.coupon_id,
+        coupon,
         true,
+        ship_to,
     ) {
         Ok(p) => p,
         Err(e) => {
@@ -287,15 +388,6 @@ pub async fn mall_order_make_pay(
         tran.rollback().unwrap();
         return Ok(web::Json(Res::fail("没有待结算")));
     }
-    // 查找用户地址
-    let user_addr =
-        match get_user_address_or_none(&mut tran, params.usr_address_id, &params.delivery_type) {
-            Ok(d) => d,
-            Err(e) => {
-                tran.rollback().unwrap();
-                return Err(e);
-            }
-        };
 
     // 生成一个总订单
     let (order_sn, pay_des) = match create_order(
@@ -317,20 +409,36 @@ pub async fn mall_order_make_pay(
         }
     };
 
-    // 如果用户使用了优惠券，则修改为已使用
-    if prepare.is_coupon_used {
-        if let Some(usr_c_id) = prepare.usr_coupon_id {
-            match upd_coupon_status(&mut tran, usr_c_id) {
-                Ok(_) => (),
-                Err(e) => {
-                    tran.rollback().unwrap();
-                    return Err(e);
-                }
+    // 拼团商品，开团或加入团
+    let is_group = prepare.user_buy.iter().any(|x| x.group_buy_id.is_some());
+    if is_group {
+        let joined = check_module(Module::GroupBuy).and_then(|_| {
+            join_group_on_order(
+                &mut tran,
+                uid,
+                &prepare.user_buy,
+                prepare.is_coupon_used,
+                &order_sn,
+            )
+        });
+        match joined {
+            Ok(r) if r.status == 1 => (),
+            Ok(r) => {
+                tran.rollback().unwrap();
+                return Ok(web::Json(Res::fail(&r.message)));
             }
-        } else {
-            // 对不上，就回滚
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        }
+    }
+
+    // 如果用户使用了优惠券，则修改为已使用
+    for usr_c_id in &prepare.usr_coupon_ids {
+        if let Err(e) = upd_coupon_status(&mut tran, *usr_c_id) {
             tran.rollback().unwrap();
-            return Ok(web::Json(Res::fail("未找到相关优惠券")));
+            return Err(e);
         }
     }
 
@@ -351,7 +459,7 @@ pub async fn mall_order_make_pay(
                 "params": serde_json::to_string(&params).unwrap(),
             });
             // 用户零钱支付
-            if prepare.pay_amount < 0. {
+            if prepare.pay_amount.is_negative() {
                 return Err(error::ErrorBadRequest("购买金额错误"));
             }
             // 零钱增减，同时有交易记录添加
@@ -369,6 +477,18 @@ pub async fn mall_order_make_pay(
                     return Err(e);
                 }
             };
+            // 拼团订单，改为已支付待成团，成团后再分成
+            if is_group {
+                if let Err(e) = group_order_paid(&mut tran, &order_sn, None) {
+                    tran.rollback().unwrap();
+                    return Err(e);
+                }
+                tran.commit().unwrap();
+                return Ok(web::Json(Res::success(MakePayRes {
+                    pay_type: PayType::PocketPay,
+                    wx_pay: None,
+                })));
+            }
             // 进行销售分成处理
             match do_order_sale_split(&mut tran, &order_sn, uid, pay_type.clone()) {
                 Ok(_) => (),
@@ -377,6 +497,11 @@ pub async fn mall_order_make_pay(
                     return Err(e);
                 }
             };
+            // 进行区域代理分成
+            if let Err(e) = do_order_agent_split(&mut tran, &order_sn) {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
             // 修改订单状态 为 已支付
             match upd_order_status(&mut tran, &order_sn, OrderPayStatus::Paid, None, None) {
                 Ok(d) => d,
@@ -405,25 +530,21 @@ pub async fn mall_order_make_pay(
         PayType::WxPay => {
             // 如果是微信支付，则进行微信支付操作
             // 发起微信支付
-            let wxpay = wx_pay_init();
-            wxinfo = match wxpay
-                .jsapi(&Jsapi {
+            wxinfo = match payment()
+                .prepay(&PrepayReq {
                     description: pay_des,
                     out_trade_no: order_sn.clone(),
-                    amount: Amount {
-                        total: keep_uint(prepare.pay_amount * 100.),
-                        ..Default::default()
-                    },
-                    payer: Payer { openid },
-                    ..Default::default()
+                    amount: prepare.pay_amount,
+                    openid,
+                    // 超时未支付的订单会被定时任务取消，微信订单同时失效
+                    time_expire: Some(pay_expire_time()),
                 })
                 .await
-                .map_err(|e| error::ErrorBadGateway(e))
             {
                 Ok(data) => data,
                 Err(e) => {
                     tran.rollback().unwrap();
-                    return Err(error::ErrorBadGateway(e));
+                    return Err(e);
                 }
             };
         }
@@ -444,14 +565,7 @@ pub async fn mall_order_make_pay(
     } else if pay_type == PayType::WxPay {
         Ok(web::Json(Res::success(MakePayRes {
             pay_type: PayType::WxPay,
-            wx_pay: Some(WxPayInfo {
-                app_id: wxinfo.app_id.clone(),
-                sign_type: wxinfo.sign_type.clone(),
-                pay_sign: wxinfo.pay_sign.clone(),
-                package: wxinfo.package.clone(),
-                nonce_str: wxinfo.nonce_str.clone(),
-                time_stamp: wxinfo.time_stamp,
-            }),
+            wx_pay: Some(wxinfo.into()),
         })))
     } else {
         Err(error::ErrorBadRequest("不支持的支付类型"))
@@ -484,8 +598,8 @@ pub struct UserOrder {
     /// 购买总数量
     total_quantity: u32,
     /// 实际付款金额
-    pay_amount: f64,
-    /// 订单状态 1待支付，2已支付，0取消支付
+    pay_amount: Money,
+    /// 订单状态 1待支付，2已支付，0取消支付，8已支付待成团
     order_status: u8,
     /// 创建时间
     created_at: String,
@@ -544,7 +658,7 @@ pub async fn mall_order_list(
         order_id: u64,
         order_status: u8,
         total_quantity: u32,
-        pay_amount: String,
+        pay_amount: Money,
         created_at: String,
     }
     let sub_order: Vec<OrderItemGet> = my_run_vec(
@@ -593,7 +707,7 @@ pub async fn mall_order_list(
                 id: item.order_id,
                 order_sn: item.order_sn.clone(),
                 total_quantity: item.total_quantity,
-                pay_amount: item.pay_amount.parse::<f64>().unwrap(),
+                pay_amount: item.pay_amount,
                 order_status: item.order_status,
                 created_at: item.created_at.clone(),
                 items: vec![UserOrderItem {
@@ -747,7 +861,7 @@ pub async fn mall_order_list(
 //                 id: x.id,
 //                 order_sn: x.order_sn.clone(),
 //                 total_quantity: x.total_quantity,
-//                 pay_amount: x.pay_amount.parse::<f64>().unwrap(),
+//                 pay_amount: x.pay_amount,
 //                 order_status: x.status,
 //                 created_at: x.created_at.clone(),
 //                 items: sub_order_all,
@@ -770,9 +884,11 @@ pub struct UserOrderItemDetail {
     /// 商品封面图
     unit_cover: Option<String>,
     /// 商品价格
-    price: f64,
+    price: Money,
     /// 购买的数量
     buy_quantity: u32,
+    /// 分摊优惠后的实付，之前的订单为空
+    pay_amount: Option<Money>,
     /// 产品编号
     product_sn: u32,
     /// 产品名
@@ -790,13 +906,15 @@ pub struct UserOrderDetail {
     /// 购买总数量
     total_quantity: u32,
     /// 合计金额
-    total_amount: f64,
+    total_amount: Money,
     /// 优惠金额
-    reduce_amount: Option<f64>,
+    reduce_amount: Option<Money>,
     /// 优惠信息
     reduce_des: Option<String>,
+    /// 使用的每一项优惠，按使用的顺序
+    discounts: Vec<OrderDiscount>,
     /// 实际付款金额
-    pay_amount: f64,
+    pay_amount: Money,
     /// 用户备注
     notes: Option<String>,
     /// 预约时间
@@ -813,7 +931,7 @@ pub struct UserOrderDetail {
     contact_user: Option<String>,
     /// 收货 联系手机
     contact_phone: Option<String>,
-    /// 订单状态 1待支付，2已支付，0取消支付
+    /// 订单状态 1待支付，2已支付，0取消支付，8已支付待成团
     order_status: u8,
     /// 创建时间
     created_at: String,
@@ -839,10 +957,10 @@ pub async fn mall_order_detail(
         id: u64,
         order_sn: String,
         total_quantity: u32,
-        total_amount: String,
-        reduce_amount: Option<String>,
+        total_amount: Money,
+        reduce_amount: Option<Money>,
         reduce_des: Option<String>,
-        pay_amount: String,
+        pay_amount: Money,
         notes: Option<String>,
         appointment_time: Option<String>,
         province: Option<String>,
@@ -869,6 +987,7 @@ pub async fn mall_order_detail(
     if order.len() == 0 {
         return Err(error::ErrorBadRequest(format!("订单 {} 不存在", order_sn)));
     }
+    let discounts = get_order_discounts(&mut conn, &order_sn)?;
 
     // 查寻用户子订单
     #[derive(Deserialize, Debug)]
@@ -878,8 +997,9 @@ pub async fn mall_order_detail(
         unit_sn: u32,
         unit_name: String,
         unit_cover: Option<String>,
-        price: String,
+        price: Money,
         buy_quantity: u32,
+        pay_amount: Option<Money>,
         product_sn: u32,
         product_name: String,
         product_cover: Option<String>,
@@ -895,7 +1015,7 @@ pub async fn mall_order_detail(
             r: "p0 && p1",
             select: "id, order_item_id, unit_sn, unit_name,
                     unit_cover, sku_unit.product_sn, product_name, status,
-                    price, buy_quantity, spu_product.product_cover_img as product_cover",
+                    price, buy_quantity, pay_amount, spu_product.product_cover_img as product_cover",
         }),
     )?;
 
@@ -907,8 +1027,9 @@ pub async fn mall_order_detail(
             unit_sn: y.unit_sn,
             unit_name: y.unit_name.clone(),
             unit_cover: get_file_url(y.unit_cover.clone()),
-            price: y.price.parse::<f64>().unwrap(),
+            price: y.price,
             buy_quantity: y.buy_quantity,
+            pay_amount: y.pay_amount,
             product_sn: y.product_sn,
             product_name: y.product_name.clone(),
             product_cover: get_file_url(y.product_cover.clone()),
@@ -922,17 +1043,11 @@ pub async fn mall_order_detail(
             id: x.id,
             order_sn: x.order_sn.clone(),
             total_quantity: x.total_quantity,
-            total_amount: x.total_amount.parse::<f64>().unwrap(),
-            reduce_amount: if let Some(r) = x.reduce_amount.clone() {
-                match r.parse::<f64>() {
-                    Ok(d) => Some(d),
-                    Err(_) => None,
-                }
-            } else {
-                None
-            },
+            total_amount: x.total_amount,
+            reduce_amount: x.reduce_amount,
             reduce_des: x.reduce_des.clone(),
-            pay_amount: x.pay_amount.parse::<f64>().unwrap(),
+            discounts: discounts.clone(),
+            pay_amount: x.pay_amount,
             notes: x.notes.clone(),
             appointment_time: x.appointment_time.clone(),
             province: x.province.clone(),
@@ -1015,6 +1130,29 @@ pub async fn mall_order_modify_status(
         return Ok(web::Json(Res::fail("只有已支付的订单才能申请退款")));
     }
 
+    // 有售后中或已部分退款的商品时，整单退款会重复退，只能走售后
+    let after_sale: Vec<serde_json::Value> = match my_run_tran_vec(
+        &mut tran,
+        myfind!("ord_after_sale", {
+            p0: ["order_sn", "=", &params.order_sn],
+            p1: ["status", "!=", AfterSaleStatus::Rejected as u8],
+            p2: ["status", "!=", AfterSaleStatus::Cancelled as u8],
+            p3: ["is_del", "=", 0],
+            r: "p0 && p1 && p2 && p3",
+            select: "id",
+        }),
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    if !after_sale.is_empty() {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail("订单有售后记录，请通过售后申请退款")));
+    }
+
     // 1. 修改主订单状态为申请退款
     match upd_order_status(
         &mut tran,
@@ -1106,6 +1244,50 @@ pub async fn mall_order_modify_status(
     Ok(web::Json(Res::success("申请退款成功，等待管理员处理")))
 }
 
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct OrderCancel {
+    /// 订单号
+    order_sn: String,
+}
+/// 【订单】用户取消待支付的订单
+#[utoipa::path(
+    request_body = OrderCancel,
+    responses((status = 200, description = "【请求：OrderCancel】【返回：String】", body = String)),
+)]
+#[put("/mall/order/cancel")]
+pub async fn mall_order_cancel(
+    user: AuthUser,
+    params: web::Json<OrderCancel>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    let mut conn = mysql_conn()?;
+
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    // 修改订单状态，返还库存和优惠券
+    let pay_type = match cancel_pending_order(&mut tran, &params.order_sn, Some(uid), "用户取消")
+    {
+        Ok(p) => p,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    // 关闭微信订单，关闭失败（如用户已支付）时，不取消
+    if pay_type == PayType::WxPay {
+        match close_wx_order(&params.order_sn).await {
+            Ok(_) => (),
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        }
+    }
+    tran.commit().unwrap();
+
+    Ok(web::Json(Res::success("订单已取消")))
+}
+
 #[cfg(test)]
 mod test {
     use mysql_quick::mysetmany;
@@ -3,10 +3,11 @@ use mysql_quick::{myfind, myset, mysetmany, myupdate};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::common::PRODUCT_START_SN;
 use crate::common::types::{NormalStatus, OssBucket};
-use crate::db::{my_run_drop, my_run_vec, mysql_conn};
+use crate::common::{Money, PRODUCT_START_SN};
+use crate::db::{my_run_drop, my_run_vec, mysql_block, mysql_conn};
 use crate::middleware::AuthUser;
+use crate::routes::utils_set::review_set::{ReviewRes, get_product_rating, get_product_reviews};
 use crate::routes::{Brand, ProductAttr, Res};
 use crate::utils::files::{get_file_url, get_file_urls, get_path_from_url};
 use crate::utils::html::to_html_image_urls;
@@ -29,7 +30,7 @@ pub struct ProductRes {
     /// 售出的累计数量
     sell_total: u32,
     /// 产品价格，只用于显示
-    combined_price: Option<f64>,
+    combined_price: Option<Money>,
     /// 店铺编号
     store_code: Option<u32>,
     /// 时间
@@ -49,7 +50,6 @@ pub async fn mall_product_list(
     let cat_id: u32 = cat_id.to_owned().parse().unwrap();
     let p_type: i8 = p_type.to_owned().parse().unwrap();
 
-    let mut conn = mysql_conn()?;
     let select = "id,product_sn,product_name,product_des,product_cover_img,product_imgs,sell_total,store_code,combined_price,created_at";
     let r;
     let order_by;
@@ -106,39 +106,40 @@ pub async fn mall_product_list(
         store_code: Option<u32>,
         sell_total: u32,
         created_at: String,
-        combined_price: Option<String>,
+        combined_price: Option<Money>,
     }
-    let list: Vec<ProductGet> = my_run_vec(&mut conn, sql)?;
-
-    let list: Vec<ProductRes> = list
-        .into_iter()
-        .map(|x| {
-            let temp_cover = if let Some(p) = x.product_cover_img {
-                p
-            } else {
-                "".to_string()
-            };
-            let temp_imgs = if let Some(p) = x.product_imgs {
-                p
-            } else {
-                "".to_string()
-            };
-            return ProductRes {
-                id: x.id,
-                product_sn: x.product_sn,
-                product_name: x.product_name,
-                product_des: x.product_des,
-                product_cover_img: get_file_url(Some(&temp_cover)).unwrap_or("".to_string()),
-                product_imgs: get_file_urls(Some(&temp_imgs)),
-                sell_total: x.sell_total,
-                store_code: x.store_code,
-                created_at: x.created_at,
-                combined_price: x
-                    .combined_price
-                    .map_or(None, |m| Some(m.parse::<f64>().unwrap())),
-            };
-        })
-        .collect();
+    let list: Vec<ProductRes> = mysql_block(move |conn| {
+        let list: Vec<ProductGet> = my_run_vec(conn, sql)?;
+        let list = list
+            .into_iter()
+            .map(|x| {
+                let temp_cover = if let Some(p) = x.product_cover_img {
+                    p
+                } else {
+                    "".to_string()
+                };
+                let temp_imgs = if let Some(p) = x.product_imgs {
+                    p
+                } else {
+                    "".to_string()
+                };
+                return ProductRes {
+                    id: x.id,
+                    product_sn: x.product_sn,
+                    product_name: x.product_name,
+                    product_des: x.product_des,
+                    product_cover_img: get_file_url(Some(&temp_cover)).unwrap_or("".to_string()),
+                    product_imgs: get_file_urls(Some(&temp_imgs)),
+                    sell_total: x.sell_total,
+                    store_code: x.store_code,
+                    created_at: x.created_at,
+                    combined_price: x.combined_price,
+                };
+            })
+            .collect();
+        Ok(list)
+    })
+    .await?;
     Ok(web::Json(Res::success(list)))
 }
 
@@ -161,7 +162,7 @@ pub struct ProductDetail {
     /// 购买的累计数量
     sell_total: u32,
     /// 产品价格，只用于显示
-    combined_price: Option<f64>,
+    combined_price: Option<Money>,
     /// 店铺编号
     store_code: Option<u32>,
     /// 品牌信息，没有则返回 null
@@ -172,6 +173,12 @@ pub struct ProductDetail {
     html: Option<String>,
     /// 产品特点，说明等，富文本
     peculiarity_html: Option<String>,
+    /// 平均评分，没有评价时为 null
+    rating: Option<f64>,
+    /// 评价数
+    review_total: u64,
+    /// 最新的评价，更多的用 /mall/review/list 分页获取
+    reviews: Vec<ReviewRes>,
     /// 时间
     created_at: String,
 }
@@ -186,7 +193,6 @@ pub async fn mall_product_detail(query: web::Path<String>) -> Result<impl Respon
         .to_owned()
         .parse::<u32>()
         .map_err(|_| error::ErrorNotFound("访问的内容不存在"))?;
-    let mut conn = mysql_conn()?;
     let sql = myfind!("spu_product", {
         j0: ["product_sn", "right", "spu_product_cat.product_sn"],
         j1: ["brand_code", "left", "brd_brand.brand_code"],
@@ -210,7 +216,7 @@ pub async fn mall_product_detail(query: web::Path<String>) -> Result<impl Respon
         product_des: Option<String>,
         product_cover_img: Option<String>,
         product_imgs: Option<String>,
-        combined_price: Option<String>,
+        combined_price: Option<Money>,
         store_code: Option<u32>,
         sell_total: u32,
         created_at: String,
@@ -224,20 +230,26 @@ pub async fn mall_product_detail(query: web::Path<String>) -> Result<impl Respon
         brand_sec_name: Option<String>,
         brand_des: Option<String>,
     }
-    let list: Vec<ProductGet> = my_run_vec(&mut conn, sql)?;
-    if list.len() == 0 {
-        return Err(error::ErrorNotFound("产品不存在或已下架"));
-    }
-    // 查寻当前产品，的产品属性
-    let attr: Vec<ProductAttr> = my_run_vec(
-        &mut conn,
-        myfind!("spu_product_attr", {
-            p0: ["is_del", "=", 0],
-            p1: ["product_sn", "=", prod_sn],
-            r: "p0 && p1",
-            select: "id,primary_id,secondary_id,primary_name,secondary_name,content",
-        }),
-    )?;
+    let (list, attr, (rating, review_total), reviews) = mysql_block(move |conn| {
+        let list: Vec<ProductGet> = my_run_vec(conn, sql)?;
+        if list.len() == 0 {
+            return Err(error::ErrorNotFound("产品不存在或已下架"));
+        }
+        // 查寻当前产品，的产品属性
+        let attr: Vec<ProductAttr> = my_run_vec(
+            conn,
+            myfind!("spu_product_attr", {
+                p0: ["is_del", "=", 0],
+                p1: ["product_sn", "=", prod_sn],
+                r: "p0 && p1",
+                select: "id,primary_id,secondary_id,primary_name,secondary_name,content",
+            }),
+        )?;
+        let rating = get_product_rating(conn, prod_sn)?;
+        let reviews = get_product_reviews(conn, prod_sn, 1)?;
+        Ok((list, attr, rating, reviews))
+    })
+    .await?;
 
     let list: Vec<ProductDetail> = list
         .into_iter()
@@ -260,9 +272,7 @@ pub async fn mall_product_detail(query: web::Path<String>) -> Result<impl Respon
                 product_des: x.product_des,
                 product_cover_img: get_file_url(Some(&temp_cover)).unwrap_or("".to_string()),
                 product_imgs: get_file_urls(Some(&temp_imgs)),
-                combined_price: x
-                    .combined_price
-                    .map_or(None, |m| Some(m.parse::<f64>().unwrap())),
+                combined_price: x.combined_price,
                 sell_total: x.sell_total,
                 store_code: x.store_code,
                 created_at: x.created_at,
@@ -293,6 +303,9 @@ pub async fn mall_product_detail(query: web::Path<String>) -> Result<impl Respon
                 } else {
                     Some(attr.clone())
                 },
+                rating,
+                review_total,
+                reviews: reviews.clone(),
             }
         })
         .collect();
@@ -315,7 +328,7 @@ pub struct UnitRes {
     /// 商品名
     unit_name: String,
     /// 商品价格
-    price: f64,
+    price: Money,
     /// 商品库存
     quantity: u32,
     /// 商品封面图
@@ -348,7 +361,7 @@ pub async fn mall_product_unit_list(query: web::Path<String>) -> Result<impl Res
         id: u64,
         unit_sn: u32,
         unit_name: String,
-        price: String,
+        price: Money,
         quantity: u32,
         unit_cover: Option<String>,
         unit_imgs: Option<String>,
@@ -408,7 +421,7 @@ pub async fn mall_product_unit_list(query: web::Path<String>) -> Result<impl Res
                 id: x.id,
                 unit_sn: x.unit_sn,
                 unit_name: x.unit_name,
-                price: x.price.parse::<f64>().unwrap(),
+                price: x.price,
                 quantity: x.quantity,
                 unit_cover: get_file_url(Some(&temp_cover)).unwrap_or("".to_string()),
                 unit_imgs: get_file_urls(Some(&temp_imgs)),
@@ -0,0 +1,274 @@
+use actix_web::{Error, Responder, Result, error, get, post, web};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, Transaction, TxOpts, myfind, myget, myset};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::types::{FileDir, NormalStatus, OrderItemStatus};
+use crate::db::{my_run_tran_drop, my_run_tran_vec, my_run_vec, mysql_conn};
+use crate::middleware::AuthUser;
+use crate::routes::utils_set::mall_set::upd_order_item_status;
+use crate::routes::utils_set::review_set::{ReviewRes, get_product_reviews};
+use crate::routes::{CheckMedia, Res, wx_check_media, wx_check_text};
+use crate::utils::files::get_file_url;
+use crate::utils::utils::log_err;
+
+/// 评价内容的最大字数
+const REVIEW_CONTENT_MAX: usize = 500;
+/// 评价图片的最大数量
+const REVIEW_IMGS_MAX: usize = 9;
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct ReviewAdd {
+    /// 子订单id，已完成的子订单才能评价
+    order_item_id: String,
+    /// 商品评分 1-5
+    rating: u8,
+    /// 评价内容
+    content: String,
+    /// 评价图片，/upload/file 上传（category 为 review）后返回的 path
+    imgs: Vec<String>,
+    /// 店铺评分 1-5，可不传
+    store_rating: Option<u8>,
+    /// 物流评分 1-5，可不传
+    delivery_rating: Option<u8>,
+}
+
+/// 检查评价的参数
+fn check_review(params: &ReviewAdd) -> Result<(), &'static str> {
+    let is_rating = |r: u8| (1..=5).contains(&r);
+    if !is_rating(params.rating) {
+        return Err("评分为1-5分");
+    }
+    if !params.store_rating.is_none_or(is_rating) || !params.delivery_rating.is_none_or(is_rating) {
+        return Err("评分为1-5分");
+    }
+    if params.content.trim().chars().count() > REVIEW_CONTENT_MAX {
+        return Err("评价内容不能超过500字");
+    }
+    if params.imgs.len() > REVIEW_IMGS_MAX {
+        return Err("评价图片不能超过9张");
+    }
+    // 只能用评价目录下上传的图片
+    let dir = format!("{}/", FileDir::Review.get_dir());
+    if params.imgs.iter().any(|x| !x.starts_with(&dir)) {
+        return Err("评价图片有误");
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ItemGet {
+    order_sn: String,
+    unit_sn: u32,
+    unit_name: Option<String>,
+    status: u8,
+    product_sn: Option<u32>,
+    store_code: Option<u32>,
+}
+/// 保存商品评价，以及可选的店铺、物流评分，并将子订单改为已评价
+fn add_review(
+    tran: &mut Transaction,
+    uid: u64,
+    params: &ReviewAdd,
+    item: &ItemGet,
+    product_sn: u32,
+    status: &NormalStatus,
+) -> Result<(), Error> {
+    let imgs = params.imgs.join(",");
+    my_run_tran_drop(
+        tran,
+        myset!("eva_product", {
+            "uid": uid,
+            "order_item_id": &params.order_item_id,
+            "order_sn": &item.order_sn,
+            "product_sn": product_sn,
+            "unit_sn": item.unit_sn,
+            "unit_name": &item.unit_name,
+            "rating": params.rating,
+            "content": params.content.trim(),
+            "imgs": if imgs.is_empty() { "null" } else { &imgs },
+            "status": status.clone() as u8,
+        }),
+    )?;
+    if let (Some(rating), Some(store_code)) = (params.store_rating, item.store_code) {
+        my_run_tran_drop(
+            tran,
+            myset!("eva_com_store", {
+                "uid": uid,
+                "order_item_id": &params.order_item_id,
+                "store_code": store_code,
+                "rating": rating,
+                "status": status.clone() as u8,
+            }),
+        )?;
+    }
+    if let Some(rating) = params.delivery_rating {
+        my_run_tran_drop(
+            tran,
+            myset!("eva_delivery", {
+                "uid": uid,
+                "order_item_id": &params.order_item_id,
+                "order_sn": &item.order_sn,
+                "rating": rating,
+                "status": status.clone() as u8,
+            }),
+        )?;
+    }
+    upd_order_item_status(tran, &params.order_item_id, OrderItemStatus::Evaluated)
+}
+
+/// 【评价】评价已完成的子订单
+///
+/// 文字先经过微信内容安全检测，不通过的直接返回原因。没有图片且检测通过的评价直接显示，
+/// 有图片或检测失败的进入人工审核，图片同时提交微信异步检测
+#[utoipa::path(
+    request_body = ReviewAdd,
+    responses((status = 200, description = "【请求：ReviewAdd】【返回：String】", body = String)),
+)]
+#[post("/mall/review/add")]
+pub async fn mall_review_add(
+    user: AuthUser,
+    params: web::Json<ReviewAdd>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if let Err(msg) = check_review(&params) {
+        return Ok(web::Json(Res::fail(msg)));
+    }
+    let content = params.content.trim();
+    let mut conn = mysql_conn()?;
+
+    #[derive(Deserialize)]
+    struct OpenId {
+        openid: Option<String>,
+    }
+    let openid: Vec<OpenId> = my_run_vec(&mut conn, myget!("usr_silent", uid, "openid"))?;
+    let openid = openid.into_iter().next().and_then(|x| x.openid);
+
+    // 文字检测
+    let text_pass = if content.is_empty() {
+        true
+    } else if let Some(o) = &openid {
+        match wx_check_text(o, content).await {
+            Ok(Some(msg)) => return Ok(web::Json(Res::fail(msg))),
+            Ok(None) => true,
+            // 检测接口出错时，转人工审核
+            Err(_) => false,
+        }
+    } else {
+        false
+    };
+    let is_pass = text_pass && params.imgs.is_empty();
+    let status = if is_pass {
+        NormalStatus::Online
+    } else {
+        NormalStatus::UnderReview
+    };
+
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    let item: Vec<ItemGet> = match my_run_tran_vec(
+        &mut tran,
+        myfind!("ord_order_item", {
+            j0: ["unit_sn", "left", "sku_unit.unit_sn"],
+            j1: ["sku_unit.product_sn", "left", "spu_product.product_sn"],
+            p0: ["order_item_id", "=", &params.order_item_id],
+            p1: ["uid", "=", uid],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "order_sn,unit_sn,unit_name,status,sku_unit.product_sn,spu_product.store_code",
+        }) + MY_EXCLUSIVE_LOCK,
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    let Some(item) = item.into_iter().next() else {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail("订单不存在")));
+    };
+    let item_status = OrderItemStatus::from(item.status);
+    if item_status == OrderItemStatus::Evaluated {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail("已评价过")));
+    }
+    if item_status != OrderItemStatus::Complete {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail("订单完成后才能评价")));
+    }
+    let Some(product_sn) = item.product_sn else {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail("商品不存在")));
+    };
+
+    if let Err(e) = add_review(&mut tran, uid, &params, &item, product_sn, &status) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+
+    // 图片提交微信异步检测，结果在后台审核时查看
+    if let Some(o) = &openid {
+        for path in params.imgs.iter() {
+            let Some(url) = get_file_url(Some(path)) else {
+                continue;
+            };
+            let media = CheckMedia::image(url, path.clone());
+            if let Err(e) = wx_check_media(&mut conn, uid, o, &media).await {
+                log_err(&e, path);
+            }
+        }
+    }
+
+    Ok(web::Json(Res::success(if is_pass {
+        "评价成功"
+    } else {
+        "评价成功，审核通过后显示"
+    })))
+}
+
+/// 【评价】产品的评价列表
+#[utoipa::path(
+    responses((status = 200, description = "【返回：ReviewRes[]】", body = Vec<ReviewRes>)),
+    params(("product_sn", description="产品编号"), ("page", description="第几页"))
+)]
+#[get("/mall/review/list/{product_sn}/{page}")]
+pub async fn mall_review_list(path: web::Path<(String, String)>) -> Result<impl Responder> {
+    let product_sn: u32 = path.0.parse().map_err(error::ErrorBadRequest)?;
+    let page: u32 = path.1.parse().map_err(error::ErrorBadRequest)?;
+    let mut conn = mysql_conn()?;
+    let list = get_product_reviews(&mut conn, product_sn, page)?;
+    Ok(web::Json(Res::success(list)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_review() {
+        let dir = FileDir::Review.get_dir();
+        let mut p = ReviewAdd {
+            order_item_id: "66c15f522854347e5023000".to_string(),
+            rating: 5,
+            content: "很好".to_string(),
+            imgs: vec![format!("{dir}/1.jpg")],
+            store_rating: None,
+            delivery_rating: Some(4),
+        };
+        assert_eq!(check_review(&p), Ok(()));
+        p.delivery_rating = Some(0);
+        assert_eq!(check_review(&p), Err("评分为1-5分"));
+        p.delivery_rating = None;
+        p.rating = 6;
+        assert_eq!(check_review(&p), Err("评分为1-5分"));
+        p.rating = 1;
+        p.imgs = vec!["mall_server/avatar/1.jpg".to_string()];
+        assert_eq!(check_review(&p), Err("评价图片有误"));
+        p.imgs = vec![];
+        p.content = "好".repeat(501);
+        assert_eq!(check_review(&p), Err("评价内容不能超过500字"));
+    }
+}
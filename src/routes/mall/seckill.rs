@@ -0,0 +1,231 @@
+use actix_web::{Responder, Result, get, post, web};
+use mysql_quick::{TxOpts, myfind, myget};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::NormalStatus;
+use crate::control::seckill::{SeckillStock, seckill_release, seckill_remain, seckill_reserve};
+use crate::db::{my_run_vec, mysql_conn};
+use crate::middleware::{AuthUser, ModuleSeckill, RequireModule};
+use crate::routes::Res;
+use crate::routes::utils_set::mall_set::add_seckill_to_buy_now;
+use crate::utils::time::{NowTimeType, get_now_time};
+use crate::utils::utils::log_err;
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct SeckillRes {
+    /// 秒杀活动id
+    id: u32,
+    /// 活动名
+    title: String,
+    /// 商品编号
+    unit_sn: u32,
+    /// 商品名
+    unit_name: Option<String>,
+    /// 商品封面图
+    unit_cover: Option<String>,
+    /// 商品原价
+    original_price: Option<Money>,
+    /// 秒杀价
+    price: Money,
+    /// 秒杀库存
+    stock: u32,
+    /// 剩余库存
+    remain: u32,
+    /// 每人限购数量
+    limit_per_user: u32,
+    /// 开始时间
+    start_time: String,
+    /// 结束时间
+    end_time: String,
+    /// 是否已开始，false 为即将开始
+    is_started: bool,
+}
+/// 【秒杀】进行中和即将开始的秒杀活动
+#[utoipa::path(
+    responses((status = 200, description = "【返回：SeckillRes[]】", body = Vec<SeckillRes>))
+)]
+#[get("/mall/seckill/list")]
+pub async fn mall_seckill_list(_m: RequireModule<ModuleSeckill>) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+
+    #[derive(Deserialize)]
+    struct SeckillGet {
+        id: u32,
+        title: String,
+        unit_sn: u32,
+        unit_name: Option<String>,
+        unit_cover: Option<String>,
+        original_price: Option<Money>,
+        price: Money,
+        stock: u32,
+        sold: u32,
+        limit_per_user: u32,
+        start_time: String,
+        end_time: String,
+    }
+    let now = get_now_time(NowTimeType::DateTime);
+    let list: Vec<SeckillGet> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_sec_kill", {
+            j0: ["unit_sn", "inner", "sku_unit.unit_sn"],
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", NormalStatus::Online as u8],
+            p2: ["end_time", ">", &now], // 未结束的
+            r: "p0 && p1 && p2",
+            order_by: "start_time",
+            select: "id,title,unit_sn,sku_unit.unit_name,sku_unit.unit_cover,sku_unit.price as original_price,price,stock,sold,limit_per_user,start_time,end_time",
+        }),
+    )?;
+
+    let list: Vec<SeckillRes> = list
+        .into_iter()
+        .map(|x| {
+            // redis 中的库存未初始化时，按数据库计算
+            let remain = seckill_remain(x.id)
+                .unwrap_or(None)
+                .unwrap_or(x.stock.saturating_sub(x.sold));
+            SeckillRes {
+                id: x.id,
+                title: x.title,
+                unit_sn: x.unit_sn,
+                unit_name: x.unit_name,
+                unit_cover: x.unit_cover,
+                original_price: x.original_price,
+                price: x.price,
+                stock: x.stock,
+                remain,
+                limit_per_user: x.limit_per_user,
+                is_started: is_started(&x.start_time, &now),
+                start_time: x.start_time,
+                end_time: x.end_time,
+            }
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(list)))
+}
+
+/// 活动是否已开始，时间格式均为 %Y-%m-%d %H:%M:%S
+fn is_started(start_time: &str, now: &str) -> bool {
+    start_time <= now
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct SeckillBuy {
+    /// 秒杀活动id
+    sec_kill_id: u32,
+    /// 购买数量
+    buy_quantity: u32,
+}
+/// 【秒杀】抢购，成功后按立即购买的方式下单
+#[utoipa::path(
+    request_body = SeckillBuy,
+    responses((status = 200, description = "【请求：SeckillBuy】【返回：String】", body = String)),
+)]
+#[post("/mall/seckill/buy")]
+pub async fn mall_seckill_buy(
+    user: AuthUser,
+    _m: RequireModule<ModuleSeckill>,
+    params: web::Json<SeckillBuy>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if params.buy_quantity == 0 {
+        return Ok(web::Json(Res::fail("购买数量不能小于1")));
+    }
+    let mut conn = mysql_conn()?;
+
+    #[derive(Deserialize)]
+    struct SeckillGet {
+        unit_sn: u32,
+        stock: u32,
+        sold: u32,
+        limit_per_user: u32,
+        start_time: String,
+        end_time: String,
+        status: i8,
+        is_del: u8,
+    }
+    let sk: Vec<SeckillGet> = my_run_vec(
+        &mut conn,
+        myget!(
+            "pmt_sec_kill",
+            params.sec_kill_id,
+            "unit_sn,stock,sold,limit_per_user,start_time,end_time,status,is_del"
+        ),
+    )?;
+    if sk.is_empty() || sk[0].is_del == 1 {
+        return Ok(web::Json(Res::fail("秒杀活动不存在")));
+    }
+    let sk = &sk[0];
+    if sk.status != NormalStatus::Online as i8 {
+        return Ok(web::Json(Res::fail("秒杀活动已下架")));
+    }
+    let now = get_now_time(NowTimeType::DateTime);
+    if !is_started(&sk.start_time, &now) {
+        return Ok(web::Json(Res::fail("秒杀活动未开始")));
+    }
+    if sk.end_time <= now {
+        return Ok(web::Json(Res::fail("秒杀活动已结束")));
+    }
+    if params.buy_quantity > sk.limit_per_user {
+        return Ok(web::Json(Res::fail("超过每人限购数量")));
+    }
+
+    // 先在 redis 中抢占库存，抢到的才操作数据库
+    let stock = SeckillStock {
+        id: params.sec_kill_id,
+        stock: sk.stock,
+        sold: sk.sold,
+        limit_per_user: sk.limit_per_user,
+        end_time: sk.end_time.clone(),
+    };
+    if let Err(e) = seckill_reserve(&stock, uid, params.buy_quantity)? {
+        return Ok(web::Json(Res::fail(e.message())));
+    }
+    let release = || {
+        if let Err(e) = seckill_release(params.sec_kill_id, uid, params.buy_quantity) {
+            log_err(&e, &params);
+        }
+    };
+
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    let add_info = match add_seckill_to_buy_now(
+        &mut tran,
+        uid,
+        params.sec_kill_id,
+        sk.unit_sn,
+        params.buy_quantity,
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            release();
+            return Err(e);
+        }
+    };
+    if add_info.status == 1 {
+        tran.commit().unwrap();
+    } else {
+        tran.rollback().unwrap();
+        release();
+        return Ok(web::Json(Res::fail(&add_info.message)));
+    }
+    // ---- 事务结束 ----
+    Ok(web::Json(Res::success("抢购成功")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seckill_is_started() {
+        let now = "2025-01-01 10:00:00";
+        assert!(is_started("2025-01-01 10:00:00", now));
+        assert!(is_started("2024-12-31 23:59:59", now));
+        assert!(!is_started("2025-01-01 10:00:01", now));
+    }
+}
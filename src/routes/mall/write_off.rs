@@ -1,13 +1,13 @@
 use actix_web::{Responder, Result, error, get, post, web};
 use mysql_quick::{TxOpts, myfind};
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::{deserialize_number_from_string, deserialize_option_number_from_string};
+use serde_aux::prelude::deserialize_option_number_from_string;
 use utoipa::ToSchema;
 
-use crate::common::LocalKeySeed;
 use crate::common::types::WriteOffStatus;
+use crate::common::{LocalKeySeed, Money};
 use crate::db::{my_run_vec, mysql_conn};
-use crate::middleware::{AuthRole, AuthUser};
+use crate::middleware::{AuthRole, AuthUser, PermMallWriteOffDo};
 use crate::routes::Res;
 use crate::routes::utils_set::write_off_item::do_write_off;
 use crate::utils::crypto::aes_256_encrypt;
@@ -26,7 +26,7 @@ pub struct WriteOffInfo {
     /// 商品封面
     unit_cover: Option<String>,
     /// 商品标价
-    price: f64,
+    price: Money,
     /// 用户购买的商品数量
     buy_quantity: u32,
     /// 店铺唯一编码
@@ -68,8 +68,7 @@ pub async fn mall_write_off_info(
         order_item_id: String,
         unit_name: String,
         unit_cover: Option<String>,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        price: f64,
+        price: Money,
         buy_quantity: u32,
         store_code: u32,
         store_name: String,
@@ -165,7 +164,7 @@ pub struct DoWriteOff {
 )]
 #[post("/mall/write_off/do")]
 pub async fn mall_write_off_do(
-    role: AuthRole,
+    role: AuthRole<PermMallWriteOffDo>,
     params: web::Json<DoWriteOff>,
 ) -> Result<impl Responder> {
     let r_uid = role.id;
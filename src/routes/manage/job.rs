@@ -0,0 +1,112 @@
+use actix_web::{Responder, Result, error, get, post, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::types::JobTrigger;
+use crate::control::jobs::{JobRun, all_jobs, find_job, job_cron, job_next_time, run_job};
+use crate::db::{my_run_vec, mysql_conn};
+use crate::middleware::AuthSuperMana;
+use crate::routes::Res;
+use crate::utils::utils::log_err;
+
+const JOB_RUN_SELECT: &str = "id,job_name,trigger_type,status,message,instance,trigger_uid,started_at,finished_at,duration_ms";
+
+#[derive(Serialize)]
+struct JobInfo {
+    name: String,
+    des: String,
+    /// 当前的 cron 表达式，为空时不定时执行
+    cron: String,
+    /// 下次定时执行的时间
+    next_time: Option<String>,
+    /// 最近一次执行记录
+    last_run: Option<JobRun>,
+}
+/// 定时任务列表，及最近一次的执行记录
+#[get("/manage/system/job/list")]
+pub async fn manage_system_job_list(_super_mana: AuthSuperMana) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let mut list: Vec<JobInfo> = vec![];
+    for job in all_jobs() {
+        let last: Vec<JobRun> = my_run_vec(
+            &mut conn,
+            myfind!("sys_job_run", {
+                p0: ["job_name", "=", job.name()],
+                r: "p0",
+                limit: 1,
+                order_by: "-id",
+                select: JOB_RUN_SELECT,
+            }),
+        )?;
+        list.push(JobInfo {
+            name: job.name().to_string(),
+            des: job.des().to_string(),
+            cron: job_cron(*job),
+            next_time: job_next_time(*job),
+            last_run: last.into_iter().next(),
+        });
+    }
+    Ok(web::Json(Res::success(list)))
+}
+
+/// 定时任务的执行记录
+#[get("/manage/system/job/runs/{name}/{page}/{limit}")]
+pub async fn manage_system_job_runs(
+    _super_mana: AuthSuperMana,
+    query: web::Path<(String, u32, u32)>,
+) -> Result<impl Responder> {
+    let (name, page, limit) = query.into_inner();
+    if find_job(&name).is_none() {
+        return Ok(web::Json(Res::fail("任务不存在")));
+    }
+    let mut conn = mysql_conn()?;
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("sys_job_run", {
+            p0: ["job_name", "=", &name],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<JobRun> = my_run_vec(
+        &mut conn,
+        myfind!("sys_job_run", {
+            p0: ["job_name", "=", &name],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "-id",
+            select: JOB_RUN_SELECT,
+        }),
+    )?;
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Deserialize)]
+struct JobRunReq {
+    name: String,
+}
+/// 手动执行一次定时任务，执行完成后返回执行记录
+#[post("/manage/system/job/run")]
+pub async fn manage_system_job_run(
+    super_mana: AuthSuperMana,
+    params: web::Json<JobRunReq>,
+) -> Result<impl Responder> {
+    let Some(job) = find_job(&params.name) else {
+        return Ok(web::Json(Res::fail("任务不存在")));
+    };
+    let uid = super_mana.id;
+    let run = web::block(move || {
+        run_job(job, JobTrigger::Manual, Some(uid)).map_err(|e| log_err(&e, "任务执行出错"))
+    })
+    .await
+    .map_err(|e| error::ErrorInternalServerError(log_err(&e, "任务执行出错")))?
+    .map_err(error::ErrorInternalServerError)?;
+    match run {
+        Some(r) => Ok(web::Json(Res::success(r))),
+        None => Ok(web::Json(Res::fail("任务正在执行中，请稍后再试"))),
+    }
+}
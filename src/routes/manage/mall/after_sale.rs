@@ -0,0 +1,261 @@
+use actix_web::{Error, Responder, Result, get, put, web};
+use mysql_quick::{
+    MY_EXCLUSIVE_LOCK, MysqlQuickCount, Transaction, TxOpts, mycount, myfind, myupdate,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::Money;
+use crate::common::types::{AfterSaleStatus, AfterSaleType};
+use crate::routes::Res;
+use crate::routes::utils_set::after_sale_set::{
+    AfterSaleItemRes, after_sale_refund, close_after_sale, get_after_sale_item_res,
+};
+use crate::routes::utils_set::mall_set::refund_wx_order;
+use crate::utils::files::get_file_urls;
+use crate::{
+    db::{my_run_tran_drop, my_run_tran_vec, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AfterSaleItem {
+    id: u64,
+    after_sale_sn: String,
+    uid: u64,
+    nickname: Option<String>,
+    order_sn: String,
+    after_sale_type: u8,
+    reason: String,
+    imgs: Vec<String>,
+    refund_amount: Money,
+    pay_type: Option<String>,
+    status: u8,
+    remark: Option<String>,
+    delivery_name: Option<String>,
+    waybill_id: Option<String>,
+    refund_time: Option<String>,
+    created_at: String,
+    items: Vec<AfterSaleItemRes>,
+}
+/// 售后单列表，status: -1 全部，0 已拒绝，1 待审核，2 待寄回，3 待收货，4 退款中，5 已退款，6 已取消
+#[get("/manage/mall/after_sale/list/{status}/{page}/{limit}")]
+pub async fn manage_mall_after_sale_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (status, page, limit) = query.to_owned();
+    let status: i8 = status.to_owned().parse().unwrap();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+    // -1 时不按状态筛选
+    let r = if status < 0 { "p0" } else { "p0 && p1" };
+
+    #[derive(Deserialize)]
+    struct AfterSaleGet {
+        id: u64,
+        after_sale_sn: String,
+        uid: u64,
+        nickname: Option<String>,
+        order_sn: String,
+        #[serde(rename = "type")]
+        after_sale_type: u8,
+        reason: String,
+        imgs: Option<String>,
+        refund_amount: Money,
+        pay_type: Option<String>,
+        status: u8,
+        remark: Option<String>,
+        delivery_name: Option<String>,
+        waybill_id: Option<String>,
+        refund_time: Option<String>,
+        created_at: String,
+    }
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("ord_after_sale", {
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: r,
+        }),
+    )?;
+    let list: Vec<AfterSaleGet> = my_run_vec(
+        &mut conn,
+        myfind!("ord_after_sale", {
+            j0: ["uid", "left", "usr_silent.id"],
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: r,
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,after_sale_sn,uid,usr_silent.nickname,order_sn,type,reason,imgs,refund_amount,pay_type,status,remark,delivery_name,waybill_id,refund_time,created_at",
+        }),
+    )?;
+    let ids: Vec<u64> = list.iter().map(|x| x.id).collect();
+    let mut items = get_after_sale_item_res(&mut conn, &ids)?;
+
+    let list: Vec<AfterSaleItem> = list
+        .into_iter()
+        .map(|x| AfterSaleItem {
+            items: items.remove(&x.id).unwrap_or_default(),
+            id: x.id,
+            after_sale_sn: x.after_sale_sn,
+            uid: x.uid,
+            nickname: x.nickname,
+            order_sn: x.order_sn,
+            after_sale_type: x.after_sale_type,
+            reason: x.reason,
+            imgs: get_file_urls(x.imgs),
+            refund_amount: x.refund_amount,
+            pay_type: x.pay_type,
+            status: x.status,
+            remark: x.remark,
+            delivery_name: x.delivery_name,
+            waybill_id: x.waybill_id,
+            refund_time: x.refund_time,
+            created_at: x.created_at,
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+/// 售后退款，微信支付的在提交事务前发起退款
+async fn refund(tran: &mut Transaction<'_>, id: u64, from: AfterSaleStatus) -> Result<(), Error> {
+    if let Some(r) = after_sale_refund(tran, id, from)? {
+        refund_wx_order(
+            &r.transaction_id,
+            &r.out_refund_no,
+            r.amount,
+            r.total,
+            "售后退款",
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AfterSaleAudit {
+    id: u64,
+    /// true 审核通过，false 拒绝
+    pass: bool,
+    /// 处理说明，拒绝时为拒绝原因
+    remark: Option<String>,
+}
+/// 审核待审核的售后单。通过时，仅退款的直接退款，退货退款的等用户寄回
+#[put("/manage/mall/after_sale/audit")]
+pub async fn manage_mall_after_sale_audit(
+    _mana: AuthMana,
+    params: web::Json<AfterSaleAudit>,
+) -> Result<impl Responder> {
+    let remark = params.remark.as_deref().map(str::trim);
+    if !params.pass && remark.is_none_or(str::is_empty) {
+        return Ok(web::Json(Res::fail("请填写拒绝原因")));
+    }
+    let mut conn = mysql_conn()?;
+
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    if !params.pass {
+        let closed = match close_after_sale(
+            &mut tran,
+            params.id,
+            &[AfterSaleStatus::Apply],
+            AfterSaleStatus::Rejected,
+            remark,
+        ) {
+            Ok(d) => d,
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        };
+        if !closed {
+            tran.rollback().unwrap();
+            return Ok(web::Json(Res::fail("售后单不是待审核状态")));
+        }
+        tran.commit().unwrap();
+        return Ok(web::Json(Res::success("成功")));
+    }
+
+    #[derive(Deserialize)]
+    struct AfterSaleGet {
+        #[serde(rename = "type")]
+        after_sale_type: u8,
+        status: u8,
+    }
+    let after_sale: Vec<AfterSaleGet> = match my_run_tran_vec(
+        &mut tran,
+        myfind!("ord_after_sale", {
+            p0: ["id", "=", params.id],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "type,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    let Some(after_sale) = after_sale
+        .into_iter()
+        .next()
+        .filter(|x| x.status == AfterSaleStatus::Apply as u8)
+    else {
+        tran.rollback().unwrap();
+        return Ok(web::Json(Res::fail("售后单不是待审核状态")));
+    };
+
+    let res = if after_sale.after_sale_type == AfterSaleType::ReturnRefund as u8 {
+        my_run_tran_drop(
+            &mut tran,
+            myupdate!("ord_after_sale", params.id, {
+                "status": AfterSaleStatus::WaitReturn as u8,
+                "remark": remark,
+            }),
+        )
+        .map(|_| ())
+    } else {
+        refund(&mut tran, params.id, AfterSaleStatus::Apply).await
+    };
+    if let Err(e) = res {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AfterSaleReceive {
+    id: u64,
+}
+/// 确认收到退货，并退款
+#[put("/manage/mall/after_sale/receive")]
+pub async fn manage_mall_after_sale_receive(
+    _mana: AuthMana,
+    params: web::Json<AfterSaleReceive>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    if let Err(e) = refund(&mut tran, params.id, AfterSaleStatus::Returning).await {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+
+    Ok(web::Json(Res::success("成功")))
+}
@@ -3,6 +3,8 @@ use mysql_quick::{MysqlQuickCount, mycount, myfind, myset, myupdate};
 use serde::{Deserialize, Serialize};
 
 use crate::PageData;
+use crate::common::Money;
+use crate::common::types::CouponValidType;
 use crate::routes::Res;
 use crate::{
     db::{my_run_drop, my_run_vec, mysql_conn},
@@ -12,7 +14,7 @@ use crate::{
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CouponConditionAdd {
     id: Option<u32>,
-    full_amount: Option<f64>,
+    full_amount: Option<Money>,
     product_brand: Option<u32>,
     product_cat: Option<String>,
     product_sn: Option<u32>,
@@ -36,7 +38,7 @@ pub async fn manage_mall_coupon_condition_add(
         // 更新
         sql = myupdate!("pmt_coupon_condition", id, {
             "title": title,
-            "full_amount": params.full_amount,
+            "full_amount": params.full_amount.map(|m| m.to_string()),
             "store_code": params.store_code,
             "brand_code": params.product_brand,
             "product_cat": &params.product_cat,
@@ -47,7 +49,7 @@ pub async fn manage_mall_coupon_condition_add(
         // 新增
         sql = myset!("pmt_coupon_condition", {
             "title": title,
-            "full_amount": params.full_amount,
+            "full_amount": params.full_amount.map(|m| m.to_string()),
             "store_code": params.store_code,
             "brand_code": params.product_brand,
             "product_cat": &params.product_cat,
@@ -64,7 +66,7 @@ pub async fn manage_mall_coupon_condition_add(
 pub struct CouponConditionRes {
     id: u32,
     title: String,
-    full_amount: Option<f64>,
+    full_amount: Option<Money>,
     product_brand: Option<u32>,
     product_cat: Option<Vec<u32>>,
     product_sn: Option<u32>,
@@ -95,7 +97,7 @@ pub async fn manage_mall_coupon_condition_list(
     pub struct CouponConditionGet {
         id: u32,
         title: String,
-        full_amount: Option<String>,
+        full_amount: Option<Money>,
         brand_code: Option<u32>,
         product_cat: Option<String>,
         product_sn: Option<u32>,
@@ -119,11 +121,7 @@ pub async fn manage_mall_coupon_condition_list(
         .map(|x| CouponConditionRes {
             id: x.id,
             title: x.title,
-            full_amount: if let Some(f) = x.full_amount {
-                Some(f.parse::<f64>().unwrap())
-            } else {
-                None
-            },
+            full_amount: x.full_amount,
             product_brand: x.brand_code,
             product_cat: if let Some(p) = x.product_cat {
                 let d = p.split(",").collect::<Vec<&str>>();
@@ -187,10 +185,19 @@ pub struct CouponAdd {
     id: Option<u32>,
     coupon_name: String,
     coupon_condition_id: u32,
-    reduce_amount: Option<f64>,
+    reduce_amount: Option<Money>,
     discount: Option<f64>,
     coupon_num: u32,
+    /// 每人最多领取的张数，默认 1 张
+    per_user_limit: Option<u32>,
+    /// 固定时间的为过期时间；领取后 N 天内有效的，为领取的截止时间
     expire_time: Option<String>,
+    /// 有效期类型，1 固定时间（默认），2 领取后 N 天内有效
+    valid_type: Option<u8>,
+    /// 固定时间的开始时间，为空时领取后即可用
+    start_time: Option<String>,
+    /// 领取后多少天内有效
+    valid_days: Option<u32>,
 }
 /// 优惠券条件新增
 #[post("/manage/mall/coupon/add")]
@@ -198,7 +205,28 @@ pub async fn manage_mall_coupon_add(
     _mana: AuthMana,
     params: web::Json<CouponAdd>,
 ) -> Result<impl Responder> {
+    if params.per_user_limit == Some(0) {
+        return Ok(web::Json(Res::fail("每人限领张数必须大于0")));
+    }
+    let valid_type = params.valid_type.unwrap_or(CouponValidType::Fixed as u8);
+    let (start_time, valid_days) = if valid_type == CouponValidType::AfterClaim as u8 {
+        if params.valid_days.is_none_or(|d| d == 0) {
+            return Ok(web::Json(Res::fail("有效天数必须大于0")));
+        }
+        (None, params.valid_days)
+    } else if valid_type == CouponValidType::Fixed as u8 {
+        let start_time = params.start_time.clone().filter(|t| !t.is_empty());
+        if let (Some(s), Some(e)) = (&start_time, &params.expire_time)
+            && s >= e
+        {
+            return Ok(web::Json(Res::fail("过期时间必须晚于开始时间")));
+        }
+        (start_time, None)
+    } else {
+        return Ok(web::Json(Res::fail("有效期类型错误")));
+    };
     let mut conn = mysql_conn()?;
+    let per_user_limit = params.per_user_limit.unwrap_or(1);
 
     let sql;
     if let Some(id) = params.id {
@@ -206,20 +234,28 @@ pub async fn manage_mall_coupon_add(
         sql = myupdate!("pmt_coupon", id, {
             "coupon_name": &params.coupon_name,
             "coupon_condition_id": params.coupon_condition_id,
-            "reduce_amount": params.reduce_amount,
+            "reduce_amount": params.reduce_amount.map(|m| m.to_string()),
             "discount": params.discount,
             "coupon_num": params.coupon_num,
+            "per_user_limit": per_user_limit,
             "expire_time": &params.expire_time,
+            "valid_type": valid_type,
+            "start_time": &start_time,
+            "valid_days": valid_days,
         });
     } else {
         // 新增
         sql = myset!("pmt_coupon", {
             "coupon_name": &params.coupon_name,
             "coupon_condition_id": params.coupon_condition_id,
-            "reduce_amount": params.reduce_amount,
+            "reduce_amount": params.reduce_amount.map(|m| m.to_string()),
             "discount": params.discount,
             "coupon_num": params.coupon_num,
+            "per_user_limit": per_user_limit,
             "expire_time": &params.expire_time,
+            "valid_type": valid_type,
+            "start_time": &start_time,
+            "valid_days": valid_days,
         });
     }
     my_run_drop(&mut conn, sql)?;
@@ -233,10 +269,14 @@ struct CouponRes {
     coupon_name: String,
     coupon_condition_id: u32,
     coupon_condition_name: String,
-    reduce_amount: Option<f64>,
+    reduce_amount: Option<Money>,
     discount: Option<f64>,
     coupon_num: u32,
+    per_user_limit: u32,
     expire_time: Option<String>,
+    valid_type: u8,
+    start_time: Option<String>,
+    valid_days: Option<u32>,
     status: i8,
     created_at: String,
 }
@@ -265,10 +305,14 @@ pub async fn manage_mall_coupon_list(
         coupon_name: String,
         coupon_condition_id: u32,
         coupon_condition_name: String,
-        reduce_amount: Option<String>,
+        reduce_amount: Option<Money>,
         discount: Option<String>,
         coupon_num: u32,
+        per_user_limit: u32,
         expire_time: Option<String>,
+        valid_type: u8,
+        start_time: Option<String>,
+        valid_days: Option<u32>,
         status: i8,
         created_at: String,
     }
@@ -281,7 +325,7 @@ pub async fn manage_mall_coupon_list(
             page: page,
             limit: limit,
             order_by: "-created_at",
-            select: "id,coupon_name,coupon_condition_id,pmt_coupon_condition.title as coupon_condition_name, reduce_amount,discount,coupon_num,expire_time,status,created_at",
+            select: "id,coupon_name,coupon_condition_id,pmt_coupon_condition.title as coupon_condition_name, reduce_amount,discount,coupon_num,per_user_limit,expire_time,valid_type,start_time,valid_days,status,created_at",
         }),
     )?;
 
@@ -292,18 +336,18 @@ pub async fn manage_mall_coupon_list(
             coupon_name: x.coupon_name,
             coupon_condition_id: x.coupon_condition_id,
             coupon_condition_name: x.coupon_condition_name,
-            reduce_amount: if let Some(d) = x.reduce_amount {
-                Some(d.parse::<f64>().unwrap())
-            } else {
-                None
-            },
+            reduce_amount: x.reduce_amount,
             discount: if let Some(d) = x.discount {
                 Some(d.parse::<f64>().unwrap())
             } else {
                 None
             },
             coupon_num: x.coupon_num,
+            per_user_limit: x.per_user_limit,
             expire_time: x.expire_time,
+            valid_type: x.valid_type,
+            start_time: x.start_time,
+            valid_days: x.valid_days,
             status: x.status,
             created_at: x.created_at,
         })
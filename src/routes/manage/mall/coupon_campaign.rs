@@ -0,0 +1,432 @@
+use actix_web::{HttpResponse, Responder, Result, error, get, http::header, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myget, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::types::{CampaignKind, CampaignStatus, CampaignTarget, JobTrigger};
+use crate::control::jobs::{CouponCampaignJob, run_job};
+use crate::routes::Res;
+use crate::routes::utils_set::coupon_campaign_set::{
+    CampaignCode, campaign_codes, check_campaign_target,
+};
+use crate::utils::utils::log_err;
+use crate::{
+    db::{my_exec_drop, my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+/// 一次最多生成的兑换码数
+const MAX_CODE_COUNT: u32 = 100000;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CouponCampaignAdd {
+    id: Option<u32>,
+    title: String,
+    coupon_id: u32,
+    /// 1 批量发放，2 兑换码，3 新用户首次登录发放
+    kind: u8,
+    /// 批量发放的用户：1 全部用户，2 某个角色，3 购买过某个产品，4 最近 N 天注册
+    target: Option<u8>,
+    /// 角色编号、产品编号或天数
+    target_value: Option<String>,
+    /// 每人发放的张数，默认 1 张
+    quantity: Option<u32>,
+    /// 兑换码的数量
+    code_count: Option<u32>,
+    /// 兑换码可兑换、新用户发放的时间，为空时不限
+    start_time: Option<String>,
+    end_time: Option<String>,
+}
+
+/// 检查发放活动的参数，返回 (用户范围, 处理后的 target_value)
+fn check_campaign(
+    params: &CouponCampaignAdd,
+) -> Result<(Option<CampaignTarget>, Option<String>), &'static str> {
+    let fmt = "%Y-%m-%d %H:%M:%S";
+    if params.title.trim().is_empty() {
+        return Err("活动名不能为空");
+    }
+    if params.quantity == Some(0) {
+        return Err("每人发放的张数必须大于0");
+    }
+    let parse = |t: &Option<String>| {
+        t.as_deref()
+            .filter(|t| !t.is_empty())
+            .map(|t| chrono::NaiveDateTime::parse_from_str(t, fmt))
+            .transpose()
+    };
+    match (parse(&params.start_time), parse(&params.end_time)) {
+        (Ok(Some(s)), Ok(Some(e))) if s >= e => return Err("结束时间必须晚于开始时间"),
+        (Ok(_), Ok(_)) => (),
+        _ => return Err("时间格式错误"),
+    }
+    match CampaignKind::try_from(params.kind)? {
+        CampaignKind::Grant => {
+            let target = CampaignTarget::try_from(params.target.unwrap_or(0))?;
+            let value = check_campaign_target(target, params.target_value.as_deref())?;
+            Ok((Some(target), Some(value).filter(|v| !v.is_empty())))
+        }
+        CampaignKind::RedeemCode => match params.code_count {
+            Some(n) if n > 0 && n <= MAX_CODE_COUNT => Ok((None, None)),
+            _ => Err("兑换码数量必须大于0，且不超过100000"),
+        },
+        CampaignKind::NewUser => Ok((None, None)),
+    }
+}
+
+/// 发放活动新增、修改，只有待执行的活动可以修改。创建后需要执行才开始发放
+#[post("/manage/mall/coupon/campaign/add")]
+pub async fn manage_mall_coupon_campaign_add(
+    mana: AuthMana,
+    params: web::Json<CouponCampaignAdd>,
+) -> Result<impl Responder> {
+    let (target, target_value) = match check_campaign(&params) {
+        Ok(d) => d,
+        Err(msg) => return Ok(web::Json(Res::fail(msg))),
+    };
+    let mut conn = mysql_conn()?;
+    let title = params.title.trim();
+    let quantity = params.quantity.unwrap_or(1);
+    let code_count = if params.kind == CampaignKind::RedeemCode as u8 {
+        params.code_count.unwrap_or(0)
+    } else {
+        0
+    };
+    let time = |t: &Option<String>| t.clone().filter(|t| !t.is_empty());
+    let sql = if let Some(id) = params.id {
+        #[derive(Deserialize)]
+        struct StatusGet {
+            status: u8,
+        }
+        let list: Vec<StatusGet> =
+            my_run_vec(&mut conn, myget!("pmt_coupon_campaign", id, "status"))?;
+        if list.first().map(|x| x.status) != Some(CampaignStatus::Draft as u8) {
+            return Ok(web::Json(Res::fail("只有待执行的活动可以修改")));
+        }
+        // 更新
+        myupdate!("pmt_coupon_campaign", id, {
+            "title": title,
+            "coupon_id": params.coupon_id,
+            "kind": params.kind,
+            "target": target.map(|t| t as u8),
+            "target_value": target_value,
+            "quantity": quantity,
+            "code_count": code_count,
+            "start_time": time(&params.start_time),
+            "end_time": time(&params.end_time),
+        })
+    } else {
+        // 新增
+        myset!("pmt_coupon_campaign", {
+            "title": title,
+            "coupon_id": params.coupon_id,
+            "kind": params.kind,
+            "target": target.map(|t| t as u8),
+            "target_value": target_value,
+            "quantity": quantity,
+            "code_count": code_count,
+            "start_time": time(&params.start_time),
+            "end_time": time(&params.end_time),
+            "status": CampaignStatus::Draft as u8,
+            "mana_uid": mana.id,
+        })
+    };
+    my_run_drop(&mut conn, sql)?;
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CouponCampaignRes {
+    id: u32,
+    title: String,
+    coupon_id: u32,
+    coupon_name: Option<String>,
+    kind: u8,
+    target: Option<u8>,
+    target_value: Option<String>,
+    quantity: u32,
+    code_count: u32,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    /// 0 待执行，1 排队中，2 执行中，3 已完成，4 失败，5 已停止
+    status: u8,
+    run_at: Option<String>,
+    /// 已发放的张数，兑换码为已生成的数量
+    done_count: u32,
+    message: Option<String>,
+    created_at: String,
+}
+/// 发放活动列表
+#[get("/manage/mall/coupon/campaign/list/{page}/{limit}")]
+pub async fn manage_mall_coupon_campaign_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (page, limit) = query.to_owned();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("pmt_coupon_campaign", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<CouponCampaignRes> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_coupon_campaign", {
+            j0: ["coupon_id", "left", "pmt_coupon.id"],
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,title,coupon_id,pmt_coupon.coupon_name,kind,target,target_value,quantity,code_count,start_time,end_time,status,run_at,done_count,message,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CouponCampaignId {
+    id: u32,
+}
+/// 执行发放活动。批量发放、生成兑换码在后台执行，可在列表中查看进度；
+/// 失败、停止的活动再次执行时，从中断的地方继续。新用户发放的活动执行后开始发放
+#[post("/manage/mall/coupon/campaign/run")]
+pub async fn manage_mall_coupon_campaign_run(
+    mana: AuthMana,
+    params: web::Json<CouponCampaignId>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    #[derive(Deserialize)]
+    struct CampaignGet {
+        kind: u8,
+        status: u8,
+        is_del: u8,
+    }
+    let list: Vec<CampaignGet> = my_run_vec(
+        &mut conn,
+        myget!("pmt_coupon_campaign", params.id, "kind,status,is_del"),
+    )?;
+    let Some(c) = list.first().filter(|c| c.is_del == 0) else {
+        return Ok(web::Json(Res::fail("活动不存在")));
+    };
+    let runnable = [
+        CampaignStatus::Draft as u8,
+        CampaignStatus::Fail as u8,
+        CampaignStatus::Stopped as u8,
+    ];
+    if !runnable.contains(&c.status) {
+        return Ok(web::Json(Res::fail("活动正在执行或已完成")));
+    }
+    if c.kind == CampaignKind::NewUser as u8 {
+        my_run_drop(
+            &mut conn,
+            myupdate!("pmt_coupon_campaign", params.id, {
+                "status": CampaignStatus::Running as u8,
+            }),
+        )?;
+        return Ok(web::Json(Res::success("新用户登录时开始发放")));
+    }
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_coupon_campaign", params.id, {
+            "status": CampaignStatus::Queued as u8,
+            "message": "",
+        }),
+    )?;
+    // 不等待执行完成。任务正在执行时不会重复执行，排队的活动由定时任务接着处理
+    let uid = mana.id;
+    actix_web::rt::task::spawn_blocking(move || {
+        if let Err(e) = run_job(&CouponCampaignJob, JobTrigger::Manual, Some(uid)) {
+            log_err(&e, "优惠券发放活动执行出错");
+        }
+    });
+    Ok(web::Json(Res::success("已开始执行")))
+}
+
+/// 停止发放活动。批量发放的，已发放的不收回；兑换码的，未兑换的码不能再兑换
+#[put("/manage/mall/coupon/campaign/stop")]
+pub async fn manage_mall_coupon_campaign_stop(
+    _mana: AuthMana,
+    params: web::Json<CouponCampaignId>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_coupon_campaign", {"id": params.id}, {
+            "status": CampaignStatus::Stopped as u8,
+        }),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+/// 删除，只有待执行、已停止的活动可以删除
+#[put("/manage/mall/coupon/campaign/del")]
+pub async fn manage_mall_coupon_campaign_del(
+    _mana: AuthMana,
+    params: web::Json<CouponCampaignId>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let n = my_exec_drop(
+        &mut conn,
+        "update pmt_coupon_campaign set is_del = 1 where id = ? and status in (?,?)",
+        (
+            params.id,
+            CampaignStatus::Draft as u8,
+            CampaignStatus::Stopped as u8,
+        ),
+    )?;
+    if n == 0 {
+        return Ok(web::Json(Res::fail("只有待执行、已停止的活动可以删除")));
+    }
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CouponCodeRes {
+    id: u64,
+    code: String,
+    uid: Option<u64>,
+    redeemed_at: Option<String>,
+    created_at: String,
+}
+/// 活动的兑换码列表
+#[get("/manage/mall/coupon/campaign/code/list/{id}/{page}/{limit}")]
+pub async fn manage_mall_coupon_campaign_code_list(
+    _mana: AuthMana,
+    query: web::Path<(u32, u32, u32)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (id, page, limit) = query.to_owned();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("pmt_coupon_code", {
+            p0: ["campaign_id", "=", id],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<CouponCodeRes> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_coupon_code", {
+            p0: ["campaign_id", "=", id],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "id",
+            select: "id,code,uid,redeemed_at,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+/// 兑换码导出为 csv 文件：兑换码,兑换的用户,兑换时间
+fn codes_csv(codes: &[CampaignCode]) -> String {
+    let mut csv = String::from("兑换码,兑换的用户,兑换时间\n");
+    for (code, uid, redeemed_at) in codes {
+        csv += &format!(
+            "{},{},{}\n",
+            code,
+            uid.map(|u| u.to_string()).unwrap_or_default(),
+            redeemed_at.as_deref().unwrap_or("")
+        );
+    }
+    csv
+}
+
+/// 导出活动的兑换码，用于线下发放
+#[get("/manage/mall/coupon/campaign/code/export/{id}")]
+pub async fn manage_mall_coupon_campaign_code_export(
+    _mana: AuthMana,
+    query: web::Path<u32>,
+) -> Result<HttpResponse> {
+    let id = query.into_inner();
+    let codes = web::block(move || {
+        let mut conn = mysql_conn().map_err(|e| e.to_string())?;
+        campaign_codes(&mut conn, id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| error::ErrorInternalServerError(log_err(&e, "兑换码导出出错")))?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"coupon_codes_{}.csv\"", id),
+        ))
+        // 带 BOM，excel 打开时中文不乱码
+        .body(format!("\u{feff}{}", codes_csv(&codes))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_campaign() {
+        let mut p = CouponCampaignAdd {
+            id: None,
+            title: "老用户回馈".to_string(),
+            coupon_id: 1,
+            kind: CampaignKind::Grant as u8,
+            target: Some(CampaignTarget::RecentRegister as u8),
+            target_value: Some("30".to_string()),
+            quantity: None,
+            code_count: None,
+            start_time: None,
+            end_time: None,
+        };
+        assert_eq!(
+            check_campaign(&p),
+            Ok((Some(CampaignTarget::RecentRegister), Some("30".to_string())))
+        );
+        p.target = Some(CampaignTarget::All as u8);
+        assert_eq!(check_campaign(&p), Ok((Some(CampaignTarget::All), None)));
+        p.target = None;
+        assert_eq!(check_campaign(&p), Err("发放的用户范围错误"));
+        p.kind = CampaignKind::RedeemCode as u8;
+        assert_eq!(
+            check_campaign(&p),
+            Err("兑换码数量必须大于0，且不超过100000")
+        );
+        p.code_count = Some(500);
+        assert_eq!(check_campaign(&p), Ok((None, None)));
+        p.start_time = Some("2025-06-02 00:00:00".to_string());
+        p.end_time = Some("2025-06-01 00:00:00".to_string());
+        assert_eq!(check_campaign(&p), Err("结束时间必须晚于开始时间"));
+        p.end_time = Some("2025-06-03".to_string());
+        assert_eq!(check_campaign(&p), Err("时间格式错误"));
+        p.end_time = None;
+        p.kind = 9;
+        assert_eq!(check_campaign(&p), Err("活动类型错误"));
+    }
+
+    #[test]
+    fn test_codes_csv() {
+        let codes = vec![
+            ("ABCDEFGH2345".to_string(), None, None),
+            (
+                "HJKMNPQR6789".to_string(),
+                Some(12),
+                Some("2025-06-01 10:00:00".to_string()),
+            ),
+        ];
+        assert_eq!(
+            codes_csv(&codes),
+            "兑换码,兑换的用户,兑换时间\nABCDEFGH2345,,\nHJKMNPQR6789,12,2025-06-01 10:00:00\n"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+
+use actix_web::{Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::types::FreightChargeType;
+use crate::routes::Res;
+use crate::routes::utils_set::freight_set::{
+    FreightRule, check_freight_rules, freight_region_names, parse_freight_rules,
+};
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FreightTemplateAdd {
+    id: Option<u32>,
+    title: String,
+    /// 店铺的运费模板，不传或为 0 时为平台的
+    store_code: Option<u32>,
+    /// 计费方式 1固定运费 2按件数 3按重量
+    charge_type: u8,
+    /// 计费规则，regions 为空的是默认规则，需要有且只有一条
+    rules: Vec<FreightRule>,
+}
+
+/// 检查运费模板的参数，regions 为可选的地区
+fn check_freight_template(
+    params: &FreightTemplateAdd,
+    regions: &HashSet<String>,
+) -> Result<(), String> {
+    if params.title.trim().is_empty() {
+        return Err("模板名不能为空".to_string());
+    }
+    let charge_type = FreightChargeType::try_from(params.charge_type)?;
+    check_freight_rules(charge_type, &params.rules, regions)
+}
+
+/// 运费模板新增、修改。已下单的运费不受影响
+#[post("/manage/mall/freight/add")]
+pub async fn manage_mall_freight_add(
+    _mana: AuthMana,
+    params: web::Json<FreightTemplateAdd>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let regions = freight_region_names(&mut conn)?;
+    if let Err(msg) = check_freight_template(&params, &regions) {
+        return Ok(web::Json(Res::fail(&msg)));
+    }
+    let title = params.title.trim();
+    let store_code = params.store_code.filter(|x| *x > 0);
+    let rules = serde_json::to_string(&params.rules).unwrap();
+    let sql = if let Some(id) = params.id {
+        // 更新
+        myupdate!("ord_freight_template", id, {
+            "title": title,
+            "store_code": store_code,
+            "charge_type": params.charge_type,
+            "rules": &rules,
+        })
+    } else {
+        // 新增
+        myset!("ord_freight_template", {
+            "title": title,
+            "store_code": store_code,
+            "charge_type": params.charge_type,
+            "rules": &rules,
+        })
+    };
+    my_run_drop(&mut conn, sql)?;
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FreightTemplateRes {
+    id: u32,
+    title: String,
+    store_code: Option<u32>,
+    charge_type: u8,
+    rules: Vec<FreightRule>,
+    created_at: String,
+}
+/// 运费模板列表
+#[get("/manage/mall/freight/list/{page}/{limit}")]
+pub async fn manage_mall_freight_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (page, limit) = query.to_owned();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    #[derive(Deserialize)]
+    struct FreightTemplateGet {
+        id: u32,
+        title: String,
+        store_code: Option<u32>,
+        charge_type: u8,
+        rules: String,
+        created_at: String,
+    }
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("ord_freight_template", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<FreightTemplateGet> = my_run_vec(
+        &mut conn,
+        myfind!("ord_freight_template", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,title,store_code,charge_type,rules,created_at",
+        }),
+    )?;
+    let list: Vec<FreightTemplateRes> = list
+        .into_iter()
+        .map(|x| FreightTemplateRes {
+            rules: parse_freight_rules(&x.rules),
+            id: x.id,
+            title: x.title,
+            store_code: x.store_code,
+            charge_type: x.charge_type,
+            created_at: x.created_at,
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FreightTemplateDel {
+    id: u32,
+}
+/// 删除，产品或店铺还在使用的不能删除
+#[put("/manage/mall/freight/del")]
+pub async fn manage_mall_freight_del(
+    _mana: AuthMana,
+    params: web::Json<FreightTemplateDel>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let product: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("spu_product", {
+            p0: ["freight_template_id", "=", params.id],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+        }),
+    )?;
+    let store: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("com_store", {
+            p0: ["freight_template_id", "=", params.id],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+        }),
+    )?;
+    if product[0].mysql_quick_count > 0 || store[0].mysql_quick_count > 0 {
+        return Ok(web::Json(Res::fail(
+            "运费模板正在使用，请先修改产品、店铺的运费模板",
+        )));
+    }
+    my_run_drop(
+        &mut conn,
+        myupdate!("ord_freight_template", {"id": params.id}, {"is_del": 1}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_freight_template() {
+        let regions: HashSet<String> = ["广东省".to_string()].into_iter().collect();
+        let rule = |regions: Vec<&str>, first: u32, fee: &str| FreightRule {
+            regions: regions.into_iter().map(|x| x.to_string()).collect(),
+            first,
+            first_fee: fee.parse().unwrap(),
+            additional: 1,
+            additional_fee: "2".parse().unwrap(),
+            free_amount: None,
+            free_quantity: None,
+        };
+        let mut p = FreightTemplateAdd {
+            id: None,
+            title: "默认运费".to_string(),
+            store_code: None,
+            charge_type: 2,
+            rules: vec![rule(vec![], 1, "10"), rule(vec!["广东省"], 1, "6")],
+        };
+        assert_eq!(check_freight_template(&p, &regions), Ok(()));
+        p.charge_type = 4;
+        assert_eq!(
+            check_freight_template(&p, &regions),
+            Err("计费方式错误".to_string())
+        );
+        p.charge_type = 2;
+        p.rules = vec![rule(vec!["广东省"], 1, "6")];
+        assert_eq!(
+            check_freight_template(&p, &regions),
+            Err("需要有一条默认规则（不选地区），且只能有一条".to_string())
+        );
+        p.title = " ".to_string();
+        assert_eq!(
+            check_freight_template(&p, &regions),
+            Err("模板名不能为空".to_string())
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use actix_web::{Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::types::NormalStatus;
+use crate::routes::Res;
+use crate::routes::utils_set::discount_set::{ReductionTier, parse_reduction_tiers};
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FullReductionAdd {
+    id: Option<u32>,
+    title: String,
+    /// 店铺的满减，不传或为 0 时为平台满减
+    store_code: Option<u32>,
+    /// 分档，如 满300减30、满500减80
+    tiers: Vec<ReductionTier>,
+    start_time: String,
+    end_time: String,
+}
+
+/// 检查满减活动的参数，时间格式为 %Y-%m-%d %H:%M:%S
+fn check_full_reduction(params: &FullReductionAdd) -> Result<(), &'static str> {
+    let fmt = "%Y-%m-%d %H:%M:%S";
+    if params.title.trim().is_empty() {
+        return Err("活动名不能为空");
+    }
+    if params.tiers.is_empty() {
+        return Err("请设置满减的分档");
+    }
+    for (i, t) in params.tiers.iter().enumerate() {
+        if !t.reduce_amount.is_positive() || t.reduce_amount >= t.full_amount {
+            return Err("减免金额必须大于0，且小于满减金额");
+        }
+        if params.tiers[..i]
+            .iter()
+            .any(|x| x.full_amount == t.full_amount)
+        {
+            return Err("分档的满减金额不能重复");
+        }
+    }
+    let start = chrono::NaiveDateTime::parse_from_str(&params.start_time, fmt);
+    let end = chrono::NaiveDateTime::parse_from_str(&params.end_time, fmt);
+    match (start, end) {
+        (Ok(s), Ok(e)) if s < e => Ok(()),
+        (Ok(_), Ok(_)) => Err("结束时间必须晚于开始时间"),
+        _ => Err("时间格式错误"),
+    }
+}
+
+/// 满减活动新增、修改。已下单的优惠不受影响
+#[post("/manage/mall/full_reduction/add")]
+pub async fn manage_mall_full_reduction_add(
+    _mana: AuthMana,
+    params: web::Json<FullReductionAdd>,
+) -> Result<impl Responder> {
+    if let Err(msg) = check_full_reduction(&params) {
+        return Ok(web::Json(Res::fail(msg)));
+    }
+    let mut conn = mysql_conn()?;
+    let title = params.title.trim();
+    let store_code = params.store_code.filter(|x| *x > 0);
+    let mut tiers = params.tiers.clone();
+    tiers.sort_by_key(|t| t.full_amount);
+    let tiers = serde_json::to_string(&tiers).unwrap();
+    let sql = if let Some(id) = params.id {
+        // 更新
+        myupdate!("pmt_full_reduction", id, {
+            "title": title,
+            "store_code": store_code,
+            "tiers": &tiers,
+            "start_time": &params.start_time,
+            "end_time": &params.end_time,
+        })
+    } else {
+        // 新增
+        myset!("pmt_full_reduction", {
+            "title": title,
+            "store_code": store_code,
+            "tiers": &tiers,
+            "start_time": &params.start_time,
+            "end_time": &params.end_time,
+            "status": NormalStatus::Online as u8,
+        })
+    };
+    my_run_drop(&mut conn, sql)?;
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FullReductionRes {
+    id: u32,
+    title: String,
+    store_code: Option<u32>,
+    tiers: Vec<ReductionTier>,
+    start_time: String,
+    end_time: String,
+    status: i8,
+    created_at: String,
+}
+/// 满减活动列表
+#[get("/manage/mall/full_reduction/list/{page}/{limit}")]
+pub async fn manage_mall_full_reduction_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (page, limit) = query.to_owned();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    #[derive(Deserialize)]
+    struct FullReductionGet {
+        id: u32,
+        title: String,
+        store_code: Option<u32>,
+        tiers: String,
+        start_time: String,
+        end_time: String,
+        status: i8,
+        created_at: String,
+    }
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("pmt_full_reduction", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<FullReductionGet> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_full_reduction", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,title,store_code,tiers,start_time,end_time,status,created_at",
+        }),
+    )?;
+    let list: Vec<FullReductionRes> = list
+        .into_iter()
+        .map(|x| FullReductionRes {
+            tiers: parse_reduction_tiers(&x.tiers),
+            id: x.id,
+            title: x.title,
+            store_code: x.store_code,
+            start_time: x.start_time,
+            end_time: x.end_time,
+            status: x.status,
+            created_at: x.created_at,
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FullReductionDel {
+    id: u32,
+}
+/// 删除
+#[put("/manage/mall/full_reduction/del")]
+pub async fn manage_mall_full_reduction_del(
+    _mana: AuthMana,
+    params: web::Json<FullReductionDel>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_full_reduction", {"id": params.id}, {"is_del": 1}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FullReductionStatus {
+    id: u32,
+    status: i8,
+}
+/// 修改状态，2 上线，3 下线
+#[put("/manage/mall/full_reduction/status")]
+pub async fn manage_mall_full_reduction_status(
+    _mana: AuthMana,
+    params: web::Json<FullReductionStatus>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_full_reduction", {"id": params.id}, {"status": params.status}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_full_reduction() {
+        let tier = |full: &str, reduce: &str| ReductionTier {
+            full_amount: full.parse().unwrap(),
+            reduce_amount: reduce.parse().unwrap(),
+        };
+        let mut p = FullReductionAdd {
+            id: None,
+            title: "双十一".to_string(),
+            store_code: None,
+            tiers: vec![tier("300", "30"), tier("500", "80")],
+            start_time: "2025-11-11 00:00:00".to_string(),
+            end_time: "2025-11-12 00:00:00".to_string(),
+        };
+        assert_eq!(check_full_reduction(&p), Ok(()));
+        p.tiers = vec![tier("300", "30"), tier("300", "50")];
+        assert_eq!(check_full_reduction(&p), Err("分档的满减金额不能重复"));
+        p.tiers = vec![tier("30", "30")];
+        assert_eq!(
+            check_full_reduction(&p),
+            Err("减免金额必须大于0，且小于满减金额")
+        );
+        p.tiers = vec![];
+        assert_eq!(check_full_reduction(&p), Err("请设置满减的分档"));
+        p.tiers = vec![tier("300", "30")];
+        p.end_time = "2025-11-10 00:00:00".to_string();
+        assert_eq!(check_full_reduction(&p), Err("结束时间必须晚于开始时间"));
+    }
+}
@@ -0,0 +1,348 @@
+use actix_web::{Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myget, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::Money;
+use crate::common::types::NormalStatus;
+use crate::routes::Res;
+use crate::utils::time::{NowTimeType, get_now_time};
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GroupBuyEdit {
+    id: Option<u32>,
+    title: String,
+    unit_sn: u32,
+    price: Money,
+    group_size: u32,
+    group_hours: u32,
+    start_time: String,
+    end_time: String,
+}
+
+/// 检查拼团活动的参数，时间格式为 %Y-%m-%d %H:%M:%S
+fn check_group_buy(params: &GroupBuyEdit) -> Result<(), &'static str> {
+    let fmt = "%Y-%m-%d %H:%M:%S";
+    if params.title.trim().is_empty() {
+        return Err("活动名不能为空");
+    }
+    if params.price <= Money::ZERO {
+        return Err("拼团价必须大于0");
+    }
+    if params.group_size < 2 {
+        return Err("成团人数不能小于2");
+    }
+    if params.group_hours == 0 {
+        return Err("成团时限不能小于1小时");
+    }
+    let start = chrono::NaiveDateTime::parse_from_str(&params.start_time, fmt);
+    let end = chrono::NaiveDateTime::parse_from_str(&params.end_time, fmt);
+    match (start, end) {
+        (Ok(s), Ok(e)) if s < e => Ok(()),
+        (Ok(_), Ok(_)) => Err("结束时间必须晚于开始时间"),
+        _ => Err("时间格式错误"),
+    }
+}
+
+/// 拼团活动新增、修改。活动开始后不能再修改，已开的团按开团时的成团人数
+#[post("/manage/mall/group_buy/add")]
+pub async fn manage_mall_group_buy_add(
+    _mana: AuthMana,
+    params: web::Json<GroupBuyEdit>,
+) -> Result<impl Responder> {
+    if let Err(msg) = check_group_buy(&params) {
+        return Ok(web::Json(Res::fail(msg)));
+    }
+    let mut conn = mysql_conn()?;
+    let unit: Vec<serde_json::Value> = my_run_vec(
+        &mut conn,
+        myfind!("sku_unit", {
+            p0: ["unit_sn", "=", params.unit_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id",
+        }),
+    )?;
+    if unit.is_empty() {
+        return Ok(web::Json(Res::fail("商品不存在")));
+    }
+    let title = params.title.trim();
+    if let Some(id) = params.id {
+        // 更新
+        #[derive(Deserialize)]
+        struct GroupBuyGet {
+            start_time: String,
+            is_del: u8,
+        }
+        let gb: Vec<GroupBuyGet> =
+            my_run_vec(&mut conn, myget!("pmt_group_buy", id, "start_time,is_del"))?;
+        if gb.is_empty() || gb[0].is_del == 1 {
+            return Ok(web::Json(Res::fail("拼团活动不存在")));
+        }
+        if gb[0].start_time <= get_now_time(NowTimeType::DateTime) {
+            return Ok(web::Json(Res::fail("活动已开始，不能修改")));
+        }
+        my_run_drop(
+            &mut conn,
+            myupdate!("pmt_group_buy", id, {
+                "title": title,
+                "unit_sn": params.unit_sn,
+                "price": params.price.to_string(),
+                "group_size": params.group_size,
+                "group_hours": params.group_hours,
+                "start_time": &params.start_time,
+                "end_time": &params.end_time,
+            }),
+        )?;
+    } else {
+        // 新增
+        my_run_drop(
+            &mut conn,
+            myset!("pmt_group_buy", {
+                "title": title,
+                "unit_sn": params.unit_sn,
+                "price": params.price.to_string(),
+                "group_size": params.group_size,
+                "group_hours": params.group_hours,
+                "start_time": &params.start_time,
+                "end_time": &params.end_time,
+                "status": NormalStatus::UnderReview as u8,
+            }),
+        )?;
+    }
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct GroupBuyItem {
+    id: u32,
+    title: String,
+    unit_sn: u32,
+    unit_name: Option<String>,
+    price: Money,
+    group_size: u32,
+    group_hours: u32,
+    start_time: String,
+    end_time: String,
+    status: i8,
+    created_at: String,
+}
+/// 拼团活动列表
+#[get("/manage/mall/group_buy/list/{page}/{limit}")]
+pub async fn manage_mall_group_buy_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (page, limit) = query.to_owned();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("pmt_group_buy", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<GroupBuyItem> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_group_buy", {
+            j0: ["unit_sn", "left", "sku_unit.unit_sn"],
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,title,unit_sn,sku_unit.unit_name,price,group_size,group_hours,start_time,end_time,status,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupBuyDel {
+    id: u32,
+}
+/// 删除。已开的团不受影响，到期未成团的照常退款
+#[put("/manage/mall/group_buy/del")]
+pub async fn manage_mall_group_buy_del(
+    _mana: AuthMana,
+    params: web::Json<GroupBuyDel>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_group_buy", {"id": params.id}, {"is_del": 1}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupBuyStatus {
+    id: u32,
+    status: i8,
+}
+/// 修改状态
+#[put("/manage/mall/group_buy/status")]
+pub async fn manage_mall_group_buy_status(
+    _mana: AuthMana,
+    params: web::Json<GroupBuyStatus>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_group_buy", {"id": params.id}, {
+            "status": &params.status
+        }),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct GroupItem {
+    id: u64,
+    group_buy_id: u32,
+    title: String,
+    share_code: String,
+    leader_uid: u64,
+    leader_name: Option<String>,
+    group_size: u32,
+    joined: u32,
+    paid: u32,
+    status: u8,
+    expire_time: Option<String>,
+    success_time: Option<String>,
+    created_at: String,
+}
+/// 团列表，status: -1 全部，0 待开团，1 拼团中，2 已成团，3 拼团失败
+#[get("/manage/mall/group_buy/group/list/{status}/{page}/{limit}")]
+pub async fn manage_mall_group_buy_group_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (status, page, limit) = query.to_owned();
+    let status: i8 = status.to_owned().parse().unwrap();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+    // -1 时不按状态筛选
+    let r = if status < 0 { "p0" } else { "p0 && p1" };
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("ord_group", {
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: r,
+        }),
+    )?;
+    let list: Vec<GroupItem> = my_run_vec(
+        &mut conn,
+        myfind!("ord_group", {
+            j0: ["group_buy_id", "left", "pmt_group_buy.id"],
+            j1: ["leader_uid", "left", "usr_silent.id"],
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: r,
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,group_buy_id,pmt_group_buy.title,share_code,leader_uid,usr_silent.nickname as leader_name,group_size,joined,paid,status,expire_time,success_time,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct GroupMemberItem {
+    uid: u64,
+    nickname: Option<String>,
+    order_sn: String,
+    is_leader: u8,
+    status: u8,
+    created_at: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+struct GroupLogItem {
+    status: u8,
+    content: String,
+    created_at: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+struct GroupDetail {
+    members: Vec<GroupMemberItem>,
+    logs: Vec<GroupLogItem>,
+}
+/// 团的成员（含订单号和成员状态 0已取消 1待支付 2已支付 3已退款）和状态变更记录
+#[get("/manage/mall/group_buy/group/detail/{group_id}")]
+pub async fn manage_mall_group_buy_group_detail(
+    _mana: AuthMana,
+    query: web::Path<String>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let group_id: u64 = query.to_owned().parse().unwrap();
+
+    let members: Vec<GroupMemberItem> = my_run_vec(
+        &mut conn,
+        myfind!("ord_group_member", {
+            j0: ["uid", "left", "usr_silent.id"],
+            p0: ["group_id", "=", group_id],
+            r: "p0",
+            order_by: "created_at",
+            select: "uid,usr_silent.nickname,order_sn,is_leader,status,created_at",
+        }),
+    )?;
+    let logs: Vec<GroupLogItem> = my_run_vec(
+        &mut conn,
+        myfind!("ord_group_log", {
+            p0: ["group_id", "=", group_id],
+            r: "p0",
+            order_by: "created_at",
+            select: "status,content,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(GroupDetail { members, logs })))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_group_buy() {
+        let mut p = GroupBuyEdit {
+            id: None,
+            title: "两人团".to_string(),
+            unit_sn: 10000001,
+            price: "19.90".parse().unwrap(),
+            group_size: 2,
+            group_hours: 24,
+            start_time: "2025-11-11 00:00:00".to_string(),
+            end_time: "2025-11-12 00:00:00".to_string(),
+        };
+        assert_eq!(check_group_buy(&p), Ok(()));
+        p.group_size = 1;
+        assert_eq!(check_group_buy(&p), Err("成团人数不能小于2"));
+        p.group_size = 3;
+        p.group_hours = 0;
+        assert_eq!(check_group_buy(&p), Err("成团时限不能小于1小时"));
+        p.group_hours = 24;
+        p.end_time = "2025-11-10 00:00:00".to_string();
+        assert_eq!(check_group_buy(&p), Err("结束时间必须晚于开始时间"));
+    }
+}
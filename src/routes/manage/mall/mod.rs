@@ -21,3 +21,24 @@ pub use order::*;
 
 mod coupon;
 pub use coupon::*;
+
+mod coupon_campaign;
+pub use coupon_campaign::*;
+
+mod full_reduction;
+pub use full_reduction::*;
+
+mod freight;
+pub use freight::*;
+
+mod seckill;
+pub use seckill::*;
+
+mod group_buy;
+pub use group_buy::*;
+
+mod review;
+pub use review::*;
+
+mod after_sale;
+pub use after_sale::*;
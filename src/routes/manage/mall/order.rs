@@ -2,10 +2,10 @@ use actix_web::{Responder, Result, get, post, web};
 use mysql_quick::{MysqlQuickCount, TxOpts, mycount, myfind, myget, mysetmany};
 use serde::{Deserialize, Serialize};
 
-use crate::common::WECHAT_PAY_REFUND_NOTIFY_URL;
+use crate::common::Money;
 use crate::common::types::{DeliveryType, OrderItemStatus, OrderPayStatus};
 use crate::control::app_data::{AppData, SlownWorker};
-use crate::control::wx_info::wx_pay_init;
+use crate::control::payment::{RefundReq, payment};
 use crate::routes::Res;
 use crate::routes::utils_set::mall_set::{
     OrderChange, OrderChangeItems, upd_order_item_status, upd_order_status,
@@ -16,7 +16,6 @@ use crate::{
     db::{my_run_tran_drop, my_run_tran_vec, my_run_vec, mysql_conn},
     middleware::AuthMana,
 };
-use wx_pay::{Refund, RefundAmount};
 
 #[derive(Serialize, Deserialize, Clone)]
 struct OrderRes {
@@ -24,10 +23,10 @@ struct OrderRes {
     uid: u64,
     nickname: Option<String>,
     order_sn: String,
-    total_amount: f64,
+    total_amount: Money,
     total_quantity: u32,
-    pay_amount: f64,
-    reduce_amount: Option<f64>,
+    pay_amount: Money,
+    reduce_amount: Option<Money>,
     reduce_des: Option<String>,
     notes: Option<String>,
     appointment_time: Option<String>,
@@ -38,6 +37,8 @@ struct OrderRes {
     contact_user: Option<String>,
     contact_phone: Option<String>,
     transaction_id: Option<String>,
+    /// 支付回调异常（如金额不一致），不为空时需人工处理
+    pay_error: Option<String>,
     reason: Option<String>,
     status: i8,
     created_at: String,
@@ -74,9 +75,9 @@ pub async fn manage_mall_order_list(
         uid: u64,
         nickname: Option<String>,
         order_sn: String,
-        total_amount: String,
-        pay_amount: String,
-        reduce_amount: Option<String>,
+        total_amount: Money,
+        pay_amount: Money,
+        reduce_amount: Option<Money>,
         reduce_des: Option<String>,
         total_quantity: u32,
         notes: Option<String>,
@@ -88,6 +89,7 @@ pub async fn manage_mall_order_list(
         contact_user: Option<String>,
         contact_phone: Option<String>,
         transaction_id: Option<String>,
+        pay_error: Option<String>,
         reason: Option<String>,
         status: i8,
         created_at: String,
@@ -108,7 +110,7 @@ pub async fn manage_mall_order_list(
             select: "
                 id,uid,usr_silent.nickname,order_sn,total_amount,reduce_amount,reduce_des,pay_amount,notes,appointment_time,
                 total_quantity,province,city,area,addr_detail,contact_user,contact_phone,status,created_at,delivery_type,
-                transaction_id,reason
+                transaction_id,pay_error,reason
                 ",
         }),
     )?;
@@ -120,14 +122,10 @@ pub async fn manage_mall_order_list(
             uid: x.uid,
             nickname: x.nickname,
             order_sn: x.order_sn,
-            total_amount: x.total_amount.parse::<f64>().unwrap(),
-            pay_amount: x.pay_amount.parse::<f64>().unwrap(),
+            total_amount: x.total_amount,
+            pay_amount: x.pay_amount,
             total_quantity: x.total_quantity,
-            reduce_amount: if let Some(ra) = x.reduce_amount {
-                Some(ra.parse::<f64>().unwrap())
-            } else {
-                None
-            },
+            reduce_amount: x.reduce_amount,
             notes: x.notes,
             appointment_time: x.appointment_time,
             reduce_des: x.reduce_des,
@@ -139,6 +137,7 @@ pub async fn manage_mall_order_list(
             addr_detail: x.addr_detail,
             contact_user: x.contact_user,
             transaction_id: x.transaction_id,
+            pay_error: x.pay_error,
             reason: x.reason,
             contact_phone: x.contact_phone,
             delivery_type: x.delivery_type.into(),
@@ -162,7 +161,7 @@ struct OrderItemRes {
     unit_cover: String,
     unit_attr_info: Vec<UnitAttrInfo>,
     buy_quantity: u32,
-    amount: f64,
+    amount: Money,
     product_name: String,
     status: i8,
     created_at: String,
@@ -202,7 +201,7 @@ pub async fn manage_mall_order_item_list(
         unit_cover: Option<String>,
         unit_attr_info: Option<String>,
         buy_quantity: u32,
-        amount: String,
+        amount: Money,
         product_name: String,
         status: i8,
         created_at: String,
@@ -241,7 +240,7 @@ pub async fn manage_mall_order_item_list(
                 vec![]
             },
             buy_quantity: x.buy_quantity,
-            amount: x.amount.parse::<f64>().unwrap(),
+            amount: x.amount,
             product_name: x.product_name,
             unit_cover: get_file_url(x.unit_cover).unwrap_or("".to_string()),
             status: x.status,
@@ -493,7 +492,7 @@ pub async fn manage_mall_order_refund(
     #[derive(Serialize, Deserialize, Clone)]
     struct OrderInfo {
         order_sn: String,
-        pay_amount: String,
+        pay_amount: Money,
         status: i8,
         transaction_id: Option<String>,
         /// 退款原因
@@ -531,9 +530,9 @@ pub async fn manage_mall_order_refund(
         return Ok(web::Json(Res::fail("订单未支付或交易号缺失")));
     }
 
-    let refund_amount = order.pay_amount.parse::<f64>().unwrap();
+    let refund_amount = order.pay_amount;
 
-    if refund_amount == 0. {
+    if refund_amount.is_zero() {
         tran.rollback().unwrap();
         return Ok(web::Json(Res::fail("订单支付金额为0.")));
     }
@@ -586,35 +585,19 @@ pub async fn manage_mall_order_refund(
         }
     }
 
-    // 3. 初始化微信支付
-    let wx_pay = wx_pay_init();
-
-    // 4. 构建退款请求
+    // 3. 发起退款
     let out_refund_no = data.rand_no(SlownWorker::OutTradeNo); // 生成退款单号
-    let refund_request = Refund {
+    let refund_request = RefundReq {
         transaction_id: order.transaction_id.clone(),
-        out_trade_no: None,
         out_refund_no: out_refund_no.clone(),
+        amount: refund_amount,
+        // 原订单金额
+        total: refund_amount,
         reason: order.reason.clone(),
-        notify_url: Some(WECHAT_PAY_REFUND_NOTIFY_URL.to_string()),
-        funds_account: None,
-        amount: RefundAmount {
-            refund: (refund_amount * 100.0) as u64, // 转换为分
-            total: (refund_amount * 100.0) as u64,  // 原订单金额
-            currency: "CNY".to_string(),
-            from: None,
-            payer_total: None,
-            payer_refund: None,
-            settlement_refund: None,
-            settlement_total: None,
-            discount_refund: None,
-            refund_fee: None,
-        },
-        goods_detail: None,
+        ..Default::default()
     };
 
-    // 5. 调用微信退款接口
-    let _refund_result = match wx_pay.refund(&refund_request).await {
+    let refund_result = match payment().refund(&refund_request).await {
         Ok(result) => result,
         Err(e) => {
             // 退款失败，恢复订单状态
@@ -639,8 +622,8 @@ pub async fn manage_mall_order_refund(
     tran.commit().unwrap();
 
     Ok(web::Json(Res::success(format!(
-        "退款申请提交成功，退款单号: {}",
-        out_refund_no
+        "退款申请提交成功，退款单号: {}，支付渠道退款单号: {}",
+        refund_result.out_refund_no, refund_result.refund_id
     ))))
 }
 
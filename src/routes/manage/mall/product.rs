@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::common::types::{DeliveryType, OssBucket};
-use crate::common::{PRODUCT_START_SN, UNIT_START_SN};
+use crate::common::{Money, PRODUCT_START_SN, UNIT_START_SN};
 use crate::routes::{BaseInfo, BaseNumInfo, PageData, PdAttr, Res, StoreInfo};
 use crate::utils::files::{get_file_url, get_file_urls, get_path_from_url, get_path_from_urls};
 use crate::utils::html::{to_html_image_paths, to_html_image_urls};
@@ -108,7 +108,9 @@ pub struct ProductAdd {
     /// 布局方式
     product_layout: Option<String>,
     /// 产品价格
-    combined_price: Option<f64>,
+    combined_price: Option<Money>,
+    /// 运费模板，不传或为 0 时使用店铺的运费模板
+    freight_template_id: Option<u32>,
 }
 /// 【产品】新增或更新
 #[utoipa::path(
@@ -198,6 +200,7 @@ pub async fn manage_mall_product_add(
     } else {
         "null".to_string()
     };
+    let freight_template_id = params.freight_template_id.filter(|x| *x > 0);
     let sql;
     if params.product_sn >= PRODUCT_START_SN {
         // 有产品编号，则更新
@@ -221,7 +224,8 @@ pub async fn manage_mall_product_add(
             "uid": &params.uid,
             "sort": sort,
             "product_layout": &params.product_layout,
-            "combined_price": &params.combined_price,
+            "combined_price": &params.combined_price.map(|m| m.to_string()),
+            "freight_template_id": freight_template_id,
         })
     } else {
         // 新增
@@ -246,7 +250,8 @@ pub async fn manage_mall_product_add(
             "uid": &params.uid,
             "sort": sort,
             "product_layout": &params.product_layout,
-            "combined_price": &params.combined_price,
+            "combined_price": &params.combined_price.map(|m| m.to_string()),
+            "freight_template_id": freight_template_id,
         })
     }
     my_run_drop(&mut conn, sql)?;
@@ -407,7 +412,8 @@ pub struct ProductInfoRes {
     peculiarity_html: Option<String>,
     sort: Option<i32>,
     product_layout: Option<String>,
-    combined_price: Option<f64>,
+    combined_price: Option<Money>,
+    freight_template_id: Option<u32>,
 }
 /// 【产品】产品列表
 #[utoipa::path(
@@ -458,7 +464,8 @@ pub async fn manage_mall_product_list(
         peculiarity_html: Option<String>,
         sort: Option<i32>,
         product_layout: Option<String>,
-        combined_price: Option<String>,
+        combined_price: Option<Money>,
+        freight_template_id: Option<u32>,
     }
 
     let list: Vec<ProductInfoGet> = my_run_vec(
@@ -470,7 +477,7 @@ pub async fn manage_mall_product_list(
             page: page,
             limit: limit,
             order_by: "-sort,-created_at",
-            select: "id,product_sn,product_name,product_sec_name,product_des,store_code,product_cover_img,province,city,area,addr_detail,lat,lng,uid,usr_silent.phone,product_imgs,delivery_type,html,peculiarity_html,brand_code,sort,product_layout,combined_price,created_at,status",
+            select: "id,product_sn,product_name,product_sec_name,product_des,store_code,product_cover_img,province,city,area,addr_detail,lat,lng,uid,usr_silent.phone,product_imgs,delivery_type,html,peculiarity_html,brand_code,sort,product_layout,combined_price,freight_template_id,created_at,status",
         }),
     )?;
 
@@ -555,9 +562,8 @@ pub async fn manage_mall_product_list(
                 },
                 sort: x.sort,
                 product_layout: x.product_layout,
-                combined_price: x
-                    .combined_price
-                    .map_or(None, |m| Some(m.parse::<f64>().unwrap())),
+                combined_price: x.combined_price,
+                freight_template_id: x.freight_template_id,
             };
         })
         .collect();
@@ -648,13 +654,15 @@ pub struct UnitAdd {
     product_sn: u32,
     unit_sn: u32,
     unit_name: String,
-    price: f64,
+    price: Money,
+    /// 重量 g，按重量计算运费
+    weight: Option<u32>,
     quantity: u32,
     unit_cover: String,
     unit_imgs: Vec<String>,
     unit_attr: Vec<UnitAddAttr>,
-    main_sale_split: Option<f64>,
-    sale_split: Option<f64>,
+    main_sale_split: Option<Money>,
+    sale_split: Option<Money>,
     is_split: bool,
 }
 /// 新增商品
@@ -671,20 +679,12 @@ pub async fn manage_mall_product_unit_add(
     if params.unit_name.trim().is_empty() {
         return Ok(web::Json(Res::fail("请输入商品名")));
     }
-    let m_amount = if let Some(ms) = params.main_sale_split {
-        ms
-    } else {
-        0.0
-    };
-    let s_amount = if let Some(ss) = params.sale_split {
-        ss
-    } else {
-        0.0
-    };
+    let m_amount = params.main_sale_split.unwrap_or_default();
+    let s_amount = params.sale_split.unwrap_or_default();
     let is_split = if params.is_split { 1 } else { 0 };
 
     if is_split == 1 {
-        if m_amount == 0. && s_amount == 0. {
+        if m_amount.is_zero() && s_amount.is_zero() {
             return Ok(web::Json(Res::fail(
                 "总销售价和销售价的分成金额，不能同时为0",
             )));
@@ -724,12 +724,13 @@ pub async fn manage_mall_product_unit_add(
         sql = myupdate!("sku_unit", {"unit_sn": unit_sn_max}, {
             "unit_name": &params.unit_name.trim(),
             "product_sn": params.product_sn,
-            "price": params.price,
+            "price": params.price.to_string(),
+            "weight": params.weight.unwrap_or_default(),
             "quantity": params.quantity,
             "unit_cover": get_path_from_url(&params.unit_cover, &OssBucket::EobFiles),
             "unit_imgs": get_path_from_urls(&params.unit_imgs, &OssBucket::EobFiles).join(","),
-            "main_sale_split": params.main_sale_split,
-            "sale_split": params.sale_split,
+            "main_sale_split": params.main_sale_split.map(|m| m.to_string()),
+            "sale_split": params.sale_split.map(|m| m.to_string()),
             "is_split": is_split,
         })
     } else {
@@ -738,12 +739,13 @@ pub async fn manage_mall_product_unit_add(
             "unit_name": &params.unit_name.trim(),
             "unit_sn": unit_sn_max,
             "product_sn": params.product_sn,
-            "price": params.price,
+            "price": params.price.to_string(),
+            "weight": params.weight.unwrap_or_default(),
             "quantity": params.quantity,
             "unit_cover": get_path_from_url(&params.unit_cover, &OssBucket::EobFiles),
             "unit_imgs": get_path_from_urls(&params.unit_imgs, &OssBucket::EobFiles).join(","),
-            "main_sale_split": params.main_sale_split,
-            "sale_split": params.sale_split,
+            "main_sale_split": params.main_sale_split.map(|m| m.to_string()),
+            "sale_split": params.sale_split.map(|m| m.to_string()),
             "is_split": is_split,
         })
     }
@@ -798,7 +800,8 @@ pub struct UnitInfoRes {
     id: u64,
     unit_sn: u32,
     unit_name: String,
-    price: f64,
+    price: Money,
+    weight: u32,
     quantity: u32,
     product_sn: u32,
     unit_cover: String,
@@ -806,8 +809,8 @@ pub struct UnitInfoRes {
     created_at: String,
     status: i8,
     unit_attr: Vec<UnitAddAttrRes>,
-    main_sale_split: Option<f64>,
-    sale_split: Option<f64>,
+    main_sale_split: Option<Money>,
+    sale_split: Option<Money>,
     is_split: bool,
 }
 /// 获取产品的商品列表
@@ -837,14 +840,15 @@ pub async fn manage_mall_product_unit_list(
         product_sn: u32,
         unit_name: String,
         unit_sn: u32,
-        price: String,
+        price: Money,
+        weight: u32,
         quantity: u32,
         unit_cover: Option<String>,
         unit_imgs: Option<String>,
         created_at: String,
         status: i8,
-        main_sale_split: Option<String>,
-        sale_split: Option<String>,
+        main_sale_split: Option<Money>,
+        sale_split: Option<Money>,
         is_split: u8,
     }
 
@@ -857,7 +861,7 @@ pub async fn manage_mall_product_unit_list(
             page: page,
             limit: limit,
             order_by: "-created_at",
-            select: "id,product_sn,unit_sn,unit_name,price,quantity,unit_cover,unit_imgs,created_at,status,main_sale_split,sale_split,is_split",
+            select: "id,product_sn,unit_sn,unit_name,price,weight,quantity,unit_cover,unit_imgs,created_at,status,main_sale_split,sale_split,is_split",
         }),
     )?;
 
@@ -899,19 +903,16 @@ pub async fn manage_mall_product_unit_list(
                 product_sn: x.product_sn,
                 unit_sn: x.unit_sn,
                 unit_name: x.unit_name,
-                price: x.price.parse::<f64>().unwrap(),
+                price: x.price,
+                weight: x.weight,
                 quantity: x.quantity,
                 unit_cover: get_file_url(Some(&temp_cover)).unwrap_or("".to_string()),
                 unit_imgs: get_file_urls(Some(&temp_imgs)),
                 created_at: x.created_at,
                 status: x.status,
                 unit_attr: pattr,
-                main_sale_split: x
-                    .main_sale_split
-                    .map_or(None, |m| Some(m.parse::<f64>().unwrap())),
-                sale_split: x
-                    .sale_split
-                    .map_or(None, |s| Some(s.parse::<f64>().unwrap())),
+                main_sale_split: x.main_sale_split,
+                sale_split: x.sale_split,
                 is_split: if x.is_split == 1 { true } else { false },
             };
         })
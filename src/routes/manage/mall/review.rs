@@ -0,0 +1,268 @@
+use actix_web::{Error, Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, PooledConn, TxOpts, mycount, myfind, myupdate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::PageData;
+use crate::common::types::NormalStatus;
+use crate::db::{in_placeholders, my_exec_tran_drop, my_exec_vec};
+use crate::routes::Res;
+use crate::utils::files::{get_file_url, get_file_urls};
+use crate::utils::time::{NowTimeType, get_now_time};
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ReviewItem {
+    id: u32,
+    uid: u64,
+    nickname: Option<String>,
+    order_item_id: String,
+    product_sn: u32,
+    unit_name: Option<String>,
+    rating: u8,
+    store_rating: Option<u8>,
+    delivery_rating: Option<u8>,
+    content: String,
+    imgs: Vec<String>,
+    /// 微信检测有风险的图片
+    risky_imgs: Vec<String>,
+    reply: Option<String>,
+    reply_at: Option<String>,
+    status: u8,
+    created_at: String,
+}
+/// 评价列表，status: -1 全部，0 审核不通过，1 待审核，2 正常显示，3 已隐藏
+#[get("/manage/mall/review/list/{status}/{page}/{limit}")]
+pub async fn manage_mall_review_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (status, page, limit) = query.to_owned();
+    let status: i8 = status.to_owned().parse().unwrap();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+    // -1 时不按状态筛选
+    let r = if status < 0 { "p0" } else { "p0 && p1" };
+
+    #[derive(Deserialize)]
+    struct ReviewGet {
+        id: u32,
+        uid: u64,
+        nickname: Option<String>,
+        order_item_id: String,
+        product_sn: u32,
+        unit_name: Option<String>,
+        rating: u8,
+        store_rating: Option<u8>,
+        delivery_rating: Option<u8>,
+        content: String,
+        imgs: Option<String>,
+        reply: Option<String>,
+        reply_at: Option<String>,
+        status: u8,
+        created_at: String,
+    }
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("eva_product", {
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: r,
+        }),
+    )?;
+    let list: Vec<ReviewGet> = my_run_vec(
+        &mut conn,
+        myfind!("eva_product", {
+            j0: ["uid", "left", "usr_silent.id"],
+            j1: ["order_item_id", "left", "eva_com_store.order_item_id"],
+            j2: ["order_item_id", "left", "eva_delivery.order_item_id"],
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: r,
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,uid,usr_silent.nickname,order_item_id,product_sn,unit_name,rating,eva_com_store.rating as store_rating,eva_delivery.rating as delivery_rating,content,imgs,reply,reply_at,status,created_at",
+        }),
+    )?;
+
+    let paths: Vec<String> = list
+        .iter()
+        .filter_map(|x| x.imgs.as_deref())
+        .flat_map(|x| x.split(','))
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect();
+    let risky = get_risky_paths(&mut conn, paths)?;
+
+    let list: Vec<ReviewItem> = list
+        .into_iter()
+        .map(|x| {
+            let imgs = x.imgs.unwrap_or_default();
+            ReviewItem {
+                id: x.id,
+                uid: x.uid,
+                nickname: x.nickname,
+                order_item_id: x.order_item_id,
+                product_sn: x.product_sn,
+                unit_name: x.unit_name,
+                rating: x.rating,
+                store_rating: x.store_rating,
+                delivery_rating: x.delivery_rating,
+                content: x.content,
+                risky_imgs: imgs
+                    .split(',')
+                    .filter(|p| risky.contains(*p))
+                    .filter_map(|p| get_file_url(Some(p)))
+                    .collect(),
+                imgs: get_file_urls(Some(&imgs)),
+                reply: x.reply,
+                reply_at: x.reply_at,
+                status: x.status,
+                created_at: x.created_at,
+            }
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+/// 微信异步检测结果为有风险的文件路径
+fn get_risky_paths(conn: &mut PooledConn, paths: Vec<String>) -> Result<HashSet<String>, Error> {
+    if paths.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let sql = format!(
+        "select local_path from check_list where suggest = 'risky' and local_path in ({})",
+        in_placeholders(paths.len())
+    );
+    let list: Vec<String> = my_exec_vec(conn, &sql, paths)?;
+    Ok(list.into_iter().collect())
+}
+
+/// 修改评价的状态，店铺、物流评分跟随商品评价。评价当前不是 from 状态时返回 false
+fn upd_review_status(
+    conn: &mut PooledConn,
+    id: u32,
+    from: NormalStatus,
+    to: NormalStatus,
+) -> Result<bool, Error> {
+    let to = to as u8;
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    let affected = match my_exec_tran_drop(
+        &mut tran,
+        "update eva_product set status = ? where id = ? and status = ? and is_del = 0",
+        (to, id, from as u8),
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    if affected == 0 {
+        tran.rollback().unwrap();
+        return Ok(false);
+    }
+    for sql in [
+        "update eva_com_store set status = ? where order_item_id = (select order_item_id from eva_product where id = ?)",
+        "update eva_delivery set status = ? where order_item_id = (select order_item_id from eva_product where id = ?)",
+    ] {
+        if let Err(e) = my_exec_tran_drop(&mut tran, sql, (to, id)) {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    }
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+    Ok(true)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewAudit {
+    id: u32,
+    /// true 审核通过并显示，false 审核不通过
+    pass: bool,
+}
+/// 审核待审核的评价
+#[put("/manage/mall/review/audit")]
+pub async fn manage_mall_review_audit(
+    _mana: AuthMana,
+    params: web::Json<ReviewAudit>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let to = if params.pass {
+        NormalStatus::Online
+    } else {
+        NormalStatus::NotPass
+    };
+    if !upd_review_status(&mut conn, params.id, NormalStatus::UnderReview, to)? {
+        return Ok(web::Json(Res::fail("评价不是待审核状态")));
+    }
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewHide {
+    id: u32,
+    /// true 隐藏，false 恢复显示
+    hide: bool,
+}
+/// 隐藏或恢复显示已审核通过的评价
+#[put("/manage/mall/review/hide")]
+pub async fn manage_mall_review_hide(
+    _mana: AuthMana,
+    params: web::Json<ReviewHide>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (from, to) = if params.hide {
+        (NormalStatus::Online, NormalStatus::OffShelf)
+    } else {
+        (NormalStatus::OffShelf, NormalStatus::Online)
+    };
+    if !upd_review_status(&mut conn, params.id, from, to)? {
+        return Ok(web::Json(Res::fail(if params.hide {
+            "评价不是显示状态"
+        } else {
+            "评价不是隐藏状态"
+        })));
+    }
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewReply {
+    id: u32,
+    reply: String,
+}
+/// 回复评价，重复回复时覆盖
+#[post("/manage/mall/review/reply")]
+pub async fn manage_mall_review_reply(
+    _mana: AuthMana,
+    params: web::Json<ReviewReply>,
+) -> Result<impl Responder> {
+    let reply = params.reply.trim();
+    if reply.is_empty() {
+        return Ok(web::Json(Res::fail("回复内容不能为空")));
+    }
+    if reply.chars().count() > 500 {
+        return Ok(web::Json(Res::fail("回复内容不能超过500字")));
+    }
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("eva_product", {"id": params.id}, {
+            "reply": reply,
+            "reply_at": get_now_time(NowTimeType::DateTime),
+        }),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
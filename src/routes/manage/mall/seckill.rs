@@ -0,0 +1,241 @@
+use actix_web::{Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myget, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::Money;
+use crate::common::types::NormalStatus;
+use crate::control::seckill::seckill_reset;
+use crate::routes::Res;
+use crate::utils::time::{NowTimeType, get_now_time};
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SeckillAdd {
+    id: Option<u32>,
+    title: String,
+    unit_sn: u32,
+    price: Money,
+    stock: u32,
+    limit_per_user: u32,
+    start_time: String,
+    end_time: String,
+}
+
+/// 检查秒杀活动的参数，时间格式为 %Y-%m-%d %H:%M:%S
+fn check_seckill(params: &SeckillAdd) -> Result<(), &'static str> {
+    let fmt = "%Y-%m-%d %H:%M:%S";
+    if params.title.trim().is_empty() {
+        return Err("活动名不能为空");
+    }
+    if params.price <= Money::ZERO {
+        return Err("秒杀价必须大于0");
+    }
+    if params.stock == 0 {
+        return Err("秒杀库存必须大于0");
+    }
+    if params.limit_per_user == 0 {
+        return Err("每人限购数量不能小于1");
+    }
+    let start = chrono::NaiveDateTime::parse_from_str(&params.start_time, fmt);
+    let end = chrono::NaiveDateTime::parse_from_str(&params.end_time, fmt);
+    match (start, end) {
+        (Ok(s), Ok(e)) if s < e => Ok(()),
+        (Ok(_), Ok(_)) => Err("结束时间必须晚于开始时间"),
+        _ => Err("时间格式错误"),
+    }
+}
+
+/// 秒杀活动新增、修改。活动开始后不能再修改
+#[post("/manage/mall/seckill/add")]
+pub async fn manage_mall_seckill_add(
+    _mana: AuthMana,
+    params: web::Json<SeckillAdd>,
+) -> Result<impl Responder> {
+    if let Err(msg) = check_seckill(&params) {
+        return Ok(web::Json(Res::fail(msg)));
+    }
+    let mut conn = mysql_conn()?;
+    let unit: Vec<serde_json::Value> = my_run_vec(
+        &mut conn,
+        myfind!("sku_unit", {
+            p0: ["unit_sn", "=", params.unit_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id",
+        }),
+    )?;
+    if unit.is_empty() {
+        return Ok(web::Json(Res::fail("商品不存在")));
+    }
+    let title = params.title.trim();
+    if let Some(id) = params.id {
+        // 更新
+        #[derive(Deserialize)]
+        struct SeckillGet {
+            start_time: String,
+            is_del: u8,
+        }
+        let sk: Vec<SeckillGet> =
+            my_run_vec(&mut conn, myget!("pmt_sec_kill", id, "start_time,is_del"))?;
+        if sk.is_empty() || sk[0].is_del == 1 {
+            return Ok(web::Json(Res::fail("秒杀活动不存在")));
+        }
+        if sk[0].start_time <= get_now_time(NowTimeType::DateTime) {
+            return Ok(web::Json(Res::fail("活动已开始，不能修改")));
+        }
+        my_run_drop(
+            &mut conn,
+            myupdate!("pmt_sec_kill", id, {
+                "title": title,
+                "unit_sn": params.unit_sn,
+                "price": params.price.to_string(),
+                "stock": params.stock,
+                "limit_per_user": params.limit_per_user,
+                "start_time": &params.start_time,
+                "end_time": &params.end_time,
+            }),
+        )?;
+        // 库存有变化，下次抢购时重新初始化
+        seckill_reset(id)?;
+    } else {
+        // 新增
+        my_run_drop(
+            &mut conn,
+            myset!("pmt_sec_kill", {
+                "title": title,
+                "unit_sn": params.unit_sn,
+                "price": params.price.to_string(),
+                "stock": params.stock,
+                "limit_per_user": params.limit_per_user,
+                "start_time": &params.start_time,
+                "end_time": &params.end_time,
+                "status": NormalStatus::UnderReview as u8,
+            }),
+        )?;
+    }
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SeckillRes {
+    id: u32,
+    title: String,
+    unit_sn: u32,
+    unit_name: Option<String>,
+    price: Money,
+    stock: u32,
+    sold: u32,
+    limit_per_user: u32,
+    start_time: String,
+    end_time: String,
+    status: i8,
+    created_at: String,
+}
+/// 秒杀活动列表
+#[get("/manage/mall/seckill/list/{page}/{limit}")]
+pub async fn manage_mall_seckill_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (page, limit) = query.to_owned();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("pmt_sec_kill", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<SeckillRes> = my_run_vec(
+        &mut conn,
+        myfind!("pmt_sec_kill", {
+            j0: ["unit_sn", "left", "sku_unit.unit_sn"],
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,title,unit_sn,sku_unit.unit_name,price,stock,sold,limit_per_user,start_time,end_time,status,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SeckillDel {
+    id: u32,
+}
+/// 删除
+#[put("/manage/mall/seckill/del")]
+pub async fn manage_mall_seckill_del(
+    _mana: AuthMana,
+    params: web::Json<SeckillDel>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_sec_kill", {"id": params.id}, {"is_del": 1}),
+    )?;
+    seckill_reset(params.id)?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SeckillStatus {
+    id: u32,
+    status: i8,
+}
+/// 修改状态
+#[put("/manage/mall/seckill/status")]
+pub async fn manage_mall_seckill_status(
+    _mana: AuthMana,
+    params: web::Json<SeckillStatus>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("pmt_sec_kill", {"id": params.id}, {
+            "status": &params.status
+        }),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_seckill() {
+        let mut p = SeckillAdd {
+            id: None,
+            title: "双十一".to_string(),
+            unit_sn: 10000001,
+            price: "9.90".parse().unwrap(),
+            stock: 100,
+            limit_per_user: 1,
+            start_time: "2025-11-11 00:00:00".to_string(),
+            end_time: "2025-11-11 02:00:00".to_string(),
+        };
+        assert_eq!(check_seckill(&p), Ok(()));
+        p.end_time = "2025-11-10 02:00:00".to_string();
+        assert_eq!(check_seckill(&p), Err("结束时间必须晚于开始时间"));
+        p.end_time = "2025-11-11".to_string();
+        assert_eq!(check_seckill(&p), Err("时间格式错误"));
+        p.end_time = "2025-11-11 02:00:00".to_string();
+        p.limit_per_user = 0;
+        assert_eq!(check_seckill(&p), Err("每人限购数量不能小于1"));
+    }
+}
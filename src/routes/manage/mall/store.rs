@@ -30,6 +30,8 @@ pub struct StoreAdd {
     addr_info: Option<StoreAddrInfo>,
     province_info: Option<[String; 3]>,
     html: Option<String>,
+    /// 店铺商品默认的运费模板，不传或为 0 时包邮
+    freight_template_id: Option<u32>,
 }
 /// 【公司店铺】新增或更新
 #[utoipa::path(
@@ -119,6 +121,7 @@ pub async fn manage_mall_store_add(
             "addr_detail": &temp_addr_detail,
             "lat": &temp_lat,
             "lng": &temp_lng,
+            "freight_template_id": params.freight_template_id.filter(|x| *x > 0),
         })
     } else {
         // 新增
@@ -136,6 +139,7 @@ pub async fn manage_mall_store_add(
             "addr_detail": &temp_addr_detail,
             "lat": &temp_lat,
             "lng": &temp_lng,
+            "freight_template_id": params.freight_template_id.filter(|x| *x > 0),
         })
     }
     my_run_drop(&mut conn, sql)?;
@@ -159,6 +163,7 @@ pub struct StoreInfo {
     lat: Option<f64>,
     lng: Option<f64>,
     com_store_type: Option<u8>,
+    freight_template_id: Option<u32>,
     status: i8,
     created_at: String,
 }
@@ -200,6 +205,7 @@ pub async fn manage_mall_store_list(
         lat: Option<f64>,
         lng: Option<f64>,
         com_store_type: Option<u8>,
+        freight_template_id: Option<u32>,
         status: i8,
         created_at: String,
     }
@@ -211,7 +217,7 @@ pub async fn manage_mall_store_list(
             page: page,
             limit: limit,
             order_by: "-created_at",
-            select: "id,code,name,des,cover_img,imgs,html,province,city,area,addr_detail,lat,lng,com_store_type,status,created_at",
+            select: "id,code,name,des,cover_img,imgs,html,province,city,area,addr_detail,lat,lng,com_store_type,freight_template_id,status,created_at",
         }),
     )?;
 
@@ -239,6 +245,7 @@ pub async fn manage_mall_store_list(
                 lat: x.lat,
                 lng: x.lng,
                 com_store_type: x.com_store_type,
+                freight_template_id: x.freight_template_id,
                 status: x.status,
                 created_at: x.created_at,
             };
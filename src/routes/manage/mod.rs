@@ -1,9 +1,18 @@
 mod user;
 pub use user::*;
 
+mod recharge;
+pub use recharge::*;
+
+mod pocket;
+pub use pocket::*;
+
 mod system;
 pub use system::*;
 
+mod job;
+pub use job::*;
+
 mod common;
 pub use common::*;
 
@@ -0,0 +1,131 @@
+use actix_web::{Responder, Result, error, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::Money;
+use crate::common::types::{JobTrigger, NormalStatus};
+use crate::control::jobs::{PocketReconcileJob, run_job};
+use crate::db::{my_run_drop, my_run_vec, mysql_conn};
+use crate::middleware::{AuthMana, AuthSuperMana};
+use crate::routes::Res;
+use crate::utils::filter::deserialize_path_to_url;
+use crate::utils::utils::log_err;
+
+/// 手动执行一次零钱对账，与定时对账共用任务锁，执行完成后返回执行记录。
+/// 发现的问题在对账问题列表中查看
+#[post("/manage/user/pocket/reconcile")]
+pub async fn manage_user_pocket_reconcile(super_mana: AuthSuperMana) -> Result<impl Responder> {
+    let uid = super_mana.id;
+    let run = web::block(move || {
+        run_job(&PocketReconcileJob, JobTrigger::Manual, Some(uid))
+            .map_err(|e| log_err(&e, "零钱对账出错"))
+    })
+    .await
+    .map_err(|e| error::ErrorInternalServerError(log_err(&e, "零钱对账出错")))?
+    .map_err(error::ErrorInternalServerError)?;
+    match run {
+        Some(r) => Ok(web::Json(Res::success(r))),
+        None => Ok(web::Json(Res::fail("零钱对账正在执行中，请稍后再试"))),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PocketAuditItem {
+    id: u64,
+    batch: String,
+    uid: u64,
+    #[serde(deserialize_with = "deserialize_path_to_url")]
+    avatar_url: String,
+    nickname: Option<String>,
+    /// 1 钱包金额的 hash 不符，2 余额与交易记录合计不符，3 交易记录的 hash 不符
+    kind: u8,
+    record_id: Option<u64>,
+    /// 钱包余额，kind 为 3 时为交易金额
+    amount: Money,
+    /// kind 为 2 时，交易记录的合计
+    expected_amount: Option<Money>,
+    /// 本次对账是否冻结了该钱包
+    is_frozen: u8,
+    /// 钱包当前的状态，2 正常，3 冻结
+    pocket_status: Option<u8>,
+    created_at: String,
+}
+/// 零钱对账发现的问题，uid 为 0 时查全部
+#[get("/manage/user/pocket/audit/list/{uid}/{page}/{limit}")]
+pub async fn manage_user_pocket_audit_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (uid, page, limit) = query.to_owned();
+    let uid: u64 = uid.to_owned().parse().unwrap();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("usr_pocket_audit", {
+            p0: ["uid", "=", uid],
+            p1: ["id", ">", 0],
+            r: if uid == 0 { "p1" } else { "p0 && p1" },
+        }),
+    )?;
+    let list: Vec<PocketAuditItem> = my_run_vec(
+        &mut conn,
+        myfind!("usr_pocket_audit", {
+            j0: ["uid", "inner", "usr_silent.id"],
+            j1: ["uid", "left", "usr_pocket_money.uid"],
+            p0: ["uid", "=", uid],
+            p1: ["id", ">", 0],
+            r: if uid == 0 { "p1" } else { "p0 && p1" },
+            page: page,
+            limit: limit,
+            order_by: "-id",
+            select: "id,batch,uid,usr_silent.avatar_url,usr_silent.nickname,kind,record_id,amount,
+                expected_amount,is_frozen,usr_pocket_money.status as pocket_status,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PocketStatusChange {
+    uid: u64,
+    /// 2 解冻，3 冻结
+    status: u8,
+}
+/// 用户钱包冻结、解冻。对账的问题处理后再解冻，余额仍与交易记录不符的，下次对账会再次冻结
+#[put("/manage/user/pocket/status")]
+pub async fn manage_user_pocket_status(
+    _super_mana: AuthSuperMana,
+    params: web::Json<PocketStatusChange>,
+) -> Result<impl Responder> {
+    if params.status != NormalStatus::Online as u8 && params.status != NormalStatus::OffShelf as u8
+    {
+        return Ok(web::Json(Res::fail("状态值只能为2、3")));
+    }
+    let mut conn = mysql_conn()?;
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("usr_pocket_money", {
+            p0: ["uid", "=", params.uid],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+        }),
+    )?;
+    if count[0].mysql_quick_count == 0 {
+        return Ok(web::Json(Res::fail("未找到用户的钱包")));
+    }
+    my_run_drop(
+        &mut conn,
+        myupdate!("usr_pocket_money", {"uid": params.uid}, {
+            "status": params.status,
+        }),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
@@ -0,0 +1,197 @@
+use actix_web::{Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, mycount, myfind, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::Money;
+use crate::common::types::NormalStatus;
+use crate::routes::Res;
+use crate::utils::filter::deserialize_path_to_url;
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RechargeAmountEdit {
+    /// 有 id 为更新，没有为新增
+    id: Option<u32>,
+    title: Option<String>,
+    /// 充值金额
+    amount: Money,
+    /// 赠送金额，不赠送为 0
+    #[serde(default)]
+    bonus_amount: Money,
+}
+/// 零钱充值金额新增、修改。已创建的充值单按创建时的金额到账
+#[post("/manage/user/recharge_amount/add")]
+pub async fn manage_user_recharge_amount_add(
+    _mana: AuthMana,
+    params: web::Json<RechargeAmountEdit>,
+) -> Result<impl Responder> {
+    if !params.amount.is_positive() {
+        return Ok(web::Json(Res::fail("充值金额必须大于0")));
+    }
+    if params.bonus_amount.is_negative() {
+        return Ok(web::Json(Res::fail("赠送金额不能小于0")));
+    }
+    let mut conn = mysql_conn()?;
+    let sql = if let Some(id) = params.id {
+        myupdate!("sys_recharge_amount", id, {
+            "title": &params.title,
+            "amount": params.amount.to_string(),
+            "bonus_amount": params.bonus_amount.to_string(),
+        })
+    } else {
+        myset!("sys_recharge_amount", {
+            "title": &params.title,
+            "amount": params.amount.to_string(),
+            "bonus_amount": params.bonus_amount.to_string(),
+            "status": NormalStatus::Online as u8,
+        })
+    };
+    my_run_drop(&mut conn, sql)?;
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RechargeAmountItem {
+    id: u32,
+    title: Option<String>,
+    amount: Money,
+    bonus_amount: Money,
+    status: i8,
+    created_at: String,
+}
+/// 零钱充值金额列表
+#[get("/manage/user/recharge_amount/list/{page}/{limit}")]
+pub async fn manage_user_recharge_amount_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (page, limit) = query.to_owned();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("sys_recharge_amount", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+        }),
+    )?;
+    let list: Vec<RechargeAmountItem> = my_run_vec(
+        &mut conn,
+        myfind!("sys_recharge_amount", {
+            p0: ["is_del", "=", 0],
+            r: "p0",
+            page: page,
+            limit: limit,
+            order_by: "amount",
+            select: "id,title,amount,bonus_amount,status,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RechargeAmountDel {
+    id: u32,
+}
+/// 零钱充值金额删除
+#[put("/manage/user/recharge_amount/del")]
+pub async fn manage_user_recharge_amount_del(
+    _mana: AuthMana,
+    params: web::Json<RechargeAmountDel>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("sys_recharge_amount", {"id": params.id}, {"is_del": 1}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RechargeAmountStatus {
+    id: u32,
+    status: i8,
+}
+/// 零钱充值金额状态修改，2 上线，3 下线。下线的不在充值选项中显示
+#[put("/manage/user/recharge_amount/status")]
+pub async fn manage_user_recharge_amount_status(
+    _mana: AuthMana,
+    params: web::Json<RechargeAmountStatus>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("sys_recharge_amount", {"id": params.id}, {
+            "status": params.status,
+        }),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RechargeItem {
+    id: u64,
+    uid: u64,
+    #[serde(deserialize_with = "deserialize_path_to_url")]
+    avatar_url: String,
+    nickname: Option<String>,
+    out_trade_no: String,
+    amount: Money,
+    bonus_amount: Money,
+    transaction_id: Option<String>,
+    pay_time: Option<String>,
+    /// 0已取消 1待支付 2已到账
+    status: u8,
+    created_at: String,
+}
+/// 零钱充值记录，status 为 -1 时查全部
+#[get("/manage/user/recharge/list/{status}/{page}/{limit}")]
+pub async fn manage_user_recharge_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (status, page, limit) = query.to_owned();
+    let status: i8 = status.to_owned().parse().unwrap();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("usr_recharge", {
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: if status == -1 { "p0" } else { "p0 && p1" },
+        }),
+    )?;
+    let list: Vec<RechargeItem> = my_run_vec(
+        &mut conn,
+        myfind!("usr_recharge", {
+            j0: ["uid", "inner", "usr_silent.id"],
+            p0: ["is_del", "=", 0],
+            p1: ["status", "=", status],
+            r: if status == -1 { "p0" } else { "p0 && p1" },
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,uid,usr_silent.avatar_url,usr_silent.nickname,out_trade_no,amount,bonus_amount,
+                transaction_id,pay_time,status,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
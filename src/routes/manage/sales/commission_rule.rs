@@ -0,0 +1,408 @@
+use actix_web::{Responder, Result, get, post, put, web};
+use mysql_quick::{MysqlQuickCount, TxOpts, mycount, myfind, myget, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::PageData;
+use crate::common::Money;
+use crate::common::types::{CommissionScope, CommissionSplitType, NormalStatus};
+use crate::routes::Res;
+use crate::routes::utils_set::after_sale_set::item_refund_amount;
+use crate::routes::utils_set::commission_rule_set::{
+    CommissionTier, UnitSplit, get_split_context, match_rule, parse_tiers,
+};
+use crate::{
+    db::{my_run_drop, my_run_vec, mysql_conn},
+    middleware::AuthMana,
+};
+
+/// 百分比的分成不能超过 100%
+const PERCENT_MAX: Money = Money::from_cent(10000);
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CommissionRuleEdit {
+    id: Option<u32>,
+    name: String,
+    /// 1 产品，2 分类，3 品牌，4 店铺
+    scope_type: u8,
+    /// 产品为 product_sn，分类为分类id，品牌为 brand_code，店铺为 store_code
+    scope_id: u32,
+    /// 1 按实付金额的百分比，2 每件固定金额
+    split_type: u8,
+    main_sale_value: Money,
+    sale_value: Money,
+    /// 按月销售额分档，不分档时为空
+    #[serde(default)]
+    tiers: Vec<CommissionTier>,
+}
+
+/// 检查佣金规则的参数
+fn check_commission_rule(params: &CommissionRuleEdit) -> Result<(), &'static str> {
+    let scopes = [
+        CommissionScope::Product,
+        CommissionScope::Category,
+        CommissionScope::Brand,
+        CommissionScope::Store,
+    ];
+    if !scopes.iter().any(|s| *s as u8 == params.scope_type) {
+        return Err("适用范围错误");
+    }
+    if params.scope_id == 0 {
+        return Err("请选择适用的产品、分类、品牌或店铺");
+    }
+    let is_percent = params.split_type == CommissionSplitType::Percent as u8;
+    if !is_percent && params.split_type != CommissionSplitType::Fixed as u8 {
+        return Err("计算方式错误");
+    }
+    let check_value = |v: Money| !v.is_negative() && (!is_percent || v <= PERCENT_MAX);
+    if !check_value(params.main_sale_value) || !check_value(params.sale_value) {
+        return Err("分成不能小于0，百分比不能大于100");
+    }
+    for (i, t) in params.tiers.iter().enumerate() {
+        if !t.min_volume.is_positive() {
+            return Err("分档的月销售额必须大于0");
+        }
+        if !check_value(t.main_sale_value) || !check_value(t.sale_value) {
+            return Err("分档的分成不能小于0，百分比不能大于100");
+        }
+        if params.tiers[..i]
+            .iter()
+            .any(|x| x.min_volume == t.min_volume)
+        {
+            return Err("分档的月销售额不能重复");
+        }
+    }
+    Ok(())
+}
+
+/// 佣金规则新增、修改。同一产品、分类、品牌或店铺只能有一条规则
+#[post("/manage/sales/commission_rule/add")]
+pub async fn manage_sales_commission_rule_add(
+    _mana: AuthMana,
+    params: web::Json<CommissionRuleEdit>,
+) -> Result<impl Responder> {
+    if let Err(msg) = check_commission_rule(&params) {
+        return Ok(web::Json(Res::fail(msg)));
+    }
+    let mut conn = mysql_conn()?;
+    #[derive(Deserialize)]
+    struct RuleGet {
+        id: u32,
+    }
+    let same: Vec<RuleGet> = my_run_vec(
+        &mut conn,
+        myfind!("sal_commission_rule", {
+            p0: ["scope_type", "=", params.scope_type],
+            p1: ["scope_id", "=", params.scope_id],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "id",
+        }),
+    )?;
+    if same.iter().any(|x| Some(x.id) != params.id) {
+        return Ok(web::Json(Res::fail("已有相同适用范围的规则")));
+    }
+    let name = params.name.trim();
+    let tiers = if params.tiers.is_empty() {
+        "null".to_string()
+    } else {
+        serde_json::to_string(&params.tiers).unwrap()
+    };
+    if let Some(id) = params.id {
+        // 更新
+        #[derive(Deserialize)]
+        struct RuleDel {
+            is_del: u8,
+        }
+        let rule: Vec<RuleDel> =
+            my_run_vec(&mut conn, myget!("sal_commission_rule", id, "is_del"))?;
+        if rule.is_empty() || rule[0].is_del == 1 {
+            return Ok(web::Json(Res::fail("规则不存在")));
+        }
+        my_run_drop(
+            &mut conn,
+            myupdate!("sal_commission_rule", id, {
+                "name": name,
+                "scope_type": params.scope_type,
+                "scope_id": params.scope_id,
+                "split_type": params.split_type,
+                "main_sale_value": params.main_sale_value.to_string(),
+                "sale_value": params.sale_value.to_string(),
+                "tiers": &tiers,
+            }),
+        )?;
+    } else {
+        // 新增
+        my_run_drop(
+            &mut conn,
+            myset!("sal_commission_rule", {
+                "name": name,
+                "scope_type": params.scope_type,
+                "scope_id": params.scope_id,
+                "split_type": params.split_type,
+                "main_sale_value": params.main_sale_value.to_string(),
+                "sale_value": params.sale_value.to_string(),
+                "tiers": &tiers,
+                "status": NormalStatus::Online as u8,
+            }),
+        )?;
+    }
+
+    Ok(web::Json(Res::success("")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CommissionRuleItem {
+    id: u32,
+    name: String,
+    scope_type: u8,
+    scope_id: u32,
+    split_type: u8,
+    main_sale_value: Money,
+    sale_value: Money,
+    tiers: Vec<CommissionTier>,
+    status: i8,
+    created_at: String,
+}
+/// 佣金规则列表，scope_type: 0 全部，1 产品，2 分类，3 品牌，4 店铺
+#[get("/manage/sales/commission_rule/list/{scope_type}/{page}/{limit}")]
+pub async fn manage_sales_commission_rule_list(
+    _mana: AuthMana,
+    query: web::Path<(String, String, String)>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let (scope_type, page, limit) = query.to_owned();
+    let scope_type: u8 = scope_type.to_owned().parse().unwrap();
+    let page: u32 = page.to_owned().parse().unwrap();
+    let limit: u32 = limit.to_owned().parse().unwrap();
+    let r = if scope_type == 0 { "p0" } else { "p0 && p1" };
+
+    #[derive(Deserialize)]
+    struct RuleGet {
+        id: u32,
+        name: String,
+        scope_type: u8,
+        scope_id: u32,
+        split_type: u8,
+        main_sale_value: Money,
+        sale_value: Money,
+        tiers: Option<String>,
+        status: i8,
+        created_at: String,
+    }
+    let count: Vec<MysqlQuickCount> = my_run_vec(
+        &mut conn,
+        mycount!("sal_commission_rule", {
+            p0: ["is_del", "=", 0],
+            p1: ["scope_type", "=", scope_type],
+            r: r,
+        }),
+    )?;
+    let list: Vec<RuleGet> = my_run_vec(
+        &mut conn,
+        myfind!("sal_commission_rule", {
+            p0: ["is_del", "=", 0],
+            p1: ["scope_type", "=", scope_type],
+            r: r,
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,name,scope_type,scope_id,split_type,main_sale_value,sale_value,tiers,status,created_at",
+        }),
+    )?;
+    let list: Vec<CommissionRuleItem> = list
+        .into_iter()
+        .map(|x| CommissionRuleItem {
+            tiers: parse_tiers(x.tiers.as_deref()),
+            id: x.id,
+            name: x.name,
+            scope_type: x.scope_type,
+            scope_id: x.scope_id,
+            split_type: x.split_type,
+            main_sale_value: x.main_sale_value,
+            sale_value: x.sale_value,
+            status: x.status,
+            created_at: x.created_at,
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(PageData::new(
+        count[0].mysql_quick_count,
+        list,
+    ))))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommissionRuleDel {
+    id: u32,
+}
+/// 删除。已下单的佣金不受影响
+#[put("/manage/sales/commission_rule/del")]
+pub async fn manage_sales_commission_rule_del(
+    _mana: AuthMana,
+    params: web::Json<CommissionRuleDel>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("sal_commission_rule", {"id": params.id}, {"is_del": 1}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommissionRuleStatus {
+    id: u32,
+    status: i8,
+}
+/// 修改状态，2 上线，3 下线。下线的规则不参与计算
+#[put("/manage/sales/commission_rule/status")]
+pub async fn manage_sales_commission_rule_status(
+    _mana: AuthMana,
+    params: web::Json<CommissionRuleStatus>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    my_run_drop(
+        &mut conn,
+        myupdate!("sal_commission_rule", {"id": params.id}, {"status": params.status}),
+    )?;
+    Ok(web::Json(Res::success("成功")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PreviewItem {
+    unit_sn: u32,
+    quantity: u32,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommissionPreview {
+    items: Vec<PreviewItem>,
+    /// 订单的优惠金额（如优惠券），按原价占比分摊到每一行商品
+    reduce_amount: Option<Money>,
+    /// 总销售、销售本月的销售额，用于选择分档
+    main_sale_volume: Option<Money>,
+    sale_volume: Option<Money>,
+}
+#[derive(Serialize, Debug)]
+struct PreviewRes {
+    unit_sn: u32,
+    quantity: u32,
+    /// 原价金额
+    amount: Money,
+    /// 分摊优惠后的实付
+    paid: Money,
+    /// 匹配的规则的适用范围，没有匹配的规则时为空
+    scope_type: Option<u8>,
+    #[serde(flatten)]
+    split: UnitSplit,
+    /// 总销售、销售这一行的佣金合计
+    main_sale_amount: Money,
+    sale_amount: Money,
+}
+/// 按当前上线的规则，预览一个假设订单的分成
+#[post("/manage/sales/commission_rule/preview")]
+pub async fn manage_sales_commission_rule_preview(
+    _mana: AuthMana,
+    params: web::Json<CommissionPreview>,
+) -> Result<impl Responder> {
+    if params.items.is_empty() || params.items.iter().any(|x| x.quantity == 0) {
+        return Ok(web::Json(Res::fail("请选择商品和数量")));
+    }
+    let mut conn = mysql_conn()?;
+    let unit_sns: Vec<u32> = params.items.iter().map(|x| x.unit_sn).collect();
+    // 只读查询，用事务复用下单时的查询方法
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    let ctx = match get_split_context(&mut tran, &unit_sns) {
+        Ok(d) => d,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    tran.rollback().unwrap();
+    if let Some(x) = params
+        .items
+        .iter()
+        .find(|x| !ctx.units.contains_key(&x.unit_sn))
+    {
+        return Ok(web::Json(Res::fail(&format!("商品 {} 不存在", x.unit_sn))));
+    }
+
+    let total_amount: Money = params
+        .items
+        .iter()
+        .map(|x| ctx.units[&x.unit_sn].price * x.quantity)
+        .sum();
+    let goods_pay = (total_amount - params.reduce_amount.unwrap_or_default()).max(Money::ZERO);
+    let main_sale_volume = params.main_sale_volume.unwrap_or_default();
+    let sale_volume = params.sale_volume.unwrap_or_default();
+    let list: Vec<PreviewRes> = params
+        .items
+        .iter()
+        .map(|x| {
+            let unit = &ctx.units[&x.unit_sn];
+            let paid = item_refund_amount(goods_pay, total_amount, unit.price, 0, x.quantity);
+            let split = ctx.split(x.unit_sn, paid, x.quantity, main_sale_volume, sale_volume);
+            PreviewRes {
+                unit_sn: x.unit_sn,
+                quantity: x.quantity,
+                amount: unit.price * x.quantity,
+                paid,
+                scope_type: match_rule(&ctx.rules, unit).map(|r| r.scope_type),
+                main_sale_amount: split.main_sale_split.unwrap_or_default() * x.quantity,
+                sale_amount: split.sale_split.unwrap_or_default() * x.quantity,
+                split,
+            }
+        })
+        .collect();
+
+    Ok(web::Json(Res::success(list)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_commission_rule() {
+        let rule = || CommissionRuleEdit {
+            id: None,
+            name: "茶叶".to_string(),
+            scope_type: CommissionScope::Category as u8,
+            scope_id: 3,
+            split_type: CommissionSplitType::Percent as u8,
+            main_sale_value: "2".parse().unwrap(),
+            sale_value: "5.5".parse().unwrap(),
+            tiers: vec![],
+        };
+        assert!(check_commission_rule(&rule()).is_ok());
+
+        let mut r = rule();
+        r.scope_type = 5;
+        assert!(check_commission_rule(&r).is_err());
+        let mut r = rule();
+        r.scope_id = 0;
+        assert!(check_commission_rule(&r).is_err());
+        let mut r = rule();
+        r.split_type = 3;
+        assert!(check_commission_rule(&r).is_err());
+        // 百分比不能超过 100，固定金额可以
+        let mut r = rule();
+        r.sale_value = "100.01".parse().unwrap();
+        assert!(check_commission_rule(&r).is_err());
+        r.split_type = CommissionSplitType::Fixed as u8;
+        assert!(check_commission_rule(&r).is_ok());
+
+        let tier = |v: &str| CommissionTier {
+            min_volume: v.parse().unwrap(),
+            main_sale_value: "3".parse().unwrap(),
+            sale_value: "6".parse().unwrap(),
+        };
+        let mut r = rule();
+        r.tiers = vec![tier("5000"), tier("20000")];
+        assert!(check_commission_rule(&r).is_ok());
+        r.tiers = vec![tier("5000"), tier("5000")];
+        assert!(check_commission_rule(&r).is_err());
+        r.tiers = vec![tier("0")];
+        assert!(check_commission_rule(&r).is_err());
+    }
+}
@@ -2,3 +2,5 @@ mod sales;
 pub use sales::*;
 mod records;
 pub use records::*;
+mod commission_rule;
+pub use commission_rule::*;
@@ -1,10 +1,10 @@
 use actix_web::{Responder, Result, get, web};
 use mysql_quick::{MysqlQuickCount, mycount, myfind};
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::deserialize_number_from_string;
 use serde_json::Value;
 
 use crate::PageData;
+use crate::common::Money;
 use crate::common::types::TranType;
 use crate::routes::Res;
 use crate::utils::filter::{deserialize_nested_json, deserialize_path_to_url};
@@ -32,8 +32,7 @@ pub struct SaleRecord {
     #[serde(deserialize_with = "deserialize_path_to_url")]
     avatar_url: String,
     nickname: Option<String>,
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    tran_amount: f64,
+    tran_amount: Money,
     tran_type: TranType,
     #[serde(deserialize_with = "deserialize_nested_json")]
     info: Value,
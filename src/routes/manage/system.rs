@@ -1,13 +1,17 @@
-use actix_web::{Responder, Result, get, post, put, web};
+use actix_web::{Responder, Result, error, get, post, put, web};
 use mysql_quick::{MysqlQuickCount, Queryable, mycount, myfind, myupdate};
 use serde::{Deserialize, Serialize};
 
-use crate::db::my_run_drop;
+use crate::db::{in_placeholders, my_exec_drop, my_exec_first, my_run_drop};
 use crate::routes::Res;
 use crate::utils::files::get_file_url;
+use crate::utils::utils::log_err;
 use crate::{
     db::{my_run_vec, mysql_conn},
-    middleware::{AuthMana, AuthSuperMana},
+    middleware::{
+        AuthMana, AuthSuperMana, all_permissions, clear_module_switch_cache,
+        clear_role_permission_cache, parse_permissions, unknown_permissions,
+    },
 };
 
 #[derive(Serialize, Deserialize)]
@@ -75,46 +79,53 @@ pub async fn manage_system_menu_list(mana: AuthMana) -> Result<impl Responder> {
     }
     let mut path_list: Vec<PathList> = vec![];
 
-    let check_auth = user_authority.join(",");
-    //  查寻用户权限的 所有子页面的path；
-    let temp_sub_list = conn
-        .query_map(
+    //  查寻用户权限的 所有子页面的path；
+    let mut temp_sub_list: Vec<SysPath> = vec![];
+    if !user_authority.is_empty() {
+        let sql = format!(
             "select id,name,sub_name,path,sub_path,icon_name,path_type,uni_key,sort_num
-        from sys_paths where path_type = 2 and sub_path in ("
-                .to_string()
-                + check_auth.as_str()
-                + ") order by sort_num desc",
-            |(id, name, sub_name, path, sub_path, icon_name, path_type, uni_key, sort_num)| {
-                SysPath {
-                    id,
-                    name,
-                    sub_name,
-                    path,
-                    sub_path,
-                    icon_name,
-                    path_type,
-                    uni_key,
-                    sort_num,
-                }
-            },
-        )
-        .unwrap();
-    let temp_path = temp_sub_list
+            from sys_paths where path_type = 2 and sub_path in ({}) order by sort_num desc",
+            in_placeholders(user_authority.len())
+        );
+        temp_sub_list = conn
+            .exec_map(
+                &sql,
+                user_authority,
+                |(id, name, sub_name, path, sub_path, icon_name, path_type, uni_key, sort_num)| {
+                    SysPath {
+                        id,
+                        name,
+                        sub_name,
+                        path,
+                        sub_path,
+                        icon_name,
+                        path_type,
+                        uni_key,
+                        sort_num,
+                    }
+                },
+            )
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, &sql)))?;
+    }
+    let mut temp_path = temp_sub_list
         .iter()
-        .map(|s| "\"".to_string() + s.path.clone().as_str() + "\"")
-        .collect::<Vec<String>>()
-        .join(",");
+        .map(|s| s.path.clone())
+        .collect::<Vec<String>>();
+    temp_path.sort();
+    temp_path.dedup();
 
-    //  查寻用户权限的 所有页面的path；
+    //  查寻用户权限的 所有页面的path；
     let mut temp_list: Vec<SysPath> = vec![];
-    if temp_path != "".to_string() {
+    if !temp_path.is_empty() {
+        let sql = format!(
+            "select id,name,sub_name,path,sub_path,icon_name,path_type,uni_key,sort_num
+            from sys_paths where path_type = 1 and path in ({}) order by sort_num desc",
+            in_placeholders(temp_path.len())
+        );
         temp_list = conn
-            .query_map(
-                "select id,name,sub_name,path,sub_path,icon_name,path_type,uni_key,sort_num
-            from sys_paths where path_type = 1 and path in ("
-                    .to_string()
-                    + temp_path.as_str()
-                    + ") order by sort_num desc",
+            .exec_map(
+                &sql,
+                temp_path,
                 |(id, name, sub_name, path, sub_path, icon_name, path_type, uni_key, sort_num)| {
                     SysPath {
                         id,
@@ -129,7 +140,7 @@ pub async fn manage_system_menu_list(mana: AuthMana) -> Result<impl Responder> {
                     }
                 },
             )
-            .unwrap();
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, &sql)))?;
     }
 
     for item in temp_list {
@@ -172,7 +183,7 @@ pub async fn manage_system_paths_list(_super_mana: AuthSuperMana) -> Result<impl
                 sort_num,
             },
         )
-        .unwrap();
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "sys_paths")))?;
 
     Ok(web::Json(Res::success(paths)))
 }
@@ -185,12 +196,10 @@ pub async fn manage_system_sub_paths_list(
 ) -> Result<impl Responder> {
     let mut conn = mysql_conn()?;
     let sub_paths = conn
-        .query_map(
+        .exec_map(
             "select id,sub_name,path,sub_path,path_type,sort_num from sys_paths
-            where path_type = 2 and path = \""
-                .to_string()
-                + f_path.as_str()
-                + "\"",
+            where path_type = 2 and path = ?",
+            (f_path.as_str(),),
             |(id, sub_name, path, sub_path, path_type, sort_num)| SubPathsList {
                 id,
                 sub_name,
@@ -200,7 +209,7 @@ pub async fn manage_system_sub_paths_list(
                 sort_num,
             },
         )
-        .unwrap();
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "sys_paths")))?;
 
     Ok(web::Json(Res::success(sub_paths)))
 }
@@ -239,71 +248,46 @@ pub async fn manage_system_paths_add(
 
     let mut conn = mysql_conn()?;
 
-    let fpath_id: Option<u64> = conn
-        .query_first(
-            "select id from sys_paths where uni_key = \"".to_string() + path.as_str() + "_1\"",
-        )
-        .unwrap();
+    let fpath_id: Option<u64> = my_exec_first(
+        &mut conn,
+        "select id from sys_paths where uni_key = ?",
+        (&path_uni,),
+    )?;
 
-    let spath_id: Option<u64> = conn
-        .query_first(
-            "select id from sys_paths where uni_key = \"".to_string() + sub_path.as_str() + "_2\"",
-        )
-        .unwrap();
+    let spath_id: Option<u64> = my_exec_first(
+        &mut conn,
+        "select id from sys_paths where uni_key = ?",
+        (&sub_path_uni,),
+    )?;
 
     if let Some(fid) = fpath_id {
-        conn.query_drop(
-            "update sys_paths set name=\"".to_string()
-                + name.as_str()
-                + "\""
-                + ", icon_name=\""
-                + icon_name.as_str()
-                + "\" "
-                + "where id = "
-                + fid.to_string().as_str(),
-        )
-        .unwrap();
+        my_exec_drop(
+            &mut conn,
+            "update sys_paths set name = ?, icon_name = ? where id = ?",
+            (name, icon_name, fid),
+        )?;
     } else {
-        conn.exec_drop(
+        my_exec_drop(
+            &mut conn,
             "insert into sys_paths (name,path,icon_name,uni_key,path_type,sort_num)
-            values (?,?,?,?,?,?)
-            ",
-            (name, path.clone(), icon_name, path_uni, 1, sort_num), // params! {
-                                                                    //     "name" => name,
-                                                                    //     "path" => path.clone(),
-                                                                    //     "icon_name" => icon_name,
-                                                                    //     "uni_key" => path_uni,
-                                                                    //     "path_type" => 1,
-                                                                    //     "sort_num" => sort_num,
-                                                                    // }
-        )
-        .unwrap();
+            values (?,?,?,?,?,?)",
+            (name, path.clone(), icon_name, path_uni, 1, sort_num),
+        )?;
     }
 
     if let Some(sid) = spath_id {
-        conn.query_drop(
-            "update sys_paths set sub_name=\"".to_string()
-                + sub_name.as_str()
-                + "\" "
-                + "where id = "
-                + sid.to_string().as_str(),
-        )
-        .unwrap();
+        my_exec_drop(
+            &mut conn,
+            "update sys_paths set sub_name = ? where id = ?",
+            (sub_name, sid),
+        )?;
     } else {
-        conn.exec_drop(
+        my_exec_drop(
+            &mut conn,
             "insert into sys_paths (sub_name,sub_path,path,uni_key,path_type,sort_num)
-            values (?,?,?,?,?,?)
-            ",
-            (sub_name, sub_path, path, sub_path_uni, 2, sort_num + 1), // params! {
-                                                                       //     "sub_name" => sub_name,
-                                                                       //     "sub_path" => sub_path,
-                                                                       //     "path" => path,
-                                                                       //     "uni_key" => sub_path_uni,
-                                                                       //     "path_type" => 2,
-                                                                       //     "sort_num" => sort_num + 1,
-                                                                       // }
-        )
-        .unwrap();
+            values (?,?,?,?,?,?)",
+            (sub_name, sub_path, path, sub_path_uni, 2, sort_num + 1),
+        )?;
     }
 
     Ok(web::Json(Res::<u8>::info(1, "操作成功")))
@@ -325,12 +309,11 @@ pub async fn manage_system_paths_delete(
     let id = params.id;
     let mut conn = mysql_conn()?;
 
-    let path_data: Option<(u8, String, Option<String>)> = conn
-        .query_first(
-            "select path_type,path,sub_path from sys_paths where id = ".to_string()
-                + id.to_string().as_str(),
-        )
-        .unwrap();
+    let path_data: Option<(u8, String, Option<String>)> = my_exec_first(
+        &mut conn,
+        "select path_type,path,sub_path from sys_paths where id = ?",
+        (id,),
+    )?;
 
     if let Some((t, p, s)) = path_data {
         if t == 2 {
@@ -347,27 +330,19 @@ pub async fn manage_system_paths_delete(
                     return Ok(web::Json(Res::<u8>::fail("删除失败，有用户具有该权限")));
                 }
             }
-            conn.query_drop(
-                "delete from sys_paths where id = ".to_string() + id.to_string().as_str(),
-            )
-            .unwrap();
+            my_exec_drop(&mut conn, "delete from sys_paths where id = ?", (id,))?;
             result = Res::<u8>::info(1, "删除成功");
         } else {
-            let count: Option<u32> = conn
-                .query_first(
-                    "select count(*) from sys_paths where path_type = 2 and path = \"".to_string()
-                        + p.as_str()
-                        + "\"",
-                )
-                .unwrap();
+            let count: Option<u32> = my_exec_first(
+                &mut conn,
+                "select count(*) from sys_paths where path_type = 2 and path = ?",
+                (p,),
+            )?;
             if let Some(c) = count {
                 if c > 0 {
                     result = Res::<u8>::info(0, "删除失败，请先删除子模块");
                 } else {
-                    conn.query_drop(
-                        "delete from sys_paths where id = ".to_string() + id.to_string().as_str(),
-                    )
-                    .unwrap();
+                    my_exec_drop(&mut conn, "delete from sys_paths where id = ?", (id,))?;
                     result = Res::<u8>::info(1, "删除成功");
                 }
             } else {
@@ -412,7 +387,7 @@ pub async fn manage_system_paths_all(_super_mana: AuthSuperMana) -> Result<impl
         |(id,name,sub_name,path,sub_path,path_type,sort_num)| {
             PathsList {id,name,sub_name,path,sub_path,path_type,sort_num}
         }
-    ).unwrap();
+    ).map_err(|e| error::ErrorInternalServerError(log_err(&e, "sys_paths")))?;
 
     let temp_list2 = temp_list.clone();
 
@@ -468,7 +443,7 @@ struct RoleList {
     name: String,
     identifier: String,
     is_del: u8,
-    api_paths: String,
+    permissions: Vec<String>,
 }
 /// 角色列表
 #[get("/manage/system/role/list")]
@@ -477,16 +452,16 @@ pub async fn manage_system_role_list(_super_mana: AuthSuperMana) -> Result<impl
 
     let roles = conn
         .query_map(
-            "select id,name,identifier,is_del,api_paths from sys_role where is_del = 0",
-            |(id, name, identifier, is_del, api_paths)| RoleList {
+            "select id,name,identifier,is_del,permissions from sys_role where is_del = 0",
+            |(id, name, identifier, is_del, permissions): (_, _, _, _, Option<String>)| RoleList {
                 id,
                 name,
                 identifier,
                 is_del,
-                api_paths,
+                permissions: parse_permissions(&permissions.unwrap_or_default()),
             },
         )
-        .unwrap();
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "sys_role")))?;
 
     Ok(web::Json(Res::success(roles)))
 }
@@ -495,7 +470,8 @@ pub async fn manage_system_role_list(_super_mana: AuthSuperMana) -> Result<impl
 pub struct RoleAdd {
     name: String,
     identifier: u16,
-    api_paths: String,
+    /// 角色拥有的接口权限 key
+    permissions: Vec<String>,
 }
 /// 新增用户角色
 #[post("/manage/system/role/add")]
@@ -505,25 +481,27 @@ pub async fn manage_system_role_add(
 ) -> Result<impl Responder> {
     let name = params.name.clone();
     let identifier = params.identifier;
-    let api_paths = params.api_paths.clone();
 
     if identifier < 1000 || identifier > 9999 {
         return Ok(web::Json(Res::<u8>::fail(
             "角色编号的取值在 1000~9999 之间",
         )));
     }
+    let unknown = unknown_permissions(&params.permissions);
+    if !unknown.is_empty() {
+        return Ok(web::Json(Res::<u8>::fail(&format!(
+            "未知的权限：{}",
+            unknown.join(",")
+        ))));
+    }
 
     let mut conn = mysql_conn()?;
 
-    let role_num: Option<u32> = conn
-        .query_first(
-            "select count(*) from sys_role where identifier = ".to_string()
-                + identifier.to_string().as_str()
-                + " or name = \""
-                + name.as_str()
-                + "\"",
-        )
-        .unwrap();
+    let role_num: Option<u32> = my_exec_first(
+        &mut conn,
+        "select count(*) from sys_role where identifier = ? or name = ?",
+        (identifier, &name),
+    )?;
 
     if let Some(n) = role_num {
         if n > 0 {
@@ -531,15 +509,12 @@ pub async fn manage_system_role_add(
         }
     }
 
-    conn.exec_drop(
-        "insert into sys_role (name,identifier,api_paths) values (?,?,?)",
-        (name, identifier, api_paths), // params! {
-                                       //     "name" => name,
-                                       //     "identifier" => identifier,
-                                       //     "api_paths" => api_paths
-                                       // }
-    )
-    .unwrap();
+    my_exec_drop(
+        &mut conn,
+        "insert into sys_role (name,identifier,permissions) values (?,?,?)",
+        (name, identifier, params.permissions.join(",")),
+    )?;
+    clear_role_permission_cache()?;
 
     Ok(web::Json(Res::<u8>::info(1, "新增成功")))
 }
@@ -550,7 +525,7 @@ struct SysRole {
     name: String,
     identifier: String,
     is_del: i8,
-    api_paths: String,
+    permissions: Vec<String>,
 }
 /// 获取角色详情
 #[get("/manage/system/role/info/{id}")]
@@ -561,12 +536,11 @@ pub async fn manage_system_role_info(
     let role_id = id.as_str();
     let mut conn = mysql_conn()?;
 
-    let info: Option<(u64, String, String, i8, String)> = conn
-        .query_first(
-            "select id,name,identifier,is_del,api_paths from sys_role where id = ".to_string()
-                + role_id,
-        )
-        .unwrap();
+    let info: Option<(u64, String, String, i8, Option<String>)> = my_exec_first(
+        &mut conn,
+        "select id,name,identifier,is_del,permissions from sys_role where id = ?",
+        (role_id,),
+    )?;
 
     if let Some(data) = info {
         return Ok(web::Json(Res::success(SysRole {
@@ -574,7 +548,7 @@ pub async fn manage_system_role_info(
             name: data.1,
             identifier: data.2,
             is_del: data.3,
-            api_paths: data.4,
+            permissions: parse_permissions(&data.4.unwrap_or_default()),
         })));
     }
 
@@ -583,7 +557,7 @@ pub async fn manage_system_role_info(
         name: "".to_string(),
         identifier: "".to_string(),
         is_del: 0,
-        api_paths: "".to_string(),
+        permissions: vec![],
     })))
 }
 
@@ -592,7 +566,8 @@ pub struct RoleUpdate {
     id: u64,
     name: String,
     identifier: u16,
-    api_paths: String,
+    /// 角色拥有的接口权限 key
+    permissions: Vec<String>,
 }
 /// 更新角色详情
 #[put("/manage/system/role/update")]
@@ -602,7 +577,6 @@ pub async fn manage_system_role_update(
 ) -> Result<impl Responder> {
     let name = params.name.clone();
     let identifier = params.identifier;
-    let api_paths = params.api_paths.clone();
     let id = params.id;
 
     let mut conn = mysql_conn()?;
@@ -610,24 +584,29 @@ pub async fn manage_system_role_update(
     if identifier < 1000 || identifier > 9999 {
         return Ok(web::Json(Res::fail("角色编号的取值在 1000~9999 之间")));
     }
+    let unknown = unknown_permissions(&params.permissions);
+    if !unknown.is_empty() {
+        return Ok(web::Json(Res::fail(&format!(
+            "未知的权限：{}",
+            unknown.join(",")
+        ))));
+    }
 
-    let ident: Option<String> = conn
-        .query_first(
-            "select identifier from sys_role where id = ".to_string() + id.to_string().as_str(),
-        )
-        .unwrap();
+    let ident: Option<String> = my_exec_first(
+        &mut conn,
+        "select identifier from sys_role where id = ?",
+        (id,),
+    )?;
 
     if let Some(ide) = ident {
         if identifier.to_string() != ide {
-            let count_role: Option<i8> = conn
-                .query_first(
-                    "select count(*) from usr_silent where role like \"%".to_string()
-                        + ide.as_str()
-                        + "%\"",
-                )
-                .unwrap();
-
-            if let Some(_cr) = count_role {
+            let count_role: Option<u32> = my_exec_first(
+                &mut conn,
+                "select count(*) from usr_silent where role like ?",
+                (format!("%{}%", ide),),
+            )?;
+
+            if count_role.unwrap_or_default() > 0 {
                 return Ok(web::Json(Res::fail("已有用户使用该角色编号，不能更改")));
             }
         }
@@ -635,19 +614,12 @@ pub async fn manage_system_role_update(
         return Ok(web::Json(Res::fail("未找到该角色")));
     }
 
-    conn.query_drop(
-        "update sys_role set identifier = ".to_string()
-            + identifier.to_string().as_str()
-            + ", api_paths = \""
-            + api_paths.as_str()
-            + "\""
-            + ", name = \""
-            + name.as_str()
-            + "\""
-            + " where id = "
-            + id.to_string().as_str(),
-    )
-    .unwrap();
+    my_exec_drop(
+        &mut conn,
+        "update sys_role set identifier = ?, permissions = ?, name = ? where id = ?",
+        (identifier, params.permissions.join(","), name, id),
+    )?;
+    clear_role_permission_cache()?;
 
     Ok(web::Json(Res::<u8>::info(1, "更新成功")))
 }
@@ -665,30 +637,28 @@ pub async fn manage_system_role_del(
     let role_id = params.id;
 
     let mut conn = mysql_conn()?;
-    let ident: Option<String> = conn
-        .query_first(
-            "select identifier from sys_role where id = ".to_string()
-                + role_id.to_string().as_str(),
-        )
-        .unwrap();
+    let ident: Option<String> = my_exec_first(
+        &mut conn,
+        "select identifier from sys_role where id = ?",
+        (role_id,),
+    )?;
 
     if let Some(ide) = ident {
-        let count: Option<i8> = conn
-            .query_first(
-                "select count(*) from usr_silent where role like \"%".to_string()
-                    + ide.as_str()
-                    + "%\"",
-            )
-            .unwrap();
+        let count: Option<u32> = my_exec_first(
+            &mut conn,
+            "select count(*) from usr_silent where role like ?",
+            (format!("%{}%", ide),),
+        )?;
         if let Some(c) = count {
             if c > 0 {
                 return Ok(web::Json(Res::fail("不能删除，当前有用户属于该角色")));
             } else {
-                conn.query_drop(
-                    "update sys_role set is_del = 1 where id = ".to_string()
-                        + role_id.to_string().as_str(),
-                )
-                .unwrap();
+                my_exec_drop(
+                    &mut conn,
+                    "update sys_role set is_del = 1 where id = ?",
+                    (role_id,),
+                )?;
+                clear_role_permission_cache()?;
 
                 return Ok(web::Json(Res::<u8>::info(1, "删除成功")));
             }
@@ -700,6 +670,12 @@ pub async fn manage_system_role_del(
     }
 }
 
+/// 所有接口权限，用于给角色分配权限
+#[get("/manage/system/permission/list")]
+pub async fn manage_system_permission_list(_super_mana: AuthSuperMana) -> Result<impl Responder> {
+    Ok(web::Json(Res::success(all_permissions())))
+}
+
 #[derive(Serialize, Clone)]
 struct RoleUsers {
     id: u64,
@@ -716,11 +692,9 @@ pub async fn manage_system_role_user(
     let mut conn = mysql_conn()?;
 
     let role_users = conn
-        .query_map(
-            "select id,avatar_url,username,nickname from usr_silent where role like \"%"
-                .to_string()
-                + role_author.as_str()
-                + "%\"",
+        .exec_map(
+            "select id,avatar_url,username,nickname from usr_silent where role like ?",
+            (format!("%{}%", role_author.as_str()),),
             |(id, avatar_url, username, nickname)| RoleUsers {
                 id,
                 avatar_url,
@@ -728,7 +702,7 @@ pub async fn manage_system_role_user(
                 nickname,
             },
         )
-        .unwrap();
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "usr_silent")))?;
     let users: Vec<RoleUsers> = role_users
         .into_iter()
         .map(|u| {
@@ -794,5 +768,7 @@ pub async fn manage_system_module_switch_change(
             "is_on": params.is_on,
         }),
     )?;
+    // 开关已修改，清除缓存，下次鉴权时重新读取
+    clear_module_switch_cache()?;
     Ok(web::Json(Res::success("")))
 }
@@ -1,12 +1,14 @@
 use actix_web::{Responder, Result, get, put, web};
-use mysql_quick::{MysqlQuickCount, Queryable, TxOpts, mycount, myfind, myset, myupdate};
+use mysql_quick::{MysqlQuickCount, TxOpts, mycount, myfind, myset, myupdate};
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::deserialize_number_from_string;
 
 use crate::PageData;
+use crate::common::Money;
 use crate::common::types::Role;
+use crate::control::token::bump_token_ver;
 use crate::db::{my_run_tran_drop, mysql_tran};
 use crate::routes::Res;
+use crate::routes::utils_set::agent_set::agent_credential_review;
 use crate::routes::utils_set::sales_set::{
     main_sale_add, sale_add, sale_and_main_del, user_and_sale_del,
 };
@@ -14,7 +16,7 @@ use crate::utils::files::{get_file_url, get_file_urls};
 use crate::utils::filter::deserialize_path_to_url;
 use crate::utils::utils::hide_phone_number;
 use crate::{
-    db::{my_run_drop, my_run_vec, mysql_conn},
+    db::{my_exec_drop, my_run_drop, my_run_vec, mysql_conn},
     middleware::{AuthMana, AuthSuperMana},
 };
 
@@ -156,13 +158,11 @@ pub async fn manage_user_update_authority(
     let user_auth: Vec<MysqlQuickCount> = my_run_vec(&mut conn, sql)?;
     if user_auth[0].mysql_quick_count > 0 {
         // 有
-        conn.query_drop(
-            "update usr_authority set authority = \"".to_string()
-                + &params.authority
-                + "\" where uid = "
-                + params.uid.to_string().as_str(),
-        )
-        .unwrap();
+        my_exec_drop(
+            &mut conn,
+            "update usr_authority set authority = ? where uid = ?",
+            (&params.authority, params.uid),
+        )?;
     } else {
         // 没有，新增
         my_run_drop(
@@ -173,6 +173,8 @@ pub async fn manage_user_update_authority(
             }),
         )?;
     }
+    // 权限变更后，该用户之前的登录立即失效
+    bump_token_ver(params.uid)?;
 
     Ok(web::Json(Res::<u8>::info(2, "更新成功")))
 }
@@ -228,10 +230,26 @@ pub async fn manage_user_update_user_role(
     )?;
     tran.commit().unwrap();
     // ---- 事务结束
+    // 角色变更后，该用户之前的登录立即失效
+    bump_token_ver(uid)?;
 
     Ok(web::Json(Res::<u8>::info(2, "更新成功")))
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionRevoke {
+    uid: u64,
+}
+/// 强制用户下线，该用户所有已登录的 token 立即失效，需要超级管理员的身份
+#[put("/manage/user/session/revoke")]
+pub async fn manage_user_session_revoke(
+    _super_mana: AuthSuperMana,
+    params: web::Json<SessionRevoke>,
+) -> Result<impl Responder> {
+    bump_token_ver(params.uid)?;
+    Ok(web::Json(Res::<u8>::info(2, "操作成功")))
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct UserItem {
     id: u64,
@@ -543,7 +561,16 @@ pub async fn manage_user_credential_status(
             return Err(e);
         }
     };
+    // 代理的认证，同步代理区域的状态
+    if cre_info[0].role == (Role::Agent as u16).to_string()
+        && let Err(e) = agent_credential_review(&mut tran, cre_info[0].uid, params.status)
+    {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
     tran.commit().unwrap();
+    // 角色变更后，该用户之前的登录立即失效
+    bump_token_ver(cre_info[0].uid)?;
 
     Ok(web::Json(Res::success("成功")))
 }
@@ -653,7 +680,7 @@ pub struct WithdrawRequestItem {
     /// 用户昵称
     nickname: Option<String>,
     /// 提现申请金额
-    req_amount: f64,
+    req_amount: Money,
     /// 审核状态 (0未通过，1审核中，2已上线，3已下线)
     status: u8,
     /// 申请时间
@@ -690,8 +717,7 @@ pub async fn manage_user_withdraw_req_list(
         #[serde(deserialize_with = "deserialize_path_to_url")]
         avatar_url: String,
         nickname: Option<String>,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        req_amount: f64,
+        req_amount: Money,
         status: u8,
         created_at: String,
         updated_at: String,
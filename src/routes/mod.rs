@@ -1,11 +1,19 @@
 use serde::{Deserialize, Serialize};
 use utoipa::{OpenApi, ToSchema};
 
-use crate::common::types::{DeliveryType, PayType, ProductLayout, QuestionFormType, TranType};
+use crate::common::Money;
+use crate::common::types::{
+    AfterSaleType, DeliveryType, PayType, ProductLayout, QuestionFormType, TranType,
+};
 // use crate::routes::BaseData;
+use crate::routes::utils_set::after_sale_set::AfterSaleItemRes;
+use crate::routes::utils_set::coupon_set::CouponOption;
+use crate::routes::utils_set::discount_set::OrderDiscount;
+use crate::routes::utils_set::freight_set::StoreFreight;
 use crate::routes::utils_set::mall_set::{PrePareRes, UserBuy};
+use crate::routes::utils_set::review_set::ReviewRes;
 
-mod utils_set;
+pub(crate) mod utils_set;
 
 // 测试用的接口
 mod test;
@@ -55,6 +63,10 @@ pub use mall::*;
 mod sales;
 pub use sales::*;
 
+/// 区域代理接口
+mod agent;
+pub use agent::*;
+
 use crate::control::wx_info::WxJsSdkSign;
 
 #[derive(Serialize, Deserialize, Clone, ToSchema)]
@@ -129,13 +141,14 @@ pub struct BaseInfo {
         login_silent_wechat_mini, login_wechat_mini_info, upload_file,
         user_feedback, user_collect_add, common_banner_list, common_province_list,
         common_base_info, mall_order_add_shop_cart, mall_order_make_prepare,
-        mall_order_make_pay, mall_coupon_receive, mall_coupon_list, mall_product_list,
+        mall_order_make_pay, mall_coupon_receive, mall_coupon_redeem, mall_coupon_list, mall_product_list,
         mall_product_unit_list, mall_product_user_publish, user_credential_add, common_sms_code,
         login_sms_bind_phone, user_addr_add, user_addr_del, user_addr_list, mall_product_detail,
         login_silent_wechat_gzh, login_wechat_gzh_info, mall_order_list, mall_order_detail,
         mall_order_add_buy_now, mall_store_list, mall_store_detail, user_collect_list, user_addr_detail,
-        test_jwt_token, user_coupon_list, user_credential_detail, common_wx_js_sdk_sign,
-        pay_make_wx_test,mall_order_modify_status, common_module_switch_list,
+        test_jwt_token, user_coupon_list, user_coupon_expiring, user_credential_detail, common_wx_js_sdk_sign,
+        pay_make_wx_test,mall_order_modify_status, mall_order_cancel, common_module_switch_list,
+        mall_order_cart_list, mall_order_cart_quantity, mall_order_cart_select, mall_order_cart_del, mall_order_cart_clear,
         que_form_detail, que_form_submit, mall_brand_options, login_wechat_phone_mini,
         mall_brand_products, mall_brand_products_all, mall_cat_products_all, mall_product_file,
         mall_product_group_all, mall_product_file_send_email, mall_cat_list, mall_cat_tertiary_of,
@@ -143,24 +156,34 @@ pub struct BaseInfo {
         mall_write_off_info, mall_write_off_do, user_pocket_tran, user_pocket_withdraw_req,
         sales_invite_sale_code, sales_invite_sale_bind, sales_invite_sale_del, sales_invite_user_code,
         sales_invite_user_bind, sales_invite_user_del, sales_list_sale, sales_list_user, user_pocket_money,
-        user_pocket_transfer, user_pocket_transfer_list, user_pocket_pending_withdraw
+        user_pocket_transfer, user_pocket_transfer_list, user_pocket_pending_withdraw,
+        login_refresh, login_logout, mall_seckill_list, mall_seckill_buy, mall_group_buy_list,
+        mall_group_buy_add, mall_group_buy_group, mall_group_buy_my, mall_review_add, mall_review_list,
+        mall_after_sale_apply, mall_after_sale_waybill, mall_after_sale_cancel, mall_after_sale_list,
+        mall_after_sale_detail, sales_commission, agent_apply, agent_dashboard, agent_order_list,
+        agent_withdraw_req, user_pocket_recharge_options, user_pocket_recharge
     ),
     components(schemas(
         UserInfo, WechatLoginMiniInfo, Res<u8>, UploadFile, QuestionFormType,
         UploadRes, BannerRes, Feedback, AreaItem, CityItem, UserAddCredential, ProductLayout,
-        ProvItem, AddShopCart, MakePrePare, MakePay, PrePareRes, UserBuy, CouponReceive, WechatPhone,
+        ProvItem, AddShopCart, MakePrePare, MakePay, PrePareRes, UserBuy, StoreFreight, CouponOption, OrderDiscount, CouponReceive, CouponRedeem, WechatPhone,
         ProductRes, UnitRes, CouponRes, AddCollect, UserAddressId, BaseNumInfo, BaseStrInfo,
         BaseInfo, BaseData, ProductAddrInfo, ProductAddCat, UserPubProduct,
         SmsCodePhone, BindPhone, WechatSilent, UserAddress, BaseNumInfo,
         UserOrder, UserOrderItem, UserOrderDetail, UserOrderItemDetail, BuyNow, UserPocket,
         StoreRes, StoreDetailRes, CollectRes, UserCouponRes, CredentialRes, TranType,
-        WxJsSdkSign, TestPay, TestJwtToken, MakePayRes, ModifyOder, WxPayInfo, DeliveryType, PayType,
+        WxJsSdkSign, TestPay, TestJwtToken, MakePayRes, ModifyOder, OrderCancel, WxPayInfo, DeliveryType, PayType,
         QueFormItem, QueForm, QueFormItemSubmit, QueFormSubmit, BrandProductItem, BrandProduct,
         CatProductItem, CatProduct, Brand, ProductAttr, ProductDetail, ProductFile, ProductCatItem,
         ProductGroupItem, ProductGroupAll, ProductGroup, ProductGroupSearch, EmailProductFile,
         ArticleCat, Article, ArticleDetail, ArticleId, WriteOffInfo, DoWriteOff, Invite, SaleDelUid,
         SaleUserItem, UserTran, WithdrawRequest, WithdrawalRequestItem, WithdrawalRequestInfo,
-        UserPendingWithdraw
+        UserPendingWithdraw, Money, CartItem, CartStore, CartRes, CartQuantity, CartSelect, CartDel,
+        RefreshToken, RefreshRes, SeckillRes, SeckillBuy, GroupBuyRes, GroupBuyAdd, GroupMemberRes,
+        GroupRes, MyGroupRes, ReviewAdd, ReviewRes, AfterSaleType, AfterSaleItemAdd, AfterSaleApply,
+        AfterSaleWaybill, AfterSaleCancel, AfterSaleRes, AfterSaleItemRes, CommissionRes,
+        CommissionItem, AgentApply, AgentAreaItem, AgentDashboard, AgentOrderItem, AgentWithdraw,
+        RechargeOption, RechargeReq, RechargeRes
     ))
 )]
 /// 小程序端接口文档
@@ -188,7 +211,7 @@ pub(crate) struct ApiDocMini;
         ProductInfoRes, ProductAddAttrRes, ProductAddCatRes,
         BrandAdd, BrandInfo, BrandSearchInfo, BrandDel, BrandStatus, ProductFileAdd,
         ProductFileInfo, ProductFileDel, ProductFileStatus, QueFormItemRes, QueFormRes,
-        AnsItemRes, AnsFormRes
+        AnsItemRes, AnsFormRes, Money
     ))
 )]
 /// 管理端接口文档
@@ -1,22 +1,12 @@
 use actix_web::{Responder, Result, post, web};
-use mysql_quick::Queryable;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
-use wx_pay::{
-    Amount,
-    Jsapi,
-    Payer,
-    WxPay,
-    WxPayData,
-    // decode::{WxPayNotify, decode_wx_pay},
-};
+use wx_pay::WxPayData;
 
-use crate::common::{
-    WECHAT_MINI_APP_ID, WECHAT_PAY_APIV3, WECHAT_PAY_MCH_ID, WECHAT_PAY_NOTIFY_URL,
-    WECHAT_PAY_PUBKEY, WECHAT_PAY_SERIAL, WECHAT_PRIVATE_KEY,
-};
+use crate::common::Money;
 use crate::control::app_data::{AppData, SlownWorker};
-use crate::db::mysql_conn;
+use crate::control::payment::{PrepayReq, payment};
+use crate::db::{my_exec_first, mysql_conn};
 use crate::middleware::AuthUser;
 use crate::routes::Res;
 
@@ -39,36 +29,21 @@ pub async fn pay_make_wx_test(
     let data = &app_data;
     // 查寻当前用户
     let mut conn = mysql_conn()?;
-    let user_info: Option<(u64, Option<String>, Option<String>)> = conn
-        .query_first(
-            "select id,nickname,openid from usr_silent where id = ".to_string()
-                + user.id.to_string().as_str(),
-        )
-        .unwrap();
+    let user_info: Option<(u64, Option<String>, Option<String>)> = my_exec_first(
+        &mut conn,
+        "select id,nickname,openid from usr_silent where id = ?",
+        (user.id,),
+    )?;
 
     if let Some((_uid, _nickname, u_openid)) = user_info {
         if let Some(openid) = u_openid {
             println!("privkkkkkkkkkk, {}", openid);
-            let wx_pay = WxPay {
-                appid: WECHAT_MINI_APP_ID,
-                mchid: WECHAT_PAY_MCH_ID,
-                private_key: WECHAT_PRIVATE_KEY,
-                serial_no: WECHAT_PAY_SERIAL,
-                api_v3_private_key: WECHAT_PAY_APIV3,
-                notify_url: WECHAT_PAY_NOTIFY_URL,
-                wx_public_key: Some(WECHAT_PAY_PUBKEY),
-                wx_public_key_id: None,
-            };
-
-            let data = wx_pay
-                .jsapi(&Jsapi {
+            let data = payment()
+                .prepay(&PrepayReq {
                     description: "测试122".to_string(),
                     out_trade_no: data.rand_no(SlownWorker::OutTradeNo),
-                    amount: Amount {
-                        total: 1,
-                        ..Default::default()
-                    },
-                    payer: Payer { openid },
+                    amount: Money::from_cent(1),
+                    openid,
                     ..Default::default()
                 })
                 .await
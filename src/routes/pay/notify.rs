@@ -1,17 +1,20 @@
-use actix_web::{Responder, Result, error, post, web};
-use mysql_quick::{TxOpts, myfind, myget, myupdate};
+use actix_web::{Error, Responder, Result, error, post, web};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, TxOpts, myfind, myget, myupdate};
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::deserialize_number_from_string;
 use wx_pay::decode::{WxPayResource, WxRefundResource, WxTransferResource};
 use wx_pay::{RefundStatus, TradeState, TransferBillStatus};
 
+use crate::common::Money;
 use crate::common::types::{
     DeliveryType, OrderItemStatus, OrderPayStatus, PayType, TranType, WithdrawalReqStatus,
     WriteOffStatus,
 };
-use crate::control::wx_info::get_decode_wx_notify;
-use crate::db::{my_run_tran_drop, my_run_tran_vec, mysql_conn};
+use crate::control::payment::decode_notify;
+use crate::db::{db_block, my_run_tran_drop, my_run_tran_vec, mysql_conn};
 use crate::routes::Res;
+use crate::routes::utils_set::after_sale_set::finish_after_sale_by_refund_no;
+use crate::routes::utils_set::agent_set::{agent_split_back, do_order_agent_split};
+use crate::routes::utils_set::group_set::{group_order_paid, is_group_order};
 use crate::routes::utils_set::hash_set::{
     hash_user_withdrawal_money, hash_user_withdrawal_money_verify,
 };
@@ -19,18 +22,24 @@ use crate::routes::utils_set::mall_set::{
     upd_order_item_status, upd_order_item_write_off_status, upd_order_status,
     upd_product_unit_sell_total,
 };
-use crate::routes::utils_set::sales_set::do_order_sale_split;
+use crate::routes::utils_set::recharge_set::{is_recharge_trade_no, recharge_paid};
+use crate::routes::utils_set::sales_set::{commission_refund_order, do_order_sale_split};
 use crate::routes::utils_set::tran_set::add_tran_record;
 use crate::routes::utils_set::write_off_item::add_write_off;
 
 /// 微信支付 回调
 #[post("/pay/notify")]
 pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result<impl Responder> {
-    let data: WxPayResource = get_decode_wx_notify(body, req)?;
+    let data: WxPayResource = decode_notify(&body, &req)?;
+    db_block(move || pay_notify_handle(data)).await?;
+    Ok(web::Json(Res::success("")))
+}
 
+/// 支付回调的处理，模拟支付、补处理丢失的支付回调时直接调用
+pub fn pay_notify_handle(data: WxPayResource) -> Result<(), Error> {
     if data.trade_state != TradeState::SUCCESS {
         // 不是成功，则不修改订单状态
-        return Ok(web::Json(Res::success("")));
+        return Ok(());
     }
 
     // ----- 业务逻辑 -----
@@ -53,17 +62,24 @@ pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result
     let mut tran = conn
         .start_transaction(TxOpts::default())
         .map_err(|_| error::ErrorInternalServerError("事务错误"))?;
+    // 充值单，到账零钱
+    if is_recharge_trade_no(&order_sn) {
+        let total = Money::from_cent(data.amount.total as i64);
+        if let Err(e) = recharge_paid(&mut tran, &order_sn, &data.transaction_id, total) {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+        tran.commit().unwrap();
+        return Ok(());
+    }
     #[derive(Deserialize, Serialize)]
     struct OrderGet {
         order_sn: String,
         uid: u64,
         total_quantity: u32,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        total_amount: f64,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        reduce_amount: f64,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        pay_amount: f64,
+        total_amount: Money,
+        reduce_amount: Option<Money>,
+        pay_amount: Money,
         reduce_des: Option<String>,
         notes: Option<String>,
         appointment_time: Option<String>,
@@ -79,7 +95,11 @@ pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result
         delivery_type: DeliveryType,
     }
     let order: Vec<OrderGet> =
-        match my_run_tran_vec(&mut tran, myget!("ord_order", {"order_sn": &order_sn})) {
+        // 加锁，与超时取消订单互斥
+        match my_run_tran_vec(
+            &mut tran,
+            myget!("ord_order", {"order_sn": &order_sn}) + MY_EXCLUSIVE_LOCK,
+        ) {
             Ok(d) => d,
             Err(e) => {
                 tran.rollback().unwrap();
@@ -90,8 +110,27 @@ pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result
         tran.rollback().unwrap();
         return Err(error::ErrorNotFound("订单不存在"));
     }
+    let paid_amount = Money::from_cent(data.amount.total as i64);
+    if order[0].pay_amount != paid_amount {
+        // 金额不一致不改订单状态，记下异常待人工处理，并应答回调，避免微信一直重试
+        let pay_error = format!(
+            "支付金额 {} 与订单金额 {} 不一致，微信支付单号 {}",
+            paid_amount, order[0].pay_amount, data.transaction_id
+        );
+        eprintln!("支付回调异常 {}: {}", order_sn, pay_error);
+        if let Err(e) = my_run_tran_drop(
+            &mut tran,
+            myupdate!("ord_order", {"order_sn": &order_sn}, {
+                "pay_error": &pay_error,
+            }),
+        ) {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+        tran.commit().unwrap();
+        return Ok(());
+    }
     let uid = order[0].uid;
-    // 如果是充值记录，要另外写
     // 新增订单的交易记录
     let order_json = serde_json::to_value(&order).unwrap();
     match add_tran_record(
@@ -108,6 +147,22 @@ pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result
             return Err(e);
         }
     };
+    // 拼团订单，改为已支付待成团，成团后再分成
+    match is_group_order(&mut tran, &order_sn) {
+        Ok(true) => {
+            if let Err(e) = group_order_paid(&mut tran, &order_sn, Some(data.transaction_id)) {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+            tran.commit().unwrap();
+            return Ok(());
+        }
+        Ok(false) => (),
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    }
     // 进行销售分成处理
     match do_order_sale_split(&mut tran, &order_sn, uid, PayType::WxPay) {
         Ok(()) => (),
@@ -116,6 +171,11 @@ pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result
             return Err(e);
         }
     };
+    // 进行区域代理分成
+    if let Err(e) = do_order_agent_split(&mut tran, &order_sn) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
     // 修改订单状态 为 已支付
     match upd_order_status(
         &mut tran,
@@ -150,7 +210,7 @@ pub async fn pay_notify(body: web::Bytes, req: actix_web::HttpRequest) -> Result
     tran.commit().unwrap();
     // ---- 事务结束 ----
 
-    Ok(web::Json(Res::success("")))
+    Ok(())
 }
 
 /// 微信转账回调通知
@@ -159,11 +219,16 @@ pub async fn pay_transfer_notify(
     body: web::Bytes,
     req: actix_web::HttpRequest,
 ) -> Result<impl Responder> {
-    let data: WxTransferResource = get_decode_wx_notify(body, req)?;
+    let data: WxTransferResource = decode_notify(&body, &req)?;
+    db_block(move || pay_transfer_notify_handle(data)).await?;
+    Ok(web::Json(Res::success("转账回调处理成功")))
+}
 
+/// 转账回调的处理，模拟支付时直接调用
+pub fn pay_transfer_notify_handle(data: WxTransferResource) -> Result<(), Error> {
     if data.state != TransferBillStatus::SUCCESS {
         // 不是成功，则不修改状态
-        return Ok(web::Json(Res::success("")));
+        return Ok(());
     }
 
     // ----- 业务逻辑 -----
@@ -182,8 +247,7 @@ pub async fn pay_transfer_notify(
     struct WithdrawalRequest {
         id: u64,
         uid: u64,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        req_amount: f64,
+        req_amount: Money,
         status: u8,
         transfer_hash: String,
     }
@@ -247,7 +311,7 @@ pub async fn pay_transfer_notify(
     // 提交事务
     tran.commit().unwrap();
 
-    Ok(web::Json(Res::success("转账回调处理成功")))
+    Ok(())
 }
 
 /// 微信退款回调通知
@@ -256,11 +320,16 @@ pub async fn pay_refund_notify(
     body: web::Bytes,
     req: actix_web::HttpRequest,
 ) -> Result<impl Responder> {
-    let data: WxRefundResource = get_decode_wx_notify(body, req)?;
+    let data: WxRefundResource = decode_notify(&body, &req)?;
+    db_block(move || pay_refund_notify_handle(data)).await?;
+    Ok(web::Json(Res::success("退款回调处理成功")))
+}
 
+/// 退款回调的处理，模拟支付时直接调用
+pub fn pay_refund_notify_handle(data: WxRefundResource) -> Result<(), Error> {
     if data.refund_status != RefundStatus::SUCCESS {
         // 不是成功，则不修改状态
-        return Ok(web::Json(Res::success("")));
+        return Ok(());
     }
 
     // ----- 业务逻辑 -----
@@ -272,6 +341,19 @@ pub async fn pay_refund_notify(
         .start_transaction(TxOpts::default())
         .map_err(|_| error::ErrorInternalServerError("事务错误"))?;
 
+    // 售后的部分退款，按退款单号处理，订单状态不变
+    match finish_after_sale_by_refund_no(&mut tran, &data.out_refund_no) {
+        Ok(true) => {
+            tran.commit().unwrap();
+            return Ok(());
+        }
+        Ok(false) => {}
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    }
+
     // 通过微信交易号查找对应的主订单
     #[derive(Deserialize, Serialize)]
     struct OrderRefundInfo {
@@ -310,6 +392,17 @@ pub async fn pay_refund_notify(
         }
     }
 
+    // 取消或扣回订单的销售佣金
+    if let Err(e) = commission_refund_order(&mut tran, &order_sn) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    // 扣回订单的代理分成
+    if let Err(e) = agent_split_back(&mut tran, &order_sn, None) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+
     // 2. 查询该订单下的所有子订单项
     #[derive(Deserialize, Serialize)]
     struct OrderItemRefund {
@@ -363,12 +456,12 @@ pub async fn pay_refund_notify(
     // 提交事务
     tran.commit().unwrap();
 
-    Ok(web::Json(Res::success("退款回调处理成功")))
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::common::WECHAT_PAY_APIV3;
+    use crate::common::config;
     use wx_pay::decode::*;
     #[test]
     fn test_decodewx() {
@@ -387,7 +480,7 @@ mod tests {
             summary: "退款成功".to_string(),
         };
 
-        let data: WxRefundResource = decode_wx_notify(WECHAT_PAY_APIV3, params).unwrap();
+        let data: WxRefundResource = decode_wx_notify(&config().wechat_pay.apiv3, params).unwrap();
 
         println!("数据： {:#?}", data);
     }
@@ -0,0 +1,98 @@
+use actix_web::{Responder, Result, error, get, web};
+use mysql_quick::myfind;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::Role;
+use crate::db::{my_exec_first, my_run_vec, mysql_conn};
+use crate::middleware::{AuthRole, ModuleDistribution, PermSalesCommission, RequireModule};
+use crate::routes::Res;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct CommissionItem {
+    id: u64,
+    order_sn: String,
+    order_item_id: String,
+    unit_sn: u32,
+    /// MAIN_SALE_SPLIT 总销售分账，SALE_SPLIT 销售分账
+    tran_type: String,
+    /// 单件商品的佣金
+    unit_amount: Money,
+    /// 购买数量
+    quantity: u32,
+    /// 已退的数量
+    refund_quantity: u32,
+    /// 下单时的佣金
+    amount: Money,
+    /// 结算入零钱的佣金
+    settled_amount: Money,
+    /// 结算后退款扣回的佣金
+    back_amount: Money,
+    /// 0 冻结中，1 已结算，2 已取消
+    status: u8,
+    settle_time: Option<String>,
+    created_at: String,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct CommissionRes {
+    /// 冻结中，商品完成并过了冻结期后结算
+    pending: Money,
+    /// 已结算到零钱（已扣除退款扣回的）
+    available: Money,
+    /// 退款取消或扣回的
+    reversed: Money,
+    list: Vec<CommissionItem>,
+}
+/// 【分销】我的佣金，冻结中、已结算和已退回的合计，及佣金明细
+#[utoipa::path(
+    responses((status = 200, description = "【返回：CommissionRes】", body = CommissionRes)),
+    params(("page", description="页码"),("limit", description="每页数量"))
+)]
+#[get("/sales/commission/{page}/{limit}")]
+pub async fn sales_commission(
+    user: AuthRole<PermSalesCommission>,
+    _m: RequireModule<ModuleDistribution>,
+    query: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    if !user.role.contains(&(Role::Sale as u16)) && !user.role.contains(&(Role::MainSale as u16)) {
+        return Err(error::ErrorUnauthorized("你不是销售"));
+    }
+    let page = query.0.parse::<u32>().unwrap();
+    let limit = query.1.parse::<u32>().unwrap();
+    let mut conn = mysql_conn()?;
+
+    // 冻结中的按未退的数量，已结算的按结算金额减去扣回的，其余为退回的
+    let sum: Option<(Option<String>, Option<String>, Option<String>)> = my_exec_first(
+        &mut conn,
+        "select sum(if(status = 0, unit_amount * (quantity - refund_quantity), 0)),
+            sum(if(status = 1, settled_amount - back_amount, 0)),
+            sum(amount)
+            from usr_commission where uid = ? and is_del = 0",
+        (uid,),
+    )?;
+    let (pending, available, total) = sum.unwrap_or_default();
+    let parse = |x: Option<String>| x.and_then(|s| s.parse::<Money>().ok()).unwrap_or_default();
+    let (pending, available, total) = (parse(pending), parse(available), parse(total));
+
+    let list: Vec<CommissionItem> = my_run_vec(
+        &mut conn,
+        myfind!("usr_commission", {
+            p0: ["is_del", "=", 0],
+            p1: ["uid", "=", uid],
+            r: "p0 && p1",
+            page: page,
+            limit: limit,
+            order_by: "-created_at",
+            select: "id,order_sn,order_item_id,unit_sn,tran_type,unit_amount,quantity,refund_quantity,amount,settled_amount,back_amount,status,settle_time,created_at",
+        }),
+    )?;
+
+    Ok(web::Json(Res::success(CommissionRes {
+        pending,
+        available,
+        reversed: total - pending - available,
+        list,
+    })))
+}
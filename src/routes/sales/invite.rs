@@ -6,7 +6,10 @@ use utoipa::ToSchema;
 use crate::common::LocalKeySeed;
 use crate::common::types::Role;
 use crate::db::mysql_conn;
-use crate::middleware::{AuthRole, AuthUser};
+use crate::middleware::{
+    AuthRole, AuthUser, ModuleDistribution, PermSalesInviteSaleCode, PermSalesInviteSaleDel,
+    PermSalesInviteUserCode, PermSalesInviteUserDel, RequireModule,
+};
 use crate::routes::Res;
 use crate::routes::utils_set::sales_set::{
     main_sale_invite_sale, sale_and_main_del, sale_invite_user, user_and_sale_del,
@@ -19,7 +22,10 @@ use crate::utils::time::{gen_now_expire_time, is_expired};
     responses((status = 200, description = "【返回：String】", body = String)),
 )]
 #[get("/sales/invite/sale/code")]
-pub async fn sales_invite_sale_code(user: AuthRole) -> Result<impl Responder> {
+pub async fn sales_invite_sale_code(
+    user: AuthRole<PermSalesInviteSaleCode>,
+    _m: RequireModule<ModuleDistribution>,
+) -> Result<impl Responder> {
     let uid = user.id;
     if !user.role.contains(&(Role::MainSale as u16)) {
         return Err(error::ErrorUnauthorized("你不是总销售"));
@@ -44,6 +50,7 @@ pub struct Invite {
 #[post("/sales/invite/sale/bind")]
 pub async fn sales_invite_sale_bind(
     user: AuthUser,
+    _m: RequireModule<ModuleDistribution>,
     params: web::Json<Invite>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -87,7 +94,8 @@ pub struct SaleDelUid {
 )]
 #[put("/sales/invite/sale/del")]
 pub async fn sales_invite_sale_del(
-    user: AuthRole,
+    user: AuthRole<PermSalesInviteSaleDel>,
+    _m: RequireModule<ModuleDistribution>,
     params: web::Json<SaleDelUid>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -116,7 +124,10 @@ pub async fn sales_invite_sale_del(
     responses((status = 200, description = "【返回：String】", body = String)),
 )]
 #[get("/sales/invite/user/code")]
-pub async fn sales_invite_user_code(user: AuthRole) -> Result<impl Responder> {
+pub async fn sales_invite_user_code(
+    user: AuthRole<PermSalesInviteUserCode>,
+    _m: RequireModule<ModuleDistribution>,
+) -> Result<impl Responder> {
     let uid = user.id;
     if !user.role.contains(&(Role::Sale as u16)) {
         return Err(error::ErrorUnauthorized("你不是销售"));
@@ -136,6 +147,7 @@ pub async fn sales_invite_user_code(user: AuthRole) -> Result<impl Responder> {
 #[post("/sales/invite/user/bind")]
 pub async fn sales_invite_user_bind(
     user: AuthUser,
+    _m: RequireModule<ModuleDistribution>,
     params: web::Json<Invite>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -175,7 +187,8 @@ pub async fn sales_invite_user_bind(
 )]
 #[put("/sales/invite/user/del")]
 pub async fn sales_invite_user_del(
-    user: AuthRole,
+    user: AuthRole<PermSalesInviteUserDel>,
+    _m: RequireModule<ModuleDistribution>,
     params: web::Json<SaleDelUid>,
 ) -> Result<impl Responder> {
     let uid = user.id;
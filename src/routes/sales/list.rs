@@ -5,7 +5,9 @@ use utoipa::ToSchema;
 
 use crate::common::types::Role;
 use crate::db::{my_run_vec, mysql_conn};
-use crate::middleware::AuthRole;
+use crate::middleware::{
+    AuthRole, ModuleDistribution, PermSalesListSale, PermSalesListUser, RequireModule,
+};
 use crate::routes::Res;
 use crate::utils::files::get_file_url;
 
@@ -28,7 +30,8 @@ pub struct SaleUserItem {
 )]
 #[get("/sales/list/sale/{page}/{limit}")]
 pub async fn sales_list_sale(
-    user: AuthRole,
+    user: AuthRole<PermSalesListSale>,
+    _m: RequireModule<ModuleDistribution>,
     query: web::Path<(String, String)>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -85,7 +88,8 @@ pub async fn sales_list_sale(
 )]
 #[get("/sales/list/user/{page}/{limit}")]
 pub async fn sales_list_user(
-    user: AuthRole,
+    user: AuthRole<PermSalesListUser>,
+    _m: RequireModule<ModuleDistribution>,
     query: web::Path<(String, String)>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -1,3 +1,6 @@
+mod commission;
+pub use commission::*;
+
 mod invite;
 pub use invite::*;
 
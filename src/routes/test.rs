@@ -3,7 +3,8 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::{
-    common::{JWT_MANAGE_EXPIRES_SEC, LocalKeySeed},
+    common::{LocalKeySeed, config},
+    control::token::token_ver,
     middleware::AuthUser,
     utils::jwt::get_token,
 };
@@ -73,7 +74,8 @@ pub struct TestJwtToken {
 #[get("/test/jwt/token/{uid}")]
 pub async fn test_jwt_token(query: web::Path<String>, _req: HttpRequest) -> Result<impl Responder> {
     let uid = query.parse::<u64>().unwrap();
-    let token = get_token(AuthUser { id: uid }, JWT_MANAGE_EXPIRES_SEC)?;
+    let ver = token_ver(uid)?.unwrap_or_default();
+    let token = get_token(AuthUser { id: uid }, ver, config().jwt.manage_expires_sec)?;
 
     // if let Some(client_ip) = get_client_ip(&req) {
     //     println!("客户端IP: {}", client_ip.ip());
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::Res;
-use crate::common::FILE_STORAGE_TYPE;
+use crate::common::config;
 use crate::common::types::{FileDir, OssBucket};
 use crate::control::app_data::{AppData, SlownWorker};
 use crate::middleware::AuthUser;
@@ -96,7 +96,7 @@ pub async fn upload_file(
     let dir_path = oss_dir.get_dir();
     let filepath = format!("{dir_path}/{rand_name}.{file_type}");
 
-    if FILE_STORAGE_TYPE == 1 {
+    if config().file.storage_type == 1 {
         put_local_file(&buf, &dir_path, &filepath)?;
     } else {
         match put_oss_file(&buf, &filepath, OssBucket::EobFiles).await {
@@ -1,11 +1,13 @@
-use actix_web::{get, web, Responder, Result};
+use actix_web::{Responder, Result, get, web};
+use chrono::{Duration, Local};
 use mysql_quick::myfind;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::{
+    common::{Money, config, types::UserCouponStatus},
     db::{my_run_vec, mysql_conn},
-    middleware::AuthUser,
+    middleware::{AuthUser, ModuleCoupon, RequireModule},
 };
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
@@ -17,9 +19,11 @@ pub struct UserCouponRes {
     /// 优惠券名称
     coupon_name: String,
     /// 优惠券优惠金额
-    reduce_amount: Option<f64>,
+    reduce_amount: Option<Money>,
     /// 优惠券折扣额度
     discount: Option<f64>,
+    /// 可用的开始时间，为空时不限
+    start_time: Option<String>,
     /// 过期时间
     expire_time: Option<String>,
     /// 优惠券条件标题
@@ -33,7 +37,11 @@ pub struct UserCouponRes {
     params(("status", description="0全部，1已过期，2未使用，3已使用"))
 )]
 #[get("/user/coupon/list/{status}")]
-pub async fn user_coupon_list(user: AuthUser, query: web::Path<u8>) -> Result<impl Responder> {
+pub async fn user_coupon_list(
+    user: AuthUser,
+    _m: RequireModule<ModuleCoupon>,
+    query: web::Path<u8>,
+) -> Result<impl Responder> {
     let uid = user.id;
     let status = query.to_owned();
 
@@ -43,8 +51,10 @@ pub async fn user_coupon_list(user: AuthUser, query: web::Path<u8>) -> Result<im
         id: u64,
         coupon_id: u32,
         coupon_name: String,
-        reduce_amount: Option<String>,
+        reduce_amount: Option<Money>,
         discount: Option<String>,
+        start_time: Option<String>,
+        end_time: Option<String>,
         expire_time: Option<String>,
         coupon_condition_title: String,
         status: u8,
@@ -56,7 +66,7 @@ pub async fn user_coupon_list(user: AuthUser, query: web::Path<u8>) -> Result<im
         p1: ["uid", "=", uid],
         p2: ["status", "=", status],
         r: if status == 0 { "p0 && p1" } else { "p0 && p1 && p2" },
-       select: "id, coupon_id, pmt_coupon.coupon_name, pmt_coupon.reduce_amount, status, pmt_coupon.discount,pmt_coupon.expire_time,
+       select: "id, coupon_id, pmt_coupon.coupon_name, pmt_coupon.reduce_amount, status, pmt_coupon.discount,start_time,end_time,pmt_coupon.expire_time,
             pmt_coupon_condition.title as coupon_condition_title",
     });
     let list: Vec<CouponGet> = my_run_vec(&mut conn, sql)?;
@@ -67,17 +77,77 @@ pub async fn user_coupon_list(user: AuthUser, query: web::Path<u8>) -> Result<im
             id: x.id,
             coupon_id: x.coupon_id,
             coupon_name: x.coupon_name,
-            reduce_amount: if let Some(d) = x.reduce_amount {
-                Some(d.parse::<f64>().unwrap())
-            } else {
-                None
-            },
+            reduce_amount: x.reduce_amount,
             discount: if let Some(d) = x.discount {
                 Some(d.parse::<f64>().unwrap())
             } else {
                 None
             },
-            expire_time: x.expire_time.clone(),
+            start_time: x.start_time,
+            // 之前领取的没有过期时间，按优惠券的
+            expire_time: x.end_time.or(x.expire_time),
+            coupon_condition_title: x.coupon_condition_title,
+            status: x.status,
+        })
+        .collect();
+
+    Ok(web::Json(list))
+}
+
+/// 【用户】即将过期的优惠券，配置的天数内过期、未使用的，先过期的在前
+#[utoipa::path(
+    responses((status = 200, description = "【返回：UserCouponRes[]】", body = Vec<UserCouponRes>))
+)]
+#[get("/user/coupon/expiring")]
+pub async fn user_coupon_expiring(
+    user: AuthUser,
+    _m: RequireModule<ModuleCoupon>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    let now = Local::now();
+    let deadline = (now + Duration::days(config().coupon.expiring_days as i64))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    let now = now.format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let mut conn = mysql_conn()?;
+    #[derive(Deserialize)]
+    pub struct CouponGet {
+        id: u64,
+        coupon_id: u32,
+        coupon_name: String,
+        reduce_amount: Option<Money>,
+        discount: Option<String>,
+        start_time: Option<String>,
+        end_time: Option<String>,
+        coupon_condition_title: String,
+        status: u8,
+    }
+    let sql = myfind!("usr_coupon", {
+        j0: ["coupon_id", "inner", "pmt_coupon.id"],
+        j1: ["pmt_coupon.coupon_condition_id", "inner", "pmt_coupon_condition.id"],
+        p0: ["is_del", "=", 0],
+        p1: ["uid", "=", uid],
+        p2: ["status", "=", UserCouponStatus::NotUsed as u8],
+        p3: ["end_time", ">", &now],
+        p4: ["end_time", "<=", &deadline],
+        r: "p0 && p1 && p2 && p3 && p4",
+        order_by: "end_time",
+        select: "id, coupon_id, pmt_coupon.coupon_name, pmt_coupon.reduce_amount, status, pmt_coupon.discount,start_time,end_time,
+            pmt_coupon_condition.title as coupon_condition_title",
+    });
+    let list: Vec<CouponGet> = my_run_vec(&mut conn, sql)?;
+
+    let list: Vec<UserCouponRes> = list
+        .into_iter()
+        .map(|x| UserCouponRes {
+            id: x.id,
+            coupon_id: x.coupon_id,
+            coupon_name: x.coupon_name,
+            reduce_amount: x.reduce_amount,
+            discount: x.discount.and_then(|d| d.parse::<f64>().ok()),
+            start_time: x.start_time,
+            expire_time: x.end_time,
             coupon_condition_title: x.coupon_condition_title,
             status: x.status,
         })
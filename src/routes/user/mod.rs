@@ -10,3 +10,5 @@ mod coupon;
 pub use coupon::*;
 mod pocket;
 pub use pocket::*;
+mod recharge;
+pub use recharge::*;
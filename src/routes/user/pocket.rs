@@ -1,38 +1,38 @@
 use actix_web::{Responder, Result, error, get, post, web};
-use mysql_quick::{TxOpts, myfind, myset, myupdate};
+use mysql_quick::{TxOpts, myfind, myupdate};
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::deserialize_number_from_string;
 use serde_json::Value;
 use utoipa::ToSchema;
-use wx_pay::{Transfer, TransferSceneReportInfo};
 
 use crate::common::types::{NormalStatus, PayType, TranType, WithdrawalReqStatus};
-use crate::common::{WECHAT_MINI_APP_ID, WECHAT_PAY_MCH_ID, WECHAT_PAY_TRANSFER_NOTIFY_URL};
-use crate::control::wx_info::wx_pay_init;
+use crate::common::{Config, Money};
+use crate::control::payment::{TransferReq, payment};
 use crate::routes::Res;
 use crate::routes::utils_set::hash_set::{
     hash_user_verify, hash_user_withdrawal_money, hash_user_withdrawal_money_verify,
 };
-use crate::routes::utils_set::pocket_set::{get_user_pocket_money, pocket_money_sub};
+use crate::routes::utils_set::pocket_set::add_withdrawal_request;
 use crate::utils::filter::deserialize_nested_json;
 use crate::{
     db::{my_run_tran_drop, my_run_vec, mysql_conn},
-    middleware::AuthUser,
+    middleware::{AuthUser, ModulePocketMoney, RequireModule},
 };
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct UserPocket {
     id: u64,
     /// 零钱，单位元
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    amount: f64,
+    amount: Money,
 }
 /// 【用户】获取用户零钱
 #[utoipa::path(
     responses((status = 200, description = "【返回：UserPocket】", body = UserPocket))
 )]
 #[get("/user/pocket/money")]
-pub async fn user_pocket_money(user: AuthUser) -> Result<impl Responder> {
+pub async fn user_pocket_money(
+    user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
+) -> Result<impl Responder> {
     let uid = user.id;
     let mut conn = mysql_conn()?;
 
@@ -57,7 +57,7 @@ pub async fn user_pocket_money(user: AuthUser) -> Result<impl Responder> {
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct WithdrawRequest {
     /// 申请提现金额，单位元
-    req_amount: f64,
+    req_amount: Money,
 }
 
 /// 【用户】提现申请
@@ -68,84 +68,20 @@ pub struct WithdrawRequest {
 #[post("/user/pocket/withdraw_req")]
 pub async fn user_pocket_withdraw_req(
     user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
     data: web::Json<WithdrawRequest>,
 ) -> Result<impl Responder> {
     let uid = user.id;
     let req_amount = data.req_amount;
 
     // 验证提现金额
-    if req_amount <= 0.0 {
+    if !req_amount.is_positive() {
         return Err(error::ErrorBadRequest("提现金额必须大于0"));
     }
 
     let mut conn = mysql_conn()?;
-
-    let s = [
-        WithdrawalReqStatus::Approved as u8,
-        WithdrawalReqStatus::UnderReview as u8,
-        WithdrawalReqStatus::Ing as u8,
-    ]
-    .iter()
-    .map(|x| x.to_string())
-    .collect::<Vec<String>>();
-    // 检查是否有正在审核中的提现申请
-    let pending_requests: Vec<serde_json::Value> = my_run_vec(
-        &mut conn,
-        myfind!("usr_withdrawal_request", {
-            p0: ["uid", "=", uid],
-            p1: ["status", "in", s.join(",")],
-            p2: ["is_del", "=", 0],
-            r: "p0 && p1 && p2",
-            select: "id",
-        }),
-    )?;
-    if !pending_requests.is_empty() {
-        return Err(error::ErrorBadRequest("您有未完成的提现申请，请先完成。"));
-    }
-
     let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
-
-    // 检查用户零钱余额
-    let pocket_info = match get_user_pocket_money(&mut tran, uid) {
-        Ok(info) => info,
-        Err(e) => {
-            tran.rollback().unwrap();
-            return Err(e);
-        }
-    };
-    if pocket_info.amount < req_amount {
-        tran.rollback().unwrap();
-        return Err(error::ErrorBadRequest("零钱余额不足"));
-    }
-
-    // 零钱减，同时有交易记录添加
-    match pocket_money_sub(
-        &mut tran,
-        uid,
-        req_amount,
-        TranType::Withdraw,
-        PayType::WxPay,
-        Some("用户提现"),
-    ) {
-        Ok(_) => (),
-        Err(e) => {
-            tran.rollback().unwrap();
-            return Err(e);
-        }
-    };
-
-    let hash =
-        hash_user_withdrawal_money(uid, req_amount, "", WithdrawalReqStatus::UnderReview as u8)?;
-    // 插入提现申请记录
-    if let Err(e) = my_run_tran_drop(
-        &mut tran,
-        myset!("usr_withdrawal_request", {
-            "uid": uid,
-            "req_amount": req_amount,
-            "status": WithdrawalReqStatus::UnderReview as u8,
-            "transfer_hash": hash,
-        }),
-    ) {
+    if let Err(e) = add_withdrawal_request(&mut tran, uid, req_amount) {
         tran.rollback().unwrap();
         return Err(e);
     }
@@ -159,8 +95,7 @@ pub async fn user_pocket_withdraw_req(
 #[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
 pub struct UserPendingWithdraw {
     /// 零钱，单位元
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    req_amount: f64,
+    req_amount: Money,
     /// 提现状态
     status: Option<WithdrawalReqStatus>,
 }
@@ -169,11 +104,14 @@ pub struct UserPendingWithdraw {
     responses((status = 200, description = "【返回：UserPendingWithdraw】", body = UserPendingWithdraw))
 )]
 #[get("/user/pocket/pending_withdraw")]
-pub async fn user_pocket_pending_withdraw(user: AuthUser) -> Result<impl Responder> {
+pub async fn user_pocket_pending_withdraw(
+    user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
+) -> Result<impl Responder> {
     let uid = user.id;
     let mut conn = mysql_conn()?;
     let mut info = UserPendingWithdraw {
-        req_amount: 0.,
+        req_amount: Money::ZERO,
         status: None,
     };
     let list: Vec<UserPendingWithdraw> = my_run_vec(
@@ -199,8 +137,7 @@ pub async fn user_pocket_pending_withdraw(user: AuthUser) -> Result<impl Respond
 pub struct UserTran {
     id: u64,
     /// 交易金额，单位元。负数表示支出，正数表示收入。
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    tran_amount: f64,
+    tran_amount: Money,
     /// 交易类型
     tran_type: TranType,
     /// 支付方式
@@ -219,6 +156,7 @@ pub struct UserTran {
 #[get("/user/pocket/tran/{tran_types}/{page}")]
 pub async fn user_pocket_tran(
     user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
     path: web::Path<(String, String)>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -247,8 +185,7 @@ pub async fn user_pocket_tran(
 pub struct WithdrawalRequestItem {
     id: u64,
     /// 申请提现金额，单位元
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    req_amount: f64,
+    req_amount: Money,
     /// 提现状态
     status: WithdrawalReqStatus,
     /// 申请时间
@@ -267,6 +204,7 @@ pub struct WithdrawalRequestItem {
 #[get("/user/pocket/transfer/list/{page}")]
 pub async fn user_pocket_transfer_list(
     user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
     path: web::Path<String>,
 ) -> Result<impl Responder> {
     let uid = user.id;
@@ -300,7 +238,11 @@ pub struct WithdrawalRequestInfo {
     responses((status = 200, description = "转账发起成功", body = String))
 )]
 #[get("/user/pocket/transfer")]
-pub async fn user_pocket_transfer(user: AuthUser) -> Result<impl Responder> {
+pub async fn user_pocket_transfer(
+    user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
+    cfg: web::Data<Config>,
+) -> Result<impl Responder> {
     let uid = user.id;
     let mut conn = mysql_conn()?;
 
@@ -308,8 +250,7 @@ pub async fn user_pocket_transfer(user: AuthUser) -> Result<impl Responder> {
     #[derive(Serialize, Deserialize)]
     struct WithdrawalRequest {
         id: u64,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        req_amount: f64,
+        req_amount: Money,
         out_bill_no: Option<String>,
         transfer_hash: String,
         status: u8,
@@ -378,36 +319,23 @@ pub async fn user_pocket_transfer(user: AuthUser) -> Result<impl Responder> {
         .start_transaction(TxOpts::default())
         .map_err(|_| error::ErrorInternalServerError("事务错误"))?;
 
-    // 初始化微信支付
-    let wx_pay = wx_pay_init();
-
-    // 构建转账场景报备信息
-    let mut transfer_scene_report_infos = Vec::new();
-    transfer_scene_report_infos.push(TransferSceneReportInfo {
-        info_type: "岗位类型".to_string(),
-        info_content: "销售".to_string(),
-    });
-    transfer_scene_report_infos.push(TransferSceneReportInfo {
-        info_type: "报酬说明".to_string(),
-        info_content: "销售分成申请提现".to_string(),
-    });
-
     // 构建转账请求
-    let transfer_req = Transfer {
-        appid: wx_pay.appid.to_string(),
+    let transfer_req = TransferReq {
         out_bill_no: out_batch_no.clone(),
-        transfer_scene_id: "1005".to_string(), // 转账场景ID
         openid: openid.clone(),
-        user_name: None, // 收款用户姓名，可选
-        transfer_amount: (withdrawal_req.req_amount * 100.0) as u64, // 转账金额，单位分
-        transfer_remark: "销售金额提现".to_string(),
-        notify_url: Some(WECHAT_PAY_TRANSFER_NOTIFY_URL.to_string()),
-        user_recv_perception: Some("劳务报酬".to_string()),
-        transfer_scene_report_infos,
+        amount: withdrawal_req.req_amount,
+        scene_id: "1005".to_string(), // 转账场景ID
+        remark: "销售金额提现".to_string(),
+        recv_perception: Some("劳务报酬".to_string()),
+        // 转账场景报备信息
+        report_infos: vec![
+            ("岗位类型".to_string(), "销售".to_string()),
+            ("报酬说明".to_string(), "销售分成申请提现".to_string()),
+        ],
     };
 
-    // 调用微信转账接口
-    match wx_pay.transfer(&transfer_req).await {
+    // 调用转账接口
+    match payment().transfer(&transfer_req).await {
         Ok(transfer_result) => {
             let hash = hash_user_withdrawal_money(
                 uid,
@@ -431,8 +359,8 @@ pub async fn user_pocket_transfer(user: AuthUser) -> Result<impl Responder> {
             // 提交事务
             tran.commit().unwrap();
             Ok(web::Json(Res::success(WithdrawalRequestInfo {
-                appid: WECHAT_MINI_APP_ID.to_string(),
-                mchid: WECHAT_PAY_MCH_ID.to_string(),
+                appid: cfg.wechat.mini_app_id.clone(),
+                mchid: cfg.wechat_pay.mch_id.clone(),
                 package: transfer_result.package_info.unwrap_or("".to_string()),
             })))
         }
@@ -0,0 +1,137 @@
+use actix_web::{Responder, Result, get, post, web};
+use mysql_quick::{TxOpts, myfind, myset};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::{NormalStatus, RechargeStatus};
+use crate::control::app_data::{AppData, SlownWorker};
+use crate::control::payment::{PrepayReq, payment};
+use crate::db::{my_run_tran_drop, my_run_vec, mysql_conn};
+use crate::middleware::{AuthUser, ModulePocketMoney, RequireModule};
+use crate::routes::utils_set::mall_set::{get_user_openid, pay_expire_time};
+use crate::routes::utils_set::pocket_set::init_user_pocket_money;
+use crate::routes::utils_set::recharge_set::recharge_trade_no;
+use crate::routes::{Res, WxPayInfo};
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+pub struct RechargeOption {
+    id: u32,
+    /// 名称
+    title: Option<String>,
+    /// 充值金额，单位元
+    amount: Money,
+    /// 充值后赠送的零钱，单位元
+    bonus_amount: Money,
+}
+/// 【用户】零钱充值的金额选项
+#[utoipa::path(
+    responses((status = 200, description = "【返回：RechargeOption[]】", body = Vec<RechargeOption>))
+)]
+#[get("/user/pocket/recharge/options")]
+pub async fn user_pocket_recharge_options(
+    _user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
+) -> Result<impl Responder> {
+    let mut conn = mysql_conn()?;
+    let list: Vec<RechargeOption> = my_run_vec(
+        &mut conn,
+        myfind!("sys_recharge_amount", {
+            p0: ["status", "=", NormalStatus::Online as u8],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            order_by: "amount",
+            select: "id,title,amount,bonus_amount",
+        }),
+    )?;
+    Ok(web::Json(Res::success(list)))
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RechargeReq {
+    /// 选择的充值金额选项 id
+    id: u32,
+}
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+pub struct RechargeRes {
+    /// 充值单号
+    out_trade_no: String,
+    /// 微信支付，支付参数
+    wx_pay: WxPayInfo,
+}
+/// 【用户】零钱充值，创建充值单并发起微信支付，支付成功后到账零钱
+#[utoipa::path(
+    request_body = RechargeReq,
+    responses((status = 200, description = "【请求：RechargeReq】【返回：RechargeRes】", body = RechargeRes))
+)]
+#[post("/user/pocket/recharge")]
+pub async fn user_pocket_recharge(
+    user: AuthUser,
+    _m: RequireModule<ModulePocketMoney>,
+    params: web::Json<RechargeReq>,
+    app_data: web::Data<AppData>,
+) -> Result<impl Responder> {
+    let uid = user.id;
+    let mut conn = mysql_conn()?;
+    let option: Vec<RechargeOption> = my_run_vec(
+        &mut conn,
+        myfind!("sys_recharge_amount", {
+            p0: ["id", "=", params.id],
+            p1: ["status", "=", NormalStatus::Online as u8],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "id,title,amount,bonus_amount",
+        }),
+    )?;
+    let Some(option) = option.into_iter().next() else {
+        return Ok(web::Json(Res::fail("充值金额不存在")));
+    };
+    if !option.amount.is_positive() {
+        return Ok(web::Json(Res::fail("充值金额错误")));
+    }
+    let openid = get_user_openid(&mut conn, uid)?;
+    init_user_pocket_money(&mut conn, uid)?;
+
+    let out_trade_no = recharge_trade_no(&app_data.rand_no(SlownWorker::OutTradeNo));
+    // ---- 事务开始 ----
+    let mut tran = conn.start_transaction(TxOpts::default()).unwrap();
+    if let Err(e) = my_run_tran_drop(
+        &mut tran,
+        myset!("usr_recharge", {
+            "uid": uid,
+            "out_trade_no": &out_trade_no,
+            "recharge_amount_id": option.id,
+            "amount": option.amount.to_string(),
+            "bonus_amount": option.bonus_amount.to_string(),
+            "status": RechargeStatus::WaitPay as u8,
+        }),
+    ) {
+        tran.rollback().unwrap();
+        return Err(e);
+    }
+    // 发起微信支付
+    let wxinfo = match payment()
+        .prepay(&PrepayReq {
+            description: format!("零钱充值{}元", option.amount),
+            out_trade_no: out_trade_no.clone(),
+            amount: option.amount,
+            openid,
+            // 超时未支付的充值单会被定时任务取消，微信订单同时失效
+            time_expire: Some(pay_expire_time()),
+        })
+        .await
+    {
+        Ok(data) => data,
+        Err(e) => {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+    };
+    tran.commit().unwrap();
+    // ---- 事务结束 ----
+
+    Ok(web::Json(Res::success(RechargeRes {
+        out_trade_no,
+        wx_pay: wxinfo.into(),
+    })))
+}
@@ -0,0 +1,587 @@
+//! 售后业务逻辑
+//!
+//! 用户按子订单和数量申请仅退款或退货退款，申请期间子订单为申请退货。后台审核通过后，
+//! 仅退款的直接退款；退货退款的等用户填写退货运单，后台确认收货后退款。
+//! 退款时返还库存、扣回销售分账；零钱支付的直接退回零钱，微信支付的等退款回调后完成。
+//! 退完订单的全部商品时，最后一个售后单一并退回剩余的运费。
+
+use actix_web::{Error, error};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, PooledConn, Transaction, myfind, myupdate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::{
+    AfterSaleStatus, OrderItemStatus, OrderPayStatus, PayType, TranType, WriteOffStatus,
+};
+use crate::db::{my_exec_tran_drop, my_run_tran_drop, my_run_tran_vec, my_run_vec};
+use crate::routes::utils_set::agent_set::agent_split_back;
+use crate::routes::utils_set::mall_set::{
+    upd_order_item_status, upd_order_item_write_off_status, upd_order_status,
+};
+use crate::routes::utils_set::pocket_set::pocket_money_add;
+use crate::routes::utils_set::sales_set::{commission_refund, sale_split_back};
+use crate::utils::files::get_file_url;
+use crate::utils::time::{NowTimeType, get_now_time};
+
+/// 子订单退 quantity 件的退款金额，refunded 为之前已退的数量。
+///
+/// 商品实付（订单实付减去运费）按原价占比分摊到子订单，再按累计退的数量向下取整到分，
+/// 同一子订单分多次退完时，合计正好是它分摊到的金额
+pub fn item_refund_amount(
+    goods_pay: Money,
+    total_amount: Money,
+    price: Money,
+    refunded: u32,
+    quantity: u32,
+) -> Money {
+    if !total_amount.is_positive() || !goods_pay.is_positive() {
+        return Money::ZERO;
+    }
+    let share = |q: u32| {
+        goods_pay.cent() as i128 * (price * q).cent() as i128 / total_amount.cent() as i128
+    };
+    Money::from_cent((share(refunded + quantity) - share(refunded)) as i64)
+}
+
+/// 子订单退 quantity 件的退款金额。
+///
+/// 下单时记录了子订单实付 item_pay 的（已分摊满减、店铺券、平台券），按它分摊到每件；
+/// 之前的订单没有记录，按订单的商品实付分摊，见 item_refund_amount
+pub fn order_item_refund_amount(
+    item_pay: Option<Money>,
+    goods_pay: Money,
+    total_amount: Money,
+    price: Money,
+    buy_quantity: u32,
+    refunded: u32,
+    quantity: u32,
+) -> Money {
+    match item_pay {
+        Some(paid) => item_refund_amount(paid, price * buy_quantity, price, refunded, quantity),
+        None => item_refund_amount(goods_pay, total_amount, price, refunded, quantity),
+    }
+}
+
+/// 订单运费还可退回的金额，refunded 为之前售后已退的运费
+pub fn remaining_freight(delivery_amount: Option<Money>, refunded: Money) -> Money {
+    (delivery_amount.unwrap_or(Money::ZERO) - refunded).max(Money::ZERO)
+}
+
+/// 售后的微信退款单号，同一售后单总是相同，重试时微信不会重复退款
+pub fn after_sale_refund_no(after_sale_sn: &str) -> String {
+    format!("AS{}", after_sale_sn)
+}
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct AfterSaleItemRes {
+    /// 子订单id
+    order_item_id: String,
+    /// 商品编号
+    unit_sn: u32,
+    /// 商品名
+    unit_name: Option<String>,
+    /// 商品封面图
+    unit_cover: Option<String>,
+    /// 下单时的价格
+    price: Option<Money>,
+    /// 退的数量
+    quantity: u32,
+    /// 分摊的退款金额
+    refund_amount: Money,
+}
+/// 售后单的商品，按售后单id分组
+pub fn get_after_sale_item_res(
+    conn: &mut PooledConn,
+    ids: &[u64],
+) -> Result<HashMap<u64, Vec<AfterSaleItemRes>>, Error> {
+    let mut res: HashMap<u64, Vec<AfterSaleItemRes>> = HashMap::new();
+    if ids.is_empty() {
+        return Ok(res);
+    }
+    #[derive(Deserialize)]
+    struct ItemGet {
+        after_sale_id: u64,
+        order_item_id: String,
+        unit_sn: u32,
+        unit_name: Option<String>,
+        unit_cover: Option<String>,
+        price: Option<Money>,
+        quantity: u32,
+        refund_amount: Money,
+    }
+    let ids = ids
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let list: Vec<ItemGet> = my_run_vec(
+        conn,
+        myfind!("ord_after_sale_item", {
+            j0: ["order_item_id", "left", "ord_order_item.order_item_id"],
+            p0: ["after_sale_id", "in", ids],
+            r: "p0",
+            select: "after_sale_id,order_item_id,unit_sn,ord_order_item.unit_name,ord_order_item.unit_cover,ord_order_item.price,quantity,refund_amount",
+        }),
+    )?;
+    for x in list {
+        res.entry(x.after_sale_id)
+            .or_default()
+            .push(AfterSaleItemRes {
+                order_item_id: x.order_item_id,
+                unit_sn: x.unit_sn,
+                unit_name: x.unit_name,
+                unit_cover: get_file_url(x.unit_cover),
+                price: x.price,
+                quantity: x.quantity,
+                refund_amount: x.refund_amount,
+            });
+    }
+    Ok(res)
+}
+
+/// 需要在提交事务前调用微信接口的退款
+#[derive(Debug)]
+pub struct AfterSaleWxRefund {
+    pub transaction_id: String,
+    pub out_refund_no: String,
+    pub amount: Money,
+    /// 原订单的支付金额
+    pub total: Money,
+}
+
+#[derive(Deserialize)]
+struct AfterSaleGet {
+    id: u64,
+    after_sale_sn: String,
+    uid: u64,
+    order_sn: String,
+    refund_amount: Money,
+    status: u8,
+}
+/// 加锁查询售后单
+fn get_after_sale_lock(tran: &mut Transaction, id: u64) -> Result<Option<AfterSaleGet>, Error> {
+    let list: Vec<AfterSaleGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_after_sale", {
+            p0: ["id", "=", id],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id,after_sale_sn,uid,order_sn,refund_amount,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    Ok(list.into_iter().next())
+}
+
+#[derive(Deserialize)]
+struct AfterSaleItemGet {
+    order_item_id: String,
+    unit_sn: u32,
+    quantity: u32,
+    item_status: u8,
+}
+fn get_after_sale_items(
+    tran: &mut Transaction,
+    after_sale_id: u64,
+) -> Result<Vec<AfterSaleItemGet>, Error> {
+    my_run_tran_vec(
+        tran,
+        myfind!("ord_after_sale_item", {
+            p0: ["after_sale_id", "=", after_sale_id],
+            r: "p0",
+            select: "order_item_id,unit_sn,quantity,item_status",
+        }),
+    )
+}
+
+/// 子订单恢复为申请售后前的状态，核销单恢复为待核销
+fn restore_item_status(tran: &mut Transaction, item: &AfterSaleItemGet) -> Result<(), Error> {
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_order_item", { "order_item_id": &item.order_item_id }, {
+            "status": item.item_status,
+        }),
+    )?;
+    // 申请时被取消的核销单
+    my_exec_tran_drop(
+        tran,
+        "update ord_write_off_item set write_off_status = ? where order_item_id = ? and write_off_status = ?",
+        (
+            WriteOffStatus::PendingWriteOff as u8,
+            &item.order_item_id,
+            WriteOffStatus::Cancel as u8,
+        ),
+    )?;
+    Ok(())
+}
+
+/// 拒绝或用户取消售后：售后单改为 to，子订单恢复为申请前的状态。售后单当前不是 from 状态时返回 false
+pub fn close_after_sale(
+    tran: &mut Transaction,
+    id: u64,
+    from: &[AfterSaleStatus],
+    to: AfterSaleStatus,
+    remark: Option<&str>,
+) -> Result<bool, Error> {
+    let Some(after_sale) = get_after_sale_lock(tran, id)? else {
+        return Ok(false);
+    };
+    if !from.iter().any(|x| x.clone() as u8 == after_sale.status) {
+        return Ok(false);
+    }
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_after_sale", id, {
+            "status": to as u8,
+            "remark": remark,
+        }),
+    )?;
+    for item in get_after_sale_items(tran, id)? {
+        restore_item_status(tran, &item)?;
+    }
+    Ok(true)
+}
+
+/// 售后退款：返还库存、扣回销售分账和代理分成、累计子订单已退的数量。
+/// 退完订单的全部商品时，退款金额加上剩余的运费。
+///
+/// 零钱支付的直接退回零钱并完成售后；微信支付的售后单改为退款中，返回需要调用微信接口的退款，
+/// 调用方需在提交事务前发起退款，回调后由 `finish_after_sale_by_refund_no` 完成售后。
+/// 售后单当前不是 from 状态时返回错误
+pub fn after_sale_refund(
+    tran: &mut Transaction,
+    id: u64,
+    from: AfterSaleStatus,
+) -> Result<Option<AfterSaleWxRefund>, Error> {
+    let Some(after_sale) = get_after_sale_lock(tran, id)? else {
+        return Err(error::ErrorNotFound("售后单不存在"));
+    };
+    if after_sale.status != from as u8 {
+        return Err(error::ErrorBadRequest("售后单状态已变更"));
+    }
+    #[derive(Deserialize)]
+    struct OrderGet {
+        pay_amount: Money,
+        delivery_amount: Option<Money>,
+        pay_type: Option<String>,
+        transaction_id: Option<String>,
+    }
+    // 锁订单，同一订单的售后依次退款，只有一个能退运费
+    let order: Vec<OrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order", {
+            p0: ["order_sn", "=", &after_sale.order_sn],
+            r: "p0",
+            select: "pay_amount,delivery_amount,pay_type,transaction_id",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(order) = order.into_iter().next() else {
+        return Err(error::ErrorNotFound("订单不存在"));
+    };
+    let pay_type: PayType = order.pay_type.unwrap_or_default().into();
+
+    let items = get_after_sale_items(tran, id)?;
+    for item in &items {
+        my_run_tran_drop(
+            tran,
+            myupdate!("sku_unit", {"unit_sn": item.unit_sn}, {
+                "quantity": ["incr", item.quantity],
+            }),
+        )?;
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_order_item", { "order_item_id": &item.order_item_id }, {
+                "refund_quantity": ["incr", item.quantity],
+            }),
+        )?;
+        if !commission_refund(
+            tran,
+            &after_sale.order_sn,
+            &item.order_item_id,
+            item.quantity,
+        )? {
+            sale_split_back(
+                tran,
+                &after_sale.order_sn,
+                item.unit_sn,
+                item.quantity,
+                pay_type.clone(),
+            )?;
+        }
+    }
+    agent_split_back(tran, &after_sale.order_sn, Some(after_sale.refund_amount))?;
+
+    let freight = order_refund_freight(tran, &after_sale.order_sn, order.delivery_amount)?;
+    let refund_amount = after_sale.refund_amount + freight;
+    if freight.is_positive() {
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_after_sale", id, {
+                "refund_amount": refund_amount.to_string(),
+                "freight_amount": freight.to_string(),
+            }),
+        )?;
+    }
+
+    if pay_type == PayType::WxPay && refund_amount.is_positive() {
+        let Some(transaction_id) = order.transaction_id else {
+            return Err(error::ErrorInternalServerError(format!(
+                "订单 {} 缺少微信交易号",
+                after_sale.order_sn
+            )));
+        };
+        let out_refund_no = after_sale_refund_no(&after_sale.after_sale_sn);
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_after_sale", id, {
+                "status": AfterSaleStatus::Refunding as u8,
+                "pay_type": pay_type.to_string(),
+                "out_refund_no": &out_refund_no,
+            }),
+        )?;
+        for item in &items {
+            upd_order_item_status(tran, &item.order_item_id, OrderItemStatus::Refunding)?;
+        }
+        return Ok(Some(AfterSaleWxRefund {
+            transaction_id,
+            out_refund_no,
+            amount: refund_amount,
+            total: order.pay_amount,
+        }));
+    }
+
+    if refund_amount.is_positive() {
+        let info = serde_json::json!({
+            "order_sn": &after_sale.order_sn,
+            "after_sale_sn": &after_sale.after_sale_sn,
+        });
+        pocket_money_add(
+            tran,
+            after_sale.uid,
+            refund_amount,
+            TranType::Refund,
+            PayType::PocketPay,
+            Some(&info.to_string()),
+        )?;
+    }
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_after_sale", id, { "pay_type": pay_type.to_string() }),
+    )?;
+    finish_after_sale(tran, &after_sale, &items)?;
+    Ok(None)
+}
+
+/// 订单的商品已全部退完（含本次）时，返回还未退回的运费，否则为 0
+fn order_refund_freight(
+    tran: &mut Transaction,
+    order_sn: &str,
+    delivery_amount: Option<Money>,
+) -> Result<Money, Error> {
+    #[derive(Deserialize)]
+    struct OrderItemGet {
+        buy_quantity: u32,
+        refund_quantity: u32,
+    }
+    let order_items: Vec<OrderItemGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order_item", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "buy_quantity,refund_quantity",
+        }),
+    )?;
+    if order_items
+        .iter()
+        .any(|x| x.refund_quantity < x.buy_quantity)
+    {
+        return Ok(Money::ZERO);
+    }
+    #[derive(Deserialize)]
+    struct FreightGet {
+        freight_amount: Money,
+        status: u8,
+    }
+    let after_sales: Vec<FreightGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_after_sale", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "freight_amount,status",
+        }),
+    )?;
+    let refunded: Money = after_sales
+        .iter()
+        .filter(|x| {
+            x.status == AfterSaleStatus::Refunding as u8
+                || x.status == AfterSaleStatus::Refunded as u8
+        })
+        .map(|x| x.freight_amount)
+        .sum();
+    Ok(remaining_freight(delivery_amount, refunded))
+}
+
+/// 退款完成：售后单改为已退款。子订单全部退完的改为已退货，否则恢复为申请前的状态；
+/// 订单的商品全部退完时，订单改为已退款
+fn finish_after_sale(
+    tran: &mut Transaction,
+    after_sale: &AfterSaleGet,
+    items: &[AfterSaleItemGet],
+) -> Result<(), Error> {
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_after_sale", after_sale.id, {
+            "status": AfterSaleStatus::Refunded as u8,
+            "refund_time": get_now_time(NowTimeType::DateTime),
+        }),
+    )?;
+    #[derive(Deserialize)]
+    struct OrderItemGet {
+        buy_quantity: u32,
+        refund_quantity: u32,
+    }
+    for item in items {
+        let order_item: Vec<OrderItemGet> = my_run_tran_vec(
+            tran,
+            myfind!("ord_order_item", {
+                p0: ["order_item_id", "=", &item.order_item_id],
+                r: "p0",
+                select: "buy_quantity,refund_quantity",
+            }),
+        )?;
+        let all_refunded = order_item
+            .first()
+            .is_some_and(|x| x.refund_quantity >= x.buy_quantity);
+        if all_refunded {
+            upd_order_item_status(tran, &item.order_item_id, OrderItemStatus::Refund)?;
+            upd_order_item_write_off_status(
+                tran,
+                &item.order_item_id,
+                WriteOffStatus::Invalidated,
+            )?;
+        } else {
+            restore_item_status(tran, item)?;
+        }
+    }
+    #[derive(Deserialize)]
+    struct StatusGet {
+        status: u8,
+    }
+    let order_items: Vec<StatusGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order_item", {
+            p0: ["order_sn", "=", &after_sale.order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "status",
+        }),
+    )?;
+    if order_items
+        .iter()
+        .all(|x| x.status == OrderItemStatus::Refund as u8)
+    {
+        upd_order_status(
+            tran,
+            &after_sale.order_sn,
+            OrderPayStatus::Refund,
+            None,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// 微信退款回调：按退款单号完成退款中的售后单。不是售后的退款单号时返回 false
+pub fn finish_after_sale_by_refund_no(
+    tran: &mut Transaction,
+    out_refund_no: &str,
+) -> Result<bool, Error> {
+    #[derive(Deserialize)]
+    struct IdGet {
+        id: u64,
+    }
+    let list: Vec<IdGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_after_sale", {
+            p0: ["out_refund_no", "=", out_refund_no],
+            r: "p0",
+            select: "id",
+        }),
+    )?;
+    let Some(IdGet { id }) = list.into_iter().next() else {
+        return Ok(false);
+    };
+    let Some(after_sale) = get_after_sale_lock(tran, id)? else {
+        return Ok(false);
+    };
+    // 重复回调时已是已退款
+    if after_sale.status == AfterSaleStatus::Refunding as u8 {
+        let items = get_after_sale_items(tran, id)?;
+        finish_after_sale(tran, &after_sale, &items)?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_item_refund_amount() {
+        let cent = Money::from_cent;
+        // 满 100 减 10，30 元的商品退 1 件
+        assert_eq!(
+            item_refund_amount(cent(9000), cent(10000), cent(3000), 0, 1),
+            cent(2700)
+        );
+        // 分次退完时，合计等于分摊的金额
+        let parts: Vec<Money> = (0..3)
+            .map(|i| item_refund_amount(cent(1000), cent(3000), cent(1000), i, 1))
+            .collect();
+        assert_eq!(parts, vec![cent(333), cent(333), cent(334)]);
+        assert_eq!(
+            item_refund_amount(cent(1000), cent(3000), cent(1000), 0, 3),
+            cent(1000)
+        );
+        assert_eq!(
+            item_refund_amount(Money::ZERO, cent(3000), cent(1000), 0, 1),
+            Money::ZERO
+        );
+    }
+
+    #[test]
+    fn test_remaining_freight() {
+        let cent = Money::from_cent;
+        assert_eq!(remaining_freight(Some(cent(1200)), Money::ZERO), cent(1200));
+        assert_eq!(remaining_freight(Some(cent(1200)), cent(1200)), Money::ZERO);
+        // 不需要物流的订单没有运费
+        assert_eq!(remaining_freight(None, Money::ZERO), Money::ZERO);
+        assert_eq!(remaining_freight(Some(cent(500)), cent(800)), Money::ZERO);
+    }
+
+    #[test]
+    fn test_order_item_refund_amount() {
+        let cent = Money::from_cent;
+        // 店铺券只减在这一行上：3 件 30 元，实付 60 元，退 1 件退 20 元
+        assert_eq!(
+            order_item_refund_amount(
+                Some(cent(6000)),
+                cent(9000),
+                cent(10000),
+                cent(3000),
+                3,
+                0,
+                1
+            ),
+            cent(2000)
+        );
+        // 之前的订单，按订单实付分摊
+        assert_eq!(
+            order_item_refund_amount(None, cent(9000), cent(10000), cent(3000), 3, 0, 1),
+            cent(2700)
+        );
+    }
+}
@@ -0,0 +1,516 @@
+use actix_web::{Error, error};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, PooledConn, Transaction, myfind, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::common::types::{
+    AgentLevel, CommissionSplitType, CommissionStatus, NormalStatus, OrderPayStatus, PayType,
+    TranType,
+};
+use crate::common::{Money, config};
+use crate::db::{
+    my_exec_tran_drop, my_exec_tran_vec, my_exec_vec, my_run_tran_drop, my_run_tran_vec,
+};
+use crate::middleware::{Module, check_module};
+use crate::routes::utils_set::commission_rule_set::unit_split_amount;
+use crate::routes::utils_set::pocket_set::pocket_money_add;
+use crate::utils::time::{NowTimeType, get_now_time};
+
+/// 代理的级别，市为空的是省代理
+pub fn agent_level(city: Option<&str>) -> AgentLevel {
+    if city.is_some_and(|c| !c.is_empty()) {
+        AgentLevel::City
+    } else {
+        AgentLevel::Province
+    }
+}
+
+/// 代理的分成，按商品实付的百分比向下取整到分
+pub fn agent_share_amount(base_amount: Money, percent: Money) -> Money {
+    unit_split_amount(CommissionSplitType::Percent as u8, percent, base_amount, 1)
+}
+
+/// 退款时扣回的分成。refunded 为之前已退的商品金额，refund 为本次退的。
+///
+/// 按累计退款占商品实付的比例向下取整到分，减去已扣回的，不超过还未扣回的部分；
+/// 累计退完，或 refund 为 None 整单退款时，扣回剩下的全部
+pub fn agent_back_amount(
+    amount: Money,
+    back_amount: Money,
+    base_amount: Money,
+    refunded: Money,
+    refund: Option<Money>,
+) -> Money {
+    let remain = (amount - back_amount).max(Money::ZERO);
+    let Some(refund) = refund else {
+        return remain;
+    };
+    let total = refunded + refund;
+    if !base_amount.is_positive() || total >= base_amount {
+        return remain;
+    }
+    let cent = amount.cent() as i128 * total.cent().max(0) as i128 / base_amount.cent() as i128;
+    (Money::from_cent(cent as i64) - back_amount)
+        .max(Money::ZERO)
+        .min(remain)
+}
+
+/// 该区域是否已有其他人在申请或代理中
+pub fn agent_area_taken(
+    tran: &mut Transaction,
+    province: &str,
+    city: Option<&str>,
+    exclude_uid: u64,
+) -> Result<bool, Error> {
+    let count: Vec<(u64,)> = my_exec_tran_vec(
+        tran,
+        "select count(*) from agt_agent_area where province = ? and ifnull(city, '') = ?
+            and status in (?, ?) and is_del = 0 and uid != ?",
+        (
+            province,
+            city.unwrap_or_default(),
+            NormalStatus::UnderReview as u8,
+            NormalStatus::Online as u8,
+            exclude_uid,
+        ),
+    )?;
+    Ok(count.first().is_some_and(|x| x.0 > 0))
+}
+
+/// 代理认证审核后，同步代理区域的状态。
+///
+/// 通过时，审核中、已下线的区域上线，并开通代理金额；其余状态时，审核中、已上线的区域改为同样的状态
+pub fn agent_credential_review(tran: &mut Transaction, uid: u64, status: u8) -> Result<(), Error> {
+    if status != NormalStatus::Online as u8 {
+        my_exec_tran_drop(
+            tran,
+            "update agt_agent_area set status = ? where uid = ? and status in (?, ?) and is_del = 0",
+            (
+                status,
+                uid,
+                NormalStatus::UnderReview as u8,
+                NormalStatus::Online as u8,
+            ),
+        )?;
+        return Ok(());
+    }
+
+    #[derive(Deserialize)]
+    struct AreaGet {
+        id: u32,
+        province: String,
+        city: Option<String>,
+    }
+    let areas: Vec<AreaGet> = my_run_tran_vec(
+        tran,
+        myfind!("agt_agent_area", {
+            p0: ["uid", "=", uid],
+            p1: ["status", "in", format!("{},{}", NormalStatus::UnderReview as u8, NormalStatus::OffShelf as u8)],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "id,province,city",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    for a in areas {
+        if agent_area_taken(tran, &a.province, a.city.as_deref(), uid)? {
+            return Err(error::ErrorBadRequest(format!(
+                "{}{} 已有代理",
+                a.province,
+                a.city.unwrap_or_default()
+            )));
+        }
+        my_run_tran_drop(
+            tran,
+            myupdate!("agt_agent_area", a.id, {
+                "status": NormalStatus::Online as u8,
+            }),
+        )?;
+    }
+    my_exec_tran_drop(
+        tran,
+        "insert ignore into agt_amount (uid) values (?)",
+        (uid,),
+    )?;
+    Ok(())
+}
+
+/// 订单支付后，收货地址所在区域的省代理、市代理按配置的百分比分成，先记为冻结中的分成。
+///
+/// 订单的商品都完成并过了冻结期后，由定时任务结算到未提现金额，见 agent_order_settle。
+/// 代理功能未开启、订单没有收货地址时不分成
+pub fn do_order_agent_split(tran: &mut Transaction, order_sn: &str) -> Result<(), Error> {
+    if check_module(Module::Agent).is_err() {
+        return Ok(());
+    }
+    #[derive(Deserialize)]
+    struct OrderGet {
+        pay_amount: Money,
+        delivery_amount: Option<Money>,
+        province: Option<String>,
+        city: Option<String>,
+    }
+    let order: Vec<OrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order", {
+            p0: ["order_sn", "=", order_sn],
+            r: "p0",
+            select: "pay_amount,delivery_amount,province,city",
+        }),
+    )?;
+    let Some(order) = order.into_iter().next() else {
+        return Err(error::ErrorNotFound("订单不存在"));
+    };
+    let Some(province) = order.province.filter(|x| !x.is_empty()) else {
+        return Ok(());
+    };
+    let base_amount = order.pay_amount - order.delivery_amount.unwrap_or_default();
+    if !base_amount.is_positive() {
+        return Ok(());
+    }
+
+    let areas: Vec<(u32, u64, Option<String>)> = my_exec_tran_vec(
+        tran,
+        "select id, uid, city from agt_agent_area where province = ? and (ifnull(city, '') = '' or city = ?)
+            and status = ? and is_del = 0",
+        (
+            &province,
+            order.city.unwrap_or_default(),
+            NormalStatus::Online as u8,
+        ),
+    )?;
+    let cfg = &config().agent;
+    for (area_id, uid, city) in areas {
+        let level = agent_level(city.as_deref());
+        let percent = match level {
+            AgentLevel::Province => cfg.province_percent,
+            AgentLevel::City => cfg.city_percent,
+        };
+        let amount = agent_share_amount(base_amount, percent);
+        if !amount.is_positive() {
+            continue;
+        }
+        my_run_tran_drop(
+            tran,
+            myset!("agt_agent_order", {
+                "uid": uid,
+                "area_id": area_id,
+                "level": level as u8,
+                "order_sn": order_sn,
+                "base_amount": base_amount.to_string(),
+                "percent": percent.to_string(),
+                "amount": amount.to_string(),
+            }),
+        )?;
+        my_exec_tran_drop(
+            tran,
+            "insert into agt_amount (uid, total_amount, freeze_amount) values (?, ?, ?)
+                on duplicate key update total_amount = total_amount + ?, freeze_amount = freeze_amount + ?",
+            (
+                uid,
+                amount.to_string(),
+                amount.to_string(),
+                amount.to_string(),
+                amount.to_string(),
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// 订单退款后扣回代理分成，refund 为商品的退款金额，为 None 时整单退款。
+///
+/// 冻结中的从冻结金额中扣回，全部扣回的取消；已结算的从未提现金额中扣回，
+/// 已提现导致不足的只扣到 0，没扣回的记在 shortfall 里，由后台线下处理
+pub fn agent_split_back(
+    tran: &mut Transaction,
+    order_sn: &str,
+    refund: Option<Money>,
+) -> Result<(), Error> {
+    #[derive(Deserialize)]
+    struct AgentOrderGet {
+        id: u64,
+        uid: u64,
+        base_amount: Money,
+        amount: Money,
+        back_amount: Money,
+        refund_amount: Money,
+        shortfall: Money,
+        status: u8,
+    }
+    let list: Vec<AgentOrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("agt_agent_order", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id,uid,base_amount,amount,back_amount,refund_amount,shortfall,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    for a in list {
+        if a.status == CommissionStatus::Cancelled as u8 {
+            continue;
+        }
+        let back = agent_back_amount(
+            a.amount,
+            a.back_amount,
+            a.base_amount,
+            a.refund_amount,
+            refund,
+        );
+        let refund_amount =
+            refund.map_or(a.base_amount, |r| (a.refund_amount + r).min(a.base_amount));
+        let back_amount = a.back_amount + back;
+        if a.status == CommissionStatus::Pending as u8 {
+            let status = if back_amount >= a.amount {
+                CommissionStatus::Cancelled
+            } else {
+                CommissionStatus::Pending
+            };
+            my_run_tran_drop(
+                tran,
+                myupdate!("agt_agent_order", a.id, {
+                    "back_amount": back_amount.to_string(),
+                    "refund_amount": refund_amount.to_string(),
+                    "status": status as u8,
+                }),
+            )?;
+            if back.is_positive() {
+                my_exec_tran_drop(
+                    tran,
+                    "update agt_amount set total_amount = total_amount - ?, freeze_amount = freeze_amount - ?
+                        where uid = ?",
+                    (back.to_string(), back.to_string(), a.uid),
+                )?;
+            }
+            continue;
+        }
+
+        let have: Vec<(String,)> = my_exec_tran_vec(
+            tran,
+            "select have_amount from agt_amount where uid = ? for update",
+            (a.uid,),
+        )?;
+        let have_amount = have
+            .first()
+            .and_then(|x| x.0.parse::<Money>().ok())
+            .unwrap_or_default();
+        let sub_amount = back.min(have_amount.max(Money::ZERO));
+        my_run_tran_drop(
+            tran,
+            myupdate!("agt_agent_order", a.id, {
+                "back_amount": back_amount.to_string(),
+                "refund_amount": refund_amount.to_string(),
+                "shortfall": (a.shortfall + back - sub_amount).to_string(),
+            }),
+        )?;
+        if back.is_positive() {
+            my_exec_tran_drop(
+                tran,
+                "update agt_amount set total_amount = total_amount - ?, have_amount = have_amount - ?
+                    where uid = ?",
+                (back.to_string(), sub_amount.to_string(), a.uid),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// 冻结期已过、待结算的代理分成 id：订单已支付，没退完的商品都已完成，且完成时间不晚于 deadline
+pub fn agent_order_settle_ids(
+    conn: &mut PooledConn,
+    deadline: &str,
+    limit: u32,
+) -> Result<Vec<u64>, Error> {
+    let list: Vec<(u64,)> = my_exec_vec(
+        conn,
+        &format!("{} order by a.id limit ?", AGENT_ORDER_SETTLE_SQL),
+        (
+            CommissionStatus::Pending as u8,
+            OrderPayStatus::Paid as u8,
+            deadline,
+            limit,
+        ),
+    )?;
+    Ok(list.into_iter().map(|x| x.0).collect())
+}
+
+/// 待结算的代理分成，参数为 分成状态、订单状态、deadline
+const AGENT_ORDER_SETTLE_SQL: &str = "select a.id from agt_agent_order a
+    inner join ord_order o on o.order_sn = a.order_sn
+    where a.status = ? and a.is_del = 0 and o.status = ?
+    and not exists (select 1 from ord_order_item i where i.order_sn = a.order_sn and i.is_del = 0
+        and i.refund_quantity < i.buy_quantity and (i.complete_time is null or i.complete_time > ?))";
+
+/// 结算一条代理分成，未扣回的部分从冻结金额转入未提现金额。加锁后重新检查条件，已不能结算的返回 false
+pub fn agent_order_settle(tran: &mut Transaction, id: u64, deadline: &str) -> Result<bool, Error> {
+    let list: Vec<(u64,)> = my_exec_tran_vec(
+        tran,
+        &format!("{} and a.id = ? for update", AGENT_ORDER_SETTLE_SQL),
+        (
+            CommissionStatus::Pending as u8,
+            OrderPayStatus::Paid as u8,
+            deadline,
+            id,
+        ),
+    )?;
+    if list.is_empty() {
+        return Ok(false);
+    }
+    #[derive(Deserialize)]
+    struct AgentOrderGet {
+        uid: u64,
+        amount: Money,
+        back_amount: Money,
+    }
+    let info: Vec<AgentOrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("agt_agent_order", {
+            p0: ["id", "=", id],
+            r: "p0",
+            select: "uid,amount,back_amount",
+        }),
+    )?;
+    let Some(a) = info.into_iter().next() else {
+        return Ok(false);
+    };
+    let amount = a.amount - a.back_amount;
+    if !amount.is_positive() {
+        my_run_tran_drop(
+            tran,
+            myupdate!("agt_agent_order", id, {
+                "status": CommissionStatus::Cancelled as u8,
+            }),
+        )?;
+        return Ok(false);
+    }
+    my_run_tran_drop(
+        tran,
+        myupdate!("agt_agent_order", id, {
+            "status": CommissionStatus::Settled as u8,
+            "settle_time": get_now_time(NowTimeType::DateTime),
+        }),
+    )?;
+    my_exec_tran_drop(
+        tran,
+        "update agt_amount set freeze_amount = freeze_amount - ?, have_amount = have_amount + ? where uid = ?",
+        (amount.to_string(), amount.to_string(), a.uid),
+    )?;
+    Ok(true)
+}
+
+/// 代理金额信息
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AgentAmount {
+    /// 累计分成金额
+    pub total_amount: Money,
+    /// 累计提现金额
+    pub withdraw_amount: Money,
+    /// 未提现金额
+    pub have_amount: Money,
+}
+
+/// 代理提现：未提现的分成转入零钱，之后按零钱提现申请处理
+pub fn agent_amount_to_pocket(
+    tran: &mut Transaction,
+    uid: u64,
+    amount: Money,
+) -> Result<(), Error> {
+    let info: Vec<AgentAmount> = my_run_tran_vec(
+        tran,
+        myfind!("agt_amount", {
+            p0: ["uid", "=", uid],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "total_amount,withdraw_amount,have_amount",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(info) = info.into_iter().next() else {
+        return Err(error::ErrorNotFound("未开通代理金额"));
+    };
+    if info.have_amount < amount {
+        return Err(error::ErrorBadRequest("可提现的代理分成不足"));
+    }
+    my_run_tran_drop(
+        tran,
+        myupdate!("agt_amount", {"uid": uid}, {
+            "have_amount": (info.have_amount - amount).to_string(),
+            "withdraw_amount": (info.withdraw_amount + amount).to_string(),
+        }),
+    )?;
+    let info = serde_json::json!({ "agent_withdraw": amount });
+    pocket_money_add(
+        tran,
+        uid,
+        amount,
+        TranType::AgentIncome,
+        PayType::PocketPay,
+        Some(&info.to_string()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_agent_amount() {
+        assert_eq!(agent_level(None), AgentLevel::Province);
+        assert_eq!(agent_level(Some("")), AgentLevel::Province);
+        assert_eq!(agent_level(Some("杭州市")), AgentLevel::City);
+
+        // 99.99 的 1.5%，向下取整到分
+        assert_eq!(
+            agent_share_amount(money("99.99"), money("1.5")),
+            money("1.49")
+        );
+        assert_eq!(agent_share_amount(money("99.99"), Money::ZERO), Money::ZERO);
+
+        // 分成 1.49，商品实付 99.99
+        let (amount, base) = (money("1.49"), money("99.99"));
+        assert_eq!(
+            agent_back_amount(amount, Money::ZERO, base, Money::ZERO, Some(money("50"))),
+            money("0.74")
+        );
+        assert_eq!(
+            agent_back_amount(amount, money("0.74"), base, money("50"), None),
+            money("0.75")
+        );
+        // 退款超过实付的，扣回剩下的全部
+        assert_eq!(
+            agent_back_amount(amount, money("0.74"), base, Money::ZERO, Some(base)),
+            money("0.75")
+        );
+        assert_eq!(
+            agent_back_amount(amount, amount, base, Money::ZERO, Some(money("1"))),
+            Money::ZERO
+        );
+        // 分几次退完的，按累计退款计算，最后一次扣回剩下的全部，不留下取整的差额
+        assert_eq!(
+            agent_back_amount(amount, Money::ZERO, base, Money::ZERO, Some(money("33.33"))),
+            money("0.49")
+        );
+        assert_eq!(
+            agent_back_amount(
+                amount,
+                money("0.49"),
+                base,
+                money("33.33"),
+                Some(money("33.33"))
+            ),
+            money("0.50")
+        );
+        assert_eq!(
+            agent_back_amount(
+                amount,
+                money("0.99"),
+                base,
+                money("66.66"),
+                Some(money("33.33"))
+            ),
+            money("0.50")
+        );
+    }
+}
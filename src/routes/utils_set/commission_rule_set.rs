@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+
+use actix_web::Error;
+use chrono::{Datelike, Local};
+use mysql_quick::{Transaction, myfind};
+use serde::{Deserialize, Serialize};
+
+use crate::common::Money;
+use crate::common::types::{CommissionScope, CommissionSplitType, CommissionStatus, NormalStatus};
+use crate::db::{in_placeholders, my_exec_tran_vec, my_run_tran_vec};
+
+/// 按月销售额分档，月销售额达到 min_volume 时使用这一档的分成
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommissionTier {
+    pub min_volume: Money,
+    pub main_sale_value: Money,
+    pub sale_value: Money,
+}
+
+/// 解析规则的分档，格式错误时当作没有分档
+pub fn parse_tiers(tiers: Option<&str>) -> Vec<CommissionTier> {
+    let mut list: Vec<CommissionTier> = tiers
+        .filter(|s| !s.trim().is_empty())
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_default();
+    list.sort_by_key(|t| t.min_volume);
+    list
+}
+
+/// 佣金规则
+#[derive(Debug, Clone)]
+pub struct CommissionRule {
+    pub id: u32,
+    pub scope_type: u8,
+    pub scope_id: u32,
+    pub split_type: u8,
+    pub main_sale_value: Money,
+    pub sale_value: Money,
+    pub tiers: Vec<CommissionTier>,
+}
+impl CommissionRule {
+    /// 按月销售额取分成，(总销售, 销售)。总销售、销售分别按自己的月销售额取档
+    pub fn values(&self, main_sale_volume: Money, sale_volume: Money) -> (Money, Money) {
+        let tier = |volume: Money| self.tiers.iter().rev().find(|t| t.min_volume <= volume);
+        let main_sale_value =
+            tier(main_sale_volume).map_or(self.main_sale_value, |t| t.main_sale_value);
+        let sale_value = tier(sale_volume).map_or(self.sale_value, |t| t.sale_value);
+        (main_sale_value, sale_value)
+    }
+}
+
+/// 匹配规则用到的商品信息
+#[derive(Debug, Clone, Default)]
+pub struct SplitUnitInfo {
+    pub price: Money,
+    pub product_sn: u32,
+    pub store_code: Option<u32>,
+    pub brand_code: Option<u32>,
+    /// 所属分类，按 三级、二级、一级 的顺序
+    pub cat_ids: Vec<u32>,
+    /// 商品上设置的固定分成，没有匹配的规则时使用
+    pub main_sale_split: Option<Money>,
+    pub sale_split: Option<Money>,
+    pub is_split: u8,
+}
+
+/// 商品匹配的规则，按 产品 > 分类 > 品牌 > 店铺 的顺序
+pub fn match_rule<'a>(
+    rules: &'a [CommissionRule],
+    unit: &SplitUnitInfo,
+) -> Option<&'a CommissionRule> {
+    let find = |scope: CommissionScope, id: u32| {
+        rules
+            .iter()
+            .find(|r| r.scope_type == scope as u8 && r.scope_id == id)
+    };
+    find(CommissionScope::Product, unit.product_sn)
+        .or_else(|| {
+            unit.cat_ids
+                .iter()
+                .find_map(|id| find(CommissionScope::Category, *id))
+        })
+        .or_else(|| {
+            unit.brand_code
+                .and_then(|id| find(CommissionScope::Brand, id))
+        })
+        .or_else(|| {
+            unit.store_code
+                .and_then(|id| find(CommissionScope::Store, id))
+        })
+}
+
+/// 每件商品的佣金。百分比的按实付金额计算后平分到每件，向下取整到分
+pub fn unit_split_amount(split_type: u8, value: Money, paid: Money, quantity: u32) -> Money {
+    if !value.is_positive() || quantity == 0 {
+        return Money::ZERO;
+    }
+    if split_type == CommissionSplitType::Fixed as u8 {
+        return value;
+    }
+    if !paid.is_positive() {
+        return Money::ZERO;
+    }
+    // value 为百分比，保留两位小数，即 万分之 value.cent()
+    let cent = paid.cent() as i128 * value.cent() as i128 / 10000 / quantity as i128;
+    Money::from_cent(cent as i64)
+}
+
+/// 一个商品的分成
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct UnitSplit {
+    /// 使用的规则，None 为商品上的固定分成
+    pub rule_id: Option<u32>,
+    /// 总销售每件的佣金
+    pub main_sale_split: Option<Money>,
+    /// 销售每件的佣金
+    pub sale_split: Option<Money>,
+}
+
+/// 计算分成需要的规则及商品信息
+pub struct SplitContext {
+    pub rules: Vec<CommissionRule>,
+    pub units: HashMap<u32, SplitUnitInfo>,
+}
+impl SplitContext {
+    /// 计算商品的分成，paid 为这一行商品分摊优惠后的实付金额
+    pub fn split(
+        &self,
+        unit_sn: u32,
+        paid: Money,
+        quantity: u32,
+        main_sale_volume: Money,
+        sale_volume: Money,
+    ) -> UnitSplit {
+        let Some(unit) = self.units.get(&unit_sn) else {
+            return UnitSplit::default();
+        };
+        if let Some(rule) = match_rule(&self.rules, unit) {
+            let (main_sale_value, sale_value) = rule.values(main_sale_volume, sale_volume);
+            return UnitSplit {
+                rule_id: Some(rule.id),
+                main_sale_split: Some(unit_split_amount(
+                    rule.split_type,
+                    main_sale_value,
+                    paid,
+                    quantity,
+                )),
+                sale_split: Some(unit_split_amount(
+                    rule.split_type,
+                    sale_value,
+                    paid,
+                    quantity,
+                )),
+            };
+        }
+        if unit.is_split == 0 {
+            return UnitSplit::default();
+        }
+        UnitSplit {
+            rule_id: None,
+            main_sale_split: unit.main_sale_split,
+            sale_split: unit.sale_split,
+        }
+    }
+}
+
+/// 查询上线的佣金规则，及商品匹配规则用到的产品、分类信息
+pub fn get_split_context(tran: &mut Transaction, unit_sns: &[u32]) -> Result<SplitContext, Error> {
+    #[derive(Deserialize)]
+    struct RuleGet {
+        id: u32,
+        scope_type: u8,
+        scope_id: u32,
+        split_type: u8,
+        main_sale_value: Money,
+        sale_value: Money,
+        tiers: Option<String>,
+    }
+    let rules: Vec<RuleGet> = my_run_tran_vec(
+        tran,
+        myfind!("sal_commission_rule", {
+            p0: ["status", "=", NormalStatus::Online as u8],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            order_by: "-id",
+            select: "id,scope_type,scope_id,split_type,main_sale_value,sale_value,tiers",
+        }),
+    )?;
+    let rules = rules
+        .into_iter()
+        .map(|r| CommissionRule {
+            tiers: parse_tiers(r.tiers.as_deref()),
+            id: r.id,
+            scope_type: r.scope_type,
+            scope_id: r.scope_id,
+            split_type: r.split_type,
+            main_sale_value: r.main_sale_value,
+            sale_value: r.sale_value,
+        })
+        .collect();
+
+    let mut units: HashMap<u32, SplitUnitInfo> = HashMap::new();
+    if unit_sns.is_empty() {
+        return Ok(SplitContext { rules, units });
+    }
+    #[derive(Deserialize)]
+    struct UnitGet {
+        unit_sn: u32,
+        price: Option<Money>,
+        product_sn: u32,
+        store_code: Option<u32>,
+        brand_code: Option<u32>,
+        main_sale_split: Option<Money>,
+        sale_split: Option<Money>,
+        is_split: u8,
+    }
+    let unit_list: Vec<UnitGet> = my_run_tran_vec(
+        tran,
+        myfind!("sku_unit", {
+            j0: ["product_sn", "inner", "spu_product.product_sn"],
+            p0: ["unit_sn", "in", unit_sns.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",")],
+            r: "p0",
+            select: "unit_sn,price,product_sn,spu_product.store_code,spu_product.brand_code,main_sale_split,sale_split,is_split",
+        }),
+    )?;
+    if unit_list.is_empty() {
+        return Ok(SplitContext { rules, units });
+    }
+    let product_sns: Vec<u32> = unit_list.iter().map(|u| u.product_sn).collect();
+    // (product_sn, 三级, 二级, 一级)
+    type CatGet = (u32, Option<u32>, Option<u32>, Option<u32>);
+    let cats: Vec<CatGet> = my_exec_tran_vec(
+        tran,
+        &format!(
+            "select product_sn, tertiary_id, secondary_id, primary_id from spu_product_cat where is_del = 0 and product_sn in ({})",
+            in_placeholders(product_sns.len())
+        ),
+        product_sns,
+    )?;
+    for u in unit_list {
+        let mut cat_ids: Vec<u32> = vec![];
+        // 同一产品可能属于多个分类，先取所有三级，再二级、一级
+        for level in 0..3 {
+            for c in cats.iter().filter(|c| c.0 == u.product_sn) {
+                let id = [c.1, c.2, c.3][level];
+                if let Some(id) = id.filter(|x| *x > 0)
+                    && !cat_ids.contains(&id)
+                {
+                    cat_ids.push(id);
+                }
+            }
+        }
+        units.insert(
+            u.unit_sn,
+            SplitUnitInfo {
+                price: u.price.unwrap_or_default(),
+                product_sn: u.product_sn,
+                store_code: u.store_code,
+                brand_code: u.brand_code,
+                cat_ids,
+                main_sale_split: u.main_sale_split,
+                sale_split: u.sale_split,
+                is_split: u.is_split,
+            },
+        );
+    }
+    Ok(SplitContext { rules, units })
+}
+
+/// 销售本月的销售额：本月产生的佣金对应的商品实付，扣除已退的部分
+pub fn get_monthly_volume(tran: &mut Transaction, uid: u64) -> Result<Money, Error> {
+    let month_start = Local::now()
+        .with_day(1)
+        .unwrap()
+        .format("%Y-%m-%d 00:00:00")
+        .to_string();
+    let sum: Vec<(Option<String>,)> = my_exec_tran_vec(
+        tran,
+        "select round(sum(base_amount * (quantity - refund_quantity) / quantity), 2) from usr_commission
+            where uid = ? and status != ? and is_del = 0 and quantity > 0 and created_at >= ?",
+        (uid, CommissionStatus::Cancelled as u8, month_start),
+    )?;
+    Ok(sum
+        .into_iter()
+        .next()
+        .and_then(|x| x.0)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn rule(id: u32, scope: CommissionScope, scope_id: u32) -> CommissionRule {
+        CommissionRule {
+            id,
+            scope_type: scope as u8,
+            scope_id,
+            split_type: CommissionSplitType::Percent as u8,
+            main_sale_value: money("2"),
+            sale_value: money("5"),
+            tiers: vec![],
+        }
+    }
+
+    #[test]
+    fn test_match_rule() {
+        let unit = SplitUnitInfo {
+            product_sn: 100,
+            store_code: Some(7),
+            brand_code: Some(8),
+            cat_ids: vec![30, 20, 10],
+            ..Default::default()
+        };
+        let rules = vec![
+            rule(1, CommissionScope::Store, 7),
+            rule(2, CommissionScope::Brand, 8),
+            rule(3, CommissionScope::Category, 10),
+            rule(4, CommissionScope::Category, 20),
+            rule(5, CommissionScope::Product, 100),
+        ];
+        assert_eq!(match_rule(&rules, &unit).unwrap().id, 5);
+        // 三级分类优先于上级分类
+        assert_eq!(match_rule(&rules[..4], &unit).unwrap().id, 4);
+        assert_eq!(match_rule(&rules[..3], &unit).unwrap().id, 3);
+        assert_eq!(match_rule(&rules[..2], &unit).unwrap().id, 2);
+        assert_eq!(match_rule(&rules[..1], &unit).unwrap().id, 1);
+        assert!(match_rule(&[rule(6, CommissionScope::Product, 101)], &unit).is_none());
+    }
+
+    #[test]
+    fn test_rule_values() {
+        let mut r = rule(1, CommissionScope::Product, 100);
+        r.tiers = parse_tiers(Some(
+            r#"[{"min_volume":20000,"main_sale_value":4,"sale_value":8},{"min_volume":5000,"main_sale_value":3,"sale_value":6}]"#,
+        ));
+        assert_eq!(r.tiers[0].min_volume, money("5000"));
+        assert_eq!(
+            r.values(Money::ZERO, money("4999.99")),
+            (money("2"), money("5"))
+        );
+        assert_eq!(
+            r.values(money("5000"), money("20000")),
+            (money("3"), money("8"))
+        );
+        assert!(parse_tiers(Some("not json")).is_empty());
+        assert!(parse_tiers(None).is_empty());
+    }
+
+    #[test]
+    fn test_unit_split_amount() {
+        let percent = CommissionSplitType::Percent as u8;
+        let fixed = CommissionSplitType::Fixed as u8;
+        // 实付 99.99 的 5.5%，3 件，每件向下取整到分
+        assert_eq!(
+            unit_split_amount(percent, money("5.5"), money("99.99"), 3),
+            money("1.83")
+        );
+        assert_eq!(
+            unit_split_amount(fixed, money("2.5"), money("99.99"), 3),
+            money("2.5")
+        );
+        assert_eq!(
+            unit_split_amount(percent, Money::ZERO, money("99.99"), 3),
+            Money::ZERO
+        );
+        assert_eq!(
+            unit_split_amount(percent, money("5"), Money::ZERO, 3),
+            Money::ZERO
+        );
+        assert_eq!(
+            unit_split_amount(percent, money("5"), money("10"), 0),
+            Money::ZERO
+        );
+    }
+
+    #[test]
+    fn test_split_context() {
+        let mut units = HashMap::new();
+        units.insert(
+            1,
+            SplitUnitInfo {
+                product_sn: 100,
+                main_sale_split: Some(money("1")),
+                sale_split: Some(money("2")),
+                is_split: 1,
+                ..Default::default()
+            },
+        );
+        units.insert(
+            2,
+            SplitUnitInfo {
+                product_sn: 200,
+                ..Default::default()
+            },
+        );
+        let ctx = SplitContext {
+            rules: vec![rule(9, CommissionScope::Product, 200)],
+            units,
+        };
+        // 没有规则时用商品上的固定分成
+        let s = ctx.split(1, money("50"), 2, Money::ZERO, Money::ZERO);
+        assert_eq!(s.rule_id, None);
+        assert_eq!(s.sale_split, Some(money("2")));
+        // 有规则时按实付比例
+        let s = ctx.split(2, money("50"), 2, Money::ZERO, Money::ZERO);
+        assert_eq!(s.rule_id, Some(9));
+        assert_eq!(s.main_sale_split, Some(money("0.5")));
+        assert_eq!(s.sale_split, Some(money("1.25")));
+        assert_eq!(
+            ctx.split(3, money("50"), 2, Money::ZERO, Money::ZERO),
+            UnitSplit::default()
+        );
+    }
+}
@@ -0,0 +1,647 @@
+use std::fmt;
+
+use actix_web::{Error, error};
+use chrono::{Duration, Local, NaiveDateTime};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, PooledConn, Transaction, TxOpts, myfind, myget, myupdate};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::common::types::{
+    CampaignKind, CampaignStatus, CampaignTarget, NormalStatus, OrderPayStatus,
+};
+use crate::db::{
+    my_exec_drop, my_exec_first, my_exec_tran_drop, my_exec_tran_vec, my_exec_vec, my_run_drop,
+    my_run_tran_drop, my_run_tran_vec, my_run_vec,
+};
+use crate::routes::utils_set::coupon_set::coupon_valid_window;
+use crate::utils::time::{NowTimeType, get_now_time};
+use crate::utils::utils::log_err;
+
+/// 批量发放时，一批处理的用户数，每批一个事务
+const CAMPAIGN_BATCH: u32 = 500;
+/// 定时任务每次最多处理的批数，剩下的下次接着处理
+const CAMPAIGN_MAX_BATCHES: u32 = 20;
+/// 一批生成的兑换码数
+const CODE_BATCH: u32 = 1000;
+/// 兑换码的字符，去掉了容易看错的 0 O 1 I L
+const CODE_CHARSET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+/// 兑换码的长度
+pub const CODE_LEN: usize = 12;
+
+/// 优惠券不能发放给用户的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrantReject {
+    /// 不存在或已删除
+    NotFound,
+    Expired,
+    Offline,
+    /// 数量已发完
+    SoldOut,
+    /// 用户已领取的张数达到每人限领张数
+    Limit,
+}
+impl fmt::Display for GrantReject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "优惠券不存在"),
+            Self::Expired => write!(f, "优惠券已过期"),
+            Self::Offline => write!(f, "优惠券已下架"),
+            Self::SoldOut => write!(f, "优惠券已被领完了"),
+            Self::Limit => write!(f, "你已领取过了"),
+        }
+    }
+}
+
+/// 发放时检查的优惠券信息
+#[derive(Deserialize, Debug, Clone)]
+struct CouponStock {
+    coupon_num: u32,
+    per_user_limit: u32,
+    expire_time: Option<String>,
+    valid_type: u8,
+    start_time: Option<String>,
+    valid_days: Option<u32>,
+    status: i8,
+    is_del: u8,
+}
+
+/// 计算能发给用户的张数，owned 为用户已有的张数（含已使用、已过期的），
+/// 最多发 quantity 张，不超过剩余数量和每人限领张数
+fn grant_count(
+    coupon: &CouponStock,
+    owned: u32,
+    quantity: u32,
+    now: &str,
+) -> Result<u32, GrantReject> {
+    if coupon.is_del == 1 {
+        return Err(GrantReject::NotFound);
+    }
+    if coupon.expire_time.as_deref().is_some_and(|t| t <= now) {
+        return Err(GrantReject::Expired);
+    }
+    if coupon.status != NormalStatus::Online as i8 {
+        return Err(GrantReject::Offline);
+    }
+    if coupon.coupon_num == 0 {
+        return Err(GrantReject::SoldOut);
+    }
+    let left = coupon.per_user_limit.saturating_sub(owned);
+    if left == 0 {
+        return Err(GrantReject::Limit);
+    }
+    Ok(quantity.min(left).min(coupon.coupon_num))
+}
+
+/// 给用户发放优惠券，扣减优惠券的剩余数量，返回发放的张数。
+///
+/// 会锁住优惠券，同一优惠券的发放在事务中排队，每人限领张数不会被并发绕过
+pub fn grant_coupon(
+    tran: &mut Transaction,
+    uid: u64,
+    coupon_id: u32,
+    quantity: u32,
+    campaign_id: Option<u32>,
+) -> Result<Result<u32, GrantReject>, Error> {
+    let coupon: Vec<CouponStock> = my_run_tran_vec(
+        tran,
+        myget!(
+            "pmt_coupon",
+            coupon_id,
+            "coupon_num,per_user_limit,expire_time,valid_type,start_time,valid_days,status,is_del"
+        ) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(coupon) = coupon.first() else {
+        return Ok(Err(GrantReject::NotFound));
+    };
+    let owned: Vec<u32> = my_exec_tran_vec(
+        tran,
+        "select count(*) from usr_coupon where uid = ? and coupon_id = ? and is_del = 0",
+        (uid, coupon_id),
+    )?;
+    let claim_time = Local::now().naive_local();
+    let now = claim_time.format("%Y-%m-%d %H:%M:%S").to_string();
+    let n = match grant_count(coupon, owned.first().copied().unwrap_or(0), quantity, &now) {
+        Ok(n) => n,
+        Err(r) => return Ok(Err(r)),
+    };
+    my_run_tran_drop(
+        tran,
+        myupdate!("pmt_coupon", coupon_id, { "coupon_num": ["incr", -(n as i64)] }),
+    )?;
+    // 每张券的可用时间
+    let (start_time, end_time) = coupon_valid_window(
+        coupon.valid_type,
+        coupon.start_time.as_deref(),
+        coupon.expire_time.as_deref(),
+        coupon.valid_days,
+        &claim_time,
+    );
+    let values = vec!["(?,?,?,?,?)"; n as usize].join(",");
+    let params: Vec<mysql::Value> = (0..n)
+        .flat_map(|_| {
+            [
+                uid.into(),
+                coupon_id.into(),
+                campaign_id.into(),
+                start_time.clone().into(),
+                end_time.clone().into(),
+            ]
+        })
+        .collect();
+    my_exec_tran_drop(
+        tran,
+        &format!(
+            "insert into usr_coupon (uid,coupon_id,campaign_id,start_time,end_time) values {}",
+            values
+        ),
+        params,
+    )?;
+    Ok(Ok(n))
+}
+
+/// 生成一个兑换码
+pub fn gen_redeem_code() -> String {
+    let mut rng = rand::rng();
+    (0..CODE_LEN)
+        .map(|_| CODE_CHARSET[rng.random_range(0..CODE_CHARSET.len())] as char)
+        .collect()
+}
+
+/// 用户输入的兑换码，去掉空格、横线，转为大写
+pub fn normalize_redeem_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(|c| c.to_uppercase())
+        .collect()
+}
+
+/// 发放活动执行时用到的信息
+#[derive(Deserialize, Debug)]
+struct CampaignGet {
+    id: u32,
+    coupon_id: u32,
+    kind: u8,
+    target: Option<u8>,
+    target_value: Option<String>,
+    quantity: u32,
+    code_count: u32,
+    status: u8,
+    run_at: Option<String>,
+    last_uid: u64,
+    done_count: u32,
+}
+
+fn get_campaign(conn: &mut PooledConn, id: u32) -> Result<Option<CampaignGet>, Error> {
+    let list: Vec<CampaignGet> = my_run_vec(
+        conn,
+        myget!(
+            "pmt_coupon_campaign",
+            id,
+            "id,coupon_id,kind,target,target_value,quantity,code_count,status,run_at,last_uid,done_count"
+        ),
+    )?;
+    Ok(list.into_iter().next())
+}
+
+/// 检查批量发放的用户范围，返回 target_value 处理后的值
+pub fn check_campaign_target(
+    target: CampaignTarget,
+    value: Option<&str>,
+) -> Result<String, &'static str> {
+    let value = value.map(|v| v.trim()).unwrap_or("");
+    match target {
+        CampaignTarget::All => Ok(String::new()),
+        CampaignTarget::Role => value
+            .parse::<u16>()
+            .map(|v| v.to_string())
+            .map_err(|_| "请选择角色"),
+        CampaignTarget::ProductBuyer => value
+            .parse::<u32>()
+            .map(|v| v.to_string())
+            .map_err(|_| "请选择产品"),
+        CampaignTarget::RecentRegister => match value.parse::<u32>() {
+            Ok(d) if d > 0 => Ok(d.to_string()),
+            _ => Err("注册天数必须大于0"),
+        },
+    }
+}
+
+/// 批量发放的一批用户，用户 id 大于 after_uid，按 id 从小到大
+fn target_uids(conn: &mut PooledConn, c: &CampaignGet, after_uid: u64) -> Result<Vec<u64>, Error> {
+    let target =
+        CampaignTarget::try_from(c.target.unwrap_or(0)).map_err(error::ErrorInternalServerError)?;
+    let value = check_campaign_target(target, c.target_value.as_deref())
+        .map_err(error::ErrorInternalServerError)?;
+    match target {
+        CampaignTarget::All => my_exec_vec(
+            conn,
+            "select id from usr_silent where id > ? order by id limit ?",
+            (after_uid, CAMPAIGN_BATCH),
+        ),
+        CampaignTarget::Role => my_exec_vec(
+            conn,
+            "select id from usr_silent where find_in_set(?, role) and id > ? order by id limit ?",
+            (value, after_uid, CAMPAIGN_BATCH),
+        ),
+        CampaignTarget::ProductBuyer => my_exec_vec(
+            conn,
+            "select distinct ord_order.uid from ord_order
+                inner join ord_order_item on ord_order_item.order_sn = ord_order.order_sn
+                inner join sku_unit on sku_unit.unit_sn = ord_order_item.unit_sn
+                where sku_unit.product_sn = ? and ord_order.status = ? and ord_order.is_del = 0
+                and ord_order.uid > ? order by ord_order.uid limit ?",
+            (value, OrderPayStatus::Paid as u8, after_uid, CAMPAIGN_BATCH),
+        ),
+        CampaignTarget::RecentRegister => {
+            let days: i64 = value.parse().unwrap_or(0);
+            let run_at = c
+                .run_at
+                .as_deref()
+                .and_then(|t| NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S").ok())
+                .unwrap_or_else(|| chrono::Local::now().naive_local());
+            let since = (run_at - Duration::days(days))
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+            my_exec_vec(
+                conn,
+                "select id from usr_silent where created_at >= ? and id > ? order by id limit ?",
+                (since, after_uid, CAMPAIGN_BATCH),
+            )
+        }
+    }
+}
+
+/// 排队中、执行中的批量发放、兑换码活动
+pub fn pending_campaign_ids(conn: &mut PooledConn) -> Result<Vec<u32>, Error> {
+    my_exec_vec(
+        conn,
+        "select id from pmt_coupon_campaign
+            where kind in (?,?) and status in (?,?) and is_del = 0 order by id limit 10",
+        (
+            CampaignKind::Grant as u8,
+            CampaignKind::RedeemCode as u8,
+            CampaignStatus::Queued as u8,
+            CampaignStatus::Running as u8,
+        ),
+    )
+}
+
+/// 结束活动，记录结果
+fn finish_campaign(
+    conn: &mut PooledConn,
+    id: u32,
+    status: CampaignStatus,
+    message: &str,
+) -> Result<(), Error> {
+    my_run_drop(
+        conn,
+        myupdate!("pmt_coupon_campaign", id, {
+            "status": status as u8,
+            "message": message,
+        }),
+    )?;
+    Ok(())
+}
+
+/// 执行一次发放活动，每次最多处理 `CAMPAIGN_MAX_BATCHES` 批，没处理完的保持执行中，下次从
+/// `last_uid` 接着处理。优惠券已领完、已下架等时活动失败，已发放的不收回
+pub fn run_coupon_campaign(conn: &mut PooledConn, id: u32) -> Result<String, Error> {
+    let Some(mut c) = get_campaign(conn, id)? else {
+        return Ok(format!("活动{}不存在", id));
+    };
+    if c.status == CampaignStatus::Queued as u8 {
+        let now = get_now_time(NowTimeType::DateTime);
+        // 重新执行失败、停止的活动时，run_at 已有值，最近 N 天注册仍按第一次执行的时间算
+        my_exec_drop(
+            conn,
+            "update pmt_coupon_campaign set status = ?, run_at = ifnull(run_at, ?) where id = ?",
+            (CampaignStatus::Running as u8, &now, id),
+        )?;
+        c.status = CampaignStatus::Running as u8;
+        c.run_at = c.run_at.or(Some(now));
+    }
+    if c.status != CampaignStatus::Running as u8 {
+        return Ok(format!("活动{}未在执行", id));
+    }
+    match CampaignKind::try_from(c.kind) {
+        Ok(CampaignKind::Grant) => run_grant_campaign(conn, c),
+        Ok(CampaignKind::RedeemCode) => run_code_campaign(conn, c),
+        _ => Ok(format!("活动{}不需要执行", id)),
+    }
+}
+
+fn run_grant_campaign(conn: &mut PooledConn, c: CampaignGet) -> Result<String, Error> {
+    let mut last_uid = c.last_uid;
+    let mut granted = 0;
+    for _ in 0..CAMPAIGN_MAX_BATCHES {
+        // 管理后台可能已停止了活动
+        let status: Option<u8> = my_exec_first(
+            conn,
+            "select status from pmt_coupon_campaign where id = ?",
+            (c.id,),
+        )?;
+        if status != Some(CampaignStatus::Running as u8) {
+            return Ok(format!("活动{}已停止，本次发放{}张", c.id, granted));
+        }
+        let uids = target_uids(conn, &c, last_uid)?;
+        let Some(batch_last) = uids.last().copied() else {
+            let msg = format!("发放完成，本次发放{}张", granted);
+            finish_campaign(conn, c.id, CampaignStatus::Done, &msg)?;
+            return Ok(format!("活动{}{}", c.id, msg));
+        };
+        let mut tran = conn
+            .start_transaction(TxOpts::default())
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+        let mut n = 0;
+        let mut reject = None;
+        let mut done_uid = batch_last;
+        for uid in &uids {
+            match grant_coupon(&mut tran, *uid, c.coupon_id, c.quantity, Some(c.id)) {
+                Ok(Ok(k)) => n += k,
+                // 已领够的用户跳过
+                Ok(Err(GrantReject::Limit)) => (),
+                Ok(Err(r)) => {
+                    reject = Some(r);
+                    done_uid = *uid - 1;
+                    break;
+                }
+                Err(e) => {
+                    tran.rollback().unwrap();
+                    return Err(e);
+                }
+            }
+        }
+        if let Err(e) = my_exec_tran_drop(
+            &mut tran,
+            "update pmt_coupon_campaign set last_uid = ?, done_count = done_count + ? where id = ?",
+            (done_uid, n, c.id),
+        ) {
+            tran.rollback().unwrap();
+            return Err(e);
+        }
+        tran.commit()
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "事务提交失败")))?;
+        granted += n;
+        last_uid = done_uid;
+        if let Some(r) = reject {
+            let msg = format!("{}，本次发放{}张", r, granted);
+            finish_campaign(conn, c.id, CampaignStatus::Fail, &msg)?;
+            return Ok(format!("活动{}{}", c.id, msg));
+        }
+    }
+    Ok(format!("活动{}本次发放{}张，下次继续", c.id, granted))
+}
+
+fn run_code_campaign(conn: &mut PooledConn, c: CampaignGet) -> Result<String, Error> {
+    let mut made = c.done_count;
+    for _ in 0..CAMPAIGN_MAX_BATCHES {
+        if made >= c.code_count {
+            break;
+        }
+        let n = (c.code_count - made).min(CODE_BATCH);
+        let codes: Vec<String> = (0..n).map(|_| gen_redeem_code()).collect();
+        let params: Vec<mysql::Value> = codes
+            .iter()
+            .flat_map(|code| [c.id.into(), c.coupon_id.into(), code.as_str().into()])
+            .collect();
+        let mut tran = conn
+            .start_transaction(TxOpts::default())
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+        // 与已有的码重复时忽略，少生成的下一批补上
+        let res = my_exec_tran_drop(
+            &mut tran,
+            &format!(
+                "insert ignore into pmt_coupon_code (campaign_id,coupon_id,code) values {}",
+                vec!["(?,?,?)"; codes.len()].join(",")
+            ),
+            params,
+        )
+        .and_then(|added| {
+            my_run_tran_drop(
+                &mut tran,
+                myupdate!("pmt_coupon_campaign", c.id, { "done_count": ["incr", added] }),
+            )
+            .map(|_| added)
+        });
+        match res {
+            Ok(added) => {
+                tran.commit()
+                    .map_err(|e| error::ErrorInternalServerError(log_err(&e, "事务提交失败")))?;
+                made += added as u32;
+            }
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        }
+    }
+    if made >= c.code_count {
+        let msg = format!("已生成兑换码{}个", made);
+        finish_campaign(conn, c.id, CampaignStatus::Done, &msg)?;
+        return Ok(format!("活动{}{}", c.id, msg));
+    }
+    Ok(format!("活动{}已生成兑换码{}个，下次继续", c.id, made))
+}
+
+/// 活动是否在可兑换、可发放的时间内，时间为空时不限
+fn in_campaign_time(start_time: Option<&str>, end_time: Option<&str>, now: &str) -> bool {
+    start_time.is_none_or(|t| t <= now) && end_time.is_none_or(|t| now < t)
+}
+
+/// 新用户首次登录时，发放进行中的新用户活动的优惠券，返回发放的张数
+pub fn grant_new_user_coupons(conn: &mut PooledConn, uid: u64) -> Result<u32, Error> {
+    let now = get_now_time(NowTimeType::DateTime);
+    #[derive(Deserialize)]
+    struct CampaignGet {
+        id: u32,
+        coupon_id: u32,
+        quantity: u32,
+        start_time: Option<String>,
+        end_time: Option<String>,
+    }
+    let list: Vec<CampaignGet> = my_run_vec(
+        conn,
+        myfind!("pmt_coupon_campaign", {
+            p0: ["kind", "=", CampaignKind::NewUser as u8],
+            p1: ["status", "=", CampaignStatus::Running as u8],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "id,coupon_id,quantity,start_time,end_time",
+        }),
+    )?;
+    let mut granted = 0;
+    for c in list {
+        if !in_campaign_time(c.start_time.as_deref(), c.end_time.as_deref(), &now) {
+            continue;
+        }
+        let (id, coupon_id, quantity) = (c.id, c.coupon_id, c.quantity);
+        let mut tran = conn
+            .start_transaction(TxOpts::default())
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+        // 优惠券已领完、已下架等不能发放的，跳过
+        let res = grant_coupon(&mut tran, uid, coupon_id, quantity, Some(id)).and_then(|r| {
+            let n = r.unwrap_or(0);
+            my_exec_tran_drop(
+                &mut tran,
+                "update pmt_coupon_campaign set done_count = done_count + ? where id = ?",
+                (n, id),
+            )
+            .map(|_| n)
+        });
+        match res {
+            Ok(n) => {
+                tran.commit()
+                    .map_err(|e| error::ErrorInternalServerError(log_err(&e, "事务提交失败")))?;
+                granted += n;
+            }
+            Err(e) => {
+                tran.rollback().unwrap();
+                return Err(e);
+            }
+        }
+    }
+    Ok(granted)
+}
+
+/// 用户兑换兑换码，成功时返回兑换到的优惠券 id
+pub fn redeem_coupon_code(
+    tran: &mut Transaction,
+    uid: u64,
+    code: &str,
+) -> Result<Result<u32, String>, Error> {
+    #[derive(Deserialize)]
+    struct CodeGet {
+        id: u64,
+        campaign_id: u32,
+        coupon_id: u32,
+        uid: Option<u64>,
+        status: u8,
+        start_time: Option<String>,
+        end_time: Option<String>,
+    }
+    let list: Vec<CodeGet> = my_run_tran_vec(
+        tran,
+        myfind!("pmt_coupon_code", {
+            j0: ["campaign_id", "inner", "pmt_coupon_campaign.id"],
+            p0: ["code", "=", code],
+            p1: ["pmt_coupon_campaign.is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id,campaign_id,coupon_id,uid,pmt_coupon_campaign.status,pmt_coupon_campaign.start_time,pmt_coupon_campaign.end_time",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(c) = list.first() else {
+        return Ok(Err("兑换码不存在".to_string()));
+    };
+    if c.uid.is_some() {
+        return Ok(Err("兑换码已被使用".to_string()));
+    }
+    if c.status == CampaignStatus::Stopped as u8 {
+        return Ok(Err("兑换码已停用".to_string()));
+    }
+    let now = get_now_time(NowTimeType::DateTime);
+    if !in_campaign_time(c.start_time.as_deref(), c.end_time.as_deref(), &now) {
+        return Ok(Err("兑换码不在兑换时间内".to_string()));
+    }
+    if let Err(r) = grant_coupon(tran, uid, c.coupon_id, 1, Some(c.campaign_id))? {
+        return Ok(Err(r.to_string()));
+    }
+    my_exec_tran_drop(
+        tran,
+        "update pmt_coupon_code set uid = ?, redeemed_at = ? where id = ?",
+        (uid, &now, c.id),
+    )?;
+    Ok(Ok(c.coupon_id))
+}
+
+/// 兑换码，(兑换码, 兑换的用户, 兑换时间)
+pub type CampaignCode = (String, Option<u64>, Option<String>);
+
+/// 活动的所有兑换码
+pub fn campaign_codes(conn: &mut PooledConn, campaign_id: u32) -> Result<Vec<CampaignCode>, Error> {
+    my_exec_vec(
+        conn,
+        "select code,uid,redeemed_at from pmt_coupon_code where campaign_id = ? order by id",
+        (campaign_id,),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::common::types::CouponValidType;
+
+    #[test]
+    fn test_grant_count() {
+        let now = "2025-06-01 10:00:00";
+        let mut c = CouponStock {
+            coupon_num: 10,
+            per_user_limit: 3,
+            expire_time: Some("2025-07-01 00:00:00".to_string()),
+            valid_type: CouponValidType::Fixed as u8,
+            start_time: None,
+            valid_days: None,
+            status: NormalStatus::Online as i8,
+            is_del: 0,
+        };
+        assert_eq!(grant_count(&c, 0, 1, now), Ok(1));
+        assert_eq!(grant_count(&c, 1, 5, now), Ok(2));
+        assert_eq!(grant_count(&c, 3, 1, now), Err(GrantReject::Limit));
+        c.coupon_num = 1;
+        assert_eq!(grant_count(&c, 0, 3, now), Ok(1));
+        c.coupon_num = 0;
+        assert_eq!(grant_count(&c, 0, 1, now), Err(GrantReject::SoldOut));
+        c.coupon_num = 10;
+        c.status = NormalStatus::OffShelf as i8;
+        assert_eq!(grant_count(&c, 0, 1, now), Err(GrantReject::Offline));
+        c.expire_time = Some("2025-06-01 10:00:00".to_string());
+        assert_eq!(grant_count(&c, 0, 1, now), Err(GrantReject::Expired));
+        c.is_del = 1;
+        assert_eq!(grant_count(&c, 0, 1, now), Err(GrantReject::NotFound));
+        assert_eq!(GrantReject::Limit.to_string(), "你已领取过了");
+    }
+
+    #[test]
+    fn test_redeem_code() {
+        let code = gen_redeem_code();
+        assert_eq!(code.len(), CODE_LEN);
+        assert!(code.bytes().all(|b| CODE_CHARSET.contains(&b)));
+        assert_ne!(code, gen_redeem_code());
+        assert_eq!(normalize_redeem_code(" abcd-efgh 2345 "), "ABCDEFGH2345");
+    }
+
+    #[test]
+    fn test_campaign_target() {
+        assert_eq!(
+            check_campaign_target(CampaignTarget::All, None),
+            Ok(String::new())
+        );
+        assert_eq!(
+            check_campaign_target(CampaignTarget::Role, Some(" 3 ")),
+            Ok("3".to_string())
+        );
+        assert_eq!(
+            check_campaign_target(CampaignTarget::Role, None),
+            Err("请选择角色")
+        );
+        assert_eq!(
+            check_campaign_target(CampaignTarget::ProductBuyer, Some("x")),
+            Err("请选择产品")
+        );
+        assert_eq!(
+            check_campaign_target(CampaignTarget::RecentRegister, Some("0")),
+            Err("注册天数必须大于0")
+        );
+        assert!(in_campaign_time(None, None, "2025-06-01 10:00:00"));
+        assert!(in_campaign_time(
+            Some("2025-06-01 00:00:00"),
+            Some("2025-06-02 00:00:00"),
+            "2025-06-01 10:00:00"
+        ));
+        assert!(!in_campaign_time(
+            None,
+            Some("2025-06-01 10:00:00"),
+            "2025-06-01 10:00:00"
+        ));
+    }
+}
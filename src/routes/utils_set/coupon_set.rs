@@ -0,0 +1,849 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use actix_web::Error;
+use chrono::{Duration, NaiveDateTime};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, PooledConn, Transaction, myfind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::types::{CouponValidType, NormalStatus, UserCouponStatus};
+use crate::common::{Money, config};
+use crate::control::sms::sms_send;
+use crate::control::wx_message::{WxSubscribeMessage, send_wx_subscribe_message, wx_thing_value};
+use crate::db::{in_placeholders, my_exec_drop, my_exec_tran_vec, my_run_tran_vec, my_run_vec};
+use crate::routes::utils_set::mall_set::UserBuy;
+
+/// 下单时使用哪些优惠券
+#[derive(Debug, Clone, PartialEq)]
+pub enum CouponSelect {
+    /// 不查询、不使用优惠券
+    Skip,
+    /// 不使用优惠券，但返回用户的优惠券及是否可用
+    None,
+    /// 使用指定的优惠券，为 pmt_coupon.id，每个店铺一张店铺券，一张平台券
+    Ids(Vec<u32>),
+    /// 自动选择优惠最多的优惠券
+    Best,
+}
+
+/// 优惠券不能使用的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum CouponReject {
+    /// 已下架或已删除
+    Offline,
+    Expired,
+    /// 还没到可用的时间
+    NotStarted {
+        start_time: String,
+    },
+    /// 拼团的商品
+    GroupBuy,
+    /// 没有指定的商品
+    Unit,
+    /// 没有指定产品的商品
+    Product,
+    /// 没有指定分类的商品
+    Category,
+    /// 没有指定店铺的商品
+    Store,
+    /// 没有指定品牌的商品
+    Brand,
+    /// 可用的商品金额没有达到满减金额
+    Threshold {
+        full_amount: Money,
+        amount: Money,
+    },
+    /// 可用的商品金额不大于优惠券的减免金额
+    TooLarge {
+        reduce_amount: Money,
+    },
+    /// 既没有设置减免金额，也没有设置折扣
+    NoDiscount,
+}
+impl fmt::Display for CouponReject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Offline => write!(f, "优惠券已下架"),
+            Self::Expired => write!(f, "优惠券已过期"),
+            Self::NotStarted { start_time } => write!(f, "{}起可用", start_time),
+            Self::GroupBuy => write!(f, "拼团订单不能使用优惠券"),
+            Self::Unit => write!(f, "不是优惠券指定的商品"),
+            Self::Product => write!(f, "不是优惠券指定的产品"),
+            Self::Category => write!(f, "不是优惠券指定分类的商品"),
+            Self::Store => write!(f, "不是优惠券指定店铺的商品"),
+            Self::Brand => write!(f, "不是优惠券指定品牌的商品"),
+            Self::Threshold {
+                full_amount,
+                amount,
+            } => write!(
+                f,
+                "满{}元可用，还差{}元",
+                full_amount,
+                *full_amount - *amount
+            ),
+            Self::TooLarge { reduce_amount } => {
+                write!(f, "可用的商品金额需大于{}元", reduce_amount)
+            }
+            Self::NoDiscount => write!(f, "优惠券未设置优惠"),
+        }
+    }
+}
+
+/// 判断优惠券是否可用时，购买的一种商品
+#[derive(Debug, Clone, Default)]
+pub struct CouponItem {
+    pub unit_sn: u32,
+    pub product_sn: u32,
+    pub store_code: Option<u32>,
+    pub brand_code: Option<u32>,
+    /// 这种商品的合计金额
+    pub amount: Money,
+    /// 所属分类，每项为 [一级, 二级, 三级]
+    pub cats: Vec<[u32; 3]>,
+    pub is_group_buy: bool,
+}
+
+/// 用户的优惠券及使用条件
+#[derive(Debug, Clone, Default)]
+pub struct CouponRule {
+    pub usr_coupon_id: u64,
+    pub coupon_id: u32,
+    pub coupon_name: String,
+    pub condition_title: String,
+    pub reduce_amount: Option<Money>,
+    /// 折扣，0.85 为按 85% 支付
+    pub discount: Option<Money>,
+    /// 可用的开始时间，为空时不限
+    pub start_time: Option<String>,
+    /// 过期时间，为空时不过期
+    pub expire_time: Option<String>,
+    /// 优惠券上线且未删除
+    pub is_online: bool,
+    /// 满减金额，没有时为 0
+    pub full_amount: Money,
+    pub store_code: Option<u32>,
+    pub brand_code: Option<u32>,
+    /// 指定的分类，按 一级、二级、三级 的顺序，可以只指定到一级或二级
+    pub product_cat: Vec<u32>,
+    pub product_sn: Option<u32>,
+    pub unit_sn: Option<u32>,
+}
+impl CouponRule {
+    /// 店铺券的店铺，平台券为 None
+    pub fn store(&self) -> Option<u32> {
+        self.store_code.filter(|x| *x > 0)
+    }
+
+    /// 一种商品没有通过的第一个条件，按 指定商品/产品/分类、店铺、品牌 的顺序。
+    ///
+    /// 指定了商品或产品的，只再看店铺，不看品牌
+    fn item_reject(&self, item: &CouponItem) -> Option<CouponReject> {
+        let by_unit = self.unit_sn.is_some() || self.product_sn.is_some();
+        if let Some(unit_sn) = self.unit_sn {
+            if item.unit_sn != unit_sn {
+                return Some(CouponReject::Unit);
+            }
+        } else if let Some(product_sn) = self.product_sn {
+            if item.product_sn != product_sn {
+                return Some(CouponReject::Product);
+            }
+        } else if !self.product_cat.is_empty() {
+            let len = self.product_cat.len();
+            let is_cat = len <= 3 && item.cats.iter().any(|c| c[..len] == self.product_cat[..]);
+            if !is_cat {
+                return Some(CouponReject::Category);
+            }
+        }
+        if let Some(store_code) = self.store_code.filter(|x| *x > 0)
+            && item.store_code != Some(store_code)
+        {
+            return Some(CouponReject::Store);
+        }
+        if !by_unit
+            && let Some(brand_code) = self.brand_code.filter(|x| *x > 0)
+            && item.brand_code != Some(brand_code)
+        {
+            return Some(CouponReject::Brand);
+        }
+        None
+    }
+
+    /// 可用的商品在 items 中的下标。没有可用的商品时，返回最接近可用的那种商品没有通过的条件
+    pub fn eligible_items(&self, items: &[CouponItem]) -> Result<Vec<usize>, CouponReject> {
+        let mut ids = vec![];
+        let mut reject: Option<CouponReject> = None;
+        let stage = |r: &CouponReject| match r {
+            CouponReject::Store => 1,
+            CouponReject::Brand => 2,
+            _ => 0,
+        };
+        for (i, item) in items.iter().enumerate() {
+            match self.item_reject(item) {
+                None => ids.push(i),
+                Some(r) => {
+                    if reject.as_ref().is_none_or(|x| stage(&r) > stage(x)) {
+                        reject = Some(r);
+                    }
+                }
+            }
+        }
+        match reject {
+            Some(r) if ids.is_empty() => Err(r),
+            _ => Ok(ids),
+        }
+    }
+
+    /// 优惠券是否已下架、已过期、还没到可用时间
+    pub fn check(&self, now: &str) -> Result<(), CouponReject> {
+        if !self.is_online {
+            return Err(CouponReject::Offline);
+        }
+        if let Some(time) = &self.expire_time
+            && time.as_str() <= now
+        {
+            return Err(CouponReject::Expired);
+        }
+        if let Some(time) = &self.start_time
+            && time.as_str() > now
+        {
+            return Err(CouponReject::NotStarted {
+                start_time: time.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 按购买的商品计算优惠券可优惠的金额，及可用的商品的下标（用于分摊优惠），
+    /// now 为当前时间 `%Y-%m-%d %H:%M:%S`。
+    ///
+    /// 同时设置了折扣和减免金额的，按折扣计算
+    pub fn apply(
+        &self,
+        items: &[CouponItem],
+        now: &str,
+    ) -> Result<(Money, Vec<usize>), CouponReject> {
+        self.check(now)?;
+        if items.iter().any(|x| x.is_group_buy) {
+            return Err(CouponReject::GroupBuy);
+        }
+        let ids = self.eligible_items(items)?;
+        let amount: Money = ids.iter().map(|i| items[*i].amount).sum();
+        if amount < self.full_amount || !amount.is_positive() {
+            return Err(CouponReject::Threshold {
+                full_amount: self.full_amount,
+                amount,
+            });
+        }
+        // 折扣 0.85 即 85%，按百分比计算
+        if let Some(d) = self.discount
+            && d.cent() > 0
+            && d.cent() < 100
+        {
+            return Ok((amount - amount.mul_percent(d.cent()), ids));
+        }
+        match self.reduce_amount {
+            Some(r) if r.is_positive() && (amount - r).is_positive() => Ok((r, ids)),
+            Some(r) if r.is_positive() => Err(CouponReject::TooLarge { reduce_amount: r }),
+            _ => Err(CouponReject::NoDiscount),
+        }
+    }
+}
+
+/// 用户的一张优惠券，及对当前商品是否可用
+#[derive(Debug, Clone)]
+pub struct UserCouponEval {
+    pub rule: CouponRule,
+    /// 可优惠的金额，或不能使用的原因
+    pub result: Result<Money, CouponReject>,
+}
+impl UserCouponEval {
+    /// 订单的优惠描述
+    pub fn reduce_des(&self) -> String {
+        format!("{}{}", self.rule.condition_title, self.rule.coupon_name)
+    }
+    pub fn option(&self, is_selected: bool) -> CouponOption {
+        CouponOption {
+            usr_coupon_id: self.rule.usr_coupon_id,
+            coupon_id: self.rule.coupon_id,
+            store_code: self.rule.store(),
+            coupon_name: self.rule.coupon_name.clone(),
+            condition_title: self.rule.condition_title.clone(),
+            reduce_amount: self.rule.reduce_amount,
+            discount: self.rule.discount,
+            start_time: self.rule.start_time.clone(),
+            expire_time: self.rule.expire_time.clone(),
+            is_usable: self.result.is_ok(),
+            reason: self.result.as_ref().err().map(|r| r.to_string()),
+            reduce: self.result.clone().unwrap_or_default(),
+            is_selected,
+        }
+    }
+}
+
+/// 优惠最多的可用优惠券，优惠相同时取先过期的
+pub fn best_coupon(list: &[UserCouponEval]) -> Option<&UserCouponEval> {
+    let expire = |c: &UserCouponEval| c.rule.expire_time.clone().unwrap_or("9999".to_string());
+    list.iter()
+        .filter(|c| c.result.as_ref().is_ok_and(|r| r.is_positive()))
+        .min_by(|a, b| {
+            let (ra, rb) = (a.result.clone().unwrap(), b.result.clone().unwrap());
+            rb.cmp(&ra).then_with(|| expire(a).cmp(&expire(b)))
+        })
+}
+
+/// 结算时，用户的优惠券
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct CouponOption {
+    /// 用户的优惠券编号id
+    pub usr_coupon_id: u64,
+    /// 优惠券id，下单时传入
+    pub coupon_id: u32,
+    /// 店铺券的店铺，平台券为空
+    pub store_code: Option<u32>,
+    /// 优惠券名称
+    pub coupon_name: String,
+    /// 使用条件
+    pub condition_title: String,
+    /// 优惠券优惠金额
+    pub reduce_amount: Option<Money>,
+    /// 优惠券折扣
+    pub discount: Option<Money>,
+    /// 可用的开始时间，为空时不限
+    pub start_time: Option<String>,
+    /// 过期时间
+    pub expire_time: Option<String>,
+    /// 当前商品是否可用
+    pub is_usable: bool,
+    /// 不可用的原因
+    pub reason: Option<String>,
+    /// 可优惠的金额，不可用时为 0
+    pub reduce: Money,
+    /// 是否为本次使用的优惠券
+    pub is_selected: bool,
+}
+
+/// 用户领取优惠券时，按优惠券的有效期计算这张券的 (可用的开始时间, 过期时间)。
+///
+/// 固定时间的为优惠券的 start_time 到 expire_time；领取后 N 天内有效的，从领取时开始算，
+/// 优惠券的 expire_time 只是领取的截止时间
+pub fn coupon_valid_window(
+    valid_type: u8,
+    start_time: Option<&str>,
+    expire_time: Option<&str>,
+    valid_days: Option<u32>,
+    claim_time: &NaiveDateTime,
+) -> (Option<String>, Option<String>) {
+    let fmt = "%Y-%m-%d %H:%M:%S";
+    match valid_days {
+        Some(days) if valid_type == CouponValidType::AfterClaim as u8 => (
+            Some(claim_time.format(fmt).to_string()),
+            Some(
+                (*claim_time + Duration::days(days as i64))
+                    .format(fmt)
+                    .to_string(),
+            ),
+        ),
+        _ => (
+            start_time.map(|t| t.to_string()),
+            expire_time.map(|t| t.to_string()),
+        ),
+    }
+}
+
+/// 查询用户所有未使用的优惠券
+pub fn get_user_coupons(
+    tran: &mut Transaction,
+    uid: u64,
+    is_lock: bool,
+) -> Result<Vec<CouponRule>, Error> {
+    let lock = if is_lock { MY_EXCLUSIVE_LOCK } else { "" };
+
+    #[derive(Deserialize)]
+    struct UserCouponGet {
+        id: u64,
+        coupon_id: u32,
+        coupon_name: Option<String>,
+        reduce_amount: Option<Money>,
+        discount: Option<Money>,
+        start_time: Option<String>,
+        end_time: Option<String>,
+        expire_time: Option<String>,
+        status: i8,
+        is_del: i8,
+        cc_title: Option<String>,
+        cc_full_amount: Option<Money>,
+        cc_store_code: Option<u32>,
+        cc_brand_code: Option<u32>,
+        cc_product_cat: Option<String>,
+        cc_product_sn: Option<u32>,
+        cc_unit_sn: Option<u32>,
+    }
+    let user_coupons: Vec<UserCouponGet> = my_run_tran_vec(
+        tran,
+        myfind!("usr_coupon", {
+            j0: ["coupon_id", "inner", "pmt_coupon.id"],
+            j1: ["pmt_coupon.coupon_condition_id", "inner", "pmt_coupon_condition.id"],
+            p0: ["uid", "=", uid],
+            p1: ["status", "=", UserCouponStatus::NotUsed as i8],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: r#"
+                id,pmt_coupon.id as coupon_id,pmt_coupon.coupon_name,pmt_coupon.reduce_amount,pmt_coupon.discount,
+                start_time,end_time,pmt_coupon.expire_time,pmt_coupon.status,pmt_coupon.is_del,
+                pmt_coupon_condition.title as cc_title,pmt_coupon_condition.full_amount as cc_full_amount,
+                pmt_coupon_condition.store_code as cc_store_code,pmt_coupon_condition.brand_code as cc_brand_code,
+                pmt_coupon_condition.product_cat as cc_product_cat,pmt_coupon_condition.product_sn as cc_product_sn,
+                pmt_coupon_condition.unit_sn as cc_unit_sn
+            "#,
+        }) + lock,
+    )?;
+    Ok(user_coupons
+        .into_iter()
+        .map(|c| CouponRule {
+            usr_coupon_id: c.id,
+            coupon_id: c.coupon_id,
+            coupon_name: c.coupon_name.unwrap_or_default(),
+            condition_title: c.cc_title.unwrap_or_default(),
+            reduce_amount: c.reduce_amount,
+            discount: c.discount,
+            start_time: c.start_time,
+            // 之前领取的没有过期时间，按优惠券的
+            expire_time: c.end_time.or(c.expire_time),
+            is_online: c.status == NormalStatus::Online as i8 && c.is_del == 0,
+            full_amount: c.cc_full_amount.unwrap_or_default(),
+            store_code: c.cc_store_code,
+            brand_code: c.cc_brand_code,
+            product_cat: c
+                .cc_product_cat
+                .unwrap_or_default()
+                .split(",")
+                .filter_map(|x| x.trim().parse::<u32>().ok())
+                .collect(),
+            product_sn: c.cc_product_sn,
+            unit_sn: c.cc_unit_sn,
+        })
+        .collect())
+}
+
+/// 购买的商品及所属的分类，与 user_buy 的顺序一致
+pub fn get_coupon_items(
+    tran: &mut Transaction,
+    user_buy: &[UserBuy],
+) -> Result<Vec<CouponItem>, Error> {
+    let mut cats: HashMap<u32, Vec<[u32; 3]>> = HashMap::new();
+    let product_sns: Vec<u32> = user_buy.iter().map(|x| x.product_sn).collect();
+    if !product_sns.is_empty() {
+        // (product_sn, 一级, 二级, 三级)
+        type CatGet = (u32, Option<u32>, Option<u32>, Option<u32>);
+        let list: Vec<CatGet> = my_exec_tran_vec(
+            tran,
+            &format!(
+                "select product_sn, primary_id, secondary_id, tertiary_id from spu_product_cat where is_del = 0 and product_sn in ({})",
+                in_placeholders(product_sns.len())
+            ),
+            product_sns,
+        )?;
+        for (product_sn, primary, secondary, tertiary) in list {
+            cats.entry(product_sn).or_default().push([
+                primary.unwrap_or_default(),
+                secondary.unwrap_or_default(),
+                tertiary.unwrap_or_default(),
+            ]);
+        }
+    }
+    Ok(user_buy
+        .iter()
+        .map(|x| CouponItem {
+            unit_sn: x.unit_sn,
+            product_sn: x.product_sn,
+            store_code: x.store_code,
+            brand_code: x.brand_code,
+            amount: x.price * x.buy_quantity,
+            cats: cats.get(&x.product_sn).cloned().unwrap_or_default(),
+            is_group_buy: x.group_buy_id.is_some(),
+        })
+        .collect())
+}
+
+/// 即将过期、还没提醒过的优惠券
+#[derive(Deserialize, Debug, Clone)]
+pub struct CouponRemindGet {
+    pub id: u64,
+    pub uid: u64,
+    pub coupon_name: String,
+    pub end_time: String,
+    pub openid: Option<String>,
+    pub phone: Option<String>,
+}
+
+/// 一个用户的到期提醒，同时过期的多张券合并为一条
+#[derive(Debug, Clone, PartialEq)]
+pub struct CouponRemind {
+    pub uid: u64,
+    pub usr_coupon_ids: Vec<u64>,
+    /// 只有一张时为优惠券名，多张时如 满100减10等3张优惠券
+    pub title: String,
+    /// 最早的过期时间
+    pub end_time: String,
+    pub openid: Option<String>,
+    pub phone: Option<String>,
+}
+
+/// 查询过期时间在 (now, deadline] 内、未使用且未提醒的优惠券
+pub fn expiring_coupons_for_remind(
+    conn: &mut PooledConn,
+    now: &str,
+    deadline: &str,
+    limit: u32,
+) -> Result<Vec<CouponRemindGet>, Error> {
+    my_run_vec(
+        conn,
+        myfind!("usr_coupon", {
+            j0: ["coupon_id", "inner", "pmt_coupon.id"],
+            j1: ["uid", "inner", "usr_silent.id"],
+            p0: ["status", "=", UserCouponStatus::NotUsed as u8],
+            p1: ["is_del", "=", 0],
+            p2: ["end_time", ">", now],
+            p3: ["end_time", "<=", deadline],
+            p4: ["remind_at", "is_null", true],
+            r: "p0 && p1 && p2 && p3 && p4",
+            limit: limit,
+            order_by: "uid",
+            select: "id,uid,pmt_coupon.coupon_name,end_time,usr_silent.openid,usr_silent.phone",
+        }),
+    )
+}
+
+/// 按用户合并到期提醒，保持用户第一次出现的顺序
+pub fn group_coupon_reminds(list: Vec<CouponRemindGet>) -> Vec<CouponRemind> {
+    let mut groups: Vec<(CouponRemind, Vec<(String, String)>)> = vec![];
+    for c in list {
+        let pos = match groups.iter().position(|(g, _)| g.uid == c.uid) {
+            Some(i) => i,
+            None => {
+                groups.push((
+                    CouponRemind {
+                        uid: c.uid,
+                        usr_coupon_ids: vec![],
+                        title: String::new(),
+                        end_time: String::new(),
+                        openid: c.openid.filter(|x| !x.is_empty()),
+                        phone: c.phone.filter(|x| !x.is_empty()),
+                    },
+                    vec![],
+                ));
+                groups.len() - 1
+            }
+        };
+        let (g, coupons) = &mut groups[pos];
+        g.usr_coupon_ids.push(c.id);
+        coupons.push((c.end_time, c.coupon_name));
+    }
+    groups
+        .into_iter()
+        .map(|(mut g, mut coupons)| {
+            coupons.sort();
+            let (end_time, name) = coupons[0].clone();
+            g.title = if coupons.len() == 1 {
+                name
+            } else {
+                format!("{}等{}张优惠券", name, coupons.len())
+            };
+            g.end_time = end_time;
+            g
+        })
+        .collect()
+}
+
+/// 记录已提醒，每张券只提醒一次
+pub fn mark_coupon_reminded(conn: &mut PooledConn, ids: &[u64], now: &str) -> Result<u64, Error> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut params: Vec<mysql::Value> = vec![now.into()];
+    params.extend(ids.iter().map(|x| (*x).into()));
+    my_exec_drop(
+        conn,
+        &format!(
+            "UPDATE usr_coupon SET remind_at = ? WHERE id IN ({})",
+            in_placeholders(ids.len())
+        ),
+        params,
+    )
+}
+
+/// 按配置的渠道发送到期提醒。返回是否至少一个渠道发送成功，用户没有 openid、手机号时为 false
+pub async fn send_coupon_remind(remind: &CouponRemind) -> Result<bool, String> {
+    let cfg = &config().coupon;
+    // 订阅消息的时间字段不带秒
+    let time = remind.end_time.chars().take(16).collect::<String>();
+    let mut sent = false;
+    let mut errs: Vec<String> = vec![];
+    for channel in &cfg.remind_channels {
+        match channel.as_str() {
+            "wechat" => {
+                let Some(openid) = &remind.openid else {
+                    continue;
+                };
+                let msg = WxSubscribeMessage::new(openid, &cfg.wx_template_id, &cfg.wx_page)
+                    .value("thing1", &wx_thing_value(&remind.title))
+                    .value("time2", &time);
+                match send_wx_subscribe_message(&msg).await {
+                    Ok(r) if r.errcode.unwrap_or(0) == 0 => sent = true,
+                    Ok(r) => errs.push(format!("wechat {:?} {:?}", r.errcode, r.errmsg)),
+                    Err(e) => errs.push(format!("wechat {}", e)),
+                }
+            }
+            "sms" => {
+                let Some(phone) = &remind.phone else {
+                    continue;
+                };
+                let param = serde_json::json!({"name": &remind.title, "time": &time}).to_string();
+                match sms_send(phone, &cfg.sms_template_code, &param).await {
+                    Ok(()) => sent = true,
+                    Err(e) => errs.push(format!("sms {}", e)),
+                }
+            }
+            _ => {}
+        }
+    }
+    if sent || errs.is_empty() {
+        Ok(sent)
+    } else {
+        Err(errs.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NOW: &str = "2025-06-01 12:00:00";
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn item(unit_sn: u32, product_sn: u32, amount: &str) -> CouponItem {
+        CouponItem {
+            unit_sn,
+            product_sn,
+            store_code: Some(7),
+            brand_code: Some(8),
+            amount: money(amount),
+            cats: vec![[1, 10, 100]],
+            is_group_buy: false,
+        }
+    }
+
+    fn eligible_amount(rule: &CouponRule, items: &[CouponItem]) -> Result<Money, CouponReject> {
+        let ids = rule.eligible_items(items)?;
+        Ok(ids.iter().map(|i| items[*i].amount).sum())
+    }
+
+    fn evaluate(rule: &CouponRule, items: &[CouponItem]) -> Result<Money, CouponReject> {
+        rule.apply(items, NOW).map(|(reduce, _)| reduce)
+    }
+
+    fn rule() -> CouponRule {
+        CouponRule {
+            reduce_amount: Some(money("10")),
+            expire_time: Some("2025-07-01 00:00:00".to_string()),
+            is_online: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_coupon_scope() {
+        let items = vec![item(1001, 101, "30"), item(1002, 102, "50")];
+
+        // 无门槛，整单可用
+        assert_eq!(eligible_amount(&rule(), &items), Ok(money("80")));
+
+        let by_unit = CouponRule {
+            unit_sn: Some(1002),
+            brand_code: Some(9),
+            ..rule()
+        };
+        // 指定了商品的，不看品牌
+        assert_eq!(eligible_amount(&by_unit, &items), Ok(money("50")));
+
+        let by_product = CouponRule {
+            product_sn: Some(103),
+            ..rule()
+        };
+        assert_eq!(
+            eligible_amount(&by_product, &items),
+            Err(CouponReject::Product)
+        );
+
+        // 分类按前缀匹配，可以只指定到一级、二级
+        for (cat, ok) in [
+            (vec![1], true),
+            (vec![1, 10], true),
+            (vec![1, 10, 100], true),
+            (vec![1, 11], false),
+            (vec![2], false),
+        ] {
+            let by_cat = CouponRule {
+                product_cat: cat,
+                ..rule()
+            };
+            assert_eq!(eligible_amount(&by_cat, &items).is_ok(), ok);
+        }
+
+        // 分类、店铺都符合，只差品牌的，返回品牌
+        let by_brand = CouponRule {
+            product_cat: vec![1],
+            store_code: Some(7),
+            brand_code: Some(9),
+            ..rule()
+        };
+        assert_eq!(eligible_amount(&by_brand, &items), Err(CouponReject::Brand));
+        let by_store = CouponRule {
+            store_code: Some(6),
+            ..rule()
+        };
+        assert_eq!(eligible_amount(&by_store, &items), Err(CouponReject::Store));
+    }
+
+    #[test]
+    fn test_coupon_evaluate() {
+        let items = vec![item(1001, 101, "30"), item(1002, 102, "50")];
+        assert_eq!(evaluate(&rule(), &items), Ok(money("10")));
+
+        let offline = CouponRule {
+            is_online: false,
+            ..rule()
+        };
+        assert_eq!(evaluate(&offline, &items), Err(CouponReject::Offline));
+        let expired = CouponRule {
+            expire_time: Some("2025-06-01 12:00:00".to_string()),
+            ..rule()
+        };
+        assert_eq!(evaluate(&expired, &items), Err(CouponReject::Expired));
+        let not_started = CouponRule {
+            start_time: Some("2025-06-02 00:00:00".to_string()),
+            ..rule()
+        };
+        let reject = evaluate(&not_started, &items).unwrap_err();
+        assert_eq!(reject.to_string(), "2025-06-02 00:00:00起可用");
+
+        let full = CouponRule {
+            full_amount: money("100"),
+            ..rule()
+        };
+        let reject = evaluate(&full, &items).unwrap_err();
+        assert_eq!(reject.to_string(), "满100.00元可用，还差20.00元");
+
+        let too_large = CouponRule {
+            unit_sn: Some(1001),
+            reduce_amount: Some(money("30")),
+            ..rule()
+        };
+        assert_eq!(
+            evaluate(&too_large, &items),
+            Err(CouponReject::TooLarge {
+                reduce_amount: money("30")
+            })
+        );
+
+        // 打 85 折，同时有减免金额的按折扣
+        let discount = CouponRule {
+            discount: Some(money("0.85")),
+            ..rule()
+        };
+        assert_eq!(evaluate(&discount, &items), Ok(money("12")));
+
+        let mut group = items.clone();
+        group[0].is_group_buy = true;
+        assert_eq!(evaluate(&rule(), &group), Err(CouponReject::GroupBuy));
+    }
+
+    #[test]
+    fn test_best_coupon() {
+        let eval = |id: u64, result: Result<Money, CouponReject>, expire: &str| UserCouponEval {
+            rule: CouponRule {
+                usr_coupon_id: id,
+                expire_time: Some(expire.to_string()),
+                ..rule()
+            },
+            result,
+        };
+        let list = vec![
+            eval(1, Ok(money("5")), "2025-07-01 00:00:00"),
+            eval(2, Err(CouponReject::Expired), "2025-06-01 00:00:00"),
+            eval(3, Ok(money("8")), "2025-08-01 00:00:00"),
+            eval(4, Ok(money("8")), "2025-07-15 00:00:00"),
+        ];
+        assert_eq!(best_coupon(&list).map(|c| c.rule.usr_coupon_id), Some(4));
+        assert!(best_coupon(&list[1..2]).is_none());
+    }
+
+    #[test]
+    fn test_coupon_valid_window() {
+        let claim =
+            NaiveDateTime::parse_from_str("2025-06-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let fixed = coupon_valid_window(
+            CouponValidType::Fixed as u8,
+            Some("2025-06-10 00:00:00"),
+            Some("2025-07-01 00:00:00"),
+            Some(7),
+            &claim,
+        );
+        assert_eq!(
+            fixed,
+            (
+                Some("2025-06-10 00:00:00".to_string()),
+                Some("2025-07-01 00:00:00".to_string())
+            )
+        );
+        let after = coupon_valid_window(
+            CouponValidType::AfterClaim as u8,
+            None,
+            Some("2025-06-02 00:00:00"),
+            Some(7),
+            &claim,
+        );
+        assert_eq!(
+            after,
+            (
+                Some("2025-06-01 12:00:00".to_string()),
+                Some("2025-06-08 12:00:00".to_string())
+            )
+        );
+        // 没有设置天数的，按固定时间
+        let no_days =
+            coupon_valid_window(CouponValidType::AfterClaim as u8, None, None, None, &claim);
+        assert_eq!(no_days, (None, None));
+    }
+
+    #[test]
+    fn test_group_coupon_reminds() {
+        let row = |id: u64, uid: u64, name: &str, end_time: &str| CouponRemindGet {
+            id,
+            uid,
+            coupon_name: name.to_string(),
+            end_time: end_time.to_string(),
+            openid: Some(format!("o{uid}")),
+            phone: Some(String::new()),
+        };
+        let list = group_coupon_reminds(vec![
+            row(1, 9, "满100减10", "2025-06-03 00:00:00"),
+            row(2, 8, "8折券", "2025-06-02 00:00:00"),
+            row(3, 9, "满50减5", "2025-06-02 12:00:00"),
+        ]);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].uid, 9);
+        assert_eq!(list[0].usr_coupon_ids, vec![1, 3]);
+        assert_eq!(list[0].title, "满50减5等2张优惠券");
+        assert_eq!(list[0].end_time, "2025-06-02 12:00:00");
+        assert_eq!(list[0].openid, Some("o9".to_string()));
+        // 空手机号不发短信
+        assert_eq!(list[0].phone, None);
+        assert_eq!(list[1].title, "8折券");
+        assert!(group_coupon_reminds(vec![]).is_empty());
+    }
+}
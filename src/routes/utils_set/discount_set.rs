@@ -0,0 +1,638 @@
+use actix_web::Error;
+use mysql_quick::{PooledConn, Transaction, myfind, mysetmany};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::types::{DiscountKind, NormalStatus};
+use crate::common::{DiscountConfig, Money};
+use crate::db::{my_run_tran_drop, my_run_tran_vec, my_run_vec};
+use crate::routes::utils_set::coupon_set::{
+    CouponItem, CouponReject, CouponRule, CouponSelect, UserCouponEval, best_coupon,
+};
+use crate::utils::time::{NowTimeType, get_now_time};
+
+/// 满减活动的一档，满 full_amount 减 reduce_amount
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ReductionTier {
+    pub full_amount: Money,
+    pub reduce_amount: Money,
+}
+
+/// 解析满减活动的分档，按满减金额从小到大。格式错误时当作没有分档
+pub fn parse_reduction_tiers(tiers: &str) -> Vec<ReductionTier> {
+    let mut list: Vec<ReductionTier> = serde_json::from_str(tiers).unwrap_or_default();
+    list.sort_by_key(|t| t.full_amount);
+    list
+}
+
+/// 满减活动，不需要领券，商品金额满足时自动减
+#[derive(Debug, Clone, Default)]
+pub struct FullReduction {
+    pub id: u32,
+    pub title: String,
+    /// 店铺的满减只算该店铺的商品，平台的满减为空
+    pub store_code: Option<u32>,
+    pub tiers: Vec<ReductionTier>,
+}
+impl FullReduction {
+    /// 店铺满减的店铺，平台满减为 None
+    pub fn store(&self) -> Option<u32> {
+        self.store_code.filter(|x| *x > 0)
+    }
+
+    /// 按商品当前的金额，取达到的最高一档，及参加满减的商品的下标
+    pub fn apply(&self, items: &[CouponItem]) -> Option<(ReductionTier, Vec<usize>)> {
+        let ids: Vec<usize> = (0..items.len())
+            .filter(|i| self.store().is_none_or(|s| items[*i].store_code == Some(s)))
+            .collect();
+        let amount: Money = ids.iter().map(|i| items[*i].amount).sum();
+        let tier = self.tiers.iter().rev().find(|t| {
+            t.full_amount <= amount && t.reduce_amount.is_positive() && t.reduce_amount < amount
+        })?;
+        Some((*tier, ids))
+    }
+
+    /// 订单的优惠描述，如 `双11满300减30`
+    fn describe(&self, tier: &ReductionTier) -> String {
+        format!(
+            "{}满{}减{}",
+            self.title,
+            tier.full_amount.to_plain_string(),
+            tier.reduce_amount.to_plain_string()
+        )
+    }
+}
+
+/// 订单使用的一项优惠
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct OrderDiscount {
+    /// 1 满减活动，2 店铺券，3 平台券
+    pub kind: u8,
+    /// 满减活动为满减活动id，优惠券为用户的优惠券编号id
+    pub ref_id: u64,
+    /// 店铺的满减、店铺券的店铺
+    pub store_code: Option<u32>,
+    /// 优惠描述
+    pub title: String,
+    /// 优惠金额
+    pub amount: Money,
+}
+
+/// 订单使用的优惠，及分摊到每种商品的金额
+#[derive(Debug, Clone, Default)]
+pub struct DiscountPlan {
+    /// 按使用顺序的每一项优惠
+    pub discounts: Vec<OrderDiscount>,
+    /// 每种商品分摊到的优惠，与购买的商品的顺序一致
+    pub item_reduces: Vec<Money>,
+    /// 使用的优惠券，(用户的优惠券编号id, 优惠券id)
+    pub coupons: Vec<(u64, u32)>,
+}
+impl DiscountPlan {
+    pub fn reduce_amount(&self) -> Money {
+        self.discounts.iter().map(|d| d.amount).sum()
+    }
+}
+
+/// 把 reduce 按金额占比分摊到每一项，向下取整到分，余下的分依次给金额大的项。
+///
+/// 每项分到的不超过它的金额，合计正好是 reduce（reduce 超过合计金额时为合计金额）
+pub fn allocate(amounts: &[Money], reduce: Money) -> Vec<Money> {
+    let total: Money = amounts.iter().copied().sum();
+    let mut shares = vec![Money::ZERO; amounts.len()];
+    if !total.is_positive() || !reduce.is_positive() {
+        return shares;
+    }
+    let reduce = reduce.min(total);
+    for (share, amount) in shares.iter_mut().zip(amounts) {
+        let v = reduce.cent() as i128 * amount.cent().max(0) as i128 / total.cent() as i128;
+        *share = Money::from_cent(v as i64);
+    }
+    let mut left = reduce - shares.iter().copied().sum();
+    let mut order: Vec<usize> = (0..amounts.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(amounts[*i]));
+    while left.is_positive() {
+        for i in &order {
+            if left.is_positive() && shares[*i] < amounts[*i] {
+                shares[*i] += Money::from_cent(1);
+                left -= Money::from_cent(1);
+            }
+        }
+    }
+    shares
+}
+
+/// 一个阶段使用哪些优惠券
+#[derive(Debug, Clone, Copy)]
+enum Pick<'a> {
+    No,
+    /// 每个店铺（或平台）取优惠最多的一张
+    Best,
+    /// 指定的用户的优惠券编号id，不可用的不使用
+    Ids(&'a [u64]),
+}
+
+/// 按顺序使用优惠，items 为减去已使用的优惠后，每种商品的金额
+struct Planner {
+    items: Vec<CouponItem>,
+    plan: DiscountPlan,
+    /// 用户的优惠券，在使用时的商品金额下是否可用
+    evals: Vec<UserCouponEval>,
+}
+impl Planner {
+    fn new(items: &[CouponItem]) -> Self {
+        Planner {
+            items: items.to_vec(),
+            plan: DiscountPlan {
+                item_reduces: vec![Money::ZERO; items.len()],
+                ..Default::default()
+            },
+            evals: vec![],
+        }
+    }
+
+    /// 使用一项优惠，分摊到 ids 的商品上
+    fn take(&mut self, ids: &[usize], discount: OrderDiscount) {
+        let amounts: Vec<Money> = ids.iter().map(|i| self.items[*i].amount).collect();
+        for (i, share) in ids.iter().zip(allocate(&amounts, discount.amount)) {
+            self.items[*i].amount -= share;
+            self.plan.item_reduces[*i] += share;
+        }
+        self.plan.discounts.push(discount);
+    }
+
+    /// 满减活动：每个店铺、平台各取优惠最多的一个，先店铺的，再平台的。拼团的订单不参加
+    fn full_reduction(&mut self, reductions: &[FullReduction]) {
+        if self.items.iter().any(|x| x.is_group_buy) {
+            return;
+        }
+        let mut scopes: Vec<Option<u32>> = reductions.iter().map(|r| r.store()).collect();
+        scopes.sort_by_key(|s| (s.is_none(), *s));
+        scopes.dedup();
+        for scope in scopes {
+            let best = reductions
+                .iter()
+                .filter(|r| r.store() == scope)
+                .filter_map(|r| r.apply(&self.items).map(|(t, ids)| (r, t, ids)))
+                .max_by_key(|(_, t, _)| t.reduce_amount);
+            if let Some((r, tier, ids)) = best {
+                let discount = OrderDiscount {
+                    kind: DiscountKind::FullReduction as u8,
+                    ref_id: r.id as u64,
+                    store_code: scope,
+                    title: r.describe(&tier),
+                    amount: tier.reduce_amount,
+                };
+                self.take(&ids, discount);
+            }
+        }
+    }
+
+    /// 按商品当前的金额判断优惠券是否可用，再使用选出的优惠券，同一店铺只用一张
+    fn coupons(&mut self, coupons: &[&CouponRule], pick: Pick, now: &str) {
+        let applied: Vec<_> = coupons.iter().map(|r| r.apply(&self.items, now)).collect();
+        let evals: Vec<UserCouponEval> = coupons
+            .iter()
+            .zip(&applied)
+            .map(|(r, a)| UserCouponEval {
+                rule: (*r).clone(),
+                result: a.clone().map(|(reduce, _)| reduce),
+            })
+            .collect();
+        let mut used: Vec<usize> = vec![];
+        match pick {
+            Pick::No => (),
+            Pick::Best => {
+                let mut stores: Vec<Option<u32>> = evals.iter().map(|e| e.rule.store()).collect();
+                stores.sort();
+                stores.dedup();
+                for store in stores {
+                    let group: Vec<UserCouponEval> = evals
+                        .iter()
+                        .filter(|e| e.rule.store() == store)
+                        .cloned()
+                        .collect();
+                    if let Some(best) = best_coupon(&group) {
+                        let id = best.rule.usr_coupon_id;
+                        used.extend(evals.iter().position(|e| e.rule.usr_coupon_id == id));
+                    }
+                }
+            }
+            Pick::Ids(ids) => {
+                used = (0..evals.len())
+                    .filter(|i| {
+                        ids.contains(&evals[*i].rule.usr_coupon_id) && evals[*i].result.is_ok()
+                    })
+                    .collect();
+            }
+        }
+        for i in used {
+            let (Ok((reduce, ids)), eval) = (&applied[i], &evals[i]) else {
+                continue;
+            };
+            let kind = match eval.rule.store() {
+                Some(_) => DiscountKind::StoreCoupon,
+                None => DiscountKind::PlatformCoupon,
+            };
+            let discount = OrderDiscount {
+                kind: kind as u8,
+                ref_id: eval.rule.usr_coupon_id,
+                store_code: eval.rule.store(),
+                title: eval.reduce_des(),
+                amount: *reduce,
+            };
+            self.take(ids, discount);
+            self.plan
+                .coupons
+                .push((eval.rule.usr_coupon_id, eval.rule.coupon_id));
+        }
+        self.evals.extend(evals);
+    }
+}
+
+/// 按 满减活动 → 店铺券 → 平台券 的顺序使用优惠
+fn run_plan(
+    items: &[CouponItem],
+    reductions: &[FullReduction],
+    coupons: &[CouponRule],
+    use_full: bool,
+    (store, platform): (Pick, Pick),
+    now: &str,
+) -> Planner {
+    let mut planner = Planner::new(items);
+    if use_full {
+        planner.full_reduction(reductions);
+    }
+    let (stores, platforms): (Vec<&CouponRule>, Vec<&CouponRule>) =
+        coupons.iter().partition(|c| c.store().is_some());
+    planner.coupons(&stores, store, now);
+    planner.coupons(&platforms, platform, now);
+    planner
+}
+
+/// 计算订单的优惠，返回使用的优惠，及用户的优惠券是否可用（按轮到该优惠券时的商品金额判断），
+/// 可用的按优惠金额从多到少排在前面。
+///
+/// 按 满减活动 → 店铺券（每个店铺一张）→ 平台券（一张）的顺序使用，后面的门槛和优惠按前面优惠后的金额计算。
+/// 自动选择时，按叠加规则比较可以的组合，取优惠最多的；指定优惠券时，检查这些优惠券能否一起使用
+pub fn plan_discounts(
+    items: &[CouponItem],
+    reductions: &[FullReduction],
+    coupons: &[CouponRule],
+    select: &CouponSelect,
+    cfg: &DiscountConfig,
+    now: &str,
+) -> Result<(DiscountPlan, Vec<UserCouponEval>), String> {
+    let run = |use_full: bool, picks: (Pick, Pick)| {
+        run_plan(items, reductions, coupons, use_full, picks, now)
+    };
+    let planner = match select {
+        CouponSelect::Best => {
+            let picks = if cfg.platform_with_store_coupon {
+                vec![(Pick::Best, Pick::Best)]
+            } else {
+                vec![(Pick::Best, Pick::No), (Pick::No, Pick::Best)]
+            };
+            let mut candidates = vec![];
+            if !cfg.full_reduction_with_coupon {
+                candidates.push(run(true, (Pick::No, Pick::No)));
+            }
+            for p in picks {
+                candidates.push(run(cfg.full_reduction_with_coupon, p));
+            }
+            // 优惠相同时，取排在前面的，即少用优惠券的
+            candidates
+                .into_iter()
+                .reduce(|a, b| {
+                    if b.plan.reduce_amount() > a.plan.reduce_amount() {
+                        b
+                    } else {
+                        a
+                    }
+                })
+                .unwrap()
+        }
+        CouponSelect::Ids(ids) => {
+            let mut chosen: Vec<&CouponRule> = vec![];
+            for id in ids {
+                let Some(c) = coupons.iter().find(|c| c.coupon_id == *id) else {
+                    return Err("没有找到对应可用优惠券".to_string());
+                };
+                if chosen.iter().any(|x| x.coupon_id == c.coupon_id) {
+                    continue;
+                }
+                // 指定的优惠券已过期、下架的，直接报错；不满足使用条件的，不使用
+                c.check(now).map_err(|e: CouponReject| e.to_string())?;
+                if chosen.iter().any(|x| x.store() == c.store()) {
+                    return Err(match c.store() {
+                        Some(_) => "每个店铺只能使用一张优惠券".to_string(),
+                        None => "只能使用一张平台优惠券".to_string(),
+                    });
+                }
+                chosen.push(c);
+            }
+            let has_store = chosen.iter().any(|c| c.store().is_some());
+            let has_platform = chosen.iter().any(|c| c.store().is_none());
+            if has_store && has_platform && !cfg.platform_with_store_coupon {
+                return Err("平台优惠券不能与店铺优惠券同时使用".to_string());
+            }
+            let usr_ids: Vec<u64> = chosen.iter().map(|c| c.usr_coupon_id).collect();
+            let picks = (Pick::Ids(&usr_ids), Pick::Ids(&usr_ids));
+            if cfg.full_reduction_with_coupon {
+                run(true, picks)
+            } else {
+                // 满减活动不能与优惠券同时使用，指定的优惠券都不可用时，才参加满减活动
+                let planner = run(false, picks);
+                if planner.plan.coupons.is_empty() {
+                    run(true, (Pick::No, Pick::No))
+                } else {
+                    planner
+                }
+            }
+        }
+        CouponSelect::None | CouponSelect::Skip => run(true, (Pick::No, Pick::No)),
+    };
+    let mut evals = planner.evals;
+    evals.sort_by_key(|c| std::cmp::Reverse(c.result.clone().ok()));
+    Ok((planner.plan, evals))
+}
+
+/// 进行中的满减活动
+pub fn get_full_reductions(tran: &mut Transaction) -> Result<Vec<FullReduction>, Error> {
+    let now = get_now_time(NowTimeType::DateTime);
+    #[derive(Deserialize)]
+    struct ReductionGet {
+        id: u32,
+        title: String,
+        store_code: Option<u32>,
+        tiers: String,
+    }
+    let list: Vec<ReductionGet> = my_run_tran_vec(
+        tran,
+        myfind!("pmt_full_reduction", {
+            p0: ["status", "=", NormalStatus::Online as u8],
+            p1: ["is_del", "=", 0],
+            p2: ["start_time", "<=", &now],
+            p3: ["end_time", ">", &now],
+            r: "p0 && p1 && p2 && p3",
+            select: "id,title,store_code,tiers",
+        }),
+    )?;
+    Ok(list
+        .into_iter()
+        .map(|x| FullReduction {
+            id: x.id,
+            title: x.title,
+            store_code: x.store_code,
+            tiers: parse_reduction_tiers(&x.tiers),
+        })
+        .collect())
+}
+
+/// 保存订单的优惠明细
+pub fn save_order_discounts(
+    tran: &mut Transaction,
+    order_sn: &str,
+    discounts: &[OrderDiscount],
+) -> Result<(), Error> {
+    if discounts.is_empty() {
+        return Ok(());
+    }
+    #[derive(Serialize)]
+    struct DiscountSet {
+        order_sn: String,
+        seq: usize,
+        kind: u8,
+        ref_id: u64,
+        store_code: Option<u32>,
+        title: String,
+        amount: Money,
+    }
+    let list: Vec<DiscountSet> = discounts
+        .iter()
+        .enumerate()
+        .map(|(i, d)| DiscountSet {
+            order_sn: order_sn.to_string(),
+            seq: i + 1,
+            kind: d.kind,
+            ref_id: d.ref_id,
+            store_code: d.store_code,
+            title: d.title.clone(),
+            amount: d.amount,
+        })
+        .collect();
+    my_run_tran_drop(tran, mysetmany!("ord_order_discount", list))?;
+    Ok(())
+}
+
+/// 订单使用的优惠券，用户的优惠券编号id
+pub fn get_order_coupon_ids(tran: &mut Transaction, order_sn: &str) -> Result<Vec<u64>, Error> {
+    #[derive(Deserialize)]
+    struct DiscountGet {
+        ref_id: u64,
+    }
+    let list: Vec<DiscountGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order_discount", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["kind", "!=", DiscountKind::FullReduction as u8],
+            r: "p0 && p1",
+            select: "ref_id",
+        }),
+    )?;
+    Ok(list.into_iter().map(|x| x.ref_id).collect())
+}
+
+/// 订单的优惠明细，按使用的顺序
+pub fn get_order_discounts(
+    conn: &mut PooledConn,
+    order_sn: &str,
+) -> Result<Vec<OrderDiscount>, Error> {
+    my_run_vec(
+        conn,
+        myfind!("ord_order_discount", {
+            p0: ["order_sn", "=", order_sn],
+            r: "p0",
+            order_by: "seq",
+            select: "kind,ref_id,store_code,title,amount",
+        }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NOW: &str = "2025-06-01 12:00:00";
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn item(unit_sn: u32, store_code: u32, amount: &str) -> CouponItem {
+        CouponItem {
+            unit_sn,
+            product_sn: unit_sn,
+            store_code: Some(store_code),
+            amount: money(amount),
+            ..Default::default()
+        }
+    }
+
+    fn reduction(id: u32, store_code: Option<u32>) -> FullReduction {
+        FullReduction {
+            id,
+            title: "促销".to_string(),
+            store_code,
+            tiers: parse_reduction_tiers(
+                r#"[{"full_amount":500,"reduce_amount":80},{"full_amount":300,"reduce_amount":30}]"#,
+            ),
+        }
+    }
+
+    fn coupon(usr_coupon_id: u64, store_code: Option<u32>, reduce: &str) -> CouponRule {
+        CouponRule {
+            usr_coupon_id,
+            coupon_id: usr_coupon_id as u32,
+            reduce_amount: Some(money(reduce)),
+            is_online: true,
+            store_code,
+            ..Default::default()
+        }
+    }
+
+    fn cfg(full_with_coupon: bool, platform_with_store: bool) -> DiscountConfig {
+        DiscountConfig {
+            full_reduction_with_coupon: full_with_coupon,
+            platform_with_store_coupon: platform_with_store,
+        }
+    }
+
+    #[test]
+    fn test_allocate() {
+        let cents = |v: &[i64]| v.iter().map(|c| Money::from_cent(*c)).collect::<Vec<_>>();
+        assert_eq!(
+            allocate(&cents(&[100, 200, 300]), Money::from_cent(60)),
+            cents(&[10, 20, 30])
+        );
+        // 余下的分给金额大的
+        assert_eq!(
+            allocate(&cents(&[100, 100, 101]), Money::from_cent(100)),
+            cents(&[33, 33, 34])
+        );
+        // 不超过每项的金额
+        assert_eq!(
+            allocate(&cents(&[1, 1000]), Money::from_cent(2000)),
+            cents(&[1, 1000])
+        );
+        assert_eq!(
+            allocate(&cents(&[0, 0]), Money::from_cent(10)),
+            cents(&[0, 0])
+        );
+    }
+
+    #[test]
+    fn test_full_reduction_tiers() {
+        let items = vec![item(1, 7, "200"), item(2, 8, "150")];
+        // 取达到的最高一档
+        let (tier, ids) = reduction(1, None).apply(&items).unwrap();
+        assert_eq!(tier.reduce_amount, money("30"));
+        assert_eq!(ids, vec![0, 1]);
+        // 店铺的满减只算该店铺的商品
+        assert!(reduction(2, Some(7)).apply(&items).is_none());
+
+        let (plan, _) = plan_discounts(
+            &items,
+            &[reduction(1, None)],
+            &[],
+            &CouponSelect::None,
+            &cfg(true, true),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(plan.discounts[0].title, "促销满300减30");
+        assert_eq!(plan.item_reduces, vec![money("17.15"), money("12.85")]);
+    }
+
+    #[test]
+    fn test_plan_stacking() {
+        let items = vec![item(1, 7, "400"), item(2, 8, "200")];
+        let reductions = vec![reduction(1, None)];
+        let coupons = vec![
+            coupon(11, Some(7), "20"),
+            coupon(12, Some(7), "50"),
+            coupon(13, Some(8), "10"),
+            coupon(14, None, "15"),
+        ];
+
+        // 满 600 减 80，店铺 7、8 各用一张，再用平台券
+        let (plan, evals) = plan_discounts(
+            &items,
+            &reductions,
+            &coupons,
+            &CouponSelect::Best,
+            &cfg(true, true),
+            NOW,
+        )
+        .unwrap();
+        let kinds: Vec<(u8, u64)> = plan.discounts.iter().map(|d| (d.kind, d.ref_id)).collect();
+        assert_eq!(kinds, vec![(1, 1), (2, 12), (2, 13), (3, 14)]);
+        assert_eq!(plan.reduce_amount(), money("155"));
+        assert_eq!(
+            plan.item_reduces.iter().copied().sum::<Money>(),
+            plan.reduce_amount()
+        );
+        assert_eq!(plan.coupons, vec![(12, 12), (13, 13), (14, 14)]);
+        assert_eq!(evals[0].rule.usr_coupon_id, 12);
+
+        // 平台券不能与店铺券同时使用时，取优惠多的一种
+        let (plan, _) = plan_discounts(
+            &items,
+            &reductions,
+            &coupons,
+            &CouponSelect::Best,
+            &cfg(true, false),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(plan.reduce_amount(), money("140"));
+
+        // 满减活动不能与优惠券同时使用时，取优惠多的一种
+        let (plan, _) = plan_discounts(
+            &items,
+            &reductions,
+            &coupons,
+            &CouponSelect::Best,
+            &cfg(false, true),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(plan.reduce_amount(), money("80"));
+        assert!(plan.coupons.is_empty());
+    }
+
+    #[test]
+    fn test_plan_select_ids() {
+        let items = vec![item(1, 7, "400"), item(2, 8, "200")];
+        let coupons = vec![
+            coupon(11, Some(7), "20"),
+            coupon(12, Some(7), "50"),
+            coupon(14, None, "15"),
+        ];
+        let plan = |ids: Vec<u32>, cfg: DiscountConfig| {
+            plan_discounts(&items, &[], &coupons, &CouponSelect::Ids(ids), &cfg, NOW)
+        };
+
+        let (p, _) = plan(vec![11, 14], cfg(true, true)).unwrap();
+        assert_eq!(p.coupons, vec![(11, 11), (14, 14)]);
+        assert_eq!(
+            plan(vec![11, 12], cfg(true, true)).unwrap_err(),
+            "每个店铺只能使用一张优惠券"
+        );
+        assert_eq!(
+            plan(vec![11, 14], cfg(true, false)).unwrap_err(),
+            "平台优惠券不能与店铺优惠券同时使用"
+        );
+        assert!(plan(vec![99], cfg(true, true)).is_err());
+    }
+}
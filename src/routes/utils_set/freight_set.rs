@@ -0,0 +1,508 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_web::Error;
+use mysql_quick::{PooledConn, Transaction, myfind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::Money;
+use crate::common::types::FreightChargeType;
+use crate::db::{my_run_tran_vec, my_run_vec};
+
+/// 运费模板的一条计费规则
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct FreightRule {
+    /// 适用的地区，省或 省/市，如 `广东省`、`广东省/深圳市`，直辖市为 `北京市/北京市`。
+    /// 为空的是默认规则，收货地址没有匹配到其他规则时使用
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// 按件数时为首件数，按重量时为首重（克），固定运费时不用
+    #[serde(default)]
+    pub first: u32,
+    /// 固定运费时为运费，其他为首件、首重的运费
+    pub first_fee: Money,
+    /// 每续多少件、多少克，为 0 时超出首件、首重的不再加收
+    #[serde(default)]
+    pub additional: u32,
+    /// 续件、续重的运费
+    #[serde(default)]
+    pub additional_fee: Money,
+    /// 商品实付满多少包邮，为空时不按金额包邮
+    #[serde(default)]
+    pub free_amount: Option<Money>,
+    /// 满多少件包邮，为空时不按件数包邮
+    #[serde(default)]
+    pub free_quantity: Option<u32>,
+}
+impl FreightRule {
+    /// 与收货地址的匹配程度，2 匹配到市，1 匹配到省，0 默认规则，不匹配为 None
+    fn match_level(&self, province: &str, city: &str) -> Option<u8> {
+        if self.regions.is_empty() {
+            return Some(0);
+        }
+        if province.is_empty() {
+            return None;
+        }
+        let full = format!("{}/{}", province, city);
+        if !city.is_empty() && self.regions.contains(&full) {
+            Some(2)
+        } else if self.regions.iter().any(|r| r == province) {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    /// 按计费方式计算运费，满足包邮条件时为 0
+    pub fn fee(&self, charge_type: FreightChargeType, goods: &FreightGoods) -> Money {
+        if self.free_amount.is_some_and(|f| goods.amount >= f)
+            || self.free_quantity.is_some_and(|q| goods.quantity >= q)
+        {
+            return Money::ZERO;
+        }
+        let count = match charge_type {
+            FreightChargeType::Fixed => return self.first_fee,
+            FreightChargeType::PerItem => goods.quantity,
+            FreightChargeType::PerWeight => goods.weight,
+        };
+        if count <= self.first || self.additional == 0 {
+            return self.first_fee;
+        }
+        let steps = (count - self.first).div_ceil(self.additional);
+        self.first_fee + self.additional_fee * steps
+    }
+}
+
+/// 解析运费模板的计费规则，格式错误时当作没有规则
+pub fn parse_freight_rules(rules: &str) -> Vec<FreightRule> {
+    serde_json::from_str(rules).unwrap_or_default()
+}
+
+/// 检查运费模板的计费规则。regions 为可选的地区，见 `freight_region_names`
+pub fn check_freight_rules(
+    charge_type: FreightChargeType,
+    rules: &[FreightRule],
+    regions: &HashSet<String>,
+) -> Result<(), String> {
+    if rules.iter().filter(|r| r.regions.is_empty()).count() != 1 {
+        return Err("需要有一条默认规则（不选地区），且只能有一条".to_string());
+    }
+    let mut used: HashSet<&str> = HashSet::new();
+    for r in rules {
+        if r.first_fee.is_negative() || r.additional_fee.is_negative() {
+            return Err("运费不能小于0".to_string());
+        }
+        if charge_type != FreightChargeType::Fixed && r.first == 0 {
+            return Err("首件数、首重必须大于0".to_string());
+        }
+        if r.additional_fee.is_positive() && r.additional == 0 {
+            return Err("设置了续件、续重的运费时，续件数、续重必须大于0".to_string());
+        }
+        if r.free_amount.is_some_and(|x| !x.is_positive()) || r.free_quantity == Some(0) {
+            return Err("包邮的金额、件数必须大于0".to_string());
+        }
+        for region in &r.regions {
+            if !regions.contains(region) {
+                return Err(format!("地区 {} 不存在", region));
+            }
+            if !used.insert(region) {
+                return Err(format!("地区 {} 重复", region));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 可选的地区：省，及 省/市。直辖市没有市一级，为 `北京市/北京市`，与收货地址一致
+pub fn region_names(list: &[(String, u32, u32)]) -> HashSet<String> {
+    let mut names: HashSet<String> = HashSet::new();
+    for (name, province, _) in list.iter().filter(|x| x.2 == 0) {
+        names.insert(name.clone());
+        let cities: Vec<&String> = list
+            .iter()
+            .filter(|x| x.1 == *province && x.2 != 0)
+            .map(|x| &x.0)
+            .collect();
+        if cities.is_empty() {
+            names.insert(format!("{}/{}", name, name));
+        }
+        for c in cities {
+            names.insert(format!("{}/{}", name, c));
+        }
+    }
+    names
+}
+
+/// 从 cmn_province 查询可选的地区
+pub fn freight_region_names(conn: &mut PooledConn) -> Result<HashSet<String>, Error> {
+    #[derive(Deserialize)]
+    struct ProvinceGet {
+        name: String,
+        province: u32,
+        city: u32,
+    }
+    let list: Vec<ProvinceGet> = my_run_vec(
+        conn,
+        myfind!("cmn_province", {
+            p0: ["area", "=", 0],
+            p1: ["town", "=", 0],
+            r: "p0 && p1",
+            select: "name,province,city",
+        }),
+    )?;
+    let list: Vec<(String, u32, u32)> = list
+        .into_iter()
+        .map(|x| (x.name, x.province, x.city))
+        .collect();
+    Ok(region_names(&list))
+}
+
+/// 运费模板
+#[derive(Debug, Clone)]
+pub struct FreightTemplate {
+    pub charge_type: FreightChargeType,
+    pub rules: Vec<FreightRule>,
+}
+impl FreightTemplate {
+    /// 收货地址使用的规则，市 > 省 > 默认规则
+    pub fn rule_for(&self, province: &str, city: &str) -> Option<&FreightRule> {
+        self.rules
+            .iter()
+            .filter_map(|r| r.match_level(province, city).map(|l| (l, r)))
+            .max_by_key(|(l, _)| *l)
+            .map(|(_, r)| r)
+    }
+}
+
+/// 同一店铺、同一运费模板的商品合计
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FreightGoods {
+    /// 件数
+    pub quantity: u32,
+    /// 重量 g
+    pub weight: u32,
+    /// 商品实付，即优惠后的金额
+    pub amount: Money,
+}
+
+/// 计算运费的一种商品
+#[derive(Debug, Clone, Default)]
+pub struct FreightItem {
+    pub store_code: Option<u32>,
+    /// 产品的运费模板，没有时为店铺的，都没有的包邮
+    pub template_id: Option<u32>,
+    pub quantity: u32,
+    /// 合计重量 g
+    pub weight: u32,
+    pub amount: Money,
+}
+
+/// 一个店铺的运费
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct StoreFreight {
+    /// 店铺，平台自营的为空
+    pub store_code: Option<u32>,
+    /// 运费,(元)
+    pub amount: Money,
+}
+
+/// (店铺, 店铺内每个运费模板的 (模板id, 合计的商品))
+type StoreGoods = (Option<u32>, Vec<(u32, FreightGoods)>);
+
+/// 按店铺计算运费，店铺的顺序与商品中第一次出现的顺序一致。
+///
+/// 同一店铺使用同一运费模板的商品，合计件数、重量和实付后计算，不同模板的运费相加；
+/// 没有运费模板、模板已删除的商品包邮
+pub fn calc_freights(
+    items: &[FreightItem],
+    templates: &HashMap<u32, FreightTemplate>,
+    province: &str,
+    city: &str,
+) -> Vec<StoreFreight> {
+    let mut stores: Vec<StoreGoods> = vec![];
+    for x in items {
+        let pos = match stores.iter().position(|s| s.0 == x.store_code) {
+            Some(i) => i,
+            None => {
+                stores.push((x.store_code, vec![]));
+                stores.len() - 1
+            }
+        };
+        let Some(tid) = x.template_id.filter(|t| templates.contains_key(t)) else {
+            continue;
+        };
+        let groups = &mut stores[pos].1;
+        let goods = match groups.iter().position(|g| g.0 == tid) {
+            Some(i) => &mut groups[i].1,
+            None => {
+                groups.push((tid, FreightGoods::default()));
+                &mut groups.last_mut().unwrap().1
+            }
+        };
+        goods.quantity += x.quantity;
+        goods.weight += x.weight;
+        goods.amount += x.amount;
+    }
+    stores
+        .into_iter()
+        .map(|(store_code, groups)| StoreFreight {
+            store_code,
+            amount: groups
+                .iter()
+                .filter_map(|(tid, goods)| {
+                    let t = &templates[tid];
+                    t.rule_for(province, city)
+                        .map(|r| r.fee(t.charge_type, goods))
+                })
+                .sum(),
+        })
+        .collect()
+}
+
+/// 查询运费模板，已删除的不返回
+/// 店铺默认的运费模板，返回 店铺code => 模板id，没有设置的不返回
+pub fn get_store_freight_template_ids(
+    tran: &mut Transaction,
+    store_codes: &[u32],
+) -> Result<HashMap<u32, u32>, Error> {
+    if store_codes.is_empty() {
+        return Ok(HashMap::new());
+    }
+    #[derive(Deserialize)]
+    struct StoreGet {
+        code: u32,
+        freight_template_id: Option<u32>,
+    }
+    let codes = store_codes
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    let list: Vec<StoreGet> = my_run_tran_vec(
+        tran,
+        myfind!("com_store", {
+            p0: ["code", "in", codes],
+            r: "p0",
+            select: "code,freight_template_id",
+        }),
+    )?;
+    Ok(list
+        .into_iter()
+        .filter_map(|x| Some((x.code, x.freight_template_id?)))
+        .collect())
+}
+
+pub fn get_freight_templates(
+    tran: &mut Transaction,
+    ids: &[u32],
+) -> Result<HashMap<u32, FreightTemplate>, Error> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    #[derive(Deserialize)]
+    struct TemplateGet {
+        id: u32,
+        charge_type: u8,
+        rules: String,
+    }
+    let ids = ids
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    let list: Vec<TemplateGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_freight_template", {
+            p0: ["id", "in", ids],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "id,charge_type,rules",
+        }),
+    )?;
+    Ok(list
+        .into_iter()
+        .filter_map(|x| {
+            let charge_type = FreightChargeType::try_from(x.charge_type).ok()?;
+            Some((
+                x.id,
+                FreightTemplate {
+                    charge_type,
+                    rules: parse_freight_rules(&x.rules),
+                },
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn m(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn rule(
+        regions: &[&str],
+        first: u32,
+        first_fee: &str,
+        additional: u32,
+        fee: &str,
+    ) -> FreightRule {
+        FreightRule {
+            regions: regions.iter().map(|x| x.to_string()).collect(),
+            first,
+            first_fee: m(first_fee),
+            additional,
+            additional_fee: m(fee),
+            free_amount: None,
+            free_quantity: None,
+        }
+    }
+
+    #[test]
+    fn test_freight_rule_fee() {
+        let goods = |quantity: u32, weight: u32, amount: &str| FreightGoods {
+            quantity,
+            weight,
+            amount: m(amount),
+        };
+        // 首件 1 件 10 元，每续 2 件 3 元
+        let mut r = rule(&[], 1, "10", 2, "3");
+        assert_eq!(
+            r.fee(FreightChargeType::PerItem, &goods(1, 0, "50")),
+            m("10")
+        );
+        assert_eq!(
+            r.fee(FreightChargeType::PerItem, &goods(2, 0, "50")),
+            m("13")
+        );
+        assert_eq!(
+            r.fee(FreightChargeType::PerItem, &goods(4, 0, "50")),
+            m("16")
+        );
+        assert_eq!(r.fee(FreightChargeType::Fixed, &goods(4, 0, "50")), m("10"));
+        // 首重 1000 克，续重 500 克
+        let w = rule(&[], 1000, "12", 500, "2");
+        assert_eq!(
+            w.fee(FreightChargeType::PerWeight, &goods(3, 0, "50")),
+            m("12")
+        );
+        assert_eq!(
+            w.fee(FreightChargeType::PerWeight, &goods(3, 1600, "50")),
+            m("16")
+        );
+        // 包邮
+        r.free_amount = Some(m("99"));
+        r.free_quantity = Some(5);
+        assert_eq!(
+            r.fee(FreightChargeType::PerItem, &goods(4, 0, "99")),
+            Money::ZERO
+        );
+        assert_eq!(
+            r.fee(FreightChargeType::PerItem, &goods(5, 0, "10")),
+            Money::ZERO
+        );
+        assert_eq!(
+            r.fee(FreightChargeType::PerItem, &goods(4, 0, "98.99")),
+            m("16")
+        );
+    }
+
+    #[test]
+    fn test_calc_freights() {
+        let t = FreightTemplate {
+            charge_type: FreightChargeType::PerItem,
+            rules: vec![
+                rule(&[], 1, "10", 1, "2"),
+                rule(&["新疆维吾尔自治区"], 1, "20", 1, "5"),
+                rule(&["广东省"], 1, "6", 1, "1"),
+                rule(&["广东省/深圳市"], 1, "0", 0, "0"),
+            ],
+        };
+        let fixed = FreightTemplate {
+            charge_type: FreightChargeType::Fixed,
+            rules: vec![rule(&[], 0, "8", 0, "0")],
+        };
+        assert_eq!(
+            t.rule_for("广东省", "深圳市").unwrap().first_fee,
+            Money::ZERO
+        );
+        assert_eq!(t.rule_for("广东省", "广州市").unwrap().first_fee, m("6"));
+        assert_eq!(t.rule_for("", "").unwrap().first_fee, m("10"));
+        let templates = HashMap::from([(1, t), (2, fixed)]);
+        let item = |store: Option<u32>, tid: Option<u32>, quantity: u32| FreightItem {
+            store_code: store,
+            template_id: tid,
+            quantity,
+            weight: 0,
+            amount: m("20"),
+        };
+        let items = vec![
+            item(Some(7), Some(1), 2),
+            item(None, Some(2), 1),
+            item(Some(7), Some(1), 1),
+            item(Some(7), Some(2), 3),
+            item(Some(9), None, 1),
+            item(Some(9), Some(404), 1),
+        ];
+        let list = calc_freights(&items, &templates, "浙江省", "杭州市");
+        let amounts: Vec<(Option<u32>, Money)> =
+            list.iter().map(|x| (x.store_code, x.amount)).collect();
+        // 店铺 7：模板 1 合计 3 件 10 + 2 * 2，模板 2 固定 8
+        assert_eq!(
+            amounts,
+            vec![(Some(7), m("22")), (None, m("8")), (Some(9), Money::ZERO)]
+        );
+        let list = calc_freights(&items, &templates, "新疆维吾尔自治区", "乌鲁木齐市");
+        assert_eq!(list[0].amount, m("38"));
+    }
+
+    #[test]
+    fn test_check_freight_rules() {
+        let list = vec![
+            ("北京市".to_string(), 11, 0),
+            ("广东省".to_string(), 44, 0),
+            ("广州市".to_string(), 44, 1),
+            ("深圳市".to_string(), 44, 3),
+        ];
+        let regions = region_names(&list);
+        assert!(regions.contains("北京市/北京市"));
+        assert!(regions.contains("广东省/深圳市"));
+        assert!(!regions.contains("广东省/广东省"));
+        assert_eq!(regions.len(), 5);
+
+        let mut rules = vec![
+            rule(&[], 1, "10", 1, "2"),
+            rule(&["广东省"], 1, "6", 0, "0"),
+        ];
+        assert_eq!(
+            check_freight_rules(FreightChargeType::PerItem, &rules, &regions),
+            Ok(())
+        );
+        rules[1].first = 0;
+        assert_eq!(
+            check_freight_rules(FreightChargeType::PerItem, &rules, &regions),
+            Err("首件数、首重必须大于0".to_string())
+        );
+        assert!(check_freight_rules(FreightChargeType::Fixed, &rules, &regions).is_ok());
+        rules.push(rule(&["广东省/深圳市", "广东省"], 0, "0", 0, "0"));
+        assert_eq!(
+            check_freight_rules(FreightChargeType::Fixed, &rules, &regions),
+            Err("地区 广东省 重复".to_string())
+        );
+        rules[2].regions = vec!["火星".to_string()];
+        assert_eq!(
+            check_freight_rules(FreightChargeType::Fixed, &rules, &regions),
+            Err("地区 火星 不存在".to_string())
+        );
+        rules[2].regions = vec![];
+        assert!(check_freight_rules(FreightChargeType::Fixed, &rules, &regions).is_err());
+        rules.pop();
+        rules[0].additional = 0;
+        assert!(check_freight_rules(FreightChargeType::Fixed, &rules, &regions).is_err());
+        rules[0].additional_fee = Money::ZERO;
+        rules[0].free_quantity = Some(0);
+        assert!(check_freight_rules(FreightChargeType::Fixed, &rules, &regions).is_err());
+    }
+}
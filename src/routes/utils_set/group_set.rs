@@ -0,0 +1,649 @@
+//! 拼团业务逻辑
+//!
+//! 用户开团或通过分享码参团后，按立即购买的方式下单。支付后订单为已支付待成团，
+//! 团满员时所有成员的订单改为已支付；超时未成团时由定时任务取消未支付的订单，已支付的自动退款。
+
+use actix_web::{Error, error};
+use chrono::{Duration, Local};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, Transaction, myfind, myget, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::common::Money;
+use crate::common::types::{
+    GroupMemberStatus, GroupStatus, NormalStatus, OrderItemStatus, OrderPayStatus, PayType,
+    ShopCartStatus, TranType,
+};
+use crate::db::{my_run_tran_drop, my_run_tran_vec};
+use crate::routes::Res;
+use crate::routes::utils_set::agent_set::do_order_agent_split;
+use crate::routes::utils_set::mall_set::{
+    UserBuy, add_unit_to_shop_cart, cancel_pending_order, upd_order_item_status, upd_order_status,
+    upd_product_unit_sell_total,
+};
+use crate::routes::utils_set::pocket_set::pocket_money_add;
+use crate::routes::utils_set::sales_set::do_order_sale_split;
+use crate::routes::utils_set::write_off_item::add_write_off;
+use crate::utils::time::{NowTimeType, get_now_time};
+
+/// 新增团的状态变更记录
+pub fn add_group_log(
+    tran: &mut Transaction,
+    group_id: u64,
+    status: GroupStatus,
+    content: &str,
+) -> Result<(), Error> {
+    my_run_tran_drop(
+        tran,
+        myset!("ord_group_log", {
+            "group_id": group_id,
+            "status": status as u8,
+            "content": content,
+        }),
+    )?;
+    Ok(())
+}
+
+/// 新团的分享码
+fn new_share_code() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..12].to_uppercase()
+}
+
+/// 团长支付后，成团的截止时间
+fn group_expire_time(group_hours: u32) -> String {
+    (Local::now() + Duration::hours(group_hours as i64))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+#[derive(Deserialize)]
+struct GroupBuyGet {
+    unit_sn: u32,
+    group_size: u32,
+    start_time: String,
+    end_time: String,
+    status: i8,
+    is_del: u8,
+}
+fn get_group_buy(tran: &mut Transaction, group_buy_id: u32) -> Result<Option<GroupBuyGet>, Error> {
+    let list: Vec<GroupBuyGet> = my_run_tran_vec(
+        tran,
+        myget!(
+            "pmt_group_buy",
+            group_buy_id,
+            "unit_sn,group_size,start_time,end_time,status,is_del"
+        ),
+    )?;
+    Ok(list
+        .into_iter()
+        .next()
+        .filter(|x| x.is_del == 0 && x.status == NormalStatus::Online as i8))
+}
+
+#[derive(Deserialize)]
+struct GroupGet {
+    id: u64,
+    group_buy_id: u32,
+    group_size: u32,
+    joined: u32,
+    paid: u32,
+    status: i8,
+    expire_time: Option<String>,
+}
+fn get_group(tran: &mut Transaction, group_id: u64) -> Result<Option<GroupGet>, Error> {
+    let list: Vec<GroupGet> = my_run_tran_vec(
+        tran,
+        myget!(
+            "ord_group",
+            group_id,
+            "id,group_buy_id,group_size,joined,paid,status,expire_time"
+        ) + MY_EXCLUSIVE_LOCK,
+    )?;
+    Ok(list.into_iter().next())
+}
+
+/// 团是否还能参加：拼团中、未到截止时间、还有名额
+fn can_join(group: &GroupGet, now: &str) -> Result<(), &'static str> {
+    if group.status != GroupStatus::Grouping as i8 {
+        return Err("该团已结束");
+    }
+    if group.expire_time.as_deref().is_some_and(|t| t <= now) {
+        return Err("该团已结束");
+    }
+    if group.joined >= group.group_size {
+        return Err("该团已满员");
+    }
+    Ok(())
+}
+
+/// 用户是否已在团中（待支付或已支付）
+fn is_group_member(tran: &mut Transaction, group_id: u64, uid: u64) -> Result<bool, Error> {
+    let list: Vec<serde_json::Value> = my_run_tran_vec(
+        tran,
+        myfind!("ord_group_member", {
+            p0: ["group_id", "=", group_id],
+            p1: ["uid", "=", uid],
+            p2: ["status", "=", GroupMemberStatus::PendingPayment as u8],
+            p3: ["status", "=", GroupMemberStatus::Paid as u8],
+            r: "p0 && p1 && (p2 || p3)",
+            select: "id",
+        }),
+    )?;
+    Ok(!list.is_empty())
+}
+
+/// 开团或参团，添加到立即购买，之后按立即购买的方式下单
+pub fn add_group_buy_to_buy_now(
+    tran: &mut Transaction,
+    uid: u64,
+    group_buy_id: u32,
+    share_code: Option<&str>,
+    buy_quantity: u32,
+) -> Result<Res<String>, Error> {
+    let Some(group_buy) = get_group_buy(tran, group_buy_id)? else {
+        return Ok(Res::fail("拼团活动不存在或已下架"));
+    };
+    let now = get_now_time(NowTimeType::DateTime);
+    let group_id = if let Some(code) = share_code {
+        // 参团
+        #[derive(Deserialize)]
+        struct GroupIdGet {
+            id: u64,
+        }
+        let ids: Vec<GroupIdGet> =
+            my_run_tran_vec(tran, myget!("ord_group", {"share_code": code}, "id"))?;
+        let group = match ids.first() {
+            Some(g) => get_group(tran, g.id)?,
+            None => None,
+        };
+        let Some(group) = group.filter(|g| g.group_buy_id == group_buy_id) else {
+            return Ok(Res::fail("团不存在"));
+        };
+        if let Err(msg) = can_join(&group, &now) {
+            return Ok(Res::fail(msg));
+        }
+        if is_group_member(tran, group.id, uid)? {
+            return Ok(Res::fail("你已在团中"));
+        }
+        Some(group.id)
+    } else {
+        // 开团，只能在活动时间内
+        if group_buy.start_time > now {
+            return Ok(Res::fail("拼团活动未开始"));
+        }
+        if group_buy.end_time <= now {
+            return Ok(Res::fail("拼团活动已结束"));
+        }
+        None
+    };
+    let add_info = add_unit_to_shop_cart(
+        tran,
+        uid,
+        group_buy.unit_sn,
+        buy_quantity,
+        ShopCartStatus::BuyNow,
+    )?;
+    let Some(cart_id) = add_info.objects.filter(|_| add_info.status == 1) else {
+        return Ok(Res::fail(&add_info.message));
+    };
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_shop_cart", cart_id, {
+            "group_buy_id": group_buy_id,
+            "group_id": group_id,
+        }),
+    )?;
+    Ok(Res::success("添加成功".to_string()))
+}
+
+/// 下单时，开团或加入团，返回团id。
+///
+/// 拼团商品只能单独下单，且不能使用优惠券。开的团要等团长支付后，其他用户才能参加
+pub fn join_group_on_order(
+    tran: &mut Transaction,
+    uid: u64,
+    user_buy: &[UserBuy],
+    is_coupon_used: bool,
+    order_sn: &str,
+) -> Result<Res<u64>, Error> {
+    if user_buy.len() != 1 {
+        return Ok(Res::fail("拼团商品需要单独下单"));
+    }
+    if is_coupon_used {
+        return Ok(Res::fail("拼团订单不能使用优惠券"));
+    }
+    let Some(group_buy_id) = user_buy[0].group_buy_id else {
+        return Ok(Res::fail("不是拼团商品"));
+    };
+    let Some(group_buy) = get_group_buy(tran, group_buy_id)? else {
+        return Ok(Res::fail("拼团活动不存在或已下架"));
+    };
+    let now = get_now_time(NowTimeType::DateTime);
+    let (group_id, is_leader) = if let Some(group_id) = user_buy[0].group_id {
+        let Some(group) = get_group(tran, group_id)? else {
+            return Ok(Res::fail("团不存在"));
+        };
+        if let Err(msg) = can_join(&group, &now) {
+            return Ok(Res::fail(msg));
+        }
+        if is_group_member(tran, group_id, uid)? {
+            return Ok(Res::fail("你已在团中"));
+        }
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_group", group_id, { "joined": ["incr", 1] }),
+        )?;
+        (group_id, false)
+    } else {
+        if group_buy.end_time <= now {
+            return Ok(Res::fail("拼团活动已结束"));
+        }
+        let group_id = my_run_tran_drop(
+            tran,
+            myset!("ord_group", {
+                "group_buy_id": group_buy_id,
+                "share_code": new_share_code(),
+                "leader_uid": uid,
+                "group_size": group_buy.group_size,
+                "joined": 1,
+                "status": GroupStatus::Opening as u8,
+            }),
+        )?;
+        add_group_log(tran, group_id, GroupStatus::Opening, "开团，等待团长支付")?;
+        (group_id, true)
+    };
+    my_run_tran_drop(
+        tran,
+        myset!("ord_group_member", {
+            "group_id": group_id,
+            "uid": uid,
+            "order_sn": order_sn,
+            "is_leader": is_leader as u8,
+            "status": GroupMemberStatus::PendingPayment as u8,
+        }),
+    )?;
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_order", {"order_sn": order_sn}, { "group_id": group_id }),
+    )?;
+    Ok(Res::success(group_id))
+}
+
+#[derive(Deserialize)]
+struct MemberGet {
+    id: u64,
+    group_id: u64,
+    uid: u64,
+    is_leader: u8,
+    status: i8,
+}
+fn get_member(tran: &mut Transaction, order_sn: &str) -> Result<Option<MemberGet>, Error> {
+    let list: Vec<MemberGet> = my_run_tran_vec(
+        tran,
+        myget!(
+            "ord_group_member",
+            {"order_sn": order_sn},
+            "id,group_id,uid,is_leader,status"
+        ) + MY_EXCLUSIVE_LOCK,
+    )?;
+    Ok(list.into_iter().next())
+}
+
+/// 拼团订单是否已支付待成团，支付完成后调用方据此跳过分成、销量等处理
+pub fn is_group_order(tran: &mut Transaction, order_sn: &str) -> Result<bool, Error> {
+    Ok(get_member(tran, order_sn)?.is_some())
+}
+
+/// 拼团订单支付成功：订单改为已支付待成团。团长支付后开始拼团，满员时成团
+pub fn group_order_paid(
+    tran: &mut Transaction,
+    order_sn: &str,
+    tran_id: Option<String>,
+) -> Result<(), Error> {
+    let Some(member) = get_member(tran, order_sn)? else {
+        return Err(error::ErrorNotFound("拼团成员不存在"));
+    };
+    // 重复的支付通知
+    if member.status != GroupMemberStatus::PendingPayment as i8 {
+        return Err(error::ErrorBadRequest("订单信息不是待支付"));
+    }
+    let Some(group) = get_group(tran, member.group_id)? else {
+        return Err(error::ErrorNotFound("团不存在"));
+    };
+    upd_order_status(tran, order_sn, OrderPayStatus::GroupPending, tran_id, None)?;
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_order_item", {"order_sn": order_sn}, {
+            "status": OrderItemStatus::GroupPending as u8,
+        }),
+    )?;
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_group_member", member.id, {
+            "status": GroupMemberStatus::Paid as u8,
+        }),
+    )?;
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_group", group.id, { "paid": ["incr", 1] }),
+    )?;
+
+    let mut status = group.status;
+    if status == GroupStatus::Opening as i8 && member.is_leader == 1 {
+        // 团长支付后，开始拼团计时
+        #[derive(Deserialize)]
+        struct HoursGet {
+            group_hours: u32,
+        }
+        let hours: Vec<HoursGet> = my_run_tran_vec(
+            tran,
+            myget!("pmt_group_buy", group.group_buy_id, "group_hours"),
+        )?;
+        let group_hours = hours.first().map(|h| h.group_hours).unwrap_or(24);
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_group", group.id, {
+                "status": GroupStatus::Grouping as u8,
+                "expire_time": group_expire_time(group_hours),
+            }),
+        )?;
+        add_group_log(
+            tran,
+            group.id,
+            GroupStatus::Grouping,
+            "团长已支付，开始拼团",
+        )?;
+        status = GroupStatus::Grouping as i8;
+    } else if status == GroupStatus::Grouping as i8 {
+        add_group_log(
+            tran,
+            group.id,
+            GroupStatus::Grouping,
+            &format!("用户 {} 已支付参团", member.uid),
+        )?;
+    } else {
+        // 团已结束后才收到支付（如微信订单关闭失败），留给后台人工退款
+        add_group_log(
+            tran,
+            group.id,
+            GroupStatus::Failed,
+            &format!(
+                "团已结束，用户 {} 的订单 {} 需人工退款",
+                member.uid, order_sn
+            ),
+        )?;
+        return Ok(());
+    }
+    if status == GroupStatus::Grouping as i8 && group.paid + 1 >= group.group_size {
+        group_success(tran, group.id)?;
+    }
+    Ok(())
+}
+
+/// 成团：所有已支付成员的订单改为已支付，进行分成、销量、核销等处理
+fn group_success(tran: &mut Transaction, group_id: u64) -> Result<(), Error> {
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_group", group_id, {
+            "status": GroupStatus::Success as u8,
+            "success_time": get_now_time(NowTimeType::DateTime),
+        }),
+    )?;
+    add_group_log(tran, group_id, GroupStatus::Success, "已成团")?;
+
+    #[derive(Deserialize)]
+    struct PaidOrderGet {
+        order_sn: String,
+        uid: u64,
+        pay_type: Option<String>,
+    }
+    let orders: Vec<PaidOrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_group_member", {
+            j0: ["order_sn", "inner", "ord_order.order_sn"],
+            p0: ["group_id", "=", group_id],
+            p1: ["status", "=", GroupMemberStatus::Paid as u8],
+            r: "p0 && p1",
+            select: "order_sn,uid,ord_order.pay_type",
+        }),
+    )?;
+    for o in orders {
+        let pay_type: PayType = o.pay_type.unwrap_or_default().into();
+        do_order_sale_split(tran, &o.order_sn, o.uid, pay_type)?;
+        do_order_agent_split(tran, &o.order_sn)?;
+        upd_order_status(tran, &o.order_sn, OrderPayStatus::Paid, None, None)?;
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_order_item", {"order_sn": &o.order_sn}, {
+                "status": OrderItemStatus::WaitDeliverGoods as u8,
+            }),
+        )?;
+        upd_product_unit_sell_total(tran, &o.order_sn)?;
+        add_write_off(tran, &o.order_sn)?;
+    }
+    Ok(())
+}
+
+/// 取消待支付的拼团订单时，释放名额。团长未支付就取消的，团直接失败
+pub fn cancel_group_member(tran: &mut Transaction, order_sn: &str) -> Result<(), Error> {
+    let Some(member) = get_member(tran, order_sn)? else {
+        return Ok(());
+    };
+    if member.status != GroupMemberStatus::PendingPayment as i8 {
+        return Ok(());
+    }
+    let Some(group) = get_group(tran, member.group_id)? else {
+        return Ok(());
+    };
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_group_member", member.id, {
+            "status": GroupMemberStatus::Cancelled as u8,
+        }),
+    )?;
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_group", group.id, { "joined": ["incr", -1] }),
+    )?;
+    if member.is_leader == 1 && group.status == GroupStatus::Opening as i8 {
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_group", group.id, { "status": GroupStatus::Failed as u8 }),
+        )?;
+        add_group_log(tran, group.id, GroupStatus::Failed, "团长未支付，开团失败")?;
+    } else if group.status == GroupStatus::Grouping as i8 {
+        add_group_log(
+            tran,
+            group.id,
+            GroupStatus::Grouping,
+            &format!("用户 {} 取消订单，释放名额", member.uid),
+        )?;
+    }
+    Ok(())
+}
+
+/// 需要在提交事务前调用微信接口的退款
+#[derive(Debug, Serialize)]
+pub struct GroupWxRefund {
+    pub order_sn: String,
+    pub transaction_id: String,
+    pub out_refund_no: String,
+    pub amount: Money,
+}
+/// 拼团失败后，需要调用微信接口的操作
+#[derive(Debug, Default)]
+pub struct GroupFailWx {
+    /// 要关闭的微信订单
+    pub close_orders: Vec<String>,
+    /// 要退款的微信订单
+    pub refunds: Vec<GroupWxRefund>,
+}
+
+/// 拼团失败的微信退款单号，同一订单总是相同，重试时微信不会重复退款
+fn group_refund_no(order_sn: &str) -> String {
+    format!("GB{}", order_sn)
+}
+
+/// 超时未成团：团改为失败，取消未支付的订单，已支付的订单退款并返还库存。
+///
+/// 零钱支付的直接退回零钱；微信支付的订单改为退款中，调用方需在提交事务前按返回值关闭、退款微信订单
+pub fn group_fail(
+    tran: &mut Transaction,
+    group_id: u64,
+    reason: &str,
+) -> Result<GroupFailWx, Error> {
+    let mut wx = GroupFailWx::default();
+    let Some(group) = get_group(tran, group_id)? else {
+        return Ok(wx);
+    };
+    if group.status != GroupStatus::Grouping as i8 {
+        return Ok(wx);
+    }
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_group", group_id, { "status": GroupStatus::Failed as u8 }),
+    )?;
+    add_group_log(tran, group_id, GroupStatus::Failed, reason)?;
+
+    #[derive(Deserialize)]
+    struct MemberOrderGet {
+        id: u64,
+        order_sn: String,
+        uid: u64,
+        status: i8,
+        pay_amount: Money,
+        pay_type: Option<String>,
+        transaction_id: Option<String>,
+    }
+    let members: Vec<MemberOrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_group_member", {
+            j0: ["order_sn", "inner", "ord_order.order_sn"],
+            p0: ["group_id", "=", group_id],
+            p1: ["status", "=", GroupMemberStatus::PendingPayment as u8],
+            p2: ["status", "=", GroupMemberStatus::Paid as u8],
+            r: "p0 && (p1 || p2)",
+            select: "id,order_sn,uid,status,ord_order.pay_amount,ord_order.pay_type,ord_order.transaction_id",
+        }),
+    )?;
+    for m in members {
+        if m.status == GroupMemberStatus::PendingPayment as i8 {
+            if cancel_pending_order(tran, &m.order_sn, None, reason)? == PayType::WxPay {
+                wx.close_orders.push(m.order_sn);
+            }
+            continue;
+        }
+        // 已支付的，返还库存后退款
+        #[derive(Deserialize)]
+        struct OrderItemGet {
+            order_item_id: String,
+            unit_sn: u32,
+            buy_quantity: u32,
+        }
+        let items: Vec<OrderItemGet> = my_run_tran_vec(
+            tran,
+            myfind!("ord_order_item", {
+                p0: ["order_sn", "=", &m.order_sn],
+                p1: ["is_del", "=", 0],
+                r: "p0 && p1",
+                select: "order_item_id,unit_sn,buy_quantity",
+            }),
+        )?;
+        for item in &items {
+            my_run_tran_drop(
+                tran,
+                myupdate!("sku_unit", {"unit_sn": item.unit_sn}, {
+                    "quantity": ["incr", item.buy_quantity],
+                }),
+            )?;
+        }
+        my_run_tran_drop(
+            tran,
+            myupdate!("ord_group_member", m.id, {
+                "status": GroupMemberStatus::Refunded as u8,
+            }),
+        )?;
+        let pay_type: PayType = m.pay_type.unwrap_or_default().into();
+        if pay_type == PayType::WxPay {
+            let Some(transaction_id) = m.transaction_id else {
+                return Err(error::ErrorInternalServerError(format!(
+                    "拼团订单 {} 缺少微信交易号",
+                    m.order_sn
+                )));
+            };
+            // 退款中，微信退款回调后改为已退款
+            upd_order_status(
+                tran,
+                &m.order_sn,
+                OrderPayStatus::Refunding,
+                None,
+                Some(reason.to_string()),
+            )?;
+            for item in &items {
+                upd_order_item_status(tran, &item.order_item_id, OrderItemStatus::Refunding)?;
+            }
+            wx.refunds.push(GroupWxRefund {
+                out_refund_no: group_refund_no(&m.order_sn),
+                order_sn: m.order_sn,
+                transaction_id,
+                amount: m.pay_amount,
+            });
+        } else {
+            let info = serde_json::json!({ "order_sn": &m.order_sn, "reason": reason });
+            pocket_money_add(
+                tran,
+                m.uid,
+                m.pay_amount,
+                TranType::Refund,
+                PayType::PocketPay,
+                Some(&info.to_string()),
+            )?;
+            upd_order_status(
+                tran,
+                &m.order_sn,
+                OrderPayStatus::Refund,
+                None,
+                Some(reason.to_string()),
+            )?;
+            for item in &items {
+                upd_order_item_status(tran, &item.order_item_id, OrderItemStatus::Refund)?;
+            }
+        }
+    }
+    Ok(wx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_group_can_join() {
+        let now = "2025-01-01 10:00:00";
+        let mut group = GroupGet {
+            id: 1,
+            group_buy_id: 1,
+            group_size: 3,
+            joined: 2,
+            paid: 1,
+            status: GroupStatus::Grouping as i8,
+            expire_time: Some("2025-01-01 12:00:00".to_string()),
+        };
+        assert_eq!(can_join(&group, now), Ok(()));
+        group.joined = 3;
+        assert_eq!(can_join(&group, now), Err("该团已满员"));
+        group.joined = 2;
+        group.expire_time = Some("2025-01-01 09:00:00".to_string());
+        assert_eq!(can_join(&group, now), Err("该团已结束"));
+        group.expire_time = None;
+        group.status = GroupStatus::Opening as i8;
+        assert_eq!(can_join(&group, now), Err("该团已结束"));
+    }
+
+    #[test]
+    fn test_group_codes() {
+        let code = new_share_code();
+        assert_eq!(code.len(), 12);
+        assert_ne!(code, new_share_code());
+        assert_eq!(group_refund_no("123"), "GB123");
+    }
+}
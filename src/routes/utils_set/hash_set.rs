@@ -1,4 +1,4 @@
-use crate::common::LocalKeySeed;
+use crate::common::{LocalKeySeed, Money};
 use crate::utils::crypto::{aes_256_decrypt, aes_256_encrypt};
 use actix_web::Error;
 
@@ -18,17 +18,23 @@ pub fn hash_user_verify(hash: &str, uid: u64, openid: &str) -> anyhow::Result<bo
     }
 }
 
-/// 用户交易记录的加密
+/// 用户交易记录的加密。
+///
+/// 金额使用 `Money::to_plain_string`，与以前 f64 的显示格式一致，已有的 hash 仍能通过校验
 pub fn hash_user_tran(
     uid: u64,
-    tran_amount: f64,
+    tran_amount: Money,
     tran_type: &str,
     pay_type: &str,
     time: i64,
 ) -> anyhow::Result<String, Error> {
     let info = format!(
         "{}_{}_{}_{}_{}",
-        uid, tran_amount, tran_type, pay_type, time
+        uid,
+        tran_amount.to_plain_string(),
+        tran_type,
+        pay_type,
+        time
     );
     Ok(aes_256_encrypt(&info, LocalKeySeed::UserTranRecord)?)
 }
@@ -38,35 +44,48 @@ pub fn hash_user_tran(
 pub fn hash_user_tran_verify(
     hash: &str,
     uid: u64,
-    tran_amount: f64,
+    tran_amount: Money,
     tran_type: &str,
     pay_type: &str,
     time: i64,
 ) -> anyhow::Result<bool, Error> {
-    let info = format!(
-        "{}_{}_{}_{}_{}",
-        uid, tran_amount, tran_type, pay_type, time
+    Ok(hash_user_tran_time(hash, uid, tran_amount, tran_type, pay_type)? == Some(time))
+}
+
+/// 交易记录的 hash 中，用户、金额、交易类型、支付方式都一致时，返回 hash 时的时间戳；
+/// 有不一致的返回 None
+pub fn hash_user_tran_time(
+    hash: &str,
+    uid: u64,
+    tran_amount: Money,
+    tran_type: &str,
+    pay_type: &str,
+) -> anyhow::Result<Option<i64>, Error> {
+    let prefix = format!(
+        "{}_{}_{}_{}_",
+        uid,
+        tran_amount.to_plain_string(),
+        tran_type,
+        pay_type
     );
-    let decrypted_info = aes_256_decrypt(&hash, LocalKeySeed::UserTranRecord)?;
-    if decrypted_info == info {
-        Ok(true)
-    } else {
-        Ok(false)
-    }
+    let decrypted_info = aes_256_decrypt(hash, LocalKeySeed::UserTranRecord)?;
+    Ok(decrypted_info
+        .strip_prefix(&prefix)
+        .and_then(|t| t.parse::<i64>().ok()))
 }
 
 /// 用户零钱的加密
-pub fn hash_user_pocket_money(uid: u64, amount: f64) -> anyhow::Result<String, Error> {
-    let info = format!("{}_{}", uid, amount);
+pub fn hash_user_pocket_money(uid: u64, amount: Money) -> anyhow::Result<String, Error> {
+    let info = format!("{}_{}", uid, amount.to_plain_string());
     Ok(aes_256_encrypt(&info, LocalKeySeed::UserPocketMoney)?)
 }
 /// 用户零钱的检验
 pub fn hash_user_pocket_money_verify(
     hash: &str,
     uid: u64,
-    amount: f64,
+    amount: Money,
 ) -> anyhow::Result<bool, Error> {
-    let info = format!("{}_{}", uid, amount);
+    let info = format!("{}_{}", uid, amount.to_plain_string());
     let decrypted_info = aes_256_decrypt(&hash, LocalKeySeed::UserPocketMoney)?;
     if decrypted_info == info {
         Ok(true)
@@ -78,22 +97,34 @@ pub fn hash_user_pocket_money_verify(
 /// 用户提现的加密
 pub fn hash_user_withdrawal_money(
     uid: u64,
-    req_amount: f64,
+    req_amount: Money,
     out_bill_no: &str,
     status: u8,
 ) -> anyhow::Result<String, Error> {
-    let info = format!("{}_{}_{}_{}", uid, req_amount, out_bill_no, status);
+    let info = format!(
+        "{}_{}_{}_{}",
+        uid,
+        req_amount.to_plain_string(),
+        out_bill_no,
+        status
+    );
     Ok(aes_256_encrypt(&info, LocalKeySeed::UserWithdrawalMoney)?)
 }
 /// 用户提现的检验
 pub fn hash_user_withdrawal_money_verify(
     hash: &str,
     uid: u64,
-    req_amount: f64,
+    req_amount: Money,
     out_bill_no: &str,
     status: u8,
 ) -> anyhow::Result<bool, Error> {
-    let info = format!("{}_{}_{}_{}", uid, req_amount, out_bill_no, status);
+    let info = format!(
+        "{}_{}_{}_{}",
+        uid,
+        req_amount.to_plain_string(),
+        out_bill_no,
+        status
+    );
     let decrypted_info = aes_256_decrypt(&hash, LocalKeySeed::UserWithdrawalMoney)?;
     if decrypted_info == info {
         Ok(true)
@@ -109,7 +140,7 @@ mod tests {
     #[test]
     fn test_hash_user_tran() {
         let uid = 12345;
-        let tran_amount = 1000.;
+        let tran_amount = Money::from_cent(100000);
         let tran_type = "transfer";
         let pay_type = "alipay";
         let time = 1672531201;
@@ -117,17 +148,21 @@ mod tests {
         let hash = hash_user_tran(uid, tran_amount, tran_type, pay_type, time).unwrap();
         println!("Hashed transaction: {}", hash);
         let is_pass =
-            hash_user_tran_verify(&hash, uid, -1000., tran_type, pay_type, 1672531201).unwrap();
+            hash_user_tran_verify(&hash, uid, -tran_amount, tran_type, pay_type, 1672531201)
+                .unwrap();
         println!("is_pass: {}", is_pass);
 
-        println!("xxxx: {}", hash_user_pocket_money(153, 500.).unwrap());
+        println!(
+            "xxxx: {}",
+            hash_user_pocket_money(153, Money::from_cent(50000)).unwrap()
+        );
 
         println!(
             "cccc: {}",
             hash_user_tran_verify(
                 "eZr6On5O_nioZWd9Fkqr2HjIRY5wyS18ROY5Gday9rBVj3AG71x-QJDbPA0gMPHk",
                 100,
-                -298.0,
+                Money::from_cent(-29800),
                 "PURCHASE",
                 "POCKET_PAY",
                 1752047089,
@@ -139,4 +174,36 @@ mod tests {
             hash_user(153, "o9PJGvksyLZCNIXe8Vxeche2fgi8").unwrap()
         );
     }
+
+    #[test]
+    fn test_hash_user_tran_time() {
+        let amount = Money::from_cent(-29800);
+        let hash = hash_user_tran(100, amount, "PURCHASE", "POCKET_PAY", 1752047089).unwrap();
+        let time = |uid, amount, tran_type, pay_type| {
+            hash_user_tran_time(&hash, uid, amount, tran_type, pay_type).unwrap()
+        };
+        assert_eq!(
+            time(100, amount, "PURCHASE", "POCKET_PAY"),
+            Some(1752047089)
+        );
+        assert!(
+            hash_user_tran_verify(&hash, 100, amount, "PURCHASE", "POCKET_PAY", 1752047089)
+                .unwrap()
+        );
+        // 用户、金额、类型有一项不一致的都不通过
+        assert_eq!(time(101, amount, "PURCHASE", "POCKET_PAY"), None);
+        assert_eq!(time(100, -amount, "PURCHASE", "POCKET_PAY"), None);
+        assert_eq!(time(100, amount, "RECHARGE", "POCKET_PAY"), None);
+        assert_eq!(time(100, amount, "PURCHASE", "POCKET"), None);
+    }
+
+    #[test]
+    fn test_hash_pocket_money_compat() {
+        // 以前 f64 金额 12.5 的 hash 内容为 "153_12.5"
+        let hash = aes_256_encrypt("153_12.5", LocalKeySeed::UserPocketMoney).unwrap();
+        let amount: Money = "12.50".parse().unwrap();
+        assert!(hash_user_pocket_money_verify(&hash, 153, amount).unwrap());
+        let hash = hash_user_pocket_money(153, Money::from_cent(30)).unwrap();
+        assert!(hash_user_pocket_money_verify(&hash, 153, "0.3".parse().unwrap()).unwrap());
+    }
 }
@@ -1,36 +1,49 @@
 use actix_web::{Error, error, web::Data};
+use chrono::{Duration, Local, SecondsFormat};
 use mysql_quick::{
     MY_EXCLUSIVE_LOCK, PooledConn, Transaction, myfind, myget, myset, mysetmany, myupdate,
     myupdatemany,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use wx_pay::TradeState;
+use wx_pay::decode::{WxPayResource, WxPayResourceAmount};
 
 use crate::MakePay;
 use crate::common::types::{
-    DeliveryType, NormalStatus, OrderItemStatus, OrderPayStatus, OssBucket, PayType,
-    ShopCartStatus, UserCouponStatus, WriteOffStatus,
+    DeliveryType, OrderItemStatus, OrderPayStatus, OssBucket, PayType, ShopCartStatus,
+    UserCouponStatus, WriteOffStatus,
 };
+use crate::common::{Money, config};
 use crate::control::app_data::{AppData, SlownWorker};
-use crate::db::{my_run_tran_drop, my_run_tran_vec, my_run_vec};
+use crate::control::payment::{RefundReq, payment};
+use crate::control::seckill::seckill_release;
+use crate::db::{my_exec_tran_drop, my_run_tran_drop, my_run_tran_vec, my_run_vec};
+use crate::routes::utils_set::coupon_set::{
+    CouponOption, CouponSelect, get_coupon_items, get_user_coupons,
+};
+use crate::routes::utils_set::discount_set::{
+    OrderDiscount, get_full_reductions, get_order_coupon_ids, plan_discounts, save_order_discounts,
+};
+use crate::routes::utils_set::freight_set::{
+    FreightItem, StoreFreight, calc_freights, get_freight_templates, get_store_freight_template_ids,
+};
+use crate::routes::utils_set::group_set::cancel_group_member;
+use crate::utils::time::{NowTimeType, get_now_time};
 use crate::utils::utils::log_err;
 use crate::{
     routes::{Res, UnitAttrInfo},
-    utils::{
-        files::{get_file_url, get_path_from_url},
-        time::{NowTimeType, get_now_time},
-        utils::keep_decimal,
-    },
+    utils::files::{get_file_url, get_path_from_url},
 };
 
-/// 添加商品到购物车
+/// 添加商品到购物车，成功时返回购物车记录的id
 pub fn add_unit_to_shop_cart(
     tran: &mut Transaction,
     uid: u64,
     unit_sn: u32,
     buy_quantity: u32,
     shop_cart_status: ShopCartStatus,
-) -> Result<Res<String>, Error> {
+) -> Result<Res<u64>, Error> {
     #[derive(Deserialize)]
     struct ShopCartGet {
         id: u64,
@@ -139,9 +152,218 @@ pub fn add_unit_to_shop_cart(
             "status": shop_cart_status as u8,
         });
     }
-    my_run_tran_drop(tran, sql)?;
+    let insert_id = my_run_tran_drop(tran, sql)?;
+    let cart_id = have_unit.first().map_or(insert_id, |c| c.id);
+
+    Ok(Res::success(cart_id))
+}
+
+/// 秒杀抢购成功后，添加到立即购买，按秒杀价下单。
+///
+/// 秒杀库存已在 redis 中抢占，这里不锁商品行，只按条件扣减商品库存
+pub fn add_seckill_to_buy_now(
+    tran: &mut Transaction,
+    uid: u64,
+    sec_kill_id: u32,
+    unit_sn: u32,
+    buy_quantity: u32,
+) -> Result<Res<String>, Error> {
+    #[derive(Serialize, Deserialize)]
+    struct UnitAttrGet {
+        primary_name: String,
+        secondary_name: String,
+    }
+    #[derive(Deserialize)]
+    struct UnitInfoGet {
+        unit_name: String,
+        unit_cover: Option<String>,
+        product_name: String,
+        status: u8,
+        product_status: u8,
+    }
+    let unit_info: Vec<UnitInfoGet> = my_run_tran_vec(
+        tran,
+        myfind!("sku_unit", {
+            j0: ["product_sn", "inner", "spu_product.product_sn"],
+            p0: ["unit_sn", "=", unit_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "unit_cover,unit_name,spu_product.product_name,status,spu_product.status as product_status",
+        }),
+    )?;
+    if unit_info.is_empty() {
+        return Ok(Res::fail("商品不存在"));
+    }
+    if unit_info[0].product_status != 2 {
+        return Ok(Res::fail("产品已下架"));
+    }
+    if unit_info[0].status != 2 {
+        return Ok(Res::fail("商品已下架"));
+    }
+    let unit_attrs: Vec<UnitAttrGet> = my_run_tran_vec(
+        tran,
+        myfind!("sku_unit_attr", {
+            p0: ["unit_sn", "=", unit_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "primary_name,secondary_name",
+        }),
+    )?;
+    let attr_json_str = serde_json::to_string(&unit_attrs)
+        .map_err(|e| error::ErrorInternalServerError(log_err(&e, "")))?;
 
-    Ok(Res::success("添加购物车成功".to_string()))
+    // 商品库存不够时，不扣减
+    let affected = my_exec_tran_drop(
+        tran,
+        "update sku_unit set quantity = quantity - ? where unit_sn = ? and quantity >= ?",
+        (buy_quantity, unit_sn, buy_quantity),
+    )?;
+    if affected == 0 {
+        return Ok(Res::fail("库存不足"));
+    }
+    my_exec_tran_drop(
+        tran,
+        "update pmt_sec_kill set sold = sold + ? where id = ?",
+        (buy_quantity, sec_kill_id),
+    )?;
+    my_run_tran_drop(
+        tran,
+        myset!("ord_shop_cart", {
+            "uid": uid,
+            "unit_sn": unit_sn,
+            "unit_name": &unit_info[0].unit_name,
+            "buy_quantity": buy_quantity,
+            "unit_cover": if let Some(c) = &unit_info[0].unit_cover { c } else { "null" },
+            "product_name": &unit_info[0].product_name,
+            "unit_attr_info": &attr_json_str,
+            "status": ShopCartStatus::BuyNow as u8,
+            "sec_kill_id": sec_kill_id,
+        }),
+    )?;
+
+    Ok(Res::success("抢购成功".to_string()))
+}
+
+/// 修改购物车里某一条商品的数量，并按差值 占用/归还 库存
+pub fn set_shop_cart_quantity(
+    tran: &mut Transaction,
+    uid: u64,
+    cart_id: u64,
+    buy_quantity: u32,
+) -> Result<Res<String>, Error> {
+    #[derive(Deserialize)]
+    struct CartGet {
+        unit_sn: u32,
+        buy_quantity: u32,
+    }
+    #[derive(Deserialize)]
+    struct UnitGet {
+        quantity: i64,
+        status: u8,
+        product_status: u8,
+    }
+    let cart: Vec<CartGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_shop_cart", {
+            p0: ["id", "=", cart_id],
+            p1: ["uid", "=", uid],
+            p2: ["is_del", "=", 0],
+            p3: ["status", "=", ShopCartStatus::PendingPayment as u8],
+            r: "p0 && p1 && p2 && p3",
+            select: "unit_sn,buy_quantity",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    if cart.is_empty() {
+        return Ok(Res::fail("购物车商品不存在"));
+    }
+    let unit_sn = cart[0].unit_sn;
+    let diff = buy_quantity as i64 - cart[0].buy_quantity as i64;
+    if diff == 0 {
+        return Ok(Res::success("修改成功".to_string()));
+    }
+    if diff > 0 {
+        // 增加数量时，需要商品在售，且库存足够
+        let unit: Vec<UnitGet> = my_run_tran_vec(
+            tran,
+            myfind!("sku_unit", {
+                j0: ["product_sn", "inner", "spu_product.product_sn"],
+                p0: ["unit_sn", "=", unit_sn],
+                p1: ["is_del", "=", 0],
+                r: "p0 && p1",
+                select: "quantity,status,spu_product.status as product_status",
+            }) + MY_EXCLUSIVE_LOCK,
+        )?;
+        if unit.is_empty() {
+            return Ok(Res::fail("商品不存在"));
+        }
+        if unit[0].product_status != 2 {
+            return Ok(Res::fail("产品已下架"));
+        }
+        if unit[0].status != 2 {
+            return Ok(Res::fail("商品已下架"));
+        }
+        if unit[0].quantity < diff {
+            return Ok(Res::fail("库存不足"));
+        }
+    }
+    // 购物车多占用的库存减去，少占用的归还
+    my_run_tran_drop(
+        tran,
+        myupdate!("sku_unit", {"unit_sn": unit_sn}, {
+            "quantity": ["incr", -diff],
+        }),
+    )?;
+    my_run_tran_drop(
+        tran,
+        myupdate!("ord_shop_cart", cart_id, { "buy_quantity": buy_quantity }),
+    )?;
+    Ok(Res::success("修改成功".to_string()))
+}
+
+/// 删除购物车里的商品，并归还占用的库存。`cart_ids` 为 None 时，清空购物车
+///
+/// 返回删除的条数
+pub fn del_shop_cart_units(
+    tran: &mut Transaction,
+    uid: u64,
+    cart_ids: Option<&[u64]>,
+) -> Result<usize, Error> {
+    #[derive(Deserialize)]
+    struct CartGet {
+        id: u64,
+        unit_sn: u32,
+        buy_quantity: u32,
+    }
+    let ids = cart_ids
+        .unwrap_or_default()
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    if cart_ids.is_some() && ids.is_empty() {
+        return Ok(0);
+    }
+    let list: Vec<CartGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_shop_cart", {
+            p0: ["uid", "=", uid],
+            p1: ["is_del", "=", 0],
+            p2: ["status", "=", ShopCartStatus::PendingPayment as u8],
+            p3: ["id", "in", ids],
+            r: if cart_ids.is_some() { "p0 && p1 && p2 && p3" } else { "p0 && p1 && p2" },
+            select: "id,unit_sn,buy_quantity",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    for c in list.iter() {
+        my_run_tran_drop(
+            tran,
+            myupdate!("sku_unit", {"unit_sn": c.unit_sn}, {
+                "quantity": ["incr", c.buy_quantity],
+            }),
+        )?;
+        my_run_tran_drop(tran, myupdate!("ord_shop_cart", c.id, { "is_del": 1 }))?;
+    }
+    Ok(list.len())
 }
 
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
@@ -153,7 +375,7 @@ pub struct UserBuy {
     /// 商品封面图
     pub unit_cover: String,
     /// 价格
-    pub price: f64,
+    pub price: Money,
     /// 商品名
     pub unit_name: Option<String>,
     /// 产品编号
@@ -170,34 +392,55 @@ pub struct UserBuy {
     pub unit_attr_info: Vec<UnitAttrInfo>,
     /// 当前产品，支持的物流方式
     pub support_delivery: Vec<DeliveryType>,
+    /// 秒杀活动id，按秒杀价购买时才有
+    pub sec_kill_id: Option<u32>,
+    /// 拼团活动id，按拼团价购买时才有
+    pub group_buy_id: Option<u32>,
+    /// 参加的团，开团时为空
+    pub group_id: Option<u64>,
+    /// 分摊到的优惠,(元)
+    pub reduce_amount: Money,
+    /// 分摊优惠后的实付,(元)
+    pub pay_amount: Money,
 }
 #[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
 pub struct PrePareRes {
     /// 合计多少件
     pub total_quantity: u32,
     /// 合计多少钱,(元)
-    pub total_amount: f64,
+    pub total_amount: Money,
     /// 优惠多少钱,(元)
-    pub reduce_amount: f64,
+    pub reduce_amount: Money,
     /// 优惠描述信息
     pub reduce_des: Vec<String>,
-    /// 实际多少钱,(元)
-    pub pay_amount: f64,
+    /// 运费,(元)
+    pub delivery_amount: Money,
+    /// 每个店铺的运费，不需要物流时为空
+    pub freights: Vec<StoreFreight>,
+    /// 实际多少钱，含运费,(元)
+    pub pay_amount: Money,
     /// 用户购买的哪些商品
     pub user_buy: Vec<UserBuy>,
     /// 优惠券，是否已使用
     pub is_coupon_used: bool,
-    /// 用户的优惠券编号id
-    pub usr_coupon_id: Option<u64>,
+    /// 使用的用户的优惠券编号id
+    pub usr_coupon_ids: Vec<u64>,
+    /// 使用的优惠券id，去支付时传入
+    pub coupon_ids: Vec<u32>,
+    /// 使用的每一项优惠，按 满减活动、店铺券、平台券 的顺序
+    pub discounts: Vec<OrderDiscount>,
+    /// 用户未使用的优惠券，及对当前商品是否可用
+    pub coupon_list: Vec<CouponOption>,
 }
-/// 获取预览订单
+/// 获取预览订单。ship_to 为收货地址，按地址计算运费，不需要物流的为 None
 pub fn get_order_prepare(
     tran: &mut Transaction,
     uid: u64,
     unit_sns: &Vec<u32>,
     shop_cart_status: &ShopCartStatus,
-    coupon_id: Option<u32>,
+    coupon: CouponSelect,
     is_lock: bool,
+    ship_to: Option<&AddressGet>,
 ) -> Result<PrePareRes, Error> {
     let lock = if is_lock { MY_EXCLUSIVE_LOCK } else { "" };
 
@@ -212,7 +455,7 @@ pub fn get_order_prepare(
         id: u64,
         unit_sn: u32,
         unit_cover: Option<String>,
-        price: String,
+        price: Money,
         unit_name: Option<String>,
         product_sn: u32,
         product_name: String,
@@ -221,26 +464,40 @@ pub fn get_order_prepare(
         buy_quantity: u32,
         unit_attr_info: Option<String>,
         delivery_type: String,
+        sec_kill_id: Option<u32>,
+        sec_kill_price: Option<Money>,
+        group_buy_id: Option<u32>,
+        group_id: Option<u64>,
+        group_buy_price: Option<Money>,
+        weight: u32,
+        freight_template_id: Option<u32>,
     }
     let user_shop_unit: Vec<UserBuyGet> = my_run_tran_vec(
         tran,
         myfind!("ord_shop_cart", {
             j0: ["unit_sn", "inner", "sku_unit.unit_sn"],
             j1: ["sku_unit.product_sn", "inner", "spu_product.product_sn"],
+            j2: ["sec_kill_id", "left", "pmt_sec_kill.id"],
+            j3: ["group_buy_id", "left", "pmt_group_buy.id"],
             p0: ["uid", "=", uid],
             p1: ["status", "=", shop_cart_status.clone() as i8],
             p2: ["unit_sn", "in", unit_info],
             p3: ["unit_sn", "=", unit_sns[0]],
             p4: ["is_del", "=", 0],
+            p5: ["is_selected", "=", 1],
+            // 购物车结算只取选中的商品，未选中的留在购物车
             r: if shop_cart_status == &ShopCartStatus::BuyNow {
                 "p0 && p1 && p3 && p4"
             } else {
-                "p0 && p1 && p2 && p4"
+                "p0 && p1 && p2 && p4 && p5"
             },
             order_by: "-created_at",
             select: "id, unit_sn, unit_cover, unit_name, sku_unit.price,
                     spu_product.store_code,spu_product.brand_code, spu_product.delivery_type,
-                    sku_unit.product_sn, product_name, buy_quantity, unit_attr_info",
+                    sku_unit.product_sn, product_name, buy_quantity, unit_attr_info,
+                    sec_kill_id, pmt_sec_kill.price as sec_kill_price,
+                    group_buy_id, group_id, pmt_group_buy.price as group_buy_price,
+                    sku_unit.weight, spu_product.freight_template_id",
         }) + lock,
     )?;
     // 购物车里没有相关信息
@@ -255,14 +512,20 @@ pub fn get_order_prepare(
     } else {
         calc_user_shop_unit = user_shop_unit;
     }
+    // 每种商品的 (运费模板, 单件重量)
+    let freight_info: Vec<(Option<u32>, u32)> = calc_user_shop_unit
+        .iter()
+        .map(|x| (x.freight_template_id, x.weight))
+        .collect();
 
-    let user_shop_unit: Vec<UserBuy> = calc_user_shop_unit
+    let mut user_shop_unit: Vec<UserBuy> = calc_user_shop_unit
         .into_iter()
         .map(|x| UserBuy {
             id: x.id,
             unit_sn: x.unit_sn,
             unit_cover: get_file_url(x.unit_cover).unwrap_or("".to_string()),
-            price: x.price.parse::<f64>().unwrap(),
+            // 秒杀抢购的按秒杀价，拼团的按拼团价
+            price: x.sec_kill_price.or(x.group_buy_price).unwrap_or(x.price),
             product_name: x.product_name,
             product_sn: x.product_sn,
             unit_name: x.unit_name,
@@ -279,410 +542,106 @@ pub fn get_order_prepare(
                 .split(",")
                 .map(|x| x.into())
                 .collect::<Vec<DeliveryType>>(),
+            sec_kill_id: x.sec_kill_id,
+            group_buy_id: x.group_buy_id,
+            group_id: x.group_id,
+            reduce_amount: Money::ZERO,
+            pay_amount: Money::ZERO,
         })
         .collect();
 
     // 计算总件数，和总价格
-    let mut total_price = 0.;
+    let mut total_price = Money::ZERO;
     let mut total_count = 0;
 
     for i in 0..user_shop_unit.len() {
         total_count = total_count + user_shop_unit[i].buy_quantity;
-        total_price =
-            total_price + ((user_shop_unit[i].buy_quantity as f64) * user_shop_unit[i].price);
-    }
-
-    let mut pay_price = total_price;
-    let mut reduce_price = 0.;
-    let mut reduce_des = vec![];
-    let mut coupon_used = false;
-    let mut usr_coupon_id = None;
-
-    // 获取用户的优惠券信息
-    if let Some(cou_id) = coupon_id {
-        let coupon_reduce_info =
-            calc_user_coupon_data(tran, uid, &user_shop_unit, cou_id, is_lock)?;
-
-        usr_coupon_id = coupon_reduce_info.usr_coupon_id;
-
-        if coupon_reduce_info.total_for_reduce > 0. {
-            // 有用于优惠的金额
-            if let Some(d) = coupon_reduce_info.c_reduce_amount {
-                let reduce_am = d.parse::<f64>().unwrap();
-                if coupon_reduce_info.total_for_reduce - reduce_am > 0. {
-                    reduce_price = reduce_am;
-                    pay_price = total_price - reduce_am;
-                    reduce_des = coupon_reduce_info.reduce_des.clone();
-                    coupon_used = true;
-                }
-            }
-            if let Some(d) = coupon_reduce_info.c_discount {
-                let discount = d.parse::<f64>().unwrap();
-                if discount > 0. && discount < 1. {
-                    reduce_price = coupon_reduce_info.total_for_reduce
-                        - coupon_reduce_info.total_for_reduce * discount;
-                    pay_price = total_price - reduce_price;
-                    reduce_des = coupon_reduce_info.reduce_des;
-                    coupon_used = true;
-                }
-            }
-        }
+        total_price += user_shop_unit[i].price * user_shop_unit[i].buy_quantity;
     }
 
-    Ok(PrePareRes {
-        total_amount: keep_decimal(total_price),
-        total_quantity: total_count,
-        reduce_des,
-        reduce_amount: keep_decimal(reduce_price),
-        pay_amount: keep_decimal(pay_price),
-        user_buy: user_shop_unit,
-        is_coupon_used: coupon_used,
-        usr_coupon_id,
-    })
-}
-
-#[derive(Serialize, Debug, Deserialize, Clone)]
-pub struct UserCouponData {
-    /// 用户的优惠券编号id
-    pub usr_coupon_id: Option<u64>,
-    /// 总共可用于减的金额
-    pub total_for_reduce: f64,
-    /// 优惠券的描述
-    pub reduce_des: Vec<String>,
-    /// 优惠券，可减的金额
-    pub c_reduce_amount: Option<String>,
-    /// 优惠券，可打折的
-    pub c_discount: Option<String>,
-}
-/// 获取用户的优惠结果
-pub fn calc_user_coupon_data(
-    tran: &mut Transaction,
-    uid: u64,
-    user_buy: &Vec<UserBuy>,
-    coupon_id: u32,
-    is_lock: bool,
-) -> Result<UserCouponData, Error> {
-    let lock = if is_lock { MY_EXCLUSIVE_LOCK } else { "" };
-
-    // 优惠券，信息
-    let mut reduce_info = UserCouponData {
-        usr_coupon_id: None,
-        total_for_reduce: 0.,
-        reduce_des: vec![],
-        c_reduce_amount: None,
-        c_discount: None,
-    };
-
-    #[derive(Serialize, Debug, Deserialize, Clone)]
-    struct UserCouponGet {
-        id: u64,
-        coupon_id: u32,
-        coupon_name: String,
-        reduce_amount: Option<String>,
-        discount: Option<String>,
-        expire_time: Option<String>,
-        status: i8,
-        is_del: i8,
-        cc_id: u32,
-        cc_title: String,
-        cc_full_amount: Option<String>,
-        cc_store_code: Option<u32>,
-        cc_brand_code: Option<u32>,
-        cc_product_cat: Option<String>,
-        cc_product_sn: Option<u32>,
-        cc_unit_sn: Option<u32>,
-    }
-    let user_coupons: Vec<UserCouponGet> = my_run_tran_vec(
-        tran,
-        myfind!("usr_coupon", {
-            j0: ["coupon_id", "inner", "pmt_coupon.id"],
-            j1: ["pmt_coupon.coupon_condition_id", "inner", "pmt_coupon_condition.id"],
-            p0: ["uid", "=", uid],
-            p1: ["status", "=", UserCouponStatus::NotUsed as i8],
-            p2: ["coupon_id", "=", coupon_id],
-            r: "p0 && p1 && p2",
-            select: r#"
-                id,pmt_coupon.id as coupon_id,pmt_coupon.coupon_name,pmt_coupon.reduce_amount,pmt_coupon.discount,
-                pmt_coupon.expire_time,pmt_coupon.status,pmt_coupon.is_del,pmt_coupon_condition.id as cc_id,
-                pmt_coupon_condition.title as cc_title,pmt_coupon_condition.full_amount as cc_full_amount,
-                pmt_coupon_condition.store_code as cc_store_code,pmt_coupon_condition.brand_code as cc_brand_code,
-                pmt_coupon_condition.product_cat as cc_product_cat,pmt_coupon_condition.product_sn as cc_product_sn,
-                pmt_coupon_condition.unit_sn as cc_unit_sn
-            "#,
-        }) + lock,
-    )?;
-
-    if user_coupons.len() == 0 {
-        return Err(error::ErrorBadRequest("没有找到对应可用优惠券"));
-    }
-    if user_coupons[0].is_del == 1 {
-        return Err(error::ErrorBadRequest("没有找到对应可用优惠券"));
-    }
-    if let Some(time) = user_coupons[0].expire_time.clone() {
-        if time <= get_now_time(NowTimeType::DateTime) {
-            return Err(error::ErrorBadRequest("优惠券已过期"));
-        }
-    }
-    if user_coupons[0].status != NormalStatus::Online as i8 {
-        return Err(error::ErrorBadRequest("优惠券已下架"));
-    }
-
-    reduce_info.usr_coupon_id = Some(user_coupons[0].id);
-    reduce_info.c_reduce_amount = user_coupons[0].reduce_amount.clone();
-    reduce_info.c_discount = user_coupons[0].discount.clone();
-    // 是否有满减条件，没有，则为0
-    let full_amount: f64;
-    if let Some(f) = &user_coupons[0].cc_full_amount {
-        full_amount = f.parse().unwrap();
-    } else {
-        full_amount = 0.;
-    }
-
-    // 是否有指定店铺，没有则为0
-    let store_code: u32;
-    if let Some(c) = &user_coupons[0].cc_store_code {
-        store_code = c.to_owned();
-    } else {
-        store_code = 0;
-    }
-
-    // 是否有指定品牌，没有则为0
-    let brand_code: u32;
-    if let Some(c) = &user_coupons[0].cc_brand_code {
-        brand_code = c.to_owned();
+    // 满减活动、店铺券、平台券，按顺序使用，并分摊到每种商品
+    let items = get_coupon_items(tran, &user_shop_unit)?;
+    let reductions = get_full_reductions(tran)?;
+    let coupons = if coupon == CouponSelect::Skip {
+        vec![]
     } else {
-        brand_code = 0;
-    }
-
-    // 是指定商品优惠券, 则就不用看 product,cat,brand 了
-    if let Some(unit_sn) = user_coupons[0].cc_unit_sn {
-        let mut am: f64 = 0.; //可用于优惠券的，合计价格。
-        for i in 0..user_buy.len() {
-            if user_buy[i].unit_sn == unit_sn {
-                if store_code > 0 {
-                    // 指定了店铺，则要该店铺的
-                    if user_buy[i].store_code == user_coupons[0].cc_store_code {
-                        am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                    }
-                } else {
-                    am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                }
-            }
-        }
-        if am >= full_amount {
-            // 满足满减条件
-            reduce_info.total_for_reduce = am;
-        }
-        reduce_info.reduce_des.push(format!(
-            "{}{}",
-            user_coupons[0].cc_title, user_coupons[0].coupon_name
-        ));
-        return Ok(reduce_info);
-    }
-
-    // 是指定产品优惠券，则不用看  ,cat,brand
-    if let Some(product_sn) = user_coupons[0].cc_product_sn {
-        let mut am: f64 = 0.; // 的合计价格。
-        for i in 0..user_buy.len() {
-            if user_buy[i].product_sn == product_sn {
-                if store_code > 0 {
-                    // 指定了店铺，则要该店铺的
-                    if user_buy[i].store_code == user_coupons[0].cc_store_code {
-                        am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                    }
-                } else {
-                    am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                }
-            }
-        }
-        if am >= full_amount {
-            // 满足满减条件
-            reduce_info.total_for_reduce = am;
-        }
-        reduce_info.reduce_des.push(format!(
-            "{}{}",
-            user_coupons[0].cc_title, user_coupons[0].coupon_name
-        ));
-        return Ok(reduce_info);
+        get_user_coupons(tran, uid, is_lock)?
+    };
+    let now = get_now_time(NowTimeType::DateTime);
+    let (plan, coupon_evals) = plan_discounts(
+        &items,
+        &reductions,
+        &coupons,
+        &coupon,
+        &config().discount,
+        &now,
+    )
+    .map_err(error::ErrorBadRequest)?;
+    for (x, reduce) in user_shop_unit.iter_mut().zip(&plan.item_reduces) {
+        x.reduce_amount = *reduce;
+        x.pay_amount = x.price * x.buy_quantity - *reduce;
     }
+    let reduce_price = plan.reduce_amount();
 
-    // 是指定产品类别优惠券，
-    if let Some(product_cat) = user_coupons[0].cc_product_cat.clone() {
-        // 查寻所有产品的类别
-        #[allow(unused)]
-        #[derive(Deserialize, Debug)]
-        struct ProductCatItem {
-            product_sn: u32,
-            primary_id: u32,
-            secondary_id: u32,
-            tertiary_id: u32,
-        }
-        let p_sns = user_buy
-            .iter()
-            .map(|x| x.product_sn.to_string())
-            .collect::<Vec<String>>();
-        let cat_list: Vec<ProductCatItem> = my_run_tran_vec(
-            tran,
-            myfind!("spu_product_cat", {
-                p0: ["product_sn", "in", p_sns.join(",")],
-                p1: ["is_del", "=", 0],
-                r: "p0 && p1",
-            }),
-        )?;
-
-        // 优惠券指定的产品类别
-        let cp_p_cat = product_cat
-            .split(",")
-            .map(|x| x.parse::<u32>().unwrap())
-            .collect::<Vec<u32>>();
-        // 的合计价格。
-        let mut am: f64 = 0.;
-        for i in 0..user_buy.len() {
-            // 当前商品，对应的产品类别
-            let by_cat = cat_list
+    // 运费按店铺计算，包邮门槛按优惠后的实付
+    let freights = match ship_to {
+        Some(addr) => {
+            // 产品没有运费模板的用店铺的
+            let store_codes: Vec<u32> = user_shop_unit
                 .iter()
-                .find(|x| x.product_sn == user_buy[i].product_sn);
-            if let Some(cat) = by_cat {
-                // 当前商品的类别存在 且为3级
-                if cp_p_cat.len() == 3 {
-                    if cp_p_cat[0] == cat.primary_id
-                        && cp_p_cat[1] == cat.secondary_id
-                        && cp_p_cat[2] == cat.tertiary_id
-                    {
-                        if store_code > 0 && brand_code > 0 {
-                            // 指定了店铺 和 品牌，则要该店铺 同时和品牌的
-                            if user_buy[i].store_code == user_coupons[0].cc_store_code
-                                && user_buy[i].brand_code == user_coupons[0].cc_brand_code
-                            {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else if store_code > 0 {
-                            if user_buy[i].store_code == user_coupons[0].cc_store_code {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else if brand_code > 0 {
-                            if user_buy[i].brand_code == user_coupons[0].cc_brand_code {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else {
-                            am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                        }
-                    }
-                }
-                // 当前商品的类别存在 且为2级
-                if cp_p_cat.len() == 2 {
-                    if cp_p_cat[0] == cat.primary_id && cp_p_cat[1] == cat.secondary_id {
-                        if store_code > 0 && brand_code > 0 {
-                            // 指定了店铺 和 品牌，则要该店铺 同时和品牌的
-                            if user_buy[i].store_code == user_coupons[0].cc_store_code
-                                && user_buy[i].brand_code == user_coupons[0].cc_brand_code
-                            {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else if store_code > 0 {
-                            if user_buy[i].store_code == user_coupons[0].cc_store_code {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else if brand_code > 0 {
-                            if user_buy[i].brand_code == user_coupons[0].cc_brand_code {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else {
-                            am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                        }
-                    }
-                }
-                // 当前商品的类别存在 且为1级
-                if cp_p_cat.len() == 1 {
-                    if cp_p_cat[0] == cat.primary_id {
-                        if store_code > 0 && brand_code > 0 {
-                            // 指定了店铺 和 品牌，则要该店铺 同时和品牌的
-                            if user_buy[i].store_code == user_coupons[0].cc_store_code
-                                && user_buy[i].brand_code == user_coupons[0].cc_brand_code
-                            {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else if store_code > 0 {
-                            if user_buy[i].store_code == user_coupons[0].cc_store_code {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else if brand_code > 0 {
-                            if user_buy[i].brand_code == user_coupons[0].cc_brand_code {
-                                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                            }
-                        } else {
-                            am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                        }
-                    }
-                }
-            }
-        }
-        if am >= full_amount {
-            // 满足满减条件
-            reduce_info.total_for_reduce = am;
-        }
-        reduce_info.reduce_des.push(format!(
-            "{}{}",
-            user_coupons[0].cc_title, user_coupons[0].coupon_name
-        ));
-        return Ok(reduce_info);
-    }
-
-    // 是店铺 或 品牌 的优惠券
-    if store_code > 0 || brand_code > 0 {
-        // 的合计价格。
-        let mut am: f64 = 0.;
-        for i in 0..user_buy.len() {
-            if store_code > 0 && brand_code > 0 {
-                // 指定了店铺 和 品牌，则要该店铺 同时和品牌的
-                if user_buy[i].store_code == user_coupons[0].cc_store_code
-                    && user_buy[i].brand_code == user_coupons[0].cc_brand_code
-                {
-                    am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                }
-            } else if store_code > 0 {
-                if user_buy[i].store_code == user_coupons[0].cc_store_code {
-                    am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                }
-            } else if brand_code > 0 {
-                if user_buy[i].brand_code == user_coupons[0].cc_brand_code {
-                    am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-                }
-            } else {
-                am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-            }
-        }
-        if am >= full_amount {
-            // 满足满减条件
-            reduce_info.total_for_reduce = am;
+                .zip(&freight_info)
+                .filter(|(_, f)| f.0.is_none())
+                .filter_map(|(x, _)| x.store_code)
+                .collect();
+            let store_templates = get_store_freight_template_ids(tran, &store_codes)?;
+            let items: Vec<FreightItem> = user_shop_unit
+                .iter()
+                .zip(&freight_info)
+                .map(|(x, (template_id, weight))| FreightItem {
+                    store_code: x.store_code,
+                    template_id: template_id
+                        .or_else(|| x.store_code.and_then(|c| store_templates.get(&c).copied())),
+                    quantity: x.buy_quantity,
+                    weight: weight * x.buy_quantity,
+                    amount: x.pay_amount,
+                })
+                .collect();
+            let mut template_ids: Vec<u32> = items.iter().filter_map(|x| x.template_id).collect();
+            template_ids.sort();
+            template_ids.dedup();
+            let templates = get_freight_templates(tran, &template_ids)?;
+            calc_freights(
+                &items,
+                &templates,
+                addr.province.as_deref().unwrap_or_default(),
+                addr.city.as_deref().unwrap_or_default(),
+            )
         }
-        reduce_info.reduce_des.push(format!(
-            "{}{}",
-            user_coupons[0].cc_title, user_coupons[0].coupon_name
-        ));
-
-        return Ok(reduce_info);
-    }
+        None => vec![],
+    };
+    let delivery_price: Money = freights.iter().map(|x| x.amount).sum();
 
-    // 单纯的满减条件
-    if full_amount > 0. {
-        // 的合计价格。
-        let mut am: f64 = 0.;
-        for i in 0..user_buy.len() {
-            am += user_buy[i].price * (user_buy[i].buy_quantity as f64);
-        }
-        if am >= full_amount {
-            // 满足满减条件
-            reduce_info.total_for_reduce = am;
-        }
-        reduce_info.reduce_des.push(format!(
-            "{}{}",
-            user_coupons[0].cc_title, user_coupons[0].coupon_name
-        ));
-        return Ok(reduce_info);
-    }
+    let usr_coupon_ids: Vec<u64> = plan.coupons.iter().map(|c| c.0).collect();
+    let coupon_list = coupon_evals
+        .iter()
+        .map(|c| c.option(usr_coupon_ids.contains(&c.rule.usr_coupon_id)))
+        .collect();
 
-    Ok(reduce_info)
+    Ok(PrePareRes {
+        total_amount: total_price,
+        total_quantity: total_count,
+        reduce_des: plan.discounts.iter().map(|d| d.title.clone()).collect(),
+        reduce_amount: reduce_price,
+        delivery_amount: delivery_price,
+        freights,
+        pay_amount: total_price - reduce_price + delivery_price,
+        user_buy: user_shop_unit,
+        is_coupon_used: !usr_coupon_ids.is_empty(),
+        coupon_ids: plan.coupons.iter().map(|c| c.1).collect(),
+        usr_coupon_ids,
+        discounts: plan.discounts,
+        coupon_list,
+    })
 }
 
 /// 修改主订单的支付状态：2 已支付，1 待支付，0 取消支付,  4 为申请退款  5 为已退款  6 为退款中
@@ -770,6 +729,13 @@ pub struct OrderChange {
     /// 商品订单状态
     pub order_items: Vec<OrderChangeItems>,
 }
+/// 子订单改为这个状态时，是否要记完成时间。佣金的冻结期从完成时间开始
+fn is_item_complete(status: &OrderItemStatus) -> bool {
+    matches!(
+        status,
+        OrderItemStatus::Complete | OrderItemStatus::Evaluated
+    )
+}
 /// 修改子订单的物流状态
 /// 0 待发货，1 待收货, 2 已完成, 3 已评价，4 申请退货，5 已退货, 6 退款中
 pub fn upd_order_item_status(
@@ -781,6 +747,14 @@ pub fn upd_order_item_status(
         tran,
         myupdate!("ord_order_item", { "order_item_id": order_item_id }, {"status": status.clone() as u8}),
     )?;
+    if is_item_complete(&status) {
+        // 已有完成时间的（如评价前已完成）不再修改
+        my_exec_tran_drop(
+            tran,
+            "update ord_order_item set complete_time = ifnull(complete_time, ?) where order_item_id = ?",
+            (get_now_time(NowTimeType::DateTime), order_item_id),
+        )?;
+    }
 
     if status == OrderItemStatus::Apply
         || status == OrderItemStatus::Refund
@@ -824,21 +798,59 @@ pub struct AddressGet {
     pub contact_user: Option<String>,
     pub contact_phone: Option<String>,
 }
-/// 去支付，查找用户地址。根据物流类型，判断要不要用户地址
-pub fn get_user_address_or_none(
-    tran: &mut Transaction,
-    id: Option<u64>,
-    delivery_type: &DeliveryType,
-) -> Result<AddressGet, Error> {
-    let is_need_addr = match delivery_type {
+/// 物流类型是否需要收货地址，需要的按地址计算运费
+pub fn is_need_address(delivery_type: &DeliveryType) -> bool {
+    match delivery_type {
         &DeliveryType::DoDelivery => true,
         &DeliveryType::WxDelivery => true,
         &DeliveryType::WxInstant => true,
         &DeliveryType::NoDelivery => false,
         &DeliveryType::DoorPickup => false,
         &DeliveryType::StoreWriteOff => false,
+    }
+}
+
+/// 预览订单，计算运费的收货地址：指定的地址，没有指定时为默认地址，都没有时按运费模板的默认规则
+pub fn get_prepare_address(
+    tran: &mut Transaction,
+    uid: u64,
+    id: Option<u64>,
+) -> Result<AddressGet, Error> {
+    let sql_addr = match id {
+        Some(addr_id) => myfind!("usr_address", {
+            p0: ["id", "=", addr_id],
+            p1: ["uid", "=", uid],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "province,city,area,addr_detail,contact_user,contact_phone",
+        }),
+        None => myfind!("usr_address", {
+            p0: ["uid", "=", uid],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            limit: 1,
+            order_by: "-is_default,-id",
+            select: "province,city,area,addr_detail,contact_user,contact_phone",
+        }),
     };
-    if is_need_addr {
+    let user_addr: Vec<AddressGet> = my_run_tran_vec(tran, sql_addr)?;
+    Ok(user_addr.into_iter().next().unwrap_or(AddressGet {
+        province: None,
+        city: None,
+        area: None,
+        addr_detail: None,
+        contact_user: None,
+        contact_phone: None,
+    }))
+}
+
+/// 去支付，查找用户地址。根据物流类型，判断要不要用户地址
+pub fn get_user_address_or_none(
+    tran: &mut Transaction,
+    id: Option<u64>,
+    delivery_type: &DeliveryType,
+) -> Result<AddressGet, Error> {
+    if is_need_address(delivery_type) {
         if let Some(addr_id) = id {
             let sql_addr = myfind!("usr_address", {
                 p0: ["id", "=", addr_id],
@@ -879,14 +891,17 @@ pub fn create_order(
     pay_type: &PayType,
 ) -> Result<(String, String), Error> {
     let order_sn = data.rand_no(SlownWorker::OrderSn);
+    let freights = serde_json::to_string(&prepare.freights).unwrap();
     let sql_all = myset!("ord_order", {
         "uid": uid,
         "order_sn": &order_sn,
-        "total_amount": prepare.total_amount,
-        "pay_amount": prepare.pay_amount,
+        "total_amount": prepare.total_amount.to_string(),
+        "pay_amount": prepare.pay_amount.to_string(),
         "total_quantity": prepare.total_quantity,
-        "reduce_amount": prepare.reduce_amount,
+        "reduce_amount": prepare.reduce_amount.to_string(),
         "reduce_des": &prepare.reduce_des.join(","),
+        "delivery_amount": prepare.delivery_amount.to_string(),
+        "freights": if prepare.freights.is_empty() { "null" } else { &freights },
         "delivery_type": &params.delivery_type.to_string(),
         "notes": &params.notes,
         "appointment_time": &params.appointment_time,
@@ -897,6 +912,8 @@ pub fn create_order(
         "contact_user": &user_addr.contact_user,
         "contact_phone": &user_addr.contact_phone,
         "pay_type": pay_type.to_string(),
+        // 使用了多张优惠券的，只记第一张，全部的见 ord_order_discount
+        "usr_coupon_id": prepare.usr_coupon_ids.first(),
     });
 
     #[derive(Serialize, Debug, Deserialize)]
@@ -909,9 +926,12 @@ pub fn create_order(
         unit_attr_info: String,
         product_name: String,
         unit_cover: String,
-        price: f64,
+        price: Money,
         buy_quantity: u32,
-        amount: f64,
+        amount: Money,
+        reduce_amount: Money,
+        pay_amount: Money,
+        sec_kill_id: Option<u32>,
     }
     let mut pay_des: Vec<String> = vec![];
     let order_items: Vec<OrderItem> = prepare
@@ -935,13 +955,17 @@ pub fn create_order(
                 unit_cover: get_path_from_url(&x.unit_cover, &OssBucket::EobFiles),
                 price: x.price,
                 buy_quantity: x.buy_quantity,
-                amount: x.price * x.buy_quantity as f64,
+                amount: x.price * x.buy_quantity,
+                reduce_amount: x.reduce_amount,
+                pay_amount: x.pay_amount,
+                sec_kill_id: x.sec_kill_id,
             }
         })
         .collect();
     let sql_items = mysetmany!("ord_order_item", order_items);
     my_run_tran_drop(tran, sql_all)?;
     my_run_tran_drop(tran, sql_items)?;
+    save_order_discounts(tran, &order_sn, &prepare.discounts)?;
     Ok((order_sn, pay_des.join("、")))
 }
 
@@ -985,6 +1009,159 @@ pub fn upd_coupon_status(tran: &mut Transaction, coupon_id: u64) -> Result<(), E
     Ok(())
 }
 
+/// 取消待支付的订单：订单改为取消支付，返还商品库存，使用了优惠券的，优惠券改为未使用。
+///
+/// uid 不为空时，只能取消该用户自己的订单。
+/// 返回订单的支付方式，微信支付的订单，调用方需在提交事务前调用 `close_wx_order` 关闭微信订单
+pub fn cancel_pending_order(
+    tran: &mut Transaction,
+    order_sn: &str,
+    uid: Option<u64>,
+    reason: &str,
+) -> Result<PayType, Error> {
+    #[derive(Deserialize)]
+    struct OrderGet {
+        uid: u64,
+        status: i8,
+        pay_type: Option<String>,
+        usr_coupon_id: Option<u64>,
+    }
+    let order: Vec<OrderGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "uid,status,pay_type,usr_coupon_id",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    if order.is_empty() || uid.is_some_and(|u| u != order[0].uid) {
+        return Err(error::ErrorNotFound("订单不存在"));
+    }
+    if order[0].status != OrderPayStatus::PendingPayment as i8 {
+        return Err(error::ErrorBadRequest("只能取消待支付的订单"));
+    }
+    upd_order_status(
+        tran,
+        order_sn,
+        OrderPayStatus::CancelPayment,
+        None,
+        Some(reason.to_string()),
+    )?;
+    // 拼团订单，释放团的名额
+    cancel_group_member(tran, order_sn)?;
+
+    // 返还库存，下单时在 add_unit_to_shop_cart 中已扣减
+    #[derive(Deserialize)]
+    struct OrderItemGet {
+        unit_sn: u32,
+        buy_quantity: u32,
+        sec_kill_id: Option<u32>,
+    }
+    let items: Vec<OrderItemGet> = my_run_tran_vec(
+        tran,
+        myfind!("ord_order_item", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["is_del", "=", 0],
+            r: "p0 && p1",
+            select: "unit_sn,buy_quantity,sec_kill_id",
+        }),
+    )?;
+    let mut seckill_items = vec![];
+    for item in items {
+        my_run_tran_drop(
+            tran,
+            myupdate!("sku_unit", {"unit_sn": item.unit_sn}, {
+                "quantity": ["incr", item.buy_quantity],
+            }),
+        )?;
+        // 秒杀的，同时返还秒杀库存
+        if let Some(sk_id) = item.sec_kill_id {
+            my_exec_tran_drop(
+                tran,
+                "update pmt_sec_kill set sold = greatest(sold - ?, 0) where id = ?",
+                (item.buy_quantity, sk_id),
+            )?;
+            seckill_items.push((sk_id, item.buy_quantity));
+        }
+    }
+    for (sk_id, quantity) in seckill_items {
+        if let Err(e) = seckill_release(sk_id, order[0].uid, quantity) {
+            log_err(&e, "取消订单，返还秒杀库存");
+        }
+    }
+
+    // 返还优惠券，过期的优惠券由定时任务再改为已过期
+    let mut usr_coupon_ids = get_order_coupon_ids(tran, order_sn)?;
+    usr_coupon_ids.extend(order[0].usr_coupon_id);
+    usr_coupon_ids.sort();
+    usr_coupon_ids.dedup();
+    for usr_coupon_id in usr_coupon_ids {
+        my_run_tran_drop(
+            tran,
+            myupdate!("usr_coupon", usr_coupon_id, {
+                "status": UserCouponStatus::NotUsed as i8,
+            }),
+        )?;
+    }
+
+    Ok(order[0].pay_type.clone().unwrap_or_default().into())
+}
+
+/// 微信支付订单的失效时间，rfc3339 格式，如 2015-05-20T13:29:35+08:00
+pub fn pay_expire_time() -> String {
+    let minutes = config().order.pay_timeout_minutes as i64;
+    (Local::now() + Duration::minutes(minutes)).to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/// 关闭微信支付的订单，关闭后用户无法再支付该订单
+pub async fn close_wx_order(order_sn: &str) -> Result<(), Error> {
+    payment().close(order_sn).await
+}
+
+/// 查询微信支付的订单是否已支付，已支付时返回与支付回调相同的数据，用于补处理丢失的支付回调。
+/// 查询失败（如用户未发起过支付）时按未支付处理
+pub async fn query_wx_paid(order_sn: &str) -> Option<WxPayResource> {
+    let detail = payment().query(order_sn).await.ok()?;
+    if detail.trade_state != TradeState::SUCCESS {
+        return None;
+    }
+    Some(WxPayResource {
+        out_trade_no: detail.out_trade_no,
+        transaction_id: detail.transaction_id?,
+        trade_state: detail.trade_state,
+        amount: WxPayResourceAmount {
+            total: detail.amount?.cent() as u64,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+/// 微信支付的订单退款，amount 为本次退款金额，total 为原订单的支付金额，全额退款时两者相同。
+/// 退款结果由 `/pay/refund/notify` 回调处理。
+///
+/// out_refund_no 相同时微信只会退款一次，重试时要使用同一个退款单号
+pub async fn refund_wx_order(
+    transaction_id: &str,
+    out_refund_no: &str,
+    amount: Money,
+    total: Money,
+    reason: &str,
+) -> Result<(), Error> {
+    payment()
+        .refund(&RefundReq {
+            transaction_id: Some(transaction_id.to_string()),
+            out_refund_no: out_refund_no.to_string(),
+            amount,
+            total,
+            reason: Some(reason.to_string()),
+            ..Default::default()
+        })
+        .await
+        .map(|_| ())
+}
+
 /// 微信物流，发货
 #[allow(unused)]
 pub fn auto_add_wx_waybill(
@@ -1069,7 +1246,9 @@ pub fn upd_user_product_multiple_status(
 
 #[cfg(test)]
 mod test {
-    use super::{UnitAttrInfo, UserProductUpd};
+    use super::{UnitAttrInfo, UserProductUpd, is_item_complete, pay_expire_time};
+    use crate::common::types::OrderItemStatus;
+    use chrono::{DateTime, Local};
     use mysql_quick::{myfind, myset, myupdatemany};
 
     #[test]
@@ -1135,4 +1314,28 @@ mod test {
         let sql = myupdatemany!("spu_product", "uid,is_del", vec![&a]);
         println!("sql....  {}", sql)
     }
+    #[test]
+    fn test_is_item_complete() {
+        // 后台发货、修改状态传入的是数字
+        assert!(is_item_complete(&2u8.into()));
+        assert!(is_item_complete(&OrderItemStatus::Evaluated));
+        for s in [
+            OrderItemStatus::WaitDeliverGoods,
+            OrderItemStatus::WaitTakeDelivery,
+            OrderItemStatus::Apply,
+            OrderItemStatus::Refund,
+            OrderItemStatus::Refunding,
+        ] {
+            assert!(!is_item_complete(&s), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_pay_expire_time() {
+        let t = pay_expire_time();
+        // 微信要求 yyyy-MM-DDTHH:mm:ss+TIMEZONE，不带毫秒
+        assert!(!t.contains('.'));
+        let expire = DateTime::parse_from_rfc3339(&t).unwrap();
+        assert!(expire > Local::now());
+    }
 }
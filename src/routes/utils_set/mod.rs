@@ -1,6 +1,17 @@
+pub(crate) mod after_sale_set;
+pub(crate) mod agent_set;
+pub(crate) mod commission_rule_set;
+pub(crate) mod coupon_campaign_set;
+pub(crate) mod coupon_set;
+pub(crate) mod discount_set;
+pub(crate) mod freight_set;
+pub(crate) mod group_set;
 pub(crate) mod hash_set;
 pub(crate) mod mall_set;
+pub(crate) mod pocket_audit_set;
 pub(crate) mod pocket_set;
+pub(crate) mod recharge_set;
+pub(crate) mod review_set;
 pub(crate) mod sales_set;
 pub(crate) mod tran_set;
 pub(crate) mod user_set;
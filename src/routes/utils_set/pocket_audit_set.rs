@@ -0,0 +1,381 @@
+use actix_web::{Error, error};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, PooledConn, Transaction, TxOpts, myfind, myset, myupdate};
+use serde::{Deserialize, Serialize};
+
+use crate::common::types::{NormalStatus, PayType, PocketAuditKind, TranType};
+use crate::common::{Money, config};
+use crate::db::{
+    my_exec_tran_vec, my_exec_vec, my_run_drop, my_run_tran_drop, my_run_tran_vec, my_run_vec,
+};
+use crate::routes::utils_set::hash_set::{hash_user_pocket_money_verify, hash_user_tran_time};
+use crate::routes::utils_set::pocket_set::UserPocketMoney;
+use crate::utils::utils::log_err;
+
+/// 对账时一次读取的钱包数、交易记录数
+const POCKET_AUDIT_BATCH: u32 = 500;
+
+/// 交易记录是否计入零钱余额。
+///
+/// 微信支付购买商品的记录只是消费流水，没有经过零钱，其他的记录都是零钱的增减
+pub fn is_pocket_tran(tran_type: &str, pay_type: &str) -> bool {
+    !(tran_type == TranType::Purchase.to_string() && pay_type == PayType::WxPay.to_string())
+}
+
+/// 按 hash 中的时间判断交易记录的问题，hash_time 为 None 时是用户、金额、类型与 hash 不一致。
+///
+/// hash 的时间是服务器时间，created_at 是数据库写入的时间，相差在 tolerance 秒内的视为正常
+fn record_time_issue(
+    hash_time: Option<i64>,
+    created_ts: i64,
+    tolerance: u64,
+) -> Option<PocketAuditKind> {
+    match hash_time {
+        None => Some(PocketAuditKind::RecordHash),
+        Some(t) if t.abs_diff(created_ts) > tolerance => Some(PocketAuditKind::RecordTime),
+        Some(_) => None,
+    }
+}
+
+/// 对账用到的交易记录，created_ts 为 created_at 的时间戳
+struct AuditRecord {
+    id: u64,
+    uid: u64,
+    tran_amount: Money,
+    hash: Option<String>,
+    tran_type: String,
+    pay_type: String,
+    created_ts: i64,
+}
+type AuditRecordRow = (u64, u64, String, Option<String>, String, String, i64);
+impl AuditRecord {
+    fn from_row(row: AuditRecordRow) -> Result<Self, Error> {
+        let (id, uid, tran_amount, hash, tran_type, pay_type, created_ts) = row;
+        let tran_amount = tran_amount
+            .parse::<Money>()
+            .map_err(|e| error::ErrorInternalServerError(log_err(&e, "交易金额格式错误")))?;
+        Ok(Self {
+            id,
+            uid,
+            tran_amount,
+            hash,
+            tran_type,
+            pay_type,
+            created_ts,
+        })
+    }
+    /// 检查交易记录的 hash，没有 hash、解密失败的算 hash 不符
+    fn check(&self, tolerance: u64) -> Option<PocketAuditIssue> {
+        let hash_time = self.hash.as_deref().and_then(|hash| {
+            hash_user_tran_time(
+                hash,
+                self.uid,
+                self.tran_amount,
+                &self.tran_type,
+                &self.pay_type,
+            )
+            .unwrap_or(None)
+        });
+        record_time_issue(hash_time, self.created_ts, tolerance).map(|kind| self.issue(kind))
+    }
+    fn issue(&self, kind: PocketAuditKind) -> PocketAuditIssue {
+        PocketAuditIssue {
+            uid: self.uid,
+            kind: kind as u8,
+            record_id: Some(self.id),
+            amount: self.tran_amount,
+            expected_amount: None,
+            is_frozen: false,
+        }
+    }
+}
+
+const AUDIT_RECORD_SELECT: &str =
+    "select r.id, r.uid, r.tran_amount, r.tran_amount_hash, r.tran_type, r.pay_type,
+    unix_timestamp(r.created_at) from usr_transaction_records r";
+
+/// 对账发现的一个问题
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PocketAuditIssue {
+    pub uid: u64,
+    /// 1 钱包金额的 hash 不符，2 余额与交易记录合计不符，3 交易记录的 hash 不符，
+    /// 4 交易记录的 hash 时间与写入时间相差过大
+    pub kind: u8,
+    /// kind 为 3、4 时，对应的交易记录
+    pub record_id: Option<u64>,
+    /// 钱包余额，kind 为 3、4 时为交易金额
+    pub amount: Money,
+    /// kind 为 2 时，交易记录的合计
+    pub expected_amount: Option<Money>,
+    /// 本次对账是否冻结了该钱包
+    pub is_frozen: bool,
+}
+
+/// 零钱对账的结果
+#[derive(Serialize, Debug, Default)]
+pub struct PocketAuditReport {
+    /// 对账批次，问题记录在 usr_pocket_audit 中
+    pub batch: String,
+    /// 检查的钱包数
+    pub wallet_count: u64,
+    /// 检查的交易记录数
+    pub record_count: u64,
+    /// 本次冻结的钱包
+    pub frozen_uids: Vec<u64>,
+    pub issues: Vec<PocketAuditIssue>,
+    /// 对账出错的钱包
+    pub fails: Vec<String>,
+}
+impl PocketAuditReport {
+    /// 对账结果的说明，写入任务的执行记录
+    pub fn summary(&self) -> String {
+        let mut msg = format!(
+            "对账批次 {}：钱包 {} 个，交易记录 {} 条，问题 {} 个，冻结钱包 {} 个",
+            self.batch,
+            self.wallet_count,
+            self.record_count,
+            self.issues.len(),
+            self.frozen_uids.len()
+        );
+        if !self.frozen_uids.is_empty() {
+            let uids = self
+                .frozen_uids
+                .iter()
+                .map(|u| u.to_string())
+                .collect::<Vec<_>>();
+            msg += &format!("（uid：{}）", uids.join(","));
+        }
+        if !self.fails.is_empty() {
+            msg += &format!("，出错 {} 个：{}", self.fails.len(), self.fails.join("；"));
+        }
+        msg
+    }
+}
+
+/// 是否要冻结钱包，只有交易记录时间不符的不冻结
+fn should_freeze(issues: &[PocketAuditIssue]) -> bool {
+    issues
+        .iter()
+        .any(|i| i.kind != PocketAuditKind::RecordTime as u8)
+}
+
+/// 单个钱包的对账结果
+pub struct UserPocketAudit {
+    pub record_count: u64,
+    pub issues: Vec<PocketAuditIssue>,
+    pub frozen: bool,
+}
+
+/// 检查一个用户的钱包：金额的 hash、余额与交易记录的合计、每条交易记录的 hash。
+///
+/// 钱包行加锁后再读交易记录，对账期间零钱不会变动。有问题且钱包正常时，冻结钱包（status 改为 3），
+/// 之后 get_user_pocket_money 会拒绝该钱包的零钱变动，直到后台处理后解冻。
+/// 只有交易记录时间不符的，只记录问题，不冻结
+pub fn audit_user_pocket(tran: &mut Transaction, uid: u64) -> Result<UserPocketAudit, Error> {
+    let pocket: Vec<UserPocketMoney> = my_run_tran_vec(
+        tran,
+        myfind!("usr_pocket_money", {
+            p0: ["uid", "=", uid],
+            r: "p0",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(pocket) = pocket.into_iter().next() else {
+        return Err(error::ErrorNotFound("未找到用户的钱包信息"));
+    };
+    let mut issues: Vec<PocketAuditIssue> = vec![];
+    if !hash_user_pocket_money_verify(&pocket.amount_hash, uid, pocket.amount).unwrap_or(false) {
+        issues.push(PocketAuditIssue {
+            uid,
+            kind: PocketAuditKind::WalletHash as u8,
+            record_id: None,
+            amount: pocket.amount,
+            expected_amount: None,
+            is_frozen: false,
+        });
+    }
+
+    let rows: Vec<AuditRecordRow> = my_exec_tran_vec(
+        tran,
+        &format!("{AUDIT_RECORD_SELECT} where r.uid = ? and r.is_del = 0 order by r.id"),
+        (uid,),
+    )?;
+    let record_count = rows.len() as u64;
+    let tolerance = config().pocket.audit_time_tolerance_sec;
+    let mut sum = Money::ZERO;
+    for row in rows {
+        let record = AuditRecord::from_row(row)?;
+        if is_pocket_tran(&record.tran_type, &record.pay_type) {
+            sum += record.tran_amount;
+        }
+        issues.extend(record.check(tolerance));
+    }
+    if sum != pocket.amount {
+        issues.push(PocketAuditIssue {
+            uid,
+            kind: PocketAuditKind::Balance as u8,
+            record_id: None,
+            amount: pocket.amount,
+            expected_amount: Some(sum),
+            is_frozen: false,
+        });
+    }
+
+    let frozen = should_freeze(&issues) && pocket.status == NormalStatus::Online as u8;
+    if frozen {
+        my_run_tran_drop(
+            tran,
+            myupdate!("usr_pocket_money", pocket.id, {
+                "status": NormalStatus::OffShelf as u8,
+            }),
+        )?;
+        for issue in issues.iter_mut() {
+            issue.is_frozen = true;
+        }
+    }
+    Ok(UserPocketAudit {
+        record_count,
+        issues,
+        frozen,
+    })
+}
+
+/// 零钱对账：逐个检查所有钱包，再检查没有钱包的用户的交易记录（只校验 hash），
+/// 发现的问题按批次写入 usr_pocket_audit。
+///
+/// 每个钱包单独一个事务，个别钱包出错时记到 fails 中，不影响其他钱包的对账
+pub fn pocket_reconcile(conn: &mut PooledConn) -> Result<PocketAuditReport, Error> {
+    let mut report = PocketAuditReport {
+        batch: chrono::Local::now().format("%Y%m%d%H%M%S").to_string(),
+        ..Default::default()
+    };
+
+    #[derive(Deserialize)]
+    struct PocketGet {
+        id: u64,
+        uid: u64,
+    }
+    let mut last_id = 0;
+    loop {
+        let pockets: Vec<PocketGet> = my_run_vec(
+            conn,
+            myfind!("usr_pocket_money", {
+                p0: ["id", ">", last_id],
+                p1: ["is_del", "=", 0],
+                r: "p0 && p1",
+                limit: POCKET_AUDIT_BATCH,
+                order_by: "id",
+                select: "id,uid",
+            }),
+        )?;
+        let Some(last) = pockets.last() else {
+            break;
+        };
+        last_id = last.id;
+        for p in &pockets {
+            let mut tran = conn
+                .start_transaction(TxOpts::default())
+                .map_err(|e| error::ErrorInternalServerError(log_err(&e, "数据库连接出错")))?;
+            match audit_user_pocket(&mut tran, p.uid) {
+                Ok(audit) => {
+                    tran.commit().map_err(|e| {
+                        error::ErrorInternalServerError(log_err(&e, "事务提交失败"))
+                    })?;
+                    report.wallet_count += 1;
+                    report.record_count += audit.record_count;
+                    if audit.frozen {
+                        report.frozen_uids.push(p.uid);
+                    }
+                    report.issues.extend(audit.issues);
+                }
+                Err(e) => {
+                    tran.rollback().unwrap();
+                    report.fails.push(format!("{}: {}", p.uid, e));
+                }
+            }
+        }
+    }
+
+    // 只用微信支付购买过的用户，可能没有钱包
+    let mut last_id = 0;
+    loop {
+        let rows: Vec<AuditRecordRow> = my_exec_vec(
+            conn,
+            &format!(
+                "{AUDIT_RECORD_SELECT} left join usr_pocket_money p on p.uid = r.uid and p.is_del = 0
+                    where p.id is null and r.is_del = 0 and r.id > ? order by r.id limit ?"
+            ),
+            (last_id, POCKET_AUDIT_BATCH),
+        )?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_id = last.0;
+        report.record_count += rows.len() as u64;
+        let tolerance = config().pocket.audit_time_tolerance_sec;
+        for row in rows {
+            let record = AuditRecord::from_row(row)?;
+            report.issues.extend(record.check(tolerance));
+        }
+    }
+
+    for issue in &report.issues {
+        my_run_drop(
+            conn,
+            myset!("usr_pocket_audit", {
+                "batch": &report.batch,
+                "uid": issue.uid,
+                "kind": issue.kind,
+                "record_id": issue.record_id,
+                "amount": issue.amount.to_string(),
+                "expected_amount": issue.expected_amount.map(|m| m.to_string()),
+                "is_frozen": issue.is_frozen as u8,
+            }),
+        )?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pocket_audit_rule() {
+        assert!(!is_pocket_tran("PURCHASE", "WX_PAY"));
+        assert!(is_pocket_tran("PURCHASE", "POCKET_PAY"));
+        assert!(is_pocket_tran("RECHARGE", "WX_PAY"));
+        assert!(is_pocket_tran("WITHDRAW", "WX_PAY"));
+
+        let created = 1_700_000_000;
+        assert_eq!(record_time_issue(Some(created), created, 300), None);
+        // 服务器与数据库的时钟相差、写入较慢，在容差内的都算正常
+        assert_eq!(record_time_issue(Some(created + 300), created, 300), None);
+        assert_eq!(record_time_issue(Some(created - 120), created, 300), None);
+        assert_eq!(
+            record_time_issue(Some(created - 301), created, 300),
+            Some(PocketAuditKind::RecordTime)
+        );
+        assert_eq!(
+            record_time_issue(None, created, 300),
+            Some(PocketAuditKind::RecordHash)
+        );
+    }
+
+    #[test]
+    fn test_should_freeze() {
+        let issue = |kind: PocketAuditKind| PocketAuditIssue {
+            uid: 1,
+            kind: kind as u8,
+            record_id: Some(1),
+            amount: Money::from_cent(100),
+            expected_amount: None,
+            is_frozen: false,
+        };
+        assert!(!should_freeze(&[]));
+        assert!(!should_freeze(&[issue(PocketAuditKind::RecordTime)]));
+        assert!(should_freeze(&[
+            issue(PocketAuditKind::RecordTime),
+            issue(PocketAuditKind::RecordHash)
+        ]));
+        assert!(should_freeze(&[issue(PocketAuditKind::Balance)]));
+    }
+}
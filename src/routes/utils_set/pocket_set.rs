@@ -3,11 +3,13 @@ use mysql_quick::{
     MY_EXCLUSIVE_LOCK, MysqlQuickCount, PooledConn, Transaction, mycount, myfind, myset, myupdate,
 };
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::deserialize_number_from_string;
 
-use crate::common::types::{PayType, TranType};
+use crate::common::Money;
+use crate::common::types::{PayType, TranType, WithdrawalReqStatus};
 use crate::db::{my_run_drop, my_run_tran_drop, my_run_tran_vec, my_run_vec};
-use crate::routes::utils_set::hash_set::{hash_user_pocket_money, hash_user_pocket_money_verify};
+use crate::routes::utils_set::hash_set::{
+    hash_user_pocket_money, hash_user_pocket_money_verify, hash_user_withdrawal_money,
+};
 use crate::routes::utils_set::tran_set::add_tran_record;
 
 /// 用户零钱信息
@@ -15,8 +17,7 @@ use crate::routes::utils_set::tran_set::add_tran_record;
 pub struct UserPocketMoney {
     pub id: u64,
     pub uid: u64,
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    pub amount: f64,
+    pub amount: Money,
     pub amount_hash: String,
     pub status: u8,
     pub is_del: u8,
@@ -25,21 +26,22 @@ pub struct UserPocketMoney {
 pub fn pocket_money_add(
     tran: &mut Transaction,
     uid: u64,
-    add_amount: f64,
+    add_amount: Money,
     tran_type: TranType,
     pay_type: PayType,
     info: Option<&str>,
 ) -> Result<(), Error> {
-    if add_amount < 0. {
+    if add_amount.is_negative() {
         return Err(error::ErrorBadRequest("金额不能为负数"));
     }
     let user_pocket = get_user_pocket_money(tran, uid)?;
+    // 行已被 get_user_pocket_money 加锁，直接写入与 hash 一致的新金额
     let money_new = user_pocket.amount + add_amount;
     let hash = hash_user_pocket_money(uid, money_new)?;
     my_run_tran_drop(
         tran,
         myupdate!("usr_pocket_money", {"uid": uid}, {
-            "amount": ["incr", add_amount],
+            "amount": ["set", money_new.to_string()],
             "amount_hash":["set", &hash],
         }),
     )?;
@@ -62,12 +64,12 @@ pub fn pocket_money_add(
 pub fn pocket_money_sub(
     tran: &mut Transaction,
     uid: u64,
-    sub_amount: f64,
+    sub_amount: Money,
     tran_type: TranType,
     pay_type: PayType,
     info: Option<&str>,
 ) -> Result<(), Error> {
-    if sub_amount < 0. {
+    if sub_amount.is_negative() {
         return Err(error::ErrorBadRequest("金额不能为负数"));
     }
     let user_pocket = get_user_pocket_money(tran, uid)?;
@@ -79,7 +81,7 @@ pub fn pocket_money_sub(
     my_run_tran_drop(
         tran,
         myupdate!("usr_pocket_money", {"uid": uid}, {
-            "amount": ["incr", -sub_amount],
+            "amount": ["set", money_new.to_string()],
             "amount_hash":["set", &hash],
         }),
     )?;
@@ -124,6 +126,122 @@ pub fn get_user_pocket_money(tran: &mut Transaction, uid: u64) -> Result<UserPoc
     Ok(user_pocket)
 }
 
+/// 查询用户可以扣款的零钱，用于退款时扣回佣金、分成等系统扣款。
+///
+/// 钱包不存在、已删除、未启用（如对账后冻结）或 hash 校验不通过时返回 None，不返回错误，不能因此影响用户的退款
+pub fn get_usable_pocket_money(
+    tran: &mut Transaction,
+    uid: u64,
+) -> Result<Option<UserPocketMoney>, Error> {
+    let info: Vec<UserPocketMoney> = my_run_tran_vec(
+        tran,
+        myfind!("usr_pocket_money", {
+            p0: ["uid", "=", uid],
+            r: "p0",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(pocket) = info.into_iter().next() else {
+        return Ok(None);
+    };
+    if pocket.is_del == 1 || pocket.status != 2 {
+        return Ok(None);
+    }
+    if !hash_user_pocket_money_verify(&pocket.amount_hash, uid, pocket.amount).unwrap_or(false) {
+        return Ok(None);
+    }
+    Ok(Some(pocket))
+}
+
+/// 从用户零钱扣回 amount，如退款时扣回佣金、分成。
+///
+/// 零钱不足时只扣到 0；钱包不能使用时不扣零钱，记一条金额为 0 的交易记录。
+/// 没扣到的差额记在交易记录 info 的 shortfall 里，由后台线下处理
+pub fn pocket_money_back(
+    tran: &mut Transaction,
+    uid: u64,
+    amount: Money,
+    tran_type: TranType,
+    pay_type: PayType,
+    mut info: serde_json::Value,
+) -> Result<(), Error> {
+    let Some(pocket) = get_usable_pocket_money(tran, uid)? else {
+        info["shortfall"] = serde_json::json!(amount);
+        info["pocket_unusable"] = serde_json::json!(true);
+        return add_tran_record(tran, tran_type, pay_type, uid, Money::ZERO, Some(&info));
+    };
+    let sub_amount = amount.min(pocket.amount.max(Money::ZERO));
+    info["shortfall"] = serde_json::json!(amount - sub_amount);
+    pocket_money_sub(
+        tran,
+        uid,
+        sub_amount,
+        tran_type,
+        pay_type,
+        Some(&info.to_string()),
+    )
+}
+
+/// 零钱提现申请：有未完成的提现申请、零钱不足时返回错误，
+/// 否则零钱减去提现金额，并新增审核中的提现申请
+pub fn add_withdrawal_request(
+    tran: &mut Transaction,
+    uid: u64,
+    req_amount: Money,
+) -> Result<(), Error> {
+    let s = [
+        WithdrawalReqStatus::Approved as u8,
+        WithdrawalReqStatus::UnderReview as u8,
+        WithdrawalReqStatus::Ing as u8,
+    ]
+    .iter()
+    .map(|x| x.to_string())
+    .collect::<Vec<String>>();
+    // 检查是否有正在审核中的提现申请
+    let pending_requests: Vec<serde_json::Value> = my_run_tran_vec(
+        tran,
+        myfind!("usr_withdrawal_request", {
+            p0: ["uid", "=", uid],
+            p1: ["status", "in", s.join(",")],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            select: "id",
+        }),
+    )?;
+    if !pending_requests.is_empty() {
+        return Err(error::ErrorBadRequest("您有未完成的提现申请，请先完成。"));
+    }
+
+    // 检查用户零钱余额
+    let pocket_info = get_user_pocket_money(tran, uid)?;
+    if pocket_info.amount < req_amount {
+        return Err(error::ErrorBadRequest("零钱余额不足"));
+    }
+
+    // 零钱减，同时有交易记录添加
+    pocket_money_sub(
+        tran,
+        uid,
+        req_amount,
+        TranType::Withdraw,
+        PayType::WxPay,
+        Some("用户提现"),
+    )?;
+
+    let hash =
+        hash_user_withdrawal_money(uid, req_amount, "", WithdrawalReqStatus::UnderReview as u8)?;
+    // 插入提现申请记录
+    my_run_tran_drop(
+        tran,
+        myset!("usr_withdrawal_request", {
+            "uid": uid,
+            "req_amount": req_amount.to_string(),
+            "status": WithdrawalReqStatus::UnderReview as u8,
+            "transfer_hash": hash,
+        }),
+    )?;
+    Ok(())
+}
+
 /// 用户零钱数据初始化。一般只在用户静默登录时，运行一次
 pub fn init_user_pocket_money(conn: &mut PooledConn, uid: u64) -> Result<(), Error> {
     let count: Vec<MysqlQuickCount> = my_run_vec(
@@ -137,13 +255,13 @@ pub fn init_user_pocket_money(conn: &mut PooledConn, uid: u64) -> Result<(), Err
         // 已经有了，就不再新增
         return Ok(());
     }
-    let hash = hash_user_pocket_money(uid, 0.)?;
+    let hash = hash_user_pocket_money(uid, Money::ZERO)?;
     // 新增
     my_run_drop(
         conn,
         myset!("usr_pocket_money", {
             "uid": uid,
-            "amount": 0.,
+            "amount": Money::ZERO.to_string(),
             "amount_hash": hash,
         }),
     )?;
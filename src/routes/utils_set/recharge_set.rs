@@ -0,0 +1,116 @@
+use actix_web::{Error, error};
+use mysql_quick::{MY_EXCLUSIVE_LOCK, PooledConn, Transaction, myfind, myupdate};
+use serde::Deserialize;
+
+use crate::common::Money;
+use crate::common::types::{PayType, RechargeStatus, TranType};
+use crate::db::{my_exec_drop, my_run_tran_drop, my_run_tran_vec};
+use crate::routes::utils_set::pocket_set::pocket_money_add;
+use crate::utils::time::{NowTimeType, get_now_time};
+
+/// 充值单的商户订单号前缀，微信支付回调按前缀区分充值单和商品订单
+pub const RECHARGE_TRADE_PREFIX: &str = "RC";
+
+/// 充值单的商户订单号，商品订单号是纯数字，加上前缀后不会重复
+pub fn recharge_trade_no(no: &str) -> String {
+    format!("{RECHARGE_TRADE_PREFIX}{no}")
+}
+
+/// 是否是充值单的商户订单号
+pub fn is_recharge_trade_no(out_trade_no: &str) -> bool {
+    out_trade_no.starts_with(RECHARGE_TRADE_PREFIX)
+}
+
+/// 充值单微信支付成功：充值金额和赠送金额到账零钱。
+///
+/// 已到账的直接返回，微信重复回调时只到账一次；超时被取消的，实际已支付，仍然到账
+pub fn recharge_paid(
+    tran: &mut Transaction,
+    out_trade_no: &str,
+    transaction_id: &str,
+    total: Money,
+) -> Result<(), Error> {
+    #[derive(Deserialize)]
+    struct RechargeGet {
+        id: u64,
+        uid: u64,
+        amount: Money,
+        bonus_amount: Money,
+        status: u8,
+    }
+    let recharge: Vec<RechargeGet> = my_run_tran_vec(
+        tran,
+        myfind!("usr_recharge", {
+            p0: ["out_trade_no", "=", out_trade_no],
+            r: "p0",
+            select: "id,uid,amount,bonus_amount,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(recharge) = recharge.into_iter().next() else {
+        return Err(error::ErrorNotFound("充值单不存在"));
+    };
+    if recharge.status == RechargeStatus::Paid as u8 {
+        return Ok(());
+    }
+    if recharge.amount != total {
+        return Err(error::ErrorBadRequest("支付金额与充值金额不一致"));
+    }
+    my_run_tran_drop(
+        tran,
+        myupdate!("usr_recharge", recharge.id, {
+            "status": RechargeStatus::Paid as u8,
+            "transaction_id": transaction_id,
+            "pay_time": get_now_time(NowTimeType::DateTime),
+        }),
+    )?;
+    let info = serde_json::json!({
+        "out_trade_no": out_trade_no,
+        "transaction_id": transaction_id,
+    })
+    .to_string();
+    pocket_money_add(
+        tran,
+        recharge.uid,
+        recharge.amount,
+        TranType::Recharge,
+        PayType::WxPay,
+        Some(&info),
+    )?;
+    if recharge.bonus_amount.is_positive() {
+        pocket_money_add(
+            tran,
+            recharge.uid,
+            recharge.bonus_amount,
+            TranType::RechargeBonus,
+            PayType::WxPay,
+            Some(&info),
+        )?;
+    }
+    Ok(())
+}
+
+/// 取消 deadline 之前创建、还未支付的充值单，返回取消的数量
+pub fn cancel_timeout_recharge(conn: &mut PooledConn, deadline: &str) -> Result<u64, Error> {
+    my_exec_drop(
+        conn,
+        "update usr_recharge set status = ? where status = ? and is_del = 0 and created_at < ?",
+        (
+            RechargeStatus::Cancel as u8,
+            RechargeStatus::WaitPay as u8,
+            deadline,
+        ),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_recharge_trade_no() {
+        let no = recharge_trade_no("299477755089784832");
+        assert_eq!(no, "RC299477755089784832");
+        assert!(is_recharge_trade_no(&no));
+        assert!(!is_recharge_trade_no("299477755089784832"));
+    }
+}
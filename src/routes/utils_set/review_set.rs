@@ -0,0 +1,101 @@
+//! 商品评价的查询，产品详情和评价列表共用
+
+use actix_web::Error;
+use mysql_quick::{PooledConn, myfind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::types::NormalStatus;
+use crate::db::{my_exec_first, my_run_vec};
+use crate::utils::files::{get_file_url, get_file_urls};
+
+/// 评价列表每页数量
+pub const REVIEW_PAGE_SIZE: u32 = 10;
+
+#[derive(Serialize, Debug, Deserialize, ToSchema, Clone)]
+pub struct ReviewRes {
+    /// 评价id
+    id: u32,
+    /// 用户昵称
+    nickname: Option<String>,
+    /// 用户头像
+    avatar_url: String,
+    /// 评分 1-5
+    rating: u8,
+    /// 评价内容
+    content: String,
+    /// 评价图片
+    imgs: Vec<String>,
+    /// 购买的商品名
+    unit_name: Option<String>,
+    /// 商家回复
+    reply: Option<String>,
+    /// 回复时间
+    reply_at: Option<String>,
+    /// 评价时间
+    created_at: String,
+}
+
+/// 产品的平均评分（保留一位小数）和评价数，只统计审核通过的评价。没有评价时平均分为 None
+pub fn get_product_rating(
+    conn: &mut PooledConn,
+    product_sn: u32,
+) -> Result<(Option<f64>, u64), Error> {
+    let res: Option<(Option<f64>, u64)> = my_exec_first(
+        conn,
+        "select round(avg(rating), 1), count(*) from eva_product where product_sn = ? and status = ? and is_del = 0",
+        (product_sn, NormalStatus::Online as u8),
+    )?;
+    Ok(res.unwrap_or((None, 0)))
+}
+
+/// 产品审核通过的评价，按时间倒序分页
+pub fn get_product_reviews(
+    conn: &mut PooledConn,
+    product_sn: u32,
+    page: u32,
+) -> Result<Vec<ReviewRes>, Error> {
+    #[derive(Deserialize)]
+    struct ReviewGet {
+        id: u32,
+        nickname: Option<String>,
+        avatar_url: Option<String>,
+        rating: u8,
+        content: String,
+        imgs: Option<String>,
+        unit_name: Option<String>,
+        reply: Option<String>,
+        reply_at: Option<String>,
+        created_at: String,
+    }
+    let list: Vec<ReviewGet> = my_run_vec(
+        conn,
+        myfind!("eva_product", {
+            j0: ["uid", "left", "usr_silent.id"],
+            p0: ["product_sn", "=", product_sn],
+            p1: ["status", "=", NormalStatus::Online as u8],
+            p2: ["is_del", "=", 0],
+            r: "p0 && p1 && p2",
+            page: page,
+            limit: REVIEW_PAGE_SIZE,
+            order_by: "-created_at",
+            select: "id,usr_silent.nickname,usr_silent.avatar_url,rating,content,imgs,unit_name,reply,reply_at,created_at",
+        }),
+    )?;
+
+    Ok(list
+        .into_iter()
+        .map(|x| ReviewRes {
+            id: x.id,
+            nickname: x.nickname,
+            avatar_url: get_file_url(x.avatar_url).unwrap_or("".to_string()),
+            rating: x.rating,
+            content: x.content,
+            imgs: get_file_urls(x.imgs),
+            unit_name: x.unit_name,
+            reply: x.reply,
+            reply_at: x.reply_at,
+            created_at: x.created_at,
+        })
+        .collect())
+}
@@ -1,11 +1,19 @@
 use actix_web::{Error, error};
-use mysql_quick::{MysqlQuickCount, Transaction, mycount, myfind, myget, myset, myupdate};
+use mysql_quick::{
+    MY_EXCLUSIVE_LOCK, MysqlQuickCount, PooledConn, Transaction, mycount, myfind, myget, myset,
+    myupdate,
+};
 use serde::{Deserialize, Serialize};
-use serde_aux::prelude::{deserialize_number_from_string, deserialize_option_number_from_string};
 
-use crate::common::types::{NormalStatus, OrderPayStatus, PayType, Role, TranType};
-use crate::db::{my_run_tran_drop, my_run_tran_vec};
-use crate::routes::utils_set::pocket_set::pocket_money_add;
+use crate::common::Money;
+use crate::common::types::{
+    CommissionStatus, NormalStatus, OrderItemStatus, OrderPayStatus, PayType, Role, TranType,
+};
+use crate::db::{my_exec_tran_vec, my_run_tran_drop, my_run_tran_vec, my_run_vec};
+use crate::routes::utils_set::after_sale_set::order_item_refund_amount;
+use crate::routes::utils_set::commission_rule_set::{get_monthly_volume, get_split_context};
+use crate::routes::utils_set::pocket_set::{pocket_money_add, pocket_money_back};
+use crate::utils::time::{NowTimeType, get_now_time};
 
 /// 直接添加用户为总销售
 pub fn main_sale_add(tran: &mut Transaction, uid: u64) -> Result<(), Error> {
@@ -388,7 +396,10 @@ pub fn get_user_sale_main_sale(
     Ok(user_sale_main_sale)
 }
 
-/// 订单分成操作。通过 order_sn 和 下单用户 uid 进行
+/// 订单分成操作。通过 order_sn 和 下单用户 uid 进行。
+///
+/// 每件的分成按佣金规则计算（见 commission_rule_set），没有匹配的规则时用商品上的固定分成。
+/// 分成先记为冻结中的佣金，商品完成并过了冻结期后，由定时任务结算到零钱，见 commission_settle
 pub fn do_order_sale_split(
     tran: &mut Transaction,
     order_sn: &str,
@@ -401,6 +412,9 @@ pub fn do_order_sale_split(
     struct OrderGet {
         id: u32,
         status: u64,
+        total_amount: Money,
+        delivery_amount: Option<Money>,
+        pay_amount: Money,
         is_del: u8,
     }
     let order: Vec<OrderGet> = my_run_tran_vec(tran, myget!("ord_order", {"order_sn": order_sn}))?;
@@ -410,67 +424,413 @@ pub fn do_order_sale_split(
     if order[0].is_del == 1 {
         return Err(error::ErrorBadRequest("订单信息不存在"));
     }
-    if order[0].status != OrderPayStatus::PendingPayment as u64 {
+    // 拼团订单在成团后才分成，此时为已支付待成团
+    if order[0].status != OrderPayStatus::PendingPayment as u64
+        && order[0].status != OrderPayStatus::GroupPending as u64
+    {
         return Err(error::ErrorBadRequest("订单信息不是待支付"));
     }
     // 查询，当前订单下面的，所有商品
     #[derive(Deserialize, Serialize)]
     struct OrderItemGet {
         order_sn: String,
+        order_item_id: String,
         unit_sn: u64,
         unit_name: String,
         product_name: String,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        price: f64,
+        price: Money,
         buy_quantity: u32,
-        #[serde(deserialize_with = "deserialize_number_from_string")]
-        amount: f64,
-        #[serde(deserialize_with = "deserialize_option_number_from_string")]
-        main_sale_split: Option<f64>,
-        #[serde(deserialize_with = "deserialize_option_number_from_string")]
-        sale_split: Option<f64>,
-        is_split: u8,
+        amount: Money,
+        pay_amount: Option<Money>,
     }
     let item_list: Vec<OrderItemGet> = my_run_tran_vec(
         tran,
         myfind!("ord_order_item", {
-            j0: ["unit_sn", "inner", "sku_unit.unit_sn"],
             p0: ["order_sn", "=", order_sn],
             p1: ["is_del", "=", 0],
             r: "p0 && p1",
-            select: "order_sn, unit_sn, unit_name, product_name, price, buy_quantity, amount, sku_unit.main_sale_split, sku_unit.sale_split, sku_unit.is_split",
+            select: "order_sn, order_item_id, unit_sn, unit_name, product_name, price, buy_quantity, amount, pay_amount",
         }),
     )?;
+    if user_sale_main_sale.sale_uid.is_none() && user_sale_main_sale.main_sale_uid.is_none() {
+        return Ok(());
+    }
+    let unit_sns: Vec<u32> = item_list.iter().map(|x| x.unit_sn as u32).collect();
+    let ctx = get_split_context(tran, &unit_sns)?;
+    let main_sale_volume = match user_sale_main_sale.main_sale_uid {
+        Some(id) => get_monthly_volume(tran, id)?,
+        None => Money::ZERO,
+    };
+    let sale_volume = match user_sale_main_sale.sale_uid {
+        Some(id) => get_monthly_volume(tran, id)?,
+        None => Money::ZERO,
+    };
+    // 每一行商品的实付，之前的订单按订单的商品实付（减去运费）和原价占比分摊
+    let goods_pay = order[0].pay_amount - order[0].delivery_amount.unwrap_or(Money::ZERO);
     for item in item_list {
-        if item.is_split == 0 {
-            // 当前商品不分成
-            continue;
-        }
-        if let Some(main_sale_uid) = user_sale_main_sale.main_sale_uid
-            && let Some(main_sale_split) = item.main_sale_split
-        {
-            pocket_money_add(
-                tran,
-                main_sale_uid,
-                main_sale_split * (item.buy_quantity as f64),
+        let paid = order_item_refund_amount(
+            item.pay_amount,
+            goods_pay,
+            order[0].total_amount,
+            item.price,
+            item.buy_quantity,
+            0,
+            item.buy_quantity,
+        );
+        let split = ctx.split(
+            item.unit_sn as u32,
+            paid,
+            item.buy_quantity,
+            main_sale_volume,
+            sale_volume,
+        );
+        let splits = [
+            (
+                user_sale_main_sale.main_sale_uid,
+                split.main_sale_split,
                 TranType::MainSaleSplit,
-                pay_type.clone(),
-                Some(&serde_json::to_string(&item).unwrap()),
+            ),
+            (
+                user_sale_main_sale.sale_uid,
+                split.sale_split,
+                TranType::SaleSplit,
+            ),
+        ];
+        for (split_uid, unit_amount, tran_type) in splits {
+            let (Some(split_uid), Some(unit_amount)) = (split_uid, unit_amount) else {
+                continue;
+            };
+            if !unit_amount.is_positive() {
+                continue;
+            }
+            my_run_tran_drop(
+                tran,
+                myset!("usr_commission", {
+                    "uid": split_uid,
+                    "order_sn": &item.order_sn,
+                    "order_item_id": &item.order_item_id,
+                    "unit_sn": item.unit_sn,
+                    "tran_type": tran_type.to_string(),
+                    "pay_type": pay_type.to_string(),
+                    "rule_id": split.rule_id,
+                    "base_amount": paid.to_string(),
+                    "unit_amount": unit_amount.to_string(),
+                    "quantity": item.buy_quantity,
+                    "amount": (unit_amount * item.buy_quantity).to_string(),
+                    "status": CommissionStatus::Pending as u8,
+                }),
             )?;
         }
-        if let Some(sale_uid) = user_sale_main_sale.sale_uid
-            && let Some(sale_split) = item.sale_split
-        {
-            pocket_money_add(
+    }
+
+    Ok(())
+}
+
+/// 佣金记录
+#[derive(Deserialize)]
+struct CommissionGet {
+    id: u64,
+    uid: u64,
+    order_sn: String,
+    order_item_id: String,
+    unit_sn: u32,
+    tran_type: String,
+    pay_type: Option<String>,
+    unit_amount: Money,
+    quantity: u32,
+    refund_quantity: u32,
+    settled_amount: Money,
+    back_amount: Money,
+    status: u8,
+}
+
+/// 订单（或其中一个子订单）未取消的佣金，加锁
+fn get_commissions_lock(
+    tran: &mut Transaction,
+    order_sn: &str,
+    order_item_id: Option<&str>,
+) -> Result<Vec<CommissionGet>, Error> {
+    let r = if order_item_id.is_some() {
+        "p0 && p1 && p2 && p3"
+    } else {
+        "p0 && p1 && p2"
+    };
+    my_run_tran_vec(
+        tran,
+        myfind!("usr_commission", {
+            p0: ["order_sn", "=", order_sn],
+            p1: ["status", "!=", CommissionStatus::Cancelled as u8],
+            p2: ["is_del", "=", 0],
+            p3: ["order_item_id", "=", order_item_id.unwrap_or_default()],
+            r: r,
+            select: "id,uid,order_sn,order_item_id,unit_sn,tran_type,pay_type,unit_amount,quantity,refund_quantity,settled_amount,back_amount,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    )
+}
+
+/// 已结算的佣金，退 quantity 件时扣回的金额，不超过结算后还未扣回的部分
+fn commission_back_amount(unit_amount: Money, remain: Money, quantity: u32) -> Money {
+    (unit_amount * quantity).min(remain).max(Money::ZERO)
+}
+
+/// 按退的数量处理佣金，quantity 为 None 时退剩下的全部。
+///
+/// 冻结中的减少数量，全部退完的取消；已结算的从零钱扣回，记 *_BACK 的交易记录。
+/// 零钱不足时只扣到 0，钱包不能使用（如被冻结）时不扣，差额记在交易记录的 shortfall 里，由后台线下处理
+fn refund_commissions(
+    tran: &mut Transaction,
+    list: Vec<CommissionGet>,
+    quantity: Option<u32>,
+) -> Result<(), Error> {
+    for c in list {
+        let left = c.quantity.saturating_sub(c.refund_quantity);
+        let q = quantity.map_or(left, |x| x.min(left));
+        if q == 0 {
+            continue;
+        }
+        let refund_quantity = c.refund_quantity + q;
+        if c.status != CommissionStatus::Settled as u8 {
+            let status = if refund_quantity >= c.quantity {
+                CommissionStatus::Cancelled
+            } else {
+                CommissionStatus::Pending
+            };
+            my_run_tran_drop(
                 tran,
-                sale_uid,
-                sale_split * (item.buy_quantity as f64),
-                TranType::SaleSplit,
-                pay_type.clone(),
-                Some(&serde_json::to_string(&item).unwrap()),
+                myupdate!("usr_commission", c.id, {
+                    "refund_quantity": refund_quantity,
+                    "status": status as u8,
+                }),
             )?;
+            continue;
         }
+
+        let amount = commission_back_amount(c.unit_amount, c.settled_amount - c.back_amount, q);
+        my_run_tran_drop(
+            tran,
+            myupdate!("usr_commission", c.id, {
+                "refund_quantity": refund_quantity,
+                "back_amount": (c.back_amount + amount).to_string(),
+            }),
+        )?;
+        if !amount.is_positive() {
+            continue;
+        }
+        let back_type = if TranType::from(c.tran_type) == TranType::MainSaleSplit {
+            TranType::MainSaleSplitBack
+        } else {
+            TranType::SaleSplitBack
+        };
+        let info = serde_json::json!({
+            "commission_id": c.id,
+            "order_sn": c.order_sn,
+            "order_item_id": c.order_item_id,
+            "unit_sn": c.unit_sn,
+            "quantity": q,
+            "amount": amount,
+        });
+        pocket_money_back(
+            tran,
+            c.uid,
+            amount,
+            back_type,
+            c.pay_type.unwrap_or_default().into(),
+            info,
+        )?;
     }
+    Ok(())
+}
 
+/// 子订单退了 quantity 件，取消或扣回对应的佣金。
+///
+/// 没有佣金记录时返回 false，是冻结期上线前的订单，由调用方按 sale_split_back 扣回
+pub fn commission_refund(
+    tran: &mut Transaction,
+    order_sn: &str,
+    order_item_id: &str,
+    quantity: u32,
+) -> Result<bool, Error> {
+    let list = get_commissions_lock(tran, order_sn, Some(order_item_id))?;
+    if list.is_empty() {
+        return Ok(false);
+    }
+    refund_commissions(tran, list, Some(quantity))?;
+    Ok(true)
+}
+
+/// 整单退款，取消或扣回订单剩下的所有佣金
+pub fn commission_refund_order(tran: &mut Transaction, order_sn: &str) -> Result<(), Error> {
+    let list = get_commissions_lock(tran, order_sn, None)?;
+    refund_commissions(tran, list, None)
+}
+
+/// 可以结算的子订单状态：已完成、已评价
+fn commission_settle_item_status() -> String {
+    [
+        OrderItemStatus::Complete as u8,
+        OrderItemStatus::Evaluated as u8,
+    ]
+    .map(|x| x.to_string())
+    .join(",")
+}
+
+/// 冻结期已过、待结算的佣金 id：子订单已完成（已评价），且完成时间不晚于 deadline
+pub fn commission_settle_ids(
+    conn: &mut PooledConn,
+    deadline: &str,
+    limit: u32,
+) -> Result<Vec<u64>, Error> {
+    #[derive(Deserialize)]
+    struct CommissionId {
+        id: u64,
+    }
+    let list: Vec<CommissionId> = my_run_vec(
+        conn,
+        myfind!("usr_commission", {
+            j0: ["order_item_id", "inner", "ord_order_item.order_item_id"],
+            p0: ["status", "=", CommissionStatus::Pending as u8],
+            p1: ["is_del", "=", 0],
+            p2: ["ord_order_item.status", "in", commission_settle_item_status()],
+            p3: ["ord_order_item.complete_time", "<=", deadline],
+            r: "p0 && p1 && p2 && p3",
+            limit: limit,
+            order_by: "id",
+            select: "id",
+        }),
+    )?;
+    Ok(list.into_iter().map(|x| x.id).collect())
+}
+
+/// 结算一条佣金，按未退的数量记入零钱。加锁后重新检查条件，已不能结算的返回 false
+pub fn commission_settle(tran: &mut Transaction, id: u64, deadline: &str) -> Result<bool, Error> {
+    let list: Vec<CommissionGet> = my_run_tran_vec(
+        tran,
+        myfind!("usr_commission", {
+            j0: ["order_item_id", "inner", "ord_order_item.order_item_id"],
+            p0: ["id", "=", id],
+            p1: ["status", "=", CommissionStatus::Pending as u8],
+            p2: ["is_del", "=", 0],
+            p3: ["ord_order_item.status", "in", commission_settle_item_status()],
+            p4: ["ord_order_item.complete_time", "<=", deadline],
+            r: "p0 && p1 && p2 && p3 && p4",
+            select: "id,uid,order_sn,order_item_id,unit_sn,tran_type,pay_type,unit_amount,quantity,refund_quantity,settled_amount,back_amount,status",
+        }) + MY_EXCLUSIVE_LOCK,
+    )?;
+    let Some(c) = list.into_iter().next() else {
+        return Ok(false);
+    };
+    let quantity = c.quantity.saturating_sub(c.refund_quantity);
+    if quantity == 0 {
+        my_run_tran_drop(
+            tran,
+            myupdate!("usr_commission", c.id, {
+                "status": CommissionStatus::Cancelled as u8,
+            }),
+        )?;
+        return Ok(false);
+    }
+    let amount = c.unit_amount * quantity;
+    my_run_tran_drop(
+        tran,
+        myupdate!("usr_commission", c.id, {
+            "status": CommissionStatus::Settled as u8,
+            "settled_amount": amount.to_string(),
+            "settle_time": get_now_time(NowTimeType::DateTime),
+        }),
+    )?;
+    let info = serde_json::json!({
+        "commission_id": c.id,
+        "order_sn": c.order_sn,
+        "order_item_id": c.order_item_id,
+        "unit_sn": c.unit_sn,
+        "buy_quantity": quantity,
+    });
+    pocket_money_add(
+        tran,
+        c.uid,
+        amount,
+        c.tran_type.into(),
+        c.pay_type.unwrap_or_default().into(),
+        Some(&info.to_string()),
+    )?;
+    Ok(true)
+}
+
+/// 售后退款时，按退的数量扣回 do_order_sale_split 直接给销售的分账，用于没有佣金记录的旧订单。
+///
+/// 销售零钱不足时只扣到 0，钱包不能使用时不扣，差额记在交易记录的 shortfall 里，由后台线下处理
+pub fn sale_split_back(
+    tran: &mut Transaction,
+    order_sn: &str,
+    unit_sn: u32,
+    quantity: u32,
+    pay_type: PayType,
+) -> Result<(), Error> {
+    #[derive(Deserialize)]
+    struct SplitInfo {
+        unit_sn: u64,
+        buy_quantity: u32,
+    }
+    let records: Vec<(u64, String, String, String)> = my_exec_tran_vec(
+        tran,
+        "select uid, tran_type, tran_amount, info from usr_transaction_records where tran_type in (?, ?) and info like ? and is_del = 0",
+        (
+            TranType::MainSaleSplit.to_string(),
+            TranType::SaleSplit.to_string(),
+            format!("%\"order_sn\":\"{}\"%", order_sn),
+        ),
+    )?;
+    for (uid, tran_type, tran_amount, info) in records {
+        let Ok(split) = serde_json::from_str::<SplitInfo>(&info) else {
+            continue;
+        };
+        if split.unit_sn != unit_sn as u64 || split.buy_quantity == 0 {
+            continue;
+        }
+        let tran_amount: Money = tran_amount
+            .parse()
+            .map_err(|_| error::ErrorInternalServerError("分账金额格式错误"))?;
+        let amount = Money::from_cent(tran_amount.cent() / split.buy_quantity as i64) * quantity;
+        if !amount.is_positive() {
+            continue;
+        }
+        let back_type = if TranType::from(tran_type) == TranType::MainSaleSplit {
+            TranType::MainSaleSplitBack
+        } else {
+            TranType::SaleSplitBack
+        };
+        let info = serde_json::json!({
+            "order_sn": order_sn,
+            "unit_sn": unit_sn,
+            "quantity": quantity,
+            "amount": amount,
+        });
+        pocket_money_back(tran, uid, amount, back_type, pay_type.clone(), info)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_commission_back_amount() {
+        let unit: Money = "3.30".parse().unwrap();
+        // 按件数扣回
+        assert_eq!(
+            commission_back_amount(unit, "9.90".parse().unwrap(), 2),
+            "6.60".parse().unwrap()
+        );
+        // 不超过结算后还未扣回的
+        assert_eq!(
+            commission_back_amount(unit, "3.30".parse().unwrap(), 2),
+            "3.30".parse().unwrap()
+        );
+        assert_eq!(commission_back_amount(unit, Money::ZERO, 1), Money::ZERO);
+        assert_eq!(
+            commission_back_amount(unit, "-1.00".parse().unwrap(), 1),
+            Money::ZERO
+        );
+    }
+}
@@ -2,6 +2,7 @@ use actix_web::Error;
 use mysql_quick::{Transaction, myset};
 use serde_json::Value;
 
+use crate::common::Money;
 use crate::common::types::{PayType, TranType};
 use crate::db::my_run_tran_drop;
 use crate::routes::utils_set::hash_set::hash_user_tran;
@@ -13,7 +14,7 @@ pub fn add_tran_record(
     tran_type: TranType,
     pay_type: PayType,
     uid: u64,
-    tran_amount: f64,
+    tran_amount: Money,
     info: Option<&Value>,
 ) -> Result<(), Error> {
     let time = chrono::Local::now().timestamp();
@@ -30,7 +31,7 @@ pub fn add_tran_record(
         tran,
         myset!("usr_transaction_records", {
             "uid": uid,
-            "tran_amount": tran_amount,
+            "tran_amount": tran_amount.to_string(),
             "tran_amount_hash": hash,
             "tran_type": tran_type.to_string(),
             "pay_type": pay_type.to_string(),
@@ -2,16 +2,17 @@ use actix_web::{Error, error};
 use mysql_quick::{Queryable, myget, myupdate};
 use serde::Deserialize;
 
-use crate::common::JWT_NORMAL_EXPIRES_SEC;
+use crate::common::config;
+use crate::control::token::issue_login_tokens;
 use crate::db::my_run_drop;
-use crate::middleware::AuthUser;
+use crate::middleware::{Module, check_module};
+use crate::routes::utils_set::coupon_campaign_set::grant_new_user_coupons;
 use crate::routes::utils_set::hash_set::hash_user;
 use crate::routes::utils_set::pocket_set::init_user_pocket_money;
 use crate::utils::files::get_file_url_sec;
-use crate::utils::jwt::get_token;
 use crate::utils::utils::log_err;
 use crate::{
-    db::{my_run_vec, mysql_conn},
+    db::{my_exec_first, my_run_vec, mysql_conn},
     routes::UserInfo,
     utils::random::rand_string,
 };
@@ -24,17 +25,17 @@ pub fn user_set_with_union_open(
     let mut conn = mysql_conn()?;
 
     let rand_user_name = rand_string(24);
+    // 是否为新注册的用户，新用户发放优惠券
+    let mut is_new = false;
 
     // unionid 存在，就用它登录
     match unionid {
         Some(uni_v) => {
-            let check_user: Option<u64> = conn
-                .query_first(
-                    "select id from usr_silent where unionid = \"".to_string()
-                        + uni_v.as_str()
-                        + "\"",
-                )
-                .unwrap();
+            let check_user: Option<u64> = my_exec_first(
+                &mut conn,
+                "select id from usr_silent where unionid = ?",
+                (&uni_v,),
+            )?;
 
             if check_user.is_none() {
                 // 没用户，新增
@@ -43,14 +44,15 @@ pub fn user_set_with_union_open(
                 values (?,?,?,?)";
                 conn.exec_drop(stmt, ("".to_string(), rand_user_name, &openid, uni_v))
                     .map_err(|e| error::ErrorInternalServerError(log_err(&e, "user_set")))?;
+                is_new = conn.affected_rows() > 0;
             }
         }
         None => {
-            let check_user: Option<u64> = conn
-                .query_first(
-                    "select id from usr_silent where openid = \"".to_string() + &openid + "\"",
-                )
-                .unwrap();
+            let check_user: Option<u64> = my_exec_first(
+                &mut conn,
+                "select id from usr_silent where openid = ?",
+                (&openid,),
+            )?;
 
             if check_user.is_none() {
                 let stmt = "insert ignore into usr_silent
@@ -58,6 +60,7 @@ pub fn user_set_with_union_open(
                 values (?,?,?)";
                 conn.exec_drop(stmt, ("".to_string(), rand_user_name, &openid))
                     .map_err(|e| error::ErrorInternalServerError(log_err(&e, "user_set")))?;
+                is_new = conn.affected_rows() > 0;
             }
         }
     }
@@ -98,7 +101,7 @@ pub fn user_set_with_union_open(
             myupdate!("usr_silent", {"openid": &openid}, {"hash": hash}),
         )?;
     }
-    let token = get_token(AuthUser { id: user_get[0].id }, JWT_NORMAL_EXPIRES_SEC)?;
+    let tokens = issue_login_tokens(user_get[0].id, false)?;
     let user = UserInfo {
         id: user_get[0].id,
         username: user_get[0].username.clone(),
@@ -107,8 +110,8 @@ pub fn user_set_with_union_open(
             if a == String::from("") {
                 None
             } else {
-                let temp_url =
-                    get_file_url_sec(Some(&a), JWT_NORMAL_EXPIRES_SEC).unwrap_or("".to_string());
+                let temp_url = get_file_url_sec(Some(&a), config().jwt.normal_expires_sec)
+                    .unwrap_or("".to_string());
                 Some(temp_url)
             }
         } else {
@@ -125,9 +128,17 @@ pub fn user_set_with_union_open(
                 .collect::<Vec<u16>>()
         },
         phone: None,
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
     };
     init_user_pocket_money(&mut conn, user.id)?;
+    // 优惠券发放失败不影响登录
+    if is_new
+        && check_module(Module::Coupon).is_ok()
+        && let Err(e) = grant_new_user_coupons(&mut conn, user.id)
+    {
+        log_err(&e, "新用户优惠券发放失败");
+    }
     Ok(user)
 }
 
@@ -159,7 +170,7 @@ pub fn user_upd_phone(uid: u64, phone: &str) -> Result<UserInfo, Error> {
             "id,username,nickname,avatar_url,gender,role,phone"
         ),
     )?;
-    let token = get_token(AuthUser { id: user_get[0].id }, JWT_NORMAL_EXPIRES_SEC)?;
+    let tokens = issue_login_tokens(user_get[0].id, false)?;
     let user = UserInfo {
         id: user_get[0].id,
         username: user_get[0].username.clone(),
@@ -168,8 +179,8 @@ pub fn user_upd_phone(uid: u64, phone: &str) -> Result<UserInfo, Error> {
             if a == String::from("") {
                 None
             } else {
-                let temp_url =
-                    get_file_url_sec(Some(&a), JWT_NORMAL_EXPIRES_SEC).unwrap_or("".to_string());
+                let temp_url = get_file_url_sec(Some(&a), config().jwt.normal_expires_sec)
+                    .unwrap_or("".to_string());
                 Some(temp_url)
             }
         } else {
@@ -186,7 +197,8 @@ pub fn user_upd_phone(uid: u64, phone: &str) -> Result<UserInfo, Error> {
                 .collect::<Vec<u16>>()
         },
         phone: user_get[0].phone.clone(),
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
     };
     Ok(user)
 }
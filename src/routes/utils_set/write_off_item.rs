@@ -174,11 +174,12 @@ pub fn do_write_off(
             "write_off_status": WriteOffStatus::SuccessWriteOff as u8,
         }),
     )?;
-    // 同时还要修改，原子订单状态为已完成
+    // 同时还要修改，原子订单状态为已完成，佣金的冻结期从完成时间开始
     my_run_tran_drop(
         tran,
         myupdate!("ord_order_item", { "order_item_id": order_item_id }, {
             "status": OrderItemStatus::Complete as u8,
+            "complete_time": get_now_time(NowTimeType::DateTime),
         }),
     )?;
     Ok(())
@@ -2,7 +2,7 @@ use actix_web::{Error, error};
 use base64::{Engine, engine};
 use libaes::Cipher;
 
-use crate::common::{LOCAL_AES_256_KEY, LocalKeySeed};
+use crate::common::{LocalKeySeed, config};
 
 // const MAX_STR_LEN: usize = 5000;
 
@@ -77,7 +77,7 @@ fn deobfuscate_reorder(input: &str, seed: u16) -> String {
 
 /// aes_256_加密
 pub fn aes_256_encrypt(endata: &str, seed: LocalKeySeed) -> anyhow::Result<String, Error> {
-    let obfuscated = obfuscate_reorder(LOCAL_AES_256_KEY, seed as u16);
+    let obfuscated = obfuscate_reorder(&config().crypto.aes_key, seed as u16);
     let plain = endata.as_bytes();
     let my_key: &[u8; 32] = obfuscated
         .as_bytes()
@@ -100,7 +100,7 @@ pub fn aes_256_encrypt(endata: &str, seed: LocalKeySeed) -> anyhow::Result<Strin
 
 /// aes_256_解密
 pub fn aes_256_decrypt(dedata: &str, seed: LocalKeySeed) -> anyhow::Result<String, Error> {
-    let obfuscated = obfuscate_reorder(LOCAL_AES_256_KEY, seed as u16);
+    let obfuscated = obfuscate_reorder(&config().crypto.aes_key, seed as u16);
     let dec_data = base64_decode(dedata).map_err(|_| error::ErrorBadRequest("字符码错误"))?;
     let my_key: &[u8; 32] = obfuscated
         .as_bytes()
@@ -4,10 +4,7 @@ use chrono::{Duration, Local};
 use redis::Commands;
 
 use crate::common::types::OssBucket;
-use crate::common::{
-    FILE_STORAGE_TYPE, FILE_URL_PASS_SEC, LocalKeySeed, OSS_ACCESS_KEY_ID, OSS_ACCESS_KEY_SECRET,
-    OSS_END_POINT, PROJECT_NAME,
-};
+use crate::common::{LocalKeySeed, PROJECT_NAME, config};
 use crate::db::redis_conn;
 use crate::utils::crypto::aes_256_encrypt;
 
@@ -52,9 +49,9 @@ pub async fn put_oss_file(
     bucket: OssBucket,
 ) -> anyhow::Result<(), Error> {
     let oss = OSS::new(
-        OSS_ACCESS_KEY_ID,
-        OSS_ACCESS_KEY_SECRET,
-        OSS_END_POINT,
+        config().oss.access_key_id.as_str(),
+        config().oss.access_key_secret.as_str(),
+        config().oss.end_point.as_str(),
         bucket.get_name(),
     );
     let builder = RequestBuilder::new();
@@ -97,7 +94,7 @@ pub fn get_file_url_sec<T: AsRef<str>>(file_path: Option<T>, sec: i64) -> Option
             match redis_con.get(&key_name) {
                 Ok(u) => url_str = u,
                 Err(_) => {
-                    if FILE_STORAGE_TYPE == 1 {
+                    if config().file.storage_type == 1 {
                         // 本地
                         let expiration = Local::now() + Duration::seconds(sec);
                         let time_stamp = expiration.timestamp();
@@ -118,9 +115,9 @@ pub fn get_file_url_sec<T: AsRef<str>>(file_path: Option<T>, sec: i64) -> Option
                         };
                     } else {
                         let oss = OSS::new(
-                            OSS_ACCESS_KEY_ID,
-                            OSS_ACCESS_KEY_SECRET,
-                            OSS_END_POINT,
+                            config().oss.access_key_id.as_str(),
+                            config().oss.access_key_secret.as_str(),
+                            config().oss.end_point.as_str(),
                             bucket.get_name(),
                         );
                         let build = RequestBuilder::new().with_expire(sec);
@@ -162,9 +159,10 @@ pub fn get_file_url<T: AsRef<str>>(file_path: Option<T>) -> Option<String> {
             match redis_con.get(&key_name) {
                 Ok(u) => url_str = u,
                 Err(_) => {
-                    if FILE_STORAGE_TYPE == 1 {
+                    if config().file.storage_type == 1 {
                         // 本地
-                        let expiration = Local::now() + Duration::seconds(FILE_URL_PASS_SEC);
+                        let expiration =
+                            Local::now() + Duration::seconds(config().file.url_pass_sec);
                         let time_stamp = expiration.timestamp();
                         match sign_local_file(&p, time_stamp) {
                             Ok(sign_info) => {
@@ -176,7 +174,7 @@ pub fn get_file_url<T: AsRef<str>>(file_path: Option<T>) -> Option<String> {
                                     sign_info
                                 );
                                 let _: () = redis_con
-                                    .set_ex(&key_name, &url, FILE_URL_PASS_SEC as u64 - 5)
+                                    .set_ex(&key_name, &url, config().file.url_pass_sec as u64 - 5)
                                     .unwrap();
                                 url_str = url;
                             }
@@ -184,19 +182,19 @@ pub fn get_file_url<T: AsRef<str>>(file_path: Option<T>) -> Option<String> {
                         }
                     } else {
                         let oss = OSS::new(
-                            OSS_ACCESS_KEY_ID,
-                            OSS_ACCESS_KEY_SECRET,
-                            OSS_END_POINT,
+                            config().oss.access_key_id.as_str(),
+                            config().oss.access_key_secret.as_str(),
+                            config().oss.end_point.as_str(),
                             bucket.get_name(),
                         );
-                        let build = RequestBuilder::new().with_expire(FILE_URL_PASS_SEC);
+                        let build = RequestBuilder::new().with_expire(config().file.url_pass_sec);
                         //.with_cdn("https://mydomain.com") //使用cdn后，无法限制ip访问
                         // .oss_download_speed_limit(30);
                         let download_url = oss.sign_download_url(p, &build);
                         let list = download_url.split("?").collect::<Vec<&str>>();
                         let url = format!("{}/{}?{}", bucket.get_base_url(), p, list[1]);
                         let _: () = redis_con
-                            .set_ex(&key_name, &url, FILE_URL_PASS_SEC as u64 - 5)
+                            .set_ex(&key_name, &url, config().file.url_pass_sec as u64 - 5)
                             .unwrap();
                         url_str = url;
                     }
@@ -282,7 +280,7 @@ pub async fn download_file_to_oss(
         .map_err(|e| error::ErrorBadGateway(e))?
         .to_vec();
     let buf = buffer.as_slice();
-    if FILE_STORAGE_TYPE == 1 {
+    if config().file.storage_type == 1 {
         put_local_file(&buf, &dir_path, &file_path)?;
     } else {
         put_oss_file(buf, file_path, bucket).await?;
@@ -6,7 +6,7 @@ use jsonwebtoken::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::common::JWT_TOKEN_SECRET;
+use crate::common::config;
 use crate::middleware::AuthUser;
 
 /// Our claims struct, it needs to derive `Serialize` and/or `Deserialize`
@@ -14,10 +14,13 @@ use crate::middleware::AuthUser;
 pub struct Claims {
     pub id: u64,
     pub exp: usize,
+    /// 签发时用户的 token 版本号，与当前版本号不一致时 token 失效
+    #[serde(default)]
+    pub ver: u32,
 }
 
-/// 创建token， expires_sec 为 token 的有效时间（秒）
-pub fn get_token(user: AuthUser, expires_sec: i64) -> std::io::Result<String> {
+/// 创建token， ver 为用户当前的 token 版本号，expires_sec 为 token 的有效时间（秒）
+pub fn get_token(user: AuthUser, ver: u32, expires_sec: i64) -> std::io::Result<String> {
     // let now_time = Local::now().timestamp();
     let now_time = Local::now()
         .checked_add_signed(chrono::Duration::seconds(expires_sec))
@@ -25,11 +28,12 @@ pub fn get_token(user: AuthUser, expires_sec: i64) -> std::io::Result<String> {
         .timestamp();
 
     let header = Header::new(Algorithm::HS512);
-    let secret = JWT_TOKEN_SECRET.to_string();
+    let secret = &config().jwt.secret;
 
     let claims = Claims {
         id: user.id,
         exp: now_time as usize,
+        ver,
     };
 
     let token = encode(&header, &claims, &EncodingKey::from_secret(secret.as_ref())).unwrap();
@@ -40,7 +44,7 @@ pub fn get_token(user: AuthUser, expires_sec: i64) -> std::io::Result<String> {
 /// 验证 token
 pub fn validate_token(token: &str) -> Result<TokenData<Claims>, JwtError> {
     let validation = Validation::new(Algorithm::HS512);
-    let secret = JWT_TOKEN_SECRET.to_string();
+    let secret = &config().jwt.secret;
     let key = DecodingKey::from_secret(secret.as_ref());
     let data = decode::<Claims>(token, &key, &validation)?;
     Ok(data)
@@ -81,4 +85,14 @@ mod test {
         println!("a>>> {:?}", c);
         assert!(true)
     }
+
+    #[test]
+    fn test_claims_ver_default() {
+        // 没有 ver 的旧 token，版本号视为 0
+        let c: super::Claims = serde_json::from_str(r#"{"id":1,"exp":1723263649}"#).unwrap();
+        assert_eq!(c.ver, 0);
+        let c: super::Claims =
+            serde_json::from_str(r#"{"id":1,"exp":1723263649,"ver":2}"#).unwrap();
+        assert_eq!(c.ver, 2);
+    }
 }
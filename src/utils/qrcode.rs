@@ -29,11 +29,13 @@ mod test {
     #[test]
     fn test_qrcode() {
         let qrcode = QRBuilder::new("https://example.com/").build().unwrap();
+        // 写到临时目录，不在仓库里生成文件
+        let out = std::env::temp_dir().join("qrcode_test.png");
         let _img = ImageBuilder::default()
             .shape(Shape::Square)
             .background_color([255, 255, 255, 255]) // Handles transparency
             .fit_width(600)
-            .to_file(&qrcode, "out.png");
+            .to_file(&qrcode, out.to_str().unwrap());
 
         // let data = ImageBuilder::default()
         //     .shape(Shape::Square)
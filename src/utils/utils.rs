@@ -29,12 +29,6 @@ pub fn keep_decimal(f_num: f64) -> f64 {
     s_num.parse::<f64>().unwrap()
 }
 
-/// 将 f64 保留为0位数小数，返回u64
-pub fn keep_uint(f_num: f64) -> u64 {
-    let s_num = format!("{:.0}", f_num);
-    s_num.parse::<u64>().unwrap()
-}
-
 /// 计算两个经纬点之前的距离
 #[allow(unused)]
 pub fn distance_lat_lng(start: (f64, f64), end: (f64, f64)) -> f64 {